@@ -2,6 +2,7 @@
 use anyhow::Context;
 #[cfg(feature = "serde_support")]
 use serde_derive::*;
+use std::collections::BTreeMap;
 use std::ffi::{OsStr, OsString};
 #[cfg(windows)]
 use std::os::windows::ffi::OsStrExt;
@@ -12,7 +13,10 @@ use std::os::windows::ffi::OsStrExt;
 #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
 pub struct CommandBuilder {
     args: Vec<OsString>,
-    envs: Vec<(OsString, OsString)>,
+    /// The environment variables to set, or to remove when the
+    /// value is None
+    envs: BTreeMap<OsString, Option<OsString>>,
+    cwd: Option<OsString>,
 }
 
 impl CommandBuilder {
@@ -21,7 +25,8 @@ impl CommandBuilder {
     pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
         Self {
             args: vec![program.as_ref().to_owned()],
-            envs: vec![],
+            envs: BTreeMap::new(),
+            cwd: None,
         }
     }
 
@@ -30,7 +35,8 @@ impl CommandBuilder {
     pub fn new_default_prog() -> Self {
         Self {
             args: vec![],
-            envs: vec![],
+            envs: BTreeMap::new(),
+            cwd: None,
         }
     }
 
@@ -66,14 +72,63 @@ impl CommandBuilder {
         V: AsRef<OsStr>,
     {
         self.envs
-            .push((key.as_ref().to_owned(), val.as_ref().to_owned()));
+            .insert(key.as_ref().to_owned(), Some(val.as_ref().to_owned()));
+    }
+
+    /// Remove an environmental variable that would otherwise be
+    /// inherited from the environment of the current process
+    pub fn env_remove<K>(&mut self, key: K)
+    where
+        K: AsRef<OsStr>,
+    {
+        self.envs.insert(key.as_ref().to_owned(), None);
+    }
+
+    /// Returns the value that was set for an environmental variable
+    /// via `env`
+    pub fn get_env<K>(&self, key: K) -> Option<&OsStr>
+    where
+        K: AsRef<OsStr>,
+    {
+        self.envs
+            .get(key.as_ref())
+            .and_then(|val| val.as_ref())
+            .map(OsString::as_os_str)
+    }
+
+    /// Iterates over the environmental variables that were set via
+    /// `env`, and those that were removed via `env_remove`, for which
+    /// the value is None
+    pub fn iter_env(&self) -> impl Iterator<Item = (&OsStr, Option<&OsStr>)> {
+        self.envs
+            .iter()
+            .map(|(key, val)| (key.as_os_str(), val.as_ref().map(OsString::as_os_str)))
+    }
+
+    /// Set the working directory for the child process
+    pub fn cwd<D>(&mut self, dir: D)
+    where
+        D: AsRef<OsStr>,
+    {
+        self.cwd = Some(dir.as_ref().to_owned());
+    }
+
+    /// Returns the working directory that was set via `cwd`
+    pub fn get_cwd(&self) -> Option<&OsString> {
+        self.cwd.as_ref()
+    }
+
+    /// Returns the argument vector; it is empty for a builder
+    /// created via `new_default_prog`
+    pub fn get_argv(&self) -> &Vec<OsString> {
+        &self.args
     }
 
     #[cfg(feature = "ssh")]
     pub(crate) fn iter_env_as_str(&self) -> impl Iterator<Item = (&str, &str)> {
         self.envs.iter().filter_map(|(key, val)| {
             let key = key.to_str()?;
-            let val = val.to_str()?;
+            let val = val.as_ref()?.to_str()?;
             Some((key, val))
         })
     }
@@ -105,32 +160,55 @@ impl CommandBuilder {
             cmd
         };
 
+        if let Some(cwd) = self.cwd.as_ref() {
+            cmd.current_dir(cwd);
+        }
+
         for (key, val) in &self.envs {
-            cmd.env(key, val);
+            match val {
+                Some(val) => cmd.env(key, val),
+                None => cmd.env_remove(key),
+            };
         }
 
         Ok(cmd)
     }
 
     /// Determine which shell to run.
-    /// We take the contents of the $SHELL env var first, then
-    /// fall back to looking it up from the password database.
+    /// We take the contents of the $SHELL env var first, so long as
+    /// it names an executable, then fall back to the login shell from
+    /// the password database.
     fn get_shell() -> anyhow::Result<String> {
-        std::env::var("SHELL").or_else(|_| {
-            let ent = unsafe { libc::getpwuid(libc::getuid()) };
+        if let Ok(shell) = std::env::var("SHELL") {
+            if Self::is_executable(&shell) {
+                return Ok(shell);
+            }
+        }
 
-            if ent.is_null() {
+        let ent = unsafe { libc::getpwuid(libc::getuid()) };
+
+        if ent.is_null() {
+            Ok("/bin/sh".into())
+        } else {
+            use std::ffi::CStr;
+            use std::str;
+            let shell = unsafe { CStr::from_ptr((*ent).pw_shell) };
+            let shell = shell.to_str().context("failed to resolve shell")?;
+            // An empty shell field means the system default shell
+            if shell.is_empty() {
                 Ok("/bin/sh".into())
             } else {
-                use std::ffi::CStr;
-                use std::str;
-                let shell = unsafe { CStr::from_ptr((*ent).pw_shell) };
-                shell
-                    .to_str()
-                    .map(str::to_owned)
-                    .context("failed to resolve shell")
+                Ok(shell.to_owned())
             }
-        })
+        }
+    }
+
+    /// Returns true if `path` names an executable file
+    fn is_executable(path: &str) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path)
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
     }
 
     fn get_home_dir() -> anyhow::Result<String> {
@@ -191,7 +269,10 @@ impl CommandBuilder {
 
         // override with the specified values
         for (k, v) in &self.envs {
-            env_hash.insert(k.to_owned(), v.to_owned());
+            match v {
+                Some(v) => env_hash.insert(k.to_owned(), v.to_owned()),
+                None => env_hash.remove(k),
+            };
         }
 
         // and now encode it as wide characters
@@ -209,6 +290,15 @@ impl CommandBuilder {
         block
     }
 
+    /// Returns the nul terminated working directory, if one was set
+    pub(crate) fn current_directory(&self) -> Option<Vec<u16>> {
+        self.cwd.as_ref().map(|cwd| {
+            let mut wide: Vec<u16> = cwd.encode_wide().collect();
+            wide.push(0);
+            wide
+        })
+    }
+
     pub(crate) fn cmdline(&self) -> anyhow::Result<(Vec<u16>, Vec<u16>)> {
         let mut cmdline = Vec::<u16>::new();
 
@@ -44,6 +44,7 @@
 //! session with an implementation of `PtySystem`, allowing
 //! you to use the same pty interface with remote ptys.
 use anyhow::{anyhow, bail, Error};
+use filedescriptor::FileDescriptor;
 #[cfg(feature = "serde_support")]
 use serde_derive::*;
 use std::io::Result as IoResult;
@@ -59,6 +60,8 @@ pub mod win;
 #[cfg(feature = "ssh")]
 pub mod ssh;
 
+pub mod procinfo;
+pub mod reactor;
 pub mod serial;
 
 /// Represents the size of the visible display area in the pty
@@ -99,6 +102,20 @@ pub trait MasterPty: std::io::Write {
     /// Obtain a readable handle; output from the slave(s) is readable
     /// via this stream.
     fn try_clone_reader(&self) -> Result<Box<dyn std::io::Read + Send>, Error>;
+    /// Obtain a readable handle that can be registered with a
+    /// `reactor::Reactor`, so that the output is read without
+    /// dedicating a thread to it.  Returns None if the pty can't be
+    /// polled for readiness, in which case the reader returned by
+    /// `try_clone_reader` must be read from a thread of its own.
+    fn try_clone_pollable_reader(&self) -> Result<Option<FileDescriptor>, Error> {
+        Ok(None)
+    }
+    /// Returns the process identifier of the foreground process group
+    /// leader of the pty, if applicable.  This is typically the program
+    /// that is currently running in the shell.
+    fn process_group_leader(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// Represents a child process spawned into the pty.
@@ -114,6 +131,11 @@ pub trait Child: std::fmt::Debug {
     /// Blocks execution until the child process has completed,
     /// yielding its exit status.
     fn wait(&mut self) -> IoResult<ExitStatus>;
+    /// Returns the process identifier of the child process,
+    /// if applicable
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// Represents the slave side of a pty.
@@ -124,30 +146,32 @@ pub trait SlavePty {
 }
 
 /// Represents the exit status of a child process.
-/// This is rather anemic in the current version of this crate,
-/// holding only an indicator of success or failure.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ExitStatus {
-    successful: bool,
+    code: u32,
 }
 
 impl ExitStatus {
     /// Construct an ExitStatus from a process return code
     pub fn with_exit_code(code: u32) -> Self {
-        Self {
-            successful: code == 0,
-        }
+        Self { code }
     }
 
     pub fn success(&self) -> bool {
-        self.successful
+        self.code == 0
+    }
+
+    /// Returns the exit code of the process.  A process that was
+    /// terminated by a signal is reported as exit code 1.
+    pub fn exit_code(&self) -> u32 {
+        self.code
     }
 }
 
 impl From<std::process::ExitStatus> for ExitStatus {
     fn from(status: std::process::ExitStatus) -> ExitStatus {
         ExitStatus {
-            successful: status.success(),
+            code: status.code().map(|code| code as u32).unwrap_or(1),
         }
     }
 }
@@ -184,6 +208,10 @@ impl Child for std::process::Child {
     fn wait(&mut self) -> IoResult<ExitStatus> {
         std::process::Child::wait(self).map(Into::into)
     }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.id())
+    }
 }
 
 /// `PtySystemSelection` allows selecting and constructing one of the
@@ -0,0 +1,509 @@
+//! Describes the processes that are running in a pty, so that the
+//! program in the foreground can be shown in a title, or the user can
+//! be asked to confirm before a pty whose program is busy is closed.
+//!
+//! On unix systems the foreground process is the leader of the
+//! foreground process group of the pty, see
+//! `MasterPty::process_group_leader`.  Windows has no equivalent, so
+//! `ProcessInfo::with_root_pid` can be used to walk the tree of
+//! processes that were spawned by the child.
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Information about a process, and optionally its descendants
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// The process identifier of the parent process
+    pub ppid: u32,
+    /// The short name of the program, such as `bash`
+    pub name: String,
+    /// The path to the executable image, if it could be determined
+    pub executable: Option<PathBuf>,
+    /// The current working directory, if it could be determined
+    pub cwd: Option<PathBuf>,
+    /// The children of the process.  This is only populated by
+    /// `with_root_pid`.
+    pub children: Vec<ProcessInfo>,
+}
+
+impl ProcessInfo {
+    /// Returns information about the process with the identifier
+    /// `pid`, without its children.  Returns None if the process
+    /// doesn't exist or cannot be inspected.
+    pub fn with_pid(pid: u32) -> Option<Self> {
+        let (ppid, name) = platform::parent_and_name(pid)?;
+        Some(Self {
+            pid,
+            ppid,
+            name,
+            executable: platform::executable(pid),
+            cwd: platform::current_working_dir(pid),
+            children: vec![],
+        })
+    }
+
+    /// Returns information about the process with the identifier
+    /// `pid` along with all of its descendants.  This examines every
+    /// process on the system, so it is more expensive than `with_pid`.
+    pub fn with_root_pid(pid: u32) -> Option<Self> {
+        /// The parent and name of each process, and the children of each
+        /// process in the order in which the system listed them
+        struct Tree {
+            procs: HashMap<u32, (u32, String)>,
+            children: HashMap<u32, Vec<u32>>,
+        }
+
+        fn build(pid: u32, tree: &Tree, seen: &mut HashSet<u32>) -> Option<ProcessInfo> {
+            let (ppid, name) = tree.procs.get(&pid)?.clone();
+            let mut info = ProcessInfo {
+                pid,
+                ppid,
+                name,
+                executable: platform::executable(pid),
+                cwd: platform::current_working_dir(pid),
+                children: vec![],
+            };
+            // Identifiers may be reused, so a process that appears
+            // to be its own ancestor is ignored
+            seen.insert(pid);
+            for &child in tree.children.get(&pid).into_iter().flatten() {
+                if !seen.contains(&child) {
+                    if let Some(child) = build(child, tree, seen) {
+                        info.children.push(child);
+                    }
+                }
+            }
+            Some(info)
+        }
+
+        // Take a single snapshot of the processes and walk that,
+        // rather than asking the system about each process in turn
+        let mut tree = Tree {
+            procs: HashMap::new(),
+            children: HashMap::new(),
+        };
+        for (child, ppid, name) in platform::processes() {
+            tree.children.entry(ppid).or_default().push(child);
+            tree.procs.insert(child, (ppid, name));
+        }
+        build(pid, &tree, &mut HashSet::new())
+    }
+
+    /// Returns this process and all of its descendants
+    pub fn flatten(&self) -> Vec<&ProcessInfo> {
+        let mut procs = vec![self];
+        for child in &self.children {
+            procs.append(&mut child.flatten());
+        }
+        procs
+    }
+
+    /// Returns the descendant that was most recently spawned, following
+    /// the last child at each level, or this process if it has no
+    /// children.  In the absence of job control this is the best guess
+    /// at the program that the user is interacting with.
+    pub fn newest_leaf(&self) -> &ProcessInfo {
+        match self.children.last() {
+            Some(child) => child.newest_leaf(),
+            None => self,
+        }
+    }
+}
+
+/// Returns the current working directory of the process with the
+/// identifier `pid`, if it can be determined
+pub fn current_working_dir(pid: u32) -> Option<PathBuf> {
+    platform::current_working_dir(pid)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::path::PathBuf;
+
+    /// Parses the parent pid and the name from /proc/<pid>/stat
+    pub fn parent_and_name(pid: u32) -> Option<(u32, String)> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The name is in parentheses and may itself contain spaces
+        // and parentheses, so look for the last closing paren
+        let open = stat.find('(')?;
+        let close = stat.rfind(')')?;
+        let name = stat.get(open + 1..close)?.to_string();
+        let mut fields = stat.get(close + 1..)?.split_whitespace();
+        let _state = fields.next()?;
+        let ppid = fields.next()?.parse().ok()?;
+        Some((ppid, name))
+    }
+
+    pub fn executable(pid: u32) -> Option<PathBuf> {
+        std::fs::read_link(format!("/proc/{}/exe", pid)).ok()
+    }
+
+    pub fn current_working_dir(pid: u32) -> Option<PathBuf> {
+        std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+    }
+
+    /// Returns the pid, parent pid and name of every process
+    pub fn processes() -> Vec<(u32, u32, String)> {
+        let dir = match std::fs::read_dir("/proc") {
+            Ok(dir) => dir,
+            Err(_) => return vec![],
+        };
+        dir.filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            let (ppid, name) = parent_and_name(pid)?;
+            Some((pid, ppid, name))
+        })
+        .collect()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{CStr, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::PathBuf;
+
+    fn bsd_info(pid: u32) -> Option<libc::proc_bsdinfo> {
+        let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of_val(&info) as libc::c_int;
+        let res = unsafe {
+            libc::proc_pidinfo(
+                pid as libc::c_int,
+                libc::PROC_PIDTBSDINFO,
+                0,
+                &mut info as *mut _ as *mut libc::c_void,
+                size,
+            )
+        };
+        if res == size {
+            Some(info)
+        } else {
+            None
+        }
+    }
+
+    pub fn parent_and_name(pid: u32) -> Option<(u32, String)> {
+        let info = bsd_info(pid)?;
+        // pbi_comm is truncated to 16 bytes, so prefer pbi_name
+        let name = unsafe { CStr::from_ptr(info.pbi_name.as_ptr()) };
+        let name = if name.to_bytes().is_empty() {
+            unsafe { CStr::from_ptr(info.pbi_comm.as_ptr()) }
+        } else {
+            name
+        };
+        Some((info.pbi_ppid, name.to_string_lossy().into_owned()))
+    }
+
+    pub fn executable(pid: u32) -> Option<PathBuf> {
+        let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+        let len = unsafe {
+            libc::proc_pidpath(
+                pid as libc::c_int,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len() as u32,
+            )
+        };
+        if len <= 0 {
+            return None;
+        }
+        buf.truncate(len as usize);
+        Some(PathBuf::from(OsString::from_vec(buf)))
+    }
+
+    pub fn current_working_dir(pid: u32) -> Option<PathBuf> {
+        let mut info: libc::proc_vnodepathinfo = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of_val(&info) as libc::c_int;
+        let res = unsafe {
+            libc::proc_pidinfo(
+                pid as libc::c_int,
+                libc::PROC_PIDVNODEPATHINFO,
+                0,
+                &mut info as *mut _ as *mut libc::c_void,
+                size,
+            )
+        };
+        if res != size {
+            return None;
+        }
+        let cwd = unsafe { CStr::from_ptr(info.pvi_cdir.vip_path.as_ptr() as *const libc::c_char) };
+        Some(PathBuf::from(std::ffi::OsStr::from_bytes(cwd.to_bytes())))
+    }
+
+    pub fn processes() -> Vec<(u32, u32, String)> {
+        let count = unsafe { libc::proc_listallpids(std::ptr::null_mut(), 0) };
+        if count <= 0 {
+            return vec![];
+        }
+        // Leave room for processes that are spawned in the meantime
+        let mut pids: Vec<libc::pid_t> = vec![0; count as usize + 32];
+        let count = unsafe {
+            libc::proc_listallpids(
+                pids.as_mut_ptr() as *mut libc::c_void,
+                (pids.len() * std::mem::size_of::<libc::pid_t>()) as libc::c_int,
+            )
+        };
+        pids.truncate(count.max(0) as usize);
+        pids.into_iter()
+            .filter_map(|pid| {
+                let (ppid, name) = parent_and_name(pid as u32)?;
+                Some((pid as u32, ppid, name))
+            })
+            .collect()
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::PathBuf;
+    use winapi::shared::basetsd::ULONG_PTR;
+    use winapi::shared::minwindef::{DWORD, LPCVOID, LPVOID, ULONG};
+    use winapi::shared::ntdef::{LONG, NTSTATUS, PVOID, UNICODE_STRING};
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::memoryapi::ReadProcessMemory;
+    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::tlhelp32::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    };
+    use winapi::um::winbase::QueryFullProcessImageNameW;
+    use winapi::um::winnt::{
+        HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_READ,
+    };
+
+    // winapi doesn't provide the native API, so the few parts of it
+    // that are needed to find the PEB of a process are declared here
+    #[allow(non_camel_case_types, non_snake_case)]
+    #[repr(C)]
+    struct PROCESS_BASIC_INFORMATION {
+        ExitStatus: NTSTATUS,
+        PebBaseAddress: PVOID,
+        AffinityMask: ULONG_PTR,
+        BasePriority: LONG,
+        UniqueProcessId: ULONG_PTR,
+        InheritedFromUniqueProcessId: ULONG_PTR,
+    }
+
+    /// The leading fields of the PEB, up to the process parameters
+    #[allow(non_snake_case)]
+    #[repr(C)]
+    struct PEB {
+        Reserved1: [u8; 2],
+        BeingDebugged: u8,
+        Reserved2: [u8; 1],
+        Reserved3: [PVOID; 2],
+        Ldr: PVOID,
+        ProcessParameters: PVOID,
+    }
+
+    #[allow(non_upper_case_globals)]
+    const ProcessBasicInformation: ULONG = 0;
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn NtQueryInformationProcess(
+            ProcessHandle: HANDLE,
+            ProcessInformationClass: ULONG,
+            ProcessInformation: PVOID,
+            ProcessInformationLength: ULONG,
+            ReturnLength: *mut ULONG,
+        ) -> NTSTATUS;
+    }
+
+    /// Closes the handle when dropped
+    struct Handle(HANDLE);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    impl Handle {
+        fn open_process(pid: u32, access: DWORD) -> Option<Self> {
+            let handle = unsafe { OpenProcess(access, 0, pid) };
+            if handle.is_null() {
+                None
+            } else {
+                Some(Self(handle))
+            }
+        }
+
+        /// Reads a `T` from the address space of the process
+        fn read<T>(&self, addr: usize) -> Option<T> {
+            let mut data: T = unsafe { std::mem::zeroed() };
+            let res = unsafe {
+                ReadProcessMemory(
+                    self.0,
+                    addr as LPCVOID,
+                    &mut data as *mut T as LPVOID,
+                    std::mem::size_of::<T>(),
+                    std::ptr::null_mut(),
+                )
+            };
+            if res == 0 {
+                None
+            } else {
+                Some(data)
+            }
+        }
+    }
+
+    fn wide_to_string(s: &[u16]) -> String {
+        let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+        String::from_utf16_lossy(&s[..len])
+    }
+
+    /// Returns the pid, parent pid and executable name of every process
+    pub fn processes() -> Vec<(u32, u32, String)> {
+        let handle = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+        if handle == INVALID_HANDLE_VALUE {
+            return vec![];
+        }
+        let handle = Handle(handle);
+
+        let mut procs = vec![];
+        let mut entry: PROCESSENTRY32W = unsafe { std::mem::zeroed() };
+        entry.dwSize = std::mem::size_of_val(&entry) as DWORD;
+        let mut ok = unsafe { Process32FirstW(handle.0, &mut entry) };
+        while ok != 0 {
+            procs.push((
+                entry.th32ProcessID,
+                entry.th32ParentProcessID,
+                wide_to_string(&entry.szExeFile),
+            ));
+            ok = unsafe { Process32NextW(handle.0, &mut entry) };
+        }
+        procs
+    }
+
+    pub fn parent_and_name(pid: u32) -> Option<(u32, String)> {
+        processes()
+            .into_iter()
+            .find(|&(p, _, _)| p == pid)
+            .map(|(_, ppid, name)| (ppid, name))
+    }
+
+    pub fn executable(pid: u32) -> Option<PathBuf> {
+        let proc = Handle::open_process(pid, PROCESS_QUERY_LIMITED_INFORMATION)?;
+        let mut buf = [0u16; 4096];
+        let mut len = buf.len() as DWORD;
+        let res = unsafe { QueryFullProcessImageNameW(proc.0, 0, buf.as_mut_ptr(), &mut len) };
+        if res == 0 {
+            return None;
+        }
+        Some(PathBuf::from(OsString::from_wide(&buf[..len as usize])))
+    }
+
+    /// The current directory is not exposed by any API, so it is read
+    /// from the process parameters that are referenced by the PEB of
+    /// the process.  This only works if the process has the same
+    /// bitness as this one.
+    pub fn current_working_dir(pid: u32) -> Option<PathBuf> {
+        let proc = Handle::open_process(pid, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ)?;
+
+        let mut info: PROCESS_BASIC_INFORMATION = unsafe { std::mem::zeroed() };
+        let status = unsafe {
+            NtQueryInformationProcess(
+                proc.0,
+                ProcessBasicInformation,
+                &mut info as *mut _ as LPVOID,
+                std::mem::size_of_val(&info) as u32,
+                std::ptr::null_mut(),
+            )
+        };
+        if status < 0 {
+            return None;
+        }
+
+        let peb: PEB = proc.read(info.PebBaseAddress as usize)?;
+        // RTL_USER_PROCESS_PARAMETERS is only partially documented;
+        // CurrentDirectory.DosPath follows 16 bytes of sizes and flags
+        // and five handles
+        let dos_path = peb.ProcessParameters as usize + 16 + 5 * std::mem::size_of::<usize>();
+        let dos_path: UNICODE_STRING = proc.read(dos_path)?;
+
+        let mut path = vec![0u16; dos_path.Length as usize / 2];
+        let res = unsafe {
+            ReadProcessMemory(
+                proc.0,
+                dos_path.Buffer as LPCVOID,
+                path.as_mut_ptr() as LPVOID,
+                dos_path.Length as usize,
+                std::ptr::null_mut(),
+            )
+        };
+        if res == 0 {
+            return None;
+        }
+        Some(PathBuf::from(OsString::from_wide(&path)))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use std::path::PathBuf;
+
+    pub fn parent_and_name(_pid: u32) -> Option<(u32, String)> {
+        None
+    }
+
+    pub fn executable(_pid: u32) -> Option<PathBuf> {
+        None
+    }
+
+    pub fn current_working_dir(_pid: u32) -> Option<PathBuf> {
+        None
+    }
+
+    pub fn processes() -> Vec<(u32, u32, String)> {
+        vec![]
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn current_process() {
+        let info = ProcessInfo::with_pid(std::process::id()).unwrap();
+        assert_eq!(info.pid, std::process::id());
+        assert_eq!(info.ppid, unsafe { libc::getppid() } as u32);
+        assert_eq!(info.executable, std::env::current_exe().ok());
+        assert_eq!(info.cwd, std::env::current_dir().ok());
+        assert_eq!(current_working_dir(info.pid), info.cwd);
+        assert!(info.children.is_empty());
+    }
+
+    #[test]
+    fn spawned_child() {
+        let mut child = Command::new("sleep")
+            .arg("10")
+            .current_dir("/")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        let info = ProcessInfo::with_root_pid(std::process::id());
+        child.kill().ok();
+        child.wait().ok();
+
+        let info = info.unwrap();
+        let child = info
+            .flatten()
+            .into_iter()
+            .find(|p| p.pid == pid)
+            .cloned()
+            .unwrap();
+        assert_eq!(child.ppid, std::process::id());
+        assert_eq!(child.name, "sleep");
+        assert_eq!(child.cwd, Some(PathBuf::from("/")));
+        assert!(child.executable.is_some());
+    }
+}
@@ -0,0 +1,282 @@
+//! Reads the output of many ptys from a single thread.
+//!
+//! Rather than dedicating a thread to a blocking read of each pty,
+//! the readers returned by `MasterPty::try_clone_pollable_reader`
+//! can be registered with a `Reactor`, which waits for any of them
+//! to become readable and passes the output to the `ReadHandler`
+//! that was registered along with it.
+//!
+//! ```no_run
+//! use portable_pty::reactor::{Reactor, ReadHandler};
+//! use portable_pty::{PtySize, PtySystemSelection};
+//! use std::time::Duration;
+//!
+//! struct Printer;
+//!
+//! impl ReadHandler for Printer {
+//!     fn data(&mut self, data: &[u8]) -> Option<Duration> {
+//!         print!("{}", String::from_utf8_lossy(data));
+//!         None
+//!     }
+//!
+//!     fn closed(&mut self, _error: Option<std::io::Error>) {}
+//! }
+//!
+//! let reactor = Reactor::new()?;
+//! let pair = PtySystemSelection::default().get()?.openpty(PtySize::default())?;
+//! if let Some(reader) = pair.master.try_clone_pollable_reader()? {
+//!     reactor.register(reader, Box::new(Printer))?;
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+use filedescriptor::{poll, pollfd, socketpair, AsRawSocketDescriptor, FileDescriptor, POLLIN};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Receives the output of a reader that is registered with a `Reactor`.
+/// The methods are called on the thread of the reactor, so they should
+/// hand the output off rather than taking a long time to process it,
+/// as that holds up the other readers.
+pub trait ReadHandler: Send {
+    /// Called with the output that was read.  Returning a duration
+    /// pauses reading for that long, which applies backpressure to
+    /// the program producing the output without affecting the other
+    /// readers.
+    fn data(&mut self, data: &[u8]) -> Option<Duration>;
+
+    /// Called once when the reader reaches EOF, which typically means
+    /// that the program has exited, or when reading fails.  The reader
+    /// is then unregistered and closed.
+    fn closed(&mut self, error: Option<std::io::Error>);
+}
+
+struct Source {
+    reader: FileDescriptor,
+    handler: Box<dyn ReadHandler>,
+    /// Set while reading is paused at the request of the handler
+    resume_at: Option<Instant>,
+}
+
+/// Owns a thread that reads from all of the registered readers.
+/// The thread exits when the `Reactor` is dropped.
+pub struct Reactor {
+    /// Registrations that the thread has yet to pick up
+    pending: Arc<Mutex<Vec<Source>>>,
+    /// Writing to this wakes the thread; dropping it stops the thread
+    wake: Mutex<FileDescriptor>,
+}
+
+impl Reactor {
+    pub fn new() -> anyhow::Result<Self> {
+        let (wake, wake_read) = socketpair()?;
+        let pending = Arc::new(Mutex::new(vec![]));
+        let thread_pending = Arc::clone(&pending);
+        std::thread::Builder::new()
+            .name("pty reactor".to_string())
+            .spawn(move || run(wake_read, thread_pending))?;
+        Ok(Self {
+            pending,
+            wake: Mutex::new(wake),
+        })
+    }
+
+    /// Starts reading from `reader`, passing its output to `handler`
+    /// until it reaches EOF.  `reader` must be pollable; readers that
+    /// are not should instead be read by a thread of their own.
+    pub fn register(
+        &self,
+        reader: FileDescriptor,
+        handler: Box<dyn ReadHandler>,
+    ) -> anyhow::Result<()> {
+        self.pending.lock().unwrap().push(Source {
+            reader,
+            handler,
+            resume_at: None,
+        });
+        self.wake.lock().unwrap().write_all(b"x")?;
+        Ok(())
+    }
+}
+
+/// How many times in a row poll may fail before the reactor gives up
+const MAX_POLL_FAILURES: u32 = 10;
+
+fn run(mut wake: FileDescriptor, pending: Arc<Mutex<Vec<Source>>>) {
+    const BUFSIZE: usize = 32 * 1024;
+    let mut buf = [0u8; BUFSIZE];
+    let mut sources: Vec<Source> = vec![];
+    let mut poll_failures = 0;
+
+    loop {
+        sources.append(&mut pending.lock().unwrap());
+
+        // Only poll the readers that are not paused, and wake up in
+        // time to resume the earliest of those that are
+        let now = Instant::now();
+        let mut polled = vec![];
+        let mut timeout: Option<Duration> = None;
+        for (idx, source) in sources.iter_mut().enumerate() {
+            match source.resume_at {
+                Some(when) if when > now => {
+                    let remaining = when - now;
+                    timeout = Some(timeout.map_or(remaining, |t| t.min(remaining)));
+                }
+                _ => {
+                    source.resume_at = None;
+                    polled.push(idx);
+                }
+            }
+        }
+
+        let mut pfd: Vec<pollfd> = std::iter::once(&wake)
+            .chain(polled.iter().map(|&idx| &sources[idx].reader))
+            .map(|fd| pollfd {
+                fd: fd.as_socket_descriptor(),
+                events: POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        if let Err(err) = poll(&mut pfd, timeout) {
+            // A signal interrupts poll now and then, but repeated
+            // failures mean that something is badly wrong, so back off
+            // and eventually give up rather than spinning
+            poll_failures += 1;
+            if poll_failures >= MAX_POLL_FAILURES {
+                log::error!("pty reactor: poll failed, giving up: {}", err);
+                sources.append(&mut pending.lock().unwrap());
+                for mut source in sources {
+                    source.handler.closed(Some(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("pty reactor: poll failed: {}", err),
+                    )));
+                }
+                return;
+            }
+            log::error!("pty reactor: poll failed: {}", err);
+            std::thread::sleep(Duration::from_millis(10 << poll_failures));
+            continue;
+        }
+        poll_failures = 0;
+
+        if pfd[0].revents != 0 {
+            match wake.read(&mut buf) {
+                // The Reactor was dropped
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+        }
+
+        let mut closed = vec![];
+        for (pfd, &idx) in pfd[1..].iter().zip(polled.iter()) {
+            if pfd.revents == 0 {
+                continue;
+            }
+            let source = &mut sources[idx];
+            // The reader is ready, so a single read won't block
+            match source.reader.read(&mut buf) {
+                Ok(0) => {
+                    source.handler.closed(None);
+                    closed.push(idx);
+                }
+                Ok(size) => {
+                    if let Some(delay) = source.handler.data(&buf[..size]) {
+                        source.resume_at = Some(Instant::now() + delay);
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    source.handler.closed(Some(err));
+                    closed.push(idx);
+                }
+            }
+        }
+        for idx in closed.into_iter().rev() {
+            sources.remove(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Data(Vec<u8>),
+        Closed,
+    }
+
+    /// Reports what it receives, pausing after each read if `pause` is set
+    struct Recorder {
+        tx: Sender<(Event, Instant)>,
+        pause: Option<Duration>,
+    }
+
+    impl ReadHandler for Recorder {
+        fn data(&mut self, data: &[u8]) -> Option<Duration> {
+            self.tx
+                .send((Event::Data(data.to_vec()), Instant::now()))
+                .unwrap();
+            self.pause
+        }
+
+        fn closed(&mut self, error: Option<std::io::Error>) {
+            assert!(error.is_none(), "unexpected error {:?}", error);
+            self.tx.send((Event::Closed, Instant::now())).unwrap();
+        }
+    }
+
+    fn register(
+        reactor: &Reactor,
+        pause: Option<Duration>,
+    ) -> (FileDescriptor, Receiver<(Event, Instant)>) {
+        let (writer, reader) = socketpair().unwrap();
+        let (tx, rx) = channel();
+        reactor
+            .register(reader, Box::new(Recorder { tx, pause }))
+            .unwrap();
+        (writer, rx)
+    }
+
+    fn next(rx: &Receiver<(Event, Instant)>) -> (Event, Instant) {
+        rx.recv_timeout(Duration::from_secs(10)).unwrap()
+    }
+
+    #[test]
+    fn data_and_eof() {
+        let reactor = Reactor::new().unwrap();
+        let (mut writer, rx) = register(&reactor, None);
+
+        writer.write_all(b"hello").unwrap();
+        assert_eq!(next(&rx).0, Event::Data(b"hello".to_vec()));
+
+        drop(writer);
+        assert_eq!(next(&rx).0, Event::Closed);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let reactor = Reactor::new().unwrap();
+        let pause = Duration::from_millis(200);
+        let (mut writer, rx) = register(&reactor, Some(pause));
+        let (mut other_writer, other_rx) = register(&reactor, None);
+
+        writer.write_all(b"one").unwrap();
+        let (event, first) = next(&rx);
+        assert_eq!(event, Event::Data(b"one".to_vec()));
+
+        // The paused reader doesn't hold up the others
+        writer.write_all(b"two").unwrap();
+        other_writer.write_all(b"other").unwrap();
+        let (event, other) = next(&other_rx);
+        assert_eq!(event, Event::Data(b"other".to_vec()));
+        assert!(other < first + pause);
+
+        let (event, second) = next(&rx);
+        assert_eq!(event, Event::Data(b"two".to_vec()));
+        assert!(second >= first + pause);
+    }
+}
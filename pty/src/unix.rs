@@ -201,6 +201,22 @@ impl MasterPty for UnixMasterPty {
         let fd = self.fd.try_clone()?;
         Ok(Box::new(fd))
     }
+
+    fn try_clone_pollable_reader(&self) -> Result<Option<FileDescriptor>, Error> {
+        // poll(2) doesn't support devices on macOS, which includes ptys
+        if cfg!(target_os = "macos") {
+            Ok(None)
+        } else {
+            Ok(Some(self.fd.try_clone()?))
+        }
+    }
+
+    fn process_group_leader(&self) -> Option<u32> {
+        match unsafe { libc::tcgetpgrp(self.fd.as_raw_fd()) } {
+            pid if pid > 0 => Some(pid as u32),
+            _ => None,
+        }
+    }
 }
 
 impl io::Write for UnixMasterPty {
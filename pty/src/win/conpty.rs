@@ -1,7 +1,7 @@
 use super::WinChild;
 use crate::cmdbuilder::CommandBuilder;
 use crate::{Child, MasterPty, PtyPair, PtySize, PtySystem, SlavePty};
-use anyhow::{bail, ensure, Error};
+use anyhow::{anyhow, bail, ensure, Error};
 use filedescriptor::{FileDescriptor, OwnedHandle, Pipe};
 use lazy_static::lazy_static;
 use shared_library::shared_library;
@@ -18,6 +18,8 @@ use winapi::shared::minwindef::DWORD;
 use winapi::shared::winerror::{HRESULT, S_OK};
 use winapi::um::handleapi::*;
 use winapi::um::processthreadsapi::*;
+use winapi::um::synchapi::WaitForSingleObject;
+use winapi::um::winbase::INFINITE;
 use winapi::um::winbase::STARTUPINFOEXW;
 use winapi::um::winbase::{CREATE_UNICODE_ENVIRONMENT, EXTENDED_STARTUPINFO_PRESENT};
 use winapi::um::wincon::COORD;
@@ -30,18 +32,11 @@ impl PtySystem for ConPtySystem {
         let stdin = Pipe::new()?;
         let stdout = Pipe::new()?;
 
-        let con = PsuedoCon::new(
-            COORD {
-                X: size.cols as i16,
-                Y: size.rows as i16,
-            },
-            &stdin.read,
-            &stdout.write,
-        )?;
+        let con = PsuedoCon::new(coord(size.rows, size.cols), &stdin.read, &stdout.write)?;
 
         let master = ConPtyMasterPty {
             inner: Arc::new(Mutex::new(Inner {
-                con,
+                con: Some(con),
                 readable: stdout.read,
                 writable: stdin.write,
                 size,
@@ -143,6 +138,18 @@ lazy_static! {
     );
 }
 
+/// Converts a size to the COORD used by the pseudo console api.
+/// The console can't be smaller than a single cell, and resizing
+/// it to zero rows or columns (eg: while the window is minimized)
+/// fails.
+fn coord(rows: u16, cols: u16) -> COORD {
+    let clamp = |value: u16| value.max(1).min(i16::max_value() as u16) as i16;
+    COORD {
+        X: clamp(cols),
+        Y: clamp(rows),
+    }
+}
+
 struct PsuedoCon {
     con: HPCON,
 }
@@ -186,7 +193,9 @@ impl PsuedoCon {
 }
 
 struct Inner {
-    con: PsuedoCon,
+    /// None once the process that was spawned into the console has
+    /// exited; see `ConPtySlavePty::spawn_command`
+    con: Option<PsuedoCon>,
     readable: FileDescriptor,
     writable: FileDescriptor,
     size: PtySize,
@@ -200,10 +209,12 @@ impl Inner {
         pixel_width: u16,
         pixel_height: u16,
     ) -> Result<(), Error> {
-        self.con.resize(COORD {
-            X: num_cols as i16,
-            Y: num_rows as i16,
-        })?;
+        if let Some(con) = self.con.as_ref() {
+            let unchanged = self.size.rows == num_rows && self.size.cols == num_cols;
+            if !unchanged {
+                con.resize(coord(num_rows, num_cols))?;
+            }
+        }
         self.size = PtySize {
             rows: num_rows,
             cols: num_cols,
@@ -251,18 +262,23 @@ impl io::Write for ConPtyMasterPty {
 impl SlavePty for ConPtySlavePty {
     fn spawn_command(&self, cmd: CommandBuilder) -> anyhow::Result<Box<dyn Child>> {
         let inner = self.inner.lock().unwrap();
+        let con = inner
+            .con
+            .as_ref()
+            .ok_or_else(|| anyhow!("the pseudo console has been closed"))?;
 
         let mut si: STARTUPINFOEXW = unsafe { mem::zeroed() };
         si.StartupInfo.cb = mem::size_of::<STARTUPINFOEXW>() as u32;
 
         let mut attrs = ProcThreadAttributeList::with_capacity(1)?;
-        attrs.set_pty(inner.con.con)?;
+        attrs.set_pty(con.con)?;
         si.lpAttributeList = attrs.as_mut_ptr();
 
         let mut pi: PROCESS_INFORMATION = unsafe { mem::zeroed() };
 
         let (mut exe, mut cmdline) = cmd.cmdline()?;
         let cmd_os = OsString::from_wide(&cmdline);
+        let cwd = cmd.current_directory();
         let res = unsafe {
             CreateProcessW(
                 exe.as_mut_slice().as_mut_ptr(),
@@ -272,7 +288,7 @@ impl SlavePty for ConPtySlavePty {
                 0,
                 EXTENDED_STARTUPINFO_PRESENT | CREATE_UNICODE_ENVIRONMENT,
                 cmd.environment_block().as_mut_slice().as_mut_ptr() as *mut _,
-                ptr::null_mut(),
+                cwd.as_ref().map(|c| c.as_ptr()).unwrap_or(ptr::null()),
                 &mut si.StartupInfo,
                 &mut pi,
             )
@@ -287,6 +303,20 @@ impl SlavePty for ConPtySlavePty {
         let _main_thread = unsafe { OwnedHandle::from_raw_handle(pi.hThread) };
         let proc = unsafe { OwnedHandle::from_raw_handle(pi.hProcess) };
 
+        // The console stays open for as long as any process is attached
+        // to it, which includes any background processes that the shell
+        // has started, and the output pipe doesn't reach EOF until it
+        // is closed.  Close it as soon as the child exits so that
+        // the tab notices that its process has gone.
+        let exited = proc.try_clone()?;
+        let weak = Arc::downgrade(&self.inner);
+        std::thread::spawn(move || {
+            unsafe { WaitForSingleObject(exited.as_raw_handle(), INFINITE) };
+            if let Some(inner) = weak.upgrade() {
+                inner.lock().unwrap().con.take();
+            }
+        });
+
         Ok(Box::new(WinChild { proc }))
     }
 }
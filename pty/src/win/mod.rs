@@ -55,4 +55,11 @@ impl Child for WinChild {
             Err(IoError::last_os_error())
         }
     }
+
+    fn process_id(&self) -> Option<u32> {
+        match unsafe { GetProcessId(self.proc.as_raw_handle()) } {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
 }
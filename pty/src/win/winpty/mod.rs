@@ -74,7 +74,7 @@ impl SlavePty for WinPtySlavePty {
             SpawnFlags::AUTO_SHUTDOWN | SpawnFlags::EXIT_AFTER_SHUTDOWN,
             Some(exe),
             Some(cmdline),
-            None, // cwd
+            cmd.current_directory(),
             None, // env
         )?;
 
@@ -0,0 +1,108 @@
+use serde_derive::*;
+use std::time::Duration;
+
+/// What happens when the bell rings in the active tab
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AudibleBell {
+    /// Sound the system bell
+    SystemBeep,
+    Disabled,
+}
+
+impl Default for AudibleBell {
+    fn default() -> Self {
+        AudibleBell::SystemBeep
+    }
+}
+
+/// The curve along which the visual bell fades in and out
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum EasingFunction {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// No fading; the flash is shown at full intensity throughout
+    Constant,
+}
+
+impl Default for EasingFunction {
+    fn default() -> Self {
+        EasingFunction::EaseInOut
+    }
+}
+
+impl EasingFunction {
+    /// Maps `t`, the fraction of the fade that has elapsed, to
+    /// the intensity of the flash.  Both range from 0.0 to 1.0.
+    pub fn evaluate(self, t: f32) -> f32 {
+        let t = t.max(0.).min(1.);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2. - t),
+            Self::EaseInOut => t * t * (3. - 2. * t),
+            Self::Constant => 1.,
+        }
+    }
+
+    /// Returns the intensity of a flash that fades in over `fade_in`
+    /// and then out over `fade_out`, `elapsed` after it started, or
+    /// None once it is over
+    pub fn flash_intensity(
+        self,
+        elapsed: Duration,
+        fade_in: Duration,
+        fade_out: Duration,
+    ) -> Option<f32> {
+        if elapsed < fade_in {
+            Some(self.evaluate(elapsed.as_secs_f32() / fade_in.as_secs_f32()))
+        } else if elapsed < fade_in + fade_out {
+            let remaining = fade_in + fade_out - elapsed;
+            Some(self.evaluate(remaining.as_secs_f32() / fade_out.as_secs_f32()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn easing() {
+        for func in &[
+            EasingFunction::Linear,
+            EasingFunction::EaseIn,
+            EasingFunction::EaseOut,
+            EasingFunction::EaseInOut,
+        ] {
+            assert_eq!(func.evaluate(0.), 0.);
+            assert_eq!(func.evaluate(1.), 1.);
+            assert_eq!(func.evaluate(2.), 1.);
+        }
+        assert_eq!(EasingFunction::Linear.evaluate(0.25), 0.25);
+        assert_eq!(EasingFunction::EaseIn.evaluate(0.5), 0.25);
+        assert_eq!(EasingFunction::EaseOut.evaluate(0.5), 0.75);
+        assert_eq!(EasingFunction::EaseInOut.evaluate(0.5), 0.5);
+        assert_eq!(EasingFunction::Constant.evaluate(0.), 1.);
+    }
+
+    #[test]
+    fn flash() {
+        let ms = Duration::from_millis;
+        let linear = EasingFunction::Linear;
+        assert_eq!(linear.flash_intensity(ms(0), ms(100), ms(200)), Some(0.));
+        assert_eq!(linear.flash_intensity(ms(50), ms(100), ms(200)), Some(0.5));
+        assert_eq!(linear.flash_intensity(ms(100), ms(100), ms(200)), Some(1.));
+        assert_eq!(
+            linear.flash_intensity(ms(250), ms(100), ms(200)),
+            Some(0.25)
+        );
+        assert_eq!(linear.flash_intensity(ms(300), ms(100), ms(200)), None);
+
+        // A zero length fade in starts at full intensity
+        assert_eq!(linear.flash_intensity(ms(0), ms(0), ms(200)), Some(1.));
+    }
+}
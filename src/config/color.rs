@@ -2,7 +2,7 @@ use crate::config::*;
 use termwiz::cell::CellAttributes;
 use termwiz::color::{ColorSpec, RgbColor};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct Palette {
     /// The text color to use when the attributes are reset to default
     pub foreground: Option<RgbColor>,
@@ -25,6 +25,49 @@ pub struct Palette {
     /// The color of the "thumb" of the scrollbar; the segment that
     /// represents the current viewable area
     pub scrollbar_thumb: Option<RgbColor>,
+    /// The color of the scrollbar thumb while the mouse is over it
+    pub scrollbar_thumb_hover: Option<RgbColor>,
+    /// The color of the divider between split panes
+    pub split: Option<RgbColor>,
+    /// The color that is blended into the background of the inactive
+    /// panes; see `inactive_pane_tint_amount`
+    pub inactive_pane_tint: Option<RgbColor>,
+    /// The color that the background flashes to when the visual
+    /// bell rings; the foreground color is used if this is not set
+    pub visual_bell: Option<RgbColor>,
+}
+
+impl Palette {
+    /// Returns a copy of this palette in which the colors that are
+    /// specified by `other` replace those of this palette
+    pub fn overlay_with(&self, other: &Self) -> Self {
+        macro_rules! overlay {
+            ($($name:ident),* $(,)?) => {
+                Self {
+                    $(
+                    $name: other.$name.clone().or_else(|| self.$name.clone()),
+                    )*
+                }
+            };
+        }
+        overlay!(
+            foreground,
+            background,
+            cursor_fg,
+            cursor_bg,
+            cursor_border,
+            selection_fg,
+            selection_bg,
+            ansi,
+            brights,
+            tab_bar,
+            scrollbar_thumb,
+            scrollbar_thumb_hover,
+            split,
+            inactive_pane_tint,
+            visual_bell,
+        )
+    }
 }
 
 impl From<Palette> for term::color::ColorPalette {
@@ -45,6 +88,8 @@ impl From<Palette> for term::color::ColorPalette {
         apply_color!(selection_fg);
         apply_color!(selection_bg);
         apply_color!(scrollbar_thumb);
+        apply_color!(scrollbar_thumb_hover);
+        apply_color!(split);
 
         if let Some(ansi) = cfg.ansi {
             for (idx, col) in ansi.iter().enumerate() {
@@ -108,6 +153,14 @@ pub struct TabBarColors {
     pub inactive_tab: TabBarColor,
     /// Styling for an inactive tab with a mouse hovering
     pub inactive_tab_hover: TabBarColor,
+    /// Styling for the `+` button that spawns a new tab.
+    /// Defaults to the `inactive_tab` styling.
+    #[serde(default)]
+    pub new_tab: Option<TabBarColor>,
+    /// Styling for the `+` button with a mouse hovering.
+    /// Defaults to the `inactive_tab_hover` styling.
+    #[serde(default)]
+    pub new_tab_hover: Option<TabBarColor>,
 }
 
 impl Default for TabBarColors {
@@ -130,6 +183,8 @@ impl Default for TabBarColors {
                 fg_color: RgbColor::new(0xc0, 0xc0, 0xc0),
                 ..TabBarColor::default()
             },
+            new_tab: None,
+            new_tab_hover: None,
         }
     }
 }
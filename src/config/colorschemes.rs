@@ -0,0 +1,133 @@
+//! A library of color schemes that can be selected by name using
+//! the `color_scheme` configuration option.
+use crate::config::Palette;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+/// The name and definition of each of the built-in schemes.
+/// The definitions take the same form as the `[colors]` section
+/// of the configuration file.
+const SCHEMES: &[(&str, &str)] = &[
+    (
+        "Dracula",
+        r##"
+foreground = "#f8f8f2"
+background = "#282a36"
+cursor_fg = "#282a36"
+cursor_bg = "#f8f8f2"
+cursor_border = "#f8f8f2"
+selection_fg = "#f8f8f2"
+selection_bg = "#44475a"
+ansi = ["#21222c", "#ff5555", "#50fa7b", "#f1fa8c", "#bd93f9", "#ff79c6", "#8be9fd", "#f8f8f2"]
+brights = ["#6272a4", "#ff6e6e", "#69ff94", "#ffffa5", "#d6acff", "#ff92df", "#a4ffff", "#ffffff"]
+"##,
+    ),
+    (
+        "Gruvbox Dark",
+        r##"
+foreground = "#ebdbb2"
+background = "#282828"
+cursor_fg = "#282828"
+cursor_bg = "#ebdbb2"
+cursor_border = "#ebdbb2"
+selection_fg = "#282828"
+selection_bg = "#ebdbb2"
+ansi = ["#282828", "#cc241d", "#98971a", "#d79921", "#458588", "#b16286", "#689d6a", "#a89984"]
+brights = ["#928374", "#fb4934", "#b8bb26", "#fabd2f", "#83a598", "#d3869b", "#8ec07c", "#ebdbb2"]
+"##,
+    ),
+    (
+        "Nord",
+        r##"
+foreground = "#d8dee9"
+background = "#2e3440"
+cursor_fg = "#2e3440"
+cursor_bg = "#d8dee9"
+cursor_border = "#d8dee9"
+selection_fg = "#d8dee9"
+selection_bg = "#434c5e"
+ansi = ["#3b4252", "#bf616a", "#a3be8c", "#ebcb8b", "#81a1c1", "#b48ead", "#88c0d0", "#e5e9f0"]
+brights = ["#4c566a", "#bf616a", "#a3be8c", "#ebcb8b", "#81a1c1", "#b48ead", "#8fbcbb", "#eceff4"]
+"##,
+    ),
+    (
+        "Solarized Dark",
+        r##"
+foreground = "#839496"
+background = "#002b36"
+cursor_fg = "#002b36"
+cursor_bg = "#93a1a1"
+cursor_border = "#93a1a1"
+selection_fg = "#93a1a1"
+selection_bg = "#073642"
+ansi = ["#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5"]
+brights = ["#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3"]
+"##,
+    ),
+    (
+        "Solarized Light",
+        r##"
+foreground = "#657b83"
+background = "#fdf6e3"
+cursor_fg = "#fdf6e3"
+cursor_bg = "#586e75"
+cursor_border = "#586e75"
+selection_fg = "#586e75"
+selection_bg = "#eee8d5"
+ansi = ["#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5"]
+brights = ["#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3"]
+"##,
+    ),
+    (
+        "Tomorrow Night",
+        r##"
+foreground = "#c5c8c6"
+background = "#1d1f21"
+cursor_fg = "#1d1f21"
+cursor_bg = "#c5c8c6"
+cursor_border = "#c5c8c6"
+selection_fg = "#c5c8c6"
+selection_bg = "#373b41"
+ansi = ["#000000", "#cc6666", "#b5bd68", "#f0c674", "#81a2be", "#b294bb", "#8abeb7", "#ffffff"]
+brights = ["#4d4d4d", "#cc6666", "#b5bd68", "#f0c674", "#81a2be", "#b294bb", "#8abeb7", "#ffffff"]
+"##,
+    ),
+];
+
+lazy_static! {
+    static ref BUILTIN_SCHEMES: HashMap<&'static str, Palette> = SCHEMES
+        .iter()
+        .map(|(name, data)| {
+            let palette = toml::from_str(data)
+                .unwrap_or_else(|err| panic!("invalid built-in color scheme {}: {}", name, err));
+            (*name, palette)
+        })
+        .collect();
+}
+
+/// Returns the built-in color scheme with the given name
+pub fn builtin_color_scheme(name: &str) -> Option<Palette> {
+    BUILTIN_SCHEMES.get(name).cloned()
+}
+
+/// Returns the names of the built-in color schemes
+pub fn builtin_color_scheme_names() -> Vec<&'static str> {
+    SCHEMES.iter().map(|(name, _)| *name).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builtin_schemes_parse() {
+        for name in builtin_color_scheme_names() {
+            let scheme = builtin_color_scheme(name).unwrap();
+            assert!(scheme.foreground.is_some(), "{} has no foreground", name);
+            assert!(scheme.background.is_some(), "{} has no background", name);
+            assert!(scheme.ansi.is_some(), "{} has no ansi colors", name);
+            assert!(scheme.brights.is_some(), "{} has no bright colors", name);
+        }
+        assert!(builtin_color_scheme("No Such Scheme").is_none());
+    }
+}
@@ -41,7 +41,7 @@ impl Default for FontAntiAliasing {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct FontAttributes {
     /// The font family name
     pub family: String,
@@ -51,6 +51,32 @@ pub struct FontAttributes {
     /// Whether the font should be an italic variant
     #[serde(default)]
     pub italic: bool,
+    /// Scales the size at which glyphs from this font are rendered,
+    /// relative to `font_size`.  This is useful for fallback fonts
+    /// that look too large or too small next to the primary font.
+    pub scale: Option<f64>,
+}
+
+// f64 is neither Eq nor Hash, so we compare and hash the bits
+// of the scale; TextStyle is used as a cache key.
+impl PartialEq for FontAttributes {
+    fn eq(&self, other: &Self) -> bool {
+        self.family == other.family
+            && self.bold == other.bold
+            && self.italic == other.italic
+            && self.scale.map(f64::to_bits) == other.scale.map(f64::to_bits)
+    }
+}
+
+impl Eq for FontAttributes {}
+
+impl std::hash::Hash for FontAttributes {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.family.hash(state);
+        self.bold.hash(state);
+        self.italic.hash(state);
+        self.scale.map(f64::to_bits).hash(state);
+    }
 }
 
 impl FontAttributes {
@@ -59,6 +85,7 @@ impl FontAttributes {
             family: family.into(),
             bold: false,
             italic: false,
+            scale: None,
         }
     }
 }
@@ -69,6 +96,7 @@ impl Default for FontAttributes {
             family: FONT_FAMILY.into(),
             bold: false,
             italic: false,
+            scale: None,
         }
     }
 }
@@ -1,5 +1,5 @@
-use crate::keyassignment::{KeyAssignment, SpawnTabDomain};
-use anyhow::{anyhow, Error};
+use crate::keyassignment::{KeyAssignment, MouseBindingButton, MouseEventTrigger, SpawnTabDomain};
+use anyhow::{anyhow, bail, Error};
 use serde::{Deserialize, Deserializer};
 use serde_derive::*;
 use termwiz::input::{KeyCode, Modifiers};
@@ -14,7 +14,71 @@ pub struct Key {
     pub arg: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct MouseBinding {
+    pub event: MouseEventTrigger,
+    pub button: MouseBindingButton,
+    /// The number of clicks in quick succession; 2 for a double click
+    #[serde(default = "default_streak")]
+    pub streak: usize,
+    #[serde(default, deserialize_with = "de_modifiers")]
+    pub mods: Modifiers,
+    pub action: KeyAction,
+    pub arg: Option<String>,
+}
+
+fn default_streak() -> usize {
+    1
+}
+
+/// A prefix key, in the style of tmux; the key that is pressed after
+/// it is looked up in the `leader_keys` bindings rather than `keys`
+#[derive(Debug, Deserialize, Clone)]
+pub struct LeaderKey {
+    #[serde(deserialize_with = "de_keycode")]
+    pub key: KeyCode,
+    #[serde(default, deserialize_with = "de_modifiers")]
+    pub mods: Modifiers,
+    /// How long to wait for the next key before giving up on it
+    #[serde(default = "default_leader_timeout")]
+    pub timeout_milliseconds: u64,
+}
+
+fn default_leader_timeout() -> u64 {
+    1000
+}
+
+/// An action without a key binding; this is the form in which
+/// lua event handlers return the action that they want performed
+#[derive(Debug, Deserialize, Clone)]
+pub struct Action {
+    pub action: KeyAction,
+    pub arg: Option<String>,
+}
+
 impl std::convert::TryInto<KeyAssignment> for &Key {
+    type Error = Error;
+    fn try_into(self) -> Result<KeyAssignment, Error> {
+        let action = Action {
+            action: self.action.clone(),
+            arg: self.arg.clone(),
+        };
+        (&action).try_into()
+    }
+}
+
+impl std::convert::TryInto<KeyAssignment> for &MouseBinding {
+    type Error = Error;
+    fn try_into(self) -> Result<KeyAssignment, Error> {
+        let action = Action {
+            action: self.action.clone(),
+            arg: self.arg.clone(),
+        };
+        (&action).try_into()
+    }
+}
+
+impl std::convert::TryInto<KeyAssignment> for &Action {
     type Error = Error;
     fn try_into(self) -> Result<KeyAssignment, Error> {
         Ok(match self.action {
@@ -38,6 +102,18 @@ impl std::convert::TryInto<KeyAssignment> for &Key {
             KeyAction::ToggleFullScreen => KeyAssignment::ToggleFullScreen,
             KeyAction::Copy => KeyAssignment::Copy,
             KeyAction::Paste => KeyAssignment::Paste,
+            KeyAction::CopyTo => KeyAssignment::CopyTo(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::PasteFrom => KeyAssignment::PasteFrom(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
             KeyAction::Hide => KeyAssignment::Hide,
             KeyAction::Show => KeyAssignment::Show,
             KeyAction::IncreaseFontSize => KeyAssignment::IncreaseFontSize,
@@ -63,6 +139,21 @@ impl std::convert::TryInto<KeyAssignment> for &Key {
                     .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
                     .to_owned(),
             ),
+            KeyAction::SendKey => {
+                let arg = self
+                    .arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?;
+                // Either just the key, or the modifiers followed by the key
+                let words: Vec<&str> = arg.split_whitespace().collect();
+                match words.as_slice() {
+                    [key] => KeyAssignment::SendKey(parse_keycode(key)?, Modifiers::NONE),
+                    [mods, key] => {
+                        KeyAssignment::SendKey(parse_keycode(key)?, parse_modifiers(mods)?)
+                    }
+                    _ => bail!("invalid key {:?} for {:?}", arg, self),
+                }
+            }
             KeyAction::ReloadConfiguration => KeyAssignment::ReloadConfiguration,
             KeyAction::MoveTab => KeyAssignment::MoveTab(
                 self.arg
@@ -76,6 +167,101 @@ impl std::convert::TryInto<KeyAssignment> for &Key {
                     .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
                     .parse()?,
             ),
+            KeyAction::MoveTabToNewWindow => KeyAssignment::MoveTabToNewWindow,
+            KeyAction::MergeAllWindows => KeyAssignment::MergeAllWindows,
+            KeyAction::SetTabTitle => {
+                KeyAssignment::SetTabTitle(self.arg.clone().unwrap_or_else(String::new))
+            }
+            KeyAction::SplitPane => KeyAssignment::SplitPane(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::ActivatePaneDirection => KeyAssignment::ActivatePaneDirection(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::AdjustPaneSize => {
+                let arg = self
+                    .arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?;
+                let mut words = arg.split_whitespace();
+                let direction = words
+                    .next()
+                    .ok_or_else(|| anyhow!("missing direction for {:?}", self))?
+                    .parse()?;
+                let amount = match words.next() {
+                    Some(amount) => amount.parse()?,
+                    None => 1,
+                };
+                KeyAssignment::AdjustPaneSize(direction, amount)
+            }
+            KeyAction::TogglePaneZoomState => KeyAssignment::TogglePaneZoomState,
+            KeyAction::CloseCurrentPane => KeyAssignment::CloseCurrentPane,
+            KeyAction::ClearScrollback => KeyAssignment::ClearScrollback,
+            KeyAction::ExportScrollback => KeyAssignment::ExportScrollback(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::QuickSelect => KeyAssignment::QuickSelect,
+            KeyAction::ShowLauncher => KeyAssignment::ShowLauncher,
+            KeyAction::ShowClipboardHistory => KeyAssignment::ShowClipboardHistory,
+            KeyAction::ShowUnicodeInput => KeyAssignment::ShowUnicodeInput,
+            KeyAction::ScrollByPage => KeyAssignment::ScrollByPage(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::ScrollByLine => KeyAssignment::ScrollByLine(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::ScrollToPrompt => KeyAssignment::ScrollToPrompt(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::ScrollToPreviousPrompt => KeyAssignment::ScrollToPreviousPrompt,
+            KeyAction::ScrollToNextPrompt => KeyAssignment::ScrollToNextPrompt,
+            KeyAction::ScrollToTop => KeyAssignment::ScrollToTop,
+            KeyAction::ScrollToBottom => KeyAssignment::ScrollToBottom,
+            KeyAction::EmitEvent => KeyAssignment::EmitEvent(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .to_owned(),
+            ),
+            KeyAction::SetColorScheme => KeyAssignment::SetColorScheme(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .to_owned(),
+            ),
+            KeyAction::SetProfile => {
+                KeyAssignment::SetProfile(self.arg.clone().unwrap_or_else(String::new))
+            }
+            KeyAction::SelectTextAtMouseCursor => KeyAssignment::SelectTextAtMouseCursor(
+                self.arg
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing arg for {:?}", self))?
+                    .parse()?,
+            ),
+            KeyAction::ExtendSelectionToMouseCursor => KeyAssignment::ExtendSelectionToMouseCursor,
+            KeyAction::CompleteSelection => KeyAssignment::CompleteSelection,
+            KeyAction::CompleteSelectionOrOpenLinkAtMouseCursor => {
+                KeyAssignment::CompleteSelectionOrOpenLinkAtMouseCursor
+            }
+            KeyAction::OpenLinkAtMouseCursor => KeyAssignment::OpenLinkAtMouseCursor,
         })
     }
 }
@@ -89,12 +275,15 @@ pub enum KeyAction {
     ToggleFullScreen,
     Copy,
     Paste,
+    CopyTo,
+    PasteFrom,
     ActivateTabRelative,
     IncreaseFontSize,
     DecreaseFontSize,
     ResetFontSize,
     ActivateTab,
     SendString,
+    SendKey,
     Nop,
     Hide,
     Show,
@@ -102,6 +291,35 @@ pub enum KeyAction {
     ReloadConfiguration,
     MoveTab,
     MoveTabRelative,
+    MoveTabToNewWindow,
+    MergeAllWindows,
+    SetTabTitle,
+    SplitPane,
+    ActivatePaneDirection,
+    AdjustPaneSize,
+    TogglePaneZoomState,
+    CloseCurrentPane,
+    ClearScrollback,
+    ExportScrollback,
+    QuickSelect,
+    ShowLauncher,
+    ShowClipboardHistory,
+    ShowUnicodeInput,
+    ScrollByPage,
+    ScrollByLine,
+    ScrollToPrompt,
+    ScrollToPreviousPrompt,
+    ScrollToNextPrompt,
+    ScrollToTop,
+    ScrollToBottom,
+    EmitEvent,
+    SetColorScheme,
+    SetProfile,
+    SelectTextAtMouseCursor,
+    ExtendSelectionToMouseCursor,
+    CompleteSelection,
+    CompleteSelectionOrOpenLinkAtMouseCursor,
+    OpenLinkAtMouseCursor,
 }
 
 fn de_keycode<'de, D>(deserializer: D) -> Result<KeyCode, D::Error>
@@ -109,7 +327,10 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
+    parse_keycode(&s).map_err(serde::de::Error::custom)
+}
 
+fn parse_keycode(s: &str) -> anyhow::Result<KeyCode> {
     macro_rules! m {
         ($($val:ident),* $(,)?) => {
             $(
@@ -202,12 +423,9 @@ where
     );
 
     if s.len() > 1 && s.starts_with('F') {
-        let num: u8 = s[1..].parse().map_err(|_| {
-            serde::de::Error::custom(format!(
-                "expected F<NUMBER> function key string, got: {}",
-                s
-            ))
-        })?;
+        let num: u8 = s[1..]
+            .parse()
+            .map_err(|_| anyhow!("expected F<NUMBER> function key string, got: {}", s))?;
         return Ok(KeyCode::Function(num));
     }
 
@@ -215,18 +433,19 @@ where
     if chars.len() == 1 {
         Ok(KeyCode::Char(chars[0]))
     } else {
-        Err(serde::de::Error::custom(format!(
-            "invalid KeyCode string {}",
-            s
-        )))
+        Err(anyhow!("invalid KeyCode string {}", s))
     }
 }
 
-fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
+pub(crate) fn de_modifiers<'de, D>(deserializer: D) -> Result<Modifiers, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
+    parse_modifiers(&s).map_err(serde::de::Error::custom)
+}
+
+fn parse_modifiers(s: &str) -> anyhow::Result<Modifiers> {
     let mut mods = Modifiers::NONE;
     for ele in s.split('|') {
         if ele == "SHIFT" {
@@ -240,10 +459,7 @@ where
         } else if ele == "NONE" || ele == "" {
             mods |= Modifiers::NONE;
         } else {
-            return Err(serde::de::Error::custom(format!(
-                "invalid modifier name {} in {}",
-                ele, s
-            )));
+            return Err(anyhow!("invalid modifier name {} in {}", ele, s));
         }
     }
     Ok(mods)
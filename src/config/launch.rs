@@ -0,0 +1,40 @@
+use crate::config::ExitBehavior;
+use serde_derive::*;
+use std::path::PathBuf;
+
+/// An entry in the launcher menu
+#[derive(Default, Debug, Clone, Deserialize, PartialEq)]
+pub struct LaunchMenuItem {
+    /// The text shown for this entry in the launcher.  If omitted,
+    /// a label is made from the command or serial port.
+    #[serde(default)]
+    pub label: Option<String>,
+
+    /// The command to run.  If omitted, the default program for
+    /// the domain is run.
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+
+    /// The directory in which to run the command
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+
+    /// The name of the domain in which to spawn the command.
+    /// If omitted, the default domain is used.
+    #[serde(default)]
+    pub domain: Option<String>,
+
+    /// If set, this entry opens the named serial port rather than
+    /// running a command
+    #[serde(default)]
+    pub serial_port: Option<String>,
+
+    /// The baud rate for `serial_port`
+    #[serde(default)]
+    pub baud: Option<usize>,
+
+    /// What to do with the tab when the program exits, overriding
+    /// the `exit_behavior` option
+    #[serde(default)]
+    pub exit_behavior: Option<ExitBehavior>,
+}
@@ -0,0 +1,189 @@
+//! The configuration may be written in lua rather than TOML.
+//! That allows it to compute values at load time and to register
+//! functions that are called when certain events occur.
+//! The lua context is retained alongside the configuration so that
+//! those functions can be called later on.
+use crate::config::HOME_DIR;
+use crate::keyassignment::KeyAssignment;
+use mlua::{Function, Lua, LuaSerdeExt, Table, ToLuaMulti, Value};
+use std::convert::TryInto;
+use std::path::Path;
+
+/// Creates a lua context in which to evaluate the configuration
+/// file at `config_path`.  The `wezterm` module is made available
+/// both as a global and via `require "wezterm"`, and other modules
+/// can be loaded from the directory that contains the config file.
+pub fn make_lua_context(config_path: &Path) -> anyhow::Result<Lua> {
+    let lua = Lua::new();
+
+    {
+        let globals = lua.globals();
+        let package: Table = globals.get("package")?;
+
+        if let Some(config_dir) = config_path.parent() {
+            let path: String = package.get("path")?;
+            package.set("path", format!("{}/?.lua;{}", config_dir.display(), path))?;
+        }
+
+        let wezterm_mod = lua.create_table()?;
+        wezterm_mod.set(
+            "config_dir",
+            config_path.parent().map(|dir| dir.display().to_string()),
+        )?;
+        wezterm_mod.set("home_dir", HOME_DIR.display().to_string())?;
+        wezterm_mod.set("on", lua.create_function(register_event)?)?;
+        wezterm_mod.set(
+            "log_info",
+            lua.create_function(|_, msg: String| {
+                log::info!("lua: {}", msg);
+                Ok(())
+            })?,
+        )?;
+        wezterm_mod.set(
+            "log_error",
+            lua.create_function(|_, msg: String| {
+                log::error!("lua: {}", msg);
+                Ok(())
+            })?,
+        )?;
+
+        let loaded: Table = package.get("loaded")?;
+        loaded.set("wezterm", wezterm_mod.clone())?;
+        globals.set("wezterm", wezterm_mod)?;
+    }
+
+    Ok(lua)
+}
+
+fn event_key(name: &str) -> String {
+    format!("wezterm-event-{}", name)
+}
+
+/// Implements `wezterm.on(name, func)`, which arranges for `func`
+/// to be called when the event `name` is emitted.  Several functions
+/// may be registered for the same event; they are called in the
+/// order in which they were registered.
+fn register_event<'lua>(
+    lua: &'lua Lua,
+    (name, func): (String, Function<'lua>),
+) -> mlua::Result<()> {
+    let key = event_key(&name);
+    match lua.named_registry_value(&key)? {
+        Value::Table(tbl) => tbl.raw_set(tbl.raw_len() + 1, func),
+        _ => {
+            let tbl = lua.create_table()?;
+            tbl.raw_set(1, func)?;
+            lua.set_named_registry_value(&key, tbl)
+        }
+    }
+}
+
+/// Calls the functions registered for the event `name` with `args`,
+/// stopping at the first one that returns something other than nil.
+/// Returns that value, or nil if no function returned a value.
+pub fn emit_event<'lua, A>(lua: &'lua Lua, name: &str, args: A) -> mlua::Result<Value<'lua>>
+where
+    A: ToLuaMulti<'lua> + Clone,
+{
+    if let Value::Table(tbl) = lua.named_registry_value(&event_key(name))? {
+        for func in tbl.sequence_values::<Function>() {
+            match func?.call(args.clone())? {
+                Value::Nil => {}
+                value => return Ok(value),
+            }
+        }
+    }
+    Ok(Value::Nil)
+}
+
+/// Emits `format-window-title`, allowing the configuration to
+/// decide how the window title is presented.  The handlers are
+/// passed the title of the active tab, the 1-based index of that
+/// tab and the number of tabs in the window.
+/// Returns None if no handler produced a title.
+pub fn format_window_title(
+    lua: &Lua,
+    title: &str,
+    tab_no: usize,
+    num_tabs: usize,
+) -> anyhow::Result<Option<String>> {
+    let value = emit_event(
+        lua,
+        "format-window-title",
+        (title.to_string(), tab_no + 1, num_tabs),
+    )?;
+    Ok(lua.from_value(value)?)
+}
+
+/// Emits the event `name` on behalf of an `EmitEvent` key assignment.
+/// The handlers are passed the id of the active tab and may return
+/// an action table such as `{action="SpawnTab"}` or
+/// `{action="SendString", arg="hello"}`, using the same action names
+/// as the `keys` configuration, which is then performed by the caller.
+/// Returning another `EmitEvent` action is an error, as that could
+/// otherwise recurse without limit.
+pub fn emit_key_event(
+    lua: &Lua,
+    name: &str,
+    tab_id: usize,
+) -> anyhow::Result<Option<KeyAssignment>> {
+    let value = emit_event(lua, name, tab_id)?;
+    let action: Option<super::Action> = lua.from_value(value)?;
+    match action {
+        Some(action) => match (&action).try_into()? {
+            KeyAssignment::EmitEvent(nested) => anyhow::bail!(
+                "the handler for event {} returned EmitEvent {}, which is not allowed",
+                name,
+                nested
+            ),
+            action => Ok(Some(action)),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let lua = make_lua_context(Path::new("/tmp/wezterm.lua")).unwrap();
+        lua.load(
+            r#"
+            local wezterm = require "wezterm";
+            wezterm.on("format-window-title", function(title, tab_no, num_tabs)
+                if num_tabs > 1 then
+                    return title .. " (" .. tab_no .. "/" .. num_tabs .. ")"
+                end
+            end);
+            wezterm.on("format-window-title", function(title)
+                return "only " .. title
+            end);
+            wezterm.on("my-event", function(tab_id)
+                return {action="SendString", arg="tab " .. tab_id}
+            end);
+            wezterm.on("loop", function(tab_id)
+                return {action="EmitEvent", arg="loop"}
+            end);
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        assert_eq!(
+            format_window_title(&lua, "foo", 1, 3).unwrap(),
+            Some("foo (2/3)".to_string())
+        );
+        assert_eq!(
+            format_window_title(&lua, "foo", 0, 1).unwrap(),
+            Some("only foo".to_string())
+        );
+        match emit_key_event(&lua, "my-event", 4).unwrap() {
+            Some(KeyAssignment::SendString(s)) => assert_eq!(s, "tab 4"),
+            other => panic!("unexpected action {:?}", other),
+        }
+        assert!(emit_key_event(&lua, "no-handlers", 4).unwrap().is_none());
+        assert!(emit_key_event(&lua, "loop", 4).is_err());
+    }
+}
@@ -5,9 +5,13 @@ use crate::font::locator::FontLocatorSelection;
 use crate::font::rasterizer::FontRasterizerSelection;
 use crate::font::shaper::FontShaperSelection;
 use crate::frontend::FrontEndSelection;
-use crate::keyassignment::KeyAssignment;
+use crate::keyassignment::{
+    ClipboardCopyDestination, ClipboardPasteSource, KeyAssignment, MouseBindingButton,
+    MouseEventTrigger,
+};
 use anyhow::{anyhow, bail, Context, Error};
 use lazy_static::lazy_static;
+use mlua::LuaSerdeExt;
 use portable_pty::{CommandBuilder, PtySystemSelection};
 use serde_derive::*;
 use std;
@@ -25,18 +29,28 @@ use termwiz::input::{KeyCode, Modifiers};
 use termwiz::surface::CursorShape;
 use toml;
 
+mod bell;
 mod color;
+mod colorschemes;
 mod daemon;
 mod font;
 mod keys;
+mod launch;
+mod lua;
+mod profile;
 mod ssh;
 mod terminal;
 mod tls;
 mod unix;
+pub use bell::*;
 pub use color::*;
+pub use colorschemes::*;
 pub use daemon::*;
 pub use font::*;
 pub use keys::*;
+pub use launch::*;
+pub use lua::*;
+pub use profile::*;
 pub use ssh::*;
 pub use terminal::*;
 pub use tls::*;
@@ -48,6 +62,12 @@ lazy_static! {
     static ref CONFIG: Configuration = Configuration::new();
 }
 
+/// Returns the error encountered while loading the configuration,
+/// if the most recent attempt to load it failed
+pub fn configuration_error() -> Option<String> {
+    CONFIG.get_error()
+}
+
 /// Discard the current configuration and replace it with
 /// the default configuration
 #[allow(dead_code)]
@@ -64,13 +84,22 @@ pub fn reload() {
     CONFIG.reload();
 }
 
-/// If there was an error loading the preferred configuration,
-/// return it, otherwise return the current configuration
-pub fn configuration_result() -> Result<ConfigHandle, Error> {
-    if let Some(error) = CONFIG.get_error() {
-        bail!("{}", error);
-    }
-    Ok(CONFIG.get())
+/// Switch to the named color scheme until the configuration
+/// is next reloaded
+pub fn set_color_scheme(name: &str) -> anyhow::Result<()> {
+    CONFIG.set_color_scheme(name)
+}
+
+/// If the configuration was written in lua, calls `func` with the
+/// lua context in which it was evaluated so that any event handlers
+/// that it registered can be called.  Returns None otherwise.
+pub fn with_lua_config<F, T>(func: F) -> Option<T>
+where
+    F: FnOnce(&mlua::Lua) -> T,
+{
+    let lua = CONFIG.get_lua()?;
+    let lua = lua.lock().unwrap();
+    Some(func(&lua))
 }
 
 struct ConfigInner {
@@ -78,6 +107,7 @@ struct ConfigInner {
     error: Option<String>,
     generation: usize,
     watcher: Option<notify::RecommendedWatcher>,
+    lua: Option<Arc<Mutex<mlua::Lua>>>,
 }
 
 impl ConfigInner {
@@ -87,6 +117,7 @@ impl ConfigInner {
             error: None,
             generation: 0,
             watcher: None,
+            lua: None,
         }
     }
 
@@ -144,8 +175,10 @@ impl ConfigInner {
     /// replace any captured error message.
     fn reload(&mut self) {
         match Config::load() {
-            Ok((config, path)) => {
+            Ok((config, path, lua)) => {
                 self.config = Arc::new(config);
+                self.apply_global_settings();
+                self.lua = lua.map(|lua| Arc::new(Mutex::new(lua)));
                 self.error.take();
                 self.generation += 1;
                 log::debug!("Reloaded configuration! generation={}", self.generation);
@@ -156,17 +189,27 @@ impl ConfigInner {
             Err(err) => {
                 let err = format!("{:#}", err);
                 log::error!("While (re)loading configuration: {}", err);
-                crate::toast_notification("Wezterm Configuration", &err);
+                crate::frontend::show_configuration_error_message(&err);
                 self.error.replace(err);
             }
         }
     }
 
+    /// Applies the settings that are held as process-wide state
+    /// outside of the configuration
+    fn apply_global_settings(&self) {
+        termwiz::cell::set_east_asian_ambiguous_is_wide(
+            self.config.treat_east_asian_ambiguous_width_as_wide,
+        );
+    }
+
     /// Discard the current configuration and any recorded
     /// error message; replace them with the default
     /// configuration
     fn use_defaults(&mut self) {
         self.config = Arc::new(Config::default_config());
+        self.apply_global_settings();
+        self.lua.take();
         self.error.take();
         self.generation += 1;
     }
@@ -204,20 +247,31 @@ impl Configuration {
         inner.reload();
     }
 
+    /// Returns the lua context that the configuration was
+    /// evaluated in, if it was written in lua
+    fn get_lua(&self) -> Option<Arc<Mutex<mlua::Lua>>> {
+        let inner = self.inner.lock().unwrap();
+        inner.lua.as_ref().map(Arc::clone)
+    }
+
+    /// Switch the effective configuration to the named color scheme.
+    /// The change lasts until the configuration is next reloaded.
+    pub fn set_color_scheme(&self, name: &str) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut config = (*inner.config).clone();
+        config.color_scheme = Some(name.to_string());
+        config.check_color_scheme()?;
+        inner.config = Arc::new(config);
+        inner.generation += 1;
+        Ok(())
+    }
+
     /// Returns a copy of any captured error message.
     /// The error message is not cleared.
     pub fn get_error(&self) -> Option<String> {
         let inner = self.inner.lock().unwrap();
         inner.error.as_ref().cloned()
     }
-
-    /// Returns any captured error message, and clears
-    /// it from the config state.
-    #[allow(dead_code)]
-    pub fn clear_error(&self) -> Option<String> {
-        let mut inner = self.inner.lock().unwrap();
-        inner.error.take()
-    }
 }
 
 #[derive(Clone, Debug)]
@@ -272,6 +326,16 @@ pub struct Config {
     /// The color palette
     pub colors: Option<Palette>,
 
+    /// The name of a color scheme to use as the basis of the
+    /// palette; either one of the built-in schemes or one defined
+    /// in `color_schemes`.  Colors specified in `colors` take
+    /// precedence over those from the scheme.
+    pub color_scheme: Option<String>,
+
+    /// Additional color schemes, keyed by name
+    #[serde(default)]
+    pub color_schemes: HashMap<String, Palette>,
+
     /// How many lines of scrollback you want to retain
     #[serde(default = "default_scrollback_lines")]
     pub scrollback_lines: usize,
@@ -293,10 +357,156 @@ pub struct Config {
     #[serde(default = "default_hyperlink_rules")]
     pub hyperlink_rules: Vec<hyperlink::Rule>,
 
+    /// The modifiers that must be held down when clicking on a
+    /// hyperlink in order to open it.  The default is `CTRL`,
+    /// or `CMD` on macOS, so that a plain click can be used to
+    /// select text without accidentally opening links.
+    #[serde(
+        default = "default_hyperlink_click_modifiers",
+        deserialize_with = "keys::de_modifiers"
+    )]
+    pub hyperlink_click_modifiers: Modifiers,
+
+    /// The URI schemes of the hyperlinks that may be opened by
+    /// clicking on them.  Clicking on a link with some other scheme
+    /// does nothing, which prevents an application from presenting
+    /// eg: a link to launch some arbitrary URI handler.
+    #[serde(default = "default_allowed_hyperlink_schemes")]
+    pub allowed_hyperlink_schemes: Vec<String>,
+
+    /// Regular expressions that match hyperlinks that must never be
+    /// opened, even though their scheme is allowed
+    #[serde(default)]
+    pub denied_hyperlink_patterns: Vec<String>,
+
+    /// The program, and any arguments, used to open hyperlinks and
+    /// paths in place of the handler that the system associates with
+    /// them.  The URL or path is passed as the final argument.
+    #[serde(default)]
+    pub open_command: Option<Vec<String>>,
+
+    /// When the application running in the terminal has enabled mouse
+    /// reporting, holding down these modifiers causes mouse events to
+    /// be handled by wezterm (eg: to make a selection) rather than
+    /// being sent to the application.
+    #[serde(
+        default = "default_bypass_mouse_reporting_modifiers",
+        deserialize_with = "keys::de_modifiers"
+    )]
+    pub bypass_mouse_reporting_modifiers: Modifiers,
+
+    /// When true, turning the mouse wheel while a full screen
+    /// application is using the alternate screen sends cursor
+    /// up/down keys to the application.  The alternate screen has
+    /// no scrollback, so otherwise the wheel does nothing there.
+    #[serde(default = "default_true")]
+    pub alternate_buffer_wheel_sends_arrow_keys: bool,
+
+    /// The set of characters that delimit a word when making a
+    /// double-click selection.  Adding characters such as `:`
+    /// or `/` to this set allows double-click to select the
+    /// individual components of a path or URL.
+    #[serde(default = "default_selection_word_boundary")]
+    pub selection_word_boundary: String,
+
+    /// The regular expressions that QuickSelect mode uses to find
+    /// the text in the viewport that can be selected by typing
+    /// its label
+    #[serde(default = "default_quick_select_patterns")]
+    pub quick_select_patterns: Vec<String>,
+
+    /// The characters from which QuickSelect mode makes its labels
+    #[serde(default = "default_quick_select_alphabet")]
+    pub quick_select_alphabet: String,
+
+    /// Which of the system clipboards receive the selected text
+    /// when making a selection with the mouse.
+    /// Only X11 and Wayland distinguish the primary selection from
+    /// the clipboard; on other systems both refer to the clipboard.
+    #[serde(default = "default_selection_copy_destination")]
+    pub selection_copy_destination: ClipboardCopyDestination,
+
+    /// Whether text selected with the mouse is copied to the clipboard
+    /// as soon as the mouse button is released.  When disabled, the
+    /// selection is only copied by the `Copy` and `CopyTo` key
+    /// assignments.
+    #[serde(default = "default_true")]
+    pub copy_on_select: bool,
+
+    /// Whether trailing whitespace is removed from each row of the
+    /// selected text
+    #[serde(default = "default_true")]
+    pub selection_trim_trailing_whitespace: bool,
+
+    /// Whether the rows of a line that was wrapped because it was
+    /// longer than the width of the terminal are joined back together
+    /// when copied, rather than being separated by newlines
+    #[serde(default = "default_true")]
+    pub selection_join_wrapped_lines: bool,
+
+    /// Which of the system clipboards is pasted when clicking
+    /// the middle mouse button.
+    #[serde(default = "default_middle_click_paste_source")]
+    pub middle_click_paste_source: ClipboardPasteSource,
+
+    /// When true, control characters other than tab, carriage return
+    /// and newline are removed from text that is pasted from the
+    /// clipboard, so that a paste cannot inject escape sequences
+    #[serde(default = "default_true")]
+    pub paste_filter_control_characters: bool,
+
+    /// When true, pasting text that contains a newline into a tab
+    /// that would run each line as a command (a shell that hasn't
+    /// enabled bracketed paste mode) asks for confirmation first
+    #[serde(default = "default_true")]
+    pub confirm_multiline_paste: bool,
+
+    /// When true (the default), the text most recently copied to the
+    /// clipboard is remembered so that it can be pasted again from
+    /// the `ShowClipboardHistory` overlay.  The history is only kept
+    /// in memory; set this to false to avoid retaining it at all.
+    #[serde(default = "default_true")]
+    pub enable_clipboard_history: bool,
+
+    /// The number of entries kept in the clipboard history
+    #[serde(default = "default_clipboard_history_size")]
+    pub clipboard_history_size: usize,
+
+    /// When true, characters whose East Asian Width property is
+    /// Ambiguous are treated as double width, as is conventional in
+    /// CJK locales.  This needs to match the setting of the programs
+    /// running in the terminal (eg: the `ambiwidth` option in vim)
+    /// for the cursor position to agree with them.
+    #[serde(default)]
+    pub treat_east_asian_ambiguous_width_as_wide: bool,
+
+    /// What to do with a tab when the program running in it exits.
+    /// Acceptable values are `Close`, `CloseOnCleanExit`, which keeps
+    /// the tab open if the program exited with a non-zero status, and
+    /// `Hold`, which keeps the tab open until it is closed.
+    /// A tab that is kept open shows the exit status of the program.
+    /// The default is `Close`.
+    #[serde(default)]
+    pub exit_behavior: ExitBehavior,
+
+    /// Closing a tab, pane or window asks for confirmation if the
+    /// program in the foreground of any of the panes being closed is
+    /// not one of these, such as an editor in which there may be
+    /// unsaved changes.  The names are compared without regard to
+    /// case.
+    #[serde(default = "default_skip_close_confirmation")]
+    pub skip_close_confirmation_for_processes_named: Vec<String>,
+
     /// What to set the TERM variable to
     #[serde(default = "default_term")]
     pub term: String,
 
+    /// Environment variables to set in the programs that are spawned.
+    /// These take precedence over the variables that wezterm sets,
+    /// such as TERM and COLORTERM.
+    #[serde(default)]
+    pub set_environment_variables: HashMap<String, String>,
+
     #[serde(default)]
     pub font_locator: FontLocatorSelection,
     #[serde(default)]
@@ -397,6 +607,30 @@ pub struct Config {
     #[serde(default)]
     pub keys: Vec<Key>,
 
+    /// The prefix key that activates the `leader_keys` bindings
+    #[serde(default)]
+    pub leader: Option<LeaderKey>,
+
+    /// Bindings for the key that is pressed after the `leader` key
+    #[serde(default)]
+    pub leader_keys: Vec<Key>,
+
+    /// Bindings of mouse buttons to actions; these take precedence
+    /// over the default mouse bindings
+    #[serde(default)]
+    pub mouse_bindings: Vec<MouseBinding>,
+
+    /// The entries that are listed in the launcher, ahead of an
+    /// entry for each of the attached domains
+    #[serde(default)]
+    pub launch_menu: Vec<LaunchMenuItem>,
+
+    /// Overrides of the colors, font size and cursor style for panes
+    /// that match the rules of a profile.  The first profile that
+    /// matches a newly spawned pane is applied to it.
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+
     #[serde(default)]
     pub daemon_options: DaemonOptions,
 
@@ -420,9 +654,26 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub enable_tab_bar: bool,
 
+    /// If true, the tab bar is shown at the bottom of the window
+    /// rather than at the top
+    #[serde(default)]
+    pub tab_bar_at_bottom: bool,
+
+    /// If true (the default), the title of each tab in the tab bar
+    /// is prefixed by its position, counting from 1
+    #[serde(default = "default_true")]
+    pub show_tab_index_in_tab_bar: bool,
+
     #[serde(default)]
     pub enable_scroll_bar: bool,
 
+    /// If true (the default), lines that hold a shell prompt are
+    /// marked with a thin bar at their left edge, so that the output
+    /// of each command is easy to pick out.  This requires that the
+    /// shell marks up its prompt using the OSC 133 escape sequences.
+    #[serde(default = "default_true")]
+    pub show_prompt_markers: bool,
+
     /// If false, do not try to use a Wayland protocol connection
     /// when starting the gui frontend, and instead use X11.
     /// This option is only considered on X11/Wayland systems and
@@ -431,19 +682,97 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub enable_wayland: bool,
 
+    /// If true (the default), a tab that rings the bell while it
+    /// is not the active tab is marked with `!` in the tab bar
+    /// until it is activated.
+    #[serde(default = "default_true")]
+    pub monitor_bell: bool,
+
+    /// If true, a tab that produces output while it is not the
+    /// active tab is marked with `*` in the tab bar until it is
+    /// activated.
+    #[serde(default)]
+    pub monitor_activity: bool,
+
+    /// If non-zero, show a desktop notification when a tab that is
+    /// not being looked at produces some output and then stays
+    /// silent for this many seconds; eg: when a long job finishes.
+    #[serde(default)]
+    pub monitor_silence_seconds: u64,
+
+    /// Whether the system bell is sounded when the bell rings in
+    /// the active tab
+    #[serde(default)]
+    pub audible_bell: AudibleBell,
+
+    /// If true, briefly flash the window when the bell rings in
+    /// the active tab
+    #[serde(default)]
+    pub visual_bell: bool,
+
+    /// How long the visual bell takes to fade in, and then out
+    #[serde(default = "default_visual_bell_fade_ms")]
+    pub visual_bell_fade_in_ms: u64,
+    #[serde(default = "default_visual_bell_fade_ms")]
+    pub visual_bell_fade_out_ms: u64,
+
+    /// The curve along which the visual bell fades
+    #[serde(default)]
+    pub visual_bell_easing: EasingFunction,
+
+    /// If true, show a desktop notification when the bell rings in
+    /// a tab that is not being looked at
+    #[serde(default)]
+    pub bell_notification: bool,
+
+    /// If true, periodically save the working directory and program
+    /// of each local tab so that they can be restored by starting
+    /// wezterm with the `--resurrect` flag.  This is off by default
+    /// as it writes the command line of every tab to disk.
+    #[serde(default)]
+    pub save_session: bool,
+
     /// Controls the amount of padding to use around the terminal cell area
     #[serde(default)]
     pub window_padding: WindowPadding,
 
+    /// If true, interactively resizing the window snaps its size to
+    /// whole cells, so that there is no partial cell left over at the
+    /// right and bottom edges.  This is not supported on Wayland.
+    #[serde(default)]
+    pub use_resize_increments: bool,
+
+    /// When the font size of a window is changed with the
+    /// `IncreaseFontSize`, `DecreaseFontSize` or `ResetFontSize`
+    /// key assignments, resize the window so that it keeps the same
+    /// number of rows and columns.  If false, the window keeps its
+    /// size and the number of rows and columns changes instead.
+    #[serde(default = "default_true")]
+    pub adjust_window_size_when_changing_font_size: bool,
+
     /// Specifies how often a blinking cursor transitions between visible
     /// and invisible, expressed in milliseconds.
     /// Setting this to 0 disables blinking.
     /// Note that this value is approximate due to the way that the system
     /// event loop schedulers manage timers; non-zero values will be at
     /// least the interval specified with some degree of slop.
+    /// Blinking is paused while the window doesn't have focus or the
+    /// system is running on battery power.
     #[serde(default = "default_cursor_blink_rate")]
     pub cursor_blink_rate: u64,
 
+    /// Specifies how often text with the slow blink attribute (SGR 5)
+    /// transitions between visible and invisible, in milliseconds.
+    /// Setting this to 0 disables slow blinking.
+    #[serde(default = "default_text_blink_rate")]
+    pub text_blink_rate: u64,
+
+    /// Specifies how often text with the rapid blink attribute (SGR 6)
+    /// transitions between visible and invisible, in milliseconds.
+    /// Setting this to 0 disables rapid blinking.
+    #[serde(default = "default_text_blink_rate_rapid")]
+    pub text_blink_rate_rapid: u64,
+
     /// Specifies the default cursor style.  various escape sequences
     /// can override the default style in different situations (eg:
     /// an editor can change it depending on the mode), but this value
@@ -454,6 +783,131 @@ pub struct Config {
     /// and `BlinkingBar`.
     #[serde(default)]
     pub default_cursor_style: DefaultCursorStyle,
+
+    /// Selects the light or dark variant of the window titlebar
+    /// and frame on systems that support it.
+    /// Acceptable values are `System`, which follows the system-wide
+    /// preference, `Light` and `Dark`.
+    /// The default is `Dark`.
+    #[serde(default)]
+    pub window_theme: WindowThemeSelection,
+
+    /// Identifies wezterm's windows to the windowing system, so that
+    /// window manager rules and taskbar grouping can tell them apart
+    /// from those of other applications.  It is used as the WM_CLASS
+    /// on X11, the app_id on Wayland and the AppUserModelID on Windows.
+    /// The default is `wezterm`.
+    #[serde(default = "default_window_class")]
+    pub window_class: String,
+
+    /// When true (the default), pressing Alt+Space on Windows opens
+    /// the window menu, as it does for other applications.  Set this
+    /// to false so that Alt+Space can be bound to a key assignment
+    /// or sent to the program running in the tab instead.
+    /// This option has no effect on other systems.
+    #[serde(default = "default_true")]
+    pub alt_space_opens_system_menu: bool,
+
+    /// When true, secure keyboard entry is turned on at startup on
+    /// macOS, so that other processes are unable to observe the keys
+    /// that are typed into wezterm while it is active.  It can also be
+    /// toggled from the application menu.
+    /// This option has no effect on other systems.
+    #[serde(default)]
+    pub secure_keyboard_entry: bool,
+
+    /// The path to a PNG, JPEG or GIF image that is drawn behind the
+    /// text of the window.  Cells that have the default background
+    /// color let the image show through.  This is only supported
+    /// when `front_end = "OpenGL"`.
+    #[serde(default)]
+    pub window_background_image: Option<PathBuf>,
+
+    /// How the background image is sized to the window.
+    /// Acceptable values are `Stretch` (the default), `Fill`, which
+    /// preserves the aspect ratio and crops the image to cover the
+    /// window, and `Tile`, which repeats the image at its natural size.
+    #[serde(default)]
+    pub window_background_image_mode: BackgroundImageMode,
+
+    /// Whether the background image stays put (`Window`, the default)
+    /// or moves along with the text when scrolling back through the
+    /// scrollback (`Scroll`).
+    #[serde(default)]
+    pub window_background_image_attachment: BackgroundImageAttachment,
+
+    /// How strongly the background image shows through the background
+    /// color, from 0.0 (not at all) to 1.0 (fully opaque).
+    #[serde(default = "default_window_background_image_opacity")]
+    pub window_background_image_opacity: f32,
+
+    /// When there are multiple panes, the background of each pane
+    /// other than the active pane is blended with the
+    /// `inactive_pane_tint` color by this amount, from 0.0 (no tint,
+    /// the default) to 1.0 (entirely the tint color).
+    #[serde(default)]
+    pub inactive_pane_tint_amount: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundImageMode {
+    Stretch,
+    Fill,
+    Tile,
+}
+
+impl Default for BackgroundImageMode {
+    fn default() -> Self {
+        BackgroundImageMode::Stretch
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundImageAttachment {
+    Window,
+    Scroll,
+}
+
+impl Default for BackgroundImageAttachment {
+    fn default() -> Self {
+        BackgroundImageAttachment::Window
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowThemeSelection {
+    System,
+    Light,
+    Dark,
+}
+
+impl Default for WindowThemeSelection {
+    fn default() -> Self {
+        WindowThemeSelection::Dark
+    }
+}
+
+impl Into<::window::WindowTheme> for WindowThemeSelection {
+    fn into(self) -> ::window::WindowTheme {
+        match self {
+            Self::System => ::window::WindowTheme::System,
+            Self::Light => ::window::WindowTheme::Light,
+            Self::Dark => ::window::WindowTheme::Dark,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitBehavior {
+    Close,
+    CloseOnCleanExit,
+    Hold,
+}
+
+impl Default for ExitBehavior {
+    fn default() -> Self {
+        ExitBehavior::Close
+    }
 }
 
 #[derive(Deserialize, Clone, Copy, Debug)]
@@ -510,16 +964,18 @@ impl Default for Config {
 }
 
 impl Config {
-    pub fn load() -> Result<(Self, Option<PathBuf>), Error> {
+    pub fn load() -> Result<(Self, Option<PathBuf>, Option<mlua::Lua>), Error> {
         // Note that the directories crate has methods for locating project
         // specific config directories, but only returns one of them, not
         // multiple.  In addition, it spawns a lot of subprocesses,
         // so we do this bit "by-hand"
         let mut paths = vec![
+            HOME_DIR.join(".config").join("wezterm").join("wezterm.lua"),
             HOME_DIR
                 .join(".config")
                 .join("wezterm")
                 .join("wezterm.toml"),
+            HOME_DIR.join(".wezterm.lua"),
             HOME_DIR.join(".wezterm.toml"),
         ];
         if cfg!(windows) {
@@ -534,6 +990,7 @@ impl Config {
             if let Ok(exe_name) = std::env::current_exe() {
                 if let Some(exe_dir) = exe_name.parent() {
                     paths.insert(0, exe_dir.join("wezterm.toml"));
+                    paths.insert(0, exe_dir.join("wezterm.lua"));
                 }
             }
         }
@@ -554,16 +1011,41 @@ impl Config {
             let mut s = String::new();
             file.read_to_string(&mut s)?;
 
-            let cfg: Self = toml::from_str(&s)
-                .with_context(|| format!("Error parsing TOML from {}", p.display()))?;
+            let (cfg, lua): (Self, _) = if p.extension() == Some(OsStr::new("lua")) {
+                let lua = make_lua_context(p)?;
+                let cfg = {
+                    let value: mlua::Value = lua
+                        .load(&s)
+                        .set_name(&p.to_string_lossy().into_owned())?
+                        .eval()
+                        .with_context(|| format!("Error evaluating {}", p.display()))?;
+                    lua.from_value(value).with_context(|| {
+                        format!("Error converting the lua value returned by {}", p.display())
+                    })?
+                };
+                (cfg, Some(lua))
+            } else {
+                let cfg = toml::from_str(&s)
+                    .with_context(|| format!("Error parsing TOML from {}", p.display()))?;
+                (cfg, None)
+            };
 
             // Compute but discard the key bindings here so that we raise any
             // problems earlier than we use them.
             let _ = cfg.key_bindings()?;
-            return Ok((cfg.compute_extra_defaults(Some(p)), Some(p.to_path_buf())));
+            let _ = cfg.leader_key_bindings()?;
+            let _ = cfg.mouse_bindings()?;
+            cfg.check_color_scheme()?;
+            cfg.check_denied_hyperlink_patterns()?;
+            cfg.check_profiles()?;
+            return Ok((
+                cfg.compute_extra_defaults(Some(p)),
+                Some(p.to_path_buf()),
+                lua,
+            ));
         }
 
-        Ok((Self::default().compute_extra_defaults(None), None))
+        Ok((Self::default().compute_extra_defaults(None), None, None))
     }
 
     pub fn default_config() -> Self {
@@ -571,9 +1053,19 @@ impl Config {
     }
 
     pub fn key_bindings(&self) -> anyhow::Result<HashMap<(KeyCode, Modifiers), KeyAssignment>> {
+        Self::key_map(&self.keys)
+    }
+
+    pub fn leader_key_bindings(
+        &self,
+    ) -> anyhow::Result<HashMap<(KeyCode, Modifiers), KeyAssignment>> {
+        Self::key_map(&self.leader_keys)
+    }
+
+    fn key_map(keys: &[Key]) -> anyhow::Result<HashMap<(KeyCode, Modifiers), KeyAssignment>> {
         let mut map = HashMap::new();
 
-        for k in &self.keys {
+        for k in keys {
             let value = k.try_into()?;
             map.insert((k.key, k.mods), value);
         }
@@ -581,6 +1073,160 @@ impl Config {
         Ok(map)
     }
 
+    pub fn mouse_bindings(
+        &self,
+    ) -> anyhow::Result<
+        HashMap<(MouseEventTrigger, MouseBindingButton, usize, Modifiers), KeyAssignment>,
+    > {
+        let mut map = HashMap::new();
+
+        for m in &self.mouse_bindings {
+            let value: KeyAssignment = m.try_into()?;
+            // The terminal performs these itself, so only the actions
+            // that it knows about can be bound to the mouse
+            match value {
+                KeyAssignment::SelectTextAtMouseCursor(_)
+                | KeyAssignment::ExtendSelectionToMouseCursor
+                | KeyAssignment::CompleteSelection
+                | KeyAssignment::CompleteSelectionOrOpenLinkAtMouseCursor
+                | KeyAssignment::OpenLinkAtMouseCursor
+                | KeyAssignment::PasteFrom(_)
+                | KeyAssignment::Nop => {}
+                _ => bail!("{:?} cannot be used in mouse_bindings", m.action),
+            }
+            map.insert((m.event, m.button, m.streak, m.mods), value);
+        }
+
+        Ok(map)
+    }
+
+    /// Returns true if the scheme of `uri` is one of the
+    /// `allowed_hyperlink_schemes` and `uri` doesn't match any of the
+    /// `denied_hyperlink_patterns`
+    pub fn is_hyperlink_allowed(&self, uri: &str) -> bool {
+        let scheme_allowed = match uri.find(':') {
+            Some(idx) => {
+                let scheme = &uri[..idx];
+                self.allowed_hyperlink_schemes
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+            }
+            None => false,
+        };
+        // The patterns were checked when the configuration was loaded
+        scheme_allowed
+            && !self
+                .denied_hyperlink_patterns
+                .iter()
+                .filter_map(|pattern| regex::Regex::new(pattern).ok())
+                .any(|re| re.is_match(uri))
+    }
+
+    /// Returns true if closing a pane whose foreground process is
+    /// named `name` should be confirmed by the user
+    pub fn needs_close_confirmation(&self, name: &str) -> bool {
+        !self
+            .skip_close_confirmation_for_processes_named
+            .iter()
+            .any(|skip| skip.eq_ignore_ascii_case(name))
+    }
+
+    fn check_denied_hyperlink_patterns(&self) -> anyhow::Result<()> {
+        for pattern in &self.denied_hyperlink_patterns {
+            regex::Regex::new(pattern)
+                .with_context(|| format!("invalid denied_hyperlink_patterns entry {}", pattern))?;
+        }
+        Ok(())
+    }
+
+    /// Looks up a color scheme by name.  Schemes defined in the
+    /// configuration take precedence over the built-in schemes.
+    pub fn get_color_scheme(&self, name: &str) -> Option<Palette> {
+        self.color_schemes
+            .get(name)
+            .cloned()
+            .or_else(|| builtin_color_scheme(name))
+    }
+
+    fn check_color_scheme(&self) -> anyhow::Result<()> {
+        if let Some(name) = self.color_scheme.as_ref() {
+            if self.get_color_scheme(name).is_none() {
+                let mut names: Vec<&str> = self.color_schemes.keys().map(String::as_str).collect();
+                names.extend(builtin_color_scheme_names());
+                names.sort();
+                bail!(
+                    "color_scheme `{}` is not defined; the available schemes are: {}",
+                    name,
+                    names.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the palette that results from applying `colors`
+    /// on top of the selected `color_scheme`
+    pub fn resolved_palette(&self) -> Palette {
+        self.resolved_palette_for_profile(None)
+    }
+
+    /// Returns the palette for a pane with the named `profile`.  The
+    /// `color_scheme` of the profile replaces the configured scheme,
+    /// and its `colors` are applied on top of the configured `colors`.
+    pub fn resolved_palette_for_profile(&self, profile: Option<&str>) -> Palette {
+        let profile = profile.and_then(|name| self.get_profile(name));
+        let scheme = profile
+            .and_then(|profile| profile.color_scheme.as_ref())
+            .or_else(|| self.color_scheme.as_ref())
+            .and_then(|name| self.get_color_scheme(name))
+            .unwrap_or_default();
+        let palette = match self.colors.as_ref() {
+            Some(colors) => scheme.overlay_with(colors),
+            None => scheme,
+        };
+        match profile.and_then(|profile| profile.colors.as_ref()) {
+            Some(colors) => palette.overlay_with(colors),
+            None => palette,
+        }
+    }
+
+    /// Returns the cursor style for a pane with the named `profile`
+    pub fn cursor_style_for_profile(&self, profile: Option<&str>) -> DefaultCursorStyle {
+        profile
+            .and_then(|name| self.get_profile(name))
+            .and_then(|profile| profile.default_cursor_style)
+            .unwrap_or(self.default_cursor_style)
+    }
+
+    /// Looks up a profile by name
+    pub fn get_profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Returns the name of the first profile that matches `pane`
+    pub fn profile_for_pane(&self, pane: &PaneInfo) -> Option<String> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches(pane))
+            .map(|profile| profile.name.clone())
+    }
+
+    fn check_profiles(&self) -> anyhow::Result<()> {
+        for profile in &self.profiles {
+            profile.check()?;
+            if let Some(name) = profile.color_scheme.as_ref() {
+                if self.get_color_scheme(name).is_none() {
+                    bail!(
+                        "color_scheme `{}` of profile {} is not defined",
+                        name,
+                        profile.name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// In some cases we need to compute expanded values based
     /// on those provided by the user.  This is where we do that.
     fn compute_extra_defaults(&self, config_path: Option<&Path>) -> Self {
@@ -627,28 +1273,95 @@ impl Config {
 
     pub fn build_prog(&self, prog: Option<Vec<&OsStr>>) -> Result<CommandBuilder, Error> {
         let mut cmd = match prog {
-            Some(args) => {
-                let mut args = args.iter();
-                let mut cmd = CommandBuilder::new(args.next().expect("executable name"));
-                cmd.args(args);
-                cmd
-            }
-            None => {
-                if let Some(prog) = self.default_prog.as_ref() {
-                    let mut args = prog.iter();
-                    let mut cmd = CommandBuilder::new(args.next().expect("executable name"));
-                    cmd.args(args);
-                    cmd
-                } else {
-                    CommandBuilder::new_default_prog()
-                }
-            }
+            Some(args) => command_from_argv(&args),
+            None => match self.default_prog.as_ref() {
+                Some(prog) => command_from_argv(prog),
+                None => CommandBuilder::new_default_prog(),
+            },
         };
 
-        cmd.env("TERM", &self.term);
+        self.apply_environment(&mut cmd);
 
         Ok(cmd)
     }
+
+    /// Sets up the environment of a program that is about to be spawned
+    /// locally: TERM and COLORTERM describe wezterm, the variables that
+    /// describe the terminal that wezterm itself was started from are
+    /// removed, LANG is given a UTF-8 default if no locale is set, and then
+    /// `set_environment_variables` is applied.  Variables that `cmd`
+    /// already sets or removes are left alone.
+    pub fn apply_environment(&self, cmd: &mut CommandBuilder) {
+        let mut env: Vec<(&str, Option<&str>)> = vec![
+            ("TERM", Some(self.term.as_str())),
+            ("COLORTERM", Some("truecolor")),
+            ("TERM_PROGRAM", Some("WezTerm")),
+        ];
+        for name in INHERITED_TERMINAL_VARIABLES {
+            env.push((*name, None));
+        }
+        if let Some(lang) = default_lang() {
+            env.push(("LANG", Some(lang)));
+        }
+        for (name, value) in &self.set_environment_variables {
+            env.retain(|(n, _)| *n != name.as_str());
+            env.push((name.as_str(), Some(value.as_str())));
+        }
+
+        for (name, value) in env {
+            if cmd.iter_env().any(|(n, _)| n == OsStr::new(name)) {
+                continue;
+            }
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            }
+        }
+    }
+}
+
+/// Environment variables that describe the terminal or multiplexer
+/// that wezterm was started from, and that would mislead the programs
+/// running in wezterm
+const INHERITED_TERMINAL_VARIABLES: &[&str] = &[
+    "COLUMNS",
+    "ITERM_SESSION_ID",
+    "KITTY_WINDOW_ID",
+    "LINES",
+    "STY",
+    "TERMCAP",
+    "TERM_PROGRAM_VERSION",
+    "TERM_SESSION_ID",
+    "TMUX",
+    "TMUX_PANE",
+    "VTE_VERSION",
+    "WINDOW",
+    "WT_SESSION",
+];
+
+/// Returns the value to give LANG when wezterm has been started without
+/// any locale settings, which is usual for GUI applications on macOS.
+fn default_lang() -> Option<&'static str> {
+    if cfg!(windows)
+        || ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .any(|name| std::env::var_os(name).is_some())
+    {
+        None
+    } else if cfg!(target_os = "macos") {
+        Some("en_US.UTF-8")
+    } else {
+        Some("C.UTF-8")
+    }
+}
+
+/// Returns a builder for the command line `argv`, which must not be
+/// empty
+pub fn command_from_argv<S: AsRef<OsStr>>(argv: &[S]) -> CommandBuilder {
+    let mut args = argv.iter();
+    let mut cmd = CommandBuilder::new(args.next().expect("executable name"));
+    cmd.args(args);
+    cmd
 }
 
 fn default_ratelimit_mux_output_scans_per_second() -> u32 {
@@ -671,6 +1384,14 @@ fn default_cursor_blink_rate() -> u64 {
     800
 }
 
+fn default_text_blink_rate() -> u64 {
+    500
+}
+
+fn default_text_blink_rate_rapid() -> u64 {
+    250
+}
+
 fn default_swap_backspace_and_delete() -> bool {
     // cfg!(target_os = "macos")
     // See: https://github.com/wez/wezterm/issues/88
@@ -681,6 +1402,104 @@ fn default_scrollback_lines() -> usize {
     3500
 }
 
+fn default_clipboard_history_size() -> usize {
+    20
+}
+
+fn default_window_class() -> String {
+    "wezterm".to_string()
+}
+
+fn default_selection_copy_destination() -> ClipboardCopyDestination {
+    ClipboardCopyDestination::ClipboardAndPrimarySelection
+}
+
+fn default_visual_bell_fade_ms() -> u64 {
+    75
+}
+
+fn default_middle_click_paste_source() -> ClipboardPasteSource {
+    ClipboardPasteSource::PrimarySelection
+}
+
+fn default_selection_word_boundary() -> String {
+    term::config::DEFAULT_SELECTION_WORD_BOUNDARY.to_string()
+}
+
+fn default_bypass_mouse_reporting_modifiers() -> Modifiers {
+    Modifiers::SHIFT
+}
+
+fn default_hyperlink_click_modifiers() -> Modifiers {
+    if cfg!(target_os = "macos") {
+        Modifiers::SUPER
+    } else {
+        Modifiers::CTRL
+    }
+}
+
+/// The alphabet used for QuickSelect labels when `quick_select_alphabet`
+/// has fewer than two distinct characters; it starts with the home row
+pub const DEFAULT_QUICK_SELECT_ALPHABET: &str = "asdfqwerzxcvjklmiuopghtybn";
+
+fn default_window_background_image_opacity() -> f32 {
+    1.0
+}
+
+fn default_quick_select_alphabet() -> String {
+    DEFAULT_QUICK_SELECT_ALPHABET.to_string()
+}
+
+fn default_quick_select_patterns() -> Vec<String> {
+    [
+        // URL with a protocol
+        r#"\b[a-z]+://[^\s<>"']*[^\s<>"'.,;:!?)\]]"#,
+        // A path containing at least one slash
+        r"(?:[\w.~-]+)?(?:/[\w.-]+)+",
+        // A git commit hash, or similar
+        r"\b[0-9a-f]{7,40}\b",
+        // An IPv4 address
+        r"\b\d{1,3}(?:\.\d{1,3}){3}\b",
+    ]
+    .iter()
+    .map(|&s| s.to_string())
+    .collect()
+}
+
+fn default_skip_close_confirmation() -> Vec<String> {
+    [
+        "bash",
+        "sh",
+        "zsh",
+        "fish",
+        "dash",
+        "ksh",
+        "mksh",
+        "csh",
+        "tcsh",
+        "nu",
+        "xonsh",
+        "elvish",
+        "tmux",
+        "screen",
+        "cmd.exe",
+        "pwsh.exe",
+        "powershell.exe",
+        "bash.exe",
+        "nu.exe",
+    ]
+    .iter()
+    .map(|&s| s.to_string())
+    .collect()
+}
+
+fn default_allowed_hyperlink_schemes() -> Vec<String> {
+    ["http", "https", "mailto", "ftp", "file"]
+        .iter()
+        .map(|&s| s.to_string())
+        .collect()
+}
+
 fn default_hyperlink_rules() -> Vec<hyperlink::Rule> {
     vec![
         // URL with a protocol
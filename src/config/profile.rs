@@ -0,0 +1,160 @@
+use crate::config::{DefaultCursorStyle, Palette};
+use anyhow::Context;
+use regex::Regex;
+use serde_derive::*;
+use std::ffi::OsString;
+
+/// A set of appearance overrides for particular panes, such as those
+/// that are attached to a production host, so that they can be
+/// recognized at a glance.  A profile is applied to a pane when it
+/// is spawned if all of the match rules that it specifies are
+/// satisfied by the pane; a profile without any match rules is only
+/// applied by the `SetProfile` key assignment.
+#[derive(Default, Debug, Clone, Deserialize)]
+pub struct Profile {
+    /// Identifies the profile for the `SetProfile` key assignment
+    pub name: String,
+
+    /// Matches panes spawned in the domain with this name
+    #[serde(default)]
+    pub domain: Option<String>,
+
+    /// A regex that is matched against the name of the remote host
+    /// that the domain of the pane is connected to
+    #[serde(default)]
+    pub hostname: Option<String>,
+
+    /// A regex that is matched against the command that was spawned
+    /// in the pane, with its arguments separated by spaces
+    #[serde(default)]
+    pub command: Option<String>,
+
+    /// Replaces the `color_scheme`
+    #[serde(default)]
+    pub color_scheme: Option<String>,
+
+    /// Colors that are applied on top of the `colors` and the
+    /// color scheme
+    #[serde(default)]
+    pub colors: Option<Palette>,
+
+    /// Replaces the `font_size` while the pane is active
+    #[serde(default)]
+    pub font_size: Option<f64>,
+
+    /// Replaces the `default_cursor_style`
+    #[serde(default)]
+    pub default_cursor_style: Option<DefaultCursorStyle>,
+}
+
+/// Describes a pane, so that it can be matched against the profiles
+#[derive(Debug, Clone, Copy)]
+pub struct PaneInfo<'a> {
+    /// The name of the domain of the pane
+    pub domain: &'a str,
+    /// The remote host that the domain is connected to, if any
+    pub hostname: Option<&'a str>,
+    /// The command that was spawned, or None if it was the default
+    /// program
+    pub argv: Option<&'a [OsString]>,
+}
+
+fn regex_matches(pattern: &str, text: &str) -> bool {
+    Regex::new(pattern)
+        .map(|re| re.is_match(text))
+        .unwrap_or(false)
+}
+
+impl Profile {
+    /// Returns true if the profile has at least one match rule, and
+    /// `pane` satisfies all of them
+    pub fn matches(&self, pane: &PaneInfo) -> bool {
+        if self.domain.is_none() && self.hostname.is_none() && self.command.is_none() {
+            return false;
+        }
+        if let Some(domain) = self.domain.as_ref() {
+            if domain != pane.domain {
+                return false;
+            }
+        }
+        if let Some(hostname) = self.hostname.as_ref() {
+            match pane.hostname {
+                Some(host) if regex_matches(hostname, host) => {}
+                _ => return false,
+            }
+        }
+        if let Some(command) = self.command.as_ref() {
+            let argv = match pane.argv {
+                Some(argv) => argv
+                    .iter()
+                    .map(|arg| arg.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                None => return false,
+            };
+            if !regex_matches(command, &argv) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Verifies that the match rules are valid regexes
+    pub fn check(&self) -> anyhow::Result<()> {
+        for pattern in self.hostname.iter().chain(self.command.iter()) {
+            Regex::new(pattern)
+                .with_context(|| format!("invalid pattern {} in profile {}", pattern, self.name))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn profile(domain: Option<&str>, hostname: Option<&str>, command: Option<&str>) -> Profile {
+        Profile {
+            name: "test".to_string(),
+            domain: domain.map(str::to_string),
+            hostname: hostname.map(str::to_string),
+            command: command.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matching() {
+        let argv = vec![OsString::from("ssh"), OsString::from("prod-db1")];
+        let ssh = PaneInfo {
+            domain: "local",
+            hostname: None,
+            argv: Some(&argv),
+        };
+        let remote = PaneInfo {
+            domain: "prod",
+            hostname: Some("prod-web1.example.com"),
+            argv: None,
+        };
+
+        assert!(!profile(None, None, None).matches(&ssh));
+
+        let by_command = profile(None, None, Some("^ssh .*prod"));
+        assert!(by_command.matches(&ssh));
+        assert!(!by_command.matches(&remote));
+
+        let by_host = profile(None, Some(r"^prod-.*\.example\.com$"), None);
+        assert!(by_host.matches(&remote));
+        assert!(!by_host.matches(&ssh));
+
+        // Every rule must be satisfied
+        assert!(profile(Some("prod"), Some("^prod-"), None).matches(&remote));
+        assert!(!profile(Some("local"), Some("^prod-"), None).matches(&remote));
+    }
+
+    #[test]
+    fn check() {
+        assert!(profile(None, Some("^prod-"), None).check().is_ok());
+        assert!(profile(None, None, Some("(")).check().is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use serde_derive::*;
+use std::collections::HashMap;
 
 #[derive(Default, Debug, Clone, Deserialize)]
 pub struct SshDomain {
@@ -19,4 +20,13 @@ pub struct SshDomain {
     /// If true, connect to this domain automatically at startup
     #[serde(default)]
     pub connect_automatically: bool,
+
+    /// The program to spawn in this domain when no program is given,
+    /// instead of the `default_prog` of the server
+    pub default_prog: Option<Vec<String>>,
+
+    /// Environment variables to set in the programs that are spawned
+    /// in this domain
+    #[serde(default)]
+    pub set_environment_variables: HashMap<String, String>,
 }
@@ -1,11 +1,30 @@
 //! Bridge our gui config into the terminal crate configuration
 
 use crate::config::configuration;
+use std::borrow::Cow;
+use std::sync::Mutex;
 use term::color::ColorPalette;
 use termwiz::hyperlink::Rule as HyperlinkRule;
 
-#[derive(Debug)]
-pub struct TermConfig;
+#[derive(Debug, Default)]
+pub struct TermConfig {
+    /// The name of the profile whose overrides apply to the terminal
+    profile: Mutex<Option<String>>,
+}
+
+impl TermConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn profile(&self) -> Option<String> {
+        self.profile.lock().unwrap().clone()
+    }
+
+    pub fn set_profile(&self, profile: Option<String>) {
+        *self.profile.lock().unwrap() = profile;
+    }
+}
 
 impl term::TerminalConfiguration for TermConfig {
     fn generation(&self) -> usize {
@@ -16,17 +35,42 @@ impl term::TerminalConfiguration for TermConfig {
         configuration().scrollback_lines
     }
 
+    fn selection_word_boundary(&self) -> Cow<str> {
+        Cow::Owned(configuration().selection_word_boundary.clone())
+    }
+
+    fn copy_on_select(&self) -> bool {
+        configuration().copy_on_select
+    }
+
+    fn selection_trim_trailing_whitespace(&self) -> bool {
+        configuration().selection_trim_trailing_whitespace
+    }
+
+    fn selection_join_wrapped_lines(&self) -> bool {
+        configuration().selection_join_wrapped_lines
+    }
+
     fn hyperlink_rules(&self) -> (usize, Vec<HyperlinkRule>) {
         let config = configuration();
         (config.generation(), config.hyperlink_rules.clone())
     }
 
+    fn hyperlink_click_modifiers(&self) -> term::KeyModifiers {
+        configuration().hyperlink_click_modifiers
+    }
+
+    fn bypass_mouse_reporting_modifiers(&self) -> term::KeyModifiers {
+        configuration().bypass_mouse_reporting_modifiers
+    }
+
+    fn alternate_buffer_wheel_sends_arrow_keys(&self) -> bool {
+        configuration().alternate_buffer_wheel_sends_arrow_keys
+    }
+
     fn color_palette(&self) -> ColorPalette {
         configuration()
-            .colors
-            .as_ref()
-            .cloned()
-            .map(Into::into)
-            .unwrap_or_else(ColorPalette::default)
+            .resolved_palette_for_profile(self.profile().as_deref())
+            .into()
     }
 }
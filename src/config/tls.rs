@@ -64,7 +64,25 @@ pub struct TlsDomainClient {
     /// should not normally need to override this value.
     pub expected_cn: Option<String>,
 
+    /// the path to the x509 PEM encoded certificate that the server
+    /// is expected to present.  When set, the connection is refused
+    /// unless the server presents exactly this certificate, and the
+    /// certificate is not otherwise verified against the CAs or the
+    /// hostname.  This allows a self-signed server certificate to be
+    /// used, and allows connecting to the server through a port that
+    /// is forwarded by ssh, where the hostname doesn't match.
+    pub pinned_server_cert: Option<PathBuf>,
+
     /// If true, connect to this domain automatically at startup
     #[serde(default)]
     pub connect_automatically: bool,
+
+    /// The program to spawn in this domain when no program is given,
+    /// instead of the `default_prog` of the server
+    pub default_prog: Option<Vec<String>>,
+
+    /// Environment variables to set in the programs that are spawned
+    /// in this domain
+    #[serde(default)]
+    pub set_environment_variables: HashMap<String, String>,
 }
@@ -37,6 +37,15 @@ pub struct UnixDomain {
     /// on the host NTFS volume.
     #[serde(default)]
     pub skip_permissions_check: bool,
+
+    /// The program to spawn in this domain when no program is given,
+    /// instead of the `default_prog` of the server
+    pub default_prog: Option<Vec<String>>,
+
+    /// Environment variables to set in the programs that are spawned
+    /// in this domain
+    #[serde(default)]
+    pub set_environment_variables: HashMap<String, String>,
 }
 
 impl UnixDomain {
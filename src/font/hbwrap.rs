@@ -272,6 +272,14 @@ impl Buffer {
         self.add_utf8(s.as_bytes())
     }
 
+    /// Fill in any of the script, direction and language that have
+    /// not been set explicitly, based on the text in the buffer
+    pub fn guess_segment_properties(&mut self) {
+        unsafe {
+            hb_buffer_guess_segment_properties(self.buf);
+        }
+    }
+
     /// Returns glyph information.  This is only valid after calling
     /// font->shape() on this buffer instance.
     pub fn glyph_infos(&self) -> &[hb_glyph_info_t] {
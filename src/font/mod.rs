@@ -21,13 +21,20 @@ use crate::font::rasterizer::{FontRasterizer, FontRasterizerSelection};
 pub use crate::font::shaper::{FallbackIdx, FontMetrics, GlyphInfo};
 use crate::font::shaper::{FontShaper, FontShaperSelection};
 
-use super::config::{configuration, ConfigHandle, TextStyle};
+use super::config::{configuration, ConfigHandle, FontAttributes, TextStyle};
 use term::CellAttributes;
 
+/// How many shaped runs of text each font retains before its
+/// cache is discarded and rebuilt
+const SHAPE_CACHE_SIZE: usize = 1024;
+
 pub struct LoadedFont {
     rasterizers: Vec<RefCell<Option<Box<dyn FontRasterizer>>>>,
     handles: Vec<FontDataHandle>,
+    /// The size scaling for each of the handles
+    scales: Vec<f64>,
     shaper: Box<dyn FontShaper>,
+    shape_cache: RefCell<HashMap<String, Rc<Vec<GlyphInfo>>>>,
     metrics: FontMetrics,
     font_size: f64,
     dpi: u32,
@@ -38,8 +45,21 @@ impl LoadedFont {
         self.metrics
     }
 
-    pub fn shape(&self, text: &str) -> anyhow::Result<Vec<GlyphInfo>> {
-        self.shaper.shape(text, self.font_size, self.dpi)
+    /// Shapes `text`, returning the glyphs that represent it.
+    /// The same runs of text tend to be painted over and over,
+    /// so the results are cached.
+    pub fn shape(&self, text: &str) -> anyhow::Result<Rc<Vec<GlyphInfo>>> {
+        if let Some(glyphs) = self.shape_cache.borrow().get(text) {
+            return Ok(Rc::clone(glyphs));
+        }
+
+        let glyphs = Rc::new(self.shaper.shape(text, self.font_size, self.dpi)?);
+        let mut cache = self.shape_cache.borrow_mut();
+        if cache.len() >= SHAPE_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(text.to_string(), Rc::clone(&glyphs));
+        Ok(glyphs)
     }
 
     pub fn rasterize_glyph(
@@ -58,10 +78,11 @@ impl LoadedFont {
             opt_raster.replace(raster);
         }
 
+        let font_size = self.font_size * self.scales[fallback];
         opt_raster
             .as_ref()
             .unwrap()
-            .rasterize_glyph(glyph_pos, self.font_size, self.dpi)
+            .rasterize_glyph(glyph_pos, font_size, self.dpi)
     }
 }
 
@@ -71,6 +92,8 @@ pub struct FontConfiguration {
     metrics: RefCell<Option<FontMetrics>>,
     dpi_scale: RefCell<f64>,
     font_scale: RefCell<f64>,
+    /// Overrides the `font_size` from the configuration
+    font_size: RefCell<Option<f64>>,
     config_generation: RefCell<usize>,
     locator: Box<dyn FontLocator>,
 }
@@ -84,6 +107,7 @@ impl FontConfiguration {
             locator,
             metrics: RefCell::new(None),
             font_scale: RefCell::new(1.0),
+            font_size: RefCell::new(None),
             dpi_scale: RefCell::new(1.0),
             config_generation: RefCell::new(configuration().generation()),
         }
@@ -108,8 +132,7 @@ impl FontConfiguration {
         }
 
         let attributes = style.font_with_fallback();
-        let mut handles = parser::ParsedFont::load_fonts(&config, &attributes)?;
-        handles.append(&mut self.locator.load_fonts(&attributes)?);
+        let (handles, scales) = self.load_handles(&config, &attributes)?;
         let mut rasterizers = vec![];
         for _ in &handles {
             rasterizers.push(RefCell::new(None));
@@ -117,14 +140,17 @@ impl FontConfiguration {
         let shaper = FontShaperSelection::get_default().new_shaper(&handles)?;
 
         let config = configuration();
-        let font_size = config.font_size * *self.font_scale.borrow();
+        let font_size =
+            self.font_size.borrow().unwrap_or(config.font_size) * *self.font_scale.borrow();
         let dpi = *self.dpi_scale.borrow() as u32 * config.dpi as u32;
         let metrics = shaper.metrics(font_size, dpi)?;
 
         let loaded = Rc::new(LoadedFont {
             rasterizers,
             handles,
+            scales,
             shaper,
+            shape_cache: RefCell::new(HashMap::new()),
             metrics,
             font_size,
             dpi,
@@ -135,6 +161,39 @@ impl FontConfiguration {
         Ok(loaded)
     }
 
+    /// Locates the fonts for `attributes`, returning the handles in
+    /// fallback order along with the scale of each one.  Fonts from
+    /// `font_dirs` come first, then those found by the system locator.
+    /// The system locator may suggest additional fallback fonts that
+    /// weren't explicitly configured; those go last and are not scaled.
+    fn load_handles(
+        &self,
+        config: &ConfigHandle,
+        attributes: &[FontAttributes],
+    ) -> anyhow::Result<(Vec<FontDataHandle>, Vec<f64>)> {
+        let (mut handles, mut scales): (Vec<_>, Vec<_>) =
+            parser::ParsedFont::load_fonts(config, attributes)?
+                .into_iter()
+                .unzip();
+
+        let mut suggested = vec![];
+        for attr in attributes {
+            let mut located = self
+                .locator
+                .load_fonts(std::slice::from_ref(attr))?
+                .into_iter();
+            if let Some(handle) = located.next() {
+                handles.push(handle);
+                scales.push(attr.scale.unwrap_or(1.0));
+            }
+            suggested.extend(located);
+        }
+        scales.resize(handles.len() + suggested.len(), 1.0);
+        handles.append(&mut suggested);
+
+        Ok((handles, scales))
+    }
+
     pub fn change_scaling(&self, font_scale: f64, dpi_scale: f64) {
         *self.dpi_scale.borrow_mut() = dpi_scale;
         *self.font_scale.borrow_mut() = font_scale;
@@ -142,6 +201,19 @@ impl FontConfiguration {
         self.metrics.borrow_mut().take();
     }
 
+    /// Overrides the `font_size` from the configuration, or reverts to
+    /// it if `font_size` is None.  Returns true if that changed the size.
+    #[allow(clippy::float_cmp)]
+    pub fn set_font_size(&self, font_size: Option<f64>) -> bool {
+        if *self.font_size.borrow() == font_size {
+            return false;
+        }
+        *self.font_size.borrow_mut() = font_size;
+        self.fonts.borrow_mut().clear();
+        self.metrics.borrow_mut().take();
+        true
+    }
+
     /// Returns the baseline font specified in the configuration
     pub fn default_font(&self) -> anyhow::Result<Rc<LoadedFont>> {
         self.resolve_font(&configuration().font)
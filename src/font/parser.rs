@@ -74,10 +74,11 @@ impl Names {
 impl ParsedFont {
     /// Load FontDataHandle's for fonts that match the configuration
     /// and that are found in the config font_dirs list.
+    /// Each handle is paired with the scale of the matching attributes.
     pub fn load_fonts(
         config: &Config,
         fonts_selection: &[FontAttributes],
-    ) -> anyhow::Result<Vec<FontDataHandle>> {
+    ) -> anyhow::Result<Vec<(FontDataHandle, f64)>> {
         // First discover the available fonts
         let mut font_info = vec![];
         for path in &config.font_dirs {
@@ -111,10 +112,13 @@ impl ParsedFont {
                         path.display(),
                         index
                     );
-                    handles.push(FontDataHandle::OnDisk {
-                        path: path.clone(),
-                        index: (*index).try_into()?,
-                    });
+                    handles.push((
+                        FontDataHandle::OnDisk {
+                            path: path.clone(),
+                            index: (*index).try_into()?,
+                        },
+                        attr.scale.unwrap_or(1.0),
+                    ));
                     found = true;
                     break;
                 }
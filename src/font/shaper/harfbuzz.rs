@@ -102,10 +102,13 @@ impl HarfbuzzShaper {
             .collect();
 
         let mut buf = harfbuzz::Buffer::new()?;
-        buf.set_script(harfbuzz::hb_script_t::HB_SCRIPT_LATIN);
+        // Text is always laid out left to right in the cell grid,
+        // but the script is taken from the text so that complex
+        // scripts are shaped according to their own rules
         buf.set_direction(harfbuzz::hb_direction_t::HB_DIRECTION_LTR);
         buf.set_language(harfbuzz::language_from_string("en")?);
         buf.add_str(s);
+        buf.guess_segment_properties();
 
         {
             match self.load_fallback(font_idx)? {
@@ -0,0 +1,13 @@
+precision mediump float;
+
+in vec2 o_tex;
+
+uniform sampler2D image_tex;
+uniform float opacity;
+
+out vec4 color;
+
+void main() {
+  color = texture(image_tex, o_tex);
+  color.a *= opacity;
+}
@@ -0,0 +1,12 @@
+precision mediump float;
+in vec2 position;
+in vec2 tex;
+
+uniform mat4 projection;
+
+out vec2 o_tex;
+
+void main() {
+    o_tex = tex;
+    gl_Position = projection * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,137 @@
+//! Loads the `window_background_image` and computes how it is
+//! positioned in the window, for drawing it behind the cells.
+
+// Clippy hates the implement_vertex macro and won't let me scope
+// this warning to its use
+#![allow(clippy::unneeded_field_pattern)]
+
+use crate::config::BackgroundImageMode;
+use ::window::glium::backend::Context as GliumContext;
+use ::window::glium::texture::{RawImage2d, SrgbTexture2d};
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Copy, Clone, Default)]
+pub struct BackgroundVertex {
+    // Physical position of the corner of the window
+    pub position: (f32, f32),
+    // Image texture
+    pub tex: (f32, f32),
+}
+::window::glium::implement_vertex!(BackgroundVertex, position, tex);
+
+pub struct BackgroundImage {
+    path: PathBuf,
+    /// None if the image could not be loaded
+    texture: Option<SrgbTexture2d>,
+}
+
+impl BackgroundImage {
+    /// Loads the image at `path`.  Failure is logged rather than
+    /// returned, so that a bad path doesn't prevent the window from
+    /// being painted, and is remembered so that it is only reported
+    /// once for each path.
+    pub fn load(context: &Rc<GliumContext>, path: &Path) -> Self {
+        let texture = match Self::load_texture(context, path) {
+            Ok(texture) => Some(texture),
+            Err(err) => {
+                log::error!("window_background_image: {:#}", err);
+                None
+            }
+        };
+        Self {
+            path: path.to_path_buf(),
+            texture,
+        }
+    }
+
+    fn load_texture(context: &Rc<GliumContext>, path: &Path) -> anyhow::Result<SrgbTexture2d> {
+        let image = image::open(path)
+            .with_context(|| format!("loading {}", path.display()))?
+            .to_rgba();
+        let dimensions = image.dimensions();
+        let raw = RawImage2d::from_raw_rgba(image.into_raw(), dimensions);
+        Ok(SrgbTexture2d::new(context, raw)?)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn texture(&self) -> Option<&SrgbTexture2d> {
+        self.texture.as_ref()
+    }
+}
+
+/// Computes the texture coordinates of the left, top, right and
+/// bottom edges of a window of `window_size` pixels, such that an
+/// image of `image_size` pixels is fitted to the window as specified
+/// by `mode`.  `scroll_offset` is the number of pixels by which the
+/// image is moved up, which is non-zero when it is attached to the
+/// scrollback.  Coordinates outside the range 0.0-1.0 are expected
+/// to repeat the image.
+pub fn texture_coords(
+    mode: BackgroundImageMode,
+    image_size: (f32, f32),
+    window_size: (f32, f32),
+    scroll_offset: f32,
+) -> (f32, f32, f32, f32) {
+    let (image_width, image_height) = image_size;
+    let (window_width, window_height) = window_size;
+
+    let (left, top, right, bottom) = match mode {
+        BackgroundImageMode::Stretch => (0.0, 0.0, 1.0, 1.0),
+        BackgroundImageMode::Fill => {
+            // Scale the image to cover the window and crop the
+            // excess equally from both sides
+            let scale = (window_width / image_width).max(window_height / image_height);
+            let width = window_width / (image_width * scale);
+            let height = window_height / (image_height * scale);
+            (
+                (1.0 - width) / 2.0,
+                (1.0 - height) / 2.0,
+                (1.0 + width) / 2.0,
+                (1.0 + height) / 2.0,
+            )
+        }
+        BackgroundImageMode::Tile => (
+            0.0,
+            0.0,
+            window_width / image_width,
+            window_height / image_height,
+        ),
+    };
+
+    let offset = scroll_offset / window_height * (bottom - top);
+    (left, top + offset, right, bottom + offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn coords() {
+        assert_eq!(
+            texture_coords(BackgroundImageMode::Stretch, (10., 20.), (100., 50.), 0.),
+            (0., 0., 1., 1.)
+        );
+        // The image is scaled by 10 to cover the width, and half of
+        // its height is cropped
+        assert_eq!(
+            texture_coords(BackgroundImageMode::Fill, (10., 10.), (100., 50.), 0.),
+            (0., 0.25, 1., 0.75)
+        );
+        assert_eq!(
+            texture_coords(BackgroundImageMode::Tile, (10., 20.), (100., 50.), 0.),
+            (0., 0., 10., 2.5)
+        );
+        // Scrolling by a whole window height moves by the height of
+        // the visible part of the image
+        assert_eq!(
+            texture_coords(BackgroundImageMode::Tile, (10., 20.), (100., 50.), 50.),
+            (0., 2.5, 10., 5.)
+        );
+    }
+}
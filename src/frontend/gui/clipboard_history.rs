@@ -0,0 +1,210 @@
+//! Keeps a bounded history of the text that was recently copied to
+//! the clipboard, and provides an overlay from which an earlier entry
+//! can be chosen and pasted.  The history is only held in memory and
+//! is not retained at all when `enable_clipboard_history` is false.
+use crate::config::configuration;
+use crate::termwiztermtab::TermWizTerminal;
+use ::window::{Window, WindowOps};
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::buffered::BufferedTerminal;
+use termwiz::terminal::Terminal;
+use termwiz::widgets::layout::{ChildOrientation, Constraints};
+use termwiz::widgets::list::{ListState, ListView};
+use termwiz::widgets::{RenderArgs, Ui, Widget, WidgetEvent};
+
+lazy_static! {
+    static ref HISTORY: Mutex<ClipboardHistory> = Mutex::new(ClipboardHistory::default());
+}
+
+/// The most recently copied text, newest first
+#[derive(Debug, Default)]
+pub struct ClipboardHistory {
+    entries: VecDeque<String>,
+}
+
+impl ClipboardHistory {
+    /// Records `text` as the newest entry, keeping at most `limit`
+    /// entries.  Copying text that is already in the history moves
+    /// it to the front rather than adding a duplicate.
+    pub fn push(&mut self, text: &str, limit: usize) {
+        if text.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != text);
+        self.entries.push_front(text.to_string());
+        self.entries.truncate(limit);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Adds `text` to the history, if it is enabled
+pub fn record(text: &str) {
+    let config = configuration();
+    let mut history = HISTORY.lock().unwrap();
+    if config.enable_clipboard_history {
+        history.push(text, config.clipboard_history_size);
+    } else {
+        // Forget anything that was recorded before the history
+        // was disabled
+        history.clear();
+    }
+}
+
+/// Returns the entries of the history, newest first
+pub fn entries() -> Vec<String> {
+    if !configuration().enable_clipboard_history {
+        return vec![];
+    }
+    HISTORY.lock().unwrap().entries().cloned().collect()
+}
+
+/// Returns the text that is shown for `entry` in the overlay.  Line
+/// breaks are shown as `↵` so that each entry fits on a single row.
+pub fn label(entry: &str) -> String {
+    entry
+        .replace("\r\n", "\n")
+        .chars()
+        .map(|c| match c {
+            '\n' | '\r' => '\u{21b5}',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+/// A single row of text shown above the list
+struct Heading(&'static str);
+
+impl Widget for Heading {
+    fn render(&mut self, args: &mut RenderArgs) {
+        args.surface.add_changes(vec![
+            Change::ClearScreen(Default::default()),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(self.0.to_string()),
+        ]);
+    }
+
+    fn get_size_constraints(&self) -> Constraints {
+        *Constraints::default().set_fixed_height(1)
+    }
+}
+
+/// Holds the heading and the list, one above the other
+struct Screen;
+
+impl Widget for Screen {
+    fn render(&mut self, _args: &mut RenderArgs) {}
+
+    fn get_size_constraints(&self) -> Constraints {
+        *Constraints::default().set_child_orientation(ChildOrientation::Vertical)
+    }
+}
+
+/// Runs the overlay on `term` until the user chooses an entry to
+/// paste or cancels.  `window` is invalidated each time the overlay
+/// is re-rendered, so that the changes are painted.
+pub fn run(
+    term: TermWizTerminal,
+    entries: Vec<String>,
+    window: &Window,
+) -> anyhow::Result<Option<String>> {
+    let mut buf = BufferedTerminal::new(term)?;
+    let labels = entries.iter().map(|entry| label(entry)).collect();
+    let mut state = ListState::new(labels);
+
+    let mut ui = Ui::new();
+    let root = ui.set_root(Screen);
+    ui.add_child(
+        root,
+        Heading(if entries.is_empty() {
+            "The clipboard history is empty; press Esc to close"
+        } else {
+            "Select an entry and press Enter to paste it, or Esc to cancel"
+        }),
+    );
+    let list = ui.add_child(root, ListView::new(&mut state));
+    ui.set_focus(list);
+
+    let chosen = loop {
+        ui.process_event_queue()?;
+        if ui.render_to_screen(&mut buf)? {
+            continue;
+        }
+        buf.flush()?;
+        window.invalidate();
+
+        match buf.terminal().poll_input(None)? {
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            })) => break false,
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                modifiers: Modifiers::NONE,
+            })) => break true,
+            Some(InputEvent::Resized { cols, rows }) => {
+                buf.add_change(Change::ClearScreen(Default::default()));
+                buf.resize(cols, rows);
+            }
+            Some(input) => ui.queue_event(WidgetEvent::Input(input)),
+            None => {}
+        }
+    };
+
+    // The list view borrows the state until the ui is dropped
+    drop(ui);
+    Ok(if chosen {
+        state.selected().map(|idx| entries[idx].clone())
+    } else {
+        None
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entries(history: &ClipboardHistory) -> Vec<&str> {
+        history.entries().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn bounded() {
+        let mut history = ClipboardHistory::default();
+        history.push("one", 2);
+        history.push("", 2);
+        history.push("two", 2);
+        assert_eq!(entries(&history), vec!["two", "one"]);
+
+        history.push("three", 2);
+        assert_eq!(entries(&history), vec!["three", "two"]);
+
+        // Copying an entry again moves it to the front
+        history.push("two", 2);
+        assert_eq!(entries(&history), vec!["two", "three"]);
+
+        history.clear();
+        assert!(entries(&history).is_empty());
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(label("ls -l"), "ls -l");
+        assert_eq!(label("one\r\ntwo\n"), "one\u{21b5}two\u{21b5}");
+        assert_eq!(label("a\tb"), "a b");
+    }
+}
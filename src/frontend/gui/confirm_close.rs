@@ -0,0 +1,115 @@
+//! An overlay that asks the user to confirm before closing panes whose
+//! foreground program may hold work that would be lost, such as an
+//! editor.  Panes in which an idle shell is in the foreground are
+//! closed without asking; the programs that are considered safe to
+//! close are listed by `skip_close_confirmation_for_processes_named`.
+use crate::config::configuration;
+use crate::mux::tab::Tab;
+use crate::termwiztermtab::TermWizTerminal;
+use ::window::{Window, WindowOps};
+use std::rc::Rc;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::buffered::BufferedTerminal;
+use termwiz::terminal::Terminal;
+
+/// Returns the names of the programs in the foreground of `panes`
+/// whose closure should be confirmed, without duplicates
+pub fn busy_processes(panes: &[Rc<dyn Tab>]) -> Vec<String> {
+    let config = configuration();
+    let mut names: Vec<String> = panes
+        .iter()
+        .filter(|pane| !pane.is_dead())
+        .filter_map(|pane| pane.get_foreground_process())
+        .map(|proc| proc.name)
+        .filter(|name| config.needs_close_confirmation(name))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Returns the question that is asked before closing `what`, which
+/// is running the programs named `names`
+fn prompt(what: &str, names: &[String]) -> String {
+    let running = match names {
+        [name] => format!("{} is", name),
+        _ => format!("{} are", names.join(", ")),
+    };
+    format!("Really close this {}?  {} still running.", what, running)
+}
+
+/// Runs the overlay on `term` until the user answers.  Returns true if
+/// the user confirmed that `what` should be closed.  `window` is
+/// invalidated each time the overlay is re-rendered, so that the
+/// changes are painted.
+pub fn run(
+    term: TermWizTerminal,
+    what: &str,
+    names: &[String],
+    window: &Window,
+) -> anyhow::Result<bool> {
+    let mut buf = BufferedTerminal::new(term)?;
+
+    loop {
+        buf.add_changes(vec![
+            Change::ClearScreen(Default::default()),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(prompt(what, names)),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(2),
+            },
+            Change::Text("Press y to close it, or n or Esc to cancel".to_string()),
+        ]);
+        buf.flush()?;
+        window.invalidate();
+
+        match buf.terminal().poll_input(None)? {
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('y'),
+                ..
+            }))
+            | Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('Y'),
+                ..
+            })) => return Ok(true),
+            Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('n'),
+                ..
+            }))
+            | Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('N'),
+                ..
+            }))
+            | Some(InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            })) => return Ok(false),
+            Some(InputEvent::Resized { cols, rows }) => {
+                buf.resize(cols, rows);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prompts() {
+        assert_eq!(
+            prompt("tab", &["vim".to_string()]),
+            "Really close this tab?  vim is still running."
+        );
+        assert_eq!(
+            prompt("window", &["top".to_string(), "vim".to_string()]),
+            "Really close this window?  top, vim are still running."
+        );
+    }
+}
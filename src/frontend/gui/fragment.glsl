@@ -3,6 +3,7 @@ precision mediump float;
 in vec2 o_tex;
 in vec4 o_fg_color;
 in vec4 o_bg_color;
+in vec4 o_underline_color;
 in float o_has_color;
 in vec2 o_underline;
 in vec2 o_cursor;
@@ -10,6 +11,7 @@ in vec4 o_cursor_color;
 
 uniform mat4 projection;
 uniform bool bg_and_line_layer;
+uniform bool overlay_layer;
 uniform sampler2D glyph_tex;
 
 out vec4 color;
@@ -33,26 +35,26 @@ vec4 multiply(vec4 src, vec4 dst) {
 }
 
 void main() {
-  if (bg_and_line_layer) {
+  if (overlay_layer) {
+    // The cursor and the selection tint for image cells are drawn
+    // above the glyphs and images.  The alpha of the cursor color
+    // allows the tint to be translucent.
+    color = vec4(o_cursor_color.rgb, o_cursor_color.a * texture(glyph_tex, o_cursor).a);
+  } else if (bg_and_line_layer) {
     color = o_bg_color;
 
     // Sample the underline glyph texture for this location.
     // Note that the texture is whitespace in the case where this is
     // no underline or strikethrough.
-    // We tint the underline glyph with the foreground color
-    vec4 under_color = multiply(o_fg_color, texture(glyph_tex, o_underline));
+    // We tint the underline glyph with the underline color, which
+    // is the foreground color unless the cell specifies otherwise
+    vec4 under_color = multiply(o_underline_color, texture(glyph_tex, o_underline));
     if (under_color.a != 0.0) {
         // if the line glyph isn't transparent in this position then
         // we take this pixel color, otherwise we'll leave the color
         // at the background color.
         color = under_color;
     }
-
-    vec4 cursor_outline = multiply(o_cursor_color, texture(glyph_tex, o_cursor));
-    if (cursor_outline.a != 0.0) {
-      color = cursor_outline;
-    }
-
   } else {
     color = texture(glyph_tex, o_tex);
     if (o_has_color == 0.0) {
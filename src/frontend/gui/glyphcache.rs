@@ -130,12 +130,21 @@ impl<T: Texture2d> GlyphCache<T> {
         }
         let (cell_width, cell_height) = (metrics.cell_width, metrics.cell_height);
 
-        let scale = if PixelLength::new(glyph.height as f64) > cell_height * 1.5 {
+        let mut scale = if PixelLength::new(glyph.height as f64) > cell_height * 1.5 {
             // This is another way to detect overside glyph images
             cell_height.get() / glyph.height as f64
         } else {
             1.0f64
         };
+        if glyph.has_color {
+            // Color bitmaps (typically emoji) can be wider than the
+            // cells that they occupy; shrink them to fit rather than
+            // clipping them against the next cell
+            let max_width = cell_width.get() * f64::from(info.num_cells.max(1));
+            if glyph.width as f64 * scale > max_width {
+                scale = max_width / glyph.width as f64;
+            }
+        }
         let glyph = if glyph.width == 0 || glyph.height == 0 {
             // a whitespace glyph
             CachedGlyph {
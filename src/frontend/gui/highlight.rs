@@ -0,0 +1,93 @@
+//! Highlights are layers that the renderer composites over the cells
+//! of a line, rather than changing the attributes of the cells, so
+//! that the selection, the matches of a search and the hovered
+//! hyperlink can all be shown at once, each in its own style.
+use std::ops::Range;
+use std::sync::Arc;
+use term::Line;
+use termwiz::hyperlink::Hyperlink;
+
+/// The kinds of highlight, in the order in which they are composited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HighlightKind {
+    /// The selected text, shown in the selection colors of the palette
+    Selection,
+    /// Text that matches a search, such as the matches labelled by
+    /// QuickSelect mode; its colors are reversed
+    SearchMatch,
+    /// The hyperlink under the mouse, which is underlined
+    Hover,
+}
+
+/// The highlights of a single line
+#[derive(Debug, Default, Clone)]
+pub struct LineHighlights {
+    highlights: Vec<(HighlightKind, Range<usize>)>,
+}
+
+impl LineHighlights {
+    /// Highlights the columns `cols` of the line
+    pub fn add(&mut self, kind: HighlightKind, cols: Range<usize>) {
+        if cols.start < cols.end {
+            self.highlights.push((kind, cols));
+            self.highlights.sort_by_key(|(kind, _)| *kind);
+        }
+    }
+
+    /// Highlights the runs of cells in `line` that belong to the
+    /// hyperlink `link`
+    pub fn add_hyperlink(&mut self, line: &Line, link: &Arc<Hyperlink>) {
+        let mut start = None;
+        for (idx, cell) in line.cells().iter().enumerate() {
+            let in_link = cell
+                .attrs()
+                .hyperlink()
+                .map_or(false, |this| this.is_same_link(link));
+            match (in_link, start) {
+                (true, None) => start = Some(idx),
+                (false, Some(first)) => {
+                    self.add(HighlightKind::Hover, first..idx);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = start {
+            self.add(HighlightKind::Hover, first..line.cells().len());
+        }
+    }
+
+    /// Returns the kinds of highlight that cover column `col`, in the
+    /// order in which they are composited
+    pub fn at(&self, col: usize) -> impl Iterator<Item = HighlightKind> + '_ {
+        self.highlights
+            .iter()
+            .filter(move |(_, cols)| cols.contains(&col))
+            .map(|(kind, _)| *kind)
+    }
+
+    pub fn contains(&self, kind: HighlightKind, col: usize) -> bool {
+        self.at(col).any(|k| k == kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layers() {
+        let mut highlights = LineHighlights::default();
+        highlights.add(HighlightKind::Hover, 2..6);
+        highlights.add(HighlightKind::Selection, 0..4);
+        highlights.add(HighlightKind::SearchMatch, 5..5);
+
+        let at = |col| highlights.at(col).collect::<Vec<_>>();
+        assert_eq!(at(0), vec![HighlightKind::Selection]);
+        assert_eq!(at(3), vec![HighlightKind::Selection, HighlightKind::Hover]);
+        assert_eq!(at(5), vec![HighlightKind::Hover]);
+        assert_eq!(at(6), vec![]);
+        assert!(highlights.contains(HighlightKind::Hover, 2));
+        assert!(!highlights.contains(HighlightKind::SearchMatch, 5));
+    }
+}
@@ -0,0 +1,269 @@
+//! The launcher is an overlay that lists the `launch_menu` entries,
+//! followed by the default program of each attached domain.  The
+//! chosen entry is spawned into a new tab, or into a split of the
+//! active pane.
+use crate::config::LaunchMenuItem;
+use crate::mux::domain::DomainState;
+use crate::mux::splits::SplitDirection;
+use crate::mux::Mux;
+use crate::termwiztermtab::TermWizTerminal;
+use ::window::{Window, WindowOps};
+use termwiz::cell::AttributeChange;
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+
+/// Where the chosen entry is spawned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchTarget {
+    NewTab,
+    SplitPane(SplitDirection),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LauncherOutcome {
+    /// Keep waiting for the user to choose
+    Pending,
+    /// The user closed the launcher without choosing
+    Cancelled,
+    /// The entry with this index was chosen
+    Launch(usize, LaunchTarget),
+}
+
+/// The number of rows above the list of entries
+const HEADER_ROWS: usize = 2;
+
+pub struct LauncherState {
+    entries: Vec<LaunchMenuItem>,
+    active: usize,
+}
+
+impl LauncherState {
+    pub fn new(entries: Vec<LaunchMenuItem>) -> Self {
+        Self { entries, active: 0 }
+    }
+
+    pub fn entries(&self) -> &[LaunchMenuItem] {
+        &self.entries
+    }
+
+    /// Processes a key press, moving the highlighted entry or
+    /// choosing it
+    pub fn key(&mut self, event: &KeyEvent) -> LauncherOutcome {
+        if event.modifiers != Modifiers::NONE && event.modifiers != Modifiers::SHIFT {
+            return LauncherOutcome::Pending;
+        }
+        let target = match event.key {
+            KeyCode::Escape | KeyCode::Char('q') => return LauncherOutcome::Cancelled,
+            KeyCode::UpArrow | KeyCode::Char('k') => {
+                self.move_by(-1);
+                return LauncherOutcome::Pending;
+            }
+            KeyCode::DownArrow | KeyCode::Char('j') => {
+                self.move_by(1);
+                return LauncherOutcome::Pending;
+            }
+            KeyCode::Enter => LaunchTarget::NewTab,
+            KeyCode::Char('h') => LaunchTarget::SplitPane(SplitDirection::Horizontal),
+            KeyCode::Char('v') => LaunchTarget::SplitPane(SplitDirection::Vertical),
+            _ => return LauncherOutcome::Pending,
+        };
+        if self.entries.is_empty() {
+            LauncherOutcome::Pending
+        } else {
+            LauncherOutcome::Launch(self.active, target)
+        }
+    }
+
+    /// Moves the highlight, wrapping around at either end of the list
+    fn move_by(&mut self, delta: isize) {
+        let len = self.entries.len() as isize;
+        if len > 0 {
+            self.active = (self.active as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Returns the changes that draw the launcher on a screen with
+    /// `rows` rows.  The list is scrolled so that the highlighted
+    /// entry is visible.
+    pub fn render(&self, rows: usize) -> Vec<Change> {
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(
+                "Select an item and press Enter to open it in a new tab, \
+                 h or v to split the pane, or Esc to cancel\r\n"
+                    .to_string(),
+            ),
+        ];
+
+        let visible = rows.saturating_sub(HEADER_ROWS).max(1);
+        let first = (self.active + 1).saturating_sub(visible);
+        for (idx, entry) in self.entries.iter().enumerate().skip(first).take(visible) {
+            changes.push(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(HEADER_ROWS + idx - first),
+            });
+            if idx == self.active {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+            changes.push(Change::Text(format!(" {} ", label(entry))));
+            if idx == self.active {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+        }
+        changes
+    }
+}
+
+/// Returns the text that is shown for `item` in the launcher
+pub fn label(item: &LaunchMenuItem) -> String {
+    if let Some(label) = &item.label {
+        label.clone()
+    } else if let Some(port) = &item.serial_port {
+        format!("Serial port {}", port)
+    } else if let Some(args) = &item.args {
+        args.join(" ")
+    } else if let Some(domain) = &item.domain {
+        format!("Default program in domain {}", domain)
+    } else {
+        "Default program".to_string()
+    }
+}
+
+/// Returns the configured `launch_menu` entries followed by an entry
+/// for each attached domain
+pub fn launch_entries(launch_menu: &[LaunchMenuItem]) -> Vec<LaunchMenuItem> {
+    let mux = Mux::get().unwrap();
+    let mut entries = launch_menu.to_vec();
+    for domain in mux.iter_domains() {
+        // Domains that are not connected can't spawn, and connecting
+        // may need to prompt the user, so they are left out
+        if domain.state() != DomainState::Attached {
+            continue;
+        }
+        let name = domain.domain_name();
+        if name == "TermWizTerminalDomain" {
+            continue;
+        }
+        entries.push(LaunchMenuItem {
+            domain: Some(name.to_string()),
+            ..Default::default()
+        });
+    }
+    entries
+}
+
+/// Runs the launcher on `term` until the user chooses an entry or
+/// cancels.  `window` is invalidated each time the launcher is
+/// re-rendered, so that the changes are painted.
+pub fn run(
+    mut term: TermWizTerminal,
+    entries: Vec<LaunchMenuItem>,
+    window: &Window,
+) -> anyhow::Result<Option<(LaunchMenuItem, LaunchTarget)>> {
+    let mut state = LauncherState::new(entries);
+    let mut rows = term.get_screen_size()?.rows;
+
+    loop {
+        term.render(&state.render(rows))?;
+        window.invalidate();
+
+        let outcome = match term.poll_input(None)? {
+            Some(InputEvent::Key(event)) => state.key(&event),
+            Some(InputEvent::Resized { rows: new_rows, .. }) => {
+                rows = new_rows;
+                LauncherOutcome::Pending
+            }
+            _ => continue,
+        };
+
+        match outcome {
+            LauncherOutcome::Pending => {}
+            LauncherOutcome::Cancelled => return Ok(None),
+            LauncherOutcome::Launch(idx, target) => {
+                return Ok(Some((state.entries()[idx].clone(), target)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(key: KeyCode) -> KeyEvent {
+        KeyEvent {
+            key,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    fn item(label: &str) -> LaunchMenuItem {
+        LaunchMenuItem {
+            label: Some(label.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn navigate() {
+        let mut state = LauncherState::new(vec![item("a"), item("b"), item("c")]);
+        assert_eq!(
+            state.key(&key(KeyCode::Enter)),
+            LauncherOutcome::Launch(0, LaunchTarget::NewTab)
+        );
+        // Moving up from the first entry wraps to the last
+        assert_eq!(state.key(&key(KeyCode::UpArrow)), LauncherOutcome::Pending);
+        assert_eq!(
+            state.key(&key(KeyCode::Char('v'))),
+            LauncherOutcome::Launch(2, LaunchTarget::SplitPane(SplitDirection::Vertical))
+        );
+        assert_eq!(
+            state.key(&key(KeyCode::Char('j'))),
+            LauncherOutcome::Pending
+        );
+        assert_eq!(
+            state.key(&key(KeyCode::Char('h'))),
+            LauncherOutcome::Launch(0, LaunchTarget::SplitPane(SplitDirection::Horizontal))
+        );
+        assert_eq!(state.key(&key(KeyCode::Escape)), LauncherOutcome::Cancelled);
+
+        let mut empty = LauncherState::new(vec![]);
+        assert_eq!(
+            empty.key(&key(KeyCode::DownArrow)),
+            LauncherOutcome::Pending
+        );
+        assert_eq!(empty.key(&key(KeyCode::Enter)), LauncherOutcome::Pending);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(label(&item("zsh")), "zsh");
+        assert_eq!(
+            label(&LaunchMenuItem {
+                args: Some(vec!["top".to_string(), "-d".to_string(), "1".to_string()]),
+                ..Default::default()
+            }),
+            "top -d 1"
+        );
+        assert_eq!(
+            label(&LaunchMenuItem {
+                serial_port: Some("/dev/ttyUSB0".to_string()),
+                ..Default::default()
+            }),
+            "Serial port /dev/ttyUSB0"
+        );
+        assert_eq!(
+            label(&LaunchMenuItem {
+                domain: Some("devbox".to_string()),
+                ..Default::default()
+            }),
+            "Default program in domain devbox"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::config::configuration;
 use crate::font::FontConfiguration;
 use crate::frontend::FrontEnd;
+use crate::mux::session::SessionState;
 use crate::mux::tab::Tab;
 use crate::mux::window::WindowId as MuxWindowId;
 use crate::mux::Mux;
@@ -11,12 +12,20 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+mod background;
+mod clipboard_history;
+mod confirm_close;
 mod glyphcache;
+mod highlight;
+mod launcher;
+mod power;
 mod quad;
+mod quickselect;
 mod renderstate;
 mod scrollbar;
 mod tabbar;
 mod termwindow;
+mod unicode_input;
 mod utilsprites;
 
 pub struct GuiFrontEnd {
@@ -45,6 +54,13 @@ impl GuiFrontEnd {
             }
         }
         let connection = Connection::init()?;
+        #[cfg(target_os = "macos")]
+        connection.set_application_menu("WezTerm", |command| match command {
+            ApplicationCommand::Quit => Connection::get().unwrap().terminate_message_loop(),
+            ApplicationCommand::NewWindow => termwindow::TermWindow::spawn_new_window(),
+        });
+        #[cfg(target_os = "macos")]
+        connection.set_secure_keyboard_entry(configuration().secure_keyboard_entry);
         let front_end = Rc::new(GuiFrontEnd { connection });
         Ok(front_end)
     }
@@ -114,12 +130,25 @@ impl FrontEnd for GuiFrontEnd {
         }
 
         let state = Arc::new(Mutex::new(State { when: None }));
+        let mut last_session = SessionState::default();
 
         self.connection
             .schedule_timer(std::time::Duration::from_millis(200), move || {
                 let mux = Mux::get().unwrap();
                 mux.prune_dead_windows();
                 state.lock().unwrap().mark(mux.is_empty());
+
+                // Snapshot the session while we still have tabs, so that
+                // the most recent layout survives the final window closing
+                if configuration().save_session {
+                    let session = SessionState::capture(&mux);
+                    if !session.is_empty() && session != last_session {
+                        if let Err(err) = session.save() {
+                            log::error!("failed to save session: {}", err);
+                        }
+                        last_session = session;
+                    }
+                }
             });
 
         self.connection.run_message_loop()
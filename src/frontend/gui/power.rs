@@ -0,0 +1,79 @@
+//! Detects whether the system is running on battery power, so that
+//! animations such as blinking can be paused to save power.
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Querying the power source is relatively expensive, so the answer
+/// is reused for this long
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref ON_BATTERY: Mutex<Option<(Instant, bool)>> = Mutex::new(None);
+}
+
+/// Returns true if the system is running on battery power.  Systems
+/// whose power source can't be determined are assumed to be on mains
+/// power.
+pub fn on_battery_power() -> bool {
+    let mut cached = ON_BATTERY.lock().unwrap();
+    match *cached {
+        Some((when, on_battery)) if when.elapsed() < POLL_INTERVAL => on_battery,
+        _ => {
+            let on_battery = query_on_battery_power();
+            *cached = Some((Instant::now(), on_battery));
+            on_battery
+        }
+    }
+}
+
+/// Any battery that is discharging means that there is no mains power
+#[cfg(all(unix, not(target_os = "macos")))]
+fn query_on_battery_power() -> bool {
+    let entries = match std::fs::read_dir("/sys/class/power_supply") {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    entries.filter_map(Result::ok).any(|entry| {
+        read(entry.path().join("type")) == "Battery"
+            && read(entry.path().join("status")) == "Discharging"
+    })
+}
+
+#[cfg(windows)]
+fn query_on_battery_power() -> bool {
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+    unsafe {
+        let mut status: SYSTEM_POWER_STATUS = std::mem::zeroed();
+        // ACLineStatus is 0 when offline, 1 when online and 255 when
+        // the status is unknown
+        GetSystemPowerStatus(&mut status) != 0 && status.ACLineStatus == 0
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn query_on_battery_power() -> bool {
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::string::{CFString, CFStringRef};
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPSCopyPowerSourcesInfo() -> CFTypeRef;
+        fn IOPSGetProvidingPowerSourceType(snapshot: CFTypeRef) -> CFStringRef;
+    }
+
+    unsafe {
+        let snapshot = IOPSCopyPowerSourcesInfo();
+        if snapshot.is_null() {
+            return false;
+        }
+        let snapshot = CFType::wrap_under_create_rule(snapshot);
+        let source = IOPSGetProvidingPowerSourceType(snapshot.as_CFTypeRef());
+        !source.is_null() && CFString::wrap_under_get_rule(source).to_string() == "Battery Power"
+    }
+}
@@ -30,6 +30,7 @@ pub struct Vertex {
     pub cursor_color: (f32, f32, f32, f32),
     pub bg_color: (f32, f32, f32, f32),
     pub fg_color: (f32, f32, f32, f32),
+    pub underline_color: (f32, f32, f32, f32),
     // "bool can't be an in in the vertex shader"
     pub has_color: f32,
 }
@@ -43,6 +44,7 @@ pub struct Vertex {
     cursor_color,
     bg_color,
     fg_color,
+    underline_color,
     has_color
 );
 
@@ -143,6 +145,13 @@ impl<'a> Quad<'a> {
         }
     }
 
+    pub fn set_underline_color(&mut self, color: Color) {
+        let color = color.to_tuple_rgba();
+        for v in self.vert.iter_mut() {
+            v.underline_color = color;
+        }
+    }
+
     /// Assign the underline texture coordinates for the cell
     pub fn set_underline(&mut self, coords: TextureRect) {
         self.vert[V_TOP_LEFT].underline = (coords.min_x(), coords.min_y());
@@ -0,0 +1,341 @@
+//! QuickSelect mode scans the viewport for text that matches the
+//! `quick_select_patterns`, such as URLs, paths and hashes, and
+//! highlights each match with a short label overlaid on it.  Typing
+//! a label copies the text of the match to the clipboard.
+use super::highlight::{HighlightKind, LineHighlights};
+use crate::mux::tab::TabId;
+use regex::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
+use term::{Cell, CellAttributes, Intensity, Line, StableRowIndex};
+use termwiz::color::ColorAttribute;
+
+/// A span of text in the viewport that matched one of the patterns
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickSelectMatch {
+    pub row: StableRowIndex,
+    /// The columns occupied by the matching text
+    pub cols: Range<usize>,
+    pub text: String,
+    pub label: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QuickSelectOutcome {
+    /// Keep waiting for the rest of a label
+    Pending,
+    /// The user abandoned the selection
+    Cancelled,
+    /// A label was typed.  `paste` is true if the label was
+    /// typed in upper case, which means that the text should also
+    /// be pasted into the pane.
+    Selected { text: String, paste: bool },
+}
+
+pub struct QuickSelectState {
+    tab_id: TabId,
+    matches: Vec<QuickSelectMatch>,
+    typed: String,
+    paste: bool,
+}
+
+impl QuickSelectState {
+    /// Finds and labels the matches in `lines`.  The labels are made
+    /// from the characters of `alphabet`; no label is a prefix of
+    /// another, and the same text is always given the same label.
+    pub fn new<'a, I: IntoIterator<Item = (StableRowIndex, &'a Line)>>(
+        tab_id: TabId,
+        lines: I,
+        patterns: &[Regex],
+        alphabet: &str,
+    ) -> Self {
+        let mut matches = find_matches(lines, patterns);
+
+        let mut unique: Vec<&str> = vec![];
+        // Label from the bottom up, so that the matches nearest the
+        // prompt get the labels that are quickest to type
+        for m in matches.iter().rev() {
+            if !unique.contains(&m.text.as_str()) {
+                unique.push(&m.text);
+            }
+        }
+        let labels = compute_labels(unique.len(), alphabet);
+        let labels: Vec<(String, String)> =
+            unique.into_iter().map(str::to_string).zip(labels).collect();
+        for m in &mut matches {
+            if let Some((_, label)) = labels.iter().find(|(text, _)| *text == m.text) {
+                m.label = label.clone();
+            }
+        }
+
+        Self {
+            tab_id,
+            matches,
+            typed: String::new(),
+            paste: false,
+        }
+    }
+
+    pub fn tab_id(&self) -> TabId {
+        self.tab_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    pub fn matches(&self) -> &[QuickSelectMatch] {
+        &self.matches
+    }
+
+    /// Processes a key press
+    pub fn key(&mut self, key: termwiz::input::KeyCode) -> QuickSelectOutcome {
+        use termwiz::input::KeyCode;
+        match key {
+            KeyCode::Escape => QuickSelectOutcome::Cancelled,
+            KeyCode::Backspace => {
+                self.typed.pop();
+                if self.typed.is_empty() {
+                    self.paste = false;
+                }
+                QuickSelectOutcome::Pending
+            }
+            KeyCode::Char(c) => {
+                let mut typed = self.typed.clone();
+                typed.extend(c.to_lowercase());
+                let mut candidates = self
+                    .matches
+                    .iter()
+                    .filter(|m| m.label.starts_with(&typed))
+                    .peekable();
+                match candidates.peek() {
+                    // Not the start of any label; ignore it
+                    None => QuickSelectOutcome::Pending,
+                    Some(m) if m.label == typed => QuickSelectOutcome::Selected {
+                        text: m.text.clone(),
+                        paste: self.paste || c.is_uppercase(),
+                    },
+                    Some(_) => {
+                        self.typed = typed;
+                        self.paste |= c.is_uppercase();
+                        QuickSelectOutcome::Pending
+                    }
+                }
+            }
+            _ => QuickSelectOutcome::Pending,
+        }
+    }
+
+    /// Returns the matches on `row` whose labels are consistent with
+    /// what has been typed so far
+    fn live_matches(&self, row: StableRowIndex) -> impl Iterator<Item = &QuickSelectMatch> {
+        self.matches
+            .iter()
+            .filter(move |m| m.row == row && m.label.starts_with(&self.typed))
+    }
+
+    /// Highlights the matches on `row` that are still live
+    pub fn highlight_line(&self, row: StableRowIndex, highlights: &mut LineHighlights) {
+        for m in self.live_matches(row) {
+            highlights.add(HighlightKind::SearchMatch, m.cols.clone());
+        }
+    }
+
+    /// Overlays the labels of the matches on `row` that are still live
+    pub fn decorate_line(&self, row: StableRowIndex, line: &mut Cow<Line>) {
+        for m in self.live_matches(row) {
+            if line.is_compressed() {
+                *line = Cow::Owned(line.uncompressed().into_owned());
+            }
+            let line = line.to_mut();
+            let mut attrs = CellAttributes::default();
+            attrs
+                .set_intensity(Intensity::Bold)
+                .set_foreground(ColorAttribute::PaletteIndex(0))
+                .set_background(ColorAttribute::PaletteIndex(11));
+            for (idx, c) in m.label.chars().enumerate() {
+                let col = m.cols.start + idx;
+                if col < line.cells().len() {
+                    line.set_cell(col, Cell::new(c, attrs.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the text that matches `patterns` in each of the lines,
+/// along with the columns that it occupies.  Where matches overlap,
+/// the one that starts first (and then the longest) is kept.
+fn find_matches<'a, I: IntoIterator<Item = (StableRowIndex, &'a Line)>>(
+    lines: I,
+    patterns: &[Regex],
+) -> Vec<QuickSelectMatch> {
+    let mut result = vec![];
+    for (row, line) in lines {
+        let line = line.uncompressed();
+
+        // Record the byte offset at which each cell starts, so that
+        // the byte ranges of the matches can be mapped to columns
+        let mut text = String::new();
+        let mut cells = vec![];
+        for (col, cell) in line.visible_cells() {
+            cells.push((text.len(), col, cell.width()));
+            text.push_str(cell.str());
+        }
+        let cell_at = |byte: usize| match cells.binary_search_by_key(&byte, |c| c.0) {
+            Ok(idx) => cells[idx],
+            Err(idx) => cells[idx - 1],
+        };
+
+        let mut found: Vec<(Range<usize>, String)> = vec![];
+        for pattern in patterns {
+            for m in pattern.find_iter(&text) {
+                if m.as_str().is_empty() {
+                    continue;
+                }
+                let (_, start, _) = cell_at(m.start());
+                let (_, last, width) = cell_at(m.end() - 1);
+                found.push((start..last + width, m.as_str().to_string()));
+            }
+        }
+        found.sort_by_key(|(cols, _)| (cols.start, std::cmp::Reverse(cols.end)));
+
+        let mut end = 0;
+        for (cols, text) in found {
+            if cols.start < end {
+                continue;
+            }
+            end = cols.end;
+            result.push(QuickSelectMatch {
+                row,
+                cols,
+                text,
+                label: String::new(),
+            });
+        }
+    }
+    result
+}
+
+/// Computes `count` labels from the characters of `alphabet`.  All of
+/// the labels have the same length, so none is a prefix of another.
+fn compute_labels(count: usize, alphabet: &str) -> Vec<String> {
+    let mut chars: Vec<char> = vec![];
+    for c in alphabet.chars().flat_map(char::to_lowercase) {
+        if !chars.contains(&c) {
+            chars.push(c);
+        }
+    }
+    if chars.len() < 2 {
+        chars = crate::config::DEFAULT_QUICK_SELECT_ALPHABET
+            .chars()
+            .collect();
+    }
+
+    let mut len = 1;
+    let mut capacity = chars.len();
+    while capacity < count {
+        len += 1;
+        capacity *= chars.len();
+    }
+
+    (0..count)
+        .map(|mut n| {
+            let mut label = vec![chars[0]; len];
+            for slot in label.iter_mut().rev() {
+                *slot = chars[n % chars.len()];
+                n /= chars.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::input::KeyCode;
+
+    fn state(lines: &[&str], alphabet: &str) -> QuickSelectState {
+        let lines: Vec<Line> = lines
+            .iter()
+            .map(|s| Line::from_text(s, &CellAttributes::default()))
+            .collect();
+        let patterns = vec![
+            Regex::new(r"\b[0-9a-f]{7,40}\b").unwrap(),
+            Regex::new(r"https?://\S+").unwrap(),
+        ];
+        QuickSelectState::new(
+            0,
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (idx as StableRowIndex, line)),
+            &patterns,
+            alphabet,
+        )
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(compute_labels(3, "abc"), vec!["a", "b", "c"]);
+        assert_eq!(compute_labels(4, "abc"), vec!["aa", "ab", "ac", "ba"]);
+        // Repeated characters and case are ignored
+        assert_eq!(compute_labels(2, "AaB"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn matches() {
+        let qs = state(
+            &[
+                "commit 1234abcd see https://example.com/a",
+                "\u{1F480} deadbeef and 1234abcd",
+            ],
+            "asdf",
+        );
+        let found: Vec<(StableRowIndex, Range<usize>, &str, &str)> = qs
+            .matches()
+            .iter()
+            .map(|m| (m.row, m.cols.clone(), m.text.as_str(), m.label.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, 7..15, "1234abcd", "a"),
+                (0, 20..41, "https://example.com/a", "d"),
+                (1, 3..11, "deadbeef", "s"),
+                (1, 16..24, "1234abcd", "a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn typing() {
+        let mut qs = state(&["1234abcd deadbeef https://example.com"], "ab");
+        assert_eq!(qs.matches().len(), 3);
+        // Not the start of any label
+        assert_eq!(qs.key(KeyCode::Char('z')), QuickSelectOutcome::Pending);
+        assert_eq!(qs.key(KeyCode::Char('A')), QuickSelectOutcome::Pending);
+        assert_eq!(qs.key(KeyCode::Backspace), QuickSelectOutcome::Pending);
+        assert_eq!(qs.key(KeyCode::Char('b')), QuickSelectOutcome::Pending);
+        assert_eq!(
+            qs.key(KeyCode::Char('a')),
+            QuickSelectOutcome::Selected {
+                text: "1234abcd".to_string(),
+                paste: false,
+            }
+        );
+
+        // Typing any part of the label in upper case also pastes it
+        let mut qs = state(&["1234abcd deadbeef https://example.com"], "ab");
+        assert_eq!(qs.key(KeyCode::Char('A')), QuickSelectOutcome::Pending);
+        assert_eq!(
+            qs.key(KeyCode::Char('b')),
+            QuickSelectOutcome::Selected {
+                text: "deadbeef".to_string(),
+                paste: true,
+            }
+        );
+        assert_eq!(qs.key(KeyCode::Escape), QuickSelectOutcome::Cancelled);
+    }
+}
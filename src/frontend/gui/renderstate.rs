@@ -1,3 +1,4 @@
+use super::background::BackgroundImage;
 use super::glyphcache::{CachedGlyph, GlyphCache};
 use super::quad::*;
 use super::utilsprites::{RenderMetrics, UtilSprites};
@@ -10,6 +11,7 @@ use ::window::glium::{IndexBuffer, VertexBuffer};
 use ::window::*;
 use anyhow::{anyhow, bail};
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
 
 pub struct SoftwareRenderState {
@@ -37,6 +39,8 @@ pub struct OpenGLRenderState {
     pub glyph_cache: RefCell<GlyphCache<SrgbTexture2d>>,
     pub util_sprites: UtilSprites<SrgbTexture2d>,
     pub program: glium::Program,
+    pub background_program: glium::Program,
+    pub background_image: RefCell<Option<BackgroundImage>>,
     pub glyph_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
     pub glyph_index_buffer: IndexBuffer<u32>,
     pub quads: Quads,
@@ -54,31 +58,16 @@ impl OpenGLRenderState {
         let glyph_cache = RefCell::new(GlyphCache::new_gl(&context, fonts, size)?);
         let util_sprites = UtilSprites::new(&mut *glyph_cache.borrow_mut(), metrics)?;
 
-        let mut errors = vec![];
-        let mut program = None;
-        for version in &["330", "300 es"] {
-            let source = glium::program::ProgramCreationInput::SourceCode {
-                vertex_shader: &Self::vertex_shader(version),
-                fragment_shader: &Self::fragment_shader(version),
-                outputs_srgb: true,
-                tessellation_control_shader: None,
-                tessellation_evaluation_shader: None,
-                transform_feedback_varyings: None,
-                uses_point_size: false,
-                geometry_shader: None,
-            };
-            log::info!("compiling a prog with version {}", version);
-            match glium::Program::new(&context, source) {
-                Ok(prog) => {
-                    program = Some(prog);
-                    break;
-                }
-                Err(err) => errors.push(err.to_string()),
-            };
-        }
-
-        let program =
-            program.ok_or_else(|| anyhow!("Failed to compile shaders: {}", errors.join("\n")))?;
+        let program = Self::compile_program(
+            &context,
+            include_str!("vertex.glsl"),
+            include_str!("fragment.glsl"),
+        )?;
+        let background_program = Self::compile_program(
+            &context,
+            include_str!("background-vertex.glsl"),
+            include_str!("background-fragment.glsl"),
+        )?;
 
         let (glyph_vertex_buffer, glyph_index_buffer, quads) =
             Self::compute_vertices(&context, metrics, pixel_width as f32, pixel_height as f32)?;
@@ -88,6 +77,8 @@ impl OpenGLRenderState {
             glyph_cache,
             util_sprites,
             program,
+            background_program,
+            background_image: RefCell::new(None),
             glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
             glyph_index_buffer,
             quads,
@@ -113,12 +104,41 @@ impl OpenGLRenderState {
         Ok(())
     }
 
-    fn vertex_shader(version: &str) -> String {
-        format!("#version {}\n{}", version, include_str!("vertex.glsl"))
+    /// Loads the background image if `path` differs from the image
+    /// that is currently loaded, or discards it if `path` is None
+    pub fn update_background_image(&self, path: Option<&Path>) {
+        let mut image = self.background_image.borrow_mut();
+        let current = image.as_ref().map(BackgroundImage::path);
+        if current != path {
+            *image = path.map(|path| BackgroundImage::load(&self.context, path));
+        }
     }
 
-    fn fragment_shader(version: &str) -> String {
-        format!("#version {}\n{}", version, include_str!("fragment.glsl"))
+    fn compile_program(
+        context: &Rc<GliumContext>,
+        vertex_shader: &str,
+        fragment_shader: &str,
+    ) -> anyhow::Result<glium::Program> {
+        let mut errors = vec![];
+        for version in &["330", "300 es"] {
+            let source = glium::program::ProgramCreationInput::SourceCode {
+                vertex_shader: &format!("#version {}\n{}", version, vertex_shader),
+                fragment_shader: &format!("#version {}\n{}", version, fragment_shader),
+                outputs_srgb: true,
+                tessellation_control_shader: None,
+                tessellation_evaluation_shader: None,
+                transform_feedback_varyings: None,
+                uses_point_size: false,
+                geometry_shader: None,
+            };
+            log::info!("compiling a prog with version {}", version);
+            match glium::Program::new(context, source) {
+                Ok(prog) => return Ok(prog),
+                Err(err) => errors.push(err.to_string()),
+            };
+        }
+
+        Err(anyhow!("Failed to compile shaders: {}", errors.join("\n")))
     }
 
     /// Compute a vertex buffer to hold the quads that comprise the visible
@@ -1,5 +1,6 @@
-use crate::config::TabBarColors;
+use crate::config::{configuration, TabBarColors};
 use crate::mux::window::Window as MuxWindow;
+use crate::mux::Mux;
 use std::cell::Ref;
 use term::Line;
 use termwiz::cell::unicode_column_width;
@@ -43,11 +44,14 @@ impl TabBarState {
     /// mouse_x is some if the mouse is on the same row as the tab bar.
     /// title_width is the total number of cell columns in the window.
     /// window allows access to the tabs associated with the window.
+    /// leader_active is true while the leader key is waiting for the
+    /// next key, which is indicated at the right of the tab bar.
     pub fn new(
         title_width: usize,
         mouse_x: Option<usize>,
         window: &Ref<MuxWindow>,
         colors: Option<&TabBarColors>,
+        leader_active: bool,
     ) -> Self {
         // We ultimately want to produce a line looking like this:
         // ` | tab1-title x | tab2-title x |  +      . - X `
@@ -57,7 +61,30 @@ impl TabBarState {
         let per_tab_overhead = 2;
         let system_overhead = 3;
 
-        let tab_titles: Vec<_> = window.iter().map(|w| w.get_title()).collect();
+        let mux = Mux::get().unwrap();
+        let config = configuration();
+        let tab_titles: Vec<_> = window
+            .iter()
+            .enumerate()
+            .map(|(idx, tab)| {
+                let title = if config.show_tab_index_in_tab_bar {
+                    format!("{}: {}", idx + 1, tab.get_title())
+                } else {
+                    tab.get_title()
+                };
+                if idx == window.get_active_idx() {
+                    return title;
+                }
+                let activity = mux.get_tab_activity(tab.tab_id());
+                if activity.bell && config.monitor_bell {
+                    format!("! {}", title)
+                } else if activity.output && config.monitor_activity {
+                    format!("* {}", title)
+                } else {
+                    title
+                }
+            })
+            .collect();
         let titles_len: usize = tab_titles.iter().map(|s| unicode_column_width(s)).sum();
         let number_of_tabs = tab_titles.len();
 
@@ -126,9 +153,17 @@ impl TabBarState {
                 .unwrap_or(false);
 
             let cell_attrs = if hover {
-                colors.inactive_tab_hover.as_cell_attributes()
+                colors
+                    .new_tab_hover
+                    .as_ref()
+                    .unwrap_or(&colors.inactive_tab_hover)
+                    .as_cell_attributes()
             } else {
-                colors.inactive_tab.as_cell_attributes()
+                colors
+                    .new_tab
+                    .as_ref()
+                    .unwrap_or(&colors.inactive_tab)
+                    .as_cell_attributes()
             };
 
             items.push(TabEntry {
@@ -153,6 +188,17 @@ impl TabBarState {
             line.set_cell(idx, black_cell.clone());
         }
 
+        if leader_active {
+            const LEADER: &str = " LEADER ";
+            if title_width >= x + LEADER.len() {
+                let cell_attrs = colors.active_tab.as_cell_attributes();
+                let start = title_width - LEADER.len();
+                for (idx, c) in LEADER.chars().enumerate() {
+                    line.set_cell(start + idx, Cell::new(c, cell_attrs.clone()));
+                }
+            }
+        }
+
         Self { line, items }
     }
 
@@ -1,35 +1,99 @@
+use super::background::{self, BackgroundVertex};
+use super::clipboard_history;
+use super::confirm_close;
+use super::highlight::{HighlightKind, LineHighlights};
+use super::launcher::{self, LaunchTarget};
+use super::power;
 use super::quad::*;
+use super::quickselect::{QuickSelectOutcome, QuickSelectState};
 use super::renderstate::*;
+use super::unicode_input;
 use super::utilsprites::RenderMetrics;
-use crate::config::{configuration, ConfigHandle};
+use crate::config::{
+    configuration, emit_key_event, format_window_title, set_color_scheme, with_lua_config,
+    AudibleBell, BackgroundImageAttachment, ConfigHandle, LaunchMenuItem,
+};
 use crate::font::units::*;
 use crate::font::FontConfiguration;
 use crate::frontend::gui::scrollbar::*;
 use crate::frontend::gui::tabbar::{TabBarItem, TabBarState};
 use crate::frontend::{executor, front_end};
-use crate::keyassignment::{KeyAssignment, KeyMap, SpawnTabDomain};
+use crate::keyassignment::{
+    ClipboardCopyDestination, ClipboardPasteSource, KeyAssignment, KeyMap, MouseBindingButton,
+    MouseEventTrigger, MouseMap, SpawnTabDomain,
+};
+use crate::mux::domain::{Domain, DomainId, DomainState, LocalDomain};
 use crate::mux::renderable::Renderable;
+use crate::mux::splits::{PaneDirection, PaneDivider, PanePosition, SplitDirection};
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::window::WindowId as MuxWindowId;
 use crate::mux::Mux;
+use crate::rangeset::RangeSet;
+use crate::termwiztermtab;
 use ::window::bitmaps::atlas::{OutOfTextureSpace, SpriteSlice};
 use ::window::bitmaps::Texture2d;
 use ::window::glium::{uniform, BlendingFunction, LinearBlendingFactor, Surface};
 use ::window::*;
 use anyhow::{anyhow, bail, ensure};
-use portable_pty::PtySize;
+use portable_pty::{CommandBuilder, PtySize};
+use regex::Regex;
 use std::any::Any;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ops::Range;
-use std::ops::{Add, Sub};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use term::color::ColorPalette;
-use term::{CursorPosition, Line, Underline, VisibleRowIndex};
+use term::export::ExportFormat;
+use term::{
+    Blink, CursorPosition, LastMouseClick, Line, MouseAction, SemanticType, StableRowIndex,
+    Underline, VisibleRowIndex,
+};
 use termwiz::color::RgbColor;
-use termwiz::surface::CursorShape;
+use termwiz::lineedit::{LineEditor, NopLineEditorHost};
+use termwiz::surface::{Change, CursorShape, SequenceNo};
+use termwiz::terminal::Terminal;
+
+/// The parts of the state of a tab that are shown in the tab bar
+/// or that may prompt a notification when they change
+#[derive(Debug, Clone, PartialEq)]
+struct TabStatus {
+    tab_id: TabId,
+    title: String,
+    bell: bool,
+    bell_count: usize,
+    output: bool,
+    silent: bool,
+}
+
+impl TabStatus {
+    fn new(mux: &Mux, tab: &Rc<dyn Tab>, silence: Option<Duration>) -> Self {
+        let tab_id = tab.tab_id();
+        let activity = mux.get_tab_activity(tab_id);
+        Self {
+            tab_id,
+            title: tab.get_title(),
+            bell: activity.bell,
+            bell_count: activity.bell_count,
+            output: activity.output,
+            silent: silence.map_or(false, |duration| activity.is_silent(duration)),
+        }
+    }
+}
+
+/// Records what was most recently painted for a pane, so that the
+/// next paint only needs to redraw the rows that changed since then
+#[derive(Debug, Clone)]
+struct PaneRenderState {
+    pos: PanePosition,
+    rows: Range<StableRowIndex>,
+    seqno: SequenceNo,
+    is_active: bool,
+}
 
 #[derive(Debug, Clone, Copy)]
 struct RowsAndCols {
@@ -63,7 +127,11 @@ impl term::Clipboard for ClipboardHelper {
     }
 
     fn set_contents(&self, data: Option<String>) -> anyhow::Result<()> {
-        self.window.set_clipboard(data.unwrap_or_else(String::new));
+        let data = data.unwrap_or_else(String::new);
+        clipboard_history::record(&data);
+        for &clipboard in configuration().selection_copy_destination.clipboards() {
+            self.window.set_clipboard(clipboard, data.clone());
+        }
         Ok(())
     }
 }
@@ -100,6 +168,14 @@ impl PrevCursorPos {
     }
 }
 
+/// Whether each kind of blinking content is currently shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlinkPhases {
+    cursor: bool,
+    slow_text: bool,
+    rapid_text: bool,
+}
+
 pub struct TermWindow {
     window: Option<Window>,
     /// When we most recently received keyboard focus
@@ -113,18 +189,54 @@ pub struct TermWindow {
     render_metrics: RenderMetrics,
     render_state: RenderState,
     keys: KeyMap,
+    mouse_bindings: MouseMap,
+    /// Tracks the click streak that selects the mouse bindings
+    last_mouse_click: Option<LastMouseClick>,
     show_tab_bar: bool,
     show_scroll_bar: bool,
     tab_bar: TabBarState,
     last_mouse_coords: (usize, i64),
     scroll_drag_start: Option<isize>,
+    /// true while the mouse is over the scrollbar thumb
+    scroll_thumb_hover: bool,
     config_generation: usize,
     prev_cursor: PrevCursorPos,
     last_scroll_info: (VisibleRowIndex, usize),
 
+    /// The phase of blinking text is measured from this time
+    text_blink_epoch: Instant,
+    /// The blink phases as of the most recent `update_blinking`
+    blink_phases: BlinkPhases,
+
     /// Gross workaround for managing async keyboard fetching
     /// just for middle mouse button paste function
     clipboard_contents: Arc<Mutex<Option<String>>>,
+
+    /// When the visual bell is showing, the time at which it
+    /// started.  Shared with the window timer.
+    visual_bell: Rc<Cell<Option<Instant>>>,
+
+    /// After the leader key has been pressed, the time by which the
+    /// next key must be pressed.  Shared with the window timer.
+    leader_deadline: Rc<Cell<Option<Instant>>>,
+
+    /// The panes that were painted in the most recent frame.
+    /// Shared with the window timer.
+    painted: Rc<RefCell<HashMap<TabId, PaneRenderState>>>,
+
+    /// The labelled matches while QuickSelect mode is active
+    quick_select: Option<QuickSelectState>,
+
+    /// A termwiz applet, such as the launcher, that is shown in
+    /// place of one of the tabs
+    overlay: Option<Overlay>,
+}
+
+struct Overlay {
+    /// The active pane at the time that the overlay was opened.
+    /// The overlay is shown whenever that pane is active.
+    tab_id: TabId,
+    pane: Rc<dyn Tab>,
 }
 
 struct Host<'a> {
@@ -148,7 +260,7 @@ impl<'a> term::TerminalHost for Host<'a> {
         let link = link.clone();
         promise::Future::with_executor(executor(), move || {
             log::error!("clicking {}", link.uri());
-            if let Err(err) = open::that(link.uri()) {
+            if let Err(err) = crate::open::open_url(link.uri()) {
                 log::error!("failed to open {}: {:?}", link.uri(), err);
             }
             Ok(())
@@ -165,6 +277,8 @@ enum Key {
 impl WindowCallbacks for TermWindow {
     fn created(&mut self, window: &Window) {
         self.window.replace(window.clone());
+        window.set_alt_space_opens_system_menu(configuration().alt_space_opens_system_menu);
+        self.update_resize_increments();
     }
 
     fn can_close(&mut self) -> bool {
@@ -176,6 +290,20 @@ impl WindowCallbacks for TermWindow {
             Some(tab) => tab,
             None => return true,
         };
+        let busy = confirm_close::busy_processes(&[Rc::clone(&tab)]);
+        if !busy.is_empty() {
+            // The window is closed by the timer once the user has
+            // confirmed and it has no tabs left
+            let (active, what) = match mux.get_window(self.mux_window_id) {
+                Some(win) if win.len() == 1 && win.get_active_panes().len() == 1 => {
+                    (win.get_active_idx(), "window")
+                }
+                Some(win) => (win.get_active_idx(), "pane"),
+                None => return true,
+            };
+            self.confirm_close(active, vec![tab.tab_id()], what, busy);
+            return false;
+        }
         mux.remove_tab(tab.tab_id());
         if let Some(mut win) = mux.get_window_mut(self.mux_window_id) {
             win.remove_by_id(tab.tab_id());
@@ -188,11 +316,27 @@ impl WindowCallbacks for TermWindow {
         self
     }
 
+    fn menu_command(&mut self, command: MenuCommand, _context: &dyn WindowOps) {
+        let assignment = match command {
+            MenuCommand::Copy => KeyAssignment::Copy,
+            MenuCommand::Paste => KeyAssignment::Paste,
+        };
+        let mux = Mux::get().unwrap();
+        if let Some(tab) = mux.get_active_tab_for_window(self.mux_window_id) {
+            self.perform_key_assignment(&tab, &assignment).ok();
+        }
+    }
+
     fn focus_change(&mut self, focused: bool) {
         log::trace!("Setting focus to {:?}", focused);
         self.focused = if focused { Some(Instant::now()) } else { None };
         // Reset the cursor blink phase
         self.prev_cursor.bump();
+        // We don't hear about the mouse leaving the window, so stop
+        // highlighting the scroll thumb when we lose the focus
+        if !focused {
+            self.scroll_thumb_hover = false;
+        }
 
         // Heavyweight way to force cursor update
         let mux = Mux::get().unwrap();
@@ -217,6 +361,7 @@ impl WindowCallbacks for TermWindow {
             WMEK::Release(MousePress::Left) => {
                 if self.scroll_drag_start.take().is_some() {
                     // Completed a drag
+                    context.invalidate();
                     return;
                 }
             }
@@ -258,37 +403,51 @@ impl WindowCallbacks for TermWindow {
         }
 
         let config = configuration();
-        let x = (event
-            .coords
-            .x
-            .sub(config.window_padding.left as isize)
-            .max(0)
-            / self.render_metrics.cell_size.width) as usize;
-        let y = (event
-            .coords
-            .y
-            .sub(config.window_padding.top as isize)
-            .max(0)
-            / self.render_metrics.cell_size.height) as i64;
-
-        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
+        let (x, y) = self.pixel_to_cell(&config, event.coords);
+
+        let first_line_offset = self.first_line_offset();
         self.last_mouse_coords = (x, y);
 
-        let in_tab_bar = self.show_tab_bar && y == 0 && event.coords.y >= 0;
+        let in_tab_bar = self.tab_bar_row() == Some(y as usize) && event.coords.y >= 0;
         let in_scroll_bar = self.show_scroll_bar && x >= self.terminal_size.cols as usize;
 
+        if self.show_scroll_bar {
+            let hover = in_scroll_bar
+                && match ScrollHit::test(
+                    event.coords.y,
+                    &*tab.renderer(),
+                    self.terminal_size,
+                    &self.dimensions,
+                ) {
+                    ScrollHit::OnThumb(_) => true,
+                    _ => false,
+                };
+            if hover != self.scroll_thumb_hover {
+                self.scroll_thumb_hover = hover;
+                context.invalidate();
+            }
+        }
+
+        let mut over_link = false;
+
         if in_tab_bar {
-            if let WMEK::Press(MousePress::Left) = event.kind {
-                match self.tab_bar.hit_test(x) {
-                    TabBarItem::Tab(tab_idx) => {
-                        self.activate_tab(tab_idx).ok();
-                    }
-                    TabBarItem::NewTabButton => {
-                        self.spawn_tab(&SpawnTabDomain::CurrentTabDomain).ok();
-                    }
-                    TabBarItem::None => {}
+            match (&event.kind, self.tab_bar.hit_test(x)) {
+                (WMEK::Press(MousePress::Left), TabBarItem::Tab(tab_idx)) => {
+                    self.activate_tab(tab_idx).ok();
+                }
+                (WMEK::Press(MousePress::Middle), TabBarItem::Tab(tab_idx)) => {
+                    self.close_tab(tab_idx);
+                }
+                (WMEK::Press(MousePress::Left), TabBarItem::NewTabButton) => {
+                    self.spawn_tab(&SpawnTabDomain::CurrentTabDomain).ok();
+                }
+                (WMEK::Press(MousePress::Right), TabBarItem::NewTabButton) => {
+                    self.show_launcher();
                 }
+                _ => {}
             }
+        } else if self.active_overlay().is_some() {
+            // The overlay doesn't respond to the mouse
         } else if in_scroll_bar {
             if let WMEK::Press(MousePress::Left) = event.kind {
                 let mux = Mux::get().unwrap();
@@ -328,8 +487,10 @@ impl WindowCallbacks for TermWindow {
             }
         } else {
             let y = y.saturating_sub(first_line_offset);
+            let (tab, x, y) = self.pane_at_mouse(&tab, &event.kind, x, y);
+            let previous_highlight = tab.renderer().current_highlight();
 
-            let mouse_event = term::MouseEvent {
+            let mut mouse_event = term::MouseEvent {
                 kind: match event.kind {
                     WMEK::Move => TMEK::Move,
                     WMEK::VertWheel(_)
@@ -369,19 +530,36 @@ impl WindowCallbacks for TermWindow {
                 x,
                 y,
                 modifiers: window_mods_to_termwiz_mods(event.modifiers),
+                action: None,
             };
 
-            if let WMEK::Press(MousePress::Middle) = event.kind {
-                // The middle button will typically want to paste the clipboard but
-                // obtaining the contents is an async operation that requires the
-                // event loop to pump.
-                // So we schedule that work and continue with dispatching the middle
+            let assignment = self.resolve_mouse_binding(&mouse_event);
+            mouse_event.action = match &assignment {
+                Some(KeyAssignment::SelectTextAtMouseCursor(mode)) => {
+                    Some(MouseAction::SelectText(*mode))
+                }
+                Some(KeyAssignment::ExtendSelectionToMouseCursor) => {
+                    Some(MouseAction::ExtendSelection)
+                }
+                Some(KeyAssignment::CompleteSelection) => Some(MouseAction::CompleteSelection),
+                Some(KeyAssignment::CompleteSelectionOrOpenLinkAtMouseCursor) => {
+                    Some(MouseAction::CompleteSelectionOrOpenLink)
+                }
+                Some(KeyAssignment::OpenLinkAtMouseCursor) => Some(MouseAction::OpenLink),
+                Some(KeyAssignment::PasteFrom(_)) => Some(MouseAction::Paste),
+                Some(_) => Some(MouseAction::Nop),
+                None => None,
+            };
+
+            if let Some(KeyAssignment::PasteFrom(source)) = assignment {
+                // Pasting needs the clipboard contents but obtaining them
+                // is an async operation that requires the event loop to pump.
+                // So we schedule that work and continue with dispatching the
                 // button once we have it.
-                // want to paste the clipboard,
 
                 let tab_id = tab.tab_id();
                 let window_clone = self.window.as_ref().cloned().unwrap();
-                let future = self.window.as_ref().unwrap().get_clipboard();
+                let future = self.window.as_ref().unwrap().get_clipboard(source.into());
                 Connection::get().unwrap().spawn_task(async move {
                     if let Ok(clip) = future.await {
                         window_clone.apply(move |myself, context| {
@@ -417,12 +595,29 @@ impl WindowCallbacks for TermWindow {
                 )
                 .ok();
             }
+
+            // The terminal only dirties the rows that contain the link
+            // that was or is now under the mouse; we just need to arrange
+            // for those rows to be repainted when that link changes.
+            let highlight = tab.renderer().current_highlight();
+            let highlight_changed = match (&previous_highlight, &highlight) {
+                (Some(previous), Some(this)) => !this.is_same_link(previous),
+                (None, None) => false,
+                _ => true,
+            };
+            if highlight_changed {
+                context.invalidate();
+            }
+            over_link = highlight.is_some();
         }
 
         match event.kind {
             WMEK::Move => {
-                if self.show_tab_bar && y <= 1 {
-                    self.update_title();
+                // Update the hover state when moving over, or just off, the tab bar
+                if let Some(row) = self.tab_bar_row() {
+                    if (y - row as i64).abs() <= 1 {
+                        self.update_title();
+                    }
                 }
             }
             _ => {
@@ -432,7 +627,7 @@ impl WindowCallbacks for TermWindow {
 
         context.set_cursor(Some(if in_tab_bar || in_scroll_bar {
             MouseCursor::Arrow
-        } else if tab.renderer().current_highlight().is_some() {
+        } else if over_link {
             // When hovering over a hyperlink, show an appropriate
             // mouse cursor to give the cue that it is clickable
             MouseCursor::Hand
@@ -462,10 +657,34 @@ impl WindowCallbacks for TermWindow {
 
         // log::error!("key_event {:?}", key);
 
+        if let Some(overlay) = self.active_overlay() {
+            // The overlay takes all of the input until it completes;
+            // the key bindings don't apply to it
+            let modifiers = window_mods_to_termwiz_mods(key.modifiers);
+            match self.win_key_code_to_termwiz_key_code(&key.key) {
+                Key::Code(key) => {
+                    overlay.key_down(key, modifiers).ok();
+                }
+                Key::Composed(s) => {
+                    overlay.writer().write_all(s.as_bytes()).ok();
+                }
+                Key::None => {}
+            }
+            return true;
+        }
+
         let mux = Mux::get().unwrap();
         if let Some(tab) = mux.get_active_tab_for_window(self.mux_window_id) {
+            if self.quick_select.is_some() {
+                return self.quick_select_key(&tab, key);
+            }
+
             let modifiers = window_mods_to_termwiz_mods(key.modifiers);
 
+            if self.leader_key_event(&tab, key, modifiers) {
+                return true;
+            }
+
             // First chance to operate on the raw key; if it matches a
             // user-defined key binding then we execute it and stop there.
             if let Some(key) = &key.raw_key {
@@ -505,9 +724,68 @@ impl WindowCallbacks for TermWindow {
         false
     }
 
+    /// Handles the leader key and the key that is pressed after it.
+    /// Returns true if `key` was consumed.
+    fn leader_key_event(
+        &mut self,
+        tab: &Rc<dyn Tab>,
+        key: &KeyEvent,
+        modifiers: ::termwiz::input::Modifiers,
+    ) -> bool {
+        // As with the other key bindings, the raw key gets the first chance
+        let codes: Vec<::termwiz::input::KeyCode> = key
+            .raw_key
+            .iter()
+            .chain(std::iter::once(&key.key))
+            .filter_map(|key| match self.win_key_code_to_termwiz_key_code(key) {
+                Key::Code(key) => Some(key),
+                _ => None,
+            })
+            .collect();
+
+        let pending = self
+            .leader_deadline
+            .get()
+            .map_or(false, |deadline| Instant::now() < deadline);
+        if pending {
+            // Holding a modifier for the next key doesn't count as the
+            // next key
+            if !codes.is_empty() && codes.iter().all(|code| code.is_modifier()) {
+                return true;
+            }
+            self.set_leader_deadline(None);
+            // The next key is consumed even if it isn't bound to anything,
+            // as it is in tmux
+            if let Some(assignment) = codes
+                .iter()
+                .find_map(|code| self.keys.lookup_leader(*code, modifiers))
+            {
+                self.perform_key_assignment(tab, &assignment).ok();
+            }
+            return true;
+        }
+
+        match codes
+            .iter()
+            .find_map(|code| self.keys.leader_timeout(*code, modifiers))
+        {
+            Some(timeout) => {
+                self.set_leader_deadline(Some(Instant::now() + timeout));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_leader_deadline(&mut self, deadline: Option<Instant>) {
+        self.leader_deadline.set(deadline);
+        // Show or take down the indicator in the tab bar
+        self.update_title();
+    }
+
     fn paint(&mut self, ctx: &mut dyn PaintContext) {
-        let mux = Mux::get().unwrap();
-        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+        self.prune_overlay();
+        let tab = match self.get_active_tab_or_overlay() {
             Some(tab) => tab,
             None => {
                 ctx.clear(Color::rgb(0, 0, 0));
@@ -516,7 +794,9 @@ impl WindowCallbacks for TermWindow {
         };
 
         self.check_for_config_reload();
+        self.update_profile_font_size();
         self.update_text_cursor(&tab);
+        self.clear_seen_activity();
         self.update_title();
 
         let start = std::time::Instant::now();
@@ -542,8 +822,8 @@ impl WindowCallbacks for TermWindow {
     }
 
     fn paint_opengl(&mut self, frame: &mut glium::Frame) {
-        let mux = Mux::get().unwrap();
-        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+        self.prune_overlay();
+        let tab = match self.get_active_tab_or_overlay() {
             Some(tab) => tab,
             None => {
                 frame.clear_color(0., 0., 0., 1.);
@@ -551,7 +831,9 @@ impl WindowCallbacks for TermWindow {
             }
         };
         self.check_for_config_reload();
+        self.update_profile_font_size();
         self.update_text_cursor(&tab);
+        self.clear_seen_activity();
         let start = std::time::Instant::now();
         if let Err(err) = self.paint_tab_opengl(&tab, frame) {
             if let Some(&OutOfTextureSpace { size }) = err.downcast_ref::<OutOfTextureSpace>() {
@@ -576,6 +858,68 @@ impl WindowCallbacks for TermWindow {
     }
 }
 
+/// Returns the lines of a pane that need to be painted, along with
+/// their index relative to the top of the pane.  These are the rows
+/// that changed since the pane was last painted, or all of the rows if
+/// the pane wasn't painted in the prior frame or has since been moved,
+/// resized, scrolled, activated or deactivated.  The new state is
+/// recorded in `painted`.
+fn pane_lines_to_paint<'a>(
+    pos: &PanePosition,
+    term: &'a dyn Renderable,
+    seqno: SequenceNo,
+    is_active: bool,
+    prior: &HashMap<TabId, PaneRenderState>,
+    painted: &mut HashMap<TabId, PaneRenderState>,
+) -> Vec<(usize, Cow<'a, Line>, Range<usize>)> {
+    let rows = term.get_viewport_rows();
+    let dirty = match prior.get(&pos.tab_id) {
+        Some(state) if state.pos == *pos && state.rows == rows && state.is_active == is_active => {
+            term.get_dirty_lines(rows.clone(), state.seqno)
+        }
+        _ => RangeSet::from(rows.clone()),
+    };
+
+    let mut lines = vec![];
+    for range in dirty.iter() {
+        for (row, line, selrange) in term.get_lines(range.clone()) {
+            lines.push(((row - rows.start) as usize, line, selrange));
+        }
+    }
+
+    painted.insert(
+        pos.tab_id,
+        PaneRenderState {
+            pos: *pos,
+            rows,
+            seqno,
+            is_active,
+        },
+    );
+    lines
+}
+
+/// Collects the highlights that are composited over `line`, which is
+/// at `row`: the selected columns `selection`, the matches of an active
+/// QuickSelect and the cells of the `hover`ed hyperlink
+fn line_highlights(
+    row: StableRowIndex,
+    line: &Line,
+    selection: Range<usize>,
+    hover: Option<&Arc<term::cell::Hyperlink>>,
+    quick_select: Option<&QuickSelectState>,
+) -> LineHighlights {
+    let mut highlights = LineHighlights::default();
+    highlights.add(HighlightKind::Selection, selection);
+    if let Some(qs) = quick_select {
+        qs.highlight_line(row, &mut highlights);
+    }
+    if let Some(link) = hover {
+        highlights.add_hyperlink(line, link);
+    }
+    highlights
+}
+
 /// Computes the effective padding for the RHS.
 /// This is needed because the default is 0, but if the user has
 /// enabled the scroll bar then they will expect it to have a reasonable
@@ -588,6 +932,57 @@ pub fn effective_right_padding(config: &ConfigHandle, render_metrics: &RenderMet
     }
 }
 
+/// Computes the size of a window whose terminal area holds `rows` by
+/// `cols` cells, allowing for the padding and the tab bar
+fn dimensions_for_cells(
+    config: &ConfigHandle,
+    render_metrics: &RenderMetrics,
+    show_tab_bar: bool,
+    rows: usize,
+    cols: usize,
+    dpi: usize,
+) -> Dimensions {
+    let rows = rows + if show_tab_bar { 1 } else { 0 };
+    Dimensions {
+        pixel_width: cols * render_metrics.cell_size.width as usize
+            + (config.window_padding.left + effective_right_padding(config, render_metrics))
+                as usize,
+        pixel_height: rows * render_metrics.cell_size.height as usize
+            + (config.window_padding.top + config.window_padding.bottom) as usize,
+        dpi,
+    }
+}
+
+/// Computes the number of whole cells that fit in a window of the
+/// given dimensions, allowing for the padding and the tab bar; the
+/// inverse of `dimensions_for_cells`.  Any remaining pixels are left
+/// as additional padding on the right and bottom edges.
+fn cells_for_dimensions(
+    config: &ConfigHandle,
+    render_metrics: &RenderMetrics,
+    show_tab_bar: bool,
+    dimensions: &Dimensions,
+) -> PtySize {
+    let avail_width = dimensions.pixel_width.saturating_sub(
+        (config.window_padding.left + effective_right_padding(config, render_metrics)) as usize,
+    );
+    let avail_height = dimensions
+        .pixel_height
+        .saturating_sub((config.window_padding.top + config.window_padding.bottom) as usize);
+
+    let cell_width = render_metrics.cell_size.width as usize;
+    let cell_height = render_metrics.cell_size.height as usize;
+    let rows = (avail_height / cell_height).saturating_sub(if show_tab_bar { 1 } else { 0 });
+    let cols = avail_width / cell_width;
+
+    PtySize {
+        rows: rows as u16,
+        cols: cols as u16,
+        pixel_width: (cols * cell_width) as u16,
+        pixel_height: (rows * cell_height) as u16,
+    }
+}
+
 impl TermWindow {
     pub fn new_window(
         config: &ConfigHandle,
@@ -606,18 +1001,14 @@ impl TermWindow {
             pixel_height: (render_metrics.cell_size.height as usize * physical_rows) as u16,
         };
 
-        let rows_with_tab_bar = if config.enable_tab_bar { 1 } else { 0 } + terminal_size.rows;
-
-        let dimensions = Dimensions {
-            pixel_width: ((terminal_size.cols * render_metrics.cell_size.width as u16)
-                + config.window_padding.left
-                + effective_right_padding(&config, &render_metrics))
-                as usize,
-            pixel_height: ((rows_with_tab_bar * render_metrics.cell_size.height as u16)
-                + config.window_padding.top
-                + config.window_padding.bottom) as usize,
-            dpi: config.dpi as usize,
-        };
+        let dimensions = dimensions_for_cells(
+            &config,
+            &render_metrics,
+            config.enable_tab_bar,
+            physical_rows,
+            physical_cols,
+            config.dpi as usize,
+        );
 
         log::info!(
             "TermWindow::new_window called with mux_window_id {} {:?} {:?}",
@@ -634,12 +1025,16 @@ impl TermWindow {
         )?);
 
         let clipboard_contents = Arc::new(Mutex::new(None));
+        let visual_bell = Rc::new(Cell::new(None));
+        let leader_deadline = Rc::new(Cell::new(None));
+        let painted = Rc::new(RefCell::new(HashMap::new()));
 
         let window = Window::new_window(
-            "wezterm",
+            &config.window_class,
             "wezterm",
             dimensions.pixel_width,
             dimensions.pixel_height,
+            config.window_theme.into(),
             Box::new(Self {
                 window: None,
                 focused: None,
@@ -650,15 +1045,29 @@ impl TermWindow {
                 terminal_size,
                 render_state,
                 keys: KeyMap::new(),
+                mouse_bindings: MouseMap::new(),
+                last_mouse_click: None,
                 show_tab_bar: config.enable_tab_bar,
                 show_scroll_bar: config.enable_scroll_bar,
                 tab_bar: TabBarState::default(),
                 last_mouse_coords: (0, -1),
                 scroll_drag_start: None,
+                scroll_thumb_hover: false,
                 config_generation: config.generation(),
                 prev_cursor: PrevCursorPos::new(),
                 last_scroll_info: (0, 0),
+                text_blink_epoch: Instant::now(),
+                blink_phases: BlinkPhases {
+                    cursor: true,
+                    slow_text: true,
+                    rapid_text: true,
+                },
                 clipboard_contents: Arc::clone(&clipboard_contents),
+                visual_bell: Rc::clone(&visual_bell),
+                leader_deadline: Rc::clone(&leader_deadline),
+                painted: Rc::clone(&painted),
+                quick_select: None,
+                overlay: None,
             }),
         )?;
 
@@ -668,11 +1077,11 @@ impl TermWindow {
         Connection::get()
             .unwrap()
             .schedule_timer(std::time::Duration::from_millis(35), {
-                let mut last_blink_paint = Instant::now();
+                let mut last_tab_status: Vec<TabStatus> = vec![];
                 move || {
                     let mux = Mux::get().unwrap();
 
-                    if let Some(tab) = mux.get_active_tab_for_window(mux_window_id) {
+                    if mux.get_active_tab_for_window(mux_window_id).is_some() {
                         // If the config was reloaded, ask the window to apply
                         // and render any changes
                         let config = configuration();
@@ -687,33 +1096,91 @@ impl TermWindow {
                             });
                         }
 
-                        let mut render = tab.renderer();
-
-                        // If blinking is permitted, and the cursor shape is set
-                        // to a blinking variant, and it's been longer than the
-                        // blink rate interval, then invalid the lines in the terminal
-                        // so that we will re-evaluate the cursor visibility.
-                        // This is pretty heavyweight: it would be nice to only invalidate
-                        // the line on which the cursor resides, and then only if the cursor
-                        // is within the viewport.
-                        if config.cursor_blink_rate != 0 {
-                            let shape = config
-                                .default_cursor_style
-                                .effective_shape(render.get_cursor_position().shape);
-                            if shape.is_blinking() {
-                                let now = Instant::now();
-                                if now.duration_since(last_blink_paint)
-                                    > Duration::from_millis(config.cursor_blink_rate)
-                                {
-                                    render.make_all_lines_dirty();
-                                    last_blink_paint = now;
+                        // The title or activity of a background tab may have
+                        // changed; the tab bar needs to be updated to reflect
+                        // that, and the user may want to be notified
+                        let silence = match config.monitor_silence_seconds {
+                            0 => None,
+                            secs => Some(Duration::from_secs(secs)),
+                        };
+                        let tab_status: Vec<TabStatus> = match mux.get_window(mux_window_id) {
+                            Some(window) => window
+                                .iter()
+                                .map(|tab| TabStatus::new(&mux, tab, silence))
+                                .collect(),
+                            None => vec![],
+                        };
+                        if tab_status != last_tab_status {
+                            let prior = std::mem::replace(&mut last_tab_status, tab_status.clone());
+                            cloned_window.apply(move |myself, _| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.tab_status_changed(&prior, &tab_status);
                                 }
+                                Ok(())
+                            });
+                        }
+
+                        // Repaint each frame of the visual bell as it fades,
+                        // and once more after it has finished
+                        if let Some(start) = visual_bell.get() {
+                            if visual_bell_intensity(&config, start).is_none() {
+                                visual_bell.set(None);
                             }
+                            cloned_window.apply(|myself, context| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.make_active_panes_dirty();
+                                    context.invalidate();
+                                }
+                                Ok(())
+                            });
+                        }
+
+                        // Take down the leader indicator if the next key
+                        // wasn't pressed in time
+                        if leader_deadline
+                            .get()
+                            .map_or(false, |deadline| Instant::now() >= deadline)
+                        {
+                            leader_deadline.set(None);
+                            cloned_window.apply(|myself, _| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.update_title();
+                                }
+                                Ok(())
+                            });
+                        }
+
+                        if config.cursor_blink_rate != 0
+                            || config.text_blink_rate != 0
+                            || config.text_blink_rate_rapid != 0
+                        {
+                            cloned_window.apply(|myself, context| {
+                                if let Some(myself) = myself.downcast_mut::<Self>() {
+                                    myself.update_blinking(context);
+                                }
+                                Ok(())
+                            });
                         }
 
-                        // If the model is dirty, arrange to re-paint
-                        if render.has_dirty_lines() {
-                            cloned_window.invalidate();
+                        // If the model changed since it was last painted, arrange
+                        // to re-paint.  When the tab has been split, any of its
+                        // panes may have changed.
+                        let painted = painted.borrow();
+                        let changed = |pane: &Rc<dyn Tab>| {
+                            let seqno = pane.renderer().get_current_seqno();
+                            painted
+                                .get(&pane.tab_id())
+                                .map_or(true, |state| state.seqno != seqno)
+                        };
+                        if mux.get_window(mux_window_id).map_or(false, |window| {
+                            window.get_visible_panes().iter().any(changed)
+                        }) {
+                            cloned_window.apply(|myself, context| {
+                                if let Some(myself) = myself.downcast_ref::<Self>() {
+                                    myself.invalidate_changed_rows(context);
+                                }
+                                Ok(())
+                            });
                         }
                     } else {
                         cloned_window.close();
@@ -900,14 +1367,24 @@ impl TermWindow {
         self.show_tab_bar = config.enable_tab_bar;
         self.show_scroll_bar = config.enable_scroll_bar;
         self.keys = KeyMap::new();
+        self.mouse_bindings = MouseMap::new();
         self.config_generation = config.generation();
         let dimensions = self.dimensions;
         let cell_dims = self.current_cell_dimensions();
         self.apply_scale_change(&dimensions, self.fonts.get_font_scale());
         self.apply_dimensions(&dimensions, Some(cell_dims));
+        // The palette may have changed
+        self.make_active_panes_dirty();
         if let Some(window) = self.window.as_ref() {
+            window.set_window_theme(config.window_theme.into());
+            window.set_alt_space_opens_system_menu(config.alt_space_opens_system_menu);
             window.invalidate();
         }
+        #[cfg(target_os = "macos")]
+        Connection::get()
+            .unwrap()
+            .set_secure_keyboard_entry(config.secure_keyboard_entry);
+        self.update_resize_increments();
     }
 
     fn update_scrollbar(&mut self) {
@@ -933,6 +1410,232 @@ impl TermWindow {
         }
     }
 
+    /// Called by the window timer when the title or activity of one
+    /// of the tabs in this window has changed
+    fn tab_status_changed(&mut self, prior: &[TabStatus], current: &[TabStatus]) {
+        let config = configuration();
+        let mux = Mux::get().unwrap();
+        let active_id = mux
+            .get_active_tab_for_window(self.mux_window_id)
+            .map(|tab| tab.tab_id());
+
+        for status in current {
+            let was = prior.iter().find(|p| p.tab_id == status.tab_id);
+            let rang = status.bell_count > was.map_or(0, |p| p.bell_count);
+            let fell_silent = status.silent && !was.map_or(false, |p| p.silent);
+            let is_active = active_id == Some(status.tab_id);
+            let is_seen = is_active && self.focused.is_some();
+
+            if rang && is_active && config.audible_bell == AudibleBell::SystemBeep {
+                Connection::get().unwrap().beep();
+            }
+            if rang && is_active && config.visual_bell {
+                self.visual_bell.set(Some(Instant::now()));
+                self.make_active_panes_dirty();
+            }
+            if rang && !is_seen && config.bell_notification {
+                crate::toast_notification(&status.title, "The bell rang");
+            }
+            if fell_silent && !is_seen {
+                crate::toast_notification(
+                    &status.title,
+                    &format!(
+                        "There has been no output for {} seconds",
+                        config.monitor_silence_seconds
+                    ),
+                );
+            }
+        }
+
+        self.update_title();
+    }
+
+    /// The panes of the active tab are visible while the window has
+    /// the focus, so any activity in them has been seen by the user
+    fn clear_seen_activity(&self) {
+        if self.focused.is_none() {
+            return;
+        }
+        let mux = Mux::get().unwrap();
+        if let Some(window) = mux.get_window(self.mux_window_id) {
+            for pane in window.get_active_panes() {
+                mux.clear_tab_activity(pane.tab_id());
+            }
+        }
+    }
+
+    /// Invalidates only the rows of the window that hold lines that
+    /// changed since they were last painted, so that a small change,
+    /// such as typing a character, doesn't present the whole window.
+    /// The whole window is invalidated when a pane will be repainted
+    /// in full anyway; see `pane_lines_to_paint`.
+    fn invalidate_changed_rows(&self, window: &dyn WindowOps) {
+        let tab = match self.get_active_tab_or_overlay() {
+            Some(tab) => tab,
+            None => return,
+        };
+        let (panes, _) = self.get_panes_to_render(&tab);
+        let first_line_offset = self.first_line_offset();
+        let painted = self.painted.borrow();
+
+        let mut damaged: Option<Range<usize>> = None;
+        for (pos, pane) in &panes {
+            let is_active = pane.tab_id() == tab.tab_id();
+            let mut term = pane.renderer();
+            let seqno = term.get_current_seqno();
+            let rows = term.get_viewport_rows();
+            let dirty = match painted.get(&pos.tab_id) {
+                Some(state) if state.seqno == seqno => continue,
+                Some(state)
+                    if state.pos == *pos && state.rows == rows && state.is_active == is_active =>
+                {
+                    term.get_dirty_lines(rows.clone(), state.seqno)
+                }
+                _ => {
+                    window.invalidate();
+                    return;
+                }
+            };
+            for range in dirty.iter() {
+                let top = first_line_offset + pos.top + (range.start - rows.start) as usize;
+                let bottom = first_line_offset + pos.top + (range.end - rows.start) as usize;
+                damaged = Some(match damaged {
+                    Some(prior) => prior.start.min(top)..prior.end.max(bottom),
+                    None => top..bottom,
+                });
+            }
+        }
+
+        if let Some(damaged) = damaged {
+            // Span the full width, so that the padding and the scroll
+            // bar alongside the rows are also presented
+            let config = configuration();
+            let top = self.cell_origin(&config, 0, damaged.start).y;
+            let bottom = self.cell_origin(&config, 0, damaged.end).y;
+            window.invalidate_rect(Rect::new(
+                Point::new(0, top),
+                Size::new(self.dimensions.pixel_width as isize, bottom - top),
+            ));
+        }
+    }
+
+    /// Blinking is paused, leaving the blinking content shown, while
+    /// the window doesn't have the focus or the system is running on
+    /// battery power
+    fn blinking_paused(&self) -> bool {
+        self.focused.is_none() || power::on_battery_power()
+    }
+
+    /// Returns whether content that has been blinking every `rate`
+    /// milliseconds since `since` is currently shown
+    fn blink_visible(&self, rate: u64, since: Instant) -> bool {
+        if rate == 0 || self.blinking_paused() {
+            return true;
+        }
+        let ticks = since.elapsed().as_millis() / rate as u128;
+        (ticks & 1) == 0
+    }
+
+    fn text_blink_visible(&self, blink: Blink) -> bool {
+        let config = configuration();
+        let rate = match blink {
+            Blink::None => return true,
+            Blink::Slow => config.text_blink_rate,
+            Blink::Rapid => config.text_blink_rate_rapid,
+        };
+        self.blink_visible(rate, self.text_blink_epoch)
+    }
+
+    fn current_blink_phases(&self, tab: &Rc<dyn Tab>) -> BlinkPhases {
+        let config = configuration();
+        let shape = config
+            .cursor_style_for_profile(tab.profile().as_deref())
+            .effective_shape(tab.renderer().get_cursor_position().shape);
+        BlinkPhases {
+            cursor: !shape.is_blinking()
+                || self.blink_visible(
+                    config.cursor_blink_rate,
+                    self.prev_cursor.last_cursor_movement(),
+                ),
+            slow_text: self.text_blink_visible(Blink::Slow),
+            rapid_text: self.text_blink_visible(Blink::Rapid),
+        }
+    }
+
+    /// Called periodically by the window timer.  When the blinking
+    /// cursor or text has changed phase, only the lines that hold
+    /// them are repainted.
+    fn update_blinking(&mut self, window: &dyn WindowOps) {
+        let tab = match self.get_active_tab_or_overlay() {
+            Some(tab) => tab,
+            None => return,
+        };
+        let phases = self.current_blink_phases(&tab);
+        let prior = std::mem::replace(&mut self.blink_phases, phases);
+        if phases == prior {
+            return;
+        }
+
+        let text_changed = |blink: Blink| match blink {
+            Blink::None => false,
+            Blink::Slow => phases.slow_text != prior.slow_text,
+            Blink::Rapid => phases.rapid_text != prior.rapid_text,
+        };
+
+        let (panes, _) = self.get_panes_to_render(&tab);
+        for (_, pane) in &panes {
+            let mut term = pane.renderer();
+            let rows = term.get_viewport_rows();
+            if phases.cursor != prior.cursor && pane.tab_id() == tab.tab_id() {
+                let row = rows.start + term.get_cursor_position().y as StableRowIndex;
+                term.make_lines_dirty(row..row + 1);
+            }
+            if phases.slow_text != prior.slow_text || phases.rapid_text != prior.rapid_text {
+                let blinking: Vec<StableRowIndex> = term
+                    .get_lines(rows.clone())
+                    .into_iter()
+                    .filter(|(_, line, _)| {
+                        line.cells()
+                            .iter()
+                            .any(|cell| text_changed(cell.attrs().blink()))
+                    })
+                    .map(|(row, ..)| row)
+                    .collect();
+                for row in blinking {
+                    term.make_lines_dirty(row..row + 1);
+                }
+            }
+        }
+
+        self.invalidate_changed_rows(window);
+    }
+
+    fn make_active_panes_dirty(&self) {
+        let mux = Mux::get().unwrap();
+        if let Some(window) = mux.get_window(self.mux_window_id) {
+            for pane in window.get_active_panes() {
+                pane.renderer().make_all_lines_dirty();
+            }
+        }
+    }
+
+    /// Returns the palette for `pane`; while the visual bell is
+    /// showing, the `visual_bell` color is blended into its background
+    fn pane_palette(&self, pane: &Rc<dyn Tab>) -> ColorPalette {
+        let mut palette = pane.palette();
+        if let Some(start) = self.visual_bell.get() {
+            let config = configuration();
+            if let Some(intensity) = visual_bell_intensity(&config, start) {
+                let color = config
+                    .resolved_palette()
+                    .visual_bell
+                    .unwrap_or(palette.foreground);
+                palette.background = blend_color(palette.background, color, intensity);
+            }
+        }
+        palette
+    }
+
     fn update_title(&mut self) {
         let mux = Mux::get().unwrap();
         let window = match mux.get_window(self.mux_window_id) {
@@ -941,16 +1644,14 @@ impl TermWindow {
         };
         let new_tab_bar = TabBarState::new(
             self.terminal_size.cols as usize,
-            if self.last_mouse_coords.1 == 0 {
+            if self.tab_bar_row().map(|row| row as i64) == Some(self.last_mouse_coords.1) {
                 Some(self.last_mouse_coords.0)
             } else {
                 None
             },
             &window,
-            configuration()
-                .colors
-                .as_ref()
-                .and_then(|c| c.tab_bar.as_ref()),
+            configuration().resolved_palette().tab_bar.as_ref(),
+            self.leader_deadline.get().is_some(),
         );
         if new_tab_bar != self.tab_bar {
             self.tab_bar = new_tab_bar;
@@ -974,8 +1675,21 @@ impl TermWindow {
 
         drop(window);
 
+        let custom_title =
+            with_lua_config(|lua| format_window_title(lua, &title, tab_no, num_tabs)).and_then(
+                |result| match result {
+                    Ok(title) => title,
+                    Err(err) => {
+                        log::error!("format-window-title: {:#}", err);
+                        None
+                    }
+                },
+            );
+
         if let Some(window) = self.window.as_ref() {
-            if num_tabs == 1 {
+            if let Some(title) = custom_title {
+                window.set_title(&title);
+            } else if num_tabs == 1 {
                 window.set_title(&title);
             } else {
                 window.set_title(&format!("[{}/{}] {}", tab_no + 1, num_tabs, title));
@@ -989,12 +1703,7 @@ impl TermWindow {
         if let Some(win) = self.window.as_ref() {
             let config = configuration();
             let r = Rect::new(
-                Point::new(
-                    (cursor.x.max(0) as isize * self.render_metrics.cell_size.width)
-                        .add(config.window_padding.left as isize),
-                    (cursor.y.max(0) as isize * self.render_metrics.cell_size.height)
-                        .add(config.window_padding.top as isize),
-                ),
+                self.cell_origin(&config, cursor.x, cursor.y.max(0) as usize),
                 self.render_metrics.cell_size,
             );
             win.set_text_cursor_position(r);
@@ -1047,9 +1756,7 @@ impl TermWindow {
 
         ensure!(tab_idx < max, "cannot move a tab out of range");
 
-        let tab_inst = window.remove_by_idx(active);
-        window.insert(tab_idx, &tab_inst);
-        window.set_active(tab_idx);
+        window.move_tab(active, tab_idx)?;
 
         drop(window);
         self.update_title();
@@ -1081,8 +1788,49 @@ impl TermWindow {
         self.move_tab(tab)
     }
 
+    fn move_tab_to_new_window(&mut self) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let window = mux
+            .get_window(self.mux_window_id)
+            .ok_or_else(|| anyhow!("no such window"))?;
+        ensure!(
+            window.len() > 1,
+            "cannot move the only tab in a window to a new window"
+        );
+        let tab = window
+            .get_active()
+            .map(Rc::clone)
+            .ok_or_else(|| anyhow!("window has no tabs?"))?;
+        drop(window);
+
+        let window_id = mux.move_tab_to_new_window(tab.tab_id())?;
+        let front_end = front_end().expect("to be called on gui thread");
+        // Each window has its own font scale
+        let fonts = Rc::new(FontConfiguration::new());
+        front_end.spawn_new_window(&fonts, &tab, window_id)?;
+
+        self.update_title();
+        self.update_scrollbar();
+        Ok(())
+    }
+
+    /// Moves the tabs of all of the other windows into this one.
+    /// The gui windows of the emptied windows close themselves when
+    /// they notice that their mux window has gone away.
+    fn merge_all_windows(&mut self) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        for window_id in mux.iter_windows() {
+            if window_id != self.mux_window_id {
+                mux.merge_windows(window_id, self.mux_window_id)?;
+            }
+        }
+
+        self.update_title();
+        self.update_scrollbar();
+        Ok(())
+    }
+
     fn spawn_tab(&mut self, domain: &SpawnTabDomain) -> anyhow::Result<TabId> {
-        let size = self.terminal_size;
         let mux = Mux::get().unwrap();
 
         let domain = match domain {
@@ -1102,7 +1850,17 @@ impl TermWindow {
                 anyhow!("spawn_tab called with unresolvable domain name {}", name)
             })?,
         };
-        let tab = domain.spawn(size, None, self.mux_window_id)?;
+        let cmd = self.command_for_active_cwd(domain.domain_id());
+        self.spawn_tab_in_domain(&domain, cmd)
+    }
+
+    fn spawn_tab_in_domain(
+        &mut self,
+        domain: &Arc<dyn Domain>,
+        cmd: Option<CommandBuilder>,
+    ) -> anyhow::Result<TabId> {
+        let mux = Mux::get().unwrap();
+        let tab = domain.spawn(self.terminal_size, cmd, self.mux_window_id)?;
         let tab_id = tab.tab_id();
 
         let clipboard: Arc<dyn term::Clipboard> = Arc::new(ClipboardHelper {
@@ -1121,6 +1879,306 @@ impl TermWindow {
         Ok(tab_id)
     }
 
+    /// Spawns the launcher entry `item` into a new tab, or into a
+    /// split of the active pane
+    fn spawn_launch_item(
+        &mut self,
+        item: &LaunchMenuItem,
+        target: LaunchTarget,
+    ) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let domain = match (&item.serial_port, &item.domain) {
+            (Some(port), _) => {
+                let mut serial = portable_pty::serial::SerialTty::new(port);
+                if let Some(baud) = item.baud {
+                    serial.set_baud_rate(serial::BaudRate::from_speed(baud));
+                }
+                let domain: Arc<dyn Domain> =
+                    Arc::new(LocalDomain::with_pty_system(port, Box::new(serial)));
+                mux.add_domain(&domain);
+                domain.attach()?;
+                domain
+            }
+            (None, Some(name)) => mux
+                .get_domain_by_name(name)
+                .ok_or_else(|| anyhow!("there is no domain named {}", name))?,
+            (None, None) => mux.default_domain(),
+        };
+        // Connecting may need to prompt the user, which can't be done
+        // from here, so only connected domains are usable
+        ensure!(
+            domain.state() == DomainState::Attached,
+            "domain {} is not connected",
+            domain.domain_name()
+        );
+
+        let mut cmd = match &item.args {
+            Some(args) => {
+                let mut args = args.iter();
+                let prog = args.next().ok_or_else(|| anyhow!("args is empty"))?;
+                let mut cmd = CommandBuilder::new(prog);
+                cmd.args(args);
+                Some(cmd)
+            }
+            None if item.cwd.is_some() => Some(CommandBuilder::new_default_prog()),
+            None => self.command_for_active_cwd(domain.domain_id()),
+        };
+        if let (Some(cmd), Some(cwd)) = (cmd.as_mut(), item.cwd.as_ref()) {
+            cmd.cwd(cwd);
+        }
+
+        let tab_id = match target {
+            LaunchTarget::NewTab => self.spawn_tab_in_domain(&domain, cmd)?,
+            LaunchTarget::SplitPane(direction) => {
+                self.split_pane_in_domain(&domain, cmd, direction)?
+            }
+        };
+        if let (Some(behavior), Some(tab)) = (item.exit_behavior, mux.get_tab(tab_id)) {
+            tab.set_exit_behavior(behavior);
+        }
+        Ok(())
+    }
+
+    /// Opens the launcher over the active pane.  It runs on its own
+    /// thread; when the user makes a choice, the overlay is removed
+    /// and the chosen entry is spawned.
+    fn show_launcher(&mut self) {
+        if self.overlay.is_some() {
+            return;
+        }
+        let mux = Mux::get().unwrap();
+        let tab_id = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab.tab_id(),
+            None => return,
+        };
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+
+        let (term, pane) = termwiztermtab::allocate(
+            self.terminal_size.cols as usize,
+            self.terminal_size.rows as usize,
+        );
+        let overlay_id = pane.tab_id();
+        let entries = launcher::launch_entries(&configuration().launch_menu);
+
+        std::thread::spawn(move || {
+            let choice = match launcher::run(term, entries, &window) {
+                Ok(choice) => choice,
+                Err(err) => {
+                    log::error!("launcher failed: {:#}", err);
+                    None
+                }
+            };
+            window.apply(move |myself, context| {
+                if let Some(myself) = myself.downcast_mut::<Self>() {
+                    if myself.overlay.as_ref().map(|overlay| overlay.pane.tab_id())
+                        == Some(overlay_id)
+                    {
+                        myself.overlay = None;
+                    }
+                    if let Some((item, target)) = &choice {
+                        if let Err(err) = myself.spawn_launch_item(item, *target) {
+                            log::error!("failed to launch {}: {:#}", launcher::label(item), err);
+                        }
+                    }
+                    context.invalidate();
+                }
+                Ok(())
+            });
+        });
+
+        self.overlay = Some(Overlay { tab_id, pane });
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Opens the clipboard history over the active pane.  When the
+    /// user chooses an entry, it is pasted into that pane.
+    fn show_clipboard_history(&mut self) {
+        if self.overlay.is_some() {
+            return;
+        }
+        let mux = Mux::get().unwrap();
+        let tab_id = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab.tab_id(),
+            None => return,
+        };
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+
+        let (term, pane) = termwiztermtab::allocate(
+            self.terminal_size.cols as usize,
+            self.terminal_size.rows as usize,
+        );
+        let overlay_id = pane.tab_id();
+        let entries = clipboard_history::entries();
+
+        std::thread::spawn(move || {
+            let choice = match clipboard_history::run(term, entries, &window) {
+                Ok(choice) => choice,
+                Err(err) => {
+                    log::error!("clipboard history failed: {:#}", err);
+                    None
+                }
+            };
+            window.apply(move |myself, context| {
+                if let Some(myself) = myself.downcast_mut::<Self>() {
+                    if myself.overlay.as_ref().map(|overlay| overlay.pane.tab_id())
+                        == Some(overlay_id)
+                    {
+                        myself.overlay = None;
+                    }
+                    if let Some(text) = choice {
+                        if let Err(err) = paste_text(tab_id, text) {
+                            log::error!("failed to paste from the clipboard history: {:#}", err);
+                        }
+                    }
+                    context.invalidate();
+                }
+                Ok(())
+            });
+        });
+
+        self.overlay = Some(Overlay { tab_id, pane });
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Opens the unicode input overlay over the active pane.  When the
+    /// user chooses a character, it is typed into that pane.
+    fn show_unicode_input(&mut self) {
+        if self.overlay.is_some() {
+            return;
+        }
+        let mux = Mux::get().unwrap();
+        let tab_id = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab.tab_id(),
+            None => return,
+        };
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+
+        let (term, pane) = termwiztermtab::allocate(
+            self.terminal_size.cols as usize,
+            self.terminal_size.rows as usize,
+        );
+        let overlay_id = pane.tab_id();
+
+        std::thread::spawn(move || {
+            let choice = match unicode_input::run(term, &window) {
+                Ok(choice) => choice,
+                Err(err) => {
+                    log::error!("unicode input failed: {:#}", err);
+                    None
+                }
+            };
+            window.apply(move |myself, context| {
+                if let Some(myself) = myself.downcast_mut::<Self>() {
+                    if myself.overlay.as_ref().map(|overlay| overlay.pane.tab_id())
+                        == Some(overlay_id)
+                    {
+                        myself.overlay = None;
+                    }
+                    if let Some(c) = choice {
+                        let mux = Mux::get().unwrap();
+                        if let Some(tab) = mux.get_tab(tab_id) {
+                            let mut text = [0u8; 4];
+                            if let Err(err) =
+                                tab.writer().write_all(c.encode_utf8(&mut text).as_bytes())
+                            {
+                                log::error!("failed to insert {:?}: {:#}", c, err);
+                            }
+                        }
+                    }
+                    context.invalidate();
+                }
+                Ok(())
+            });
+        });
+
+        self.overlay = Some(Overlay { tab_id, pane });
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Returns the overlay if it covers the active pane
+    fn active_overlay(&self) -> Option<Rc<dyn Tab>> {
+        let overlay = self.overlay.as_ref()?;
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_active_tab_for_window(self.mux_window_id)?;
+        if tab.tab_id() == overlay.tab_id {
+            Some(Rc::clone(&overlay.pane))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the overlay if it covers the active pane, or else
+    /// the active pane
+    fn get_active_tab_or_overlay(&self) -> Option<Rc<dyn Tab>> {
+        self.active_overlay().or_else(|| {
+            let mux = Mux::get().unwrap();
+            mux.get_active_tab_for_window(self.mux_window_id)
+        })
+    }
+
+    /// Removes the overlay once it has completed, or if the pane
+    /// that it covers has been closed
+    fn prune_overlay(&mut self) {
+        let mux = Mux::get().unwrap();
+        let done = self.overlay.as_ref().map_or(false, |overlay| {
+            overlay.pane.is_dead() || mux.get_tab(overlay.tab_id).is_none()
+        });
+        if done {
+            self.overlay = None;
+        }
+    }
+
+    /// Returns a command that spawns the default program in the
+    /// working directory of the active tab, so long as that tab
+    /// belongs to the domain being spawned into.  Returns None to
+    /// let the domain pick its usual defaults.
+    fn command_for_active_cwd(&self, domain_id: DomainId) -> Option<CommandBuilder> {
+        let mux = Mux::get().unwrap();
+        let tab = mux.get_active_tab_for_window(self.mux_window_id)?;
+        if tab.domain_id() != domain_id {
+            return None;
+        }
+        let cwd = tab.get_current_working_dir()?;
+        let mut cmd = CommandBuilder::new_default_prog();
+        cmd.cwd(cwd);
+        Some(cmd)
+    }
+
+    fn copy_to_clipboard(&self, tab: &Rc<dyn Tab>, dest: ClipboardCopyDestination) {
+        if let Some(text) = tab.selection_text() {
+            clipboard_history::record(&text);
+            let window = self.window.as_ref().unwrap();
+            for &clipboard in dest.clipboards() {
+                window.set_clipboard(clipboard, text.clone());
+            }
+        }
+    }
+
+    fn paste_from_clipboard(&self, tab: &Rc<dyn Tab>, source: ClipboardPasteSource) {
+        let tab_id = tab.tab_id();
+        let future = self.window.as_ref().unwrap().get_clipboard(source.into());
+        Connection::get().unwrap().spawn_task(async move {
+            if let Ok(clip) = future.await {
+                promise::Future::with_executor(executor(), move || paste_text(tab_id, clip));
+            }
+        });
+    }
+
     fn perform_key_assignment(
         &mut self,
         tab: &Rc<dyn Tab>,
@@ -1132,30 +2190,22 @@ impl TermWindow {
                 self.spawn_tab(spawn_where)?;
             }
             SpawnWindow => {
-                self.spawn_new_window();
+                Self::spawn_new_window();
             }
             ToggleFullScreen => {
                 // self.toggle_full_screen(),
             }
             Copy => {
-                if let Some(text) = tab.selection_text() {
-                    self.window.as_ref().unwrap().set_clipboard(text);
-                }
+                self.copy_to_clipboard(&tab, ClipboardCopyDestination::Clipboard);
             }
             Paste => {
-                let tab_id = tab.tab_id();
-                let future = self.window.as_ref().unwrap().get_clipboard();
-                Connection::get().unwrap().spawn_task(async move {
-                    if let Ok(clip) = future.await {
-                        promise::Future::with_executor(executor(), move || {
-                            let mux = Mux::get().unwrap();
-                            if let Some(tab) = mux.get_tab(tab_id) {
-                                tab.trickle_paste(clip)?;
-                            }
-                            Ok(())
-                        });
-                    }
-                });
+                self.paste_from_clipboard(&tab, ClipboardPasteSource::Clipboard);
+            }
+            CopyTo(dest) => {
+                self.copy_to_clipboard(&tab, *dest);
+            }
+            PasteFrom(source) => {
+                self.paste_from_clipboard(&tab, *source);
             }
             ActivateTabRelative(n) => {
                 self.activate_tab_relative(*n)?;
@@ -1167,6 +2217,7 @@ impl TermWindow {
                 self.activate_tab(*n)?;
             }
             SendString(s) => tab.writer().write_all(s.as_bytes())?,
+            SendKey(key, mods) => tab.key_down(*key, *mods)?,
             Hide => {
                 if let Some(w) = self.window.as_ref() {
                     w.hide();
@@ -1182,11 +2233,267 @@ impl TermWindow {
             ReloadConfiguration => crate::config::reload(),
             MoveTab(n) => self.move_tab(*n)?,
             MoveTabRelative(n) => self.move_tab_relative(*n)?,
+            MoveTabToNewWindow => self.move_tab_to_new_window()?,
+            MergeAllWindows => self.merge_all_windows()?,
+            SetTabTitle(title) => {
+                tab.set_user_title(if title.is_empty() {
+                    None
+                } else {
+                    Some(title.clone())
+                });
+                self.update_title();
+            }
+            SplitPane(direction) => self.split_pane(*direction)?,
+            ActivatePaneDirection(direction) => self.activate_pane_direction(*direction),
+            AdjustPaneSize(direction, amount) => self.adjust_pane_size(*direction, *amount),
+            TogglePaneZoomState => self.toggle_pane_zoom(),
+            CloseCurrentPane => self.close_current_pane(),
+            ClearScrollback => tab.erase_scrollback(),
+            ExportScrollback(format) => self.export_scrollback(tab, *format),
+            QuickSelect => self.start_quick_select(tab),
+            ShowLauncher => self.show_launcher(),
+            ShowClipboardHistory => self.show_clipboard_history(),
+            ShowUnicodeInput => self.show_unicode_input(),
+            ScrollByPage(n) => self.scroll_by_page(tab, *n),
+            ScrollByLine(n) => self.scroll_by_line(tab, *n),
+            ScrollToPrompt(n) => self.scroll_to_prompt(tab, *n),
+            ScrollToPreviousPrompt => self.scroll_to_prompt(tab, -1),
+            ScrollToNextPrompt => self.scroll_to_prompt(tab, 1),
+            ScrollToTop => tab
+                .renderer()
+                .set_viewport_position(VisibleRowIndex::max_value()),
+            ScrollToBottom => tab.renderer().set_viewport_position(0),
+            EmitEvent(name) => {
+                let tab_id = tab.tab_id();
+                if let Some(action) = with_lua_config(|lua| emit_key_event(lua, name, tab_id)) {
+                    if let Some(action) = action? {
+                        self.perform_key_assignment(tab, &action)?;
+                    }
+                }
+            }
+            SetColorScheme(name) => {
+                set_color_scheme(name)?;
+                self.check_for_config_reload();
+            }
+            SetProfile(name) => self.set_profile(tab, name)?,
+            // These are performed by the terminal when they are bound
+            // to the mouse, and have no meaning for a key press
+            SelectTextAtMouseCursor(_)
+            | ExtendSelectionToMouseCursor
+            | CompleteSelection
+            | CompleteSelectionOrOpenLinkAtMouseCursor
+            | OpenLinkAtMouseCursor => {}
         };
         Ok(())
     }
 
-    pub fn spawn_new_window(&mut self) {
+    /// Applies the profile `name` to `tab`, or reverts it to the
+    /// appearance from the configuration if `name` is empty
+    fn set_profile(&mut self, tab: &Rc<dyn Tab>, name: &str) -> anyhow::Result<()> {
+        if name.is_empty() {
+            tab.set_profile(None);
+        } else {
+            ensure!(
+                configuration().get_profile(name).is_some(),
+                "profile {} is not defined",
+                name
+            );
+            tab.set_profile(Some(name.to_string()));
+        }
+        self.update_profile_font_size();
+        self.make_active_panes_dirty();
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+        Ok(())
+    }
+
+    /// Uses the `font_size` of the profile of the active pane, if it
+    /// has one, so that the font size follows the active pane
+    fn update_profile_font_size(&mut self) {
+        let config = configuration();
+        let mux = Mux::get().unwrap();
+        let font_size = mux
+            .get_active_tab_for_window(self.mux_window_id)
+            .and_then(|tab| tab.profile())
+            .and_then(|name| config.get_profile(&name)?.font_size);
+        if !self.fonts.set_font_size(font_size) {
+            return;
+        }
+        let dimensions = self.dimensions;
+        let font_scale = self.fonts.get_font_scale();
+        if config.adjust_window_size_when_changing_font_size {
+            let cell_dims = self.current_cell_dimensions();
+            self.apply_scale_change(&dimensions, font_scale);
+            self.apply_dimensions(&dimensions, Some(cell_dims));
+        } else {
+            self.apply_scale_change(&dimensions, font_scale);
+            self.apply_dimensions(&dimensions, None);
+            self.make_active_panes_dirty();
+        }
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Saves the scrollback of `tab` to a file in the downloads
+    /// directory, falling back to the home directory, and shows
+    /// a notification naming the file
+    fn export_scrollback(&self, tab: &Rc<dyn Tab>, format: ExportFormat) {
+        tab.export_scrollback(format).then(move |result| {
+            let result = result.and_then(|text| {
+                let dir = dirs::download_dir()
+                    .or_else(dirs::home_dir)
+                    .ok_or_else(|| anyhow!("can't find home dir"))?;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                let path = dir.join(format!(
+                    "wezterm-scrollback-{}.{}",
+                    now.as_secs(),
+                    format.extension()
+                ));
+                std::fs::write(&path, text)
+                    .map_err(|err| anyhow!("writing {}: {}", path.display(), err))?;
+                Ok(path)
+            });
+            match result {
+                Ok(path) => {
+                    crate::toast_notification("Scrollback saved", &path.display().to_string())
+                }
+                Err(err) => {
+                    crate::toast_notification("Failed to save scrollback", &err.to_string())
+                }
+            }
+            promise::Future::ok(())
+        });
+    }
+
+    /// Labels the matches of the `quick_select_patterns` in the
+    /// viewport of `tab` and waits for a label to be typed
+    fn start_quick_select(&mut self, tab: &Rc<dyn Tab>) {
+        let config = configuration();
+        let patterns: Vec<Regex> = config
+            .quick_select_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(err) => {
+                    log::error!("invalid quick_select_patterns entry {}: {}", pattern, err);
+                    None
+                }
+            })
+            .collect();
+
+        let state = {
+            let term = tab.renderer();
+            let lines = term.get_lines(term.get_viewport_rows());
+            QuickSelectState::new(
+                tab.tab_id(),
+                lines.iter().map(|(row, line, _)| (*row, &**line)),
+                &patterns,
+                &config.quick_select_alphabet,
+            )
+        };
+        if state.is_empty() {
+            return;
+        }
+        self.quick_select = Some(state);
+        self.repaint_pane(tab.tab_id());
+    }
+
+    /// Handles a key press while QuickSelect mode is active.  All keys
+    /// are consumed until a label is typed or the mode is cancelled.
+    fn quick_select_key(&mut self, tab: &Rc<dyn Tab>, key: &KeyEvent) -> bool {
+        let qs_tab_id = match self.quick_select.as_ref() {
+            Some(qs) => qs.tab_id(),
+            None => return false,
+        };
+        let outcome = if qs_tab_id != tab.tab_id() {
+            // The active tab changed out from under us
+            QuickSelectOutcome::Cancelled
+        } else {
+            match self.win_key_code_to_termwiz_key_code(&key.key) {
+                Key::Code(code) => self.quick_select.as_mut().unwrap().key(code),
+                _ => QuickSelectOutcome::Pending,
+            }
+        };
+
+        match outcome {
+            QuickSelectOutcome::Pending => {}
+            QuickSelectOutcome::Cancelled => self.quick_select = None,
+            QuickSelectOutcome::Selected { text, paste } => {
+                self.quick_select = None;
+                clipboard_history::record(&text);
+                if let Some(window) = self.window.as_ref() {
+                    window.set_clipboard(Clipboard::Clipboard, text.clone());
+                }
+                if paste {
+                    if let Err(err) = tab.trickle_paste(text) {
+                        log::error!("failed to paste QuickSelect text: {}", err);
+                    }
+                }
+            }
+        }
+        self.repaint_pane(qs_tab_id);
+        true
+    }
+
+    /// Arranges for all of the lines of a pane to be painted in
+    /// the next frame
+    fn repaint_pane(&self, tab_id: TabId) {
+        self.painted.borrow_mut().remove(&tab_id);
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Scrolls the viewport of `tab` by `amount` rows.  Negative
+    /// values scroll back into the scrollback.
+    fn scroll_by_line(&mut self, tab: &Rc<dyn Tab>, amount: isize) {
+        let mut render = tab.renderer();
+        let (position, _) = render.get_scrollbar_info();
+        render.set_viewport_position(position.saturating_sub(amount as VisibleRowIndex));
+    }
+
+    /// Scrolls the viewport of `tab` by `amount` pages
+    fn scroll_by_page(&mut self, tab: &Rc<dyn Tab>, amount: isize) {
+        let (rows, _cols) = tab.renderer().physical_dimensions();
+        self.scroll_by_line(tab, amount * rows as isize);
+    }
+
+    /// Scrolls the viewport of `tab` so that the prompt that is `amount`
+    /// prompts away from the top of the viewport is at the top.
+    /// Negative values move back into the scrollback.
+    fn scroll_to_prompt(&mut self, tab: &Rc<dyn Tab>, amount: isize) {
+        if amount == 0 {
+            return;
+        }
+        let mut render = tab.renderer();
+        let prompts = render.get_prompt_rows();
+        let top = render.get_viewport_rows().start;
+        let target = if amount < 0 {
+            prompts
+                .iter()
+                .rev()
+                .filter(|&&row| row < top)
+                .nth((-amount - 1) as usize)
+        } else {
+            prompts
+                .iter()
+                .filter(|&&row| row > top)
+                .nth((amount - 1) as usize)
+        };
+
+        if let Some(target) = target {
+            // The viewport position is measured in rows back from the
+            // bottom of the scrollback, so compute the row that would be
+            // at the top of the viewport if it weren't scrolled
+            let (position, _) = render.get_scrollbar_info();
+            let unscrolled_top = top + position as StableRowIndex;
+            render.set_viewport_position((unscrolled_top - target) as VisibleRowIndex);
+        }
+    }
+
+    pub fn spawn_new_window() {
         promise::Future::with_executor(executor(), move || {
             let mux = Mux::get().unwrap();
             let fonts = Rc::new(FontConfiguration::new());
@@ -1207,6 +2514,7 @@ impl TermWindow {
 
         self.recreate_texture_atlas(None)
             .expect("failed to recreate atlas");
+        self.update_resize_increments();
     }
 
     fn apply_dimensions(
@@ -1236,40 +2544,20 @@ impl TermWindow {
                 pixel_width: cell_dims.cols as u16 * self.render_metrics.cell_size.width as u16,
             };
 
-            let rows = size.rows + if self.show_tab_bar { 1 } else { 0 };
-            let cols = size.cols;
-
-            let pixel_height = (rows * self.render_metrics.cell_size.height as u16)
-                + (config.window_padding.top + config.window_padding.bottom);
-
-            let pixel_width = (cols * self.render_metrics.cell_size.width as u16)
-                + (config.window_padding.left + self.effective_right_padding(&config));
-
-            let dims = Dimensions {
-                pixel_width: pixel_width as usize,
-                pixel_height: pixel_height as usize,
-                dpi: dimensions.dpi,
-            };
+            let dims = dimensions_for_cells(
+                &config,
+                &self.render_metrics,
+                self.show_tab_bar,
+                cell_dims.rows,
+                cell_dims.cols,
+                dimensions.dpi,
+            );
 
             (size, dims)
         } else {
             // Resize of the window dimensions may result in changed terminal dimensions
-            let avail_width = dimensions.pixel_width
-                - (config.window_padding.left + self.effective_right_padding(&config)) as usize;
-            let avail_height = dimensions.pixel_height
-                - (config.window_padding.top + config.window_padding.bottom) as usize;
-
-            let rows = (avail_height / self.render_metrics.cell_size.height as usize)
-                .saturating_sub(if self.show_tab_bar { 1 } else { 0 });
-            let cols = avail_width / self.render_metrics.cell_size.width as usize;
-
-            let size = PtySize {
-                rows: rows as u16,
-                cols: cols as u16,
-                pixel_height: avail_height as u16,
-                pixel_width: avail_width as u16,
-            };
-
+            let size =
+                cells_for_dimensions(&config, &self.render_metrics, self.show_tab_bar, dimensions);
             (size, *dimensions)
         };
 
@@ -1284,11 +2572,12 @@ impl TermWindow {
         self.terminal_size = size;
 
         let mux = Mux::get().unwrap();
-        if let Some(window) = mux.get_window(self.mux_window_id) {
-            for tab in window.iter() {
-                tab.resize(size).ok();
-            }
+        if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+            window.resize(size);
         };
+        if let Some(overlay) = self.overlay.as_ref() {
+            overlay.pane.resize(size).ok();
+        }
         self.update_title();
 
         // Queue up a speculative resize in order to preserve the number of rows+cols
@@ -1323,73 +2612,476 @@ impl TermWindow {
         self.apply_dimensions(&dimensions, scale_changed_cells);
     }
 
+    /// Changes the font scale of this window.  Depending on the
+    /// `adjust_window_size_when_changing_font_size` option, either the
+    /// window is resized to keep the same number of rows and columns,
+    /// or it keeps its size and the number of rows and columns changes.
+    fn set_font_scale(&mut self, font_scale: f64) {
+        let dimensions = self.dimensions;
+        if configuration().adjust_window_size_when_changing_font_size {
+            self.scaling_changed(dimensions, font_scale);
+        } else {
+            self.apply_scale_change(&dimensions, font_scale);
+            self.apply_dimensions(&dimensions, None);
+            // The number of rows and columns may be unchanged, in which
+            // case the panes don't know that they need to be repainted
+            self.make_active_panes_dirty();
+            if let Some(window) = self.window.as_ref() {
+                window.invalidate();
+            }
+        }
+    }
+
     fn decrease_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 0.9);
+        self.set_font_scale(self.fonts.get_font_scale() * 0.9);
     }
     fn increase_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 1.1);
+        self.set_font_scale(self.fonts.get_font_scale() * 1.1);
     }
     fn reset_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, 1.);
+        self.set_font_scale(1.);
     }
 
     fn close_current_tab(&mut self) {
+        let mux = Mux::get().unwrap();
+        let active = match mux.get_window(self.mux_window_id) {
+            Some(win) => win.get_active_idx(),
+            None => return,
+        };
+        self.close_tab(active);
+    }
+
+    /// Closes all of the panes of the tab at `tab_idx`
+    fn close_tab(&mut self, tab_idx: usize) {
+        let mux = Mux::get().unwrap();
+        let panes = match mux.get_window(self.mux_window_id) {
+            Some(win) => win.get_panes_by_idx(tab_idx),
+            None => return,
+        };
+        let busy = confirm_close::busy_processes(&panes);
+        let pane_ids: Vec<TabId> = panes.iter().map(|pane| pane.tab_id()).collect();
+        if busy.is_empty() {
+            self.remove_panes(&pane_ids);
+        } else {
+            self.confirm_close(tab_idx, pane_ids, "tab", busy);
+        }
+    }
+
+    fn close_current_pane(&mut self) {
+        let mux = Mux::get().unwrap();
+        let (tab_idx, tab) = match mux.get_window(self.mux_window_id) {
+            Some(win) => match win.get_active() {
+                Some(tab) => (win.get_active_idx(), Rc::clone(tab)),
+                None => return,
+            },
+            None => return,
+        };
+        let busy = confirm_close::busy_processes(&[Rc::clone(&tab)]);
+        if busy.is_empty() {
+            self.remove_panes(&[tab.tab_id()]);
+        } else {
+            self.confirm_close(tab_idx, vec![tab.tab_id()], "pane", busy);
+        }
+    }
+
+    /// Closes the panes with the ids in `pane_ids` without asking
+    fn remove_panes(&mut self, pane_ids: &[TabId]) {
+        let mux = Mux::get().unwrap();
+        for &pane_id in pane_ids {
+            mux.remove_tab(pane_id);
+            if let Some(mut win) = mux.get_window_mut(self.mux_window_id) {
+                win.remove_by_id(pane_id);
+            }
+        }
+        self.activate_tab_relative(0).ok();
+    }
+
+    /// Asks the user to confirm that `what`, which consists of the
+    /// panes with the ids in `pane_ids` and is running the programs
+    /// named `busy`, should be closed, and closes the panes if they
+    /// agree.  The question is shown in an overlay over the tab at
+    /// `tab_idx`, which is activated so that it can be seen.
+    fn confirm_close(
+        &mut self,
+        tab_idx: usize,
+        pane_ids: Vec<TabId>,
+        what: &'static str,
+        busy: Vec<String>,
+    ) {
+        if self.overlay.is_some() {
+            return;
+        }
+        self.activate_tab(tab_idx).ok();
+        let mux = Mux::get().unwrap();
+        let tab_id = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab.tab_id(),
+            None => return,
+        };
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+
+        let (term, pane) = termwiztermtab::allocate(
+            self.terminal_size.cols as usize,
+            self.terminal_size.rows as usize,
+        );
+        let overlay_id = pane.tab_id();
+
+        std::thread::spawn(move || {
+            let confirmed = match confirm_close::run(term, what, &busy, &window) {
+                Ok(confirmed) => confirmed,
+                Err(err) => {
+                    log::error!("close confirmation failed: {:#}", err);
+                    false
+                }
+            };
+            window.apply(move |myself, context| {
+                if let Some(myself) = myself.downcast_mut::<Self>() {
+                    if myself.overlay.as_ref().map(|overlay| overlay.pane.tab_id())
+                        == Some(overlay_id)
+                    {
+                        myself.overlay = None;
+                    }
+                    if confirmed {
+                        myself.remove_panes(&pane_ids);
+                    }
+                    context.invalidate();
+                }
+                Ok(())
+            });
+        });
+
+        self.overlay = Some(Overlay { tab_id, pane });
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    fn split_pane(&mut self, direction: SplitDirection) -> anyhow::Result<()> {
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
             Some(tab) => tab,
-            None => return,
+            None => bail!("window has no tabs?"),
         };
-        mux.remove_tab(tab.tab_id());
-        if let Some(mut win) = mux.get_window_mut(self.mux_window_id) {
-            win.remove_by_id(tab.tab_id());
+        let domain = mux
+            .get_domain(tab.domain_id())
+            .ok_or_else(|| anyhow!("current tab has unresolvable domain id!?"))?;
+
+        let cmd = self.command_for_active_cwd(domain.domain_id());
+        self.split_pane_in_domain(&domain, cmd, direction)?;
+        Ok(())
+    }
+
+    fn split_pane_in_domain(
+        &mut self,
+        domain: &Arc<dyn Domain>,
+        cmd: Option<CommandBuilder>,
+        direction: SplitDirection,
+    ) -> anyhow::Result<TabId> {
+        let mux = Mux::get().unwrap();
+        // The domain adds the new pane to the window as a tab in its
+        // own right; we then move it into the split and size it to fit.
+        let pane = domain.spawn(self.terminal_size, cmd, self.mux_window_id)?;
+        {
+            let mut window = mux
+                .get_window_mut(self.mux_window_id)
+                .ok_or_else(|| anyhow!("no such window!?"))?;
+            window.split_active(pane.tab_id(), direction)?;
+            window.resize(self.terminal_size);
+        }
+        self.update_title();
+        self.update_scrollbar();
+        Ok(pane.tab_id())
+    }
+
+    fn activate_pane_direction(&mut self, direction: PaneDirection) {
+        let mux = Mux::get().unwrap();
+        if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+            window.activate_pane_direction(direction);
+        }
+        self.update_title();
+        self.update_scrollbar();
+    }
+
+    fn adjust_pane_size(&mut self, direction: PaneDirection, amount: usize) {
+        let mux = Mux::get().unwrap();
+        if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+            window.adjust_active_pane_size(direction, amount);
+        }
+    }
+
+    fn toggle_pane_zoom(&mut self) {
+        let mux = Mux::get().unwrap();
+        if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+            window.toggle_zoom_active_pane();
+        }
+        self.update_scrollbar();
+    }
+
+    /// Returns the panes that make up the active tab, along with their
+    /// positions, and the dividers that separate them.  A tab that has
+    /// not been split consists of a single pane.
+    fn get_panes_to_render(
+        &self,
+        tab: &Rc<dyn Tab>,
+    ) -> (Vec<(PanePosition, Rc<dyn Tab>)>, Vec<PaneDivider>) {
+        let is_overlay = self
+            .overlay
+            .as_ref()
+            .map_or(false, |overlay| overlay.pane.tab_id() == tab.tab_id());
+        let mux = Mux::get().unwrap();
+        if let Some(window) = mux.get_window(self.mux_window_id).filter(|_| !is_overlay) {
+            if let Some(split) = window.get_active_split() {
+                let layout = split.layout(
+                    self.terminal_size.cols as usize,
+                    self.terminal_size.rows as usize,
+                );
+                let panes = layout
+                    .panes
+                    .into_iter()
+                    .filter_map(|pos| {
+                        window
+                            .get_pane(pos.tab_id)
+                            .map(|pane| (pos, Rc::clone(pane)))
+                    })
+                    .collect();
+                return (panes, layout.dividers);
+            }
+        }
+
+        let (rows, cols) = tab.renderer().physical_dimensions();
+        let pos = PanePosition {
+            tab_id: tab.tab_id(),
+            left: 0,
+            top: 0,
+            cols,
+            rows,
+        };
+        (vec![(pos, Rc::clone(tab))], vec![])
+    }
+
+    /// Looks up the mouse binding for `event`, keeping track of the
+    /// click streak as buttons are pressed.  Returns None for events
+    /// that can't be bound, such as the mouse wheel, and Nop for events
+    /// that have no binding, so that they do nothing.
+    fn resolve_mouse_binding(&mut self, event: &term::MouseEvent) -> Option<KeyAssignment> {
+        use ::term::input::MouseButton as TMB;
+        use ::term::input::MouseEventKind as TMEK;
+
+        let button = match event.button {
+            TMB::Left => MouseBindingButton::Left,
+            TMB::Middle => MouseBindingButton::Middle,
+            TMB::Right => MouseBindingButton::Right,
+            _ => return None,
+        };
+
+        let (trigger, streak) = match event.kind {
+            TMEK::Press => {
+                let click = match self.last_mouse_click.take() {
+                    Some(click) => click.add(event.button),
+                    None => LastMouseClick::new(event.button),
+                };
+                let streak = click.streak;
+                self.last_mouse_click = Some(click);
+                (MouseEventTrigger::Down, streak)
+            }
+            TMEK::Release => (
+                MouseEventTrigger::Up,
+                self.last_mouse_click
+                    .as_ref()
+                    .map(|click| click.streak)
+                    .unwrap_or(1),
+            ),
+            TMEK::Move => (MouseEventTrigger::Drag, 1),
+        };
+
+        Some(
+            self.mouse_bindings
+                .lookup(trigger, button, streak, event.modifiers)
+                .unwrap_or(KeyAssignment::Nop),
+        )
+    }
+
+    /// Maps cell coordinates relative to the top left of the terminal
+    /// area to the pane of the active tab that lies under them, and
+    /// coordinates relative to the top left of that pane.
+    /// Pressing a mouse button within an inactive pane activates it.
+    fn pane_at_mouse(
+        &self,
+        tab: &Rc<dyn Tab>,
+        kind: &::window::MouseEventKind,
+        x: usize,
+        y: i64,
+    ) -> (Rc<dyn Tab>, usize, i64) {
+        let mux = Mux::get().unwrap();
+        let mut window = match mux.get_window_mut(self.mux_window_id) {
+            Some(window) => window,
+            None => return (Rc::clone(tab), x, y),
+        };
+        let pos = match window.get_active_split() {
+            Some(split) => split
+                .layout(
+                    self.terminal_size.cols as usize,
+                    self.terminal_size.rows as usize,
+                )
+                .panes
+                .into_iter()
+                .find(|pos| pos.contains(x, y.max(0) as usize)),
+            None => None,
+        };
+        let (pos, pane) =
+            match pos.and_then(|pos| window.get_pane(pos.tab_id).map(|p| (pos, Rc::clone(p)))) {
+                Some(found) => found,
+                None => return (Rc::clone(tab), x, y),
+            };
+        if let ::window::MouseEventKind::Press(_) = kind {
+            if pos.tab_id != tab.tab_id() {
+                window.activate_pane(pos.tab_id);
+            }
         }
-        self.activate_tab_relative(0).ok();
+        (pane, x - pos.left, y - pos.top as i64)
     }
 
-    fn paint_tab(&mut self, tab: &Rc<dyn Tab>, ctx: &mut dyn PaintContext) -> anyhow::Result<()> {
-        let palette = tab.palette();
-        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
-
-        let mut term = tab.renderer();
-        let cursor = {
-            let cursor = term.get_cursor_position();
-            CursorPosition {
-                x: cursor.x,
-                y: cursor.y + first_line_offset as i64,
-                ..cursor
-            }
-        };
-        self.prev_cursor.update(&cursor);
-
-        if self.show_tab_bar {
-            self.render_screen_line(ctx, 0, self.tab_bar.line(), 0..0, &cursor, &*term, &palette)?;
+    /// Computes the cursor position for a pane, with its y coordinate
+    /// adjusted to be relative to the top of the window.
+    /// The cursor is only shown in the active pane.
+    fn pane_cursor(
+        &self,
+        pos: &PanePosition,
+        pane: &Rc<dyn Tab>,
+        term: &dyn Renderable,
+        is_active: bool,
+    ) -> CursorPosition {
+        let first_line_offset = self.first_line_offset();
+        let cursor = term.get_cursor_position();
+        CursorPosition {
+            y: cursor.y + (first_line_offset + pos.top) as i64,
+            shape: if is_active {
+                // Resolve the default shape using the profile of the pane
+                configuration()
+                    .cursor_style_for_profile(pane.profile().as_deref())
+                    .effective_shape(cursor.shape)
+            } else {
+                CursorShape::Hidden
+            },
+            ..cursor
         }
+    }
 
-        {
-            let dirty_lines = term.get_dirty_lines();
+    fn paint_tab(&mut self, tab: &Rc<dyn Tab>, ctx: &mut dyn PaintContext) -> anyhow::Result<()> {
+        let palette = self.pane_palette(tab);
+        let first_line_offset = self.first_line_offset();
+        let (panes, dividers) = self.get_panes_to_render(tab);
+        let num_cols = self.terminal_size.cols as usize;
+
+        let prior = std::mem::replace(&mut *self.painted.borrow_mut(), HashMap::new());
+        let mut painted = HashMap::new();
+
+        let mut num_rows = 0;
+        for (pos, pane) in &panes {
+            let is_active = pane.tab_id() == tab.tab_id();
+            let mut pane_palette = self.pane_palette(pane);
+            let tint = if is_active {
+                None
+            } else {
+                inactive_pane_tint(&configuration())
+            };
+            if let Some((color, amount)) = tint {
+                pane_palette.background = blend_color(pane_palette.background, color, amount);
+            }
+            let mut term = pane.renderer();
+            let seqno = term.get_current_seqno();
+            let cursor = self.pane_cursor(pos, pane, &*term, is_active);
+
+            if is_active {
+                self.prev_cursor.update(&cursor);
+
+                if let Some(tab_bar_row) = self.tab_bar_row() {
+                    self.render_screen_line(
+                        ctx,
+                        tab_bar_row,
+                        0,
+                        num_cols,
+                        self.tab_bar.line(),
+                        &LineHighlights::default(),
+                        &cursor,
+                        &palette,
+                    )?;
+                    self.fill_right_margin(ctx, tab_bar_row, num_cols, &palette);
+                }
+            }
 
-            for (line_idx, line, selrange) in dirty_lines {
+            // Only fill to the right of the panes on the right hand edge;
+            // the others are bordered by a divider
+            let fill_margin = pos.left + pos.cols >= num_cols || panes.len() == 1;
+
+            let dirty_lines =
+                pane_lines_to_paint(pos, &*term, seqno, is_active, &prior, &mut painted);
+            let quick_select = self.pane_quick_select(pane);
+            let hover = term.current_highlight();
+            let top = term.get_viewport_rows().start;
+            let show_prompt_markers = configuration().show_prompt_markers;
+
+            for (line_idx, mut line, selrange) in dirty_lines {
+                let is_prompt = show_prompt_markers && is_prompt_line(&line);
+                let row = top + line_idx as StableRowIndex;
+                let highlights =
+                    line_highlights(row, &line, selrange, hover.as_ref(), quick_select);
+                if let Some(qs) = quick_select {
+                    qs.decorate_line(row, &mut line);
+                }
+                let line_idx = line_idx + first_line_offset + pos.top;
                 self.render_screen_line(
                     ctx,
-                    line_idx + first_line_offset,
+                    line_idx,
+                    pos.left,
+                    pos.cols,
                     &line,
-                    selrange,
+                    &highlights,
                     &cursor,
-                    &*term,
-                    &palette,
+                    &pane_palette,
                 )?;
+                if is_prompt {
+                    self.paint_prompt_marker(ctx, line_idx, pos.left, &cursor, &pane_palette);
+                }
+                if fill_margin {
+                    self.fill_right_margin(ctx, line_idx, pos.left + pos.cols, &pane_palette);
+                }
             }
+
+            num_rows = num_rows.max(pos.top + pos.rows);
+        }
+        *self.painted.borrow_mut() = painted;
+
+        let config = configuration();
+        let split_color = rgbcolor_to_window_color(palette.split);
+        for divider in &dividers {
+            let (cols, rows) = match divider.direction {
+                SplitDirection::Horizontal => (1, divider.length),
+                SplitDirection::Vertical => (divider.length, 1),
+            };
+            ctx.clear_rect(
+                Rect::new(
+                    self.cell_origin(&config, divider.left, divider.top + first_line_offset),
+                    Size::new(
+                        cols as isize * self.render_metrics.cell_size.width,
+                        rows as isize * self.render_metrics.cell_size.height,
+                    ),
+                ),
+                split_color,
+            );
         }
 
-        term.clean_dirty_lines();
+        let term = tab.renderer();
 
         // Fill any padding
-        let config = configuration();
         let bg = rgbcolor_to_window_color(palette.background);
-        // Fill any padding below the last row
-        let (num_rows, _num_cols) = term.physical_dimensions();
+        // Fill any padding below the last row, which may be the tab bar
+        let rows_of_cells =
+            (num_rows + first_line_offset).max(self.tab_bar_row().map(|row| row + 1).unwrap_or(0));
         let pixel_height_of_cells = config.window_padding.top as usize
-            + (num_rows + first_line_offset) * self.render_metrics.cell_size.height as usize;
+            + rows_of_cells * self.render_metrics.cell_size.height as usize;
         ctx.clear_rect(
             Rect::new(
                 Point::new(0, pixel_height_of_cells as isize),
@@ -1461,53 +3153,159 @@ impl TermWindow {
                     ),
                     Size::new(padding_right as isize, thumb_size),
                 ),
-                rgbcolor_to_window_color(palette.scrollbar_thumb),
+                rgbcolor_to_window_color(self.scroll_thumb_color(&palette)),
             );
         }
 
         Ok(())
     }
 
+    /// Software rendering counterpart of `paint_prompt_marker_opengl`
+    fn paint_prompt_marker(
+        &self,
+        ctx: &mut dyn PaintContext,
+        line_idx: usize,
+        left: usize,
+        cursor: &CursorPosition,
+        palette: &ColorPalette,
+    ) {
+        if cursor.y == line_idx as i64 && cursor.x == 0 {
+            return;
+        }
+        let software = self.render_state.software();
+        let sprite = &software.util_sprites.cursor_i_beam;
+        ctx.draw_image(
+            self.cell_origin(&configuration(), left, line_idx),
+            Some(sprite.coords),
+            &*sprite.texture.image.borrow(),
+            Operator::MultiplyThenOver(rgbcolor_to_window_color(prompt_marker_color(palette))),
+        );
+    }
+
+    /// The scrollbar thumb is highlighted while the mouse is over
+    /// it or is dragging it
+    /// Returns the QuickSelect state if it applies to `pane`
+    fn pane_quick_select(&self, pane: &Rc<dyn Tab>) -> Option<&QuickSelectState> {
+        self.quick_select
+            .as_ref()
+            .filter(|qs| qs.tab_id() == pane.tab_id())
+    }
+
+    fn scroll_thumb_color(&self, palette: &ColorPalette) -> RgbColor {
+        if self.scroll_thumb_hover || self.scroll_drag_start.is_some() {
+            palette.scrollbar_thumb_hover
+        } else {
+            palette.scrollbar_thumb
+        }
+    }
+
     fn effective_right_padding(&self, config: &ConfigHandle) -> u16 {
         effective_right_padding(config, &self.render_metrics)
     }
 
+    /// Returns the row of the window in which the tab bar is shown,
+    /// or None if it is hidden
+    fn tab_bar_row(&self) -> Option<usize> {
+        if !self.show_tab_bar {
+            None
+        } else if configuration().tab_bar_at_bottom {
+            Some(self.terminal_size.rows as usize)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Returns the number of rows between the top of the window and
+    /// the panes; 1 if the tab bar is shown at the top of the window
+    fn first_line_offset(&self) -> usize {
+        match self.tab_bar_row() {
+            Some(0) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Returns the pixel coordinates of the top left corner of the
+    /// cell at column `x` and row `y`, where row 0 is the tab bar
+    /// if it is shown
+    fn cell_origin(&self, config: &ConfigHandle, x: usize, y: usize) -> Point {
+        Point::new(
+            x as isize * self.render_metrics.cell_size.width + config.window_padding.left as isize,
+            y as isize * self.render_metrics.cell_size.height + config.window_padding.top as isize,
+        )
+    }
+
+    /// Returns the column and row of the cell that contains the pixel
+    /// at `coords`; the inverse of `cell_origin`.  Positions in the
+    /// left or top padding map to the first column or row.
+    fn pixel_to_cell(&self, config: &ConfigHandle, coords: Point) -> (usize, i64) {
+        let x = (coords.x - config.window_padding.left as isize).max(0)
+            / self.render_metrics.cell_size.width;
+        let y = (coords.y - config.window_padding.top as isize).max(0)
+            / self.render_metrics.cell_size.height;
+        (x as usize, y as i64)
+    }
+
+    /// Constrains interactive resizing of the window to whole cells
+    /// if `use_resize_increments` is enabled
+    fn update_resize_increments(&self) {
+        let config = configuration();
+        let increments = if config.use_resize_increments {
+            Some(ResizeIncrements {
+                base_width: (config.window_padding.left + self.effective_right_padding(&config))
+                    as usize,
+                base_height: (config.window_padding.top + config.window_padding.bottom) as usize,
+                x: self.render_metrics.cell_size.width as usize,
+                y: self.render_metrics.cell_size.height as usize,
+            })
+        } else {
+            None
+        };
+        if let Some(window) = self.window.as_ref() {
+            window.set_resize_increments(increments);
+        }
+    }
+
     fn paint_tab_opengl(
         &mut self,
         tab: &Rc<dyn Tab>,
         frame: &mut glium::Frame,
     ) -> anyhow::Result<()> {
-        let palette = tab.palette();
+        let palette = self.pane_palette(tab);
 
         let background_color = palette.resolve_bg(term::color::ColorAttribute::Default);
         let (r, g, b, a) = background_color.to_tuple_rgba();
         frame.clear_color(r, g, b, a);
 
-        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
+        let has_background_image = self.paint_background_image(tab, frame)?;
 
-        let mut term = tab.renderer();
-        let cursor = {
-            let cursor = term.get_cursor_position();
-            CursorPosition {
-                x: cursor.x,
-                y: cursor.y + first_line_offset as i64,
-                ..cursor
-            }
-        };
+        let first_line_offset = self.first_line_offset();
+        let (panes, dividers) = self.get_panes_to_render(tab);
+        let num_cols = self.terminal_size.cols as usize;
+
+        let active_pos = panes
+            .iter()
+            .find(|(_, pane)| pane.tab_id() == tab.tab_id())
+            .map(|(pos, _)| *pos)
+            .ok_or_else(|| anyhow!("active pane is missing from the layout"))?;
+        let cursor = self.pane_cursor(&active_pos, tab, &*tab.renderer(), true);
         self.prev_cursor.update(&cursor);
 
         let gl_state = self.render_state.opengl();
         let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
         let mut quads = gl_state.quads.map(&mut vb);
 
-        if self.show_tab_bar {
+        let term = tab.renderer();
+
+        if let Some(tab_bar_row) = self.tab_bar_row() {
             self.render_screen_line_opengl(
+                tab_bar_row,
                 0,
+                num_cols,
                 self.tab_bar.line(),
-                0..0,
+                &LineHighlights::default(),
                 &cursor,
-                &*term,
                 &palette,
+                rgbcolor_to_window_color(palette.background),
                 &mut quads,
             )?;
         }
@@ -1517,10 +3315,10 @@ impl TermWindow {
                 let info = ScrollHit::thumb(&*term, self.terminal_size, &self.dimensions);
                 let thumb_top = info.top as f32;
                 let thumb_size = info.height as f32;
-                let color = rgbcolor_to_window_color(palette.scrollbar_thumb);
+                let color = rgbcolor_to_window_color(self.scroll_thumb_color(&palette));
                 (thumb_top, thumb_size, color)
             } else {
-                let color = rgbcolor_to_window_color(background_color);
+                let color = default_bg_color(background_color, None, has_background_image);
                 (0., 0., color)
             };
 
@@ -1549,19 +3347,85 @@ impl TermWindow {
             quad.set_cursor_color(rgbcolor_to_window_color(background_color));
         }
 
-        {
-            let dirty_lines = term.get_dirty_lines();
+        drop(term);
 
-            for (line_idx, line, selrange) in dirty_lines {
+        let prior = std::mem::replace(&mut *self.painted.borrow_mut(), HashMap::new());
+        let mut painted = HashMap::new();
+        for (pos, pane) in &panes {
+            let is_active = pane.tab_id() == tab.tab_id();
+            let mut pane_palette = self.pane_palette(pane);
+            let tint = if is_active {
+                None
+            } else {
+                inactive_pane_tint(&configuration())
+            };
+            if let Some((color, amount)) = tint {
+                pane_palette.background = blend_color(pane_palette.background, color, amount);
+            }
+            let default_bg = default_bg_color(pane_palette.background, tint, has_background_image);
+            let mut term = pane.renderer();
+            let seqno = term.get_current_seqno();
+            let cursor = self.pane_cursor(pos, pane, &*term, is_active);
+
+            let dirty_lines =
+                pane_lines_to_paint(pos, &*term, seqno, is_active, &prior, &mut painted);
+            let quick_select = self.pane_quick_select(pane);
+            let hover = term.current_highlight();
+            let top = term.get_viewport_rows().start;
+            let show_prompt_markers = configuration().show_prompt_markers;
+
+            for (line_idx, mut line, selrange) in dirty_lines {
+                let is_prompt = show_prompt_markers && is_prompt_line(&line);
+                let row = top + line_idx as StableRowIndex;
+                let highlights =
+                    line_highlights(row, &line, selrange, hover.as_ref(), quick_select);
+                if let Some(qs) = quick_select {
+                    qs.decorate_line(row, &mut line);
+                }
+                let line_idx = line_idx + first_line_offset + pos.top;
                 self.render_screen_line_opengl(
-                    line_idx + first_line_offset,
+                    line_idx,
+                    pos.left,
+                    pos.cols,
                     &line,
-                    selrange,
+                    &highlights,
                     &cursor,
-                    &*term,
-                    &palette,
+                    &pane_palette,
+                    default_bg,
                     &mut quads,
                 )?;
+                if is_prompt {
+                    self.paint_prompt_marker_opengl(
+                        line_idx,
+                        pos.left,
+                        &cursor,
+                        &pane_palette,
+                        &mut quads,
+                    )?;
+                }
+            }
+        }
+        *self.painted.borrow_mut() = painted;
+
+        {
+            let white_space = gl_state.util_sprites.white_space.texture_coords();
+            let split_color = rgbcolor_to_window_color(palette.split);
+            for divider in &dividers {
+                for n in 0..divider.length {
+                    let (x, y) = match divider.direction {
+                        SplitDirection::Horizontal => (divider.left, divider.top + n),
+                        SplitDirection::Vertical => (divider.left + n, divider.top),
+                    };
+                    let mut quad = quads.cell(x, y + first_line_offset)?;
+                    quad.set_bg_color(split_color);
+                    quad.set_fg_color(split_color);
+                    quad.set_texture(white_space);
+                    quad.set_texture_adjust(0., 0., 0., 0.);
+                    quad.set_underline(white_space);
+                    quad.set_has_color(false);
+                    quad.set_cursor(white_space);
+                    quad.set_cursor_color(split_color);
+                }
             }
         }
 
@@ -1576,10 +3440,17 @@ impl TermWindow {
         )
         .to_column_arrays();
 
-        let draw_params = glium::DrawParameters {
+        let draw_params = if has_background_image {
+            // The cells that have the default background are
+            // translucent so that the image shows through
+            glium::DrawParameters {
+                blend: alpha_blend(),
+                ..Default::default()
+            }
+        } else {
             // No alpha blending for the background layer: let's make
             // sure that our background pixels are at 100% opacity.
-            ..Default::default()
+            Default::default()
         };
 
         drop(quads);
@@ -1593,33 +3464,31 @@ impl TermWindow {
                 projection: projection,
                 glyph_tex: &*tex,
                 bg_and_line_layer: true,
+                overlay_layer: false,
             },
             &draw_params,
         )?;
 
         let draw_params = glium::DrawParameters {
-            blend: glium::Blend {
-                color: BlendingFunction::Addition {
-                    source: LinearBlendingFactor::SourceAlpha,
-                    destination: LinearBlendingFactor::OneMinusSourceAlpha,
-                },
-                alpha: BlendingFunction::Addition {
-                    source: LinearBlendingFactor::SourceAlpha,
-                    // On Wayland, the compositor takes the destination alpha
-                    // value and blends with the window behind our own, which
-                    // can make the text look brighter or less sharp.
-                    // We set the destination alpha to 1.0 to prevent that
-                    // from happening.
-                    // (The normal alpha blending operation would set this to
-                    // OneMinusSourceAlpha).
-                    destination: LinearBlendingFactor::One,
-                },
-                constant_value: (0.0, 0.0, 0.0, 0.0),
-            },
+            blend: alpha_blend(),
             ..Default::default()
         };
 
-        // Pass 2: Draw glyphs
+        // Pass 2: Draw glyphs and images
+        frame.draw(
+            &*vb,
+            &gl_state.glyph_index_buffer,
+            &gl_state.program,
+            &uniform! {
+                projection: projection,
+                glyph_tex: &*tex,
+                bg_and_line_layer: false,
+                overlay_layer: false,
+            },
+            &draw_params,
+        )?;
+
+        // Pass 3: Draw the cursor and selection overlay
         frame.draw(
             &*vb,
             &gl_state.glyph_index_buffer,
@@ -1628,34 +3497,145 @@ impl TermWindow {
                 projection: projection,
                 glyph_tex: &*tex,
                 bg_and_line_layer: false,
+                overlay_layer: true,
             },
             &draw_params,
         )?;
 
-        term.clean_dirty_lines();
+        Ok(())
+    }
 
+    /// Draws the marker beside a line that holds a shell prompt.  The
+    /// marker is a thin bar over the left edge of the first cell of the
+    /// line, drawn in the cursor layer, so it is left out when the
+    /// cursor is in that cell.
+    fn paint_prompt_marker_opengl(
+        &self,
+        line_idx: usize,
+        left: usize,
+        cursor: &CursorPosition,
+        palette: &ColorPalette,
+        quads: &mut MappedQuads,
+    ) -> anyhow::Result<()> {
+        if cursor.y == line_idx as i64 && cursor.x == 0 {
+            return Ok(());
+        }
+        let gl_state = self.render_state.opengl();
+        let mut quad = quads.cell(left, line_idx)?;
+        quad.set_cursor(gl_state.util_sprites.cursor_i_beam.texture_coords());
+        quad.set_cursor_color(rgbcolor_to_window_color(prompt_marker_color(palette)));
         Ok(())
     }
 
+    /// Draws the `window_background_image`, if any, over the whole
+    /// window.  Returns true if the image was drawn.
+    fn paint_background_image(
+        &self,
+        tab: &Rc<dyn Tab>,
+        frame: &mut glium::Frame,
+    ) -> anyhow::Result<bool> {
+        let config = configuration();
+        let gl_state = self.render_state.opengl();
+        gl_state.update_background_image(config.window_background_image.as_deref());
+        let image = gl_state.background_image.borrow();
+        let texture = match image.as_ref().and_then(|image| image.texture()) {
+            Some(texture) => texture,
+            None => return Ok(false),
+        };
+
+        let width = self.dimensions.pixel_width as f32;
+        let height = self.dimensions.pixel_height as f32;
+
+        let scroll_offset = match config.window_background_image_attachment {
+            BackgroundImageAttachment::Window => 0.,
+            BackgroundImageAttachment::Scroll => {
+                let top = tab.renderer().get_viewport_rows().start;
+                top as f32 * self.render_metrics.cell_size.height as f32
+            }
+        };
+        let (tex_left, tex_top, tex_right, tex_bottom) = background::texture_coords(
+            config.window_background_image_mode,
+            (texture.width() as f32, texture.height() as f32),
+            (width, height),
+            scroll_offset,
+        );
+
+        let (left, top, right, bottom) = (-width / 2., -height / 2., width / 2., height / 2.);
+        let vertices = [
+            BackgroundVertex {
+                position: (left, top),
+                tex: (tex_left, tex_top),
+            },
+            BackgroundVertex {
+                position: (right, top),
+                tex: (tex_right, tex_top),
+            },
+            BackgroundVertex {
+                position: (left, bottom),
+                tex: (tex_left, tex_bottom),
+            },
+            BackgroundVertex {
+                position: (right, bottom),
+                tex: (tex_right, tex_bottom),
+            },
+        ];
+        let vb = glium::VertexBuffer::new(&gl_state.context, &vertices)?;
+
+        let projection = euclid::Transform3D::<f32, f32, f32>::ortho(
+            -width / 2.0,
+            width / 2.0,
+            height / 2.0,
+            -height / 2.0,
+            -1.0,
+            1.0,
+        )
+        .to_column_arrays();
+
+        let draw_params = glium::DrawParameters {
+            blend: alpha_blend(),
+            ..Default::default()
+        };
+
+        frame.draw(
+            &vb,
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+            &gl_state.background_program,
+            &uniform! {
+                projection: projection,
+                image_tex: texture
+                    .sampled()
+                    .wrap_function(glium::uniforms::SamplerWrapFunction::Repeat),
+                opacity: config.window_background_image_opacity.max(0.).min(1.),
+            },
+            &draw_params,
+        )?;
+
+        Ok(true)
+    }
+
     /// "Render" a line of the terminal screen into the vertex buffer.
     /// This is nominally a matter of setting the fg/bg color and the
     /// texture coordinates for a given glyph.  There's a little bit
     /// of extra complexity to deal with multi-cell glyphs.
+    ///
+    /// `line_idx` is relative to the top of the window, while `left`
+    /// is the column at which the pane starts and `num_cols` is its width.
+    /// `default_bg` is used for cells that have the default background.
+    #[allow(clippy::too_many_arguments)]
     fn render_screen_line_opengl(
         &self,
         line_idx: usize,
+        left: usize,
+        num_cols: usize,
         line: &Line,
-        selection: Range<usize>,
+        highlights: &LineHighlights,
         cursor: &CursorPosition,
-        terminal: &dyn Renderable,
         palette: &ColorPalette,
+        default_bg: Color,
         quads: &mut MappedQuads,
     ) -> anyhow::Result<()> {
         let gl_state = self.render_state.opengl();
 
-        let (_num_rows, num_cols) = terminal.physical_dimensions();
-
-        let current_highlight = terminal.current_highlight();
         let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
 
         // Break the line into clusters of cells with the same attributes
@@ -1664,10 +3644,10 @@ impl TermWindow {
         let config = configuration();
         for cluster in cell_clusters {
             let attrs = &cluster.attrs;
-            let is_highlited_hyperlink = match (&attrs.hyperlink, &current_highlight) {
-                (&Some(ref this), &Some(ref highlight)) => Arc::ptr_eq(this, highlight),
-                _ => false,
-            };
+            let is_highlited_hyperlink = cluster
+                .byte_to_cell_idx
+                .first()
+                .map_or(false, |&idx| highlights.contains(HighlightKind::Hover, idx));
             let style = self.fonts.match_style(&config, attrs);
 
             let bg_color = palette.resolve_bg(attrs.background);
@@ -1701,19 +3681,35 @@ impl TermWindow {
                     std::mem::swap(&mut fg, &mut bg);
                 }
 
+                // Blinking text is hidden while it is blinked off
+                if !self.text_blink_visible(attrs.blink()) {
+                    fg = bg;
+                }
+
                 (fg, bg)
             };
+            let bg_is_default =
+                attrs.background == term::color::ColorAttribute::Default && !attrs.reverse();
 
             let glyph_color = rgbcolor_to_window_color(fg_color);
             let bg_color = rgbcolor_to_window_color(bg_color);
 
+            // The underline uses the foreground color unless one was
+            // explicitly set.  The strikethrough shares the same sprite,
+            // so we stick with the foreground color when it is present.
+            let underline_color = match attrs.underline_color() {
+                term::color::ColorAttribute::Default => None,
+                _ if attrs.strikethrough() => None,
+                color => Some(rgbcolor_to_window_color(palette.resolve_fg(color))),
+            };
+
             // Shape the printable text from this cluster
             let glyph_info = {
                 let font = self.fonts.resolve_font(style)?;
                 font.shape(&cluster.text)?
             };
 
-            for info in &glyph_info {
+            for info in glyph_info.iter() {
                 let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
                 let glyph = gl_state
                     .glyph_cache
@@ -1750,17 +3746,23 @@ impl TermWindow {
                     }
                     last_cell_idx = cell_idx;
 
-                    let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
+                    let (glyph_color, cell_bg_color, cursor_shape) = self.compute_cell_fg_bg(
                         line_idx,
                         cell_idx,
                         cursor,
-                        &selection,
+                        highlights,
                         glyph_color,
                         bg_color,
                         palette,
                     );
+                    // Leave the cursor and selection colors alone
+                    let bg_color = if bg_is_default && cell_bg_color == bg_color {
+                        default_bg
+                    } else {
+                        cell_bg_color
+                    };
 
-                    if let Some(image) = attrs.image.as_ref() {
+                    if let Some(image) = attrs.image() {
                         // Render iTerm2 style image attributes
 
                         if let Ok(sprite) = gl_state
@@ -1788,7 +3790,34 @@ impl TermWindow {
 
                             let texture_rect = sprite.texture.to_texture_coords(coords);
 
-                            let mut quad = quads.cell(cell_idx, line_idx)?;
+                            // The image hides the background color, so a block
+                            // cursor or the selection is shown by tinting the
+                            // image with a translucent fill
+                            let is_block = match cursor_shape {
+                                CursorShape::BlinkingBlock | CursorShape::SteadyBlock => true,
+                                _ => false,
+                            };
+                            let (cursor_sprite, cursor_color) =
+                                if is_block && self.focused.is_some() {
+                                    (
+                                        &gl_state.util_sprites.fill,
+                                        translucent_window_color(palette.cursor_bg),
+                                    )
+                                } else if cursor_shape == CursorShape::Hidden
+                                    && highlights.contains(HighlightKind::Selection, cell_idx)
+                                {
+                                    (
+                                        &gl_state.util_sprites.fill,
+                                        translucent_window_color(palette.selection_bg),
+                                    )
+                                } else {
+                                    (
+                                        gl_state.util_sprites.cursor_sprite(cursor_shape),
+                                        cursor_border_color,
+                                    )
+                                };
+
+                            let mut quad = quads.cell(left + cell_idx, line_idx)?;
 
                             quad.set_fg_color(glyph_color);
                             quad.set_bg_color(bg_color);
@@ -1796,13 +3825,8 @@ impl TermWindow {
                             quad.set_texture_adjust(0., 0., 0., 0.);
                             quad.set_underline(gl_state.util_sprites.white_space.texture_coords());
                             quad.set_has_color(true);
-                            quad.set_cursor(
-                                gl_state
-                                    .util_sprites
-                                    .cursor_sprite(cursor_shape)
-                                    .texture_coords(),
-                            );
-                            quad.set_cursor_color(cursor_border_color);
+                            quad.set_cursor(cursor_sprite.texture_coords());
+                            quad.set_cursor_color(cursor_color);
 
                             continue;
                         }
@@ -1830,13 +3854,14 @@ impl TermWindow {
                     let right = pixel_rect.size.width as f32 + left
                         - self.render_metrics.cell_size.width as f32;
 
-                    let mut quad = quads.cell(cell_idx, line_idx)?;
+                    let mut quad = quads.cell(left + cell_idx, line_idx)?;
 
                     quad.set_fg_color(glyph_color);
                     quad.set_bg_color(bg_color);
                     quad.set_texture(texture_rect);
                     quad.set_texture_adjust(left, top, right, bottom);
                     quad.set_underline(underline_tex_rect);
+                    quad.set_underline_color(underline_color.unwrap_or(glyph_color));
                     quad.set_has_color(glyph.has_color);
                     quad.set_cursor(
                         gl_state
@@ -1862,17 +3887,23 @@ impl TermWindow {
             // Even though we don't have a cell for these, they still
             // hold the cursor or the selection so we need to compute
             // the colors in the usual way.
-            let (glyph_color, bg_color, cursor_shape) = self.compute_cell_fg_bg(
+            let bg_color = rgbcolor_to_window_color(palette.background);
+            let (glyph_color, cell_bg_color, cursor_shape) = self.compute_cell_fg_bg(
                 line_idx,
                 cell_idx,
                 cursor,
-                &selection,
+                highlights,
                 rgbcolor_to_window_color(palette.foreground),
-                rgbcolor_to_window_color(palette.background),
+                bg_color,
                 palette,
             );
+            let bg_color = if cell_bg_color == bg_color {
+                default_bg
+            } else {
+                cell_bg_color
+            };
 
-            let mut quad = quads.cell(cell_idx, line_idx)?;
+            let mut quad = quads.cell(left + cell_idx, line_idx)?;
 
             quad.set_bg_color(bg_color);
             quad.set_fg_color(glyph_color);
@@ -1892,24 +3923,23 @@ impl TermWindow {
         Ok(())
     }
 
+    /// Render a line of a pane into the window.
+    /// `line_idx` is relative to the top of the window, while `left`
+    /// is the column at which the pane starts and `num_cols` is its width.
     #[allow(clippy::too_many_arguments)]
     fn render_screen_line(
         &self,
         ctx: &mut dyn PaintContext,
         line_idx: usize,
+        left: usize,
+        num_cols: usize,
         line: &Line,
-        selection: Range<usize>,
+        highlights: &LineHighlights,
         cursor: &CursorPosition,
-        terminal: &dyn Renderable,
         palette: &ColorPalette,
     ) -> anyhow::Result<()> {
         let config = configuration();
 
-        let padding_left = config.window_padding.left as isize;
-        let padding_top = config.window_padding.top as isize;
-
-        let (_num_rows, num_cols) = terminal.physical_dimensions();
-        let current_highlight = terminal.current_highlight();
         let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
 
         // Break the line into clusters of cells with the same attributes
@@ -1917,10 +3947,10 @@ impl TermWindow {
         let mut last_cell_idx = 0;
         for cluster in cell_clusters {
             let attrs = &cluster.attrs;
-            let is_highlited_hyperlink = match (&attrs.hyperlink, &current_highlight) {
-                (&Some(ref this), &Some(ref highlight)) => this == highlight,
-                _ => false,
-            };
+            let is_highlited_hyperlink = cluster
+                .byte_to_cell_idx
+                .first()
+                .map_or(false, |&idx| highlights.contains(HighlightKind::Hover, idx));
             let style = self.fonts.match_style(&config, attrs);
 
             let bg_color = palette.resolve_bg(attrs.background);
@@ -1954,19 +3984,33 @@ impl TermWindow {
                     std::mem::swap(&mut fg, &mut bg);
                 }
 
+                // Blinking text is hidden while it is blinked off
+                if !self.text_blink_visible(attrs.blink()) {
+                    fg = bg;
+                }
+
                 (fg, bg)
             };
 
             let glyph_color = rgbcolor_to_window_color(fg_color);
             let bg_color = rgbcolor_to_window_color(bg_color);
 
+            // The underline uses the foreground color unless one was
+            // explicitly set.  The strikethrough shares the same sprite,
+            // so we stick with the foreground color when it is present.
+            let underline_color = match attrs.underline_color() {
+                term::color::ColorAttribute::Default => None,
+                _ if attrs.strikethrough() => None,
+                color => Some(rgbcolor_to_window_color(palette.resolve_fg(color))),
+            };
+
             // Shape the printable text from this cluster
             let glyph_info = {
                 let font = self.fonts.resolve_font(style)?;
                 font.shape(&cluster.text)?
             };
 
-            for info in &glyph_info {
+            for info in glyph_info.iter() {
                 let cell_idx = cluster.byte_to_cell_idx[info.cluster as usize];
                 let glyph = self.render_state.cached_software_glyph(info, style)?;
 
@@ -2003,19 +4047,14 @@ impl TermWindow {
                         line_idx,
                         cell_idx,
                         cursor,
-                        &selection,
+                        highlights,
                         glyph_color,
                         bg_color,
                         palette,
                     );
 
                     let cell_rect = Rect::new(
-                        Point::new(
-                            (cell_idx as isize * self.render_metrics.cell_size.width)
-                                + padding_left,
-                            (self.render_metrics.cell_size.height * line_idx as isize)
-                                + padding_top,
-                        ),
+                        self.cell_origin(&config, left + cell_idx, line_idx),
                         self.render_metrics.cell_size,
                     );
                     ctx.clear_rect(cell_rect, bg_color);
@@ -2031,7 +4070,7 @@ impl TermWindow {
                             cell_rect.origin,
                             Some(sprite.coords),
                             &*sprite.texture.image.borrow(),
-                            Operator::MultiplyThenOver(glyph_color),
+                            Operator::MultiplyThenOver(underline_color.unwrap_or(glyph_color)),
                         );
                     }
 
@@ -2061,7 +4100,7 @@ impl TermWindow {
                                 Operator::MultiplyThenOver(glyph_color)
                             },
                         );
-                    } else if let Some(image) = attrs.image.as_ref() {
+                    } else if let Some(image) = attrs.image() {
                         // Render iTerm2 style image attributes
                         let software = self.render_state.software();
                         if let Ok(sprite) = software
@@ -2125,7 +4164,7 @@ impl TermWindow {
                 line_idx,
                 cell_idx,
                 cursor,
-                &selection,
+                highlights,
                 rgbcolor_to_window_color(palette.foreground),
                 rgbcolor_to_window_color(palette.background),
                 palette,
@@ -2133,7 +4172,7 @@ impl TermWindow {
 
             let cell_rect = Rect::new(
                 Point::new(
-                    cell_idx as isize * self.render_metrics.cell_size.width,
+                    (left + cell_idx) as isize * self.render_metrics.cell_size.width,
                     self.render_metrics.cell_size.height * line_idx as isize,
                 ),
                 self.render_metrics.cell_size,
@@ -2152,26 +4191,30 @@ impl TermWindow {
             }
         }
 
-        // Fill any marginal area to the right of the last cell
-        let pixel_width_of_cells =
-            padding_left as usize + (num_cols * self.render_metrics.cell_size.width as usize);
+        Ok(())
+    }
+
+    /// Fill any marginal area to the right of the last cell of a line,
+    /// where `num_cols` is the number of cells to the left of that area
+    fn fill_right_margin(
+        &self,
+        ctx: &mut dyn PaintContext,
+        line_idx: usize,
+        num_cols: usize,
+        palette: &ColorPalette,
+    ) {
+        let config = configuration();
+        let origin = self.cell_origin(&config, num_cols, line_idx);
         ctx.clear_rect(
             Rect::new(
-                Point::new(
-                    pixel_width_of_cells as isize,
-                    self.render_metrics.cell_size.height * line_idx as isize,
-                ),
+                origin,
                 Size::new(
-                    self.dimensions
-                        .pixel_width
-                        .saturating_sub(pixel_width_of_cells) as isize,
+                    (self.dimensions.pixel_width as isize - origin.x).max(0),
                     self.render_metrics.cell_size.height,
                 ),
             ),
             rgbcolor_to_window_color(palette.background),
         );
-
-        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -2180,13 +4223,11 @@ impl TermWindow {
         line_idx: usize,
         cell_idx: usize,
         cursor: &CursorPosition,
-        selection: &Range<usize>,
+        highlights: &LineHighlights,
         fg_color: Color,
         bg_color: Color,
         palette: &ColorPalette,
     ) -> (Color, Color, CursorShape) {
-        let selected = selection.contains(&cell_idx);
-
         let is_cursor = line_idx as i64 == cursor.y && cursor.x == cell_idx;
 
         let cursor_shape = if is_cursor {
@@ -2197,42 +4238,42 @@ impl TermWindow {
             // depending on the current time.
             let config = configuration();
             let shape = config.default_cursor_style.effective_shape(cursor.shape);
-            // Work out the blinking shape if its a blinking cursor and it hasn't been disabled
-            // and the window is focused.
-            let blinking =
-                shape.is_blinking() && config.cursor_blink_rate != 0 && self.focused.is_some();
-            if blinking {
-                // Divide the time since we last moved by the blink rate.
-                // If the result is even then the cursor is "on", else it
-                // is "off"
-                let now = std::time::Instant::now();
-                let milli_uptime = now
-                    .duration_since(self.prev_cursor.last_cursor_movement())
-                    .as_millis();
-                let ticks = milli_uptime / config.cursor_blink_rate as u128;
-                if (ticks & 1) == 0 {
-                    shape
-                } else {
-                    CursorShape::Hidden
-                }
-            } else {
+            // The blink phase is measured from when the cursor last moved,
+            // so that it is shown while it is being moved around
+            let visible = !shape.is_blinking()
+                || self.blink_visible(
+                    config.cursor_blink_rate,
+                    self.prev_cursor.last_cursor_movement(),
+                );
+            if visible {
                 shape
+            } else {
+                CursorShape::Hidden
             }
         } else {
             CursorShape::Hidden
         };
 
-        let (fg_color, bg_color) = match (selected, self.focused.is_some(), cursor_shape) {
-            // Selected text overrides colors
-            (true, _, CursorShape::Hidden) => (
-                rgbcolor_to_window_color(palette.selection_fg),
-                rgbcolor_to_window_color(palette.selection_bg),
-            ),
+        let (fg_color, bg_color) = match (self.focused.is_some(), cursor_shape) {
             // Cursor cell overrides colors
-            (_, true, CursorShape::BlinkingBlock) | (_, true, CursorShape::SteadyBlock) => (
+            (true, CursorShape::BlinkingBlock) | (true, CursorShape::SteadyBlock) => (
                 rgbcolor_to_window_color(palette.cursor_fg),
                 rgbcolor_to_window_color(palette.cursor_bg),
             ),
+            // Composite the highlights over the colors of the cell, in order
+            (_, CursorShape::Hidden) => {
+                highlights
+                    .at(cell_idx)
+                    .fold((fg_color, bg_color), |(fg, bg), kind| match kind {
+                        HighlightKind::Selection => (
+                            rgbcolor_to_window_color(palette.selection_fg),
+                            rgbcolor_to_window_color(palette.selection_bg),
+                        ),
+                        HighlightKind::SearchMatch => (bg, fg),
+                        // The hovered hyperlink is underlined instead
+                        HighlightKind::Hover => (fg, bg),
+                    })
+            }
             // Normally, render the cell as configured (or if the window is unfocused)
             _ => (fg_color, bg_color),
         };
@@ -2241,10 +4282,193 @@ impl TermWindow {
     }
 }
 
+/// Pastes `clip` into the tab, first applying the paste filter and
+/// asking for confirmation of a multi-line paste, as configured
+fn paste_text(tab_id: TabId, clip: String) -> anyhow::Result<()> {
+    let config = configuration();
+    let clip = if config.paste_filter_control_characters {
+        filter_paste_control_characters(&clip)
+    } else {
+        clip
+    };
+    let confirm = config.confirm_multiline_paste && clip.contains(|c| c == '\n' || c == '\r');
+    let mux = Mux::get().unwrap();
+    if let Some(tab) = mux.get_tab(tab_id) {
+        if confirm && !tab.is_multiline_paste_safe() {
+            confirm_multiline_paste(tab_id, clip);
+        } else {
+            tab.trickle_paste(clip)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes control characters other than tab, carriage return and
+/// newline from text that is about to be pasted.  This prevents the
+/// clipboard from injecting escape sequences into the terminal.
+fn filter_paste_control_characters(text: &str) -> String {
+    text.chars()
+        .filter(|&c| !c.is_control() || c == '\t' || c == '\n' || c == '\r')
+        .collect()
+}
+
+/// Asks the user whether they really want to paste `text` into the
+/// tab, and pastes it if they agree.  The prompt is shown in its own
+/// window, so we wait for the answer on a separate thread.
+fn confirm_multiline_paste(tab_id: TabId, text: String) {
+    std::thread::spawn(move || {
+        let num_lines = text.lines().count();
+        let mut message = format!(
+            "You are about to paste {} line(s) into a program that\r\n\
+             may run each of them as a command:\r\n\r\n",
+            num_lines
+        );
+        for line in text.lines().take(3) {
+            message.push_str(&format!("  {}\r\n", line));
+        }
+        if num_lines > 3 {
+            message.push_str("  ...\r\n");
+        }
+        message.push_str("\r\nPaste anyway?\r\n");
+
+        let allow = termwiztermtab::run(80, 12, move |mut term| {
+            let title = "wezterm: confirm paste".to_string();
+            term.render(&[Change::Title(title), Change::Text(message.to_string())])?;
+
+            let mut editor = LineEditor::new(term);
+            editor.set_prompt("Enter [y/N]> ");
+
+            let mut host = NopLineEditorHost::default();
+            loop {
+                let line = match editor.read_line(&mut host) {
+                    Ok(Some(line)) => line,
+                    Ok(None) | Err(_) => return Ok(false),
+                };
+                match line.as_ref() {
+                    "y" | "Y" | "yes" | "YES" => return Ok(true),
+                    "" | "n" | "N" | "no" | "NO" => return Ok(false),
+                    _ => continue,
+                }
+            }
+        })
+        .wait()
+        .unwrap_or(false);
+
+        if allow {
+            promise::Future::with_executor(executor(), move || {
+                let mux = Mux::get().unwrap();
+                if let Some(tab) = mux.get_tab(tab_id) {
+                    tab.trickle_paste(text)?;
+                }
+                Ok(())
+            });
+        }
+    });
+}
+
+/// Returns the color and amount with which the background of the
+/// inactive panes is tinted, or None if they are not tinted
+fn inactive_pane_tint(config: &ConfigHandle) -> Option<(RgbColor, f32)> {
+    let amount = config.inactive_pane_tint_amount.max(0.).min(1.);
+    if amount == 0. {
+        return None;
+    }
+    let color = config
+        .resolved_palette()
+        .inactive_pane_tint
+        .unwrap_or_else(|| RgbColor::new(0, 0, 0));
+    Some((color, amount))
+}
+
+/// Returns the intensity of the visual bell that started at `start`,
+/// or None if it has finished
+fn visual_bell_intensity(config: &ConfigHandle, start: Instant) -> Option<f32> {
+    config.visual_bell_easing.flash_intensity(
+        start.elapsed(),
+        Duration::from_millis(config.visual_bell_fade_in_ms),
+        Duration::from_millis(config.visual_bell_fade_out_ms),
+    )
+}
+
+fn alpha_blend() -> glium::Blend {
+    glium::Blend {
+        color: BlendingFunction::Addition {
+            source: LinearBlendingFactor::SourceAlpha,
+            destination: LinearBlendingFactor::OneMinusSourceAlpha,
+        },
+        alpha: BlendingFunction::Addition {
+            source: LinearBlendingFactor::SourceAlpha,
+            // On Wayland, the compositor takes the destination alpha
+            // value and blends with the window behind our own, which
+            // can make the text look brighter or less sharp.
+            // We set the destination alpha to 1.0 to prevent that
+            // from happening.
+            // (The normal alpha blending operation would set this to
+            // OneMinusSourceAlpha).
+            destination: LinearBlendingFactor::One,
+        },
+        constant_value: (0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+/// Returns the color for the cells of a pane that have the default
+/// background.  When there is a background image, those cells are
+/// transparent so that the image shows through, other than the tint
+/// of an inactive pane.
+fn default_bg_color(
+    background: RgbColor,
+    tint: Option<(RgbColor, f32)>,
+    has_background_image: bool,
+) -> Color {
+    if !has_background_image {
+        // The tint has already been applied to the palette
+        return rgbcolor_to_window_color(background);
+    }
+    match tint {
+        Some((color, amount)) => Color::rgba(
+            color.red,
+            color.green,
+            color.blue,
+            (amount * 255.).round() as u8,
+        ),
+        None => Color::rgba(0, 0, 0, 0),
+    }
+}
+
+/// Mixes `amount` of `tint` into `color`
+fn blend_color(color: RgbColor, tint: RgbColor, amount: f32) -> RgbColor {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    RgbColor::new(
+        mix(color.red, tint.red),
+        mix(color.green, tint.green),
+        mix(color.blue, tint.blue),
+    )
+}
+
+/// Returns true if `line` holds part of a shell prompt, as marked
+/// up by the OSC 133 semantic zones
+fn is_prompt_line(line: &Line) -> bool {
+    line.cells()
+        .iter()
+        .any(|cell| cell.attrs().semantic_type() == SemanticType::Prompt)
+}
+
+/// The color of the marker beside prompt lines; a subtle mix of the
+/// foreground into the background
+fn prompt_marker_color(palette: &ColorPalette) -> RgbColor {
+    blend_color(palette.background, palette.foreground, 0.35)
+}
+
 fn rgbcolor_to_window_color(color: RgbColor) -> Color {
     Color::rgba(color.red, color.green, color.blue, 0xff)
 }
 
+/// Used to tint image cells, which would otherwise completely
+/// obscure the cursor and selection colors
+fn translucent_window_color(color: RgbColor) -> Color {
+    Color::rgba(color.red, color.green, color.blue, 0x80)
+}
+
 fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input::Modifiers {
     let mut result = termwiz::input::Modifiers::NONE;
     if modifiers.contains(::window::Modifiers::SHIFT) {
@@ -0,0 +1,207 @@
+//! An overlay from which a unicode character or emoji can be found by
+//! name and inserted into the active pane.  The names come from a table
+//! that is compiled in, so that no unicode database needs to be
+//! installed on the system.
+use crate::termwiztermtab::TermWizTerminal;
+use ::window::{Window, WindowOps};
+use lazy_static::lazy_static;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use termwiz::surface::{Change, CursorShape, Position};
+use termwiz::terminal::buffered::BufferedTerminal;
+use termwiz::terminal::Terminal;
+use termwiz::widgets::layout::{ChildOrientation, Constraints};
+use termwiz::widgets::list::{ListState, ListView};
+use termwiz::widgets::{CursorShapeAndPosition, RenderArgs, Ui, Widget, WidgetEvent};
+
+lazy_static! {
+    static ref NAMES: Vec<(char, &'static str)> = parse_names(include_str!("unicode_names.txt"));
+}
+
+fn parse_names(table: &'static str) -> Vec<(char, &'static str)> {
+    table
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.splitn(2, ';');
+            let code = u32::from_str_radix(fields.next()?, 16).ok()?;
+            Some((std::char::from_u32(code)?, fields.next()?))
+        })
+        .collect()
+}
+
+/// Returns the characters whose name contains each of the words of
+/// `query`, ignoring case.  A query of the form `U+1F600` finds the
+/// character with that code point.
+pub fn search(query: &str) -> Vec<(char, &'static str)> {
+    let query = query.to_uppercase();
+    let query = query.trim();
+    if query.starts_with("U+") {
+        let hex = &query[2..];
+        return NAMES
+            .iter()
+            .filter(|(c, _)| format!("{:04X}", *c as u32) == hex)
+            .cloned()
+            .collect();
+    }
+    let words: Vec<&str> = query.split_whitespace().collect();
+    NAMES
+        .iter()
+        .filter(|(_, name)| words.iter().all(|word| name.contains(word)))
+        .cloned()
+        .collect()
+}
+
+/// Returns the text that is shown for a character in the list
+fn label(c: char, name: &str) -> String {
+    format!("{}  U+{:04X}  {}", c, c as u32, name.to_lowercase())
+}
+
+/// The instructions and the search text, shown above the list
+struct SearchBox<'a> {
+    query: &'a str,
+}
+
+impl<'a> Widget for SearchBox<'a> {
+    fn render(&mut self, args: &mut RenderArgs) {
+        args.surface.add_changes(vec![
+            Change::ClearScreen(Default::default()),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(
+                "Type to search by name; Enter inserts the selected character, Esc cancels"
+                    .to_string(),
+            ),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(1),
+            },
+            Change::Text(format!("> {}", self.query)),
+        ]);
+        *args.cursor = CursorShapeAndPosition {
+            shape: CursorShape::Default,
+            coords: (2 + termwiz::cell::unicode_column_width(self.query), 1).into(),
+            ..Default::default()
+        };
+    }
+
+    fn get_size_constraints(&self) -> Constraints {
+        *Constraints::default().set_fixed_height(2)
+    }
+}
+
+/// Holds the search box and the list, one above the other
+struct Screen;
+
+impl Widget for Screen {
+    fn render(&mut self, _args: &mut RenderArgs) {}
+
+    fn get_size_constraints(&self) -> Constraints {
+        *Constraints::default().set_child_orientation(ChildOrientation::Vertical)
+    }
+}
+
+enum Outcome {
+    Cancel,
+    Insert,
+    /// The search text changed
+    Search,
+}
+
+/// Runs the overlay on `term` until the user chooses a character to
+/// insert or cancels.  `window` is invalidated each time the overlay
+/// is re-rendered, so that the changes are painted.
+pub fn run(term: TermWizTerminal, window: &Window) -> anyhow::Result<Option<char>> {
+    let mut buf = BufferedTerminal::new(term)?;
+    let mut query = String::new();
+    let mut matches = search(&query);
+    let mut state = ListState::new(matches.iter().map(|&(c, name)| label(c, name)).collect());
+
+    loop {
+        // The list view borrows the state, so the ui is rebuilt
+        // whenever the search changes the items in the list
+        let mut ui = Ui::new();
+        let root = ui.set_root(Screen);
+        ui.add_child(root, SearchBox { query: &query });
+        let list = ui.add_child(root, ListView::new(&mut state));
+        ui.set_focus(list);
+
+        let outcome = loop {
+            ui.process_event_queue()?;
+            if ui.render_to_screen(&mut buf)? {
+                continue;
+            }
+            buf.flush()?;
+            window.invalidate();
+
+            match buf.terminal().poll_input(None)? {
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Escape,
+                    ..
+                })) => break Outcome::Cancel,
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Enter,
+                    modifiers: Modifiers::NONE,
+                })) => break Outcome::Insert,
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char(c),
+                    modifiers,
+                })) if modifiers == Modifiers::NONE || modifiers == Modifiers::SHIFT => {
+                    query.push(c);
+                    break Outcome::Search;
+                }
+                Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Backspace,
+                    ..
+                })) => {
+                    query.pop();
+                    break Outcome::Search;
+                }
+                Some(InputEvent::Resized { cols, rows }) => {
+                    buf.add_change(Change::ClearScreen(Default::default()));
+                    buf.resize(cols, rows);
+                }
+                Some(input) => ui.queue_event(WidgetEvent::Input(input)),
+                None => {}
+            }
+        };
+        drop(ui);
+
+        match outcome {
+            Outcome::Cancel => return Ok(None),
+            Outcome::Insert => return Ok(state.selected().map(|idx| matches[idx].0)),
+            Outcome::Search => {
+                matches = search(&query);
+                state.set_items(matches.iter().map(|&(c, name)| label(c, name)).collect());
+                state.select_first();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert!(NAMES.contains(&('\u{1f600}', "GRINNING FACE")));
+        assert!(NAMES.iter().all(|(_, name)| !name.is_empty()));
+    }
+
+    #[test]
+    fn searching() {
+        let found = search("grinning face");
+        assert!(found.contains(&('\u{1f600}', "GRINNING FACE")));
+        assert!(found
+            .iter()
+            .all(|(_, name)| name.contains("GRINNING") && name.contains("FACE")));
+
+        // The words may appear in any order
+        assert_eq!(search("arrow rightwards")[0].1, "RIGHTWARDS ARROW");
+
+        assert_eq!(search("u+20ac"), vec![('\u{20ac}', "EURO SIGN")]);
+        assert!(search("no such character").is_empty());
+    }
+}
@@ -55,9 +55,16 @@ pub struct UtilSprites<T: Texture2d> {
     pub strike_through: Sprite<T>,
     pub single_and_strike: Sprite<T>,
     pub double_and_strike: Sprite<T>,
+    pub curly_underline: Sprite<T>,
+    pub dotted_underline: Sprite<T>,
+    pub dashed_underline: Sprite<T>,
+    pub curly_and_strike: Sprite<T>,
+    pub dotted_and_strike: Sprite<T>,
+    pub dashed_and_strike: Sprite<T>,
     pub cursor_box: Sprite<T>,
     pub cursor_i_beam: Sprite<T>,
     pub cursor_underline: Sprite<T>,
+    pub fill: Sprite<T>,
 }
 
 impl<T: Texture2d> UtilSprites<T> {
@@ -124,6 +131,65 @@ impl<T: Texture2d> UtilSprites<T> {
             }
         };
 
+        // A sine wave spanning the space used by the double underline.
+        // Its period is the width of the cell so that the wave is
+        // continuous across adjacent cells.
+        let draw_curly = |buffer: &mut Image| {
+            let amplitude = (metrics.descender_plus_two - metrics.descender_row) as f64 / 2.;
+            let center = metrics.descender_row as f64 + amplitude;
+            let y_at = |x: isize| {
+                let phase = 2. * std::f64::consts::PI * x as f64 / metrics.cell_size.width as f64;
+                (center - amplitude * phase.sin()).round() as isize
+            };
+            for row in 0..metrics.underline_height {
+                for x in 0..metrics.cell_size.width {
+                    buffer.draw_line(
+                        Point::new(cell_rect.origin.x + x, cell_rect.origin.y + y_at(x) + row),
+                        Point::new(
+                            cell_rect.origin.x + x + 1,
+                            cell_rect.origin.y + y_at(x + 1) + row,
+                        ),
+                        white,
+                        Operator::Over,
+                    );
+                }
+            }
+        };
+
+        // Square dots the size of the underline thickness
+        let draw_dotted = |buffer: &mut Image| {
+            let dot = metrics.underline_height;
+            let mut x = 0;
+            while x < metrics.cell_size.width {
+                buffer.clear_rect(
+                    Rect::new(
+                        Point::new(
+                            cell_rect.origin.x + x,
+                            cell_rect.origin.y + metrics.descender_row,
+                        ),
+                        Size::new(dot, metrics.underline_height),
+                    ),
+                    white,
+                );
+                x += 2 * dot;
+            }
+        };
+
+        // A dash in the middle half of the cell, so that adjacent
+        // cells are separated by a gap of the same length
+        let draw_dashed = |buffer: &mut Image| {
+            buffer.clear_rect(
+                Rect::new(
+                    Point::new(
+                        cell_rect.origin.x + metrics.cell_size.width / 4,
+                        cell_rect.origin.y + metrics.descender_row,
+                    ),
+                    Size::new(metrics.cell_size.width / 2, metrics.underline_height),
+                ),
+                white,
+            );
+        };
+
         let draw_strike = |buffer: &mut Image| {
             for row in 0..metrics.underline_height {
                 buffer.draw_line(
@@ -163,6 +229,33 @@ impl<T: Texture2d> UtilSprites<T> {
         draw_strike(&mut buffer);
         let double_and_strike = glyph_cache.atlas.allocate(&buffer)?;
 
+        buffer.clear_rect(cell_rect, black);
+        draw_curly(&mut buffer);
+        let curly_underline = glyph_cache.atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_dotted(&mut buffer);
+        let dotted_underline = glyph_cache.atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_dashed(&mut buffer);
+        let dashed_underline = glyph_cache.atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_curly(&mut buffer);
+        draw_strike(&mut buffer);
+        let curly_and_strike = glyph_cache.atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_dotted(&mut buffer);
+        draw_strike(&mut buffer);
+        let dotted_and_strike = glyph_cache.atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_dashed(&mut buffer);
+        draw_strike(&mut buffer);
+        let dashed_and_strike = glyph_cache.atlas.allocate(&buffer)?;
+
         // Derive a width for the border box from the underline height,
         // but aspect ratio adjusted for width.
         let border_width = (metrics.underline_height as f64 * metrics.cell_size.width as f64
@@ -255,6 +348,9 @@ impl<T: Texture2d> UtilSprites<T> {
         }
         let cursor_underline = glyph_cache.atlas.allocate(&buffer)?;
 
+        buffer.clear_rect(cell_rect, white);
+        let fill = glyph_cache.atlas.allocate(&buffer)?;
+
         Ok(Self {
             white_space,
             single_underline,
@@ -262,9 +358,16 @@ impl<T: Texture2d> UtilSprites<T> {
             strike_through,
             single_and_strike,
             double_and_strike,
+            curly_underline,
+            dotted_underline,
+            dashed_underline,
+            curly_and_strike,
+            dotted_and_strike,
+            dashed_and_strike,
             cursor_box,
             cursor_i_beam,
             cursor_underline,
+            fill,
         })
     }
 
@@ -290,6 +393,12 @@ impl<T: Texture2d> UtilSprites<T> {
             (false, true, Underline::None) => &self.strike_through,
             (false, true, Underline::Single) => &self.single_and_strike,
             (false, true, Underline::Double) => &self.double_and_strike,
+            (_, false, Underline::Curly) => &self.curly_underline,
+            (_, false, Underline::Dotted) => &self.dotted_underline,
+            (_, false, Underline::Dashed) => &self.dashed_underline,
+            (_, true, Underline::Curly) => &self.curly_and_strike,
+            (_, true, Underline::Dotted) => &self.dotted_and_strike,
+            (_, true, Underline::Dashed) => &self.dashed_and_strike,
         }
     }
 
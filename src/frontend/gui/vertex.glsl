@@ -5,16 +5,19 @@ in vec2 tex;
 in vec2 underline;
 in vec4 bg_color;
 in vec4 fg_color;
+in vec4 underline_color;
 in float has_color;
 in vec2 cursor;
 in vec4 cursor_color;
 
 uniform mat4 projection;
 uniform bool bg_and_line_layer;
+uniform bool overlay_layer;
 
 out vec2 o_tex;
 out vec4 o_fg_color;
 out vec4 o_bg_color;
+out vec4 o_underline_color;
 out float o_has_color;
 out vec2 o_underline;
 out vec2 o_cursor;
@@ -25,12 +28,14 @@ void main() {
     o_has_color = has_color;
     o_fg_color = fg_color;
     o_bg_color = bg_color;
+    o_underline_color = underline_color;
     o_underline = underline;
     o_cursor = cursor;
     o_cursor_color = cursor_color;
 
-    if (bg_and_line_layer) {
+    if (bg_and_line_layer || overlay_layer) {
       // Want to fill the whole cell when painting backgrounds
+      // and the cursor
       gl_Position = projection * vec4(position, 0.0, 1.0);
     } else {
       // Use only the adjusted cell position to render the glyph
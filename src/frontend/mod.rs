@@ -51,6 +51,54 @@ pub fn low_pri_executor() -> Box<dyn Executor> {
     }
 }
 
+/// Shows an error that was encountered while loading the configuration.
+/// When the GUI is running, the error is displayed in a window of its
+/// own so that it isn't lost in stderr; otherwise it is shown as a
+/// toast notification.  If the front end hasn't been started yet, the
+/// error is shown when it starts, unless the configuration has been
+/// successfully reloaded in the meantime.
+pub fn show_configuration_error_message(err: &str) {
+    if EXECUTOR.lock().unwrap().is_none() {
+        // The error remains recorded in the configuration, and is
+        // shown once the front end has started
+        return;
+    }
+
+    let err = err.to_string();
+    promise::Future::with_executor(executor(), move || {
+        let is_gui = front_end()
+            .map(|front_end| front_end.is::<gui::GuiFrontEnd>())
+            .unwrap_or(false);
+        if is_gui {
+            show_error_window("Wezterm Configuration Error", err);
+        } else {
+            crate::toast_notification("Wezterm Configuration", &err);
+        }
+        Ok(())
+    });
+}
+
+/// Opens a window that displays `message` until a key is pressed
+fn show_error_window(title: &str, message: String) {
+    use termwiz::input::InputEvent;
+    use termwiz::surface::Change;
+    use termwiz::terminal::Terminal;
+
+    let title = title.to_string();
+    crate::termwiztermtab::run(80, 24, move |mut term| {
+        term.render(&[
+            Change::Title(title.clone()),
+            Change::Text(message.replace("\n", "\r\n")),
+            Change::Text("\r\n\r\nPress any key to close this window".to_string()),
+        ])?;
+        loop {
+            if let Some(InputEvent::Key(_)) = term.poll_input(None)? {
+                return Ok(());
+            }
+        }
+    });
+}
+
 pub fn front_end() -> Option<Rc<dyn FrontEnd>> {
     let mut res = None;
     FRONT_END.with(|f| {
@@ -77,6 +125,10 @@ impl FrontEndSelection {
             .replace(front_end.low_pri_executor());
         FRONT_END.with(|f| *f.borrow_mut() = Some(Rc::clone(&front_end)));
 
+        if let Some(err) = crate::config::configuration_error() {
+            show_configuration_error_message(&err);
+        }
+
         Ok(front_end)
     }
 
@@ -1,7 +1,12 @@
-use crate::config::configuration;
+use crate::config::{configuration, LeaderKey};
 use crate::mux::domain::DomainId;
+use crate::mux::splits::{PaneDirection, SplitDirection};
+use anyhow::bail;
+use serde_derive::*;
 use std::collections::HashMap;
-use term::{KeyCode, KeyModifiers};
+use std::time::Duration;
+use term::export::ExportFormat;
+use term::{KeyCode, KeyModifiers, MouseSelectionMode};
 
 /// When spawning a tab, specify which domain should be used to
 /// host/spawn that tab.
@@ -17,6 +22,86 @@ pub enum SpawnTabDomain {
     DomainName(String),
 }
 
+/// Specifies which of the system clipboards text should be copied to
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardCopyDestination {
+    Clipboard,
+    PrimarySelection,
+    ClipboardAndPrimarySelection,
+}
+
+impl ClipboardCopyDestination {
+    /// Returns the list of clipboards that this destination refers to
+    pub fn clipboards(self) -> &'static [::window::Clipboard] {
+        use ::window::Clipboard;
+        match self {
+            Self::Clipboard => &[Clipboard::Clipboard],
+            Self::PrimarySelection => &[Clipboard::PrimarySelection],
+            Self::ClipboardAndPrimarySelection => {
+                &[Clipboard::Clipboard, Clipboard::PrimarySelection]
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ClipboardCopyDestination {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Clipboard" => Ok(Self::Clipboard),
+            "PrimarySelection" => Ok(Self::PrimarySelection),
+            "ClipboardAndPrimarySelection" => Ok(Self::ClipboardAndPrimarySelection),
+            _ => bail!("invalid ClipboardCopyDestination {}", s),
+        }
+    }
+}
+
+/// Specifies which of the system clipboards text should be pasted from
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardPasteSource {
+    Clipboard,
+    PrimarySelection,
+}
+
+impl Into<::window::Clipboard> for ClipboardPasteSource {
+    fn into(self) -> ::window::Clipboard {
+        match self {
+            Self::Clipboard => ::window::Clipboard::Clipboard,
+            Self::PrimarySelection => ::window::Clipboard::PrimarySelection,
+        }
+    }
+}
+
+impl std::str::FromStr for ClipboardPasteSource {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Clipboard" => Ok(Self::Clipboard),
+            "PrimarySelection" => Ok(Self::PrimarySelection),
+            _ => bail!("invalid ClipboardPasteSource {}", s),
+        }
+    }
+}
+
+/// The kind of mouse event that a mouse binding responds to
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseEventTrigger {
+    /// The button was pressed
+    Down,
+    /// The button was released
+    Up,
+    /// The mouse was moved while the button was held down
+    Drag,
+}
+
+/// The mouse buttons that may be used in a mouse binding
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseBindingButton {
+    Left,
+    Middle,
+    Right,
+}
+
 #[derive(Debug, Clone)]
 pub enum KeyAssignment {
     SpawnTab(SpawnTabDomain),
@@ -24,12 +109,17 @@ pub enum KeyAssignment {
     ToggleFullScreen,
     Copy,
     Paste,
+    CopyTo(ClipboardCopyDestination),
+    PasteFrom(ClipboardPasteSource),
     ActivateTabRelative(isize),
     IncreaseFontSize,
     DecreaseFontSize,
     ResetFontSize,
     ActivateTab(usize),
     SendString(String),
+    /// Sends the encoding of the key, with the modifiers held, that
+    /// the terminal expects in its current keyboard modes
+    SendKey(KeyCode, KeyModifiers),
     Nop,
     Hide,
     Show,
@@ -37,15 +127,54 @@ pub enum KeyAssignment {
     ReloadConfiguration,
     MoveTabRelative(isize),
     MoveTab(usize),
+    MoveTabToNewWindow,
+    MergeAllWindows,
+    SetTabTitle(String),
+    SplitPane(SplitDirection),
+    ActivatePaneDirection(PaneDirection),
+    AdjustPaneSize(PaneDirection, usize),
+    TogglePaneZoomState,
+    CloseCurrentPane,
+    ClearScrollback,
+    ExportScrollback(ExportFormat),
+    QuickSelect,
+    ShowLauncher,
+    ShowClipboardHistory,
+    ShowUnicodeInput,
+    ScrollByPage(isize),
+    ScrollByLine(isize),
+    ScrollToPrompt(isize),
+    ScrollToPreviousPrompt,
+    ScrollToNextPrompt,
+    ScrollToTop,
+    ScrollToBottom,
+    EmitEvent(String),
+    SetColorScheme(String),
+    /// Applies the named profile to the current pane; an empty name
+    /// reverts to the appearance from the configuration
+    SetProfile(String),
+    SelectTextAtMouseCursor(MouseSelectionMode),
+    ExtendSelectionToMouseCursor,
+    CompleteSelection,
+    CompleteSelectionOrOpenLinkAtMouseCursor,
+    OpenLinkAtMouseCursor,
 }
 
-pub struct KeyMap(HashMap<(KeyCode, KeyModifiers), KeyAssignment>);
+pub struct KeyMap {
+    keys: HashMap<(KeyCode, KeyModifiers), KeyAssignment>,
+    leader: Option<LeaderKey>,
+    leader_keys: HashMap<(KeyCode, KeyModifiers), KeyAssignment>,
+}
 
 impl KeyMap {
     pub fn new() -> Self {
-        let mut map = configuration()
+        let config = configuration();
+        let mut map = config
             .key_bindings()
             .expect("keys section of config to be valid");
+        let leader_keys = config
+            .leader_key_bindings()
+            .expect("leader_keys section of config to be valid");
 
         macro_rules! m {
             ($([$mod:expr, $code:expr, $action:expr]),* $(,)?) => {
@@ -58,6 +187,7 @@ impl KeyMap {
         use KeyAssignment::*;
 
         let ctrl_shift = KeyModifiers::CTRL | KeyModifiers::SHIFT;
+        let ctrl_shift_alt = ctrl_shift | KeyModifiers::ALT;
 
         // Apply the default bindings; if the user has already mapped
         // a given entry then that will take precedence.
@@ -141,16 +271,234 @@ impl KeyMap {
             ],
             [KeyModifiers::SUPER, KeyCode::Char('r'), ReloadConfiguration],
             [ctrl_shift, KeyCode::Char('R'), ReloadConfiguration],
+            [KeyModifiers::SUPER, KeyCode::Char('k'), ClearScrollback],
+            [ctrl_shift, KeyCode::Char('K'), ClearScrollback],
+            [ctrl_shift, KeyCode::Char(' '), QuickSelect],
+            [ctrl_shift, KeyCode::Char('U'), ShowUnicodeInput],
             [ctrl_shift, KeyCode::PageUp, MoveTabRelative(-1)],
             [ctrl_shift, KeyCode::PageDown, MoveTabRelative(1)],
+            // Pane management
+            [
+                ctrl_shift_alt,
+                KeyCode::Char('"'),
+                SplitPane(SplitDirection::Vertical)
+            ],
+            [
+                ctrl_shift_alt,
+                KeyCode::Char('%'),
+                SplitPane(SplitDirection::Horizontal)
+            ],
+            [
+                ctrl_shift,
+                KeyCode::LeftArrow,
+                ActivatePaneDirection(PaneDirection::Left)
+            ],
+            [
+                ctrl_shift,
+                KeyCode::RightArrow,
+                ActivatePaneDirection(PaneDirection::Right)
+            ],
+            [
+                ctrl_shift,
+                KeyCode::UpArrow,
+                ActivatePaneDirection(PaneDirection::Up)
+            ],
+            [
+                ctrl_shift,
+                KeyCode::DownArrow,
+                ActivatePaneDirection(PaneDirection::Down)
+            ],
+            [ctrl_shift, KeyCode::Char('Z'), TogglePaneZoomState],
+            [
+                ctrl_shift_alt,
+                KeyCode::LeftArrow,
+                AdjustPaneSize(PaneDirection::Left, 1)
+            ],
+            [
+                ctrl_shift_alt,
+                KeyCode::RightArrow,
+                AdjustPaneSize(PaneDirection::Right, 1)
+            ],
+            [
+                ctrl_shift_alt,
+                KeyCode::UpArrow,
+                AdjustPaneSize(PaneDirection::Up, 1)
+            ],
+            [
+                ctrl_shift_alt,
+                KeyCode::DownArrow,
+                AdjustPaneSize(PaneDirection::Down, 1)
+            ],
         );
 
-        Self(map)
+        Self {
+            keys: map,
+            leader: config.leader.clone(),
+            leader_keys,
+        }
     }
 
     pub fn lookup(&self, key: KeyCode, mods: KeyModifiers) -> Option<KeyAssignment> {
-        self.0
+        self.keys
             .get(&(key.normalize_shift_to_upper_case(mods), mods))
             .cloned()
     }
+
+    /// Returns how long to wait for the key that follows the leader,
+    /// if `key` is the leader key
+    pub fn leader_timeout(&self, key: KeyCode, mods: KeyModifiers) -> Option<Duration> {
+        let leader = self.leader.as_ref()?;
+        if leader.key.normalize_shift_to_upper_case(leader.mods)
+            == key.normalize_shift_to_upper_case(mods)
+            && leader.mods == mods
+        {
+            Some(Duration::from_millis(leader.timeout_milliseconds))
+        } else {
+            None
+        }
+    }
+
+    /// Looks up the key that was pressed after the leader key
+    pub fn lookup_leader(&self, key: KeyCode, mods: KeyModifiers) -> Option<KeyAssignment> {
+        self.leader_keys
+            .get(&(key.normalize_shift_to_upper_case(mods), mods))
+            .cloned()
+    }
+}
+
+pub struct MouseMap(
+    HashMap<(MouseEventTrigger, MouseBindingButton, usize, KeyModifiers), KeyAssignment>,
+);
+
+impl MouseMap {
+    pub fn new() -> Self {
+        let config = configuration();
+        let mut map = config
+            .mouse_bindings()
+            .expect("mouse_bindings section of config to be valid");
+
+        macro_rules! m {
+            ($([$trigger:expr, $button:expr, $streak:expr, $mod:expr, $action:expr]),* $(,)?) => {
+                $(
+                map.entry(($trigger, $button, $streak, $mod)).or_insert($action);
+                )*
+            };
+        };
+
+        use KeyAssignment::*;
+        use MouseBindingButton::*;
+        use MouseEventTrigger::*;
+
+        let link_mods = config.hyperlink_click_modifiers;
+        let paste = PasteFrom(config.middle_click_paste_source);
+
+        // Apply the default bindings; if the user has already mapped
+        // a given entry then that will take precedence.
+        m!(
+            [
+                Down,
+                Left,
+                1,
+                KeyModifiers::NONE,
+                SelectTextAtMouseCursor(MouseSelectionMode::Cell)
+            ],
+            [
+                Down,
+                Left,
+                1,
+                link_mods,
+                SelectTextAtMouseCursor(MouseSelectionMode::Cell)
+            ],
+            [
+                Down,
+                Left,
+                1,
+                KeyModifiers::ALT,
+                SelectTextAtMouseCursor(MouseSelectionMode::Block)
+            ],
+            [
+                Down,
+                Left,
+                1,
+                KeyModifiers::SHIFT,
+                ExtendSelectionToMouseCursor
+            ],
+            [
+                Down,
+                Left,
+                2,
+                KeyModifiers::NONE,
+                SelectTextAtMouseCursor(MouseSelectionMode::Word)
+            ],
+            [
+                Down,
+                Left,
+                3,
+                KeyModifiers::NONE,
+                SelectTextAtMouseCursor(MouseSelectionMode::Line)
+            ],
+            [
+                Down,
+                Left,
+                3,
+                KeyModifiers::ALT,
+                SelectTextAtMouseCursor(MouseSelectionMode::SemanticZone)
+            ],
+            [
+                Down,
+                Left,
+                4,
+                KeyModifiers::NONE,
+                SelectTextAtMouseCursor(MouseSelectionMode::Paragraph)
+            ],
+            // This comes first so that links can be opened with a plain
+            // click when `hyperlink_click_modifiers` is NONE
+            [
+                Up,
+                Left,
+                1,
+                link_mods,
+                CompleteSelectionOrOpenLinkAtMouseCursor
+            ],
+            [Up, Left, 1, KeyModifiers::NONE, CompleteSelection],
+            [Up, Left, 1, KeyModifiers::SHIFT, CompleteSelection],
+            [Up, Left, 1, KeyModifiers::ALT, CompleteSelection],
+            [
+                Drag,
+                Left,
+                1,
+                KeyModifiers::NONE,
+                ExtendSelectionToMouseCursor
+            ],
+            [
+                Drag,
+                Left,
+                1,
+                KeyModifiers::SHIFT,
+                ExtendSelectionToMouseCursor
+            ],
+            [
+                Drag,
+                Left,
+                1,
+                KeyModifiers::ALT,
+                ExtendSelectionToMouseCursor
+            ],
+            [Drag, Left, 1, link_mods, ExtendSelectionToMouseCursor],
+            [Down, Middle, 1, KeyModifiers::NONE, paste.clone()],
+            [Down, Middle, 1, KeyModifiers::SHIFT, paste],
+        );
+
+        Self(map)
+    }
+
+    pub fn lookup(
+        &self,
+        trigger: MouseEventTrigger,
+        button: MouseBindingButton,
+        streak: usize,
+        mods: KeyModifiers,
+    ) -> Option<KeyAssignment> {
+        self.0.get(&(trigger, button, streak, mods)).cloned()
+    }
 }
@@ -1,20 +1,36 @@
+use crate::config::{configuration, ExitBehavior, TermConfig};
 use crate::mux::domain::DomainId;
 use crate::mux::renderable::Renderable;
 use crate::mux::tab::{alloc_tab_id, Tab, TabId};
+use crate::tmux::TmuxControlHandler;
 use anyhow::Error;
-use portable_pty::{Child, MasterPty, PtySize};
-use std::cell::{RefCell, RefMut};
+use filedescriptor::FileDescriptor;
+use portable_pty::procinfo::{current_working_dir, ProcessInfo};
+use portable_pty::{Child, ExitStatus, MasterPty, PtySize};
+use promise::Future;
+use std::cell::{Cell, RefCell, RefMut};
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::sync::Arc;
 use term::color::ColorPalette;
+use term::export::ExportFormat;
 use term::selection::SelectionRange;
 use term::{Clipboard, KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalHost};
 
 pub struct LocalTab {
     tab_id: TabId,
     terminal: RefCell<Terminal>,
+    /// The configuration of the terminal, which holds its profile
+    config: Arc<TermConfig>,
     process: RefCell<Box<dyn Child>>,
     pty: RefCell<Box<dyn MasterPty>>,
     domain_id: DomainId,
+    argv: Vec<OsString>,
+    user_title: RefCell<Option<String>>,
+    exit_behavior: Cell<ExitBehavior>,
+    /// Set when the user closes a tab that was held open after
+    /// its process exited
+    dismissed: Cell<bool>,
 }
 
 impl Tab for LocalTab {
@@ -28,14 +44,46 @@ impl Tab for LocalTab {
     }
 
     fn is_dead(&self) -> bool {
-        if let Ok(None) = self.process.borrow_mut().try_wait() {
-            false
-        } else {
-            log::error!("is_dead: {:?}", self.tab_id);
-            true
+        if self.dismissed.get() {
+            return true;
+        }
+        match self.process.borrow_mut().try_wait() {
+            Ok(None) => false,
+            Ok(Some(status)) if hold_on_exit(self.exit_behavior.get(), status) => false,
+            _ => {
+                log::error!("is_dead: {:?}", self.tab_id);
+                true
+            }
+        }
+    }
+
+    fn exit_status_pending(&self) -> bool {
+        match self.process.borrow_mut().try_wait() {
+            Ok(None) => true,
+            _ => false,
         }
     }
 
+    fn hold_after_exit(&self, host: &mut dyn TerminalHost) -> bool {
+        let status = match self.exit_status() {
+            Some(status) => status,
+            None => return false,
+        };
+        if !hold_on_exit(self.exit_behavior.get(), status) {
+            return false;
+        }
+        // Show the status as a banner in reverse video, and hide the
+        // cursor as there is nothing left to receive input
+        let message = format!(
+            "\x1b[0m\r\n\x1b[7m Process exited with code {}; press Enter to close this tab \x1b[0m\x1b[?25l",
+            status.exit_code()
+        );
+        self.terminal
+            .borrow_mut()
+            .advance_bytes(message.as_bytes(), host);
+        true
+    }
+
     fn set_clipboard(&self, clipboard: &Arc<dyn Clipboard>) {
         self.terminal.borrow_mut().set_clipboard(clipboard);
     }
@@ -48,7 +96,18 @@ impl Tab for LocalTab {
         self.terminal.borrow_mut().mouse_event(event, host)
     }
 
+    fn set_exit_behavior(&self, behavior: ExitBehavior) {
+        self.exit_behavior.set(behavior);
+    }
+
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if key == KeyCode::Enter {
+            if let Ok(Some(_)) = self.process.borrow_mut().try_wait() {
+                // The tab is being held open after its process exited
+                self.dismissed.set(true);
+                return Ok(());
+            }
+        }
         self.terminal
             .borrow_mut()
             .key_down(key, mods, &mut *self.pty.borrow_mut())
@@ -73,14 +132,46 @@ impl Tab for LocalTab {
         self.pty.borrow_mut().try_clone_reader()
     }
 
+    fn pollable_reader(&self) -> Result<Option<FileDescriptor>, Error> {
+        self.pty.borrow().try_clone_pollable_reader()
+    }
+
     fn send_paste(&self, text: &str) -> Result<(), Error> {
         self.terminal
             .borrow_mut()
             .send_paste(text, &mut *self.pty.borrow_mut())
     }
 
+    fn is_multiline_paste_safe(&self) -> bool {
+        let terminal = self.terminal.borrow();
+        terminal.bracketed_paste_enabled() || terminal.is_alt_screen_active()
+    }
+
     fn get_title(&self) -> String {
-        self.terminal.borrow_mut().get_title().to_string()
+        match self.user_title.borrow().as_ref() {
+            Some(title) => title.clone(),
+            None => self.terminal.borrow_mut().get_title().to_string(),
+        }
+    }
+
+    fn set_user_title(&self, title: Option<String>) {
+        *self.user_title.borrow_mut() = title;
+    }
+
+    fn profile(&self) -> Option<String> {
+        self.config.profile()
+    }
+
+    fn set_profile(&self, profile: Option<String>) {
+        self.config.set_profile(profile);
+    }
+
+    fn erase_scrollback(&self) {
+        self.terminal.borrow_mut().erase_scrollback_and_viewport();
+    }
+
+    fn export_scrollback(&self, format: ExportFormat) -> Future<String> {
+        Future::ok(self.terminal.borrow().export_scrollback(format))
     }
 
     fn palette(&self) -> ColorPalette {
@@ -91,6 +182,46 @@ impl Tab for LocalTab {
         self.domain_id
     }
 
+    fn get_current_working_dir(&self) -> Option<PathBuf> {
+        // Prefer the directory reported by the shell via OSC 7,
+        // but only if it names a directory on this host
+        if let Some(url) = self.terminal.borrow().get_current_dir() {
+            let hostname = local_hostname();
+            if let Some(path) = path_from_file_url(url, hostname.as_ref().map(String::as_str)) {
+                if path.is_dir() {
+                    return Some(path);
+                }
+            }
+        }
+
+        // Otherwise ask the system about the foreground process,
+        // falling back to the process that we spawned
+        let pid = match self.pty.borrow().process_group_leader() {
+            Some(pid) => pid,
+            None => self.process.borrow().process_id()?,
+        };
+        current_working_dir(pid)
+    }
+
+    fn get_foreground_process(&self) -> Option<ProcessInfo> {
+        if let Some(pid) = self.pty.borrow().process_group_leader() {
+            return ProcessInfo::with_pid(pid);
+        }
+        // Without job control, assume that the most recently spawned
+        // descendant of the process that we spawned is in the foreground
+        let pid = self.process.borrow().process_id()?;
+        let tree = ProcessInfo::with_root_pid(pid)?;
+        Some(tree.newest_leaf().clone())
+    }
+
+    fn get_spawn_argv(&self) -> Option<Vec<OsString>> {
+        if self.argv.is_empty() {
+            None
+        } else {
+            Some(self.argv.clone())
+        }
+    }
+
     fn selection_range(&self) -> Option<SelectionRange> {
         let terminal = self.terminal.borrow();
         let rows = terminal.screen().physical_rows;
@@ -107,20 +238,48 @@ impl Tab for LocalTab {
 
 impl LocalTab {
     pub fn new(
-        terminal: Terminal,
+        mut terminal: Terminal,
+        config: Arc<TermConfig>,
         process: Box<dyn Child>,
         pty: Box<dyn MasterPty>,
         domain_id: DomainId,
+        argv: Vec<OsString>,
     ) -> Self {
         let tab_id = alloc_tab_id();
+        // Allow tmux to enter control mode in this tab
+        terminal.set_device_control_handler(Box::new(TmuxControlHandler::new(tab_id)));
         Self {
             tab_id,
             terminal: RefCell::new(terminal),
+            config,
             process: RefCell::new(process),
             pty: RefCell::new(pty),
             domain_id,
+            argv,
+            user_title: RefCell::new(None),
+            exit_behavior: Cell::new(configuration().exit_behavior),
+            dismissed: Cell::new(false),
         }
     }
+
+    /// Returns the exit status of the process, if it has exited
+    fn exit_status(&self) -> Option<ExitStatus> {
+        self.process
+            .borrow_mut()
+            .try_wait()
+            .ok()
+            .and_then(|status| status)
+    }
+}
+
+/// Returns true if a tab whose process exited with `status` should
+/// remain open, according to its `exit_behavior`
+fn hold_on_exit(behavior: ExitBehavior, status: ExitStatus) -> bool {
+    match behavior {
+        ExitBehavior::Close => false,
+        ExitBehavior::CloseOnCleanExit => !status.success(),
+        ExitBehavior::Hold => true,
+    }
 }
 
 impl Drop for LocalTab {
@@ -130,3 +289,94 @@ impl Drop for LocalTab {
         self.process.borrow_mut().wait().ok();
     }
 }
+
+#[cfg(unix)]
+fn local_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if res != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+#[cfg(windows)]
+fn local_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Extracts the local path from a `file://host/path` url, such as
+/// those reported by the shell via OSC 7.  Returns None if the url
+/// names a host other than `local_host`, such as when the shell is
+/// running on the far side of an ssh session.
+fn path_from_file_url(url: &str, local_host: Option<&str>) -> Option<PathBuf> {
+    const SCHEME: &str = "file://";
+    if !url.starts_with(SCHEME) {
+        return None;
+    }
+    let rest = &url[SCHEME.len()..];
+    let slash = rest.find('/')?;
+    let host = &rest[..slash];
+    let is_local = host.is_empty()
+        || host.eq_ignore_ascii_case("localhost")
+        || local_host.map_or(false, |local| host.eq_ignore_ascii_case(local));
+    if !is_local {
+        return None;
+    }
+    let path = &rest[slash..];
+
+    let mut bytes = vec![];
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+
+    // file:///C:/Users/wez -> C:/Users/wez
+    #[cfg(windows)]
+    let path = match path.get(2..3) {
+        Some(":") => path[1..].to_string(),
+        _ => path,
+    };
+
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_url() {
+        assert_eq!(
+            path_from_file_url("file://localhost/home/wez/some%20dir", None),
+            Some(PathBuf::from("/home/wez/some dir"))
+        );
+        assert_eq!(
+            path_from_file_url("file:///tmp", None),
+            Some(PathBuf::from("/tmp"))
+        );
+        assert_eq!(path_from_file_url("http://example.com/", None), None);
+        assert_eq!(path_from_file_url("file://localhost/bad%2", None), None);
+    }
+
+    #[test]
+    fn file_url_host() {
+        assert_eq!(
+            path_from_file_url("file://MyHost/tmp", Some("myhost")),
+            Some(PathBuf::from("/tmp"))
+        );
+        assert_eq!(
+            path_from_file_url("file://remotehost/tmp", Some("myhost")),
+            None
+        );
+        assert_eq!(path_from_file_url("file://remotehost/tmp", None), None);
+    }
+}
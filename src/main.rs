@@ -21,10 +21,14 @@ mod frontend;
 mod keyassignment;
 mod localtab;
 mod mux;
+mod open;
+mod rangeset;
 mod ratelim;
+mod recording;
 mod server;
 mod ssh;
 mod termwiztermtab;
+mod tmux;
 
 use crate::frontend::{executor, front_end, FrontEndSelection};
 use crate::mux::domain::{Domain, LocalDomain};
@@ -118,6 +122,12 @@ struct StartCommand {
     #[structopt(long = "daemonize")]
     daemonize: bool,
 
+    /// Restore the windows and tabs that were open the last time
+    /// that wezterm was running, rather than spawning a new tab.
+    /// This requires the `save_session` option to be enabled.
+    #[structopt(long = "resurrect")]
+    resurrect: bool,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm start -- bash -l` will spawn bash
     /// as if it were a login shell.
@@ -144,6 +154,21 @@ enum SubCommand {
 
     #[structopt(name = "imgcat", about = "Output an image to the terminal")]
     ImageCat(ImgCatCommand),
+
+    #[structopt(
+        name = "record",
+        about = "Record a session in the asciicast format used by asciinema"
+    )]
+    Record(recording::RecordCommand),
+
+    #[structopt(name = "replay", about = "Play back a recorded session")]
+    Replay(recording::ReplayCommand),
+
+    #[structopt(
+        name = "terminfo",
+        about = "Print the source of the wezterm terminfo entry, to be compiled with `tic -x -`"
+    )]
+    Terminfo,
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -161,6 +186,53 @@ enum CliSubCommand {
     #[structopt(name = "list", about = "list windows and tabs")]
     List,
 
+    #[structopt(name = "spawn", about = "spawn a new tab and print its tab id")]
+    Spawn {
+        /// Add the tab to this window, rather than creating a new window
+        #[structopt(long = "window-id")]
+        window_id: Option<mux::window::WindowId>,
+
+        /// The working directory for the spawned program
+        #[structopt(long = "cwd", parse(from_os_str))]
+        cwd: Option<OsString>,
+
+        /// Instead of executing your shell, run PROG.
+        /// For example: `wezterm cli spawn -- bash -l` will spawn bash
+        /// as if it were a login shell.
+        #[structopt(parse(from_os_str))]
+        prog: Vec<OsString>,
+    },
+
+    #[structopt(
+        name = "send-text",
+        about = "send text to a tab as though it were typed"
+    )]
+    SendText {
+        /// The tab to receive the text
+        #[structopt(long = "tab-id")]
+        tab_id: mux::tab::TabId,
+
+        /// The text to send.  If omitted, the text is read from stdin
+        text: Option<String>,
+    },
+
+    #[structopt(name = "activate-tab", about = "activate a tab in its window")]
+    ActivateTab {
+        /// The tab to activate
+        #[structopt(long = "tab-id")]
+        tab_id: mux::tab::TabId,
+    },
+
+    #[structopt(
+        name = "kill-pane",
+        about = "kill the program in a tab or pane and close it"
+    )]
+    KillPane {
+        /// The tab or pane to kill
+        #[structopt(long = "tab-id")]
+        tab_id: mux::tab::TabId,
+    },
+
     #[structopt(name = "proxy", about = "start rpc proxy pipe")]
     Proxy,
 }
@@ -206,6 +278,16 @@ struct SerialCommand {
     #[structopt(long = "baud")]
     baud: Option<usize>,
 
+    /// Set the parity; one of `none`, `odd` or `even`.
+    /// The default is no parity.
+    #[structopt(long = "parity", parse(try_from_str = "parse_parity"))]
+    parity: Option<serial::Parity>,
+
+    /// Set the flow control; one of `none`, `software` or `hardware`.
+    /// The default is software flow control.
+    #[structopt(long = "flow-control", parse(try_from_str = "parse_flow_control"))]
+    flow_control: Option<serial::FlowControl>,
+
     /// Specifies the serial device name.
     /// On Windows systems this can be a name like `COM0`.
     /// On posix systems this will be something like `/dev/ttyUSB0`
@@ -213,6 +295,27 @@ struct SerialCommand {
     port: OsString,
 }
 
+fn parse_parity(s: &str) -> anyhow::Result<serial::Parity> {
+    match s.to_lowercase().as_str() {
+        "none" => Ok(serial::Parity::ParityNone),
+        "odd" => Ok(serial::Parity::ParityOdd),
+        "even" => Ok(serial::Parity::ParityEven),
+        _ => bail!("invalid parity `{}`; expected one of none, odd, even", s),
+    }
+}
+
+fn parse_flow_control(s: &str) -> anyhow::Result<serial::FlowControl> {
+    match s.to_lowercase().as_str() {
+        "none" => Ok(serial::FlowControl::FlowNone),
+        "software" => Ok(serial::FlowControl::FlowSoftware),
+        "hardware" => Ok(serial::FlowControl::FlowHardware),
+        _ => bail!(
+            "invalid flow control `{}`; expected one of none, software, hardware",
+            s
+        ),
+    }
+}
+
 #[derive(Debug, StructOpt, Clone)]
 struct ConnectCommand {
     #[structopt(
@@ -419,8 +522,14 @@ fn run_serial(config: config::ConfigHandle, opts: &SerialCommand) -> anyhow::Res
     if let Some(baud) = opts.baud {
         serial.set_baud_rate(serial::BaudRate::from_speed(baud));
     }
+    if let Some(parity) = opts.parity {
+        serial.set_parity(parity);
+    }
+    if let Some(flow_control) = opts.flow_control {
+        serial.set_flow_control(flow_control);
+    }
 
-    let pty_system = Box::new(portable_pty::serial::SerialTty::new(&opts.port));
+    let pty_system = Box::new(serial);
     let domain: Arc<dyn Domain> = Arc::new(LocalDomain::with_pty_system("local", pty_system));
     let mux = Rc::new(mux::Mux::new(Some(domain.clone())));
     Mux::set_mux(&mux);
@@ -577,6 +686,14 @@ fn run_terminal_gui(config: config::ConfigHandle, opts: &StartCommand) -> anyhow
         }
     }
 
+    if mux.is_empty() && opts.resurrect {
+        match mux::session::SessionState::load() {
+            Ok(Some(session)) => session.restore(&mux, &gui)?,
+            Ok(None) => log::warn!("--resurrect: there is no saved session to restore"),
+            Err(err) => log::error!("--resurrect: {}", err),
+        }
+    }
+
     if mux.is_empty() {
         let window_id = mux.new_empty_window();
         let tab = mux
@@ -694,14 +811,10 @@ fn run() -> anyhow::Result<()> {
     if !opts.skip_config {
         config::reload();
     }
-    let config = match config::configuration_result() {
-        Err(err) => {
-            let err = format!("{:#}", err);
-            toast_notification("Wezterm configuration", &err);
-            config::configuration()
-        }
-        Ok(config) => config,
-    };
+    // If there was an error loading the configuration, it has
+    // been reported via show_configuration_error_message and the
+    // default configuration is used instead
+    let config = config::configuration();
 
     match opts
         .cmd
@@ -717,6 +830,12 @@ fn run() -> anyhow::Result<()> {
         SubCommand::Serial(serial) => run_serial(config, &serial),
         SubCommand::Connect(connect) => run_mux_client(config, &connect),
         SubCommand::ImageCat(cmd) => cmd.run(),
+        SubCommand::Record(cmd) => cmd.run(),
+        SubCommand::Replay(cmd) => cmd.run(),
+        SubCommand::Terminfo => {
+            print!("{}", term::terminfo::terminfo_source());
+            Ok(())
+        }
         SubCommand::Cli(cli) => {
             let initial = true;
             let client = Client::new_default_unix_domain(initial)?;
@@ -752,6 +871,59 @@ fn run() -> anyhow::Result<()> {
                     }
                     tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
                 }
+                CliSubCommand::Spawn {
+                    window_id,
+                    cwd,
+                    prog,
+                } => {
+                    let mut builder = if prog.is_empty() {
+                        CommandBuilder::new_default_prog()
+                    } else {
+                        let mut builder = CommandBuilder::new(&prog[0]);
+                        builder.args(&prog[1..]);
+                        builder
+                    };
+                    if let Some(cwd) = cwd {
+                        builder.cwd(cwd);
+                    }
+                    let command = if prog.is_empty() && builder.get_cwd().is_none() {
+                        None
+                    } else {
+                        Some(builder)
+                    };
+
+                    let spawned = client
+                        .spawn(server::codec::Spawn {
+                            // The default domain of the server
+                            domain_id: 0,
+                            window_id,
+                            command,
+                            size: PtySize::default(),
+                        })
+                        .wait()?;
+                    println!("{}", spawned.tab_id);
+                }
+                CliSubCommand::SendText { tab_id, text } => {
+                    let data = match text {
+                        Some(text) => text.into_bytes(),
+                        None => {
+                            let mut data = vec![];
+                            std::io::stdin().read_to_end(&mut data)?;
+                            data
+                        }
+                    };
+                    client
+                        .write_to_tab(server::codec::WriteToTab { tab_id, data })
+                        .wait()?;
+                }
+                CliSubCommand::ActivateTab { tab_id } => {
+                    client
+                        .activate_tab(server::codec::ActivateTab { tab_id })
+                        .wait()?;
+                }
+                CliSubCommand::KillPane { tab_id } => {
+                    client.kill_tab(server::codec::KillTab { tab_id }).wait()?;
+                }
                 CliSubCommand::Proxy => {
                     // The client object we created above will have spawned
                     // the server if needed, so now all we need to do is turn
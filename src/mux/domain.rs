@@ -30,6 +30,25 @@ pub fn alloc_domain_id() -> DomainId {
     DOMAIN_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
 }
 
+/// Returns the host portion of an address such as `user@host:port`
+/// or `[::1]:22`
+pub fn host_from_address(address: &str) -> &str {
+    let address = match address.rfind('@') {
+        Some(at) => &address[at + 1..],
+        None => address,
+    };
+    if address.starts_with('[') {
+        if let Some(end) = address.find(']') {
+            return &address[1..end];
+        }
+    }
+    match address.find(':') {
+        // A bare IPv6 address has more than one colon
+        Some(colon) if address.rfind(':') == Some(colon) => &address[..colon],
+        _ => address,
+    }
+}
+
 pub trait Domain: Downcast {
     /// Spawn a new command within this domain
     fn spawn(
@@ -54,6 +73,12 @@ pub trait Domain: Downcast {
 
     /// Indicates the state of the domain
     fn state(&self) -> DomainState;
+
+    /// Returns the name of the remote host that the domain is
+    /// connected to, or None if its programs run on this host
+    fn remote_host(&self) -> Option<String> {
+        None
+    }
 }
 impl_downcast!(Domain);
 
@@ -88,23 +113,51 @@ impl Domain for LocalDomain {
     ) -> Result<Rc<dyn Tab>, Error> {
         let config = configuration();
         let cmd = match command {
-            Some(c) => c,
+            Some(c) if c.is_default_prog() => {
+                // Use the default program from the configuration, but
+                // keep the working directory and the environment that
+                // the caller asked for
+                let mut cmd = config.build_prog(None)?;
+                if let Some(cwd) = c.get_cwd() {
+                    cmd.cwd(cwd);
+                }
+                for (key, value) in c.iter_env() {
+                    match value {
+                        Some(value) => cmd.env(key, value),
+                        None => cmd.env_remove(key),
+                    }
+                }
+                cmd
+            }
+            Some(mut c) => {
+                config.apply_environment(&mut c);
+                c
+            }
             None => config.build_prog(None)?,
         };
+        let argv = cmd.get_argv().clone();
         let pair = self.pty_system.openpty(size)?;
         let child = pair.slave.spawn_command(cmd)?;
         info!("spawned: {:?}", child);
 
+        let config = std::sync::Arc::new(crate::config::TermConfig::new());
         let terminal = term::Terminal::new(
             size.rows as usize,
             size.cols as usize,
             size.pixel_width as usize,
             size.pixel_height as usize,
-            std::sync::Arc::new(crate::config::TermConfig {}),
+            config.clone(),
         );
 
         let mux = Mux::get().unwrap();
-        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(terminal, child, pair.master, self.id));
+        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(
+            terminal,
+            config,
+            child,
+            pair.master,
+            self.id,
+            argv,
+        ));
 
         mux.add_tab(&tab)?;
         mux.add_tab_to_window(&tab, window)?;
@@ -132,3 +185,16 @@ impl Domain for LocalDomain {
         DomainState::Attached
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hosts() {
+        assert_eq!(host_from_address("example.com"), "example.com");
+        assert_eq!(host_from_address("user@example.com:2222"), "example.com");
+        assert_eq!(host_from_address("[::1]:22"), "::1");
+        assert_eq!(host_from_address("::1"), "::1");
+    }
+}
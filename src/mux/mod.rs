@@ -1,11 +1,13 @@
+use crate::config::{configuration, PaneInfo};
 use crate::frontend::{executor, low_pri_executor};
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::window::{Window, WindowId};
 use crate::ratelim::RateLimiter;
 use crate::server::pollable::{pollable_channel, PollableReceiver, PollableSender};
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, ensure, Error};
 use domain::{Domain, DomainId};
 use log::{debug, error};
+use portable_pty::reactor::{Reactor, ReadHandler};
 use portable_pty::ExitStatus;
 use promise::Future;
 use std::cell::{Ref, RefCell, RefMut};
@@ -15,18 +17,49 @@ use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 use term::TerminalHost;
 use termwiz::hyperlink::Hyperlink;
 use thiserror::*;
 
 pub mod domain;
 pub mod renderable;
+pub mod session;
+pub mod splits;
 pub mod tab;
 pub mod window;
 
 #[derive(Clone, Debug)]
 pub enum MuxNotification {
     TabOutput(TabId),
+    Bell(TabId),
+}
+
+/// Tracks the events in a tab that the user may want to know
+/// about when they are not looking at it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TabActivity {
+    /// The bell has rung since the activity was last cleared
+    pub bell: bool,
+    /// The number of times that the bell has rung in the tab
+    pub bell_count: usize,
+    /// Output was received since the activity was last cleared
+    pub output: bool,
+    /// When output was most recently received
+    pub last_output: Option<Instant>,
+}
+
+impl TabActivity {
+    /// Returns true if the tab produced output that has not been
+    /// seen, but has not produced any more for at least `duration`.
+    /// This is typically the sign that a long running job has finished.
+    pub fn is_silent(&self, duration: Duration) -> bool {
+        self.output
+            && self
+                .last_output
+                .map(|when| when.elapsed() >= duration)
+                .unwrap_or(false)
+    }
 }
 
 static SUB_ID: AtomicUsize = AtomicUsize::new(0);
@@ -40,6 +73,75 @@ pub struct Mux {
     domains: RefCell<HashMap<DomainId, Arc<dyn Domain>>>,
     domains_by_name: RefCell<HashMap<String, Arc<dyn Domain>>>,
     subscribers: RefCell<HashMap<usize, PollableSender<MuxNotification>>>,
+    activity: RefCell<HashMap<TabId, TabActivity>>,
+    /// Reads the output of the tabs whose ptys can be polled; the
+    /// others are each read by a thread of their own
+    reactor: Option<Reactor>,
+}
+
+/// Applies output that was read from the pty of `tab_id` to the tab
+pub fn apply_tab_output(tab_id: TabId, data: Vec<u8>) {
+    Future::with_executor(low_pri_executor(), move || {
+        let mux = Mux::get().unwrap();
+        if let Some(tab) = mux.get_tab(tab_id) {
+            let mut bell = false;
+            tab.advance_bytes(
+                &data,
+                &mut Host {
+                    writer: &mut *tab.writer(),
+                    bell: &mut bell,
+                },
+            );
+            mux.record_activity(tab_id, bell);
+            mux.notify(MuxNotification::TabOutput(tab_id));
+            if bell {
+                mux.notify(MuxNotification::Bell(tab_id));
+            }
+        }
+        Ok(())
+    });
+}
+
+/// How many times to check whether the program in a tab has been
+/// reaped after its pty has reached EOF, before giving up on it
+const EXIT_STATUS_CHECKS: usize = 10;
+
+/// Called when the pty of `tab_id` has reached EOF; the tab is
+/// removed unless it is to be held open
+fn tab_output_ended(tab_id: TabId) {
+    check_tab_exited(tab_id, 1);
+}
+
+/// Removes `tab_id`, or holds it open, once its program has exited
+fn check_tab_exited(tab_id: TabId, attempt: usize) {
+    Future::with_executor(executor(), move || {
+        let mux = Mux::get().unwrap();
+        let hold = match mux.get_tab(tab_id) {
+            Some(tab) => {
+                if attempt < EXIT_STATUS_CHECKS && tab.exit_status_pending() {
+                    // The pty may reach EOF slightly before the program
+                    // can be reaped; wait for that away from the gui thread
+                    thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(10));
+                        check_tab_exited(tab_id, attempt + 1);
+                    });
+                    return Ok(());
+                }
+                let mut bell = false;
+                tab.hold_after_exit(&mut Host {
+                    writer: &mut *tab.writer(),
+                    bell: &mut bell,
+                })
+            }
+            None => false,
+        };
+        if hold {
+            mux.notify(MuxNotification::TabOutput(tab_id));
+        } else {
+            mux.remove_tab(tab_id);
+        }
+        Ok(())
+    });
 }
 
 fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
@@ -66,21 +168,8 @@ fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
                     match lim.admit_check((size - pos) as u32) {
                         Ok(len) => {
                             let len = len as usize;
-                            let data = buf[pos..pos + len].to_vec();
+                            apply_tab_output(tab_id, buf[pos..pos + len].to_vec());
                             pos += len;
-                            Future::with_executor(low_pri_executor(), move || {
-                                let mux = Mux::get().unwrap();
-                                if let Some(tab) = mux.get_tab(tab_id) {
-                                    tab.advance_bytes(
-                                        &data,
-                                        &mut Host {
-                                            writer: &mut *tab.writer(),
-                                        },
-                                    );
-                                    mux.notify(MuxNotification::TabOutput(tab_id));
-                                }
-                                Ok(())
-                            });
                         }
                         Err(delay) => {
                             log::trace!("RateLimiter: sleep for {:?}", delay);
@@ -91,11 +180,41 @@ fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
             }
         }
     }
-    Future::with_executor(executor(), move || {
-        let mux = Mux::get().unwrap();
-        mux.remove_tab(tab_id);
-        Ok(())
-    });
+    tab_output_ended(tab_id);
+}
+
+/// Receives the output of a tab whose pty is read by the reactor
+struct TabOutputHandler {
+    tab_id: TabId,
+    lim: RateLimiter,
+}
+
+impl ReadHandler for TabOutputHandler {
+    fn data(&mut self, data: &[u8]) -> Option<Duration> {
+        // The reactor thread is shared with the other tabs, so rather
+        // than sleeping until the output is within the rate limit, the
+        // output is applied and further reads from this tab are paused
+        apply_tab_output(self.tab_id, data.to_vec());
+        let mut remaining = data.len() as u32;
+        while remaining > 0 {
+            match self.lim.admit_check(remaining) {
+                Ok(len) => remaining -= len,
+                Err(delay) => {
+                    log::trace!("RateLimiter: pause for {:?}", delay);
+                    return Some(delay);
+                }
+            }
+        }
+        None
+    }
+
+    fn closed(&mut self, error: Option<std::io::Error>) {
+        match error {
+            Some(err) => error!("read_pty failed: tab {} {:?}", self.tab_id, err),
+            None => error!("read_pty EOF: tab_id {}", self.tab_id),
+        }
+        tab_output_ended(self.tab_id);
+    }
 }
 
 /// This is just a stub impl of TerminalHost; it really only exists
@@ -104,6 +223,7 @@ fn read_from_tab_pty(tab_id: TabId, mut reader: Box<dyn std::io::Read>) {
 /// The GUI driven flows provide their own impl of TerminalHost.
 struct Host<'a> {
     writer: &'a mut dyn std::io::Write,
+    bell: &'a mut bool,
 }
 
 impl<'a> TerminalHost for Host<'a> {
@@ -112,13 +232,16 @@ impl<'a> TerminalHost for Host<'a> {
     }
 
     fn click_link(&mut self, link: &Arc<Hyperlink>) {
-        match open::that(link.uri()) {
-            Ok(_) => {}
-            Err(err) => error!("failed to open {}: {:?}", link.uri(), err),
+        if let Err(err) = crate::open::open_url(link.uri()) {
+            error!("failed to open {}: {:?}", link.uri(), err);
         }
     }
 
     fn set_title(&mut self, _title: &str) {}
+
+    fn bell(&mut self) {
+        *self.bell = true;
+    }
 }
 
 thread_local! {
@@ -145,6 +268,14 @@ impl Mux {
             domains_by_name: RefCell::new(domains_by_name),
             domains: RefCell::new(domains),
             subscribers: RefCell::new(HashMap::new()),
+            activity: RefCell::new(HashMap::new()),
+            reactor: match Reactor::new() {
+                Ok(reactor) => Some(reactor),
+                Err(err) => {
+                    error!("failed to start the pty reactor: {:#}", err);
+                    None
+                }
+            },
         }
     }
 
@@ -214,17 +345,47 @@ impl Mux {
 
     pub fn add_tab(&self, tab: &Rc<dyn Tab>) -> Result<(), Error> {
         self.tabs.borrow_mut().insert(tab.tab_id(), Rc::clone(tab));
+        self.apply_profile(tab);
 
-        let reader = tab.reader()?;
         let tab_id = tab.tab_id();
+        if let Some(reactor) = self.reactor.as_ref() {
+            if let Some(reader) = tab.pollable_reader()? {
+                let handler = TabOutputHandler {
+                    tab_id,
+                    lim: RateLimiter::new(|config| config.ratelimit_output_bytes_per_second),
+                };
+                return reactor.register(reader, Box::new(handler));
+            }
+        }
+        let reader = tab.reader()?;
         thread::spawn(move || read_from_tab_pty(tab_id, reader));
 
         Ok(())
     }
 
+    /// Applies the first of the configured profiles that matches
+    /// the domain, remote host and command of `tab`
+    fn apply_profile(&self, tab: &Rc<dyn Tab>) {
+        let domain = match self.get_domain(tab.domain_id()) {
+            Some(domain) => domain,
+            None => return,
+        };
+        let hostname = domain.remote_host();
+        let argv = tab.get_spawn_argv();
+        let profile = configuration().profile_for_pane(&PaneInfo {
+            domain: domain.domain_name(),
+            hostname: hostname.as_ref().map(String::as_str),
+            argv: argv.as_ref().map(Vec::as_slice),
+        });
+        if profile.is_some() {
+            tab.set_profile(profile);
+        }
+    }
+
     pub fn remove_tab(&self, tab_id: TabId) {
         debug!("removing tab {}", tab_id);
         self.tabs.borrow_mut().remove(&tab_id);
+        self.activity.borrow_mut().remove(&tab_id);
         self.prune_dead_windows();
     }
 
@@ -294,6 +455,99 @@ impl Mux {
         Ok(())
     }
 
+    fn record_activity(&self, tab_id: TabId, bell: bool) {
+        let mut activity = self.activity.borrow_mut();
+        let activity = activity.entry(tab_id).or_insert_with(TabActivity::default);
+        activity.output = true;
+        activity.last_output = Some(Instant::now());
+        if bell {
+            activity.bell = true;
+            activity.bell_count += 1;
+        }
+    }
+
+    /// Returns the bell and output activity in `tab_id` since it
+    /// was last cleared by `clear_tab_activity`
+    pub fn get_tab_activity(&self, tab_id: TabId) -> TabActivity {
+        self.activity
+            .borrow()
+            .get(&tab_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Marks the activity in `tab_id` as having been seen by the user
+    pub fn clear_tab_activity(&self, tab_id: TabId) {
+        if let Some(activity) = self.activity.borrow_mut().get_mut(&tab_id) {
+            activity.bell = false;
+            activity.output = false;
+        }
+    }
+
+    /// Returns the id of the window whose tab list includes `tab_id`
+    pub fn window_containing_tab(&self, tab_id: TabId) -> Option<WindowId> {
+        self.windows
+            .borrow()
+            .iter()
+            .find(|(_, window)| window.idx_by_id(tab_id).is_some())
+            .map(|(&window_id, _)| window_id)
+    }
+
+    /// Moves the tab `tab_id`, along with its panes, to the end of
+    /// the tab list in `window_id` and makes it the active tab there.
+    /// A window that is left without any tabs is removed.
+    pub fn move_tab_to_window(&self, tab_id: TabId, window_id: WindowId) -> anyhow::Result<()> {
+        let src_id = self
+            .window_containing_tab(tab_id)
+            .ok_or_else(|| anyhow!("move_tab_to_window: no window contains tab {}", tab_id))?;
+        if src_id == window_id {
+            return Ok(());
+        }
+        ensure!(
+            self.windows.borrow().contains_key(&window_id),
+            "move_tab_to_window: no such window_id {}",
+            window_id
+        );
+
+        let mut windows = self.windows.borrow_mut();
+        let src = windows.get_mut(&src_id).unwrap();
+        let idx = src.idx_by_id(tab_id).unwrap();
+        let detached = src.detach_tab(idx)?;
+        if src.is_empty() {
+            windows.remove(&src_id);
+        }
+        windows.get_mut(&window_id).unwrap().attach_tab(detached);
+        Ok(())
+    }
+
+    /// Moves the tab `tab_id` into a new window and returns the id of
+    /// that window.  The caller is responsible for creating a gui
+    /// window to display it.
+    pub fn move_tab_to_new_window(&self, tab_id: TabId) -> anyhow::Result<WindowId> {
+        let window_id = self.new_empty_window();
+        if let Err(err) = self.move_tab_to_window(tab_id, window_id) {
+            self.windows.borrow_mut().remove(&window_id);
+            return Err(err);
+        }
+        Ok(window_id)
+    }
+
+    /// Moves all of the tabs from `src_id` to the end of the tab list
+    /// in `dest_id`, then removes `src_id`
+    pub fn merge_windows(&self, src_id: WindowId, dest_id: WindowId) -> anyhow::Result<()> {
+        ensure!(src_id != dest_id, "cannot merge a window with itself");
+        let tab_ids: Vec<TabId> = self
+            .get_window(src_id)
+            .ok_or_else(|| anyhow!("merge_windows: no such window_id {}", src_id))?
+            .iter()
+            .map(|tab| tab.tab_id())
+            .collect();
+        for tab_id in tab_ids {
+            self.move_tab_to_window(tab_id, dest_id)?;
+        }
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.tabs.borrow().is_empty()
     }
@@ -1,9 +1,11 @@
+use crate::rangeset::RangeSet;
 use downcast_rs::{impl_downcast, Downcast};
 use std::borrow::Cow;
 use std::ops::Range;
 use std::sync::Arc;
-use term::{CursorPosition, Line, Terminal, TerminalState, VisibleRowIndex};
+use term::{CursorPosition, Line, StableRowIndex, Terminal, TerminalState, VisibleRowIndex};
 use termwiz::hyperlink::Hyperlink;
+use termwiz::surface::SequenceNo;
 
 /// Renderable allows passing something that isn't an actual term::Terminal
 /// instance into the renderer, which opens up remoting of the terminal
@@ -13,19 +15,41 @@ pub trait Renderable: Downcast {
     /// the visible screen
     fn get_cursor_position(&self) -> CursorPosition;
 
-    /// Returns the set of visible lines that are dirty.
-    /// The return value is a Vec<(line_idx, line, selrange)>, where
-    /// line_idx is relative to the top of the viewport.
+    /// Returns the current sequence number.  Lines that change are
+    /// assigned a larger sequence number than any that was returned
+    /// before they changed, so a consumer can remember this value
+    /// and later pass it to `get_dirty_lines` to learn what changed.
+    fn get_current_seqno(&mut self) -> SequenceNo;
+
+    /// Returns the set of rows in `lines` that have changed after
+    /// the point described by `seqno`
+    fn get_dirty_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+        seqno: SequenceNo,
+    ) -> RangeSet<StableRowIndex>;
+
+    /// Returns the lines in `lines`.
+    /// The return value is a Vec<(row, line, selrange)>.
     /// The selrange value is the column range representing the selected
     /// columns on this line.
-    fn get_dirty_lines(&self) -> Vec<(usize, Cow<Line>, Range<usize>)>;
+    fn get_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+    ) -> Vec<(StableRowIndex, Cow<Line>, Range<usize>)>;
 
-    fn has_dirty_lines(&self) -> bool;
+    /// Returns the range of rows that are displayed in the viewport
+    fn get_viewport_rows(&self) -> Range<StableRowIndex>;
 
     fn make_all_lines_dirty(&mut self);
 
-    /// Clear the dirty flag for all dirty lines
-    fn clean_dirty_lines(&mut self);
+    /// Marks the rows in `lines` as dirty, so that they are repainted
+    /// even though they haven't changed; this is used to animate
+    /// blinking.  Renderables that can't mark individual lines mark
+    /// all of them.
+    fn make_lines_dirty(&mut self, _lines: Range<StableRowIndex>) {
+        self.make_all_lines_dirty();
+    }
 
     /// Returns the currently highlighted hyperlink
     fn current_highlight(&self) -> Option<Arc<Hyperlink>>;
@@ -39,6 +63,10 @@ pub trait Renderable: Downcast {
     /// used to render a scrollbar UI
     fn get_scrollbar_info(&self) -> (VisibleRowIndex, usize);
     fn set_viewport_position(&mut self, position: VisibleRowIndex);
+
+    /// Returns the rows on which a shell prompt begins, in
+    /// ascending order
+    fn get_prompt_rows(&self) -> Vec<StableRowIndex>;
 }
 impl_downcast!(Renderable);
 
@@ -47,21 +75,41 @@ impl Renderable for Terminal {
         self.cursor_pos()
     }
 
-    fn get_dirty_lines(&self) -> Vec<(usize, Cow<Line>, Range<usize>)> {
-        TerminalState::get_dirty_lines(self)
-            .into_iter()
-            .map(|(idx, line, range)| (idx, Cow::Borrowed(line), range))
-            .collect()
+    fn get_current_seqno(&mut self) -> SequenceNo {
+        TerminalState::current_seqno(self)
+    }
+
+    fn get_dirty_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+        seqno: SequenceNo,
+    ) -> RangeSet<StableRowIndex> {
+        let mut set = RangeSet::new();
+        for row in TerminalState::get_changed_stable_rows(self, lines, seqno) {
+            set.add(row);
+        }
+        set
+    }
+
+    fn get_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+    ) -> Vec<(StableRowIndex, Cow<Line>, Range<usize>)> {
+        TerminalState::get_stable_lines(self, lines)
     }
 
-    fn clean_dirty_lines(&mut self) {
-        TerminalState::clean_dirty_lines(self)
+    fn get_viewport_rows(&self) -> Range<StableRowIndex> {
+        TerminalState::get_viewport_stable_rows(self)
     }
 
     fn make_all_lines_dirty(&mut self) {
         TerminalState::make_all_lines_dirty(self)
     }
 
+    fn make_lines_dirty(&mut self, lines: Range<StableRowIndex>) {
+        TerminalState::make_stable_lines_dirty(self, lines)
+    }
+
     fn current_highlight(&self) -> Option<Arc<Hyperlink>> {
         TerminalState::current_highlight(self)
     }
@@ -71,10 +119,6 @@ impl Renderable for Terminal {
         (screen.physical_rows, screen.physical_cols)
     }
 
-    fn has_dirty_lines(&self) -> bool {
-        TerminalState::has_dirty_lines(self)
-    }
-
     fn get_scrollbar_info(&self) -> (VisibleRowIndex, usize) {
         let offset = self.get_viewport_offset();
         let num_lines = self.screen().lines.len();
@@ -84,4 +128,8 @@ impl Renderable for Terminal {
     fn set_viewport_position(&mut self, position: VisibleRowIndex) {
         self.set_scroll_viewport(position);
     }
+
+    fn get_prompt_rows(&self) -> Vec<StableRowIndex> {
+        TerminalState::get_prompt_stable_rows(self)
+    }
 }
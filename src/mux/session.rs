@@ -0,0 +1,226 @@
+//! Saves the layout of the local tabs in the mux so that it can
+//! be restored the next time that wezterm is started with the
+//! `--resurrect` flag.
+use crate::config::configuration;
+use crate::font::FontConfiguration;
+use crate::frontend::FrontEnd;
+use crate::mux::domain::LocalDomain;
+use crate::mux::Mux;
+use anyhow::{anyhow, bail, Context};
+use portable_pty::PtySize;
+use serde_derive::*;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// The state of a tab that is needed to respawn it
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabState {
+    /// The working directory of the process in the tab
+    pub cwd: Option<PathBuf>,
+    /// The program that was spawned in the tab; None means
+    /// that the default program was used
+    pub argv: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub cols: u16,
+    pub rows: u16,
+    pub active_tab: usize,
+    pub tabs: Vec<TabState>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub windows: Vec<WindowState>,
+}
+
+/// Returns the path to the file that holds the saved session
+pub fn session_file_path() -> anyhow::Result<PathBuf> {
+    let data_dir = match dirs::data_dir() {
+        Some(dir) => dir,
+        None => dirs::home_dir()
+            .ok_or_else(|| anyhow!("can't find home dir"))?
+            .join(".local/share"),
+    };
+    Ok(data_dir.join("wezterm").join("session.toml"))
+}
+
+impl SessionState {
+    /// Captures the state of the windows in the mux.  Only tabs
+    /// that belong to the local domain are recorded; tabs from
+    /// remote domains are restored by reconnecting to the domain.
+    /// Tabs that have been split into panes are recorded as their
+    /// active pane.
+    pub fn capture(mux: &Mux) -> Self {
+        let mut windows = vec![];
+        for window_id in mux.iter_windows() {
+            let window = match mux.get_window(window_id) {
+                Some(window) => window,
+                None => continue,
+            };
+
+            let mut tabs = vec![];
+            let mut active_tab = 0;
+            for (idx, tab) in window.iter().enumerate() {
+                let is_local = mux
+                    .get_domain(tab.domain_id())
+                    .map(|domain| domain.is::<LocalDomain>())
+                    .unwrap_or(false);
+                if !is_local {
+                    continue;
+                }
+                if idx == window.get_active_idx() {
+                    active_tab = tabs.len();
+                }
+                tabs.push(TabState {
+                    cwd: tab.get_current_working_dir(),
+                    argv: tab.get_spawn_argv().map(|argv| {
+                        argv.iter()
+                            .map(|arg| arg.to_string_lossy().into_owned())
+                            .collect()
+                    }),
+                });
+            }
+
+            if tabs.is_empty() {
+                continue;
+            }
+
+            let (rows, cols) = match window.get_size() {
+                Some(size) => (size.rows, size.cols),
+                None => {
+                    let size = PtySize::default();
+                    (size.rows, size.cols)
+                }
+            };
+
+            windows.push(WindowState {
+                cols,
+                rows,
+                active_tab,
+                tabs,
+            });
+        }
+        Self { windows }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = session_file_path()?;
+        if let Some(parent) = path.parent() {
+            crate::create_user_owned_dirs(parent)?;
+        }
+        let data = toml::to_string(self)?;
+        // Write to a file of our own and then move it into place, so
+        // that a crash or another instance saving at the same time
+        // can't leave a truncated session behind
+        let temp = path.with_extension(format!("toml.{}.tmp", std::process::id()));
+        std::fs::write(&temp, data)
+            .with_context(|| format!("writing session to {}", temp.display()))?;
+        if let Err(err) = std::fs::rename(&temp, &path) {
+            std::fs::remove_file(&temp).ok();
+            bail!(
+                "moving session from {} to {}: {}",
+                temp.display(),
+                path.display(),
+                err
+            );
+        }
+        Ok(())
+    }
+
+    /// Loads the most recently saved session.  Returns None if
+    /// no session has been saved.
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let path = session_file_path()?;
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => bail!("reading session from {}: {}", path.display(), err),
+        };
+        let state = toml::from_str(&data)
+            .with_context(|| format!("parsing session from {}", path.display()))?;
+        Ok(Some(state))
+    }
+
+    /// Spawns the windows and tabs described by this session
+    /// in the default domain of the mux
+    pub fn restore(&self, mux: &Rc<Mux>, gui: &Rc<dyn FrontEnd>) -> anyhow::Result<()> {
+        let config = configuration();
+        let domain = mux.default_domain();
+
+        for window_state in &self.windows {
+            let size = PtySize {
+                rows: window_state.rows,
+                cols: window_state.cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            let window_id = mux.new_empty_window();
+
+            for (idx, tab_state) in window_state.tabs.iter().enumerate() {
+                let mut cmd = config.build_prog(
+                    tab_state
+                        .argv
+                        .as_ref()
+                        .map(|argv| argv.iter().map(OsStr::new).collect()),
+                )?;
+                if let Some(cwd) = tab_state.cwd.as_ref() {
+                    if cwd.is_dir() {
+                        cmd.cwd(cwd);
+                    }
+                }
+
+                let tab = domain.spawn(size, Some(cmd), window_id)?;
+                if idx == 0 {
+                    // Each window has its own font scale
+                    let fontconfig = Rc::new(FontConfiguration::new());
+                    gui.spawn_new_window(&fontconfig, &tab, window_id)?;
+                }
+            }
+
+            if let Some(mut window) = mux.get_window_mut(window_id) {
+                if window_state.active_tab < window.len() {
+                    window.set_active(window_state.active_tab);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn session_round_trip() {
+        let state = SessionState {
+            windows: vec![WindowState {
+                cols: 80,
+                rows: 24,
+                active_tab: 1,
+                tabs: vec![
+                    TabState {
+                        cwd: Some(PathBuf::from("/tmp")),
+                        argv: None,
+                    },
+                    TabState {
+                        cwd: None,
+                        argv: Some(vec!["top".to_string(), "-d".to_string(), "1".to_string()]),
+                    },
+                ],
+            }],
+        };
+
+        let data = toml::to_string(&state).unwrap();
+        let parsed: SessionState = toml::from_str(&data).unwrap();
+        assert_eq!(parsed, state);
+    }
+}
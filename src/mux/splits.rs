@@ -0,0 +1,660 @@
+//! Split panes allow a tab to be subdivided into a tree of panes,
+//! each of which hosts its own terminal.  The tree only tracks the
+//! ids of the panes and the proportion of space allocated to each
+//! side of a split; the cell dimensions of the individual panes are
+//! computed on demand from the size of the containing window.
+
+use crate::mux::tab::TabId;
+use anyhow::bail;
+use portable_pty::PtySize;
+use serde_derive::*;
+
+/// Specifies how a pane should be split
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// The panes are arranged side by side, separated by a vertical divider
+    Horizontal,
+    /// The panes are arranged one above the other, separated by a
+    /// horizontal divider
+    Vertical,
+}
+
+impl std::str::FromStr for SplitDirection {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Horizontal" => Ok(Self::Horizontal),
+            "Vertical" => Ok(Self::Vertical),
+            _ => bail!("invalid SplitDirection {}", s),
+        }
+    }
+}
+
+/// Used to navigate between, or to resize, panes
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PaneDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl PaneDirection {
+    fn split_direction(self) -> SplitDirection {
+        match self {
+            Self::Left | Self::Right => SplitDirection::Horizontal,
+            Self::Up | Self::Down => SplitDirection::Vertical,
+        }
+    }
+}
+
+impl std::str::FromStr for PaneDirection {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Left" => Ok(Self::Left),
+            "Right" => Ok(Self::Right),
+            "Up" => Ok(Self::Up),
+            "Down" => Ok(Self::Down),
+            _ => bail!("invalid PaneDirection {}", s),
+        }
+    }
+}
+
+/// The location of a pane, expressed in cells relative to the
+/// top left of the area occupied by the tab
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanePosition {
+    pub tab_id: TabId,
+    pub left: usize,
+    pub top: usize,
+    pub cols: usize,
+    pub rows: usize,
+}
+
+impl PanePosition {
+    /// Returns true if the cell at x, y falls within this pane
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.left && x < self.left + self.cols && y >= self.top && y < self.top + self.rows
+    }
+
+    /// Computes the pty size for this pane, given the size of the
+    /// area occupied by the whole tab
+    pub fn pty_size(&self, size: PtySize) -> PtySize {
+        let scale = |pixels: u16, cells: usize, total: u16| {
+            if total == 0 {
+                0
+            } else {
+                (pixels as usize * cells / total as usize) as u16
+            }
+        };
+        PtySize {
+            rows: self.rows as u16,
+            cols: self.cols as u16,
+            pixel_width: scale(size.pixel_width, self.cols, size.cols),
+            pixel_height: scale(size.pixel_height, self.rows, size.rows),
+        }
+    }
+}
+
+/// A one cell wide line separating two panes.
+/// For a Horizontal split the divider runs vertically
+/// from `top` for `length` rows; for a Vertical split it
+/// runs horizontally from `left` for `length` columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneDivider {
+    pub direction: SplitDirection,
+    pub left: usize,
+    pub top: usize,
+    pub length: usize,
+}
+
+/// The result of computing the layout of a SplitTree
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SplitLayout {
+    pub panes: Vec<PanePosition>,
+    pub dividers: Vec<PaneDivider>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf(TabId),
+    Split {
+        direction: SplitDirection,
+        /// The proportion of the available space given to `first`
+        ratio: f32,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+/// Divides `total` cells into the sizes of the two sides of a split,
+/// leaving one cell for the divider between them.
+fn split_sizes(total: usize, ratio: f32) -> (usize, usize) {
+    let avail = total.saturating_sub(1);
+    if avail < 2 {
+        return (avail, 0);
+    }
+    let first = ((avail as f32 * ratio).round() as usize)
+        .max(1)
+        .min(avail - 1);
+    (first, avail - first)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Adjust {
+    NotFound,
+    /// The pane was found, but no suitable divider has been found yet
+    Pending,
+    Done,
+}
+
+impl Node {
+    fn contains(&self, tab_id: TabId) -> bool {
+        match self {
+            Node::Leaf(id) => *id == tab_id,
+            Node::Split { first, second, .. } => first.contains(tab_id) || second.contains(tab_id),
+        }
+    }
+
+    fn collect_ids(&self, ids: &mut Vec<TabId>) {
+        match self {
+            Node::Leaf(id) => ids.push(*id),
+            Node::Split { first, second, .. } => {
+                first.collect_ids(ids);
+                second.collect_ids(ids);
+            }
+        }
+    }
+
+    fn first_leaf(&self) -> TabId {
+        match self {
+            Node::Leaf(id) => *id,
+            Node::Split { first, .. } => first.first_leaf(),
+        }
+    }
+
+    fn split(&mut self, existing: TabId, new_pane: TabId, direction: SplitDirection) -> bool {
+        match self {
+            Node::Leaf(id) if *id == existing => {
+                *self = Node::Split {
+                    direction,
+                    ratio: 0.5,
+                    first: Box::new(Node::Leaf(existing)),
+                    second: Box::new(Node::Leaf(new_pane)),
+                };
+                true
+            }
+            Node::Leaf(_) => false,
+            Node::Split { first, second, .. } => {
+                first.split(existing, new_pane, direction)
+                    || second.split(existing, new_pane, direction)
+            }
+        }
+    }
+
+    /// Removes tab_id from the tree below this node, returning the
+    /// id of the pane that took over its space
+    fn remove(&mut self, tab_id: TabId) -> Option<TabId> {
+        let replacement = match self {
+            Node::Leaf(_) => return None,
+            Node::Split { first, second, .. } => match (&**first, &**second) {
+                (Node::Leaf(id), _) if *id == tab_id => Some((**second).clone()),
+                (_, Node::Leaf(id)) if *id == tab_id => Some((**first).clone()),
+                _ => None,
+            },
+        };
+
+        match replacement {
+            Some(node) => {
+                let focus = node.first_leaf();
+                *self = node;
+                Some(focus)
+            }
+            None => match self {
+                Node::Split { first, second, .. } => {
+                    first.remove(tab_id).or_else(|| second.remove(tab_id))
+                }
+                Node::Leaf(_) => None,
+            },
+        }
+    }
+
+    fn layout(&self, left: usize, top: usize, cols: usize, rows: usize, layout: &mut SplitLayout) {
+        match self {
+            Node::Leaf(tab_id) => layout.panes.push(PanePosition {
+                tab_id: *tab_id,
+                left,
+                top,
+                cols,
+                rows,
+            }),
+            Node::Split {
+                direction: SplitDirection::Horizontal,
+                ratio,
+                first,
+                second,
+            } => {
+                let (first_cols, second_cols) = split_sizes(cols, *ratio);
+                first.layout(left, top, first_cols, rows, layout);
+                layout.dividers.push(PaneDivider {
+                    direction: SplitDirection::Horizontal,
+                    left: left + first_cols,
+                    top,
+                    length: rows,
+                });
+                second.layout(left + first_cols + 1, top, second_cols, rows, layout);
+            }
+            Node::Split {
+                direction: SplitDirection::Vertical,
+                ratio,
+                first,
+                second,
+            } => {
+                let (first_rows, second_rows) = split_sizes(rows, *ratio);
+                first.layout(left, top, cols, first_rows, layout);
+                layout.dividers.push(PaneDivider {
+                    direction: SplitDirection::Vertical,
+                    left,
+                    top: top + first_rows,
+                    length: cols,
+                });
+                second.layout(left, top + first_rows + 1, cols, second_rows, layout);
+            }
+        }
+    }
+
+    fn adjust_size(
+        &mut self,
+        tab_id: TabId,
+        direction: PaneDirection,
+        amount: usize,
+        cols: usize,
+        rows: usize,
+    ) -> Adjust {
+        match self {
+            Node::Leaf(id) => {
+                if *id == tab_id {
+                    Adjust::Pending
+                } else {
+                    Adjust::NotFound
+                }
+            }
+            Node::Split {
+                direction: split_direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let total = match split_direction {
+                    SplitDirection::Horizontal => cols,
+                    SplitDirection::Vertical => rows,
+                };
+                let (first_size, second_size) = split_sizes(total, *ratio);
+                let (first_dims, second_dims) = match split_direction {
+                    SplitDirection::Horizontal => ((first_size, rows), (second_size, rows)),
+                    SplitDirection::Vertical => ((cols, first_size), (cols, second_size)),
+                };
+                let same_axis = direction.split_direction() == *split_direction;
+
+                // The divider can move to grow the pane towards
+                // the requested direction; a pane in the first half
+                // grows right/down, a pane in the second half grows
+                // left/up.
+                let mut move_divider = |delta: isize| {
+                    let avail = total.saturating_sub(1);
+                    if avail >= 2 {
+                        let new_first =
+                            (first_size as isize + delta).max(1).min(avail as isize - 1);
+                        *ratio = new_first as f32 / avail as f32;
+                    }
+                    Adjust::Done
+                };
+
+                match first.adjust_size(tab_id, direction, amount, first_dims.0, first_dims.1) {
+                    Adjust::NotFound => {}
+                    Adjust::Pending => {
+                        if same_axis
+                            && (direction == PaneDirection::Right
+                                || direction == PaneDirection::Down)
+                        {
+                            return move_divider(amount as isize);
+                        }
+                        return Adjust::Pending;
+                    }
+                    Adjust::Done => return Adjust::Done,
+                }
+
+                match second.adjust_size(tab_id, direction, amount, second_dims.0, second_dims.1) {
+                    Adjust::Pending => {
+                        if same_axis
+                            && (direction == PaneDirection::Left || direction == PaneDirection::Up)
+                        {
+                            move_divider(-(amount as isize))
+                        } else {
+                            Adjust::Pending
+                        }
+                    }
+                    result => result,
+                }
+            }
+        }
+    }
+}
+
+/// The layout of the panes within a tab
+#[derive(Debug, Clone)]
+pub struct SplitTree {
+    root: Node,
+    /// The pane that temporarily fills the whole tab, if any
+    zoomed: Option<TabId>,
+}
+
+impl SplitTree {
+    /// Create a tree holding just the specified pane
+    pub fn new(tab_id: TabId) -> Self {
+        Self {
+            root: Node::Leaf(tab_id),
+            zoomed: None,
+        }
+    }
+
+    pub fn contains(&self, tab_id: TabId) -> bool {
+        self.root.contains(tab_id)
+    }
+
+    /// Returns the ids of the panes, ordered from top left
+    /// to bottom right
+    pub fn pane_ids(&self) -> Vec<TabId> {
+        let mut ids = vec![];
+        self.root.collect_ids(&mut ids);
+        ids
+    }
+
+    pub fn num_panes(&self) -> usize {
+        self.pane_ids().len()
+    }
+
+    /// Splits the `existing` pane in two, placing `new_pane`
+    /// to the right of or below it, depending on `direction`.
+    /// Returns false if `existing` is not part of this tree.
+    /// Splitting a pane ends any zoom, so that the new pane is visible.
+    pub fn split(&mut self, existing: TabId, new_pane: TabId, direction: SplitDirection) -> bool {
+        let split = self.root.split(existing, new_pane, direction);
+        if split {
+            self.zoomed = None;
+        }
+        split
+    }
+
+    /// Removes a pane from the tree; its sibling expands to fill
+    /// the space that it occupied.  Returns the id of the pane that
+    /// should receive focus in its place, or None if the pane was
+    /// not found or is the only pane in the tree.
+    pub fn remove(&mut self, tab_id: TabId) -> Option<TabId> {
+        if self.zoomed == Some(tab_id) {
+            self.zoomed = None;
+        }
+        self.root.remove(tab_id)
+    }
+
+    /// Returns the pane that fills the whole tab, if one is zoomed
+    pub fn zoomed(&self) -> Option<TabId> {
+        self.zoomed
+    }
+
+    /// Zooms `tab_id` so that it fills the whole tab, or passing None
+    /// restores the layout of the panes.  Returns false if the pane
+    /// is not part of this tree.
+    pub fn set_zoomed(&mut self, tab_id: Option<TabId>) -> bool {
+        match tab_id {
+            Some(id) if !self.contains(id) => false,
+            _ => {
+                self.zoomed = tab_id;
+                true
+            }
+        }
+    }
+
+    /// Computes the position of each pane, and the dividers
+    /// between them, for a tab that is `cols` x `rows` cells in size.
+    /// While a pane is zoomed it is the only pane in the layout.
+    pub fn layout(&self, cols: usize, rows: usize) -> SplitLayout {
+        match self.zoomed {
+            Some(tab_id) => SplitLayout {
+                panes: vec![PanePosition {
+                    tab_id,
+                    left: 0,
+                    top: 0,
+                    cols,
+                    rows,
+                }],
+                dividers: vec![],
+            },
+            None => self.unzoomed_layout(cols, rows),
+        }
+    }
+
+    /// Computes the layout as it is when no pane is zoomed
+    fn unzoomed_layout(&self, cols: usize, rows: usize) -> SplitLayout {
+        let mut layout = SplitLayout::default();
+        self.root.layout(0, 0, cols, rows, &mut layout);
+        layout
+    }
+
+    /// Returns the pane that is adjacent to `tab_id` in the
+    /// specified direction.  When several panes are adjacent, the
+    /// one sharing the most rows or columns with `tab_id` is chosen.
+    /// Any zoom is ignored.
+    pub fn neighbor(
+        &self,
+        tab_id: TabId,
+        direction: PaneDirection,
+        cols: usize,
+        rows: usize,
+    ) -> Option<TabId> {
+        let layout = self.unzoomed_layout(cols, rows);
+        let current = layout.panes.iter().find(|p| p.tab_id == tab_id)?;
+
+        let overlap = |a_start: usize, a_len: usize, b_start: usize, b_len: usize| {
+            let start = a_start.max(b_start);
+            let end = (a_start + a_len).min(b_start + b_len);
+            end.saturating_sub(start)
+        };
+
+        layout
+            .panes
+            .iter()
+            .filter_map(|p| {
+                let (adjacent, shared) = match direction {
+                    PaneDirection::Left => (
+                        p.left + p.cols + 1 == current.left,
+                        overlap(p.top, p.rows, current.top, current.rows),
+                    ),
+                    PaneDirection::Right => (
+                        current.left + current.cols + 1 == p.left,
+                        overlap(p.top, p.rows, current.top, current.rows),
+                    ),
+                    PaneDirection::Up => (
+                        p.top + p.rows + 1 == current.top,
+                        overlap(p.left, p.cols, current.left, current.cols),
+                    ),
+                    PaneDirection::Down => (
+                        current.top + current.rows + 1 == p.top,
+                        overlap(p.left, p.cols, current.left, current.cols),
+                    ),
+                };
+                if adjacent && shared > 0 {
+                    Some((shared, p.tab_id))
+                } else {
+                    None
+                }
+            })
+            // max_by_key returns the last of equal elements; reverse
+            // so that ties resolve to the top/leftmost pane
+            .rev()
+            .max_by_key(|(shared, _)| *shared)
+            .map(|(_, id)| id)
+    }
+
+    /// Grows `tab_id` by `amount` cells in the specified direction
+    /// by moving the nearest divider on that side of the pane.
+    /// Returns false if there is no such divider.
+    pub fn adjust_size(
+        &mut self,
+        tab_id: TabId,
+        direction: PaneDirection,
+        amount: usize,
+        cols: usize,
+        rows: usize,
+    ) -> bool {
+        self.root.adjust_size(tab_id, direction, amount, cols, rows) == Adjust::Done
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pos(tab_id: TabId, left: usize, top: usize, cols: usize, rows: usize) -> PanePosition {
+        PanePosition {
+            tab_id,
+            left,
+            top,
+            cols,
+            rows,
+        }
+    }
+
+    #[test]
+    fn split_layout() {
+        let mut tree = SplitTree::new(0);
+        assert_eq!(tree.layout(80, 24).panes, vec![pos(0, 0, 0, 80, 24)]);
+
+        assert!(tree.split(0, 1, SplitDirection::Horizontal));
+        assert!(tree.split(1, 2, SplitDirection::Vertical));
+        assert!(!tree.split(42, 3, SplitDirection::Vertical));
+        assert_eq!(tree.pane_ids(), vec![0, 1, 2]);
+
+        let layout = tree.layout(81, 25);
+        assert_eq!(
+            layout.panes,
+            vec![
+                pos(0, 0, 0, 40, 25),
+                pos(1, 41, 0, 40, 12),
+                pos(2, 41, 13, 40, 12),
+            ]
+        );
+        assert_eq!(
+            layout.dividers,
+            vec![
+                PaneDivider {
+                    direction: SplitDirection::Horizontal,
+                    left: 40,
+                    top: 0,
+                    length: 25,
+                },
+                PaneDivider {
+                    direction: SplitDirection::Vertical,
+                    left: 41,
+                    top: 12,
+                    length: 40,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn split_remove() {
+        let mut tree = SplitTree::new(0);
+        tree.split(0, 1, SplitDirection::Horizontal);
+        tree.split(1, 2, SplitDirection::Vertical);
+
+        // Removing the left pane gives its space to the right hand split
+        assert_eq!(tree.remove(0), Some(1));
+        assert_eq!(
+            tree.layout(80, 25).panes,
+            vec![pos(1, 0, 0, 80, 12), pos(2, 0, 13, 80, 12)]
+        );
+
+        assert_eq!(tree.remove(2), Some(1));
+        assert_eq!(tree.pane_ids(), vec![1]);
+        assert_eq!(tree.remove(1), None);
+    }
+
+    #[test]
+    fn split_neighbor() {
+        let mut tree = SplitTree::new(0);
+        tree.split(0, 1, SplitDirection::Horizontal);
+        tree.split(1, 2, SplitDirection::Vertical);
+
+        assert_eq!(tree.neighbor(0, PaneDirection::Right, 81, 25), Some(1));
+        assert_eq!(tree.neighbor(0, PaneDirection::Left, 81, 25), None);
+        assert_eq!(tree.neighbor(1, PaneDirection::Down, 81, 25), Some(2));
+        assert_eq!(tree.neighbor(2, PaneDirection::Up, 81, 25), Some(1));
+        assert_eq!(tree.neighbor(2, PaneDirection::Left, 81, 25), Some(0));
+        assert_eq!(tree.neighbor(1, PaneDirection::Right, 81, 25), None);
+    }
+
+    #[test]
+    fn split_zoom() {
+        let mut tree = SplitTree::new(0);
+        tree.split(0, 1, SplitDirection::Horizontal);
+        tree.split(1, 2, SplitDirection::Vertical);
+
+        assert!(!tree.set_zoomed(Some(42)));
+        assert!(tree.set_zoomed(Some(1)));
+        assert_eq!(tree.zoomed(), Some(1));
+        assert_eq!(
+            tree.layout(81, 25),
+            SplitLayout {
+                panes: vec![pos(1, 0, 0, 81, 25)],
+                dividers: vec![],
+            }
+        );
+        // Navigation still considers the unzoomed layout
+        assert_eq!(tree.neighbor(1, PaneDirection::Down, 81, 25), Some(2));
+
+        assert!(tree.set_zoomed(None));
+        assert_eq!(tree.layout(81, 25).panes.len(), 3);
+
+        // Splitting or removing the zoomed pane ends the zoom
+        tree.set_zoomed(Some(2));
+        tree.split(0, 3, SplitDirection::Vertical);
+        assert_eq!(tree.zoomed(), None);
+        tree.set_zoomed(Some(2));
+        tree.remove(2);
+        assert_eq!(tree.zoomed(), None);
+        assert_eq!(tree.layout(81, 25).panes.len(), 3);
+    }
+
+    #[test]
+    fn split_adjust_size() {
+        let mut tree = SplitTree::new(0);
+        tree.split(0, 1, SplitDirection::Horizontal);
+
+        assert!(tree.adjust_size(0, PaneDirection::Right, 10, 81, 25));
+        assert_eq!(
+            tree.layout(81, 25).panes,
+            vec![pos(0, 0, 0, 50, 25), pos(1, 51, 0, 30, 25)]
+        );
+
+        assert!(tree.adjust_size(1, PaneDirection::Left, 20, 81, 25));
+        assert_eq!(
+            tree.layout(81, 25).panes,
+            vec![pos(0, 0, 0, 30, 25), pos(1, 31, 0, 50, 25)]
+        );
+
+        // There is no divider to the left of the leftmost pane,
+        // nor any vertical split to adjust
+        assert!(!tree.adjust_size(0, PaneDirection::Left, 1, 81, 25));
+        assert!(!tree.adjust_size(0, PaneDirection::Down, 1, 81, 25));
+
+        // Panes are never shrunk below a single cell
+        assert!(tree.adjust_size(0, PaneDirection::Right, 100, 81, 25));
+        assert_eq!(
+            tree.layout(81, 25).panes,
+            vec![pos(0, 0, 0, 79, 25), pos(1, 80, 0, 1, 25)]
+        );
+    }
+}
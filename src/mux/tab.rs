@@ -1,12 +1,19 @@
+use crate::config::ExitBehavior;
 use crate::frontend::executor;
 use crate::mux::domain::DomainId;
 use crate::mux::renderable::Renderable;
 use crate::mux::Mux;
 use downcast_rs::{impl_downcast, Downcast};
+use filedescriptor::FileDescriptor;
+use portable_pty::procinfo::ProcessInfo;
 use portable_pty::PtySize;
+use promise::Future;
 use std::cell::RefMut;
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use term::color::ColorPalette;
+use term::export::ExportFormat;
 use term::selection::SelectionRange;
 use term::{Clipboard, KeyCode, KeyModifiers, MouseEvent, TerminalHost};
 
@@ -25,6 +32,19 @@ struct Paste {
     offset: usize,
 }
 
+/// Returns the length of the next chunk of `text` to paste, taking
+/// care not to split a multi-byte character across chunks
+fn paste_chunk_len(text: &str) -> usize {
+    if text.len() <= PASTE_CHUNK_SIZE {
+        return text.len();
+    }
+    let mut len = PASTE_CHUNK_SIZE;
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
+
 fn schedule_next_paste(paste: &Arc<Mutex<Paste>>) {
     let paste = Arc::clone(paste);
     promise::Future::with_executor(executor(), move || {
@@ -33,7 +53,7 @@ fn schedule_next_paste(paste: &Arc<Mutex<Paste>>) {
         let tab = mux.get_tab(locked.tab_id).unwrap();
 
         let remain = locked.text.len() - locked.offset;
-        let chunk = remain.min(PASTE_CHUNK_SIZE);
+        let chunk = paste_chunk_len(&locked.text[locked.offset..]);
         let text_slice = &locked.text[locked.offset..locked.offset + chunk];
         tab.send_paste(text_slice).unwrap();
 
@@ -64,6 +84,90 @@ pub trait Tab: Downcast {
 
     fn set_clipboard(&self, _clipboard: &Arc<dyn Clipboard>) {}
 
+    /// Returns a reader for the output of the tab that can be polled
+    /// for readiness, so that the mux can read it along with the
+    /// output of other tabs rather than from a thread of its own.
+    /// Returns None if `reader` must be used instead.
+    fn pollable_reader(&self) -> anyhow::Result<Option<FileDescriptor>> {
+        Ok(None)
+    }
+
+    /// Sets a title that takes precedence over the title set by the
+    /// program running in the tab via escape sequences.
+    /// Passing None reverts to the escape sequence title.
+    fn set_user_title(&self, _title: Option<String>) {}
+
+    /// Returns the name of the profile whose overrides apply to
+    /// this tab
+    fn profile(&self) -> Option<String> {
+        None
+    }
+
+    /// Applies the named profile to this tab, replacing the profile
+    /// that was applied before.  Passing None reverts to the
+    /// appearance from the configuration.
+    fn set_profile(&self, _profile: Option<String>) {}
+
+    /// Discards the scrollback and clears the display, keeping only
+    /// the line that holds the cursor
+    fn erase_scrollback(&self) {}
+
+    /// Renders the scrollback and the visible portion of the
+    /// screen in the requested format
+    fn export_scrollback(&self, _format: ExportFormat) -> Future<String> {
+        Future::err(anyhow::anyhow!(
+            "this tab doesn't support exporting its scrollback"
+        ))
+    }
+
+    /// Returns the current working directory of the process
+    /// running in the tab, if it can be determined
+    fn get_current_working_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Returns the process that is in the foreground of the tab,
+    /// which is typically the program that the user is interacting
+    /// with, such as an editor, or the shell itself when it is idle.
+    /// Returns None if it cannot be determined, such as when the
+    /// process runs on another host.
+    fn get_foreground_process(&self) -> Option<ProcessInfo> {
+        None
+    }
+
+    /// Called when the output of the tab has reached EOF.  Returns true
+    /// if the program running in it has not yet been reaped, in which
+    /// case the mux checks again shortly before calling `hold_after_exit`.
+    fn exit_status_pending(&self) -> bool {
+        false
+    }
+
+    /// Called when the output of the tab has reached EOF, which means
+    /// that the program running in it has exited.  Returns true if
+    /// the tab should remain open so that its final output can be
+    /// seen, in which case the tab shows how the program exited.
+    fn hold_after_exit(&self, _host: &mut dyn TerminalHost) -> bool {
+        false
+    }
+
+    /// Overrides the `exit_behavior` from the configuration for this
+    /// tab.  Tabs whose program runs elsewhere, such as in a multiplexer
+    /// server, follow the configuration of that host instead.
+    fn set_exit_behavior(&self, _behavior: ExitBehavior) {}
+
+    /// Returns false if pasting multiple lines into the tab is likely
+    /// to run each line as a command, which is the case for a shell
+    /// that hasn't enabled bracketed paste mode.
+    fn is_multiline_paste_safe(&self) -> bool {
+        true
+    }
+
+    /// Returns the argv that was used to spawn the tab, or None
+    /// if it was spawned with the default program
+    fn get_spawn_argv(&self) -> Option<Vec<OsString>> {
+        None
+    }
+
     /// Returns the selection range adjusted to the viewport
     /// (eg: it has been normalized and had clip_to_viewport called
     /// on it prior to being returned)
@@ -76,12 +180,13 @@ pub trait Tab: Downcast {
             self.send_paste(&text)?;
         } else {
             // It's pretty heavy, so we trickle it into the pty
-            self.send_paste(&text[0..PASTE_CHUNK_SIZE])?;
+            let chunk = paste_chunk_len(&text);
+            self.send_paste(&text[0..chunk])?;
 
             let paste = Arc::new(Mutex::new(Paste {
                 tab_id: self.tab_id(),
                 text,
-                offset: PASTE_CHUNK_SIZE,
+                offset: chunk,
             }));
             schedule_next_paste(&paste);
         }
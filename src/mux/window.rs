@@ -1,4 +1,8 @@
+use crate::mux::splits::{PaneDirection, SplitDirection, SplitTree};
 use crate::mux::{Tab, TabId};
+use anyhow::{anyhow, ensure};
+use portable_pty::PtySize;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
 use term::Clipboard;
@@ -6,11 +10,29 @@ use term::Clipboard;
 static WIN_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 pub type WindowId = usize;
 
+/// A tab that has been removed from a window by `Window::detach_tab`,
+/// along with the panes and layout of its split, if any, so that it
+/// can be attached to another window by `Window::attach_tab`.
+pub struct DetachedTab {
+    tab: Rc<dyn Tab>,
+    split: Option<SplitTree>,
+    panes: Vec<Rc<dyn Tab>>,
+}
+
 pub struct Window {
     id: WindowId,
     tabs: Vec<Rc<dyn Tab>>,
     active: usize,
     clipboard: Option<Arc<dyn Clipboard>>,
+    /// The layouts of the tabs that have been split into panes.
+    /// The entry in `tabs` for a split tab is its active pane.
+    splits: Vec<SplitTree>,
+    /// All of the panes that belong to a split, including the
+    /// active pane of each split tab
+    panes: HashMap<TabId, Rc<dyn Tab>>,
+    /// The size most recently passed to resize; used to lay out
+    /// the panes of a split tab
+    size: Option<PtySize>,
 }
 
 impl Window {
@@ -20,11 +42,19 @@ impl Window {
             tabs: vec![],
             active: 0,
             clipboard: None,
+            splits: vec![],
+            panes: HashMap::new(),
+            size: None,
         }
     }
 
     pub fn set_clipboard(&mut self, clipboard: &Arc<dyn Clipboard>) {
         self.clipboard.replace(Arc::clone(clipboard));
+        // Tabs that were moved here from another window are still
+        // using the clipboard of that window
+        for tab in self.tabs.iter().chain(self.panes.values()) {
+            tab.set_clipboard(clipboard);
+        }
     }
 
     pub fn window_id(&self) -> WindowId {
@@ -80,8 +110,85 @@ impl Window {
         self.tabs.remove(idx)
     }
 
+    /// Moves the tab at index `from` so that it has index `to`.
+    /// The active tab remains active.
+    pub fn move_tab(&mut self, from: usize, to: usize) -> anyhow::Result<()> {
+        let len = self.tabs.len();
+        ensure!(
+            from < len && to < len,
+            "move_tab: index out of range; from={} to={} len={}",
+            from,
+            to,
+            len
+        );
+        let active_id = self.get_active().map(|tab| tab.tab_id());
+        let tab = self.remove_by_idx(from);
+        self.insert(to, &tab);
+        if let Some(idx) = active_id.and_then(|id| self.idx_by_id(id)) {
+            self.set_active(idx);
+        }
+        Ok(())
+    }
+
+    /// Removes the tab at `idx` from this window, taking its panes
+    /// with it.  The tab is not killed; pass it to `attach_tab` to
+    /// add it to another window.
+    pub fn detach_tab(&mut self, idx: usize) -> anyhow::Result<DetachedTab> {
+        ensure!(
+            idx < self.tabs.len(),
+            "detach_tab: no tab with index {}",
+            idx
+        );
+        let tab = self.tabs.remove(idx);
+        let (split, panes) = match self.split_idx_by_id(tab.tab_id()) {
+            Some(split_idx) => {
+                let split = self.splits.remove(split_idx);
+                let panes = split
+                    .pane_ids()
+                    .into_iter()
+                    .filter_map(|id| self.panes.remove(&id))
+                    .collect();
+                (Some(split), panes)
+            }
+            None => (None, vec![]),
+        };
+
+        let len = self.tabs.len();
+        if idx < self.active || (self.active >= len && len > 0) {
+            self.active -= 1;
+        }
+        if len > 0 {
+            self.make_active_panes_dirty();
+        }
+
+        Ok(DetachedTab { tab, split, panes })
+    }
+
+    /// Appends a tab that was removed from a window by `detach_tab`
+    /// to this window, resizes it to fit and makes it active.
+    pub fn attach_tab(&mut self, detached: DetachedTab) {
+        let DetachedTab { tab, split, panes } = detached;
+        self.push(&tab);
+        for pane in panes {
+            self.assign_clipboard_to_tab(&pane);
+            self.panes.insert(pane.tab_id(), pane);
+        }
+        if let Some(split) = split {
+            self.splits.push(split);
+        }
+        if let Some(size) = self.size {
+            self.resize(size);
+        }
+        let idx = self.tabs.len() - 1;
+        self.set_active(idx);
+        self.make_active_panes_dirty();
+    }
+
     pub fn remove_by_id(&mut self, id: TabId) -> bool {
-        if let Some(idx) = self.idx_by_id(id) {
+        if let Some(split_idx) = self.split_idx_by_id(id) {
+            self.remove_pane(split_idx, id);
+            true
+        } else if let Some(idx) = self.idx_by_id(id) {
             self.tabs.remove(idx);
             let len = self.tabs.len();
             if len > 0 && self.active == idx && idx >= len {
@@ -95,6 +202,31 @@ impl Window {
         }
     }
 
+    /// Removes a pane from a split; its neighbor takes over its space
+    /// and, if the pane was the active pane of its tab, the focus.
+    fn remove_pane(&mut self, split_idx: usize, id: TabId) {
+        let focus = self.splits[split_idx].remove(id);
+        self.panes.remove(&id);
+
+        if let (Some(idx), Some(focus)) = (self.idx_by_id(id), focus) {
+            if let Some(pane) = self.panes.get(&focus) {
+                self.tabs[idx] = Rc::clone(pane);
+            }
+        }
+
+        if self.splits[split_idx].num_panes() == 1 {
+            let split = self.splits.remove(split_idx);
+            for pane_id in split.pane_ids() {
+                self.panes.remove(&pane_id);
+            }
+        }
+
+        if let Some(size) = self.size {
+            self.resize(size);
+        }
+        self.make_active_panes_dirty();
+    }
+
     pub fn get_active(&self) -> Option<&Rc<dyn Tab>> {
         self.get_by_idx(self.active)
     }
@@ -117,16 +249,295 @@ impl Window {
         self.tabs.iter()
     }
 
-    pub fn prune_dead_tabs(&mut self, live_tab_ids: &[TabId]) {
-        let dead: Vec<TabId> = self
-            .tabs
+    fn split_idx_by_id(&self, id: TabId) -> Option<usize> {
+        self.splits.iter().position(|split| split.contains(id))
+    }
+
+    /// Returns the split layout of the active tab, if it has been split
+    pub fn get_active_split(&self) -> Option<&SplitTree> {
+        let tab = self.get_active()?;
+        self.splits
             .iter()
-            .filter_map(|tab| {
-                if tab.is_dead() {
-                    Some(tab.tab_id())
-                } else {
-                    None
+            .find(|split| split.contains(tab.tab_id()))
+    }
+
+    pub fn get_pane(&self, id: TabId) -> Option<&Rc<dyn Tab>> {
+        self.panes.get(&id)
+    }
+
+    /// Returns all of the panes that make up the active tab
+    pub fn get_active_panes(&self) -> Vec<Rc<dyn Tab>> {
+        self.get_panes_by_idx(self.active)
+    }
+
+    /// Returns all of the panes that make up the tab at `idx`
+    pub fn get_panes_by_idx(&self, idx: usize) -> Vec<Rc<dyn Tab>> {
+        let tab = match self.get_by_idx(idx) {
+            Some(tab) => tab,
+            None => return vec![],
+        };
+        match self.split_idx_by_id(tab.tab_id()) {
+            Some(split_idx) => self.splits[split_idx]
+                .pane_ids()
+                .into_iter()
+                .filter_map(|id| self.panes.get(&id).map(Rc::clone))
+                .collect(),
+            None => vec![Rc::clone(tab)],
+        }
+    }
+
+    /// Returns the panes of the active tab that are displayed;
+    /// while a pane is zoomed, it is the only one
+    pub fn get_visible_panes(&self) -> Vec<Rc<dyn Tab>> {
+        match self.get_active_split().and_then(SplitTree::zoomed) {
+            Some(id) => self.panes.get(&id).map(Rc::clone).into_iter().collect(),
+            None => self.get_active_panes(),
+        }
+    }
+
+    fn make_active_panes_dirty(&self) {
+        for pane in self.get_active_panes() {
+            pane.renderer().make_all_lines_dirty();
+        }
+    }
+
+    /// Moves `pane_id`, which must have already been added to this
+    /// window (eg: by Domain::spawn), into a split alongside the
+    /// active pane of the active tab, and makes it the active pane.
+    pub fn split_active(
+        &mut self,
+        pane_id: TabId,
+        direction: SplitDirection,
+    ) -> anyhow::Result<()> {
+        let idx = self
+            .idx_by_id(pane_id)
+            .ok_or_else(|| anyhow!("split_active: no such tab_id {}", pane_id))?;
+        let active = self
+            .get_active()
+            .map(Rc::clone)
+            .ok_or_else(|| anyhow!("split_active: window has no tabs"))?;
+        let active_id = active.tab_id();
+        ensure!(active_id != pane_id, "cannot split a pane with itself");
+
+        let pane = self.tabs.remove(idx);
+        if idx < self.active {
+            self.active -= 1;
+        }
+
+        match self.split_idx_by_id(active_id) {
+            Some(split_idx) => {
+                self.splits[split_idx].split(active_id, pane_id, direction);
+            }
+            None => {
+                let mut split = SplitTree::new(active_id);
+                split.split(active_id, pane_id, direction);
+                self.splits.push(split);
+                self.panes.insert(active_id, active);
+            }
+        }
+        self.panes.insert(pane_id, Rc::clone(&pane));
+        let active_idx = self.active;
+        self.tabs[active_idx] = pane;
+        self.make_active_panes_dirty();
+        Ok(())
+    }
+
+    /// Moves `pane_id`, which must have already been added to this
+    /// window, into a split alongside `existing_id`, which may be a
+    /// pane of any of the tabs in the window.  The active tab remains
+    /// active, but `pane_id` becomes the active pane of its tab.
+    pub fn split_pane(
+        &mut self,
+        existing_id: TabId,
+        pane_id: TabId,
+        direction: SplitDirection,
+    ) -> anyhow::Result<()> {
+        let existing_idx = match self.split_idx_by_id(existing_id) {
+            Some(split_idx) => self
+                .tabs
+                .iter()
+                .position(|tab| self.splits[split_idx].contains(tab.tab_id())),
+            None => self.idx_by_id(existing_id),
+        }
+        .ok_or_else(|| anyhow!("split_pane: no such tab_id {}", existing_id))?;
+        let pane_idx = self
+            .idx_by_id(pane_id)
+            .ok_or_else(|| anyhow!("split_pane: no such tab_id {}", pane_id))?;
+
+        let prior_active = self.active;
+        self.active = existing_idx;
+        self.activate_pane(existing_id);
+        let result = self.split_active(pane_id, direction);
+        self.active = if result.is_ok() && pane_idx < prior_active {
+            prior_active - 1
+        } else {
+            prior_active
+        };
+        result
+    }
+
+    /// Makes `pane_id` the active pane of the active tab.
+    /// Returns false if it is not part of the active tab.
+    pub fn activate_pane(&mut self, pane_id: TabId) -> bool {
+        match self.get_active_split() {
+            Some(split) if split.contains(pane_id) => {}
+            _ => return false,
+        }
+        let pane = match self.panes.get(&pane_id) {
+            Some(pane) => Rc::clone(pane),
+            None => return false,
+        };
+        let active_idx = self.active;
+        self.tabs[active_idx] = pane;
+        // Switching to another pane restores the layout so that it
+        // can be seen
+        let zoomed = self.get_active_split().and_then(SplitTree::zoomed);
+        if zoomed.is_some() && zoomed != Some(pane_id) {
+            self.set_active_zoom(None);
+        }
+        self.make_active_panes_dirty();
+        true
+    }
+
+    /// Toggles whether the active pane is zoomed to fill the whole
+    /// tab, resizing the panes to match.  Returns false if the active
+    /// tab hasn't been split.
+    pub fn toggle_zoom_active_pane(&mut self) -> bool {
+        let active_id = match self.get_active() {
+            Some(tab) => tab.tab_id(),
+            None => return false,
+        };
+        let zoomed = match self.get_active_split() {
+            Some(split) => split.zoomed(),
+            None => return false,
+        };
+        self.set_active_zoom(match zoomed {
+            Some(_) => None,
+            None => Some(active_id),
+        });
+        self.make_active_panes_dirty();
+        true
+    }
+
+    /// Sets the pane that is zoomed in the active tab, resizing the
+    /// panes to match
+    fn set_active_zoom(&mut self, zoom: Option<TabId>) {
+        let split_idx = match self
+            .get_active()
+            .and_then(|tab| self.split_idx_by_id(tab.tab_id()))
+        {
+            Some(idx) => idx,
+            None => return,
+        };
+        if self.splits[split_idx].zoomed() == zoom {
+            return;
+        }
+        self.splits[split_idx].set_zoomed(zoom);
+        if let Some(size) = self.size {
+            self.resize(size);
+        }
+    }
+
+    /// Activates the pane adjacent to the active pane in the specified
+    /// direction
+    pub fn activate_pane_direction(&mut self, direction: PaneDirection) -> bool {
+        let (active_id, size) = match (self.get_active(), self.size) {
+            (Some(tab), Some(size)) => (tab.tab_id(), size),
+            _ => return false,
+        };
+        let neighbor = match self.get_active_split() {
+            Some(split) => {
+                split.neighbor(active_id, direction, size.cols as usize, size.rows as usize)
+            }
+            None => None,
+        };
+        match neighbor {
+            Some(id) => self.activate_pane(id),
+            None => false,
+        }
+    }
+
+    /// Grows the active pane by `amount` cells in the specified
+    /// direction, then resizes the panes to match.  This ends any
+    /// zoom so that the effect can be seen.
+    pub fn adjust_active_pane_size(&mut self, direction: PaneDirection, amount: usize) -> bool {
+        let (active_id, size) = match (self.get_active(), self.size) {
+            (Some(tab), Some(size)) => (tab.tab_id(), size),
+            _ => return false,
+        };
+        let adjusted = match self.split_idx_by_id(active_id) {
+            Some(split_idx) => self.splits[split_idx].adjust_size(
+                active_id,
+                direction,
+                amount,
+                size.cols as usize,
+                size.rows as usize,
+            ),
+            None => false,
+        };
+        if adjusted {
+            self.set_active_zoom(None);
+            self.resize(size);
+            self.make_active_panes_dirty();
+        }
+        adjusted
+    }
+
+    /// Returns the size most recently passed to `resize`
+    pub fn get_size(&self) -> Option<PtySize> {
+        self.size
+    }
+
+    /// Resizes the tabs in this window to `size`.  Tabs that have
+    /// been split divide that space between their panes.
+    pub fn resize(&mut self, size: PtySize) {
+        self.size = Some(size);
+        for tab in &self.tabs {
+            match self
+                .splits
+                .iter()
+                .find(|split| split.contains(tab.tab_id()))
+            {
+                Some(split) => {
+                    for pos in split.layout(size.cols as usize, size.rows as usize).panes {
+                        if let Some(pane) = self.panes.get(&pos.tab_id) {
+                            pane.resize(pos.pty_size(size)).ok();
+                        }
+                    }
                 }
+                None => {
+                    tab.resize(size).ok();
+                }
+            }
+        }
+    }
+
+    /// Returns the ids of every tab and pane in this window
+    fn all_ids(&self) -> Vec<TabId> {
+        let mut ids: Vec<TabId> = self
+            .tabs
+            .iter()
+            .map(|tab| tab.tab_id())
+            .chain(self.panes.keys().cloned())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    fn get_tab_or_pane(&self, id: TabId) -> Option<&Rc<dyn Tab>> {
+        self.panes
+            .get(&id)
+            .or_else(|| self.idx_by_id(id).and_then(|idx| self.get_by_idx(idx)))
+    }
+
+    pub fn prune_dead_tabs(&mut self, live_tab_ids: &[TabId]) {
+        let dead: Vec<TabId> = self
+            .all_ids()
+            .into_iter()
+            .filter(|&id| match self.get_tab_or_pane(id) {
+                Some(tab) => tab.is_dead(),
+                None => false,
             })
             .collect();
         for tab_id in dead {
@@ -134,19 +545,9 @@ impl Window {
         }
 
         let dead: Vec<TabId> = self
-            .tabs
-            .iter()
-            .filter_map(|tab| {
-                if live_tab_ids
-                    .iter()
-                    .find(|&&id| id == tab.tab_id())
-                    .is_none()
-                {
-                    Some(tab.tab_id())
-                } else {
-                    None
-                }
-            })
+            .all_ids()
+            .into_iter()
+            .filter(|id| !live_tab_ids.contains(id))
             .collect();
         for tab_id in dead {
             self.remove_by_id(tab_id);
@@ -0,0 +1,218 @@
+//! Opens hyperlinks and paths with the application that the system
+//! associates with them, or with the `open_command` from the
+//! configuration.  Links are only opened if they are permitted by
+//! `allowed_hyperlink_schemes` and `denied_hyperlink_patterns`.
+use crate::config::configuration;
+use anyhow::{anyhow, bail, Context};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Opens `url`, if the configuration allows it
+pub fn open_url(url: &str) -> anyhow::Result<()> {
+    let config = configuration();
+    if !config.is_hyperlink_allowed(url) {
+        bail!(
+            "not opening {} because it is not permitted by \
+             allowed_hyperlink_schemes and denied_hyperlink_patterns",
+            url
+        );
+    }
+    if let Some(path) = file_url_to_path(url) {
+        return open_path(&path);
+    }
+    match &config.open_command {
+        Some(command) => run_command(command, OsStr::new(url)),
+        None => platform::open_url(url),
+    }
+}
+
+/// Opens the file or directory at `path`
+pub fn open_path(path: &Path) -> anyhow::Result<()> {
+    match &configuration().open_command {
+        Some(command) => run_command(command, path.as_os_str()),
+        None => platform::open_path(path),
+    }
+}
+
+/// Runs the `open_command`, passing it `arg` as its final argument
+fn run_command(command: &[String], arg: &OsStr) -> anyhow::Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("open_command is empty"))?;
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .arg(arg)
+        .spawn()
+        .with_context(|| format!("running open_command {:?}", command))?;
+    // Reap the command when it exits, without waiting for it here
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Returns the local path named by a `file:` URL.  URLs that name a
+/// file on some other host are left to the URL handler.
+fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    const PREFIX: &str = "file://";
+    if url.len() < PREFIX.len() || !url[..PREFIX.len()].eq_ignore_ascii_case(PREFIX) {
+        return None;
+    }
+    let rest = &url[PREFIX.len()..];
+    let slash = rest.find('/')?;
+    let (host, path) = rest.split_at(slash);
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return None;
+    }
+    let path = percent_decode(path)?;
+    // file:///C:/Windows names C:\Windows
+    #[cfg(windows)]
+    let path = path.trim_start_matches('/').replace('/', "\\");
+    Some(PathBuf::from(path))
+}
+
+/// Decodes the `%XX` escapes in `s`
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = s.get(idx + 1..idx + 3)?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use anyhow::ensure;
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    fn xdg_open(arg: &OsStr) -> anyhow::Result<()> {
+        let status = std::process::Command::new("xdg-open").arg(arg).status()?;
+        ensure!(status.success(), "xdg-open {:?} failed: {}", arg, status);
+        Ok(())
+    }
+
+    pub fn open_url(url: &str) -> anyhow::Result<()> {
+        xdg_open(OsStr::new(url))
+    }
+
+    pub fn open_path(path: &Path) -> anyhow::Result<()> {
+        xdg_open(path.as_os_str())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use anyhow::ensure;
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr::{null, null_mut};
+    use winapi::um::shellapi::ShellExecuteW;
+    use winapi::um::winuser::SW_SHOWNORMAL;
+
+    fn wide_string(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(std::iter::once(0)).collect()
+    }
+
+    fn shell_execute(target: &OsStr) -> anyhow::Result<()> {
+        let operation = wide_string(OsStr::new("open"));
+        let file = wide_string(target);
+        let result = unsafe {
+            ShellExecuteW(
+                null_mut(),
+                operation.as_ptr(),
+                file.as_ptr(),
+                null(),
+                null(),
+                SW_SHOWNORMAL,
+            )
+        };
+        // Values greater than 32 indicate success; smaller values
+        // are error codes
+        let code = result as usize;
+        ensure!(code > 32, "ShellExecuteW {:?} failed: {}", target, code);
+        Ok(())
+    }
+
+    pub fn open_url(url: &str) -> anyhow::Result<()> {
+        shell_execute(OsStr::new(url))
+    }
+
+    pub fn open_path(path: &Path) -> anyhow::Result<()> {
+        shell_execute(path.as_os_str())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::{anyhow, ensure};
+    use cocoa::base::{id, nil, NO};
+    use cocoa::foundation::NSString;
+    use objc::rc::{autoreleasepool, StrongPtr};
+    use objc::runtime::BOOL;
+    use objc::*;
+    use std::path::Path;
+
+    fn nsstring(s: &str) -> StrongPtr {
+        unsafe { StrongPtr::new(NSString::alloc(nil).init_str(s)) }
+    }
+
+    /// Asks NSWorkspace to open `url`, which is an NSURL
+    unsafe fn open(url: id) -> bool {
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let opened: BOOL = msg_send![workspace, openURL: url];
+        opened != NO
+    }
+
+    pub fn open_url(url: &str) -> anyhow::Result<()> {
+        autoreleasepool(|| unsafe {
+            let nsurl: id = msg_send![class!(NSURL), URLWithString: *nsstring(url)];
+            ensure!(nsurl != nil, "{} is not a valid URL", url);
+            ensure!(open(nsurl), "NSWorkspace failed to open {}", url);
+            Ok(())
+        })
+    }
+
+    pub fn open_path(path: &Path) -> anyhow::Result<()> {
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| anyhow!("{} is not a valid UTF-8 path", path.display()))?;
+        autoreleasepool(|| unsafe {
+            let nsurl: id = msg_send![class!(NSURL), fileURLWithPath: *nsstring(path_str)];
+            ensure!(open(nsurl), "NSWorkspace failed to open {}", path.display());
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn file_urls() {
+        assert_eq!(
+            file_url_to_path("file:///tmp/a%20b.txt"),
+            Some(PathBuf::from("/tmp/a b.txt"))
+        );
+        assert_eq!(
+            file_url_to_path("FILE://localhost/tmp"),
+            Some(PathBuf::from("/tmp"))
+        );
+        assert_eq!(file_url_to_path("file://otherhost/tmp"), None);
+        assert_eq!(file_url_to_path("https://example.com/"), None);
+        assert_eq!(file_url_to_path("file:///tmp/%zz"), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! A compact representation of a set of values, such as the rows
+//! that have changed in a terminal, as a list of ranges.
+use std::ops::{Add, Range};
+
+/// Holds a set of values as a sorted list of non-overlapping,
+/// non-adjacent ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangeSet<T> {
+    ranges: Vec<Range<T>>,
+}
+
+impl<T> RangeSet<T>
+where
+    T: Ord + Copy + Add<Output = T> + From<u8>,
+{
+    pub fn new() -> Self {
+        Self { ranges: vec![] }
+    }
+
+    /// Adds a single value to the set
+    pub fn add(&mut self, value: T) {
+        self.add_range(value..value + T::from(1));
+    }
+
+    /// Adds a range of values to the set, coalescing it with any
+    /// ranges that it overlaps or is adjacent to
+    pub fn add_range(&mut self, range: Range<T>) {
+        if range.start >= range.end {
+            return;
+        }
+
+        // Fast path for the common case of adding values in order
+        if let Some(last) = self.ranges.last_mut() {
+            if last.start <= range.start && range.start <= last.end {
+                last.end = last.end.max(range.end);
+                return;
+            }
+        }
+
+        let mut merged = range;
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut inserted = false;
+        for r in self.ranges.drain(..) {
+            if r.end < merged.start {
+                ranges.push(r);
+            } else if merged.end < r.start {
+                if !inserted {
+                    ranges.push(merged.clone());
+                    inserted = true;
+                }
+                ranges.push(r);
+            } else {
+                merged = merged.start.min(r.start)..merged.end.max(r.end);
+            }
+        }
+        if !inserted {
+            ranges.push(merged);
+        }
+        self.ranges = ranges;
+    }
+
+    /// Iterates the ranges in the set, in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = &Range<T>> {
+        self.ranges.iter()
+    }
+}
+
+impl<T> From<Range<T>> for RangeSet<T>
+where
+    T: Ord + Copy + Add<Output = T> + From<u8>,
+{
+    fn from(range: Range<T>) -> Self {
+        let mut set = Self::new();
+        set.add_range(range);
+        set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ranges(set: &RangeSet<isize>) -> Vec<Range<isize>> {
+        set.iter().cloned().collect()
+    }
+
+    #[test]
+    fn add_values() {
+        let mut set = RangeSet::new();
+        assert_eq!(ranges(&set), vec![]);
+        set.add(1);
+        set.add(2);
+        set.add(3);
+        set.add(6);
+        assert_eq!(ranges(&set), vec![1..4, 6..7]);
+
+        set.add(5);
+        set.add(4);
+        assert_eq!(ranges(&set), vec![1..7]);
+    }
+
+    #[test]
+    fn add_ranges() {
+        let mut set = RangeSet::new();
+        set.add_range(10..20);
+        set.add_range(0..2);
+        set.add_range(4..6);
+        assert_eq!(ranges(&set), vec![0..2, 4..6, 10..20]);
+
+        set.add_range(5..5);
+        assert_eq!(ranges(&set), vec![0..2, 4..6, 10..20]);
+
+        set.add_range(1..11);
+        assert_eq!(ranges(&set), vec![0..20]);
+
+        assert_eq!(ranges(&RangeSet::from(3..7)), vec![3..7]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Implements the `wezterm record` and `wezterm replay` subcommands,
+//! which capture a session in the asciicast format and play it back.
+use anyhow::{bail, Context};
+use portable_pty::cmdbuilder::CommandBuilder;
+use portable_pty::{PtySize, PtySystemSelection};
+use std::ffi::OsString;
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use termwiz::caps::Capabilities;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use termwiz::recording::{Header, Player, Recorder, Recording};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::buffered::BufferedTerminal;
+use termwiz::terminal::{new_terminal, Terminal};
+
+/// The data that is passed to the main thread while recording
+enum Message {
+    Input(Vec<u8>),
+    Output(Vec<u8>),
+    Exited,
+}
+
+/// Keeps the terminal in raw mode, returning it to cooked mode
+/// when dropped so that an error doesn't leave the tty raw
+struct RawMode<'a, T: Terminal> {
+    terminal: &'a mut T,
+}
+
+impl<'a, T: Terminal> RawMode<'a, T> {
+    fn new(terminal: &'a mut T) -> anyhow::Result<Self> {
+        terminal.set_raw_mode()?;
+        Ok(Self { terminal })
+    }
+}
+
+impl<'a, T: Terminal> Drop for RawMode<'a, T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.terminal.set_cooked_mode() {
+            log::error!("failed to restore cooked mode: {:#}", err);
+        }
+    }
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct RecordCommand {
+    /// Also record the keyboard input that is sent to the program
+    #[structopt(long = "record-input")]
+    record_input: bool,
+
+    /// The title to store in the recording
+    #[structopt(long = "title")]
+    title: Option<String>,
+
+    /// The file in which to save the recording
+    #[structopt(parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Instead of executing your shell, run PROG.
+    /// For example: `wezterm record demo.cast -- top` will record
+    /// a session running top.
+    #[structopt(parse(from_os_str))]
+    prog: Vec<OsString>,
+}
+
+impl RecordCommand {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let file = std::fs::File::create(&self.file_name)
+            .with_context(|| format!("creating {}", self.file_name.display()))?;
+
+        let caps = Capabilities::new_from_env()?;
+        let mut terminal = new_terminal(caps)?;
+        let size = terminal.get_screen_size()?;
+
+        let mut header = Header::new(size.cols, size.rows);
+        header.title = self.title.clone();
+        for name in &["SHELL", "TERM"] {
+            if let Ok(value) = std::env::var(name) {
+                header.env.insert(name.to_string(), value);
+            }
+        }
+        let mut recorder = Recorder::new(file, &header)?;
+
+        let pty_system = PtySystemSelection::default().get()?;
+        let pair = pty_system.openpty(PtySize {
+            rows: size.rows as u16,
+            cols: size.cols as u16,
+            pixel_width: size.xpixel as u16,
+            pixel_height: size.ypixel as u16,
+        })?;
+        let cmd = if self.prog.is_empty() {
+            CommandBuilder::new_default_prog()
+        } else {
+            let mut args = self.prog.iter();
+            let mut cmd = CommandBuilder::new(args.next().expect("executable name"));
+            cmd.args(args);
+            cmd
+        };
+        let mut child = pair.slave.spawn_command(cmd)?;
+        drop(pair.slave);
+
+        let mut reader = pair.master.try_clone_reader()?;
+        let mut master = pair.master;
+
+        let raw_mode = RawMode::new(&mut terminal)?;
+
+        let (tx, rx) = channel();
+
+        // This thread is left blocked in read when the program
+        // exits; it ends along with the process.
+        let input_tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut buf = [0u8; 1024];
+            while let Ok(len) = stdin.read(&mut buf) {
+                if len == 0 || input_tx.send(Message::Input(buf[..len].to_vec())).is_err() {
+                    break;
+                }
+            }
+        });
+
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 32 * 1024];
+            // The read fails rather than returning EOF on some
+            // systems once the program has exited
+            while let Ok(len) = reader.read(&mut buf) {
+                if len == 0 || tx.send(Message::Output(buf[..len].to_vec())).is_err() {
+                    break;
+                }
+            }
+            tx.send(Message::Exited).ok();
+        });
+
+        let mut stdout = std::io::stdout();
+        for message in rx {
+            match message {
+                Message::Input(data) => {
+                    master.write_all(&data)?;
+                    if self.record_input {
+                        recorder.input(&data)?;
+                    }
+                }
+                Message::Output(data) => {
+                    stdout.write_all(&data)?;
+                    stdout.flush()?;
+                    recorder.output(&data)?;
+                }
+                Message::Exited => break,
+            }
+        }
+
+        let status = child.wait()?;
+        drop(raw_mode);
+        eprintln!(
+            "\r\nrecording saved to {}{}",
+            self.file_name.display(),
+            if status.success() {
+                ""
+            } else {
+                " (the program exited with an error)"
+            }
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct ReplayCommand {
+    /// Play the recording faster (greater than 1) or slower
+    /// (less than 1) than it was recorded
+    #[structopt(long = "speed", default_value = "1.0")]
+    speed: f64,
+
+    /// Limit the pauses between output to at most this many seconds
+    #[structopt(long = "idle-time-limit")]
+    idle_time_limit: Option<f64>,
+
+    /// The recording to play.  Press `q` or CTRL-C to stop.
+    #[structopt(parse(from_os_str))]
+    file_name: PathBuf,
+}
+
+impl ReplayCommand {
+    pub fn run(&self) -> anyhow::Result<()> {
+        if self.speed <= 0.0 {
+            bail!("--speed must be greater than zero");
+        }
+        let file = std::fs::File::open(&self.file_name)
+            .with_context(|| format!("opening {}", self.file_name.display()))?;
+        let recording = Recording::parse(BufReader::new(file))
+            .with_context(|| format!("reading {}", self.file_name.display()))?;
+        let mut player = Player::new(recording);
+
+        let caps = Capabilities::new_from_env()?;
+        let mut terminal = new_terminal(caps)?;
+        terminal.set_raw_mode()?;
+        let mut buf = BufferedTerminal::new(terminal)?;
+        buf.add_change(Change::ClearScreen(Default::default()));
+        buf.flush()?;
+
+        let mut elapsed = 0.0;
+        while let Some(next) = player.next_event_time() {
+            let mut delay = (next - elapsed).max(0.0);
+            if let Some(limit) = self.idle_time_limit {
+                delay = delay.min(limit);
+            }
+            let deadline =
+                Instant::now() + Duration::from_micros((delay / self.speed * 1_000_000.0) as u64);
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                if let Some(event) = buf.terminal().poll_input(Some(deadline - now))? {
+                    if Self::is_quit(&event) {
+                        return Ok(());
+                    }
+                }
+            }
+            elapsed = next;
+            player.advance_to(next);
+            Self::render(&mut buf, &player);
+            buf.flush()?;
+        }
+
+        // Don't leave the recorded attributes in effect for whatever
+        // is output after we exit
+        buf.add_change(Change::AllAttributes(Default::default()));
+        buf.add_change("\r\n");
+        buf.flush()?;
+        Ok(())
+    }
+
+    fn is_quit(event: &InputEvent) -> bool {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('q'),
+                ..
+            }) => true,
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('c'),
+                modifiers,
+            }) => *modifiers == Modifiers::CTRL,
+            _ => false,
+        }
+    }
+
+    /// Draws as much of the recorded screen as fits in the terminal
+    fn render<T: Terminal>(buf: &mut BufferedTerminal<T>, player: &Player) {
+        let surface = player.surface();
+        let (width, height) = surface.dimensions();
+        let (term_width, term_height) = buf.dimensions();
+        let width = width.min(term_width);
+        let height = height.min(term_height);
+        let changes = buf.diff_region(0, 0, width, height, surface, 0, 0);
+        buf.add_changes(changes);
+
+        let (x, y) = surface.cursor_position();
+        buf.add_change(Change::CursorPosition {
+            x: Position::Absolute(x.min(term_width.saturating_sub(1))),
+            y: Position::Absolute(y.min(term_height.saturating_sub(1))),
+        });
+    }
+}
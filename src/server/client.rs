@@ -9,7 +9,7 @@ use crate::server::pollable::*;
 use crate::server::tab::ClientTab;
 use crate::server::UnixStream;
 use crate::ssh::ssh_connect;
-use anyhow::{anyhow, bail, Context, Error};
+use anyhow::{anyhow, bail, ensure, Context, Error};
 use crossbeam_channel::TryRecvError;
 use filedescriptor::{pollfd, AsRawSocketDescriptor};
 use log::info;
@@ -103,6 +103,25 @@ fn process_unilateral(local_domain_id: DomainId, decoded: DecodedPdu) -> anyhow:
     Ok(())
 }
 
+/// Runs `func` on the executor with the ClientDomain whose id is
+/// `local_domain_id`
+fn with_client_domain<F>(local_domain_id: DomainId, func: F)
+where
+    F: FnOnce(&ClientDomain) + Send + 'static,
+{
+    Future::with_executor(executor(), move || {
+        let mux = Mux::get().unwrap();
+        let client_domain = mux
+            .get_domain(local_domain_id)
+            .ok_or_else(|| anyhow!("no such domain {}", local_domain_id))?;
+        let client_domain = client_domain
+            .downcast_ref::<ClientDomain>()
+            .ok_or_else(|| anyhow!("domain {} is not a ClientDomain instance", local_domain_id))?;
+        func(client_domain);
+        Ok(())
+    });
+}
+
 fn client_thread(
     reconnectable: &mut Reconnectable,
     local_domain_id: DomainId,
@@ -375,7 +394,7 @@ impl Reconnectable {
     #[cfg(any(feature = "openssl", unix))]
     pub fn tls_connect(&mut self, tls_client: TlsDomainClient) -> anyhow::Result<()> {
         use crate::server::listener::read_bytes;
-        use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+        use openssl::ssl::{SslConnector, SslFiletype, SslMethod, SslVerifyMode};
         use openssl::x509::X509;
 
         openssl::init();
@@ -417,40 +436,61 @@ impl Reconnectable {
             }
         }
 
+        let pinned = match tls_client.pinned_server_cert.as_ref() {
+            Some(name) => Some(load_cert(name)?.to_der()?),
+            None => None,
+        };
+        if pinned.is_some() {
+            // The pinned certificate takes the place of the CA chain
+            connector.set_verify(SslVerifyMode::NONE);
+        }
+
         let connector = connector.build();
         let connector = connector
             .configure()?
-            .verify_hostname(!tls_client.accept_invalid_hostnames);
+            .verify_hostname(!tls_client.accept_invalid_hostnames && pinned.is_none());
 
         let stream = TcpStream::connect(remote_address)
             .with_context(|| format!("connecting to {}", remote_address))?;
         stream.set_nodelay(true)?;
 
-        let stream = Box::new(
-            connector
-                .connect(
-                    tls_client
-                        .expected_cn
-                        .as_ref()
-                        .map(String::as_str)
-                        .unwrap_or(remote_host_name),
-                    stream,
+        let stream = connector
+            .connect(
+                tls_client
+                    .expected_cn
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or(remote_host_name),
+                stream,
+            )
+            .with_context(|| {
+                format!(
+                    "SslConnector for {} with host name {}",
+                    remote_address, remote_host_name,
                 )
-                .with_context(|| {
-                    format!(
-                        "SslConnector for {} with host name {}",
-                        remote_address, remote_host_name,
-                    )
-                })?,
-        );
+            })?;
+
+        if let Some(pinned) = pinned {
+            let cert = stream
+                .ssl()
+                .peer_certificate()
+                .ok_or_else(|| anyhow!("{} presented no certificate", remote_address))?;
+            ensure!(
+                cert.to_der()? == pinned,
+                "the certificate presented by {} is not the pinned_server_cert",
+                remote_address
+            );
+        }
+
+        let stream = Box::new(stream);
         self.stream.replace(stream);
         Ok(())
     }
 
     #[cfg(not(any(feature = "openssl", unix)))]
     pub fn tls_connect(&mut self, tls_client: TlsDomainClient) -> anyhow::Result<()> {
-        use crate::server::listener::IdentitySource;
-        use native_tls::TlsConnector;
+        use crate::server::listener::{read_bytes, IdentitySource};
+        use native_tls::{Certificate, TlsConnector};
         use std::convert::TryInto;
 
         let remote_address = &tls_client.remote_address;
@@ -472,25 +512,45 @@ impl Reconnectable {
             chain: tls_client.pem_ca.clone(),
         };
 
+        let pinned = match tls_client.pinned_server_cert.as_ref() {
+            Some(name) => Some(Certificate::from_pem(&read_bytes(name)?)?.to_der()?),
+            None => None,
+        };
+
+        // The pinned certificate takes the place of the CA chain
         let connector = TlsConnector::builder()
             .identity(identity.try_into()?)
-            .danger_accept_invalid_hostnames(tls_client.accept_invalid_hostnames)
+            .danger_accept_invalid_hostnames(
+                tls_client.accept_invalid_hostnames || pinned.is_some(),
+            )
+            .danger_accept_invalid_certs(pinned.is_some())
             .build()?;
 
         let stream = TcpStream::connect(remote_address)
             .with_context(|| format!("connecting to {}", remote_address))?;
         stream.set_nodelay(true)?;
 
-        let stream = Box::new(
-            connector
-                .connect(remote_host_name, stream)
-                .with_context(|| {
-                    format!(
-                        "TlsConnector for {} with host name {}",
-                        remote_address, remote_host_name,
-                    )
-                })?,
-        );
+        let stream = connector
+            .connect(remote_host_name, stream)
+            .with_context(|| {
+                format!(
+                    "TlsConnector for {} with host name {}",
+                    remote_address, remote_host_name,
+                )
+            })?;
+
+        if let Some(pinned) = pinned {
+            let cert = stream
+                .peer_certificate()?
+                .ok_or_else(|| anyhow!("{} presented no certificate", remote_address))?;
+            ensure!(
+                cert.to_der()? == pinned,
+                "the certificate presented by {} is not the pinned_server_cert",
+                remote_address
+            );
+        }
+
+        let stream = Box::new(stream);
         self.stream.replace(stream);
         Ok(())
     }
@@ -520,6 +580,7 @@ impl Client {
                             Ok(_) => {
                                 backoff = BASE_INTERVAL;
                                 log::error!("Reconnected!");
+                                with_client_domain(local_domain_id, ClientDomain::perform_reattach);
                                 break;
                             }
                             Err(err) => {
@@ -534,20 +595,7 @@ impl Client {
                     }
                 }
             }
-            Future::with_executor(executor(), move || {
-                let mux = Mux::get().unwrap();
-                let client_domain = mux
-                    .get_domain(local_domain_id)
-                    .ok_or_else(|| anyhow!("no such domain {}", local_domain_id))?;
-                let client_domain =
-                    client_domain
-                        .downcast_ref::<ClientDomain>()
-                        .ok_or_else(|| {
-                            anyhow!("domain {} is not a ClientDomain instance", local_domain_id)
-                        })?;
-                client_domain.perform_detach();
-                Ok(())
-            });
+            with_client_domain(local_domain_id, ClientDomain::perform_detach);
         });
 
         Self {
@@ -615,4 +663,12 @@ impl Client {
     rpc!(mouse_event, SendMouseEvent, SendMouseEventResponse);
     rpc!(resize, Resize, UnitResponse);
     rpc!(get_tab_render_changes, GetTabRenderChanges, UnitResponse);
+    rpc!(activate_tab, ActivateTab, UnitResponse);
+    rpc!(kill_tab, KillTab, UnitResponse);
+    rpc!(erase_scrollback, EraseScrollback, UnitResponse);
+    rpc!(
+        export_scrollback,
+        ExportScrollback,
+        ExportScrollbackResponse
+    );
 }
@@ -20,6 +20,7 @@ use portable_pty::{CommandBuilder, PtySize};
 use serde_derive::*;
 use std::io::Cursor;
 use std::sync::Arc;
+use term::export::ExportFormat;
 use term::selection::SelectionRange;
 use termwiz::hyperlink::Hyperlink;
 use termwiz::surface::{Change, SequenceNo};
@@ -233,6 +234,11 @@ pdu! {
     GetTabRenderChangesResponse: 19,
     SetClipboard: 20,
     OpenURL: 21,
+    ActivateTab: 22,
+    KillTab: 23,
+    EraseScrollback: 24,
+    ExportScrollback: 25,
+    ExportScrollbackResponse: 26,
 }
 
 impl Pdu {
@@ -402,6 +408,32 @@ pub struct Resize {
     pub size: PtySize,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ActivateTab {
+    pub tab_id: TabId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct KillTab {
+    pub tab_id: TabId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct EraseScrollback {
+    pub tab_id: TabId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ExportScrollback {
+    pub tab_id: TabId,
+    pub format: ExportFormat,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ExportScrollbackResponse {
+    pub text: String,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct GetTabRenderChanges {
     pub tab_id: TabId,
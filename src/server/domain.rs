@@ -1,7 +1,7 @@
-use crate::config::{SshDomain, TlsDomainClient, UnixDomain};
+use crate::config::{command_from_argv, SshDomain, TlsDomainClient, UnixDomain};
 use crate::font::FontConfiguration;
-use crate::frontend::front_end;
-use crate::mux::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use crate::frontend::{executor, front_end};
+use crate::mux::domain::{alloc_domain_id, host_from_address, Domain, DomainId, DomainState};
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::window::WindowId;
 use crate::mux::Mux;
@@ -10,8 +10,9 @@ use crate::server::codec::Spawn;
 use crate::server::tab::ClientTab;
 use anyhow::{anyhow, bail};
 use portable_pty::{CommandBuilder, PtySize};
+use promise::Future;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
@@ -77,6 +78,47 @@ impl ClientDomainConfig {
             ClientDomainConfig::Ssh(ssh) => ssh.connect_automatically,
         }
     }
+
+    pub fn default_prog(&self) -> Option<&Vec<String>> {
+        match self {
+            ClientDomainConfig::Unix(unix) => unix.default_prog.as_ref(),
+            ClientDomainConfig::Tls(tls) => tls.default_prog.as_ref(),
+            ClientDomainConfig::Ssh(ssh) => ssh.default_prog.as_ref(),
+        }
+    }
+
+    pub fn set_environment_variables(&self) -> &HashMap<String, String> {
+        match self {
+            ClientDomainConfig::Unix(unix) => &unix.set_environment_variables,
+            ClientDomainConfig::Tls(tls) => &tls.set_environment_variables,
+            ClientDomainConfig::Ssh(ssh) => &ssh.set_environment_variables,
+        }
+    }
+
+    /// Applies the `default_prog` and `set_environment_variables`
+    /// of the domain to a command that is about to be spawned in it.
+    /// The server sets up the rest of the environment.
+    fn apply_spawn_overrides(&self, command: Option<CommandBuilder>) -> Option<CommandBuilder> {
+        let env = self.set_environment_variables();
+        let mut cmd = match (command, self.default_prog()) {
+            (Some(c), _) if !c.is_default_prog() => c,
+            (command, Some(prog)) => {
+                let mut cmd = command_from_argv(prog);
+                if let Some(cwd) = command.as_ref().and_then(|c| c.get_cwd()) {
+                    cmd.cwd(cwd);
+                }
+                cmd
+            }
+            (None, None) if env.is_empty() => return None,
+            (command, None) => command.unwrap_or_else(CommandBuilder::new_default_prog),
+        };
+        for (key, value) in env {
+            if cmd.get_env(key).is_none() {
+                cmd.env(key, value);
+            }
+        }
+        Some(cmd)
+    }
 }
 
 impl ClientInner {
@@ -123,6 +165,36 @@ impl ClientDomain {
         mux.domain_was_detached(self.local_domain_id);
     }
 
+    /// Called when the client has reconnected to the server after
+    /// losing its connection.  The server keeps running the programs
+    /// in the meantime, so each tab requests its screen afresh, which
+    /// replays the output that it missed.  Tabs that the server no
+    /// longer has, such as when it was restarted, are marked as dead.
+    pub fn perform_reattach(&self) {
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return,
+        };
+        let local_domain_id = self.local_domain_id;
+        inner.client.list_tabs().then(move |result| {
+            let remote_tabs: HashSet<TabId> =
+                result?.tabs.iter().map(|entry| entry.tab_id).collect();
+            Future::with_executor(executor(), move || {
+                let mux = Mux::get().unwrap();
+                for tab in mux.iter_tabs() {
+                    if tab.domain_id() != local_domain_id {
+                        continue;
+                    }
+                    if let Some(tab) = tab.downcast_ref::<ClientTab>() {
+                        tab.reattach(remote_tabs.contains(&tab.remote_tab_id()));
+                    }
+                }
+                Ok(())
+            });
+            Ok(())
+        });
+    }
+
     pub fn remote_to_local_tab_id(&self, remote_tab_id: TabId) -> Option<TabId> {
         let inner = self.inner()?;
         let mut tab_map = inner.remote_to_local_tab.lock().unwrap();
@@ -171,7 +243,7 @@ impl Domain for ClientDomain {
                     domain_id: inner.remote_domain_id,
                     window_id: inner.local_to_remote_window(window),
                     size,
-                    command,
+                    command: self.config.apply_spawn_overrides(command),
                 })
                 .wait()?;
 
@@ -248,4 +320,13 @@ impl Domain for ClientDomain {
             DomainState::Detached
         }
     }
+
+    fn remote_host(&self) -> Option<String> {
+        let address = match &self.config {
+            ClientDomainConfig::Unix(_) => return None,
+            ClientDomainConfig::Tls(tls) => &tls.remote_address,
+            ClientDomainConfig::Ssh(ssh) => &ssh.remote_address,
+        };
+        Some(host_from_address(address).to_string())
+    }
 }
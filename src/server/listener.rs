@@ -3,6 +3,7 @@ use crate::create_user_owned_dirs;
 use crate::frontend::executor;
 use crate::mux::tab::{Tab, TabId};
 use crate::mux::{Mux, MuxNotification, MuxSubscriber};
+use crate::rangeset::RangeSet;
 use crate::ratelim::RateLimiter;
 use crate::server::codec::*;
 use crate::server::pollable::*;
@@ -20,12 +21,14 @@ use std::convert::TryFrom;
 use std::fs::remove_file;
 use std::io::Read;
 use std::net::TcpListener;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Instant;
 use term::terminal::Clipboard;
+use term::StableRowIndex;
 use termwiz::surface::{Change, Position, SequenceNo, Surface};
 
 struct LocalListener {
@@ -298,8 +301,10 @@ mod ossl {
                         match acceptor.accept(stream) {
                             Ok(stream) => {
                                 if let Err(err) = Self::verify_peer_cert(&stream) {
+                                    // Keep listening, so that legitimate
+                                    // clients can still connect and reattach
                                     error!("problem with peer cert: {}", err);
-                                    break;
+                                    continue;
                                 }
 
                                 Future::with_executor(executor(), move || {
@@ -412,6 +417,9 @@ fn maybe_push_tab_changes(
 struct ClientSurfaceState {
     surface: Surface,
     last_seq: SequenceNo,
+    /// The viewport rows and renderable sequence number as of the
+    /// last update of the surface
+    last_render: Option<(Range<StableRowIndex>, SequenceNo)>,
     push_limiter: RateLimiter,
     update_limiter: RateLimiter,
 }
@@ -425,6 +433,7 @@ impl ClientSurfaceState {
         Self {
             surface,
             last_seq: 0,
+            last_render: None,
             push_limiter,
             update_limiter,
         }
@@ -442,7 +451,7 @@ impl ClientSurfaceState {
 
             if (rows != surface_height) || (cols != surface_width) {
                 self.surface.resize(cols, rows);
-                renderable.make_all_lines_dirty();
+                self.last_render = None;
             }
 
             let (x, y) = self.surface.cursor_position();
@@ -460,9 +469,21 @@ impl ClientSurfaceState {
 
             let mut changes = vec![];
 
-            for (line_idx, line, _selrange) in renderable.get_dirty_lines() {
-                changes.append(&mut self.surface.diff_against_numbered_line(line_idx, &line));
+            let seqno = renderable.get_current_seqno();
+            let viewport = renderable.get_viewport_rows();
+            let dirty = match self.last_render.take() {
+                Some((rows, last_seqno)) if rows == viewport => {
+                    renderable.get_dirty_lines(viewport.clone(), last_seqno)
+                }
+                _ => RangeSet::from(viewport.clone()),
+            };
+            for range in dirty.iter() {
+                for (row, line, _selrange) in renderable.get_lines(range.clone()) {
+                    let line_idx = (row - viewport.start) as usize;
+                    changes.append(&mut self.surface.diff_against_numbered_line(line_idx, &line));
+                }
             }
+            self.last_render = Some((viewport, seqno));
 
             self.surface.add_changes(changes);
         }
@@ -586,7 +607,10 @@ impl<S: ReadAndWrite> ClientSession<S> {
                 match self.mux_rx.try_recv() {
                     Ok(notif) => match notif {
                         // Coalesce multiple TabOutputs for the same tab
-                        MuxNotification::TabOutput(tab_id) => tabs_to_output.insert(tab_id),
+                        MuxNotification::TabOutput(tab_id) => {
+                            tabs_to_output.insert(tab_id);
+                        }
+                        MuxNotification::Bell(_) => {}
                     },
                     Err(TryRecvError::Empty) => break,
                     Err(TryRecvError::Disconnected) => bail!("ClientSession was destroyed"),
@@ -779,6 +803,60 @@ impl<S: ReadAndWrite> ClientSession<S> {
                 }
             }),
 
+            Pdu::ActivateTab(ActivateTab { tab_id }) => {
+                Future::with_executor(executor(), move || {
+                    let mux = Mux::get().unwrap();
+                    let window_id = mux
+                        .window_containing_tab(tab_id)
+                        .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                    let mut window = mux
+                        .get_window_mut(window_id)
+                        .ok_or_else(|| anyhow!("no such window {}", window_id))?;
+                    let idx = window
+                        .idx_by_id(tab_id)
+                        .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                    window.set_active(idx);
+                    Ok(Pdu::UnitResponse(UnitResponse {}))
+                })
+            }
+
+            Pdu::KillTab(KillTab { tab_id }) => Future::with_executor(executor(), move || {
+                let mux = Mux::get().unwrap();
+                mux.get_tab(tab_id)
+                    .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                // Removing the tab from the mux also removes it from its
+                // window; dropping it kills the process running in it
+                mux.remove_tab(tab_id);
+                Ok(Pdu::UnitResponse(UnitResponse {}))
+            }),
+
+            Pdu::EraseScrollback(EraseScrollback { tab_id }) => {
+                let surfaces = Arc::clone(&self.surfaces_by_tab);
+                let sender = self.to_write_tx.clone();
+                Future::with_executor(executor(), move || {
+                    let mux = Mux::get().unwrap();
+                    let tab = mux
+                        .get_tab(tab_id)
+                        .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                    tab.erase_scrollback();
+                    maybe_push_tab_changes(&surfaces, &tab, sender)?;
+                    Ok(Pdu::UnitResponse(UnitResponse {}))
+                })
+            }
+
+            Pdu::ExportScrollback(ExportScrollback { tab_id, format }) => {
+                Future::with_executor(executor(), move || {
+                    let mux = Mux::get().unwrap();
+                    let tab = mux
+                        .get_tab(tab_id)
+                        .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+                    let text = tab.export_scrollback(format).wait()?;
+                    Ok(Pdu::ExportScrollbackResponse(ExportScrollbackResponse {
+                        text,
+                    }))
+                })
+            }
+
             Pdu::GetTabRenderChanges(GetTabRenderChanges { tab_id, .. }) => {
                 let surfaces = Arc::clone(&self.surfaces_by_tab);
                 let sender = self.to_write_tx.clone();
@@ -800,6 +878,7 @@ impl<S: ReadAndWrite> ClientSession<S> {
             | Pdu::OpenURL { .. }
             | Pdu::SpawnResponse { .. }
             | Pdu::GetTabRenderChangesResponse { .. }
+            | Pdu::ExportScrollbackResponse { .. }
             | Pdu::UnitResponse { .. }
             | Pdu::ErrorResponse { .. } => {
                 Future::err(anyhow!("expected a request, got {:?}", pdu))
@@ -3,6 +3,7 @@ use crate::frontend::executor;
 use crate::mux::domain::DomainId;
 use crate::mux::renderable::Renderable;
 use crate::mux::tab::{alloc_tab_id, Tab, TabId};
+use crate::rangeset::RangeSet;
 use crate::server::client::Client;
 use crate::server::codec::*;
 use crate::server::domain::ClientInner;
@@ -20,8 +21,9 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use term::color::ColorPalette;
+use term::export::ExportFormat;
 use term::selection::SelectionRange;
-use term::{Clipboard, CursorPosition, Line, VisibleRowIndex};
+use term::{Clipboard, CursorPosition, Line, StableRowIndex, VisibleRowIndex};
 use term::{KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind, TerminalHost};
 use termwiz::hyperlink::Hyperlink;
 use termwiz::input::KeyEvent;
@@ -134,6 +136,8 @@ pub struct ClientTab {
     reader: Pipe,
     mouse: Arc<Mutex<MouseState>>,
     clipboard: RefCell<Option<Arc<dyn Clipboard>>>,
+    user_title: RefCell<Option<String>>,
+    profile: RefCell<Option<String>>,
 }
 
 impl ClientTab {
@@ -167,6 +171,7 @@ impl ClientTab {
                 surface: Surface::new(size.cols as usize, size.rows as usize),
                 remote_sequence: 0,
                 local_sequence: 0,
+                seqno: 0,
                 selection_range,
                 something_changed,
                 highlight,
@@ -184,6 +189,8 @@ impl ClientTab {
             writer: RefCell::new(writer),
             reader,
             clipboard: RefCell::new(None),
+            user_title: RefCell::new(None),
+            profile: RefCell::new(None),
         }
     }
 
@@ -212,9 +219,8 @@ impl ClientTab {
                 // capture (like the clipboard) so that we can propagate
                 // the click back to the ultimate client, but for now
                 // we just do a single stage
-                match open::that(&url) {
-                    Ok(_) => {}
-                    Err(err) => error!("failed to open {}: {:?}", url, err),
+                if let Err(err) = crate::open::open_url(&url) {
+                    error!("failed to open {}: {:?}", url, err);
                 }
             }
             _ => bail!("unhandled unilateral pdu: {:?}", pdu),
@@ -225,6 +231,24 @@ impl ClientTab {
     pub fn remote_tab_id(&self) -> TabId {
         self.remote_tab_id
     }
+
+    /// Called when the client has reconnected to the server.  If the
+    /// tab still `exists` on the server, its screen is requested
+    /// promptly; the new session on the server starts by sending all
+    /// of it, replacing what was shown before the disconnect.
+    pub fn reattach(&self, exists: bool) {
+        let renderable = self.renderable.borrow();
+        let mut inner = renderable.inner.borrow_mut();
+        if !exists {
+            inner.dead = true;
+            return;
+        }
+        // Any poll that was in flight was lost along with the
+        // connection, and the sequence numbers start over
+        inner.poll_future = None;
+        inner.poll_interval = BASE_POLL_INTERVAL;
+        inner.remote_sequence = 0;
+    }
 }
 
 impl Tab for ClientTab {
@@ -240,11 +264,42 @@ impl Tab for ClientTab {
     }
 
     fn get_title(&self) -> String {
+        if let Some(title) = self.user_title.borrow().as_ref() {
+            return title.clone();
+        }
         let renderable = self.renderable.borrow();
         let surface = &renderable.inner.borrow().surface;
         surface.title().to_string()
     }
 
+    fn set_user_title(&self, title: Option<String>) {
+        *self.user_title.borrow_mut() = title;
+    }
+
+    fn profile(&self) -> Option<String> {
+        self.profile.borrow().clone()
+    }
+
+    fn set_profile(&self, profile: Option<String>) {
+        *self.profile.borrow_mut() = profile;
+    }
+
+    fn erase_scrollback(&self) {
+        self.client.client.erase_scrollback(EraseScrollback {
+            tab_id: self.remote_tab_id,
+        });
+    }
+
+    fn export_scrollback(&self, format: ExportFormat) -> Future<String> {
+        self.client
+            .client
+            .export_scrollback(ExportScrollback {
+                tab_id: self.remote_tab_id,
+                format,
+            })
+            .map(|response| Future::ok(response.text))
+    }
+
     fn send_paste(&self, text: &str) -> anyhow::Result<()> {
         self.client.client.send_paste(SendPaste {
             tab_id: self.remote_tab_id,
@@ -303,11 +358,8 @@ impl Tab for ClientTab {
 
     fn palette(&self) -> ColorPalette {
         configuration()
-            .colors
-            .as_ref()
-            .cloned()
-            .map(Into::into)
-            .unwrap_or_else(ColorPalette::default)
+            .resolved_palette_for_profile(self.profile.borrow().as_deref())
+            .into()
     }
 
     fn domain_id(&self) -> DomainId {
@@ -340,6 +392,9 @@ struct RenderableInner {
     surface: Surface,
     remote_sequence: SequenceNo,
     local_sequence: SequenceNo,
+    /// Advanced each time that the surface changes; every line is
+    /// considered to have changed at that point
+    seqno: SequenceNo,
     poll_interval: Duration,
     selection_range: Arc<Mutex<Option<SelectionRange>>>,
     something_changed: Arc<AtomicBool>,
@@ -411,44 +466,70 @@ impl Renderable for RenderableState {
         }
     }
 
-    fn get_dirty_lines(&self) -> Vec<(usize, Cow<Line>, Range<usize>)> {
+    fn get_current_seqno(&mut self) -> SequenceNo {
         let mut inner = self.inner.borrow_mut();
-        let seq = inner.surface.current_seqno();
-        inner.surface.flush_changes_older_than(seq);
+        if let Err(err) = inner.poll() {
+            // We allow for BrokenPromise here for now; for a TLS backed
+            // session it indicates that we'll retry.  For a local unix
+            // domain session it is terminal... but we will detect that
+            // terminal condition elsewhere
+            if let Err(err) = err.downcast::<BrokenPromise>() {
+                log::error!("remote tab poll failed: {}, marking as dead", err);
+                inner.dead = true;
+            }
+        }
+        if inner.something_changed.swap(false, Ordering::SeqCst)
+            || inner.surface.has_changes(inner.local_sequence)
+        {
+            let seq = inner.surface.current_seqno();
+            inner.surface.flush_changes_older_than(seq);
+            inner.local_sequence = seq;
+            inner.seqno += 1;
+        }
+        inner.seqno
+    }
+
+    fn get_dirty_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+        seqno: SequenceNo,
+    ) -> RangeSet<StableRowIndex> {
+        if seqno < self.inner.borrow().seqno {
+            let rows = self.get_viewport_rows();
+            RangeSet::from(lines.start.max(rows.start)..lines.end.min(rows.end))
+        } else {
+            RangeSet::new()
+        }
+    }
+
+    fn get_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+    ) -> Vec<(StableRowIndex, Cow<Line>, Range<usize>)> {
+        let inner = self.inner.borrow();
         let selection = *inner.selection_range.lock().unwrap();
-        inner.something_changed.store(false, Ordering::SeqCst);
-        inner.local_sequence = seq;
         inner
             .surface
             .screen_lines()
             .into_iter()
             .enumerate()
-            .map(|(idx, line)| {
+            .map(|(idx, line)| (idx as StableRowIndex, line))
+            .filter(|(row, _)| lines.contains(row))
+            .map(|(row, line)| {
                 let r = match selection {
                     None => 0..0,
-                    Some(sel) => sel.normalize().cols_for_row(idx as i32),
+                    Some(sel) => sel.normalize().cols_for_row(row as i32),
                 };
-                (idx, Cow::Owned(line.into_owned()), r)
+                (row, Cow::Owned(line.into_owned()), r)
             })
             .collect()
     }
 
-    fn has_dirty_lines(&self) -> bool {
-        let mut inner = self.inner.borrow_mut();
-        if let Err(err) = inner.poll() {
-            // We allow for BrokenPromise here for now; for a TLS backed
-            // session it indicates that we'll retry.  For a local unix
-            // domain session it is terminal... but we will detect that
-            // terminal condition elsewhere
-            if let Err(err) = err.downcast::<BrokenPromise>() {
-                log::error!("remote tab poll failed: {}, marking as dead", err);
-                inner.dead = true;
-            }
-        }
-        if inner.something_changed.load(Ordering::SeqCst) {
-            return true;
-        }
-        inner.surface.has_changes(inner.local_sequence)
+    fn get_viewport_rows(&self) -> Range<StableRowIndex> {
+        // The protocol doesn't yet convey the scrollback, so the
+        // rows are simply those of the remote screen
+        let (rows, _cols) = self.physical_dimensions();
+        0..rows as StableRowIndex
     }
 
     fn make_all_lines_dirty(&mut self) {
@@ -458,8 +539,6 @@ impl Renderable for RenderableState {
             .store(true, Ordering::SeqCst);
     }
 
-    fn clean_dirty_lines(&mut self) {}
-
     fn current_highlight(&self) -> Option<Arc<Hyperlink>> {
         self.inner
             .borrow()
@@ -482,6 +561,10 @@ impl Renderable for RenderableState {
         (0, rows)
     }
     fn set_viewport_position(&mut self, _: VisibleRowIndex) {}
+
+    fn get_prompt_rows(&self) -> Vec<StableRowIndex> {
+        vec![]
+    }
 }
 
 struct TabWriter {
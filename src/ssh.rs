@@ -1,5 +1,5 @@
 use crate::localtab::LocalTab;
-use crate::mux::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use crate::mux::domain::{alloc_domain_id, host_from_address, Domain, DomainId, DomainState};
 use crate::mux::tab::Tab;
 use crate::mux::window::WindowId;
 use crate::mux::Mux;
@@ -334,20 +334,29 @@ impl Domain for RemoteSshDomain {
             Some(c) => c,
             None => CommandBuilder::new_default_prog(),
         };
+        let argv = cmd.get_argv().clone();
         let pair = self.pty_system.openpty(size)?;
         let child = pair.slave.spawn_command(cmd)?;
         log::info!("spawned: {:?}", child);
 
+        let config = std::sync::Arc::new(crate::config::TermConfig::new());
         let terminal = term::Terminal::new(
             size.rows as usize,
             size.cols as usize,
             size.pixel_width as usize,
             size.pixel_height as usize,
-            std::sync::Arc::new(crate::config::TermConfig {}),
+            config.clone(),
         );
 
         let mux = Mux::get().unwrap();
-        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(terminal, child, pair.master, self.id));
+        let tab: Rc<dyn Tab> = Rc::new(LocalTab::new(
+            terminal,
+            config,
+            child,
+            pair.master,
+            self.id,
+            argv,
+        ));
 
         mux.add_tab(&tab)?;
         mux.add_tab_to_window(&tab, window)?;
@@ -374,4 +383,9 @@ impl Domain for RemoteSshDomain {
     fn state(&self) -> DomainState {
         DomainState::Attached
     }
+
+    fn remote_host(&self) -> Option<String> {
+        // The domain is named for the user@host:port that it connects to
+        Some(host_from_address(&self.name).to_string())
+    }
 }
@@ -10,6 +10,7 @@ use crate::mux::renderable::Renderable;
 use crate::mux::tab::{alloc_tab_id, Tab, TabId};
 use crate::mux::window::WindowId;
 use crate::mux::Mux;
+use crate::rangeset::RangeSet;
 use anyhow::{bail, Error};
 use crossbeam_channel::{unbounded as channel, Receiver, Sender};
 use filedescriptor::Pipe;
@@ -26,7 +27,8 @@ use std::time::Duration;
 use term::color::ColorPalette;
 use term::selection::SelectionRange;
 use term::{
-    CursorPosition, KeyCode, KeyModifiers, Line, MouseEvent, TerminalHost, VisibleRowIndex,
+    CursorPosition, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex, TerminalHost,
+    VisibleRowIndex,
 };
 use termwiz::hyperlink::Hyperlink;
 use termwiz::input::{InputEvent, KeyEvent};
@@ -40,6 +42,9 @@ struct RenderableInner {
     something_changed: Arc<AtomicBool>,
     highlight: Arc<Mutex<Option<Arc<Hyperlink>>>>,
     local_sequence: SequenceNo,
+    /// Advanced each time that the surface changes; every line is
+    /// considered to have changed at that point
+    seqno: SequenceNo,
     dead: bool,
     render_rx: Receiver<Vec<Change>>,
     input_tx: Sender<InputEvent>,
@@ -76,29 +81,7 @@ impl Renderable for RenderableState {
         }
     }
 
-    fn get_dirty_lines(&self) -> Vec<(usize, Cow<Line>, Range<usize>)> {
-        let mut inner = self.inner.borrow_mut();
-        let seq = inner.surface.current_seqno();
-        inner.surface.flush_changes_older_than(seq);
-        let selection = *inner.selection_range.lock().unwrap();
-        inner.something_changed.store(false, Ordering::SeqCst);
-        inner.local_sequence = seq;
-        inner
-            .surface
-            .screen_lines()
-            .into_iter()
-            .enumerate()
-            .map(|(idx, line)| {
-                let r = match selection {
-                    None => 0..0,
-                    Some(sel) => sel.normalize().cols_for_row(idx as i32),
-                };
-                (idx, Cow::Owned(line.into_owned()), r)
-            })
-            .collect()
-    }
-
-    fn has_dirty_lines(&self) -> bool {
+    fn get_current_seqno(&mut self) -> SequenceNo {
         let mut inner = self.inner.borrow_mut();
 
         loop {
@@ -115,10 +98,56 @@ impl Renderable for RenderableState {
             }
         }
 
-        if inner.something_changed.load(Ordering::SeqCst) {
-            return true;
+        if inner.something_changed.swap(false, Ordering::SeqCst)
+            || inner.surface.has_changes(inner.local_sequence)
+        {
+            let seq = inner.surface.current_seqno();
+            inner.surface.flush_changes_older_than(seq);
+            inner.local_sequence = seq;
+            inner.seqno += 1;
+        }
+        inner.seqno
+    }
+
+    fn get_dirty_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+        seqno: SequenceNo,
+    ) -> RangeSet<StableRowIndex> {
+        if seqno < self.inner.borrow().seqno {
+            let rows = self.get_viewport_rows();
+            RangeSet::from(lines.start.max(rows.start)..lines.end.min(rows.end))
+        } else {
+            RangeSet::new()
         }
-        inner.surface.has_changes(inner.local_sequence)
+    }
+
+    fn get_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+    ) -> Vec<(StableRowIndex, Cow<Line>, Range<usize>)> {
+        let inner = self.inner.borrow();
+        let selection = *inner.selection_range.lock().unwrap();
+        inner
+            .surface
+            .screen_lines()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, line)| (idx as StableRowIndex, line))
+            .filter(|(row, _)| lines.contains(row))
+            .map(|(row, line)| {
+                let r = match selection {
+                    None => 0..0,
+                    Some(sel) => sel.normalize().cols_for_row(row as i32),
+                };
+                (row, Cow::Owned(line.into_owned()), r)
+            })
+            .collect()
+    }
+
+    fn get_viewport_rows(&self) -> Range<StableRowIndex> {
+        let (rows, _cols) = self.physical_dimensions();
+        0..rows as StableRowIndex
     }
 
     fn make_all_lines_dirty(&mut self) {
@@ -128,8 +157,6 @@ impl Renderable for RenderableState {
             .store(true, Ordering::SeqCst);
     }
 
-    fn clean_dirty_lines(&mut self) {}
-
     fn current_highlight(&self) -> Option<Arc<Hyperlink>> {
         self.inner
             .borrow()
@@ -150,6 +177,10 @@ impl Renderable for RenderableState {
         (0, rows)
     }
     fn set_viewport_position(&mut self, _: VisibleRowIndex) {}
+
+    fn get_prompt_rows(&self) -> Vec<StableRowIndex> {
+        vec![]
+    }
 }
 
 struct TermWizTerminalDomain {
@@ -257,12 +288,13 @@ impl Tab for TermWizTerminalTab {
     }
 
     fn resize(&self, size: PtySize) -> anyhow::Result<()> {
-        self.renderable
-            .borrow()
-            .inner
-            .borrow_mut()
-            .surface
-            .resize(size.cols as usize, size.rows as usize);
+        let renderable = self.renderable.borrow();
+        let mut inner = renderable.inner.borrow_mut();
+        let cols = size.cols as usize;
+        let rows = size.rows as usize;
+        inner.surface.resize(cols, rows);
+        // Let the applet know, so that it can re-render to fit
+        inner.input_tx.send(InputEvent::Resized { cols, rows })?;
         Ok(())
     }
 
@@ -403,19 +435,7 @@ pub fn run<T: Send + 'static, F: Send + 'static + Fn(TermWizTerminal) -> anyhow:
     height: usize,
     f: F,
 ) -> Future<T> {
-    let (render_tx, render_rx) = channel();
-    let (input_tx, input_rx) = channel();
-
-    let tw_term = TermWizTerminal {
-        render_tx,
-        input_rx,
-        screen_size: ScreenSize {
-            cols: width,
-            rows: height,
-            xpixel: 0,
-            ypixel: 0,
-        },
-    };
+    let (tw_term, inner) = new_terminal(width, height);
 
     let mut promise = Promise::new();
     let future = promise.get_future().expect("just made the promise");
@@ -429,17 +449,6 @@ pub fn run<T: Send + 'static, F: Send + 'static + Fn(TermWizTerminal) -> anyhow:
 
         let window_id = mux.new_empty_window();
 
-        let inner = RenderableInner {
-            surface: Surface::new(width, height),
-            highlight: Arc::new(Mutex::new(None)),
-            local_sequence: 0,
-            dead: false,
-            something_changed: Arc::new(AtomicBool::new(false)),
-            selection_range: Arc::new(Mutex::new(None)),
-            input_tx,
-            render_rx,
-        };
-
         let tab: Rc<dyn Tab> = Rc::new(TermWizTerminalTab::new(domain.domain_id(), inner));
 
         mux.add_tab(&tab)?;
@@ -460,6 +469,48 @@ pub fn run<T: Send + 'static, F: Send + 'static + Fn(TermWizTerminal) -> anyhow:
     future
 }
 
+/// Constructs a tab hosting a termwiz Terminal without adding it to
+/// the mux or to a window, so that the caller can display it over
+/// one of its own tabs.  The Terminal is expected to be moved to a
+/// thread that runs the applet; the tab is dead once the Terminal
+/// has been dropped.
+/// This must be called on the gui thread.
+pub fn allocate(width: usize, height: usize) -> (TermWizTerminal, Rc<dyn Tab>) {
+    let (tw_term, inner) = new_terminal(width, height);
+    let tab: Rc<dyn Tab> = Rc::new(TermWizTerminalTab::new(alloc_domain_id(), inner));
+    (tw_term, tab)
+}
+
+fn new_terminal(width: usize, height: usize) -> (TermWizTerminal, RenderableInner) {
+    let (render_tx, render_rx) = channel();
+    let (input_tx, input_rx) = channel();
+
+    let tw_term = TermWizTerminal {
+        render_tx,
+        input_rx,
+        screen_size: ScreenSize {
+            cols: width,
+            rows: height,
+            xpixel: 0,
+            ypixel: 0,
+        },
+    };
+
+    let inner = RenderableInner {
+        surface: Surface::new(width, height),
+        highlight: Arc::new(Mutex::new(None)),
+        local_sequence: 0,
+        seqno: 0,
+        dead: false,
+        something_changed: Arc::new(AtomicBool::new(false)),
+        selection_range: Arc::new(Mutex::new(None)),
+        input_tx,
+        render_rx,
+    };
+
+    (tw_term, inner)
+}
+
 pub fn message_box_ok(message: &str) {
     let title = "wezterm";
     let message = message.to_string();
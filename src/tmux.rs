@@ -0,0 +1,745 @@
+//! A client for tmux control mode.
+//! When tmux is started with `-CC` in one of our tabs it switches
+//! that tab into control mode by emitting a DCS sequence, after which
+//! it describes its windows and panes, along with their output, as
+//! notifications rather than drawing them in the tab.
+//! The `TmuxDomain` that is created for the control tab materializes
+//! each tmux window as a tab in the same window as the control tab,
+//! with the tmux panes of that window as split panes, and sends
+//! input, resizes and title changes back to tmux as commands.
+use crate::config::TermConfig;
+use crate::frontend::executor;
+use crate::mux::domain::{alloc_domain_id, Domain, DomainId, DomainState};
+use crate::mux::renderable::Renderable;
+use crate::mux::splits::SplitDirection;
+use crate::mux::tab::{alloc_tab_id, Tab, TabId};
+use crate::mux::window::WindowId;
+use crate::mux::{apply_tab_output, Mux};
+use anyhow::{anyhow, bail, Context};
+use filedescriptor::{FileDescriptor, Pipe};
+use portable_pty::{CommandBuilder, PtySize};
+use promise::Future;
+use std::cell::{RefCell, RefMut};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::Arc;
+use term::color::ColorPalette;
+use term::export::ExportFormat;
+use term::selection::SelectionRange;
+use term::{
+    Clipboard, DeviceControlHandler, KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalHost,
+};
+use termwiz::escape::DeviceControlMode;
+use termwiz::tmux_cc::{Event, Parser, TmuxPaneId, TmuxWindowId, WindowLayout};
+
+/// The format used to describe the windows in response to `list-windows`
+const LIST_WINDOWS: &str = "list-windows -F \"#{window_id} #{window_layout} #{window_name}\"";
+
+/// Installed in the terminal of each local tab so that we notice
+/// when tmux enters control mode in that tab
+pub struct TmuxControlHandler {
+    control_tab_id: TabId,
+    parser: Option<Parser>,
+}
+
+impl TmuxControlHandler {
+    pub fn new(control_tab_id: TabId) -> Self {
+        Self {
+            control_tab_id,
+            parser: None,
+        }
+    }
+}
+
+impl DeviceControlHandler for TmuxControlHandler {
+    fn handle_device_control(&mut self, control: DeviceControlMode) {
+        // The terminal of the control tab is borrowed while we are
+        // called, so the events are processed once it has finished
+        // with the current batch of output
+        let control_tab_id = self.control_tab_id;
+        match control {
+            DeviceControlMode::Enter {
+                ref params,
+                byte: b'p',
+                ..
+            } if params.as_slice() == [1000] => {
+                self.parser = Some(Parser::new());
+                Future::with_executor(executor(), move || {
+                    TmuxDomain::start(control_tab_id)?;
+                    Ok(())
+                });
+            }
+            DeviceControlMode::Data(byte) => {
+                if let Some(event) = self.parser.as_mut().and_then(|p| p.advance_byte(byte)) {
+                    Future::with_executor(executor(), move || {
+                        TmuxDomain::process_event_for(control_tab_id, event)
+                    });
+                }
+            }
+            DeviceControlMode::Exit if self.parser.is_some() => {
+                self.parser = None;
+                Future::with_executor(executor(), move || {
+                    TmuxDomain::process_event_for(control_tab_id, Event::Exit { reason: None })
+                });
+            }
+            _ => log::error!("Unhandled {:?}", control),
+        }
+    }
+}
+
+/// What to do with the response to a command that we sent to tmux
+enum PendingCommand {
+    Ignore,
+    ListWindows,
+    CapturePane(TmuxPaneId),
+    CursorPosition(TmuxPaneId),
+}
+
+struct TmuxWindow {
+    name: String,
+    panes: Vec<TmuxPaneId>,
+}
+
+struct DomainInner {
+    state: DomainState,
+    /// The responses that we are expecting, in the order in which
+    /// the commands were sent.  tmux responds to the command that
+    /// started control mode before we send anything, hence the
+    /// initial Ignore.
+    pending: VecDeque<PendingCommand>,
+    windows: HashMap<TmuxWindowId, TmuxWindow>,
+    panes: HashMap<TmuxPaneId, Rc<TmuxTab>>,
+}
+
+pub struct TmuxDomain {
+    id: DomainId,
+    name: String,
+    control_tab_id: TabId,
+    inner: RefCell<DomainInner>,
+}
+
+impl TmuxDomain {
+    /// Creates the domain for a tab that has just entered control
+    /// mode and asks tmux to describe its windows
+    fn start(control_tab_id: TabId) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        // tmux was started again in the same tab without the
+        // previous session having exited cleanly
+        Self::process_event_for(control_tab_id, Event::Exit { reason: None })?;
+
+        let domain = TmuxDomain {
+            id: alloc_domain_id(),
+            name: format!("tmux-{}", control_tab_id),
+            control_tab_id,
+            inner: RefCell::new(DomainInner {
+                state: DomainState::Attached,
+                pending: VecDeque::from(vec![PendingCommand::Ignore]),
+                windows: HashMap::new(),
+                panes: HashMap::new(),
+            }),
+        };
+        domain.send_command(LIST_WINDOWS, PendingCommand::ListWindows)?;
+
+        let domain: Arc<dyn Domain> = Arc::new(domain);
+        mux.add_domain(&domain);
+        Ok(())
+    }
+
+    /// Returns the attached domain whose control mode session is
+    /// running in `control_tab_id`
+    fn for_control_tab(control_tab_id: TabId) -> Option<Arc<dyn Domain>> {
+        let mux = Mux::get().unwrap();
+        mux.iter_domains().into_iter().find(|domain| {
+            domain
+                .downcast_ref::<TmuxDomain>()
+                .map(|tmux| {
+                    tmux.control_tab_id == control_tab_id
+                        && tmux.inner.borrow().state == DomainState::Attached
+                })
+                .unwrap_or(false)
+        })
+    }
+
+    fn process_event_for(control_tab_id: TabId, event: Event) -> anyhow::Result<()> {
+        if let Some(domain) = Self::for_control_tab(control_tab_id) {
+            if let Some(tmux) = domain.downcast_ref::<TmuxDomain>() {
+                tmux.process_event(event)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_command(&self, command: &str, pending: PendingCommand) -> anyhow::Result<()> {
+        let mux = Mux::get().unwrap();
+        let tab = mux
+            .get_tab(self.control_tab_id)
+            .ok_or_else(|| anyhow!("tmux control tab {} has gone", self.control_tab_id))?;
+        writeln!(tab.writer(), "{}", command)?;
+        self.inner.borrow_mut().pending.push_back(pending);
+        Ok(())
+    }
+
+    fn get_pane(&self, pane: TmuxPaneId) -> Option<Rc<TmuxTab>> {
+        self.inner.borrow().panes.get(&pane).map(Rc::clone)
+    }
+
+    fn process_event(&self, event: Event) -> anyhow::Result<()> {
+        log::trace!("tmux: {:?}", event);
+        match event {
+            Event::CommandResponse { output, error, .. } => {
+                let pending = self.inner.borrow_mut().pending.pop_front();
+                if error {
+                    log::error!("tmux command failed: {}", output);
+                    return Ok(());
+                }
+                match pending {
+                    Some(PendingCommand::ListWindows) => self.sync_windows(&output)?,
+                    Some(PendingCommand::CapturePane(pane)) => {
+                        if let Some(tab) = self.get_pane(pane) {
+                            tab.feed(output.replace('\n', "\r\n").as_bytes());
+                        }
+                    }
+                    Some(PendingCommand::CursorPosition(pane)) => {
+                        let mut coords = output.trim().split(' ').map(str::parse::<usize>);
+                        if let (Some(Ok(x)), Some(Ok(y)), Some(tab)) =
+                            (coords.next(), coords.next(), self.get_pane(pane))
+                        {
+                            tab.feed(format!("\x1b[{};{}H", y + 1, x + 1).as_bytes());
+                        }
+                    }
+                    Some(PendingCommand::Ignore) | None => {}
+                }
+            }
+            Event::Output { pane, text } => {
+                if let Some(tab) = self.get_pane(pane) {
+                    tab.feed(&text);
+                }
+            }
+            Event::WindowAdd { .. } | Event::SessionChanged { .. } => {
+                self.send_command(LIST_WINDOWS, PendingCommand::ListWindows)?;
+            }
+            Event::WindowClose { window } => self.close_window(window),
+            Event::WindowRenamed { window, name } => {
+                let mut inner = self.inner.borrow_mut();
+                let inner = &mut *inner;
+                if let Some(win) = inner.windows.get_mut(&window) {
+                    for pane in &win.panes {
+                        if let Some(tab) = inner.panes.get(pane) {
+                            *tab.title.borrow_mut() = name.clone();
+                        }
+                    }
+                    win.name = name;
+                }
+            }
+            Event::LayoutChange { window, layout } => {
+                let known = self.inner.borrow().windows.contains_key(&window);
+                if known {
+                    self.sync_window(window, &layout, None)?;
+                }
+            }
+            Event::Exit { reason } => {
+                if let Some(reason) = reason {
+                    log::error!("tmux exited: {}", reason);
+                }
+                self.close_all_panes();
+                self.inner.borrow_mut().state = DomainState::Detached;
+                Mux::get().unwrap().domain_was_detached(self.id);
+            }
+            Event::WindowPaneChanged { .. }
+            | Event::SessionRenamed { .. }
+            | Event::SessionWindowChanged { .. }
+            | Event::SessionsChanged => {}
+        }
+        Ok(())
+    }
+
+    /// Processes the response to `LIST_WINDOWS`, creating tabs for the
+    /// windows that we haven't seen before and closing those that
+    /// no longer exist, such as after switching sessions
+    fn sync_windows(&self, output: &str) -> anyhow::Result<()> {
+        let mut seen = vec![];
+        for line in output.lines() {
+            let mut fields = line.splitn(3, ' ');
+            let (window, layout, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(window), Some(layout), name) => (window, layout, name.unwrap_or("")),
+                _ => bail!("unexpected list-windows output: {}", line),
+            };
+            let window: TmuxWindowId = window
+                .trim_start_matches('@')
+                .parse()
+                .with_context(|| format!("parsing window id in {}", line))?;
+            seen.push(window);
+            self.sync_window(window, layout, Some(name))?;
+        }
+
+        let closed: Vec<TmuxWindowId> = self
+            .inner
+            .borrow()
+            .windows
+            .keys()
+            .filter(|window| !seen.contains(window))
+            .cloned()
+            .collect();
+        for window in closed {
+            self.close_window(window);
+        }
+        Ok(())
+    }
+
+    /// Reconciles the panes of a tmux window with its layout.
+    /// New panes are split from the pane that precedes them in the
+    /// layout, which reproduces the tmux arrangement for the common
+    /// layouts, although the sizes of the panes are decided by the
+    /// mux and then sent back to tmux.
+    fn sync_window(
+        &self,
+        window: TmuxWindowId,
+        layout: &str,
+        name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let layout = WindowLayout::parse(layout)?;
+        let mut panes = vec![];
+        pane_directions(&layout, None, &mut panes);
+
+        let mux = Mux::get().unwrap();
+        let mux_window_id = mux
+            .window_containing_tab(self.control_tab_id)
+            .ok_or_else(|| anyhow!("tmux control tab {} has no window", self.control_tab_id))?;
+
+        let (name, existing) = {
+            let mut inner = self.inner.borrow_mut();
+            let win = inner.windows.entry(window).or_insert_with(|| TmuxWindow {
+                name: String::new(),
+                panes: vec![],
+            });
+            if let Some(name) = name {
+                win.name = name.to_string();
+            }
+            (win.name.clone(), win.panes.clone())
+        };
+
+        let mut previous: Option<TabId> = None;
+        for (pane, size, direction) in panes.iter() {
+            if let Some(tab) = self.get_pane(*pane) {
+                previous = Some(tab.tab_id());
+                continue;
+            }
+
+            let tab = Rc::new(TmuxTab::new(self.id, window, *pane, size, &name));
+            self.inner.borrow_mut().panes.insert(*pane, Rc::clone(&tab));
+            let tab: Rc<dyn Tab> = tab;
+            mux.add_tab(&tab)?;
+            mux.add_tab_to_window(&tab, mux_window_id)?;
+            if let (Some(previous), Some(direction)) = (previous, direction) {
+                let mut mux_window = mux
+                    .get_window_mut(mux_window_id)
+                    .ok_or_else(|| anyhow!("no such window {}", mux_window_id))?;
+                mux_window.split_pane(previous, tab.tab_id(), *direction)?;
+                if let Some(size) = mux_window.get_size() {
+                    mux_window.resize(size);
+                }
+            }
+            previous = Some(tab.tab_id());
+
+            // Show what was already in the pane
+            self.send_command(
+                &format!("capture-pane -p -e -t %{}", pane),
+                PendingCommand::CapturePane(*pane),
+            )?;
+            self.send_command(
+                &format!(
+                    "display-message -p -t %{} \"#{{cursor_x}} #{{cursor_y}}\"",
+                    pane
+                ),
+                PendingCommand::CursorPosition(*pane),
+            )?;
+        }
+
+        let pane_ids: Vec<TmuxPaneId> = panes.iter().map(|(pane, _, _)| *pane).collect();
+        for pane in existing {
+            if !pane_ids.contains(&pane) {
+                self.close_pane(pane);
+            }
+        }
+        if let Some(win) = self.inner.borrow_mut().windows.get_mut(&window) {
+            win.panes = pane_ids;
+        }
+        Ok(())
+    }
+
+    fn close_pane(&self, pane: TmuxPaneId) {
+        if let Some(tab) = self.inner.borrow_mut().panes.remove(&pane) {
+            tab.close();
+        }
+    }
+
+    fn close_window(&self, window: TmuxWindowId) {
+        let win = self.inner.borrow_mut().windows.remove(&window);
+        if let Some(win) = win {
+            for pane in win.panes {
+                self.close_pane(pane);
+            }
+        }
+    }
+
+    fn close_all_panes(&self) {
+        let windows: Vec<TmuxWindowId> = self.inner.borrow().windows.keys().cloned().collect();
+        for window in windows {
+            self.close_window(window);
+        }
+    }
+
+    fn resize_pane(&self, window: TmuxWindowId, pane: TmuxPaneId, size: PtySize) {
+        let single_pane = self
+            .inner
+            .borrow()
+            .windows
+            .get(&window)
+            .map(|win| win.panes.len() <= 1)
+            .unwrap_or(true);
+        let command = if single_pane {
+            format!("refresh-client -C {},{}", size.cols, size.rows)
+        } else {
+            format!("resize-pane -t %{} -x {} -y {}", pane, size.cols, size.rows)
+        };
+        if let Err(err) = self.send_command(&command, PendingCommand::Ignore) {
+            log::error!("resizing tmux pane %{}: {:#}", pane, err);
+        }
+    }
+}
+
+impl Domain for TmuxDomain {
+    fn spawn(
+        &self,
+        _size: PtySize,
+        _command: Option<CommandBuilder>,
+        _window: WindowId,
+    ) -> anyhow::Result<Rc<dyn Tab>> {
+        bail!("spawning tabs in a tmux domain is not supported; use tmux to create a new window");
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.id
+    }
+
+    fn domain_name(&self) -> &str {
+        &self.name
+    }
+
+    fn attach(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn detach(&self) -> anyhow::Result<()> {
+        self.send_command("detach-client", PendingCommand::Ignore)
+    }
+
+    fn state(&self) -> DomainState {
+        self.inner.borrow().state
+    }
+}
+
+/// Flattens a layout into its panes, along with the direction in
+/// which each pane is split from the pane that precedes it
+fn pane_directions(
+    layout: &WindowLayout,
+    direction: Option<SplitDirection>,
+    panes: &mut Vec<(TmuxPaneId, PtySize, Option<SplitDirection>)>,
+) {
+    let (children, child_direction) = match layout {
+        WindowLayout::Pane { pane, geometry } => {
+            let size = PtySize {
+                rows: geometry.rows as u16,
+                cols: geometry.cols as u16,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            panes.push((*pane, size, direction));
+            return;
+        }
+        WindowLayout::LeftRight { children, .. } => (children, SplitDirection::Horizontal),
+        WindowLayout::TopBottom { children, .. } => (children, SplitDirection::Vertical),
+    };
+    for (idx, child) in children.iter().enumerate() {
+        let direction = if idx == 0 {
+            direction
+        } else {
+            Some(child_direction)
+        };
+        pane_directions(child, direction, panes);
+    }
+}
+
+/// Quotes `text` for use as an argument in a tmux command
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        if c == '"' || c == '\\' || c == '$' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Sends the input for a pane to tmux using `send-keys`
+struct PaneWriter {
+    domain_id: DomainId,
+    pane: TmuxPaneId,
+}
+
+impl PaneWriter {
+    fn domain(&self) -> std::io::Result<Arc<dyn Domain>> {
+        Mux::get()
+            .unwrap()
+            .get_domain(self.domain_id)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "tmux has gone"))
+    }
+}
+
+impl std::io::Write for PaneWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let mut command = format!("send-keys -t %{} -H", self.pane);
+        for byte in data {
+            command.push_str(&format!(" {:02x}", byte));
+        }
+        let domain = self.domain()?;
+        domain
+            .downcast_ref::<TmuxDomain>()
+            .unwrap()
+            .send_command(&command, PendingCommand::Ignore)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:#}", err)))?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A tmux pane.  The output that tmux reports for the pane is
+/// applied to its terminal in the same way as the output of a
+/// local pty; the pane has a pipe that is only ever closed, so
+/// that the mux sees EOF and removes the tab when the pane goes.
+pub struct TmuxTab {
+    tab_id: TabId,
+    domain_id: DomainId,
+    window: TmuxWindowId,
+    pane: TmuxPaneId,
+    terminal: RefCell<Terminal>,
+    /// The configuration of the terminal, which holds its profile
+    config: Arc<TermConfig>,
+    writer: RefCell<PaneWriter>,
+    reader: FileDescriptor,
+    /// The write end of the pipe; None once the pane has closed
+    output: RefCell<Option<FileDescriptor>>,
+    /// The name of the tmux window
+    title: RefCell<String>,
+    size: RefCell<PtySize>,
+}
+
+impl TmuxTab {
+    fn new(
+        domain_id: DomainId,
+        window: TmuxWindowId,
+        pane: TmuxPaneId,
+        size: &PtySize,
+        title: &str,
+    ) -> Self {
+        let pipe = Pipe::new().expect("Pipe::new failed");
+        let config = Arc::new(TermConfig::new());
+        let terminal = Terminal::new(size.rows as usize, size.cols as usize, 0, 0, config.clone());
+        Self {
+            tab_id: alloc_tab_id(),
+            domain_id,
+            window,
+            pane,
+            terminal: RefCell::new(terminal),
+            config,
+            writer: RefCell::new(PaneWriter { domain_id, pane }),
+            reader: pipe.read,
+            output: RefCell::new(Some(pipe.write)),
+            title: RefCell::new(title.to_string()),
+            size: RefCell::new(*size),
+        }
+    }
+
+    /// Passes output from tmux to the terminal
+    fn feed(&self, data: &[u8]) {
+        if self.output.borrow().is_some() {
+            apply_tab_output(self.tab_id, data.to_vec());
+        }
+    }
+
+    /// Closes the pipe, which causes the mux to remove the tab
+    fn close(&self) {
+        self.output.borrow_mut().take();
+    }
+
+    fn domain(&self) -> Option<Arc<dyn Domain>> {
+        Mux::get().unwrap().get_domain(self.domain_id)
+    }
+}
+
+impl Tab for TmuxTab {
+    fn tab_id(&self) -> TabId {
+        self.tab_id
+    }
+
+    fn renderer(&self) -> RefMut<dyn Renderable> {
+        RefMut::map(self.terminal.borrow_mut(), |t| &mut *t)
+    }
+
+    fn get_title(&self) -> String {
+        self.title.borrow().clone()
+    }
+
+    fn profile(&self) -> Option<String> {
+        self.config.profile()
+    }
+
+    fn set_profile(&self, profile: Option<String>) {
+        self.config.set_profile(profile);
+    }
+
+    fn set_user_title(&self, title: Option<String>) {
+        let command = match title {
+            Some(title) => format!("rename-window -t @{} {}", self.window, quote(&title)),
+            None => format!("set-option -w -t @{} automatic-rename on", self.window),
+        };
+        if let Some(domain) = self.domain() {
+            if let Some(tmux) = domain.downcast_ref::<TmuxDomain>() {
+                if let Err(err) = tmux.send_command(&command, PendingCommand::Ignore) {
+                    log::error!("renaming tmux window @{}: {:#}", self.window, err);
+                }
+            }
+        }
+    }
+
+    fn send_paste(&self, text: &str) -> anyhow::Result<()> {
+        self.terminal
+            .borrow_mut()
+            .send_paste(text, &mut *self.writer.borrow_mut())
+    }
+
+    fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
+        Ok(Box::new(self.reader.try_clone()?))
+    }
+
+    fn writer(&self) -> RefMut<dyn std::io::Write> {
+        self.writer.borrow_mut()
+    }
+
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        let changed = {
+            let mut current = self.size.borrow_mut();
+            let changed = current.rows != size.rows || current.cols != size.cols;
+            *current = size;
+            changed
+        };
+        self.terminal.borrow_mut().resize(
+            size.rows as usize,
+            size.cols as usize,
+            size.pixel_width as usize,
+            size.pixel_height as usize,
+        );
+        if changed {
+            if let Some(domain) = self.domain() {
+                if let Some(tmux) = domain.downcast_ref::<TmuxDomain>() {
+                    tmux.resize_pane(self.window, self.pane, size);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        self.terminal
+            .borrow_mut()
+            .key_down(key, mods, &mut *self.writer.borrow_mut())
+    }
+
+    fn mouse_event(&self, event: MouseEvent, host: &mut dyn TerminalHost) -> anyhow::Result<()> {
+        self.terminal.borrow_mut().mouse_event(event, host)
+    }
+
+    fn advance_bytes(&self, buf: &[u8], host: &mut dyn TerminalHost) {
+        self.terminal.borrow_mut().advance_bytes(buf, host)
+    }
+
+    fn is_dead(&self) -> bool {
+        self.output.borrow().is_none()
+    }
+
+    fn palette(&self) -> ColorPalette {
+        self.terminal.borrow().palette().clone()
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.domain_id
+    }
+
+    fn set_clipboard(&self, clipboard: &Arc<dyn Clipboard>) {
+        self.terminal.borrow_mut().set_clipboard(clipboard);
+    }
+
+    fn erase_scrollback(&self) {
+        self.terminal.borrow_mut().erase_scrollback_and_viewport();
+    }
+
+    fn export_scrollback(&self, format: ExportFormat) -> Future<String> {
+        Future::ok(self.terminal.borrow().export_scrollback(format))
+    }
+
+    fn selection_range(&self) -> Option<SelectionRange> {
+        let terminal = self.terminal.borrow();
+        let rows = terminal.screen().physical_rows;
+        terminal
+            .selection_range()
+            .map(|r| r.clip_to_viewport(terminal.get_viewport_offset(), rows))
+    }
+
+    fn selection_text(&self) -> Option<String> {
+        let terminal = self.terminal.borrow();
+        Some(terminal.get_selection_text())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("plain"), "\"plain\"");
+        assert_eq!(quote("say \"$HOME\\\""), "\"say \\\"\\$HOME\\\\\\\"\"");
+    }
+
+    #[test]
+    fn layout_directions() {
+        let layout = WindowLayout::parse(
+            "e5b3,160x48,0,0{80x48,0,0,1,79x48,81,0[79x24,81,0,2,79x23,81,25,4]}",
+        )
+        .unwrap();
+        let mut panes = vec![];
+        pane_directions(&layout, None, &mut panes);
+        let summary: Vec<(TmuxPaneId, u16, u16, Option<SplitDirection>)> = panes
+            .into_iter()
+            .map(|(pane, size, direction)| (pane, size.cols, size.rows, direction))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 80, 48, None),
+                (2, 79, 24, Some(SplitDirection::Horizontal)),
+                (4, 79, 23, Some(SplitDirection::Vertical)),
+            ]
+        );
+    }
+}
@@ -18,6 +18,8 @@ pub struct ColorPalette {
     pub selection_fg: RgbColor,
     pub selection_bg: RgbColor,
     pub scrollbar_thumb: RgbColor,
+    pub scrollbar_thumb_hover: RgbColor,
+    pub split: RgbColor,
 }
 
 impl fmt::Debug for Palette256 {
@@ -189,6 +191,8 @@ impl Default for ColorPalette {
         let selection_bg = RgbColor::new(0xff, 0xfa, 0xcd);
 
         let scrollbar_thumb = RgbColor::new(0x22, 0x22, 0x22);
+        let scrollbar_thumb_hover = RgbColor::new(0x44, 0x44, 0x44);
+        let split = RgbColor::new(0x44, 0x44, 0x44);
 
         ColorPalette {
             colors: Palette256(colors),
@@ -200,6 +204,8 @@ impl Default for ColorPalette {
             selection_fg,
             selection_bg,
             scrollbar_thumb,
+            scrollbar_thumb_hover,
+            split,
         }
     }
 }
@@ -1,6 +1,22 @@
 use crate::color::ColorPalette;
+use crate::input::KeyModifiers;
+use std::borrow::Cow;
 use termwiz::hyperlink::Rule as HyperlinkRule;
 
+/// The characters that break words for double-click selection
+/// when the configuration doesn't specify its own set
+pub const DEFAULT_SELECTION_WORD_BOUNDARY: &str = " \t\n{}[]()\"'";
+
+/// Returns true if `s`, the text of a cell, is part of a word
+/// given the set of `boundary` characters
+pub fn is_double_click_word(s: &str, boundary: &str) -> bool {
+    match s.len() {
+        1 => !boundary.contains(s.chars().nth(0).unwrap()),
+        0 => false,
+        _ => true,
+    }
+}
+
 pub trait TerminalConfiguration: std::fmt::Debug {
     /// Returns a generation counter for the active
     /// configuration.  If the implementation may be
@@ -15,16 +31,36 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         3500
     }
 
-    // TODO: expose is_double_click_word in config file
+    /// Returns the set of characters that delimit a word when
+    /// making a double-click selection
+    fn selection_word_boundary(&self) -> Cow<str> {
+        Cow::Borrowed(DEFAULT_SELECTION_WORD_BOUNDARY)
+    }
+
+    /// Returns true if `s`, the text of a cell, is part of a word
+    /// when making a double-click selection
     fn is_double_click_word(&self, s: &str) -> bool {
-        match s.len() {
-            1 => match s.chars().nth(0).unwrap() {
-                ' ' | '\t' | '\n' | '{' | '[' | '}' | ']' | '(' | ')' | '"' | '\'' => false,
-                _ => true,
-            },
-            0 => false,
-            _ => true,
-        }
+        is_double_click_word(s, &self.selection_word_boundary())
+    }
+
+    /// Returns true if the selection should be copied to the clipboard
+    /// as soon as it is made with the mouse, rather than only by an
+    /// explicit copy
+    fn copy_on_select(&self) -> bool {
+        true
+    }
+
+    /// Returns true if trailing whitespace should be removed from each
+    /// row of the selected text
+    fn selection_trim_trailing_whitespace(&self) -> bool {
+        true
+    }
+
+    /// Returns true if a selection that spans a wrapped line should
+    /// be copied as a single line, rather than with a newline at the
+    /// point where it wrapped
+    fn selection_join_wrapped_lines(&self) -> bool {
+        true
     }
 
     // TODO: expose scroll_to_bottom_on_key_input in config file
@@ -37,5 +73,25 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         (self.generation(), vec![])
     }
 
+    /// Returns the modifiers that must be held down when clicking
+    /// on a hyperlink in order to open it.
+    fn hyperlink_click_modifiers(&self) -> KeyModifiers {
+        KeyModifiers::NONE
+    }
+
+    /// Returns the modifiers that, when held down, cause mouse events
+    /// to be handled locally (eg: to make a selection) rather than
+    /// being reported to an application that has enabled mouse reporting.
+    fn bypass_mouse_reporting_modifiers(&self) -> KeyModifiers {
+        KeyModifiers::SHIFT
+    }
+
+    /// Returns true if turning the mouse wheel while the alternate
+    /// screen is active should send cursor up/down keys to the
+    /// application, rather than being ignored.
+    fn alternate_buffer_wheel_sends_arrow_keys(&self) -> bool {
+        true
+    }
+
     fn color_palette(&self) -> ColorPalette;
 }
@@ -0,0 +1,203 @@
+//! Renders the contents of a screen as plain text or as an HTML
+//! document, for saving the scrollback to a file.
+use crate::color::ColorPalette;
+use serde_derive::*;
+use std::fmt::Write;
+use termwiz::cell::{Cell, Intensity, Underline};
+use termwiz::color::ColorAttribute;
+use termwiz::surface::line::Line;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ExportFormat {
+    /// The text of the lines, without any styling
+    Text,
+    /// An HTML document that uses inline styles to reproduce the
+    /// colors and attributes of the text
+    Html,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Text" => Ok(Self::Text),
+            "Html" => Ok(Self::Html),
+            _ => anyhow::bail!("invalid ExportFormat {}", s),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// Returns the conventional file name extension for the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// Renders `lines` in the requested format.  Lines that were wrapped
+/// are joined back together and trailing blank lines are omitted.
+/// `title` is used as the title of an HTML document.
+pub fn export_lines<'a, I: IntoIterator<Item = &'a Line>>(
+    lines: I,
+    format: ExportFormat,
+    palette: &ColorPalette,
+    title: &str,
+) -> String {
+    let mut rows = vec![];
+    let mut current = String::new();
+    for line in lines {
+        let line = line.uncompressed();
+        let wrapped = line.last_cell_was_wrapped();
+        match format {
+            ExportFormat::Text => {
+                let text = line.as_str();
+                current.push_str(if wrapped { &text } else { text.trim_end() });
+            }
+            ExportFormat::Html => html_line(&line, wrapped, palette, &mut current),
+        }
+        if !wrapped {
+            rows.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        rows.push(current);
+    }
+    while rows.last().map(String::is_empty).unwrap_or(false) {
+        rows.pop();
+    }
+
+    let mut result = String::new();
+    if format == ExportFormat::Html {
+        writeln!(
+            result,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>",
+            escape_html(title)
+        )
+        .ok();
+        write!(
+            result,
+            "<body style=\"color:{};background-color:{}\">\n<pre>",
+            palette.foreground.to_rgb_string(),
+            palette.background.to_rgb_string()
+        )
+        .ok();
+    }
+    for row in rows {
+        result.push_str(&row);
+        result.push('\n');
+    }
+    if format == ExportFormat::Html {
+        result.push_str("</pre>\n</body>\n</html>\n");
+    }
+    result
+}
+
+/// Appends the cells of `line` to `out` as a series of styled spans.
+/// Unless the line is wrapped, trailing spaces that have the default
+/// background are omitted.
+fn html_line(line: &Line, wrapped: bool, palette: &ColorPalette, out: &mut String) {
+    let cells: Vec<&Cell> = line.visible_cells().map(|(_, cell)| cell).collect();
+    let len = if wrapped {
+        cells.len()
+    } else {
+        cells
+            .iter()
+            .rposition(|cell| {
+                cell.str() != " "
+                    || cell.attrs().background != ColorAttribute::Default
+                    || cell.attrs().reverse()
+            })
+            .map(|idx| idx + 1)
+            .unwrap_or(0)
+    };
+
+    let mut run = String::new();
+    let mut run_style = String::new();
+    for cell in &cells[..len] {
+        let style = cell_style(cell, palette);
+        if style != run_style {
+            push_span(&run_style, &run, out);
+            run.clear();
+            run_style = style;
+        }
+        run.push_str(&escape_html(cell.str()));
+    }
+    push_span(&run_style, &run, out);
+}
+
+fn push_span(style: &str, text: &str, out: &mut String) {
+    if text.is_empty() {
+        return;
+    }
+    if style.is_empty() {
+        out.push_str(text);
+    } else {
+        write!(out, "<span style=\"{}\">{}</span>", style, text).ok();
+    }
+}
+
+/// Computes the inline style for a cell, omitting anything that
+/// matches the defaults that are set on the document body
+fn cell_style(cell: &Cell, palette: &ColorPalette) -> String {
+    let attrs = cell.attrs();
+    let mut fg = palette.resolve_fg(attrs.foreground);
+    let mut bg = palette.resolve_bg(attrs.background);
+    if attrs.reverse() {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.invisible() {
+        fg = bg;
+    }
+
+    let mut style = String::new();
+    if fg != palette.foreground {
+        write!(style, "color:{};", fg.to_rgb_string()).ok();
+    }
+    if bg != palette.background {
+        write!(style, "background-color:{};", bg.to_rgb_string()).ok();
+    }
+    match attrs.intensity() {
+        Intensity::Bold => style.push_str("font-weight:bold;"),
+        Intensity::Half => style.push_str("opacity:0.5;"),
+        Intensity::Normal => {}
+    }
+    if attrs.italic() {
+        style.push_str("font-style:italic;");
+    }
+    let underline = attrs.underline() != Underline::None;
+    match (underline, attrs.strikethrough()) {
+        (true, true) => style.push_str("text-decoration:underline line-through;"),
+        (true, false) => style.push_str("text-decoration:underline;"),
+        (false, true) => style.push_str("text-decoration:line-through;"),
+        (false, false) => {}
+    }
+    if underline && attrs.underline_color() != ColorAttribute::Default {
+        write!(
+            style,
+            "text-decoration-color:{};",
+            palette.resolve_fg(attrs.underline_color()).to_rgb_string()
+        )
+        .ok();
+    }
+    if attrs.underline() == Underline::Double {
+        style.push_str("text-decoration-style:double;");
+    }
+    style
+}
+
+fn escape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
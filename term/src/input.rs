@@ -1,5 +1,8 @@
 // clippy hates bitflags
-#![cfg_attr(feature = "cargo-clippy", allow(clippy::suspicious_arithmetic_impl, clippy::redundant_field_names))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    allow(clippy::suspicious_arithmetic_impl, clippy::redundant_field_names)
+)]
 
 use super::VisibleRowIndex;
 use serde_derive::*;
@@ -25,6 +28,60 @@ pub enum MouseEventKind {
     Move,
 }
 
+/// Specifies the text that `MouseAction::SelectText` selects
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MouseSelectionMode {
+    /// Start a selection at the mouse position; dragging the mouse
+    /// extends it
+    Cell,
+    /// Like `Cell`, but the selection is a rectangular block
+    Block,
+    /// The word under the mouse
+    Word,
+    /// The logical line under the mouse, including any rows that
+    /// it wraps onto
+    Line,
+    /// The run of non-blank lines surrounding the mouse
+    Paragraph,
+    /// The prompt, command line or command output under the mouse
+    SemanticZone,
+}
+
+impl std::str::FromStr for MouseSelectionMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Cell" => Ok(Self::Cell),
+            "Block" => Ok(Self::Block),
+            "Word" => Ok(Self::Word),
+            "Line" => Ok(Self::Line),
+            "Paragraph" => Ok(Self::Paragraph),
+            "SemanticZone" => Ok(Self::SemanticZone),
+            _ => anyhow::bail!("invalid MouseSelectionMode {}", s),
+        }
+    }
+}
+
+/// What the terminal does with a mouse event that it handles itself,
+/// rather than reporting it to the application.  These are resolved
+/// from the mouse bindings by the gui.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MouseAction {
+    SelectText(MouseSelectionMode),
+    /// Extend the selection to the mouse position
+    ExtendSelection,
+    /// Copy the selection to the clipboard
+    CompleteSelection,
+    /// Copy the selection to the clipboard, or if nothing is selected,
+    /// open the hyperlink under the mouse
+    CompleteSelectionOrOpenLink,
+    /// Open the hyperlink under the mouse
+    OpenLink,
+    /// Paste the clipboard into the terminal
+    Paste,
+    Nop,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct MouseEvent {
     pub kind: MouseEventKind,
@@ -32,6 +89,9 @@ pub struct MouseEvent {
     pub y: VisibleRowIndex,
     pub button: MouseButton,
     pub modifiers: KeyModifiers,
+    /// The action to perform if the terminal handles this event
+    /// itself.  If None, the built-in behavior is used.
+    pub action: Option<MouseAction>,
 }
 
 /// This is a little helper that keeps track of the "click streak",
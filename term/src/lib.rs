@@ -8,6 +8,8 @@ use std::str;
 pub mod config;
 pub use config::TerminalConfiguration;
 
+pub mod export;
+
 pub mod input;
 pub use crate::input::*;
 
@@ -29,6 +31,13 @@ pub use crate::terminal::*;
 pub mod terminalstate;
 pub use crate::terminalstate::*;
 
+pub mod terminfo;
+
+#[cfg(feature = "widget")]
+pub mod widget;
+#[cfg(feature = "widget")]
+pub use crate::widget::TerminalWidget;
+
 /// Represents the index into screen.lines.  Index 0 is the top of
 /// the scrollback (if any).  The index of the top of the visible screen
 /// depends on the terminal dimensions and the scrollback size.
@@ -53,6 +62,15 @@ pub type VisibleRowIndex = i64;
 /// to want to scroll back or select more than ~2billion lines of scrollback.
 pub type ScrollbackOrVisibleRowIndex = i32;
 
+/// Allows referencing a logical line in the scrollback, even as the
+/// screen scrolls.  The StableRowIndex counts from the top of the
+/// scrollback, growing larger as you move down through the rows.
+/// Initially the very first line has StableRowIndex==0.  When the
+/// scrollback is full and lines are purged (say 5 lines), whichever line
+/// is then first in the scrollback (PhysRowIndex==0) has StableRowIndex==5,
+/// which is the same value that that logical line had before the purge.
+pub type StableRowIndex = isize;
+
 /// Returns true if r1 intersects r2
 pub fn intersects_range<T: Ord + Copy>(r1: Range<T>, r2: Range<T>) -> bool {
     use std::cmp::{max, min};
@@ -27,6 +27,16 @@ pub struct Screen {
     pub physical_rows: usize,
     /// Physical, visible width of the screen
     pub physical_cols: usize,
+
+    /// The number of lines that have been purged from the top of
+    /// the scrollback; used to compute StableRowIndex values
+    stable_row_index_offset: usize,
+
+    /// The topmost row that may have been dirtied via `line_mut`
+    /// since `take_dirty_range` was last called.  The visible rows
+    /// are always treated as possibly dirty, so this only matters
+    /// for the scrollback.
+    dirty_top: StableRowIndex,
 }
 
 fn scrollback_size(config: &Arc<dyn TerminalConfiguration>, allow_scrollback: bool) -> usize {
@@ -37,6 +47,50 @@ fn scrollback_size(config: &Arc<dyn TerminalConfiguration>, allow_scrollback: bo
     }
 }
 
+/// Returns true if the cell is a space with default attributes
+fn is_blank_cell(cell: &Cell) -> bool {
+    cell.str() == " " && *cell.attrs() == CellAttributes::default()
+}
+
+/// Returns true if the line holds nothing but blank cells
+fn is_blank(line: &Line) -> bool {
+    line.uncompressed().cells().iter().all(is_blank_cell)
+}
+
+/// Splits the cells of a logical line into rows of physical_cols cells,
+/// marking all but the last as wrapped, and appends them to lines.
+/// Double width cells are not split across rows.
+/// Returns the logical offset at which each of the rows begins.
+fn split_logical_line(
+    logical: &mut Vec<Cell>,
+    physical_cols: usize,
+    lines: &mut VecDeque<Line>,
+) -> Vec<usize> {
+    let mut row_starts = vec![0];
+    let mut row = Vec::with_capacity(physical_cols);
+    let mut idx = 0;
+    let cells = std::mem::replace(logical, vec![]);
+    while idx < cells.len() {
+        let end = (idx + cells[idx].width().max(1)).min(cells.len());
+        if !row.is_empty() && row.len() + end - idx > physical_cols {
+            let mut full_row = std::mem::replace(&mut row, Vec::with_capacity(physical_cols));
+            full_row.resize(physical_cols, Cell::default());
+            if let Some(cell) = full_row.last_mut() {
+                cell.attrs_mut().set_wrapped(true);
+            }
+            lines.push_back(Line::from_cells(full_row));
+            row_starts.push(idx);
+        }
+        row.extend_from_slice(&cells[idx..end]);
+        idx = end;
+    }
+    if row.len() < physical_cols {
+        row.resize(physical_cols, Cell::default());
+    }
+    lines.push_back(Line::from_cells(row));
+    row_starts
+}
+
 impl Screen {
     /// Create a new Screen with the specified dimensions.
     /// The Cells in the viewable portion of the screen are set to the
@@ -62,6 +116,8 @@ impl Screen {
             allow_scrollback,
             physical_rows,
             physical_cols,
+            stable_row_index_offset: 0,
+            dirty_top: StableRowIndex::max_value(),
         }
     }
 
@@ -69,8 +125,22 @@ impl Screen {
         scrollback_size(&self.config, self.allow_scrollback)
     }
 
-    /// Resize the physical, viewable portion of the screen
-    pub fn resize(&mut self, physical_rows: usize, physical_cols: usize) {
+    /// Resize the physical, viewable portion of the screen.
+    /// When the width of a screen with scrollback changes, logical lines
+    /// that were wrapped across several rows are re-flowed to fit the new
+    /// width.
+    /// `cursor` is the position of the cursor, and `positions` holds the
+    /// physical row and column of other points of interest, such as the
+    /// ends of the selection.  They are adjusted to refer to the same
+    /// text after the lines have moved, and the new cursor position is
+    /// returned.
+    pub fn resize(
+        &mut self,
+        physical_rows: usize,
+        physical_cols: usize,
+        cursor: CursorPosition,
+        positions: &mut [(PhysRowIndex, usize)],
+    ) -> CursorPosition {
         let physical_rows = physical_rows.max(1);
         let physical_cols = physical_cols.max(1);
 
@@ -80,22 +150,175 @@ impl Screen {
             self.lines.reserve(capacity - current_capacity);
         }
 
-        if physical_rows > self.physical_rows {
-            // Enlarging the viewable portion?  Add more lines at the bottom
-            for _ in self.physical_rows..physical_rows {
-                self.lines.push_back(Line::with_width(physical_cols));
+        let (mut cursor_row, mut cursor_x) = (self.phys_row(cursor.y), cursor.x);
+        let rewrapped = physical_cols != self.physical_cols && self.allow_scrollback;
+        if rewrapped {
+            let (row, x) = self.rewrap_lines(physical_cols, (cursor_row, cursor_x), positions);
+            cursor_row = row;
+            cursor_x = x;
+        }
+
+        // Discard blank rows from the bottom rather than letting them
+        // push the cursor, and the text above it, out of view.  If the
+        // cursor is still out of view, discard rows from the bottom
+        // until it is visible.
+        while self.lines.len() > physical_rows
+            && (cursor_row < self.lines.len() - physical_rows
+                || (cursor_row + 1 < self.lines.len()
+                    && is_blank(self.lines.back().expect("lines to be non-empty"))))
+        {
+            self.lines.pop_back();
+        }
+        while self.lines.len() < physical_rows {
+            self.lines.push_back(Line::with_width(physical_cols));
+        }
+
+        // The rewrapped lines may not fit in the scrollback
+        let max_lines = capacity;
+        if self.lines.len() > max_lines {
+            let to_remove = self.lines.len() - max_lines;
+            self.lines.drain(0..to_remove);
+            self.stable_row_index_offset += to_remove;
+            cursor_row = cursor_row.saturating_sub(to_remove);
+            for (row, _) in positions.iter_mut() {
+                *row = row.saturating_sub(to_remove);
+            }
+        }
+        let last_row = self.lines.len() - 1;
+        for (row, _) in positions.iter_mut() {
+            *row = (*row).min(last_row);
+        }
+
+        if rewrapped {
+            // The rewrapped scrollback is stored in its compact form,
+            // as it was before
+            for idx in 0..self.lines.len() - physical_rows {
+                self.lines[idx].compress();
             }
         }
+
+        // Growing the screen may have brought lines from the scrollback
+        // back into view, and the visible lines are accessed directly
+        for idx in self.lines.len() - physical_rows..self.lines.len() {
+            self.lines[idx].decompress();
+        }
+
         self.physical_rows = physical_rows;
         self.physical_cols = physical_cols;
+        // Lines may have been rewrapped, or pushed into the scrollback
+        // while they were dirty
+        self.dirty_top = self.phys_to_stable_row_index(0);
+
+        CursorPosition {
+            x: cursor_x,
+            y: (cursor_row - (self.lines.len() - physical_rows)) as VisibleRowIndex,
+            shape: cursor.shape,
+        }
+    }
+
+    /// Re-flows the lines to fit physical_cols.  Rows that were wrapped
+    /// are joined to form logical lines, which are then split into rows
+    /// of the new width.  Returns the new location of the cursor and
+    /// updates positions to match.
+    fn rewrap_lines(
+        &mut self,
+        physical_cols: usize,
+        cursor: (PhysRowIndex, usize),
+        positions: &mut [(PhysRowIndex, usize)],
+    ) -> (PhysRowIndex, usize) {
+        let mut new_cursor = cursor;
+        let mut new_lines = VecDeque::with_capacity(self.lines.capacity());
+
+        // The cells of the logical line being accumulated, along with
+        // the logical offsets of the cursor and positions within it
+        let mut logical = vec![];
+        let mut cursor_offset = None;
+        let mut offsets = vec![];
+
+        let old_positions = positions.to_vec();
+        let old_lines: Vec<Line> = self.lines.drain(..).collect();
+        let num_old_lines = old_lines.len();
+        for (row, line) in old_lines.into_iter().enumerate() {
+            let wrapped = line.last_cell_was_wrapped();
+            let mut cells = line.into_cells();
+            if wrapped {
+                if let Some(cell) = cells.last_mut() {
+                    cell.attrs_mut().set_wrapped(false);
+                }
+            } else {
+                while cells.last().map(is_blank_cell).unwrap_or(false) {
+                    cells.pop();
+                }
+            }
+
+            let start = logical.len();
+            if cursor.0 == row {
+                cursor_offset = Some(start + cursor.1);
+                // Keep the blank cells up to the cursor, so that it
+                // stays in the same place relative to the text
+                if !wrapped && cells.len() < cursor.1 {
+                    cells.resize(cursor.1, Cell::default());
+                }
+            }
+            for (idx, (pos_row, pos_x)) in old_positions.iter().enumerate() {
+                if *pos_row == row {
+                    offsets.push((idx, start + (*pos_x).min(cells.len())));
+                }
+            }
+            logical.append(&mut cells);
+
+            // A wrapped final row is the end of its logical line
+            if !wrapped || row + 1 == num_old_lines {
+                let row_starts = split_logical_line(&mut logical, physical_cols, &mut new_lines);
+                let first_row = new_lines.len() - row_starts.len();
+                let locate = |offset: usize| {
+                    let idx = match row_starts.binary_search(&offset) {
+                        Ok(idx) => idx,
+                        Err(idx) => idx - 1,
+                    };
+                    (
+                        first_row + idx,
+                        (offset - row_starts[idx]).min(physical_cols - 1),
+                    )
+                };
+                if let Some(offset) = cursor_offset.take() {
+                    new_cursor = locate(offset);
+                }
+                for (idx, offset) in offsets.drain(..) {
+                    positions[idx] = locate(offset);
+                }
+            }
+        }
+
+        self.lines = new_lines;
+        new_cursor
     }
 
     /// Get mutable reference to a line, relative to start of scrollback.
     #[inline]
     pub fn line_mut(&mut self, idx: PhysRowIndex) -> &mut Line {
+        self.dirty_top = self.dirty_top.min(self.phys_to_stable_row_index(idx));
         &mut self.lines[idx]
     }
 
+    /// Sets all of the lines dirty, including those in the scrollback
+    pub fn make_all_lines_dirty(&mut self) {
+        for line in &mut self.lines {
+            line.set_dirty();
+        }
+        self.dirty_top = self.phys_to_stable_row_index(0);
+    }
+
+    /// Returns the range of lines that may have been dirtied since the
+    /// prior call; the visible lines, along with any lines from the
+    /// scrollback that were obtained via `line_mut` in the meantime.
+    pub fn take_dirty_range(&mut self) -> Range<PhysRowIndex> {
+        let first_visible = self.lines.len() - self.physical_rows;
+        let top = self.dirty_top - self.stable_row_index_offset as StableRowIndex;
+        self.dirty_top = StableRowIndex::max_value();
+        (top.max(0) as usize).min(first_visible)..self.lines.len()
+    }
+
     /// Sets a line dirty.  The line is relative to the visible origin.
     #[inline]
     pub fn dirty_line(&mut self, idx: VisibleRowIndex) {
@@ -114,7 +337,7 @@ impl Screen {
             if lines.len() >= self.physical_rows {
                 break;
             }
-            lines.push(line.clone());
+            lines.push(line.uncompressed().into_owned());
         }
         lines
     }
@@ -122,7 +345,10 @@ impl Screen {
     /// Returns a copy of the lines in the screen (including scrollback)
     #[cfg(test)]
     pub fn all_lines(&self) -> Vec<Line> {
-        self.lines.iter().map(|l| l.clone()).collect()
+        self.lines
+            .iter()
+            .map(|l| l.uncompressed().into_owned())
+            .collect()
     }
 
     pub fn insert_cell(&mut self, x: usize, y: VisibleRowIndex) {
@@ -165,6 +391,25 @@ impl Screen {
         line.fill_range(cols, &Cell::new(' ', attr.clone()));
     }
 
+    /// Translate a PhysRowIndex into a StableRowIndex
+    #[inline]
+    pub fn phys_to_stable_row_index(&self, idx: PhysRowIndex) -> StableRowIndex {
+        (idx + self.stable_row_index_offset) as StableRowIndex
+    }
+
+    /// Translate a StableRowIndex into a PhysRowIndex.  Returns None
+    /// if the row has been purged from the scrollback, or is beyond
+    /// the bottom of the screen.
+    #[inline]
+    pub fn stable_row_to_phys(&self, idx: StableRowIndex) -> Option<PhysRowIndex> {
+        let idx = idx - self.stable_row_index_offset as StableRowIndex;
+        if idx < 0 || idx as usize >= self.lines.len() {
+            None
+        } else {
+            Some(idx as usize)
+        }
+    }
+
     /// Translate a VisibleRowIndex into a PhysRowIndex.  The resultant index
     /// will be invalidated by inserting or removing rows!
     #[inline]
@@ -268,6 +513,11 @@ impl Screen {
             self.lines.remove(remove_idx);
         }
 
+        if remove_idx == 0 {
+            // Lines were purged from the top of the scrollback
+            self.stable_row_index_offset += lines_removed;
+        }
+
         if scroll_region.end as usize == self.physical_rows {
             // It's cheaper to push() than it is insert() at the end
             for _ in 0..to_add {
@@ -279,6 +529,120 @@ impl Screen {
                     .insert(phys_scroll.end, Line::with_width(self.physical_cols));
             }
         }
+
+        if scroll_region.start == 0 {
+            // The lines that scrolled off the top are now part of the
+            // scrollback and are unlikely to change again, so store them
+            // in their compact form to keep a large scrollback affordable
+            let first_visible = self.lines.len() - self.physical_rows;
+            for idx in first_visible.saturating_sub(num_rows)..first_visible {
+                self.lines[idx].compress();
+            }
+        }
+    }
+
+    /// Scrolls the scroll_region up by num_rows, but only within the
+    /// columns of left_and_right_margins; the cells outside of the
+    /// margins are left alone.  When the margins span the full width
+    /// of the screen this is equivalent to scroll_up().
+    pub fn scroll_up_within_margins(
+        &mut self,
+        scroll_region: &Range<VisibleRowIndex>,
+        left_and_right_margins: &Range<usize>,
+        num_rows: usize,
+    ) {
+        if left_and_right_margins.start == 0 && left_and_right_margins.end >= self.physical_cols {
+            return self.scroll_up(scroll_region, num_rows);
+        }
+        let phys_scroll = self.phys_range(scroll_region);
+        let num_rows = num_rows.min(phys_scroll.end - phys_scroll.start);
+        debug!(
+            "scroll_up_within_margins {:?} {:?} num_rows={}",
+            scroll_region, left_and_right_margins, num_rows
+        );
+
+        for dest in phys_scroll.clone() {
+            let src = if dest + num_rows < phys_scroll.end {
+                Some(dest + num_rows)
+            } else {
+                None
+            };
+            let cells = self.cells_within_margins(src, left_and_right_margins);
+            self.set_cells_within_margins(dest, left_and_right_margins, cells);
+        }
+    }
+
+    /// Scrolls the scroll_region down by num_rows, but only within the
+    /// columns of left_and_right_margins; the cells outside of the
+    /// margins are left alone.  When the margins span the full width
+    /// of the screen this is equivalent to scroll_down().
+    pub fn scroll_down_within_margins(
+        &mut self,
+        scroll_region: &Range<VisibleRowIndex>,
+        left_and_right_margins: &Range<usize>,
+        num_rows: usize,
+    ) {
+        if left_and_right_margins.start == 0 && left_and_right_margins.end >= self.physical_cols {
+            return self.scroll_down(scroll_region, num_rows);
+        }
+        let phys_scroll = self.phys_range(scroll_region);
+        let num_rows = num_rows.min(phys_scroll.end - phys_scroll.start);
+        debug!(
+            "scroll_down_within_margins {:?} {:?} num_rows={}",
+            scroll_region, left_and_right_margins, num_rows
+        );
+
+        for dest in phys_scroll.clone().rev() {
+            let src = if dest >= phys_scroll.start + num_rows {
+                Some(dest - num_rows)
+            } else {
+                None
+            };
+            let cells = self.cells_within_margins(src, left_and_right_margins);
+            self.set_cells_within_margins(dest, left_and_right_margins, cells);
+        }
+    }
+
+    /// Returns a copy of the cells of the physical row idx that fall
+    /// within the margins, or blank cells if idx is None
+    fn cells_within_margins(
+        &self,
+        idx: Option<PhysRowIndex>,
+        left_and_right_margins: &Range<usize>,
+    ) -> Vec<Cell> {
+        match idx {
+            Some(idx) => {
+                let line = self.lines[idx].uncompressed();
+                left_and_right_margins
+                    .clone()
+                    .map(|x| line.cells().get(x).cloned().unwrap_or_default())
+                    .collect()
+            }
+            None => left_and_right_margins
+                .clone()
+                .map(|_| Cell::default())
+                .collect(),
+        }
+    }
+
+    fn set_cells_within_margins(
+        &mut self,
+        idx: PhysRowIndex,
+        left_and_right_margins: &Range<usize>,
+        cells: Vec<Cell>,
+    ) {
+        let line = self.line_mut(idx);
+        for (x, cell) in left_and_right_margins.clone().zip(cells) {
+            line.set_cell(x, cell);
+        }
+    }
+
+    /// Discards the lines in the scrollback, leaving only the
+    /// visible lines
+    pub fn erase_scrollback(&mut self) {
+        let to_clear = self.lines.len() - self.physical_rows;
+        self.lines.drain(0..to_clear);
+        self.stable_row_index_offset += to_clear;
     }
 
     /// ---------
@@ -2,8 +2,10 @@
 // and inclusive range
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 use super::{ScrollbackOrVisibleRowIndex, VisibleRowIndex};
+use crate::screen::Screen;
 use serde_derive::*;
 use std::ops::Range;
+use termwiz::cell::SemanticType;
 
 /// The x,y coordinates of either the start or end of a selection region
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -12,19 +14,163 @@ pub struct SelectionCoordinate {
     pub y: ScrollbackOrVisibleRowIndex,
 }
 
+/// Controls how the columns of a multi-line selection are computed
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SelectionMode {
+    /// The selection flows like text: from the start coordinate to
+    /// the end of its row, through any intervening rows, and then
+    /// from the start of the last row to the end coordinate.
+    Cell,
+    /// The selection is a rectangle with the start and end coordinates
+    /// at opposite corners; every row selects the same span of columns.
+    Block,
+}
+
+impl Default for SelectionMode {
+    fn default() -> Self {
+        SelectionMode::Cell
+    }
+}
+
 /// Represents the selected text range.
 /// The end coordinates are inclusive.
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SelectionRange {
     pub start: SelectionCoordinate,
     pub end: SelectionCoordinate,
+    #[serde(default)]
+    pub mode: SelectionMode,
 }
 
 impl SelectionRange {
     /// Create a new range that starts at the specified location
     pub fn start(start: SelectionCoordinate) -> Self {
         let end = start;
-        Self { start, end }
+        Self {
+            start,
+            end,
+            mode: SelectionMode::Cell,
+        }
+    }
+
+    /// Create a new rectangular range that starts at the specified location
+    pub fn start_block(start: SelectionCoordinate) -> Self {
+        Self {
+            mode: SelectionMode::Block,
+            ..Self::start(start)
+        }
+    }
+
+    /// Create a range that covers the semantic zone (the prompt, the
+    /// command line input or the command output, as marked up by
+    /// OSC 133 sequences from the shell) that contains `start`.
+    /// Trailing blank rows are excluded from the range, so that
+    /// selecting the output of the most recent command doesn't
+    /// include the empty remainder of the screen.
+    pub fn zone_around(start: SelectionCoordinate, screen: &Screen) -> Self {
+        let lines = &screen.lines;
+        let idx = screen.scrollback_or_visible_row(start.y);
+        let zone_type = |row: usize, col: usize| {
+            lines[row]
+                .cell_attrs(col)
+                .map(|attrs| attrs.semantic_type())
+                .unwrap_or_default()
+        };
+        let last_col = |row: usize| lines[row].len().saturating_sub(1);
+
+        let x = start.x.min(last_col(idx));
+        let semantic_type = zone_type(idx, x);
+
+        let (mut first_row, mut first_col) = (idx, x);
+        loop {
+            let (row, col) = if first_col > 0 {
+                (first_row, first_col - 1)
+            } else if first_row > 0 {
+                (first_row - 1, last_col(first_row - 1))
+            } else {
+                break;
+            };
+            if zone_type(row, col) != semantic_type {
+                break;
+            }
+            first_row = row;
+            first_col = col;
+        }
+
+        let (mut last_row, mut end_col) = (idx, x);
+        loop {
+            let (row, col) = if end_col < last_col(last_row) {
+                (last_row, end_col + 1)
+            } else if last_row + 1 < lines.len() {
+                (last_row + 1, 0)
+            } else {
+                break;
+            };
+            if zone_type(row, col) != semantic_type {
+                break;
+            }
+            last_row = row;
+            end_col = col;
+        }
+
+        while last_row > first_row.max(idx) && lines[last_row].as_str().trim().is_empty() {
+            last_row -= 1;
+            end_col = last_col(last_row);
+        }
+
+        let to_y =
+            |row: usize| start.y + (row as isize - idx as isize) as ScrollbackOrVisibleRowIndex;
+        Self::start(SelectionCoordinate {
+            x: first_col,
+            y: to_y(first_row),
+        })
+        .extend(SelectionCoordinate {
+            x: end_col,
+            y: to_y(last_row),
+        })
+    }
+
+    /// Returns a copy of the selection with its rows shifted by `delta`
+    /// so that it continues to refer to the same text after rows have
+    /// moved relative to the top of the visible screen; for example,
+    /// when lines are scrolled into the scrollback or the screen is
+    /// resized.
+    /// `first_row` is the row index of the top of the scrollback.  Any
+    /// portion of the selection that has moved above it has been
+    /// discarded from the scrollback and is trimmed away; if nothing
+    /// remains then `None` is returned.
+    pub fn adjust_for_scrollback_trim(
+        &self,
+        delta: ScrollbackOrVisibleRowIndex,
+        first_row: ScrollbackOrVisibleRowIndex,
+    ) -> Option<Self> {
+        let block = self.is_block();
+        let adjust = |coord: SelectionCoordinate| {
+            let y = coord.y + delta;
+            if y < first_row {
+                SelectionCoordinate {
+                    x: if block { coord.x } else { 0 },
+                    y: first_row,
+                }
+            } else {
+                SelectionCoordinate { x: coord.x, y }
+            }
+        };
+
+        if self.start.y.max(self.end.y) + delta < first_row {
+            return None;
+        }
+
+        Some(Self {
+            start: adjust(self.start),
+            end: adjust(self.end),
+            mode: self.mode,
+        })
+    }
+
+    /// Returns true if this is a rectangular selection
+    pub fn is_block(&self) -> bool {
+        self.mode == SelectionMode::Block
     }
 
     /// Returns a modified version of the selection that is adjusted
@@ -50,6 +196,7 @@ impl SelectionRange {
                     .min(offset + height as ScrollbackOrVisibleRowIndex)
                     - offset,
             },
+            mode: self.mode,
         }
     }
 
@@ -58,6 +205,7 @@ impl SelectionRange {
         Self {
             start: self.start,
             end,
+            mode: self.mode,
         }
     }
 
@@ -70,6 +218,7 @@ impl SelectionRange {
             Self {
                 start: self.end,
                 end: self.start,
+                mode: self.mode,
             }
         }
     }
@@ -97,8 +246,9 @@ impl SelectionRange {
         );
         if row < self.start.y || row > self.end.y {
             0..0
-        } else if self.start.y == self.end.y {
-            // A single line selection
+        } else if self.start.y == self.end.y || self.is_block() {
+            // A single line selection, or a block selection in
+            // which every row has the same span
             if self.start.x <= self.end.x {
                 self.start.x..self.end.x.saturating_add(1)
             } else {
@@ -1,6 +1,24 @@
 use super::*;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use termwiz::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, CSI};
 use termwiz::escape::parser::Parser;
+use termwiz::escape::{Action, DeviceControlMode};
+
+/// If the application doesn't complete a synchronized update within
+/// this time, the deferred output is presented anyway
+const SYNCHRONIZED_UPDATE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The maximum number of actions that are deferred by a synchronized
+/// update before the output is presented anyway
+const SYNCHRONIZED_UPDATE_MAX_ACTIONS: usize = 1_000_000;
+
+/// Receives the device control strings (DCS) that are not
+/// handled by the terminal itself, such as the notifications
+/// sent by tmux when it is running in control mode
+pub trait DeviceControlHandler {
+    fn handle_device_control(&mut self, control: DeviceControlMode);
+}
 
 pub trait Clipboard {
     fn get_contents(&self) -> anyhow::Result<String>;
@@ -31,6 +49,9 @@ pub trait TerminalHost {
     /// Called when a URL is clicked
     fn click_link(&mut self, link: &Arc<Hyperlink>);
 
+    /// Called when the BEL control code is received
+    fn bell(&mut self) {}
+
     /// Switch to a specific tab
     fn activate_tab(&mut self, _tab: usize) {}
 
@@ -50,11 +71,84 @@ pub trait TerminalHost {
     fn reset_font_size(&mut self) {}
 }
 
+/// The actions deferred while the application is performing a
+/// synchronized update
+struct SynchronizedUpdate {
+    started: Instant,
+    actions: Vec<Action>,
+}
+
+impl SynchronizedUpdate {
+    fn is_overdue(&self) -> bool {
+        self.actions.len() >= SYNCHRONIZED_UPDATE_MAX_ACTIONS
+            || self.started.elapsed() >= SYNCHRONIZED_UPDATE_TIMEOUT
+    }
+}
+
+/// Requests to begin or end a synchronized update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyncRequest {
+    Begin,
+    End,
+    /// DECRQM for DEC private mode 2026, which is answered immediately,
+    /// even during a synchronized update
+    Query,
+    /// Part of a `DCS = 1 s` or `DCS = 2 s` sequence that carries
+    /// no meaning on its own
+    Ignore,
+}
+
+/// Recognizes the requests to begin and end synchronized updates,
+/// which may be made either by setting DEC private mode 2026, or by
+/// the `DCS = 1 s` (BSU) and `DCS = 2 s` (ESU) sequences.
+/// `sync_dcs` tracks the parameter of a BSU/ESU sequence that is in
+/// progress.
+fn sync_request(action: &Action, sync_dcs: &mut Option<i64>) -> Option<SyncRequest> {
+    match action {
+        Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+            DecPrivateModeCode::SynchronizedOutput,
+        )))) => Some(SyncRequest::Begin),
+        Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+            DecPrivateModeCode::SynchronizedOutput,
+        )))) => Some(SyncRequest::End),
+        Action::CSI(CSI::Mode(Mode::QueryDecPrivateMode(DecPrivateMode::Code(
+            DecPrivateModeCode::SynchronizedOutput,
+        )))) => Some(SyncRequest::Query),
+        Action::DeviceControl(mode) => match &**mode {
+            DeviceControlMode::Enter {
+                params,
+                intermediates,
+                byte: b's',
+                ..
+            } if intermediates.as_slice() == b"=" => {
+                *sync_dcs = Some(params.first().cloned().unwrap_or(0));
+                Some(SyncRequest::Ignore)
+            }
+            DeviceControlMode::Data(_) if sync_dcs.is_some() => Some(SyncRequest::Ignore),
+            DeviceControlMode::Exit => match sync_dcs.take() {
+                Some(1) => Some(SyncRequest::Begin),
+                Some(2) => Some(SyncRequest::End),
+                Some(_) => Some(SyncRequest::Ignore),
+                None => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 pub struct Terminal {
     /// The terminal model/state
     state: TerminalState,
     /// Baseline terminal escape sequence parser
     parser: Parser,
+    /// While the application is performing a synchronized update,
+    /// its output is deferred here so that it can be applied to the
+    /// model all at once when the update is complete
+    synchronized_update: Option<SynchronizedUpdate>,
+    /// The parameter of a BSU/ESU device control sequence that is
+    /// being parsed
+    sync_dcs: Option<i64>,
 }
 
 impl Deref for Terminal {
@@ -88,15 +182,75 @@ impl Terminal {
                 config,
             ),
             parser: Parser::new(),
+            synchronized_update: None,
+            sync_dcs: None,
         }
     }
 
     /// Feed the terminal parser a slice of bytes of input.
+    /// While the application is performing a synchronized update,
+    /// the effect of the input is deferred until the update is
+    /// complete, or until it has taken too long.  Note that a deferred
+    /// update that has taken too long is only applied when more input
+    /// arrives.
     pub fn advance_bytes<B: AsRef<[u8]>>(&mut self, bytes: B, host: &mut dyn TerminalHost) {
         let bytes = bytes.as_ref();
 
+        let mut actions = vec![];
+        self.parser.parse(bytes, |action| actions.push(action));
+
         let mut performer = Performer::new(&mut self.state, host);
 
-        self.parser.parse(bytes, |action| performer.perform(action));
+        for action in actions {
+            match sync_request(&action, &mut self.sync_dcs) {
+                Some(SyncRequest::Begin) => {
+                    if self.synchronized_update.is_none() {
+                        self.synchronized_update = Some(SynchronizedUpdate {
+                            started: Instant::now(),
+                            actions: vec![],
+                        });
+                    }
+                    continue;
+                }
+                Some(SyncRequest::End) => {
+                    if let Some(update) = self.synchronized_update.take() {
+                        for action in update.actions {
+                            performer.perform(action);
+                        }
+                    }
+                    continue;
+                }
+                Some(SyncRequest::Query) => {
+                    let status = if self.synchronized_update.is_some() {
+                        1
+                    } else {
+                        2
+                    };
+                    write!(performer.host.writer(), "\x1b[?2026;{}$y", status).ok();
+                    continue;
+                }
+                Some(SyncRequest::Ignore) => continue,
+                None => {}
+            }
+
+            match self.synchronized_update.as_mut() {
+                Some(update) if !update.is_overdue() => update.actions.push(action),
+                Some(_) => {
+                    // Give up on the synchronized update
+                    let update = self.synchronized_update.take().unwrap();
+                    for action in update.actions {
+                        performer.perform(action);
+                    }
+                    performer.perform(action);
+                }
+                None => performer.perform(action),
+            }
+        }
+    }
+
+    /// Returns true if the application is in the middle of
+    /// a synchronized update
+    pub fn is_synchronized_update_active(&self) -> bool {
+        self.synchronized_update.is_some()
     }
 }
@@ -3,21 +3,29 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 use super::*;
 use crate::color::ColorPalette;
+use crate::config::is_double_click_word;
+use crate::export::{export_lines, ExportFormat};
 use anyhow::bail;
-use image::{self, GenericImageView};
+use image::{self, DynamicImage, GenericImageView, RgbaImage};
 use log::{debug, error};
 use ordered_float::NotNan;
+use std::borrow::Cow;
 use std::fmt::Write;
 use std::sync::Arc;
 use termwiz::escape::csi::{
     Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
     EraseInLine, Mode, Sgr, TerminalMode, TerminalModeCode, Window,
 };
-use termwiz::escape::osc::{ChangeColorPair, ColorOrQuery, ITermFileData, ITermProprietary};
-use termwiz::escape::{Action, ControlCode, Esc, EscCode, OneBased, OperatingSystemCommand, CSI};
+use termwiz::escape::osc::{
+    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermFileData, ITermProprietary,
+};
+use termwiz::escape::{
+    Action, ControlCode, DeviceControlMode, Esc, EscCode, OneBased, OperatingSystemCommand, Sixel,
+    CSI,
+};
 use termwiz::hyperlink::Rule as HyperlinkRule;
 use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
-use termwiz::surface::CursorShape;
+use termwiz::surface::{CursorShape, SequenceNo};
 
 struct TabStop {
     tabs: Vec<bool>,
@@ -60,11 +68,13 @@ impl TabStop {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 struct SavedCursor {
     position: CursorPosition,
     wrap_next: bool,
     insert: bool,
+    pen: CellAttributes,
+    dec_origin_mode: bool,
 }
 
 struct ScreenOrAlt {
@@ -76,6 +86,10 @@ struct ScreenOrAlt {
     alt_screen_is_active: bool,
     saved_cursor: Option<SavedCursor>,
     alt_saved_cursor: Option<SavedCursor>,
+    /// Each screen has its own scroll region, so that a full screen
+    /// application can't leave the primary screen with a stale one
+    scroll_region: Range<VisibleRowIndex>,
+    alt_scroll_region: Range<VisibleRowIndex>,
 }
 
 impl Deref for ScreenOrAlt {
@@ -115,12 +129,49 @@ impl ScreenOrAlt {
             alt_screen_is_active: false,
             saved_cursor: None,
             alt_saved_cursor: None,
+            scroll_region: 0..physical_rows as VisibleRowIndex,
+            alt_scroll_region: 0..physical_rows as VisibleRowIndex,
         }
     }
 
-    pub fn resize(&mut self, physical_rows: usize, physical_cols: usize) {
-        self.screen.resize(physical_rows, physical_cols);
-        self.alt_screen.resize(physical_rows, physical_cols);
+    /// Resizes both screens.  `cursor` and `positions` refer to the
+    /// active screen and are adjusted as described by `Screen::resize`;
+    /// the inactive screen is resized around its saved cursor.
+    /// Returns the new cursor position.
+    pub fn resize(
+        &mut self,
+        physical_rows: usize,
+        physical_cols: usize,
+        cursor: CursorPosition,
+        positions: &mut [(PhysRowIndex, usize)],
+    ) -> CursorPosition {
+        let (active, inactive, inactive_saved_cursor) = if self.alt_screen_is_active {
+            (
+                &mut self.alt_screen,
+                &mut self.screen,
+                &mut self.saved_cursor,
+            )
+        } else {
+            (
+                &mut self.screen,
+                &mut self.alt_screen,
+                &mut self.alt_saved_cursor,
+            )
+        };
+        let inactive_cursor = inactive_saved_cursor
+            .as_ref()
+            .map(|saved| saved.position)
+            .unwrap_or_default();
+        let inactive_cursor =
+            inactive.resize(physical_rows, physical_cols, inactive_cursor, &mut []);
+        if let Some(saved) = inactive_saved_cursor.as_mut() {
+            saved.position = inactive_cursor;
+        }
+        let cursor = active.resize(physical_rows, physical_cols, cursor, positions);
+
+        self.scroll_region = 0..physical_rows as VisibleRowIndex;
+        self.alt_scroll_region = 0..physical_rows as VisibleRowIndex;
+        cursor
     }
 
     pub fn activate_alt_screen(&mut self) {
@@ -142,6 +193,23 @@ impl ScreenOrAlt {
             &mut self.saved_cursor
         }
     }
+
+    /// Returns the scroll region of the active screen
+    pub fn scroll_region(&self) -> &Range<VisibleRowIndex> {
+        if self.alt_screen_is_active {
+            &self.alt_scroll_region
+        } else {
+            &self.scroll_region
+        }
+    }
+
+    pub fn set_scroll_region(&mut self, region: Range<VisibleRowIndex>) {
+        if self.alt_screen_is_active {
+            self.alt_scroll_region = region;
+        } else {
+            self.scroll_region = region;
+        }
+    }
 }
 
 pub struct TerminalState {
@@ -162,8 +230,18 @@ pub struct TerminalState {
     /// If true, writing a character inserts a new cell
     insert: bool,
 
-    /// The scroll region
-    scroll_region: Range<VisibleRowIndex>,
+    /// DECOM: when set, cursor addressing is relative to the scroll
+    /// region and the left/right margins
+    dec_origin_mode: bool,
+
+    /// DECLRMM: when set, DECSLRM may be used to set the left and
+    /// right margins
+    dec_left_and_right_margin_mode: bool,
+
+    /// The columns that text is confined to when scrolling,
+    /// inserting and deleting lines, and wrapping.  Spans the
+    /// full width of the screen unless set by DECSLRM.
+    left_and_right_margins: Range<usize>,
 
     /// When set, modifies the sequence of bytes sent for keys
     /// designated as cursor keys.  This includes various navigation
@@ -178,9 +256,21 @@ pub struct TerminalState {
     /// designated marker characters.
     bracketed_paste: bool,
 
+    /// When set, changes in the focus of the terminal are reported
+    /// to the application
+    focus_tracking: bool,
+
     /// SGR style mouse tracking and reporting is enabled
+    mouse_tracking: bool,
     sgr_mouse: bool,
     button_event_mouse: bool,
+    any_event_mouse: bool,
+    /// X10 compatible reporting of button presses is enabled
+    x10_mouse: bool,
+    /// The alternative encodings for mouse reports; SGR takes
+    /// precedence over urxvt, which takes precedence over UTF-8
+    utf8_mouse: bool,
+    urxvt_mouse: bool,
     current_mouse_button: MouseButton,
     mouse_position: CursorPosition,
     cursor_visible: bool,
@@ -208,6 +298,9 @@ pub struct TerminalState {
     selection_start: Option<SelectionCoordinate>,
     /// Holds the not-normalized selection range.
     selection_range: Option<SelectionRange>,
+    /// True if dragging forms a rectangular block selection, as
+    /// requested by `MouseSelectionMode::Block`
+    block_selection: bool,
 
     tabs: TabStop,
 
@@ -216,12 +309,22 @@ pub struct TerminalState {
 
     /// The terminal title string
     title: String,
+    /// The `file://` url most recently set via OSC 7
+    current_dir: Option<String>,
     palette: Option<ColorPalette>,
 
     pixel_width: usize,
     pixel_height: usize,
 
     clipboard: Option<Arc<dyn Clipboard>>,
+    device_control_handler: Option<Box<dyn DeviceControlHandler>>,
+    /// The hex encoded capability names of an XTGETTCAP request that
+    /// is being parsed
+    xtgettcap: Option<Vec<u8>>,
+
+    /// The sequence number most recently assigned to changed lines
+    /// by `current_seqno`
+    seqno: SequenceNo,
 }
 
 fn encode_modifiers(mods: KeyModifiers) -> u8 {
@@ -248,6 +351,16 @@ fn csi_u_encode(buf: &mut String, c: char, mods: KeyModifiers) -> Result<(), Err
     Ok(())
 }
 
+/// The largest number of pixels that an inline image may be scaled to
+const MAX_IMAGE_PIXELS: usize = 4096 * 4096;
+
+/// Round a pixel dimension up to a whole number of cells,
+/// occupying at least one cell
+fn round_up_to_cell(value: u32, cell_size: usize) -> u32 {
+    let cell_size = cell_size as u32;
+    ((value + cell_size - 1) / cell_size).max(1) * cell_size
+}
+
 /// characters that when masked for CTRL could be an ascii control character
 /// or could be a key that a user legitimately wants to process in their
 /// terminal application
@@ -275,14 +388,22 @@ impl TerminalState {
             screen,
             pen: CellAttributes::default(),
             cursor: CursorPosition::default(),
-            scroll_region: 0..physical_rows as VisibleRowIndex,
             wrap_next: false,
             insert: false,
+            dec_origin_mode: false,
+            dec_left_and_right_margin_mode: false,
+            left_and_right_margins: 0..physical_cols,
             application_cursor_keys: false,
             application_keypad: false,
             bracketed_paste: false,
+            focus_tracking: false,
+            mouse_tracking: false,
             sgr_mouse: false,
             button_event_mouse: false,
+            any_event_mouse: false,
+            x10_mouse: false,
+            utf8_mouse: false,
+            urxvt_mouse: false,
             cursor_visible: true,
             dec_line_drawing_mode: false,
             current_mouse_button: MouseButton::None,
@@ -292,14 +413,19 @@ impl TerminalState {
             viewport_offset: 0,
             selection_range: None,
             selection_start: None,
+            block_selection: false,
             tabs: TabStop::new(physical_cols, 8),
             hyperlink_rules,
             hyperlink_rules_generation,
             title: "wezterm".to_string(),
+            current_dir: None,
             palette: None,
             pixel_height,
             pixel_width,
             clipboard: None,
+            device_control_handler: None,
+            xtgettcap: None,
+            seqno: 0,
         }
     }
 
@@ -307,10 +433,20 @@ impl TerminalState {
         self.clipboard.replace(Arc::clone(clipboard));
     }
 
+    pub fn set_device_control_handler(&mut self, handler: Box<dyn DeviceControlHandler>) {
+        self.device_control_handler.replace(handler);
+    }
+
     pub fn get_title(&self) -> &str {
         &self.title
     }
 
+    /// Returns the `file://` url of the current working directory,
+    /// if the application has reported it via OSC 7
+    pub fn get_current_dir(&self) -> Option<&str> {
+        self.current_dir.as_ref().map(String::as_str)
+    }
+
     /// Returns a copy of the palette.
     /// By default we don't keep a copy in the terminal state,
     /// preferring to take the config values from the users
@@ -343,24 +479,44 @@ impl TerminalState {
         &mut self.screen
     }
 
+    /// Renders the scrollback and the visible portion of the current
+    /// screen in the requested format
+    pub fn export_scrollback(&self, format: ExportFormat) -> String {
+        export_lines(
+            self.screen().lines.iter(),
+            format,
+            &self.palette(),
+            self.get_title(),
+        )
+    }
+
     pub fn get_selection_text(&self) -> String {
         let mut s = String::new();
 
         if let Some(sel) = self.selection_range.as_ref().map(|r| r.normalize()) {
             let screen = self.screen();
+            let trim = self.config.selection_trim_trailing_whitespace();
+            let join = self.config.selection_join_wrapped_lines();
             let mut last_was_wrapped = false;
             for y in sel.rows() {
                 let idx = screen.scrollback_or_visible_row(y);
+                let line = screen.lines[idx].uncompressed();
                 let cols = sel.cols_for_row(y);
-                let last_col_idx = cols.end.min(screen.lines[idx].cells().len()) - 1;
+                let last_col_idx = cols.end.min(line.cells().len()) - 1;
                 if !s.is_empty() && !last_was_wrapped {
                     s.push('\n');
                 }
-                s.push_str(screen.lines[idx].columns_as_str(cols).trim_end());
+                let text = line.columns_as_str(cols);
+                s.push_str(if trim { text.trim_end() } else { &text });
 
-                let last_cell = &screen.lines[idx].cells()[last_col_idx];
+                let last_cell = &line.cells()[last_col_idx];
+                // A block selection is a column of text; never join
+                // its rows together, even if the lines are wrapped.
                 // TODO: should really test for any unicode whitespace
-                last_was_wrapped = last_cell.attrs().wrapped() && last_cell.str() != " ";
+                last_was_wrapped = join
+                    && !sel.is_block()
+                    && last_cell.attrs().wrapped()
+                    && last_cell.str() != " ";
             }
         }
 
@@ -371,7 +527,10 @@ impl TerminalState {
     fn dirty_selection_lines(&mut self) {
         if let Some(sel) = self.selection_range.as_ref().map(|r| r.normalize()) {
             let screen = self.screen_mut();
-            for y in screen.scrollback_or_visible_range(&sel.rows()) {
+            // The selection may extend past the bottom of the screen
+            // while it is being adjusted for a resize
+            let rows = screen.scrollback_or_visible_range(&sel.rows());
+            for y in rows.start..rows.end.min(screen.lines.len()) {
                 screen.line_mut(y).set_dirty();
             }
         }
@@ -448,8 +607,8 @@ impl TerminalState {
         match self.screen.lines.get_mut(idx) {
             Some(ref mut line) => {
                 line.scan_and_create_hyperlinks(rules);
-                match line.cells().get(x) {
-                    Some(cell) => cell.attrs().hyperlink.as_ref().cloned(),
+                match line.cell_attrs(x) {
+                    Some(attrs) => attrs.hyperlink().cloned(),
                     None => None,
                 }
             }
@@ -457,12 +616,33 @@ impl TerminalState {
         }
     }
 
-    /// Invalidate rows that have hyperlinks
-    fn invalidate_hyperlinks(&mut self) {
+    /// Invalidate the rows in the viewport that contain any of the
+    /// specified links, so that a change in the hover highlight only
+    /// repaints the rows that it affects.
+    fn invalidate_hyperlinks(&mut self, links: &[Arc<Hyperlink>]) {
+        if links.is_empty() {
+            return;
+        }
+        let viewport_offset = self.viewport_offset as usize;
         let screen = self.screen_mut();
-        for line in &mut screen.lines {
-            if line.has_hyperlink() {
-                line.set_dirty();
+        let top = screen
+            .lines
+            .len()
+            .saturating_sub(screen.physical_rows + viewport_offset);
+        let end = (top + screen.physical_rows).min(screen.lines.len());
+        for idx in top..end {
+            let line = &screen.lines[idx];
+            if !line.has_hyperlink() {
+                continue;
+            }
+            let contains_link = (0..line.len()).any(|idx| {
+                match line.cell_attrs(idx).and_then(|attrs| attrs.hyperlink()) {
+                    Some(this) => links.iter().any(|link| this.is_same_link(link)),
+                    None => false,
+                }
+            });
+            if contains_link {
+                screen.line_mut(idx).set_dirty();
             }
         }
     }
@@ -481,8 +661,8 @@ impl TerminalState {
             let screen = self.screen_mut();
             for line in &mut screen.lines {
                 line.invalidate_implicit_hyperlinks();
-                line.set_dirty();
             }
+            screen.make_all_lines_dirty();
         }
     }
 
@@ -493,8 +673,23 @@ impl TerminalState {
         let line_idx = self.mouse_position.y as ScrollbackOrVisibleRowIndex
             - self.viewport_offset as ScrollbackOrVisibleRowIndex;
         let x = self.mouse_position.x;
-        self.current_highlight = self.hyperlink_for_cell(x, line_idx);
-        self.invalidate_hyperlinks();
+        let highlight = self.hyperlink_for_cell(x, line_idx);
+
+        let changed = match (&highlight, &self.current_highlight) {
+            (Some(this), Some(previous)) => !this.is_same_link(previous),
+            (None, None) => false,
+            _ => true,
+        };
+        if !changed {
+            return;
+        }
+
+        let previous = std::mem::replace(&mut self.current_highlight, highlight);
+        let links: Vec<Arc<Hyperlink>> = previous
+            .into_iter()
+            .chain(self.current_highlight.iter().cloned())
+            .collect();
+        self.invalidate_hyperlinks(&links);
     }
 
     fn set_clipboard_contents(&self, text: Option<String>) -> anyhow::Result<()> {
@@ -504,6 +699,17 @@ impl TerminalState {
         Ok(())
     }
 
+    /// Copies a selection that was just made (or cleared, if `text` is
+    /// None) with the mouse to the clipboard, unless `copy_on_select`
+    /// is disabled, in which case the clipboard is left alone
+    fn copy_selection(&self, text: Option<String>) -> anyhow::Result<()> {
+        if self.config.copy_on_select() {
+            self.set_clipboard_contents(text)
+        } else {
+            Ok(())
+        }
+    }
+
     fn get_clipboard_contents(&self) -> anyhow::Result<String> {
         if let Some(clip) = self.clipboard.as_ref() {
             clip.get_contents()
@@ -522,7 +728,7 @@ impl TerminalState {
             y: event.y as ScrollbackOrVisibleRowIndex
                 - self.viewport_offset as ScrollbackOrVisibleRowIndex,
         });
-        self.set_clipboard_contents(None)
+        self.copy_selection(None)
     }
 
     /// Double click to select a word on the current line
@@ -530,19 +736,18 @@ impl TerminalState {
         let y = event.y as ScrollbackOrVisibleRowIndex
             - self.viewport_offset as ScrollbackOrVisibleRowIndex;
         let idx = self.screen().scrollback_or_visible_row(y);
-        let selection_range = match self.screen().lines[idx]
-            .compute_double_click_range(event.x, |s| self.config.is_double_click_word(s))
-        {
-            DoubleClickRange::Range(click_range) => SelectionRange {
-                start: SelectionCoordinate {
-                    x: click_range.start,
-                    y,
-                },
-                end: SelectionCoordinate {
-                    x: click_range.end - 1,
-                    y,
-                },
-            },
+        // Fetch the boundary once rather than for every cell that is tested
+        let boundary = self.config.selection_word_boundary();
+        let is_word = |s: &str| is_double_click_word(s, &boundary);
+        let selection_range = match self.screen().lines[idx].cluster_range_for(event.x, is_word) {
+            DoubleClickRange::Range(click_range) => SelectionRange::start(SelectionCoordinate {
+                x: click_range.start,
+                y,
+            })
+            .extend(SelectionCoordinate {
+                x: click_range.end - 1,
+                y,
+            }),
             DoubleClickRange::RangeWithWrap(range_start) => {
                 let start_coord = SelectionCoordinate {
                     x: range_start.start,
@@ -555,9 +760,7 @@ impl TerminalState {
                 };
 
                 for y_cont in idx + 1..self.screen().lines.len() {
-                    match self.screen().lines[y_cont]
-                        .compute_double_click_range(0, |s| self.config.is_double_click_word(s))
-                    {
+                    match self.screen().lines[y_cont].cluster_range_for(0, is_word) {
                         DoubleClickRange::Range(range_end) => {
                             if range_end.end > range_end.start {
                                 end_coord = SelectionCoordinate {
@@ -576,10 +779,7 @@ impl TerminalState {
                     }
                 }
 
-                SelectionRange {
-                    start: start_coord,
-                    end: end_coord,
-                }
+                SelectionRange::start(start_coord).extend(end_coord)
             }
         };
 
@@ -595,32 +795,116 @@ impl TerminalState {
             "finish 2click selection {:?} '{}'",
             self.selection_range, text
         );
-        self.set_clipboard_contents(Some(text))
+        self.copy_selection(Some(text))
     }
 
     /// triple click to select the current line
-    fn mouse_triple_click_left(&mut self, event: MouseEvent) -> Result<(), Error> {
+    /// Returns the range of indices into `screen.lines` that make up
+    /// the logical line containing the physical row `idx`, following
+    /// the wrap flags backwards and forwards.
+    fn logical_line_rows(&self, idx: usize) -> Range<usize> {
+        let lines = &self.screen().lines;
+        let mut start = idx;
+        while start > 0 && lines[start - 1].last_cell_was_wrapped() {
+            start -= 1;
+        }
+        let mut end = idx;
+        while end + 1 < lines.len() && lines[end].last_cell_was_wrapped() {
+            end += 1;
+        }
+        start..end + 1
+    }
+
+    /// Returns the range of indices into `screen.lines` that make up
+    /// the paragraph containing the physical row `idx`; that is, the
+    /// run of non-blank rows bounded by blank rows.
+    fn paragraph_rows(&self, idx: usize) -> Range<usize> {
+        let lines = &self.screen().lines;
+        let is_blank = |i: usize| lines[i].as_str().trim().is_empty();
+        if is_blank(idx) {
+            return self.logical_line_rows(idx);
+        }
+        let mut start = idx;
+        while start > 0 && !is_blank(start - 1) {
+            start -= 1;
+        }
+        let mut end = idx;
+        while end + 1 < lines.len() && !is_blank(end + 1) {
+            end += 1;
+        }
+        start..end + 1
+    }
+
+    /// Select the full width of `rows`, which are indices into
+    /// `screen.lines` and must include the row clicked by `event`.
+    fn select_rows(
+        &mut self,
+        event: &MouseEvent,
+        rows: Range<usize>,
+        label: &str,
+    ) -> Result<(), Error> {
         let y = event.y as ScrollbackOrVisibleRowIndex
             - self.viewport_offset as ScrollbackOrVisibleRowIndex;
+        let idx = self.screen().scrollback_or_visible_row(y);
         self.selection_start = Some(SelectionCoordinate { x: event.x, y });
-        self.selection_range = Some(SelectionRange {
-            start: SelectionCoordinate { x: 0, y },
-            end: SelectionCoordinate {
+        self.selection_range = Some(
+            SelectionRange::start(SelectionCoordinate {
+                x: 0,
+                y: y - (idx - rows.start) as ScrollbackOrVisibleRowIndex,
+            })
+            .extend(SelectionCoordinate {
                 x: usize::max_value(),
-                y,
-            },
-        });
+                y: y + (rows.end - 1 - idx) as ScrollbackOrVisibleRowIndex,
+            }),
+        );
+        self.dirty_selection_lines();
+        let text = self.get_selection_text();
+        debug!(
+            "finish {} selection {:?} '{}'",
+            label, self.selection_range, text
+        );
+        self.copy_selection(Some(text))
+    }
+
+    /// Triple click to select the logical line, including any
+    /// rows that it wraps onto
+    fn mouse_triple_click_left(&mut self, event: MouseEvent) -> Result<(), Error> {
+        let y = event.y as ScrollbackOrVisibleRowIndex
+            - self.viewport_offset as ScrollbackOrVisibleRowIndex;
+        let idx = self.screen().scrollback_or_visible_row(y);
+        let rows = self.logical_line_rows(idx);
+        self.select_rows(&event, rows, "3click")
+    }
+
+    /// Alt + triple click to select the semantic zone; the prompt,
+    /// command line or command output that was clicked
+    fn mouse_semantic_zone_click_left(&mut self, event: MouseEvent) -> Result<(), Error> {
+        let y = event.y as ScrollbackOrVisibleRowIndex
+            - self.viewport_offset as ScrollbackOrVisibleRowIndex;
+        let start = SelectionCoordinate { x: event.x, y };
+        self.selection_start = Some(start);
+        self.selection_range = Some(SelectionRange::zone_around(start, self.screen()));
         self.dirty_selection_lines();
         let text = self.get_selection_text();
         debug!(
-            "finish 3click selection {:?} '{}'",
+            "finish zone selection {:?} '{}'",
             self.selection_range, text
         );
-        self.set_clipboard_contents(Some(text))
+        self.copy_selection(Some(text))
+    }
+
+    /// Quadruple click to select the paragraph
+    fn mouse_quadruple_click_left(&mut self, event: MouseEvent) -> Result<(), Error> {
+        let y = event.y as ScrollbackOrVisibleRowIndex
+            - self.viewport_offset as ScrollbackOrVisibleRowIndex;
+        let idx = self.screen().scrollback_or_visible_row(y);
+        let rows = self.paragraph_rows(idx);
+        self.select_rows(&event, rows, "4click")
     }
 
     fn mouse_press_left(&mut self, event: MouseEvent) -> Result<(), Error> {
         self.current_mouse_button = MouseButton::Left;
+        self.block_selection = false;
         self.dirty_selection_lines();
         match self.last_mouse_click.as_ref() {
             Some(&LastMouseClick { streak: 1, .. }) => {
@@ -629,14 +913,22 @@ impl TerminalState {
             Some(&LastMouseClick { streak: 2, .. }) => {
                 self.mouse_double_click_left(event)?;
             }
+            Some(&LastMouseClick { streak: 3, .. })
+                if event.modifiers.contains(KeyModifiers::ALT) =>
+            {
+                self.mouse_semantic_zone_click_left(event)?;
+            }
             Some(&LastMouseClick { streak: 3, .. }) => {
                 self.mouse_triple_click_left(event)?;
             }
+            Some(&LastMouseClick { streak: 4, .. }) => {
+                self.mouse_quadruple_click_left(event)?;
+            }
             // otherwise, clear out the selection
             _ => {
                 self.selection_range = None;
                 self.selection_start = None;
-                self.set_clipboard_contents(None)?;
+                self.copy_selection(None)?;
             }
         }
 
@@ -659,15 +951,17 @@ impl TerminalState {
                     "finish drag selection {:?} '{}'",
                     self.selection_range, text
                 );
-                self.set_clipboard_contents(Some(text))?;
+                self.copy_selection(Some(text))?;
             } else if let Some(link) = self.current_highlight() {
                 // If the button release wasn't a drag, consider
                 // whether it was a click on a hyperlink
-                host.click_link(&link);
+                if event.modifiers == self.config.hyperlink_click_modifiers() {
+                    host.click_link(&link);
+                }
             }
             Ok(())
         } else {
-            self.mouse_button_release(event, host.writer())
+            self.mouse_button_release(event, false, host.writer())
         }
     }
 
@@ -675,6 +969,76 @@ impl TerminalState {
         self.selection_range.map(|r| r.normalize())
     }
 
+    /// Copies the selected text, if any, to the clipboard, subject
+    /// to `copy_on_select`.
+    /// Returns false if nothing was selected.
+    fn complete_selection(&mut self) -> Result<bool, Error> {
+        let text = self.get_selection_text();
+        if text.is_empty() {
+            return Ok(false);
+        }
+        debug!("complete selection {:?} '{}'", self.selection_range, text);
+        self.copy_selection(Some(text))?;
+        Ok(true)
+    }
+
+    fn open_link_at_mouse(&self, host: &mut dyn TerminalHost) {
+        if let Some(link) = self.current_highlight() {
+            host.click_link(&link);
+        }
+    }
+
+    /// Performs the action that the gui resolved from its mouse
+    /// bindings for an event that isn't reported to the application
+    fn perform_mouse_action(
+        &mut self,
+        action: MouseAction,
+        event: MouseEvent,
+        host: &mut dyn TerminalHost,
+    ) -> Result<(), Error> {
+        match event.kind {
+            MouseEventKind::Press => self.current_mouse_button = event.button,
+            MouseEventKind::Release => self.current_mouse_button = MouseButton::None,
+            MouseEventKind::Move => {}
+        }
+
+        match action {
+            MouseAction::SelectText(mode) => {
+                self.dirty_selection_lines();
+                self.block_selection = mode == MouseSelectionMode::Block;
+                match mode {
+                    MouseSelectionMode::Cell | MouseSelectionMode::Block => {
+                        self.mouse_single_click_left(event)
+                    }
+                    MouseSelectionMode::Word => self.mouse_double_click_left(event),
+                    MouseSelectionMode::Line => self.mouse_triple_click_left(event),
+                    MouseSelectionMode::Paragraph => self.mouse_quadruple_click_left(event),
+                    MouseSelectionMode::SemanticZone => self.mouse_semantic_zone_click_left(event),
+                }
+            }
+            MouseAction::ExtendSelection => self.mouse_drag_left(event),
+            MouseAction::CompleteSelection => {
+                self.complete_selection()?;
+                Ok(())
+            }
+            MouseAction::CompleteSelectionOrOpenLink => {
+                if !self.complete_selection()? {
+                    self.open_link_at_mouse(host);
+                }
+                Ok(())
+            }
+            MouseAction::OpenLink => {
+                self.open_link_at_mouse(host);
+                Ok(())
+            }
+            MouseAction::Paste => {
+                let clip = self.get_clipboard_contents()?;
+                self.send_paste(&clip, host.writer())
+            }
+            MouseAction::Nop => Ok(()),
+        }
+    }
+
     fn mouse_drag_left(&mut self, event: MouseEvent) -> Result<(), Error> {
         // dragging out the selection region
         // TODO: may drag and change the viewport
@@ -685,6 +1049,10 @@ impl TerminalState {
                 - self.viewport_offset as ScrollbackOrVisibleRowIndex,
         };
         let sel = match self.selection_range.take() {
+            // Holding ALT when the drag begins selects a rectangular block
+            None if self.block_selection || event.modifiers.contains(KeyModifiers::ALT) => {
+                SelectionRange::start_block(self.selection_start.unwrap_or(end)).extend(end)
+            }
             None => SelectionRange::start(self.selection_start.unwrap_or(end)).extend(end),
             Some(sel) => sel.extend(end),
         };
@@ -694,9 +1062,101 @@ impl TerminalState {
         Ok(())
     }
 
+    /// Returns true if the application has enabled any of the
+    /// mouse reporting modes
+    fn mouse_reporting(&self) -> bool {
+        self.mouse_tracking || self.button_event_mouse || self.any_event_mouse || self.x10_mouse
+    }
+
+    /// Returns true if X10 compatible mouse reporting is enabled
+    /// and none of the other reporting modes override it
+    fn x10_mouse_only(&self) -> bool {
+        self.x10_mouse && !(self.mouse_tracking || self.button_event_mouse || self.any_event_mouse)
+    }
+
+    /// Encode a mouse report and send it to the application.
+    /// `button` is the base button code, to which the modifier bits
+    /// are added.  `release` indicates that the button was released,
+    /// which the legacy encoding can't attribute to a specific button.
+    fn send_mouse_report(
+        &self,
+        button: u8,
+        release: bool,
+        event: &MouseEvent,
+        writer: &mut dyn std::io::Write,
+    ) -> Result<(), Error> {
+        let x10 = self.x10_mouse_only();
+        if x10 && release {
+            // X10 mode only reports button presses
+            return Ok(());
+        }
+
+        let mut modifiers = 0;
+        if !x10 {
+            if event.modifiers.contains(KeyModifiers::SHIFT) {
+                modifiers |= 4;
+            }
+            if event.modifiers.contains(KeyModifiers::ALT) {
+                modifiers |= 8;
+            }
+            if event.modifiers.contains(KeyModifiers::CTRL) {
+                modifiers |= 16;
+            }
+        }
+
+        if self.sgr_mouse {
+            write!(
+                writer,
+                "\x1b[<{};{};{}{}",
+                button | modifiers,
+                event.x + 1,
+                event.y + 1,
+                if release { 'm' } else { 'M' }
+            )?;
+        } else if self.urxvt_mouse {
+            let button = if release { 3 } else { button };
+            write!(
+                writer,
+                "\x1b[{};{};{}M",
+                32 + (button | modifiers),
+                event.x + 1,
+                event.y + 1
+            )?;
+        } else {
+            let button = if release { 3 } else { button };
+            let mut buf = vec![0x1b, b'[', b'M', 32 + (button | modifiers)];
+            for &v in &[event.x, event.y as usize] {
+                if self.utf8_mouse {
+                    // The UTF-8 encoding offsets each value by 32 and
+                    // encodes it as a character of up to two bytes,
+                    // so it can represent coordinates up to 2015
+                    let c = std::char::from_u32((v + 1 + 32).min(0x7ff) as u32).unwrap_or(' ');
+                    let mut utf8 = [0u8; 4];
+                    buf.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                } else {
+                    // The legacy encoding offsets each value by 32 and
+                    // can only represent coordinates up to 223
+                    buf.push((v + 1 + 32).min(255) as u8);
+                }
+            }
+            writer.write_all(&buf)?;
+        }
+        Ok(())
+    }
+
+    fn mouse_button_code(button: MouseButton) -> Option<u8> {
+        match button {
+            MouseButton::Left => Some(0),
+            MouseButton::Middle => Some(1),
+            MouseButton::Right => Some(2),
+            _ => None,
+        }
+    }
+
     fn mouse_wheel(
         &mut self,
         event: MouseEvent,
+        send_event: bool,
         writer: &mut dyn std::io::Write,
     ) -> Result<(), Error> {
         let (report_button, scroll_delta, key) = match event.button {
@@ -705,11 +1165,11 @@ impl TerminalState {
             _ => bail!("unexpected mouse event {:?}", event),
         };
 
-        if self.sgr_mouse {
-            writer.write_all(
-                format!("\x1b[<{};{};{}M", report_button, event.x + 1, event.y + 1).as_bytes(),
-            )?;
-        } else if self.screen.is_alt_screen_active() {
+        if send_event {
+            self.send_mouse_report(report_button, false, &event, writer)?;
+        } else if self.screen.is_alt_screen_active()
+            && self.config.alternate_buffer_wheel_sends_arrow_keys()
+        {
             // Send cursor keys instead (equivalent to xterm's alternateScroll mode)
             self.key_down(key, KeyModifiers::default(), writer)?;
         } else {
@@ -721,19 +1181,13 @@ impl TerminalState {
     fn mouse_button_press(
         &mut self,
         event: MouseEvent,
+        send_event: bool,
         host: &mut dyn TerminalHost,
     ) -> Result<(), Error> {
         self.current_mouse_button = event.button;
-        if let Some(button) = match event.button {
-            MouseButton::Left => Some(0),
-            MouseButton::Middle => Some(1),
-            MouseButton::Right => Some(2),
-            _ => None,
-        } {
-            if self.sgr_mouse {
-                host.writer().write_all(
-                    format!("\x1b[<{};{};{}M", button, event.x + 1, event.y + 1).as_bytes(),
-                )?;
+        if let Some(button) = Self::mouse_button_code(event.button) {
+            if send_event {
+                self.send_mouse_report(button, false, &event, host.writer())?;
             } else if event.button == MouseButton::Middle {
                 let clip = self.get_clipboard_contents()?;
                 self.send_paste(&clip, host.writer())?
@@ -746,12 +1200,14 @@ impl TerminalState {
     fn mouse_button_release(
         &mut self,
         event: MouseEvent,
+        send_event: bool,
         writer: &mut dyn std::io::Write,
     ) -> Result<(), Error> {
         if self.current_mouse_button != MouseButton::None {
+            let button = Self::mouse_button_code(self.current_mouse_button);
             self.current_mouse_button = MouseButton::None;
-            if self.sgr_mouse {
-                write!(writer, "\x1b[<3;{};{}m", event.x + 1, event.y + 1)?;
+            if let (Some(button), true) = (button, send_event) {
+                self.send_mouse_report(button, true, &event, writer)?;
             }
         }
 
@@ -761,17 +1217,21 @@ impl TerminalState {
     fn mouse_move(
         &mut self,
         event: MouseEvent,
+        send_event: bool,
         writer: &mut dyn std::io::Write,
     ) -> Result<(), Error> {
-        if let Some(button) = match (self.current_mouse_button, self.button_event_mouse) {
-            (MouseButton::Left, true) => Some(32),
-            (MouseButton::Middle, true) => Some(33),
-            (MouseButton::Right, true) => Some(34),
-            (..) => None,
-        } {
-            if self.sgr_mouse {
-                write!(writer, "\x1b[<{};{};{}M", button, event.x + 1, event.y + 1)?;
-            }
+        if !send_event {
+            return Ok(());
+        }
+        // Motion is reported with 32 added to the button code; when
+        // no button is held, the code for "release" (3) is used.
+        let button = match Self::mouse_button_code(self.current_mouse_button) {
+            Some(button) if self.button_event_mouse || self.any_event_mouse => Some(button),
+            None if self.any_event_mouse => Some(3),
+            _ => None,
+        };
+        if let Some(button) = button {
+            self.send_mouse_report(32 + button, false, &event, writer)?;
         }
         Ok(())
     }
@@ -804,7 +1264,12 @@ impl TerminalState {
         }
 
         // First pass to figure out if we're messing with the selection
-        let send_event = self.sgr_mouse && !event.modifiers.contains(KeyModifiers::SHIFT);
+        // Holding down the bypass modifiers forces local handling of
+        // the mouse (eg: to make a selection) even though the
+        // application has requested mouse reporting.
+        let bypass = self.config.bypass_mouse_reporting_modifiers();
+        let bypassed = !bypass.is_empty() && event.modifiers.contains(bypass);
+        let send_event = self.mouse_reporting() && !bypassed;
 
         // Perform click counting
         if event.kind == MouseEventKind::Press {
@@ -816,6 +1281,9 @@ impl TerminalState {
         }
 
         if !send_event {
+            if let Some(action) = event.action {
+                return self.perform_mouse_action(action, event, host);
+            }
             match (event, self.current_mouse_button) {
                 (
                     MouseEvent {
@@ -860,19 +1328,19 @@ impl TerminalState {
                 kind: MouseEventKind::Press,
                 button: MouseButton::WheelDown(_),
                 ..
-            } => self.mouse_wheel(event, host.writer()),
+            } => self.mouse_wheel(event, send_event, host.writer()),
             MouseEvent {
                 kind: MouseEventKind::Press,
                 ..
-            } => self.mouse_button_press(event, host),
+            } => self.mouse_button_press(event, send_event, host),
             MouseEvent {
                 kind: MouseEventKind::Release,
                 ..
-            } => self.mouse_button_release(event, host.writer()),
+            } => self.mouse_button_release(event, send_event, host.writer()),
             MouseEvent {
                 kind: MouseEventKind::Move,
                 ..
-            } => self.mouse_move(event, host.writer()),
+            } => self.mouse_move(event, send_event, host.writer()),
         }
     }
 
@@ -880,6 +1348,24 @@ impl TerminalState {
         self.bracketed_paste
     }
 
+    /// Informs the terminal that it has gained or lost the focus.
+    /// If focus tracking is enabled, the change is reported to the
+    /// application via the `Write`-able object provided by the caller.
+    pub fn focus_changed(
+        &mut self,
+        focused: bool,
+        writer: &mut dyn std::io::Write,
+    ) -> Result<(), Error> {
+        if self.focus_tracking {
+            writer.write_all(if focused { b"\x1b[I" } else { b"\x1b[O" })?;
+        }
+        Ok(())
+    }
+
+    pub fn is_alt_screen_active(&self) -> bool {
+        self.screen.is_alt_screen_active()
+    }
+
     /// Send text to the terminal that is the result of pasting.
     /// If bracketed paste mode is enabled, the paste is enclosed
     /// in the bracketing, otherwise it is fed to the pty as-is.
@@ -1114,7 +1600,11 @@ impl TerminalState {
                         12 => "\x1b[24",
                         _ => bail!("unhandled fkey number {}", n),
                     };
-                    write!(buf, "{};{}~", intro, 1 + encode_modifiers(mods))?;
+                    if mods.is_empty() {
+                        write!(buf, "{}~", intro)?;
+                    } else {
+                        write!(buf, "{};{}~", intro, 1 + encode_modifiers(mods))?;
+                    }
                     buf.as_str()
                 }
             }
@@ -1156,12 +1646,46 @@ impl TerminalState {
         pixel_width: usize,
         pixel_height: usize,
     ) {
-        self.screen.resize(physical_rows, physical_cols);
-        self.scroll_region = 0..physical_rows as i64;
+        // The rows may move into the scrollback or be rewrapped to
+        // the new width; keep the selection in sync with the text
+        // that it covers by tracking its physical position.
+        let mut positions = vec![];
+        {
+            let screen = self.screen();
+            let to_phys =
+                |coord: SelectionCoordinate| (screen.scrollback_or_visible_row(coord.y), coord.x);
+            if let Some(sel) = self.selection_range.as_ref() {
+                positions.push(to_phys(sel.start));
+                positions.push(to_phys(sel.end));
+            }
+            if let Some(start) = self.selection_start {
+                positions.push(to_phys(start));
+            }
+        }
+
+        let cursor = self
+            .screen
+            .resize(physical_rows, physical_cols, self.cursor, &mut positions);
+        self.cursor = cursor;
+
+        let first_visible = self.screen().lines.len() - self.screen().physical_rows;
+        let mut coords = positions.into_iter().map(|(row, x)| SelectionCoordinate {
+            x,
+            y: row as ScrollbackOrVisibleRowIndex - first_visible as ScrollbackOrVisibleRowIndex,
+        });
+        if let Some(sel) = self.selection_range.as_mut() {
+            sel.start = coords.next().expect("selection start position");
+            sel.end = coords.next().expect("selection end position");
+        }
+        if let Some(start) = self.selection_start.as_mut() {
+            *start = coords.next().expect("selection anchor position");
+        }
+
         self.pixel_height = pixel_height;
         self.pixel_width = pixel_width;
         self.tabs.resize(physical_cols);
-        self.set_scroll_viewport(0);
+        self.left_and_right_margins = 0..physical_cols;
+        self.set_viewport_offset(0);
         // Ensure that the cursor is within the new bounds of the screen
         self.set_cursor_pos(&Position::Relative(0), &Position::Relative(0));
     }
@@ -1233,12 +1757,112 @@ impl TerminalState {
 
     /// When dealing with selection, mark a range of lines as dirty
     pub fn make_all_lines_dirty(&mut self) {
+        self.screen_mut().make_all_lines_dirty();
+    }
+
+    /// Mark the lines in the stable row range `lines` as dirty
+    pub fn make_stable_lines_dirty(&mut self, lines: Range<StableRowIndex>) {
         let screen = self.screen_mut();
-        for line in &mut screen.lines {
-            line.set_dirty();
+        for row in lines {
+            if let Some(idx) = screen.stable_row_to_phys(row) {
+                screen.line_mut(idx).set_dirty();
+            }
         }
     }
 
+    /// Returns the current sequence number.  Lines that have been
+    /// dirtied since the prior call are first assigned a new sequence
+    /// number, which allows any number of consumers to independently
+    /// track the changes since they last looked at the screen, rather
+    /// than competing over the dirty bits.
+    pub fn current_seqno(&mut self) -> SequenceNo {
+        let seqno = self.seqno + 1;
+        let mut changed = false;
+        let screen = self.screen_mut();
+        for idx in screen.take_dirty_range() {
+            if screen.lines[idx].update_last_change_seqno(seqno) {
+                changed = true;
+            }
+        }
+        if changed {
+            self.seqno = seqno;
+        }
+        self.seqno
+    }
+
+    /// Returns the range of rows that are displayed in the viewport
+    pub fn get_viewport_stable_rows(&self) -> Range<StableRowIndex> {
+        let screen = self.screen();
+        let top = screen.lines.len() - (screen.physical_rows + self.viewport_offset as usize);
+        let top = screen.phys_to_stable_row_index(top);
+        top..top + screen.physical_rows as StableRowIndex
+    }
+
+    /// Returns the rows on which a shell prompt begins, as marked up
+    /// by OSC 133 semantic zones, in ascending order.  A prompt that
+    /// spans several lines is reported as its first line.
+    pub fn get_prompt_stable_rows(&self) -> Vec<StableRowIndex> {
+        let screen = self.screen();
+        let mut rows = vec![];
+        let mut in_prompt = false;
+        for (idx, line) in screen.lines.iter().enumerate() {
+            let has_prompt = (0..line.len()).any(|col| {
+                line.cell_attrs(col)
+                    .map(|attrs| attrs.semantic_type() == SemanticType::Prompt)
+                    .unwrap_or(false)
+            });
+            if has_prompt && !in_prompt {
+                rows.push(screen.phys_to_stable_row_index(idx));
+            }
+            in_prompt = has_prompt;
+        }
+        rows
+    }
+
+    /// Returns the rows in `lines` that have changed after the
+    /// point described by `seqno`
+    pub fn get_changed_stable_rows(
+        &self,
+        lines: Range<StableRowIndex>,
+        seqno: SequenceNo,
+    ) -> Vec<StableRowIndex> {
+        let screen = self.screen();
+        lines
+            .filter(|&row| match screen.stable_row_to_phys(row) {
+                Some(idx) => screen.lines[idx].changed_since(seqno),
+                None => false,
+            })
+            .collect()
+    }
+
+    /// Returns the lines in `lines`.
+    /// The return value is a Vec<(row, line, selrange)>, where
+    /// selrange is the column range representing the selected
+    /// columns on this line.
+    /// Lines from the scrollback are returned in uncompressed form.
+    pub fn get_stable_lines(
+        &self,
+        lines: Range<StableRowIndex>,
+    ) -> Vec<(StableRowIndex, Cow<Line>, Range<usize>)> {
+        let screen = self.screen();
+        let selection = self.selection_range.map(|r| r.normalize());
+        let first_visible = screen.lines.len() - screen.physical_rows;
+
+        lines
+            .filter_map(|row| {
+                let idx = screen.stable_row_to_phys(row)?;
+                let selrange = match selection {
+                    None => 0..0,
+                    Some(sel) => sel.cols_for_row(
+                        idx as ScrollbackOrVisibleRowIndex
+                            - first_visible as ScrollbackOrVisibleRowIndex,
+                    ),
+                };
+                Some((row, screen.lines[idx].uncompressed(), selrange))
+            })
+            .collect()
+    }
+
     /// Returns the 0-based cursor position relative to the top left of
     /// the visible screen
     pub fn cursor_pos(&self) -> CursorPosition {
@@ -1258,9 +1882,33 @@ impl TerminalState {
         self.current_highlight.as_ref().cloned()
     }
 
+    /// Sets the cursor position for the cursor addressing sequences.
+    /// x and y are 0-based and, in DEC origin mode, absolute positions
+    /// are relative to the top of the scroll region and the left margin,
+    /// and the cursor is confined to the region and margins.
+    fn set_cursor_pos_with_origin(&mut self, x: &Position, y: &Position) {
+        if !self.dec_origin_mode {
+            return self.set_cursor_pos(x, y);
+        }
+        let scroll_region = self.screen.scroll_region().clone();
+        let margins = self.left_and_right_margins.clone();
+
+        let x = match *x {
+            Position::Relative(x) => self.cursor.x as i64 + x,
+            Position::Absolute(x) => margins.start as i64 + x,
+        };
+        let y = match *y {
+            Position::Relative(y) => self.cursor.y + y,
+            Position::Absolute(y) => scroll_region.start + y,
+        };
+
+        let x = x.min(margins.end as i64 - 1).max(margins.start as i64);
+        let y = y.min(scroll_region.end - 1).max(scroll_region.start);
+        self.set_cursor_pos(&Position::Absolute(x), &Position::Absolute(y));
+    }
+
     /// Sets the cursor position. x and y are 0-based and relative to the
     /// top left of the visible screen.
-    /// TODO: DEC origin mode impacts the interpreation of these
     fn set_cursor_pos(&mut self, x: &Position, y: &Position) {
         let x = match *x {
             Position::Relative(x) => (self.cursor.x as i64 + x).max(0),
@@ -1287,6 +1935,11 @@ impl TerminalState {
 
     pub fn set_scroll_viewport(&mut self, position: VisibleRowIndex) {
         self.clear_selection();
+        self.set_viewport_offset(position);
+    }
+
+    /// Moves the viewport without disturbing the selection
+    fn set_viewport_offset(&mut self, position: VisibleRowIndex) {
         let position = position.max(0);
 
         let rows = self.screen().physical_rows;
@@ -1305,6 +1958,35 @@ impl TerminalState {
         self.recompute_highlight();
     }
 
+    /// Discards the lines in the scrollback, leaving the visible
+    /// lines alone.  This is the effect of the xterm `CSI 3 J` sequence.
+    pub fn erase_scrollback(&mut self) {
+        self.clear_selection();
+        self.screen_mut().erase_scrollback();
+        self.set_scroll_viewport(0);
+    }
+
+    /// Discards the scrollback and clears the display, except for the
+    /// line holding the cursor, which is moved to the top of the display.
+    /// This is intended to be triggered by the user rather than by the
+    /// application running in the terminal, so the prompt being edited
+    /// remains visible.
+    pub fn erase_scrollback_and_viewport(&mut self) {
+        self.clear_selection();
+        let cursor_y = self.cursor.y;
+        {
+            let screen = self.screen_mut();
+            let rows = screen.physical_rows as VisibleRowIndex;
+            screen.scroll_up(&(0..rows), cursor_y as usize);
+            screen.erase_scrollback();
+            for y in 1..rows {
+                screen.clear_line(y, 0..usize::max_value(), &CellAttributes::default());
+            }
+        }
+        self.set_cursor_pos(&Position::Relative(0), &Position::Absolute(0));
+        self.set_scroll_viewport(0);
+    }
+
     /// Adjust the scroll position of the viewport by delta.
     /// Dirties the lines that are now in view.
     pub fn scroll_viewport(&mut self, delta: VisibleRowIndex) {
@@ -1313,25 +1995,95 @@ impl TerminalState {
     }
 
     fn scroll_up(&mut self, num_rows: usize) {
-        self.clear_selection();
-        let scroll_region = self.scroll_region.clone();
-        self.screen_mut().scroll_up(&scroll_region, num_rows)
+        let scroll_region = self.screen.scroll_region().clone();
+        let margins = self.left_and_right_margins.clone();
+        let physical_rows = self.screen().physical_rows as VisibleRowIndex;
+        let physical_cols = self.screen().physical_cols;
+        if scroll_region == (0..physical_rows) && margins == (0..physical_cols) {
+            // The whole screen moves up, so the selection can
+            // follow the text into the scrollback
+            let num_rows = num_rows.min(physical_rows as usize);
+            self.screen_mut().scroll_up(&scroll_region, num_rows);
+            self.adjust_selection(-(num_rows as ScrollbackOrVisibleRowIndex));
+        } else {
+            self.clear_selection_if_intersects_rows(
+                scroll_region.start as ScrollbackOrVisibleRowIndex
+                    ..scroll_region.end as ScrollbackOrVisibleRowIndex,
+            );
+            self.screen_mut()
+                .scroll_up_within_margins(&scroll_region, &margins, num_rows)
+        }
     }
 
     fn scroll_down(&mut self, num_rows: usize) {
-        self.clear_selection();
-        let scroll_region = self.scroll_region.clone();
-        self.screen_mut().scroll_down(&scroll_region, num_rows)
+        let scroll_region = self.screen.scroll_region().clone();
+        let margins = self.left_and_right_margins.clone();
+        self.clear_selection_if_intersects_rows(
+            scroll_region.start as ScrollbackOrVisibleRowIndex
+                ..scroll_region.end as ScrollbackOrVisibleRowIndex,
+        );
+        self.screen_mut()
+            .scroll_down_within_margins(&scroll_region, &margins, num_rows)
+    }
+
+    /// Returns the row index of the top of the scrollback
+    fn first_scrollback_row(&self) -> ScrollbackOrVisibleRowIndex {
+        let screen = self.screen();
+        -((screen.lines.len() - screen.physical_rows) as ScrollbackOrVisibleRowIndex)
+    }
+
+    /// Shift the selection by `delta` rows so that it continues to
+    /// refer to the same text after the rows have moved, trimming or
+    /// clearing it if that text has been discarded from the scrollback.
+    fn adjust_selection(&mut self, delta: ScrollbackOrVisibleRowIndex) {
+        if delta == 0 {
+            return;
+        }
+        let first_row = self.first_scrollback_row();
+        self.dirty_selection_lines();
+        self.selection_range = self
+            .selection_range
+            .and_then(|sel| sel.adjust_for_scrollback_trim(delta, first_row));
+        self.selection_start = self
+            .selection_start
+            .map(|start| SelectionCoordinate {
+                x: start.x,
+                y: start.y + delta,
+            })
+            .filter(|start| start.y >= first_row);
+        self.dirty_selection_lines();
+    }
+
+    /// Printing in the last column marks the cell as wrapped, in
+    /// anticipation of the text continuing on the next row.  If the
+    /// application explicitly moves to the next line instead, the row
+    /// isn't really wrapped, so clear that mark to avoid joining the
+    /// rows when selecting or rewrapping the text.
+    fn cancel_pending_wrap(&mut self) {
+        if !self.wrap_next {
+            return;
+        }
+        let x = self.cursor.x;
+        let y = self.cursor.y;
+        let screen = self.screen_mut();
+        let line_idx = screen.phys_row(y);
+        let line = screen.line_mut(line_idx);
+        if let Some(mut cell) = line.cells().get(x).cloned() {
+            if cell.attrs().wrapped() {
+                cell.attrs_mut().set_wrapped(false);
+                line.set_cell(x, cell);
+            }
+        }
     }
 
     fn new_line(&mut self, move_to_first_column: bool) {
         let x = if move_to_first_column {
-            0
+            self.left_and_right_margins.start
         } else {
             self.cursor.x
         };
         let y = self.cursor.y;
-        let y = if y == self.scroll_region.end - 1 {
+        let y = if y == self.screen.scroll_region().end - 1 {
             self.scroll_up(1);
             y
         } else {
@@ -1344,7 +2096,7 @@ impl TerminalState {
     /// If the cursor is at the bottom margin, the page scrolls up.
     fn c1_index(&mut self) {
         let y = self.cursor.y;
-        let y = if y == self.scroll_region.end - 1 {
+        let y = if y == self.screen.scroll_region().end - 1 {
             self.scroll_up(1);
             y
         } else {
@@ -1379,7 +2131,7 @@ impl TerminalState {
     /// scroll the region down.
     fn c1_reverse_index(&mut self) {
         let y = self.cursor.y;
-        let y = if y == self.scroll_region.start {
+        let y = if y == self.screen.scroll_region().start {
             self.scroll_down(1);
             y
         } else {
@@ -1389,10 +2141,7 @@ impl TerminalState {
     }
 
     fn set_hyperlink(&mut self, link: Option<Hyperlink>) {
-        self.pen.hyperlink = match link {
-            Some(hyperlink) => Some(Arc::new(hyperlink)),
-            None => None,
-        }
+        self.pen.set_hyperlink(link.map(Arc::new));
     }
 
     fn set_image(&mut self, image: ITermFileData) {
@@ -1422,10 +2171,18 @@ impl TerminalState {
         // Figure out the dimensions.
         let physical_cols = self.screen().physical_cols;
         let physical_rows = self.screen().physical_rows;
-        let cell_pixel_width = self.pixel_width / physical_cols;
-        let cell_pixel_height = self.pixel_height / physical_rows;
+        let (cell_pixel_width, cell_pixel_height) = self.cell_pixel_dimensions();
+        if cell_pixel_width == 0 || cell_pixel_height == 0 {
+            error!("Ignoring image because the cell size is unknown");
+            return;
+        }
 
-        let width = image.width.to_pixels(cell_pixel_width, physical_cols);
+        // Don't allow the image to be wider than the terminal
+        let max_pixel_width = physical_cols * cell_pixel_width;
+        let width = image
+            .width
+            .to_pixels(cell_pixel_width, physical_cols)
+            .map(|w| w.min(max_pixel_width));
         let height = image.height.to_pixels(cell_pixel_height, physical_rows);
 
         // Compute any Automatic dimensions
@@ -1435,47 +2192,102 @@ impl TerminalState {
                 decoded_image.height() as usize,
             ),
             (Some(w), None) => {
-                let scale = decoded_image.width() as f32 / w as f32;
+                let scale = w as f32 / decoded_image.width() as f32;
                 let h = decoded_image.height() as f32 * scale;
                 (w, h as usize)
             }
             (None, Some(h)) => {
-                let scale = decoded_image.height() as f32 / h as f32;
+                let scale = h as f32 / decoded_image.height() as f32;
                 let w = decoded_image.width() as f32 * scale;
                 (w as usize, h)
             }
             (Some(w), Some(h)) => (w, h),
         };
+        let width = width.max(1).min(max_pixel_width);
+        // Keep the scaled image within a reasonable memory budget
+        let height = height.max(1).min(MAX_IMAGE_PIXELS / width);
 
-        let width_in_cells = width / cell_pixel_width;
-        let height_in_cells = height / cell_pixel_height;
+        // The image occupies a whole number of cells
+        let available_pixel_width = round_up_to_cell(width as u32, cell_pixel_width);
+        let available_pixel_height = round_up_to_cell(height as u32, cell_pixel_height);
 
-        // TODO: defer this to the actual renderer
-        /*
-        let available_pixel_width = width_in_cells * cell_pixel_width;
-        let available_pixel_height = height_in_cells * cell_pixel_height;
-
-        let resized_image = if image.preserve_aspect_ratio {
-            let resized = decoded_image.resize(
-                available_pixel_width as u32,
-                available_pixel_height as u32,
-                image::FilterType::Lanczos3,
-            );
-            // Pad with black bars to preserve aspect ratio
-            // Assumption: new_rgba8 returns black/transparent pixels by default.
-            let dest = DynamicImage::new_rgba8(available_pixel_width, available_pixel_height);
-            dest.copy_from(resized, 0, 0);
-            dest
+        if decoded_image.dimensions() == (width as u32, height as u32)
+            && decoded_image.dimensions() == (available_pixel_width, available_pixel_height)
+        {
+            // No scaling or padding is required, so we can use the
+            // original data
+            let image_data = Arc::new(ImageData::with_raw_data(image.data));
+            self.assign_image_to_cells(width, height, image_data);
+            return;
+        }
+
+        let resized = if image.preserve_aspect_ratio {
+            // Fit within the requested size
+            decoded_image.resize(width as u32, height as u32, image::FilterType::Lanczos3)
         } else {
-            decoded_image.resize_exact(
-                available_pixel_width as u32,
-                available_pixel_height as u32,
-                image::FilterType::Lanczos3,
-            )
-        };
+            decoded_image.resize_exact(width as u32, height as u32, image::FilterType::Lanczos3)
+        }
+        .to_rgba();
+
+        // Pad with transparent pixels to fill out the cells
+        let mut dest = RgbaImage::new(available_pixel_width, available_pixel_height);
+        image::imageops::replace(&mut dest, &resized, 0, 0);
+
+        self.assign_rgba_image_to_cells(dest);
+
+        // FIXME: check cursor positioning in iterm
+        /*
+        self.set_cursor_pos(
+            &Position::Relative(width_in_cells as i64),
+            &Position::Relative(-(height_in_cells as i64)),
+        );
         */
+    }
 
-        let image_data = Arc::new(ImageData::with_raw_data(image.data));
+    /// Encode `image` and place it at the cursor position.
+    /// The dimensions of `image` are expected to be a multiple
+    /// of the cell size.
+    fn assign_rgba_image_to_cells(&mut self, image: RgbaImage) {
+        let (width, height) = image.dimensions();
+        let mut data = vec![];
+        if let Err(e) =
+            DynamicImage::ImageRgba8(image).write_to(&mut data, image::ImageOutputFormat::PNG)
+        {
+            error!("Unable to encode image: {}", e);
+            return;
+        }
+
+        let image_data = Arc::new(ImageData::with_raw_data(data));
+        self.assign_image_to_cells(width as usize, height as usize, image_data);
+    }
+
+    /// Returns the size of a cell in pixels, or `(0, 0)` if the
+    /// pixel dimensions of the terminal are not known
+    fn cell_pixel_dimensions(&self) -> (usize, usize) {
+        let physical_cols = self.screen().physical_cols;
+        let physical_rows = self.screen().physical_rows;
+        if physical_cols == 0 || physical_rows == 0 {
+            return (0, 0);
+        }
+        (
+            self.pixel_width / physical_cols,
+            self.pixel_height / physical_rows,
+        )
+    }
+
+    /// Fill the cells starting at the cursor position with slices of
+    /// an image that is `width` x `height` pixels in size, moving the
+    /// cursor down as each row of cells is assigned.
+    fn assign_image_to_cells(&mut self, width: usize, height: usize, image_data: Arc<ImageData>) {
+        let physical_cols = self.screen().physical_cols;
+        let physical_rows = self.screen().physical_rows;
+        let (cell_pixel_width, cell_pixel_height) = self.cell_pixel_dimensions();
+        if cell_pixel_width == 0 || cell_pixel_height == 0 {
+            error!("Unable to place image because the cell size is unknown");
+            return;
+        }
+        let width_in_cells = width / cell_pixel_width;
+        let height_in_cells = height / cell_pixel_height;
 
         let mut ypos = NotNan::new(0.0).unwrap();
         let cursor_x = self.cursor.x;
@@ -1514,14 +2326,35 @@ impl TerminalState {
             ypos += y_delta;
             self.new_line(false);
         }
+    }
 
-        // FIXME: check cursor positioning in iterm
-        /*
-        self.set_cursor_pos(
-            &Position::Relative(width_in_cells as i64),
-            &Position::Relative(-(height_in_cells as i64)),
-        );
-        */
+    /// Decode a sixel image and place it at the cursor position.
+    /// The image is drawn at its native pixel size, padded with
+    /// transparent pixels up to the next whole cell.
+    fn sixel(&mut self, sixel: Box<Sixel>) {
+        let (cell_pixel_width, cell_pixel_height) = self.cell_pixel_dimensions();
+        if cell_pixel_width == 0 || cell_pixel_height == 0 {
+            error!("Ignoring sixel image because the cell size is unknown");
+            return;
+        }
+
+        let decoded = match sixel.decode() {
+            Some(decoded) => decoded,
+            None => {
+                error!(
+                    "Ignoring sixel image with dimensions {:?}",
+                    sixel.dimensions()
+                );
+                return;
+            }
+        };
+
+        let width = round_up_to_cell(decoded.width(), cell_pixel_width);
+        let height = round_up_to_cell(decoded.height(), cell_pixel_height);
+        let mut padded = RgbaImage::new(width, height);
+        image::imageops::replace(&mut padded, &decoded, 0, 0);
+
+        self.assign_rgba_image_to_cells(padded);
     }
 
     fn perform_device(&mut self, dev: Device, host: &mut dyn TerminalHost) {
@@ -1529,6 +2362,11 @@ impl TerminalState {
             Device::DeviceAttributes(a) => error!("unhandled: {:?}", a),
             Device::SoftReset => {
                 self.pen = CellAttributes::default();
+                self.dec_origin_mode = false;
+                self.dec_left_and_right_margin_mode = false;
+                self.left_and_right_margins = 0..self.screen().physical_cols;
+                let rows = self.screen().physical_rows as VisibleRowIndex;
+                self.screen.set_scroll_region(0..rows);
                 // TODO: see https://vt100.net/docs/vt510-rm/DECSTR.html
             }
             Device::RequestPrimaryDeviceAttributes => {
@@ -1543,8 +2381,81 @@ impl TerminalState {
         }
     }
 
-    fn perform_csi_mode(&mut self, mode: Mode) {
+    /// Returns the DECRPM status of a DEC private mode: 0 if the mode
+    /// is not recognized, 1 if it is set, 2 if it is reset and 4 if it
+    /// is permanently reset
+    fn dec_private_mode_status(&self, mode: &DecPrivateMode) -> u8 {
+        let set = match mode {
+            DecPrivateMode::Code(code) => match code {
+                DecPrivateModeCode::ApplicationCursorKeys => self.application_cursor_keys,
+                DecPrivateModeCode::OriginMode => self.dec_origin_mode,
+                DecPrivateModeCode::ShowCursor => self.cursor_visible,
+                DecPrivateModeCode::X10Mouse => self.x10_mouse,
+                DecPrivateModeCode::MouseTracking => self.mouse_tracking,
+                DecPrivateModeCode::ButtonEventMouse => self.button_event_mouse,
+                DecPrivateModeCode::AnyEventMouse => self.any_event_mouse,
+                DecPrivateModeCode::FocusTracking => self.focus_tracking,
+                DecPrivateModeCode::Utf8Mouse => self.utf8_mouse,
+                DecPrivateModeCode::SGRMouse => self.sgr_mouse,
+                DecPrivateModeCode::UrxvtMouse => self.urxvt_mouse,
+                DecPrivateModeCode::EnableAlternateScreen
+                | DecPrivateModeCode::OptEnableAlternateScreen
+                | DecPrivateModeCode::ClearAndEnableAlternateScreen => {
+                    self.screen.is_alt_screen_active()
+                }
+                DecPrivateModeCode::LeftAndRightMarginMode => self.dec_left_and_right_margin_mode,
+                DecPrivateModeCode::BracketedPaste => self.bracketed_paste,
+                // Setting and resetting these has no effect
+                DecPrivateModeCode::StartBlinkingCursor
+                | DecPrivateModeCode::HighlightMouseTracking => return 4,
+                // These act when they are set or reset, rather than
+                // persisting, and synchronized output is reported by
+                // `Terminal::advance_bytes`
+                DecPrivateModeCode::SaveCursor | DecPrivateModeCode::SynchronizedOutput => false,
+            },
+            DecPrivateMode::Unspecified(_) => return 0,
+        };
+        if set {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Returns the DECRPM status of an ANSI mode, as for
+    /// `dec_private_mode_status`
+    fn terminal_mode_status(&self, mode: &TerminalMode) -> u8 {
         match mode {
+            TerminalMode::Code(TerminalModeCode::Insert) => {
+                if self.insert {
+                    1
+                } else {
+                    2
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn perform_csi_mode(&mut self, mode: Mode, host: &mut dyn TerminalHost) {
+        match mode {
+            Mode::QueryDecPrivateMode(mode) => {
+                let status = self.dec_private_mode_status(&mode);
+                let code = match mode {
+                    DecPrivateMode::Code(code) => code as u16,
+                    DecPrivateMode::Unspecified(n) => n,
+                };
+                write!(host.writer(), "\x1b[?{};{}$y", code, status).ok();
+            }
+            Mode::QueryMode(mode) => {
+                let status = self.terminal_mode_status(&mode);
+                let code = match mode {
+                    TerminalMode::Code(code) => code as u16,
+                    TerminalMode::Unspecified(n) => n,
+                };
+                write!(host.writer(), "\x1b[{};{}$y", code, status).ok();
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::StartBlinkingCursor,
             ))
@@ -1583,6 +2494,31 @@ impl TerminalState {
                 }
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::OptEnableAlternateScreen,
+            )) => {
+                if !self.screen.is_alt_screen_active() {
+                    self.screen.activate_alt_screen();
+                    self.set_scroll_viewport(0);
+                }
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::OptEnableAlternateScreen,
+            )) => {
+                if self.screen.is_alt_screen_active() {
+                    self.erase_in_display(EraseInDisplay::EraseDisplay);
+                    self.screen.activate_primary_screen();
+                    self.set_scroll_viewport(0);
+                }
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SaveCursor)) => {
+                self.save_cursor();
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SaveCursor)) => {
+                self.restore_cursor();
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ApplicationCursorKeys,
             )) => {
@@ -1594,6 +2530,27 @@ impl TerminalState {
                 self.application_cursor_keys = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
+                self.dec_origin_mode = true;
+                self.set_cursor_pos_with_origin(&Position::Absolute(0), &Position::Absolute(0));
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
+                self.dec_origin_mode = false;
+                self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::LeftAndRightMarginMode,
+            )) => {
+                self.dec_left_and_right_margin_mode = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::LeftAndRightMarginMode,
+            )) => {
+                self.dec_left_and_right_margin_mode = false;
+                self.left_and_right_margins = 0..self.screen().physical_cols;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
             }
@@ -1601,8 +2558,18 @@ impl TerminalState {
                 self.cursor_visible = false;
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking))
-            | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::X10Mouse)) => {
+                self.x10_mouse = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::X10Mouse)) => {
+                self.x10_mouse = false;
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
+                self.mouse_tracking = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
+                self.mouse_tracking = false;
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
@@ -1621,8 +2588,11 @@ impl TerminalState {
                 self.button_event_mouse = false;
             }
 
-            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse))
-            | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
+                self.any_event_mouse = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
+                self.any_event_mouse = false;
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
@@ -1632,6 +2602,35 @@ impl TerminalState {
                 self.sgr_mouse = false;
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Utf8Mouse)) => {
+                self.utf8_mouse = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::Utf8Mouse)) => {
+                self.utf8_mouse = false;
+            }
+
+            // Synchronized output is handled by `Terminal::advance_bytes`
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            ))
+            | Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::SynchronizedOutput,
+            )) => {}
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
+                self.focus_tracking = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
+                self.focus_tracking = false;
+            }
+
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::UrxvtMouse)) => {
+                self.urxvt_mouse = true;
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::UrxvtMouse)) => {
+                self.urxvt_mouse = false;
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ClearAndEnableAlternateScreen,
             )) => {
@@ -1752,7 +2751,7 @@ impl TerminalState {
             }
             EraseInDisplay::EraseDisplay => 0..rows,
             EraseInDisplay::EraseScrollback => {
-                error!("TODO: ed: no support for xterm Erase Saved Lines yet");
+                self.erase_scrollback();
                 return;
             }
         };
@@ -1788,12 +2787,21 @@ impl TerminalState {
                 self.clear_selection_if_intersects(x..limit, y as ScrollbackOrVisibleRowIndex);
             }
             Edit::DeleteLine(n) => {
-                if self.scroll_region.contains(&self.cursor.y) {
-                    let scroll_region = self.cursor.y..self.scroll_region.end;
-                    self.screen_mut().scroll_up(&scroll_region, n as usize);
+                if self.screen.scroll_region().contains(&self.cursor.y)
+                    && self.left_and_right_margins.contains(&self.cursor.x)
+                {
+                    let scroll_region = self.cursor.y..self.screen.scroll_region().end;
+                    let margins = self.left_and_right_margins.clone();
+                    self.screen_mut().scroll_up_within_margins(
+                        &scroll_region,
+                        &margins,
+                        n as usize,
+                    );
+                    self.cursor.x = margins.start;
+                    self.wrap_next = false;
 
                     let scrollback_region = self.cursor.y as ScrollbackOrVisibleRowIndex
-                        ..self.scroll_region.end as ScrollbackOrVisibleRowIndex;
+                        ..self.screen.scroll_region().end as ScrollbackOrVisibleRowIndex;
                     self.clear_selection_if_intersects_rows(scrollback_region);
                 }
             }
@@ -1840,12 +2848,21 @@ impl TerminalState {
                 self.clear_selection_if_intersects(x..limit, y as ScrollbackOrVisibleRowIndex);
             }
             Edit::InsertLine(n) => {
-                if self.scroll_region.contains(&self.cursor.y) {
-                    let scroll_region = self.cursor.y..self.scroll_region.end;
-                    self.screen_mut().scroll_down(&scroll_region, n as usize);
+                if self.screen.scroll_region().contains(&self.cursor.y)
+                    && self.left_and_right_margins.contains(&self.cursor.x)
+                {
+                    let scroll_region = self.cursor.y..self.screen.scroll_region().end;
+                    let margins = self.left_and_right_margins.clone();
+                    self.screen_mut().scroll_down_within_margins(
+                        &scroll_region,
+                        &margins,
+                        n as usize,
+                    );
+                    self.cursor.x = margins.start;
+                    self.wrap_next = false;
 
                     let scrollback_region = self.cursor.y as ScrollbackOrVisibleRowIndex
-                        ..self.scroll_region.end as ScrollbackOrVisibleRowIndex;
+                        ..self.screen.scroll_region().end as ScrollbackOrVisibleRowIndex;
                     self.clear_selection_if_intersects_rows(scrollback_region);
                 }
             }
@@ -1878,7 +2895,23 @@ impl TerminalState {
                 if top > bottom {
                     std::mem::swap(&mut top, &mut bottom);
                 }
-                self.scroll_region = top..bottom + 1;
+                self.screen.set_scroll_region(top..bottom + 1);
+                self.set_cursor_pos_with_origin(&Position::Absolute(0), &Position::Absolute(0));
+            }
+            Cursor::SetLeftAndRightMargins { left, right } => {
+                if self.dec_left_and_right_margin_mode {
+                    let cols = self.screen().physical_cols;
+                    let left = (left.as_zero_based() as usize).min(cols - 1);
+                    let right = (right.as_zero_based() as usize).min(cols - 1);
+                    // A region must span at least two columns
+                    if left < right {
+                        self.left_and_right_margins = left..right + 1;
+                        self.set_cursor_pos_with_origin(
+                            &Position::Absolute(0),
+                            &Position::Absolute(0),
+                        );
+                    }
+                }
             }
             Cursor::ForwardTabulation(n) => {
                 for _ in 0..n {
@@ -1903,12 +2936,12 @@ impl TerminalState {
                 self.set_cursor_pos(&Position::Relative(0), &Position::Relative(i64::from(n)))
             }
             Cursor::CharacterAndLinePosition { line, col } | Cursor::Position { line, col } => self
-                .set_cursor_pos(
+                .set_cursor_pos_with_origin(
                     &Position::Absolute(i64::from(col.as_zero_based())),
                     &Position::Absolute(i64::from(line.as_zero_based())),
                 ),
             Cursor::CharacterAbsolute(col) | Cursor::CharacterPositionAbsolute(col) => self
-                .set_cursor_pos(
+                .set_cursor_pos_with_origin(
                     &Position::Absolute(i64::from(col.as_zero_based())),
                     &Position::Relative(0),
                 ),
@@ -1919,7 +2952,7 @@ impl TerminalState {
             Cursor::CharacterPositionForward(col) => {
                 self.set_cursor_pos(&Position::Relative(i64::from(col)), &Position::Relative(0))
             }
-            Cursor::LinePositionAbsolute(line) => self.set_cursor_pos(
+            Cursor::LinePositionAbsolute(line) => self.set_cursor_pos_with_origin(
                 &Position::Relative(0),
                 &Position::Absolute((i64::from(line)).saturating_sub(1)),
             ),
@@ -1943,12 +2976,29 @@ impl TerminalState {
                 // we don't need to process it as a terminal command
             }
             Cursor::RequestActivePositionReport => {
-                let line = OneBased::from_zero_based(self.cursor.y as u32);
-                let col = OneBased::from_zero_based(self.cursor.x as u32);
+                let (line, col) = if self.dec_origin_mode {
+                    (
+                        self.cursor.y - self.screen.scroll_region().start,
+                        self.cursor.x - self.left_and_right_margins.start,
+                    )
+                } else {
+                    (self.cursor.y, self.cursor.x)
+                };
+                let line = OneBased::from_zero_based(line as u32);
+                let col = OneBased::from_zero_based(col as u32);
                 let report = CSI::Cursor(Cursor::ActivePositionReport { line, col });
                 write!(host.writer(), "{}", report).ok();
             }
-            Cursor::SaveCursor => self.save_cursor(),
+            Cursor::SaveCursor => {
+                if self.dec_left_and_right_margin_mode {
+                    // `CSI s` is DECSLRM with default parameters
+                    // while DECLRMM is enabled
+                    self.left_and_right_margins = 0..self.screen().physical_cols;
+                    self.set_cursor_pos_with_origin(&Position::Absolute(0), &Position::Absolute(0));
+                } else {
+                    self.save_cursor();
+                }
+            }
             Cursor::RestoreCursor => self.restore_cursor(),
             Cursor::CursorStyle(style) => {
                 self.cursor.shape = match style {
@@ -1970,6 +3020,8 @@ impl TerminalState {
             position: self.cursor,
             insert: self.insert,
             wrap_next: self.wrap_next,
+            pen: self.pen.clone(),
+            dec_origin_mode: self.dec_origin_mode,
         };
         debug!(
             "saving cursor {:?} is_alt={}",
@@ -1979,11 +3031,17 @@ impl TerminalState {
         *self.screen.saved_cursor() = Some(saved);
     }
     fn restore_cursor(&mut self) {
-        let saved = self.screen.saved_cursor().unwrap_or_else(|| SavedCursor {
-            position: CursorPosition::default(),
-            insert: false,
-            wrap_next: false,
-        });
+        let saved = self
+            .screen
+            .saved_cursor()
+            .clone()
+            .unwrap_or_else(|| SavedCursor {
+                position: CursorPosition::default(),
+                insert: false,
+                wrap_next: false,
+                pen: CellAttributes::default(),
+                dec_origin_mode: false,
+            });
         debug!(
             "restore cursor {:?} is_alt={}",
             saved,
@@ -1995,15 +3053,21 @@ impl TerminalState {
         self.cursor.shape = saved.position.shape;
         self.wrap_next = saved.wrap_next;
         self.insert = saved.insert;
+        self.pen = saved.pen;
+        self.dec_origin_mode = saved.dec_origin_mode;
     }
 
     fn perform_csi_sgr(&mut self, sgr: Sgr) {
         debug!("{:?}", sgr);
         match sgr {
             Sgr::Reset => {
-                let link = self.pen.hyperlink.take();
+                // The hyperlink and semantic type are not part of the
+                // SGR state, so preserve them across the reset
+                let link = self.pen.hyperlink().cloned();
+                let semantic_type = self.pen.semantic_type();
                 self.pen = CellAttributes::default();
-                self.pen.hyperlink = link;
+                self.pen.set_hyperlink(link);
+                self.pen.set_semantic_type(semantic_type);
             }
             Sgr::Intensity(intensity) => {
                 self.pen.set_intensity(intensity);
@@ -2032,6 +3096,9 @@ impl TerminalState {
             Sgr::Background(col) => {
                 self.pen.set_background(col);
             }
+            Sgr::UnderlineColor(col) => {
+                self.pen.set_underline_color(col);
+            }
             Sgr::Font(_) => {}
         }
     }
@@ -2081,6 +3148,10 @@ impl<'a> Performer<'a> {
         };
 
         let mut x_offset = 0;
+        // Whether the grapheme may continue the one that was printed
+        // before it, which is only possible at the start of the text
+        // or after a grapheme that did so
+        let mut may_combine = !self.insert;
 
         for g in unicode_segmentation::UnicodeSegmentation::graphemes(p.as_str(), true) {
             let g = if self.dec_line_drawing_mode {
@@ -2102,13 +3173,27 @@ impl<'a> Performer<'a> {
                 g
             };
 
+            if may_combine {
+                if self.combine_with_previous_cell(g) {
+                    continue;
+                }
+                may_combine = false;
+            }
+
             if !self.insert && self.wrap_next {
                 self.new_line(true);
             }
 
             let x = self.cursor.x;
             let y = self.cursor.y;
-            let width = self.screen().physical_cols;
+            let physical_cols = self.screen().physical_cols;
+            // Text wraps at the right margin, unless the cursor has
+            // been placed beyond it
+            let width = if x < self.left_and_right_margins.end {
+                self.left_and_right_margins.end
+            } else {
+                physical_cols
+            };
 
             let mut pen = self.pen.clone();
             // the max(1) here is to ensure that we advance to the next cell
@@ -2118,7 +3203,7 @@ impl<'a> Performer<'a> {
             // the model, which seems like a lossy design choice.
             let print_width = unicode_column_width(g).max(1);
 
-            if !self.insert && x + print_width >= width {
+            if !self.insert && x + print_width >= width && width == physical_cols {
                 pen.set_wrapped(true);
             }
 
@@ -2150,18 +3235,132 @@ impl<'a> Performer<'a> {
         }
     }
 
+    /// Appends `g` to the grapheme in the cell before the cursor if
+    /// the two form a single grapheme.  This happens when a combining
+    /// mark, or the remainder of an emoji sequence, arrives separately
+    /// from the text that precedes it, and keeps the cursor where it
+    /// would have been had they arrived together.
+    /// Returns false if `g` starts a new grapheme.
+    fn combine_with_previous_cell(&mut self, g: &str) -> bool {
+        let y = self.cursor.y;
+        let x = if self.wrap_next {
+            // The cursor hasn't advanced past the last printed cell
+            self.cursor.x
+        } else {
+            match self.cursor.x.checked_sub(1) {
+                Some(x) => x,
+                None => return false,
+            }
+        };
+        let physical_cols = self.screen().physical_cols;
+        let width = if x < self.left_and_right_margins.end {
+            self.left_and_right_margins.end
+        } else {
+            physical_cols
+        };
+
+        let (x, prior) = {
+            let screen = self.screen();
+            let cells = screen.lines[screen.phys_row(y)].cells();
+            // Skip over the second half of a double width character
+            let x = match x.checked_sub(1) {
+                Some(left) if !self.wrap_next && cells.get(left).map(Cell::width) == Some(2) => {
+                    left
+                }
+                _ => x,
+            };
+            match cells.get(x) {
+                Some(cell) => (x, cell.clone()),
+                None => return false,
+            }
+        };
+
+        let combined = format!("{}{}", prior.str(), g);
+        if unicode_segmentation::UnicodeSegmentation::graphemes(combined.as_str(), true).count()
+            != 1
+        {
+            return false;
+        }
+        let cell = Cell::new_grapheme(&combined, prior.attrs().clone());
+        let cell_width = cell.width();
+        if x + cell_width > width {
+            // It no longer fits on the line
+            return false;
+        }
+
+        self.screen_mut().set_cell(x, y, &cell);
+        self.clear_selection_if_intersects(x..x + cell_width, y as ScrollbackOrVisibleRowIndex);
+
+        if cell_width > prior.width() && !self.wrap_next {
+            if x + cell_width < width {
+                self.cursor.x = x + cell_width;
+            } else {
+                self.cursor.x = x;
+                self.wrap_next = true;
+            }
+        }
+        true
+    }
+
     pub fn perform(&mut self, action: Action) {
         debug!("perform {:?}", action);
         match action {
             Action::Print(c) => self.print(c),
             Action::Control(code) => self.control(code),
-            Action::DeviceControl(ctrl) => error!("Unhandled {:?}", ctrl),
+            Action::DeviceControl(ctrl) => {
+                if self.xtgettcap(&ctrl) {
+                    return;
+                }
+                match self.device_control_handler.as_mut() {
+                    Some(handler) => handler.handle_device_control(*ctrl),
+                    None => error!("Unhandled {:?}", ctrl),
+                }
+            }
             Action::OperatingSystemCommand(osc) => self.osc_dispatch(*osc),
             Action::Esc(esc) => self.esc_dispatch(esc),
             Action::CSI(csi) => self.csi_dispatch(csi),
+            Action::Sixel(sixel) => self.sixel(sixel),
         }
     }
 
+    /// Handles XTGETTCAP, `DCS + q Pt ST`, which asks for the values of
+    /// the terminfo capabilities whose hex encoded names are listed in
+    /// `Pt`, separated by semicolons.  Returns false if `ctrl` is not
+    /// part of such a request.
+    fn xtgettcap(&mut self, ctrl: &DeviceControlMode) -> bool {
+        match ctrl {
+            DeviceControlMode::Enter {
+                intermediates,
+                byte: b'q',
+                ..
+            } if intermediates.as_slice() == b"+" => {
+                self.xtgettcap = Some(vec![]);
+            }
+            DeviceControlMode::Data(b) => match self.xtgettcap.as_mut() {
+                Some(names) => {
+                    // Ignore an unreasonably long request rather than
+                    // buffering it indefinitely
+                    if names.len() < 4096 {
+                        names.push(*b);
+                    }
+                }
+                None => return false,
+            },
+            DeviceControlMode::Exit => match self.xtgettcap.take() {
+                Some(names) => {
+                    let names = String::from_utf8_lossy(&names);
+                    for name in names.split(';') {
+                        let response = crate::terminfo::xtgettcap_response(name);
+                        self.host.writer().write(response.as_bytes()).ok();
+                    }
+                }
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
     /// Draw a character to the screen
     fn print(&mut self, c: char) {
         // We buffer up the chars to increase the chances of correctly grouping graphemes into cells
@@ -2172,16 +3371,26 @@ impl<'a> Performer<'a> {
         self.flush_print();
         match control {
             ControlCode::LineFeed | ControlCode::VerticalTab | ControlCode::FormFeed => {
+                self.cancel_pending_wrap();
                 self.new_line(false)
             }
             ControlCode::CarriageReturn => {
-                self.set_cursor_pos(&Position::Absolute(0), &Position::Relative(0));
+                self.cancel_pending_wrap();
+                // Return to the left margin, unless the cursor is
+                // already to the left of it
+                let left = self.left_and_right_margins.start;
+                let x = if self.cursor.x >= left || self.dec_origin_mode {
+                    left
+                } else {
+                    0
+                };
+                self.set_cursor_pos(&Position::Absolute(x as i64), &Position::Relative(0));
             }
             ControlCode::Backspace => {
                 self.set_cursor_pos(&Position::Relative(-1), &Position::Relative(0));
             }
             ControlCode::HorizontalTab => self.c0_horizontal_tab(),
-            ControlCode::Bell => error!("Ding! (this is the bell)"),
+            ControlCode::Bell => self.host.bell(),
             _ => error!("unhandled ControlCode {:?}", control),
         }
     }
@@ -2192,7 +3401,7 @@ impl<'a> Performer<'a> {
             CSI::Sgr(sgr) => self.state.perform_csi_sgr(sgr),
             CSI::Cursor(cursor) => self.state.perform_csi_cursor(cursor, self.host),
             CSI::Edit(edit) => self.state.perform_csi_edit(edit),
-            CSI::Mode(mode) => self.state.perform_csi_mode(mode),
+            CSI::Mode(mode) => self.state.perform_csi_mode(mode, self.host),
             CSI::Device(dev) => self.state.perform_device(*dev, self.host),
             CSI::Mouse(mouse) => error!("mouse report sent by app? {:?}", mouse),
             CSI::Window(window) => self.state.perform_csi_window(window, self.host),
@@ -2242,6 +3451,9 @@ impl<'a> Performer<'a> {
                 self.host.set_title(&title);
             }
             OperatingSystemCommand::SetIconName(_) => {}
+            OperatingSystemCommand::SetCurrentWorkingDirectory(url) => {
+                self.current_dir = Some(url);
+            }
             OperatingSystemCommand::SetHyperlink(link) => {
                 self.set_hyperlink(link);
             }
@@ -2268,11 +3480,22 @@ impl<'a> Performer<'a> {
                 ITermProprietary::File(image) => self.set_image(*image),
                 _ => error!("unhandled iterm2: {:?}", iterm),
             },
+            OperatingSystemCommand::FinalTermSemanticPrompt(prompt) => {
+                // Tag the text that follows so that it can be
+                // selected as a unit with `SelectionRange::zone_around`
+                let semantic_type = match prompt {
+                    FinalTermSemanticPrompt::PromptStart => SemanticType::Prompt,
+                    FinalTermSemanticPrompt::CommandStart => SemanticType::Input,
+                    FinalTermSemanticPrompt::CommandExecuted
+                    | FinalTermSemanticPrompt::CommandFinished { .. } => SemanticType::Output,
+                };
+                self.pen.set_semantic_type(semantic_type);
+            }
             OperatingSystemCommand::SystemNotification(message) => {
                 error!("Application sends SystemNotification: {}", message);
             }
             OperatingSystemCommand::ChangeColorNumber(specs) => {
-                error!("ChangeColorNumber: {:?}", specs);
+                debug!("ChangeColorNumber: {:?}", specs);
                 for pair in specs {
                     match pair.color {
                         ColorOrQuery::Query => {
@@ -2293,7 +3516,7 @@ impl<'a> Performer<'a> {
                 self.make_all_lines_dirty();
             }
             OperatingSystemCommand::ChangeDynamicColors(first_color, colors) => {
-                error!("ChangeDynamicColors: {:?} {:?}", first_color, colors);
+                debug!("ChangeDynamicColors: {:?} {:?}", first_color, colors);
                 use termwiz::escape::osc::DynamicColorNumber;
                 let mut idx: u8 = first_color as u8;
                 for color in colors {
@@ -2316,7 +3539,12 @@ impl<'a> Performer<'a> {
                         match which_color {
                             DynamicColorNumber::TextForegroundColor => set_or_query!(foreground),
                             DynamicColorNumber::TextBackgroundColor => set_or_query!(background),
-                            DynamicColorNumber::TextCursorColor => set_or_query!(cursor_bg),
+                            DynamicColorNumber::TextCursorColor => {
+                                if let ColorOrQuery::Color(c) = color {
+                                    self.palette_mut().cursor_border = c;
+                                }
+                                set_or_query!(cursor_bg)
+                            }
                             DynamicColorNumber::HighlightForegroundColor => {
                                 set_or_query!(selection_fg)
                             }
@@ -2334,6 +3562,54 @@ impl<'a> Performer<'a> {
                 }
                 self.make_all_lines_dirty();
             }
+            OperatingSystemCommand::ResetColors(colors) => {
+                debug!("ResetColors: {:?}", colors);
+                // There is nothing to reset if the palette was never changed
+                if self.palette.is_some() {
+                    let defaults = self.config.color_palette();
+                    let palette = self.palette_mut();
+                    if colors.is_empty() {
+                        palette.colors = defaults.colors;
+                    } else {
+                        for idx in colors {
+                            palette.colors.0[idx as usize] = defaults.colors.0[idx as usize];
+                        }
+                    }
+                    self.make_all_lines_dirty();
+                }
+            }
+            OperatingSystemCommand::ResetDynamicColor(color) => {
+                debug!("ResetDynamicColor: {:?}", color);
+                use termwiz::escape::osc::DynamicColorNumber;
+                if self.palette.is_some() {
+                    let defaults = self.config.color_palette();
+                    let palette = self.palette_mut();
+                    match color {
+                        DynamicColorNumber::TextForegroundColor => {
+                            palette.foreground = defaults.foreground
+                        }
+                        DynamicColorNumber::TextBackgroundColor => {
+                            palette.background = defaults.background
+                        }
+                        DynamicColorNumber::TextCursorColor => {
+                            palette.cursor_bg = defaults.cursor_bg;
+                            palette.cursor_border = defaults.cursor_border;
+                        }
+                        DynamicColorNumber::HighlightForegroundColor => {
+                            palette.selection_fg = defaults.selection_fg
+                        }
+                        DynamicColorNumber::HighlightBackgroundColor => {
+                            palette.selection_bg = defaults.selection_bg
+                        }
+                        DynamicColorNumber::MouseForegroundColor
+                        | DynamicColorNumber::MouseBackgroundColor
+                        | DynamicColorNumber::TektronixForegroundColor
+                        | DynamicColorNumber::TektronixBackgroundColor
+                        | DynamicColorNumber::TektronixCursorColor => {}
+                    }
+                    self.make_all_lines_dirty();
+                }
+            }
         }
     }
 }
@@ -0,0 +1,239 @@
+//! The terminfo description of the terminal.  The capabilities listed
+//! here are used to answer XTGETTCAP queries, and to generate the source
+//! of the `wezterm` terminfo entry, which can be compiled and installed
+//! with `wezterm terminfo | tic -x -`.
+use std::fmt::Write;
+
+/// The name of the terminfo entry
+pub const TERMINFO_NAME: &str = "wezterm";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// A boolean capability that the terminal has
+    Flag,
+    Number(u32),
+    /// A string capability, in its decoded form; parameters use the
+    /// usual terminfo `%` notation
+    Str(&'static str),
+}
+
+use Capability::{Flag, Number, Str};
+
+/// The capabilities of the terminal.  The keys are those that are sent
+/// when the cursor keys are in application mode, as `smkx` enables it.
+/// The names that start with an uppercase letter are the extended
+/// capabilities understood by ncurses, tmux and neovim.
+pub const CAPABILITIES: &[(&str, Capability)] = &[
+    ("AX", Flag),
+    ("Tc", Flag),
+    ("XT", Flag),
+    ("am", Flag),
+    ("bce", Flag),
+    ("ccc", Flag),
+    ("km", Flag),
+    ("mir", Flag),
+    ("msgr", Flag),
+    ("npc", Flag),
+    ("xenl", Flag),
+    ("colors", Number(256)),
+    ("cols", Number(80)),
+    ("it", Number(8)),
+    ("lines", Number(24)),
+    ("pairs", Number(32767)),
+    ("BD", Str("\x1b[?2004l")),
+    ("BE", Str("\x1b[?2004h")),
+    ("Ms", Str("\x1b]52;%p1%s;%p2%s\x07")),
+    ("PE", Str("\x1b[201~")),
+    ("PS", Str("\x1b[200~")),
+    ("Se", Str("\x1b[2 q")),
+    ("Setulc", Str("\x1b[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%dm")),
+    ("Smulx", Str("\x1b[4:%p1%dm")),
+    ("Ss", Str("\x1b[%p1%d q")),
+    ("acsc", Str("``aaffggiijjkkllmmnnooppqqrrssttuuvvwwxxyyzz{{||}}~~")),
+    ("Sync", Str("\x1b[?2026%?%p1%{1}%-%tl%eh%;")),
+    ("bel", Str("\x07")),
+    ("blink", Str("\x1b[5m")),
+    ("bold", Str("\x1b[1m")),
+    ("civis", Str("\x1b[?25l")),
+    ("clear", Str("\x1b[H\x1b[2J")),
+    ("cnorm", Str("\x1b[?25h")),
+    ("cr", Str("\r")),
+    ("csr", Str("\x1b[%i%p1%d;%p2%dr")),
+    ("cub", Str("\x1b[%p1%dD")),
+    ("cub1", Str("\x08")),
+    ("cud", Str("\x1b[%p1%dB")),
+    ("cud1", Str("\n")),
+    ("cuf", Str("\x1b[%p1%dC")),
+    ("cuf1", Str("\x1b[C")),
+    ("cup", Str("\x1b[%i%p1%d;%p2%dH")),
+    ("cuu", Str("\x1b[%p1%dA")),
+    ("cuu1", Str("\x1b[A")),
+    ("dch", Str("\x1b[%p1%dP")),
+    ("dch1", Str("\x1b[P")),
+    ("dim", Str("\x1b[2m")),
+    ("dl", Str("\x1b[%p1%dM")),
+    ("dl1", Str("\x1b[M")),
+    ("ech", Str("\x1b[%p1%dX")),
+    ("ed", Str("\x1b[J")),
+    ("el", Str("\x1b[K")),
+    ("el1", Str("\x1b[1K")),
+    ("fd", Str("\x1b[?1004l")),
+    ("fe", Str("\x1b[?1004h")),
+    ("home", Str("\x1b[H")),
+    ("hpa", Str("\x1b[%i%p1%dG")),
+    ("ht", Str("\t")),
+    ("hts", Str("\x1bH")),
+    ("ich", Str("\x1b[%p1%d@")),
+    ("il", Str("\x1b[%p1%dL")),
+    ("il1", Str("\x1b[L")),
+    ("ind", Str("\n")),
+    ("indn", Str("\x1b[%p1%dS")),
+    (
+        "initc",
+        Str("\x1b]4;%p1%d;rgb:%p2%{255}%*%{1000}%/%2.2X/%p3%{255}%*%{1000}%/%2.2X/%p4%{255}%*%{1000}%/%2.2X\x1b\\"),
+    ),
+    ("invis", Str("\x1b[8m")),
+    ("kbs", Str("\x7f")),
+    ("kcbt", Str("\x1b[Z")),
+    ("kcub1", Str("\x1bOD")),
+    ("kcud1", Str("\x1bOB")),
+    ("kcuf1", Str("\x1bOC")),
+    ("kcuu1", Str("\x1bOA")),
+    ("kdch1", Str("\x1b[3~")),
+    ("kend", Str("\x1bOF")),
+    ("kf1", Str("\x1bOP")),
+    ("kf2", Str("\x1bOQ")),
+    ("kf3", Str("\x1bOR")),
+    ("kf4", Str("\x1bOS")),
+    ("kf5", Str("\x1b[15~")),
+    ("kf6", Str("\x1b[17~")),
+    ("kf7", Str("\x1b[18~")),
+    ("kf8", Str("\x1b[19~")),
+    ("kf9", Str("\x1b[20~")),
+    ("kf10", Str("\x1b[21~")),
+    ("kf11", Str("\x1b[23~")),
+    ("kf12", Str("\x1b[24~")),
+    ("khome", Str("\x1bOH")),
+    ("kich1", Str("\x1b[2~")),
+    ("kmous", Str("\x1b[M")),
+    ("knp", Str("\x1b[6~")),
+    ("kpp", Str("\x1b[5~")),
+    ("kxIN", Str("\x1b[I")),
+    ("kxOUT", Str("\x1b[O")),
+    ("op", Str("\x1b[39;49m")),
+    ("rc", Str("\x1b8")),
+    ("rep", Str("%p1%c\x1b[%p2%{1}%-%db")),
+    ("rev", Str("\x1b[7m")),
+    ("ri", Str("\x1bM")),
+    ("rin", Str("\x1b[%p1%dT")),
+    ("ritm", Str("\x1b[23m")),
+    ("rmacs", Str("\x1b(B")),
+    ("rmcup", Str("\x1b[?1049l")),
+    ("rmir", Str("\x1b[4l")),
+    ("rmkx", Str("\x1b[?1l\x1b>")),
+    ("rmso", Str("\x1b[27m")),
+    ("rmul", Str("\x1b[24m")),
+    ("rmxx", Str("\x1b[29m")),
+    ("sc", Str("\x1b7")),
+    (
+        "setab",
+        Str("\x1b[%?%p1%{8}%<%t4%p1%d%e%p1%{16}%<%t10%p1%{8}%-%d%e48;5;%p1%d%;m"),
+    ),
+    (
+        "setaf",
+        Str("\x1b[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m"),
+    ),
+    ("sgr0", Str("\x1b(B\x1b[m")),
+    ("sitm", Str("\x1b[3m")),
+    ("smacs", Str("\x1b(0")),
+    ("smcup", Str("\x1b[?1049h")),
+    ("smir", Str("\x1b[4h")),
+    ("smkx", Str("\x1b[?1h\x1b=")),
+    ("smso", Str("\x1b[7m")),
+    ("smul", Str("\x1b[4m")),
+    ("smxx", Str("\x1b[9m")),
+    ("u6", Str("\x1b[%i%d;%dR")),
+    ("u7", Str("\x1b[6n")),
+    ("u8", Str("\x1b[?%[;0123456789]c")),
+    ("u9", Str("\x1b[c")),
+    ("vpa", Str("\x1b[%i%p1%dd")),
+];
+
+/// Returns the capability with the terminfo name `name`
+pub fn lookup(name: &str) -> Option<Capability> {
+    CAPABILITIES
+        .iter()
+        .find(|(cap, _)| *cap == name)
+        .map(|(_, value)| *value)
+}
+
+/// Escapes `value` for use as the value of a string capability in
+/// terminfo source
+fn escape(value: &str) -> String {
+    let mut result = String::new();
+    for c in value.chars() {
+        match c {
+            '\x1b' => result.push_str("\\E"),
+            ',' | '\\' | '^' => {
+                result.push('\\');
+                result.push(c);
+            }
+            c if (c as u32) < 0x20 => {
+                result.push('^');
+                result.push(((c as u8) + b'@') as char);
+            }
+            '\x7f' => result.push_str("^?"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Returns the terminfo source of the `wezterm` entry, in the format
+/// that is compiled by `tic`
+pub fn terminfo_source() -> String {
+    let mut source = format!("{}|Wez's terminal emulator,\n", TERMINFO_NAME);
+    for (name, value) in CAPABILITIES {
+        match value {
+            Flag => writeln!(source, "\t{},", name),
+            Number(n) => writeln!(source, "\t{}#{},", name, n),
+            Str(s) => writeln!(source, "\t{}={},", name, escape(s)),
+        }
+        .ok();
+    }
+    source
+}
+
+fn hex_encode(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn hex_decode(s: &str) -> Option<String> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Returns the response to an XTGETTCAP request for the capability
+/// whose name is hex encoded as `hex_name`.  In addition to the
+/// terminfo names, the `TN` and `Co` names used by xterm are
+/// recognized for the name of the terminal and its number of colors.
+pub fn xtgettcap_response(hex_name: &str) -> String {
+    let value = match hex_decode(hex_name).as_deref() {
+        Some("TN") | Some("name") => Some(Str(TERMINFO_NAME)),
+        Some("Co") => lookup("colors"),
+        Some(name) => lookup(name),
+        None => None,
+    };
+    match value {
+        Some(Flag) => format!("\x1bP1+r{}\x1b\\", hex_name),
+        Some(Number(n)) => format!("\x1bP1+r{}={}\x1b\\", hex_name, hex_encode(&n.to_string())),
+        Some(Str(s)) => format!("\x1bP1+r{}={}\x1b\\", hex_name, hex_encode(s)),
+        None => format!("\x1bP0+r{}\x1b\\", hex_name),
+    }
+}
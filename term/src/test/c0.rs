@@ -13,6 +13,14 @@ fn test_bs() {
     // TODO: when we can set the left margin, we should test that here
 }
 
+#[test]
+fn test_bell() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("a\x07b\x07");
+    assert_eq!(term.host.bells, 2);
+    term.assert_cursor_pos(2, 0, Some("BEL doesn't move the cursor"));
+}
+
 #[test]
 fn test_lf() {
     let mut term = TestTerm::new(3, 10, 0);
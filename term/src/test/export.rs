@@ -0,0 +1,37 @@
+use super::*;
+use crate::export::ExportFormat;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn export_text() {
+    let mut term = TestTerm::new(3, 5, 10);
+    term.print("one\r\ntwo and three\r\n\r\nfour\r\n");
+    // "two and three" wraps across three rows and is joined back
+    // together; trailing blank lines are omitted
+    assert_eq!(
+        term.export_scrollback(ExportFormat::Text),
+        "one\ntwo and three\n\nfour\n"
+    );
+}
+
+#[test]
+fn export_html() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b]2;a <title>\x07");
+    term.print("plain \x1b[1;31mred\x1b[0m &\r\n");
+    let palette = term.palette();
+    let html = term.export_scrollback(ExportFormat::Html);
+    assert_eq!(
+        html,
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>a &lt;title&gt;</title>\n</head>\n\
+             <body style=\"color:{};background-color:{}\">\n<pre>\
+             plain <span style=\"color:{};font-weight:bold;\">red</span> &amp;\n\
+             </pre>\n</body>\n</html>\n",
+            palette.foreground.to_rgb_string(),
+            palette.background.to_rgb_string(),
+            palette.colors.0[1].to_rgb_string(),
+        )
+    );
+}
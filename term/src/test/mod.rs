@@ -6,33 +6,45 @@ mod c0;
 use bitflags::bitflags;
 mod c1;
 mod csi;
+mod export;
+mod mouse;
 mod selection;
 use crate::color::ColorPalette;
 use pretty_assertions::assert_eq;
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::sync::Arc;
+use termwiz::color::RgbColor;
 use termwiz::escape::csi::{Edit, EraseInDisplay, EraseInLine};
-use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
+use termwiz::escape::osc::{ITermDimension, ITermFileData, ITermProprietary};
+use termwiz::escape::{DeviceControlMode, OneBased, OperatingSystemCommand, CSI};
 use termwiz::surface::CursorShape;
 
 struct TestHost {
     title: String,
+    clicked_links: Vec<Arc<Hyperlink>>,
+    output: Vec<u8>,
+    bells: usize,
 }
 
 impl TestHost {
     fn new() -> Self {
         Self {
             title: String::new(),
+            clicked_links: vec![],
+            output: vec![],
+            bells: 0,
         }
     }
 }
 
 impl std::io::Write for TestHost {
-    fn write(&mut self, _buf: &[u8]) -> Result<usize, std::io::Error> {
-        panic!("no writer support in TestHost");
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
     }
     fn flush(&mut self) -> Result<(), std::io::Error> {
-        panic!("no writer support in TestHost");
+        Ok(())
     }
 }
 
@@ -73,7 +85,13 @@ impl TerminalHost for TestHost {
         self
     }
 
-    fn click_link(&mut self, _link: &Arc<Hyperlink>) {}
+    fn click_link(&mut self, link: &Arc<Hyperlink>) {
+        self.clicked_links.push(Arc::clone(link));
+    }
+
+    fn bell(&mut self) {
+        self.bells += 1;
+    }
 }
 
 struct TestTerm {
@@ -85,12 +103,48 @@ struct TestTerm {
 #[derive(Debug)]
 struct TestTermConfig {
     scrollback: usize,
+    word_boundary: Option<String>,
+    copy_on_select: bool,
+    trim_trailing_whitespace: bool,
+    join_wrapped_lines: bool,
+}
+
+impl Default for TestTermConfig {
+    fn default() -> Self {
+        Self {
+            scrollback: 0,
+            word_boundary: None,
+            copy_on_select: true,
+            trim_trailing_whitespace: true,
+            join_wrapped_lines: true,
+        }
+    }
 }
+
 impl TerminalConfiguration for TestTermConfig {
     fn scrollback_size(&self) -> usize {
         self.scrollback
     }
 
+    fn selection_word_boundary(&self) -> Cow<str> {
+        match self.word_boundary.as_ref() {
+            Some(boundary) => Cow::Borrowed(boundary),
+            None => Cow::Borrowed(crate::config::DEFAULT_SELECTION_WORD_BOUNDARY),
+        }
+    }
+
+    fn copy_on_select(&self) -> bool {
+        self.copy_on_select
+    }
+
+    fn selection_trim_trailing_whitespace(&self) -> bool {
+        self.trim_trailing_whitespace
+    }
+
+    fn selection_join_wrapped_lines(&self) -> bool {
+        self.join_wrapped_lines
+    }
+
     fn color_palette(&self) -> ColorPalette {
         ColorPalette::default()
     }
@@ -98,13 +152,29 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
-        let mut term = Terminal::new(
+        Self::with_config(
             height,
             width,
-            height * 16,
-            width * 8,
-            Arc::new(TestTermConfig { scrollback }),
-        );
+            TestTermConfig {
+                scrollback,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn with_word_boundary(height: usize, width: usize, word_boundary: &str) -> Self {
+        Self::with_config(
+            height,
+            width,
+            TestTermConfig {
+                word_boundary: Some(word_boundary.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn with_config(height: usize, width: usize, config: TestTermConfig) -> Self {
+        let mut term = Terminal::new(height, width, height * 16, width * 8, Arc::new(config));
         let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
         term.set_clipboard(&clip);
 
@@ -175,19 +245,36 @@ impl TestTerm {
         self.term.mouse_event(event, &mut self.host)
     }
 
+    /// Returns the data sent to the pty since the last call
+    fn take_output(&mut self) -> String {
+        String::from_utf8(std::mem::replace(&mut self.host.output, vec![])).unwrap()
+    }
+
     fn get_clipboard(&self) -> Option<String> {
         self.clip.get_contents().ok()
     }
 
     /// Inject n_times clicks of the button at the specified coordinates
     fn click_n(&mut self, x: usize, y: i64, button: MouseButton, n_times: usize) {
+        self.click_n_with_mods(x, y, button, n_times, KeyModifiers::default())
+    }
+
+    fn click_n_with_mods(
+        &mut self,
+        x: usize,
+        y: i64,
+        button: MouseButton,
+        n_times: usize,
+        modifiers: KeyModifiers,
+    ) {
         for _ in 0..n_times {
             self.mouse(MouseEvent {
                 kind: MouseEventKind::Press,
                 x,
                 y,
                 button,
-                modifiers: KeyModifiers::default(),
+                modifiers,
+                action: None,
             })
             .unwrap();
             self.mouse(MouseEvent {
@@ -195,14 +282,46 @@ impl TestTerm {
                 x,
                 y,
                 button,
-                modifiers: KeyModifiers::default(),
+                modifiers,
+                action: None,
             })
             .unwrap();
         }
     }
 
+    /// Inject a left mouse button event that carries a resolved
+    /// mouse binding action
+    fn mouse_action(&mut self, kind: MouseEventKind, x: usize, y: i64, action: MouseAction) {
+        self.mouse(MouseEvent {
+            kind,
+            x,
+            y,
+            button: if kind == MouseEventKind::Move {
+                MouseButton::None
+            } else {
+                MouseButton::Left
+            },
+            modifiers: KeyModifiers::default(),
+            action: Some(action),
+        })
+        .unwrap();
+    }
+
     /// Left mouse button drag from the start to the end coordinates
     fn drag_select(&mut self, start_x: usize, start_y: i64, end_x: usize, end_y: i64) {
+        self.drag_select_with_mods(start_x, start_y, end_x, end_y, KeyModifiers::default())
+    }
+
+    /// Left mouse button drag from the start to the end coordinates,
+    /// while holding down the specified modifiers
+    fn drag_select_with_mods(
+        &mut self,
+        start_x: usize,
+        start_y: i64,
+        end_x: usize,
+        end_y: i64,
+        modifiers: KeyModifiers,
+    ) {
         // Break any outstanding click streak that might falsely trigger due to
         // this unit test happening much faster than the CLICK_INTERVAL allows.
         self.click_n(0, 0, MouseButton::Right, 1);
@@ -214,7 +333,8 @@ impl TestTerm {
             x: start_x,
             y: start_y,
             button: MouseButton::Left,
-            modifiers: KeyModifiers::default(),
+            modifiers,
+            action: None,
         })
         .unwrap();
         assert!(self.get_clipboard().is_none());
@@ -224,7 +344,8 @@ impl TestTerm {
             x: end_x,
             y: end_y,
             button: MouseButton::None,
-            modifiers: KeyModifiers::default(),
+            modifiers,
+            action: None,
         })
         .unwrap();
         assert!(self.get_clipboard().is_none());
@@ -234,7 +355,8 @@ impl TestTerm {
             x: end_x,
             y: end_y,
             button: MouseButton::Left,
-            modifiers: KeyModifiers::default(),
+            modifiers,
+            action: None,
         })
         .unwrap();
     }
@@ -481,6 +603,72 @@ fn cursor_movement_damage() {
     term.assert_dirty_lines(&[0, 1], Some("cursor movement dirties old and new lines"));
 }
 
+/// Ensure that consumers can independently track the lines that
+/// changed since they last looked, via sequence numbers
+#[test]
+fn test_seqno_damage() {
+    let mut term = TestTerm::new(3, 3, 0);
+    let rows = term.get_viewport_stable_rows();
+    assert_eq!(rows, 0..3);
+
+    let first = term.current_seqno();
+    assert_eq!(term.get_changed_stable_rows(rows.clone(), 0), vec![0, 1, 2]);
+    assert_eq!(term.get_changed_stable_rows(rows.clone(), first), vec![]);
+
+    term.print("a");
+    let second = term.current_seqno();
+    assert!(second > first);
+    assert_eq!(term.get_changed_stable_rows(rows.clone(), first), vec![0]);
+    assert_eq!(term.get_changed_stable_rows(rows.clone(), second), vec![]);
+    assert_eq!(
+        term.current_seqno(),
+        second,
+        "seqno doesn't advance without changes"
+    );
+
+    // Lines can be dirtied without changing them, eg: to blink
+    term.make_stable_lines_dirty(1..3);
+    let third = term.current_seqno();
+    assert_eq!(
+        term.get_changed_stable_rows(rows.clone(), second),
+        vec![1, 2]
+    );
+    assert_eq!(term.get_changed_stable_rows(rows.clone(), third), vec![]);
+
+    // Scrolling purges the top line, but the remaining lines keep
+    // their stable indices
+    term.print("\r\n\r\n\r\n");
+    assert_eq!(term.get_viewport_stable_rows(), 1..4);
+    let lines = term.get_stable_lines(0..4);
+    assert_eq!(
+        lines.iter().map(|&(row, ..)| row).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+}
+
+/// Only the visible lines, and the scrollback lines that were
+/// dirtied, are examined when assigning sequence numbers
+#[test]
+fn test_seqno_scrollback() {
+    let mut term = TestTerm::new(3, 3, 10);
+    term.print("a\r\nb\r\nc\r\nd\r\ne");
+    assert_eq!(term.get_viewport_stable_rows(), 2..5);
+
+    // The lines that scrolled off while dirty are stamped too
+    let first = term.current_seqno();
+    assert_eq!(
+        term.get_changed_stable_rows(0..5, first),
+        Vec::<StableRowIndex>::new()
+    );
+    assert_eq!(term.screen_mut().take_dirty_range(), 2..5);
+
+    term.make_stable_lines_dirty(0..1);
+    let second = term.current_seqno();
+    assert!(second > first);
+    assert_eq!(term.get_changed_stable_rows(0..5, first), vec![0]);
+    assert_eq!(term.get_changed_stable_rows(0..5, second), vec![]);
+}
+
 /// Replicates a bug I initially found via:
 /// $ vim
 /// :help
@@ -509,6 +697,9 @@ fn test_delete_lines() {
 
     // test with a scroll region smaller than the screen
     term.set_scroll_region(1, 3);
+    term.cup(0, 1);
+    // Setting the scroll region homes the cursor, dirtying the top line
+    term.clean_dirty_lines();
     print_all_lines(&term);
     term.delete_lines(2);
 
@@ -517,6 +708,7 @@ fn test_delete_lines() {
 
     // expand the scroll region to fill the screen
     term.set_scroll_region(0, 4);
+    term.cup(0, 1);
     term.clean_dirty_lines();
     term.delete_lines(1);
 
@@ -556,6 +748,9 @@ fn test_scroll_margins() {
         bottom: OneBased::new(2),
     });
     term.print(format!("{}", margins));
+    // Setting the margins homes the cursor
+    term.assert_cursor_pos(0, 0, None);
+    term.cup(0, 4);
 
     term.print("z\n");
     assert_all_contents(&term, &["1", "2", "3", "4", "z"]);
@@ -568,6 +763,85 @@ fn test_scroll_margins() {
     assert_all_contents(&term, &["1", "2", "3", "W", " ", "a"]);
 }
 
+#[test]
+fn test_rewrap() {
+    let mut term = TestTerm::new(3, 10, 10);
+    term.print("hello world\r\nfoo");
+    assert_visible_contents(&term, &["hello worl", "d         ", "foo       "]);
+
+    term.resize(3, 5, 5 * 8, 3 * 16);
+    assert_all_contents(&term, &["hello", " worl", "d    ", "foo  "]);
+    term.assert_cursor_pos(3, 2, None);
+
+    term.resize(3, 10, 10 * 8, 3 * 16);
+    assert_all_contents(&term, &["hello worl", "d         ", "foo       "]);
+    term.assert_cursor_pos(3, 2, None);
+
+    // Rows that were filled to the last column and then explicitly
+    // moved to the next line are not joined together
+    let mut term = TestTerm::new(2, 5, 10);
+    term.print("abcde\r\nfg");
+    term.resize(2, 10, 10 * 8, 2 * 16);
+    assert_all_contents(&term, &["abcde     ", "fg        "]);
+    term.assert_cursor_pos(2, 1, None);
+}
+
+#[test]
+fn test_origin_mode() {
+    let mut term = TestTerm::new(5, 4, 0);
+    term.set_scroll_region(1, 3);
+    term.set_mode("?6", true);
+    term.assert_cursor_pos(0, 1, None);
+
+    // Addressing is relative to the top of the scroll region
+    term.cup(1, 0);
+    term.assert_cursor_pos(1, 1, None);
+
+    // and the cursor cannot leave it
+    term.cup(0, 10);
+    term.assert_cursor_pos(0, 3, None);
+    term.print("x");
+    term.assert_viewport_contents(&["    ", "    ", "    ", "x   ", "    "]);
+
+    term.set_mode("?6", false);
+    term.assert_cursor_pos(0, 0, None);
+}
+
+#[test]
+fn test_left_and_right_margins() {
+    let mut term = TestTerm::new(4, 5, 0);
+    term.print("abcde\r\nfghij\r\nklmno\r\npqrst");
+
+    // DECSLRM is ignored unless DECLRMM is enabled
+    term.print(CSI);
+    term.print("2;4s");
+    term.set_mode("?69", true);
+    term.print(CSI);
+    term.print("2;4s");
+    term.assert_cursor_pos(0, 0, None);
+
+    // Deleting lines only moves the text between the margins
+    term.cup(1, 1);
+    term.delete_lines(1);
+    term.assert_viewport_contents(&["abcde", "flmnj", "kqrso", "p   t"]);
+    term.assert_cursor_pos(1, 1, None);
+
+    // Text wraps at the right margin to the left margin, scrolling
+    // only the text between the margins
+    term.cup(3, 3);
+    term.print("XYZ");
+    term.assert_viewport_contents(&["almne", "fqrsj", "k  Xo", "pYZ t"]);
+
+    // Carriage return moves to the left margin
+    term.print("\rW");
+    term.assert_viewport_contents(&["almne", "fqrsj", "k  Xo", "pWZ t"]);
+
+    // Disabling DECLRMM resets the margins
+    term.set_mode("?69", false);
+    term.print("\r\r");
+    term.assert_cursor_pos(0, 3, None);
+}
+
 #[test]
 fn test_emoji_with_modifier() {
     let waving_hand = "\u{1f44b}";
@@ -588,6 +862,36 @@ fn test_emoji_with_modifier() {
     );
 }
 
+#[test]
+fn test_graphemes_split_across_writes() {
+    let mut term = TestTerm::new(3, 5, 0);
+
+    // A combining mark that arrives on its own joins the previous cell
+    term.print("e");
+    term.print("\u{301}x");
+    term.assert_cursor_pos(2, 0, None);
+
+    // The remainder of a ZWJ sequence joins the emoji before it
+    term.print("\r\n\u{1f469}");
+    term.print("\u{200d}\u{1f467}");
+    term.assert_cursor_pos(2, 1, None);
+
+    // Adding emoji presentation widens the cell
+    term.print("\r\n\u{2764}");
+    term.assert_cursor_pos(1, 2, None);
+    term.print("\u{fe0f}!");
+    term.assert_cursor_pos(3, 2, None);
+
+    assert_all_contents(
+        &term,
+        &[
+            "e\u{301}x   ",
+            "\u{1f469}\u{200d}\u{1f467}   ",
+            "\u{2764}\u{fe0f}!  ",
+        ],
+    );
+}
+
 #[test]
 fn test_hyperlinks() {
     let mut term = TestTerm::new(3, 5, 0);
@@ -597,7 +901,7 @@ fn test_hyperlinks() {
     term.hyperlink_off();
 
     let mut linked = CellAttributes::default();
-    linked.hyperlink = Some(Arc::clone(&link));
+    linked.set_hyperlink(Some(Arc::clone(&link)));
 
     assert_lines_equal(
         &term.screen().visible_lines(),
@@ -665,3 +969,438 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_hyperlink_click() {
+    let mut term = TestTerm::new(3, 5, 0);
+    let link = Arc::new(Hyperlink::new("http://example.com"));
+    term.hyperlink(&link);
+    term.print("hello");
+    term.hyperlink_off();
+
+    // Clicking with modifiers other than those configured
+    // doesn't open the link
+    term.click_n_with_mods(1, 0, MouseButton::Left, 1, KeyModifiers::CTRL);
+    assert!(term.host.clicked_links.is_empty());
+
+    // Clear the click streak
+    term.click_n(0, 1, MouseButton::Right, 1);
+
+    term.click_n(1, 0, MouseButton::Left, 1);
+    assert_eq!(term.host.clicked_links, vec![link]);
+}
+
+#[test]
+fn test_hyperlink_hover() {
+    let mut term = TestTerm::new(3, 5, 0);
+    let link = Arc::new(Hyperlink::new("http://example.com"));
+    term.print("\r\n");
+    term.hyperlink(&link);
+    term.print("hello");
+    term.hyperlink_off();
+    term.print("\r\nworld");
+    term.clean_dirty_lines();
+
+    let hover = |term: &mut TestTerm, x: usize, y: i64| {
+        term.mouse(MouseEvent {
+            kind: MouseEventKind::Move,
+            x,
+            y,
+            button: MouseButton::None,
+            modifiers: KeyModifiers::default(),
+            action: None,
+        })
+        .unwrap();
+    };
+
+    hover(&mut term, 1, 0);
+    assert_eq!(term.current_highlight(), None);
+    term.assert_dirty_lines(&[], Some("no link under the mouse"));
+
+    hover(&mut term, 2, 1);
+    assert_eq!(term.current_highlight(), Some(Arc::clone(&link)));
+    term.assert_dirty_lines(&[1], Some("only the linked row is repainted"));
+    term.clean_dirty_lines();
+
+    hover(&mut term, 3, 1);
+    term.assert_dirty_lines(&[], Some("moving within the same link"));
+
+    hover(&mut term, 3, 2);
+    assert_eq!(term.current_highlight(), None);
+    term.assert_dirty_lines(&[1], Some("leaving the link repaints it"));
+}
+
+#[test]
+fn test_current_dir() {
+    let mut term = TestTerm::new(3, 5, 0);
+    assert_eq!(term.get_current_dir(), None);
+
+    term.print("\x1b]7;file://localhost/tmp\x07");
+    assert_eq!(term.get_current_dir(), Some("file://localhost/tmp"));
+
+    // Nothing is printed for the escape sequence
+    term.assert_viewport_contents(&["     ", "     ", "     "]);
+}
+
+#[test]
+fn test_dynamic_colors() {
+    let mut term = TestTerm::new(3, 5, 0);
+    let defaults = ColorPalette::default();
+
+    term.print("\x1b]11;?\x07");
+    assert_eq!(
+        term.take_output(),
+        format!(
+            "\x1b]11;{}\x07",
+            defaults.background.to_x11_16bit_rgb_string()
+        )
+    );
+
+    let orange = RgbColor::new(0xff, 0x80, 0);
+    term.print("\x1b]11;#ff8000\x07\x1b]11;?\x07");
+    assert_eq!(term.take_output(), "\x1b]11;rgb:ffff/8080/0000\x07");
+    assert_eq!(term.palette().background, orange);
+
+    // Setting several colors at once starts from the given one
+    term.print("\x1b]10;rgb:00/00/ff;rgb:ff/00/00\x07");
+    assert_eq!(term.palette().foreground, RgbColor::new(0, 0, 0xff));
+    assert_eq!(term.palette().background, RgbColor::new(0xff, 0, 0));
+    term.print("\x1b]12;#00ff00\x07");
+    assert_eq!(term.palette().cursor_bg, RgbColor::new(0, 0xff, 0));
+
+    term.print("\x1b]4;1;rgb:11/22/33\x07\x1b]4;1;?;2;?\x07");
+    assert_eq!(
+        term.take_output(),
+        format!(
+            "\x1b]4;1;rgb:1111/2222/3333\x07\x1b]4;2;{}\x07",
+            defaults.colors.0[2].to_x11_16bit_rgb_string()
+        )
+    );
+
+    term.print("\x1b]104;1\x07");
+    assert_eq!(term.palette().colors.0[1], defaults.colors.0[1]);
+    term.print("\x1b]110\x07\x1b]111\x07\x1b]112\x07");
+    assert_eq!(term.palette().foreground, defaults.foreground);
+    assert_eq!(term.palette().background, defaults.background);
+    assert_eq!(term.palette().cursor_bg, defaults.cursor_bg);
+}
+
+#[test]
+fn test_erase_scrollback() {
+    let mut term = TestTerm::new(3, 2, 10);
+    term.print("1\r\n2\r\n3\r\n4\r\n5");
+    assert_all_contents(&term, &["1 ", "2 ", "3 ", "4 ", "5 "]);
+    // Lines that scroll off the top are stored compactly
+    assert!(term.screen().lines[0].is_compressed());
+    assert!(!term.screen().lines[2].is_compressed());
+
+    term.erase_in_display(EraseInDisplay::EraseScrollback);
+    assert_all_contents(&term, &["3 ", "4 ", "5 "]);
+    term.assert_cursor_pos(1, 2, None);
+
+    term.print("\r\n6");
+    term.erase_scrollback_and_viewport();
+    assert_all_contents(&term, &["6 ", "  ", "  "]);
+    term.assert_cursor_pos(1, 0, None);
+}
+
+#[test]
+fn test_resize_reveals_compressed_scrollback() {
+    let mut term = TestTerm::new(3, 2, 10);
+    term.print("1\r\n2\r\n3\r\n4\r\n5");
+    assert!(term.screen().lines[0].is_compressed());
+
+    // Growing the screen brings the scrollback back into view, where
+    // the lines can be modified again
+    term.resize(5, 2, 16, 80);
+    assert_visible_contents(&term, &["1 ", "2 ", "3 ", "4 ", "5 "]);
+    assert!(term.screen().lines.iter().all(|line| !line.is_compressed()));
+
+    term.cup(0, 0);
+    term.print("abc");
+    term.cup(1, 3);
+    term.print("de");
+    assert_visible_contents(&term, &["ab", "c ", "3 ", "4d", "e "]);
+}
+
+#[test]
+fn test_prompt_rows() {
+    let mut term = TestTerm::new(3, 10, 10);
+    term.print("\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n");
+    term.print("\x1b]133;C\x07a.txt\r\nb.txt\r\n");
+    term.print("\x1b]133;D;0\x07\x1b]133;A\x07$ ");
+    assert_all_contents(
+        &term,
+        &["$ ls      ", "a.txt     ", "b.txt     ", "$         "],
+    );
+
+    // The first prompt is found even though it has been
+    // compressed in the scrollback
+    assert_eq!(term.get_prompt_stable_rows(), vec![0, 3]);
+}
+
+fn assert_image_cells(term: &TestTerm, expect: &[&str]) {
+    let lines = term.screen().visible_lines();
+    let actual: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            line.cells()
+                .iter()
+                .map(|cell| {
+                    if cell.attrs().image().is_some() {
+                        '#'
+                    } else {
+                        ' '
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    assert_eq!(actual, expect);
+}
+
+#[test]
+fn test_iterm_image() {
+    let mut term = TestTerm::new(3, 4, 0);
+    // The cells are 8x16 pixels.  Scaling this image to be two cells
+    // wide makes it 16x16 pixels, so it is padded out to 2x1 cells.
+    let mut data = vec![];
+    image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 4))
+        .write_to(&mut data, image::ImageOutputFormat::PNG)
+        .unwrap();
+    let file = ITermFileData {
+        name: None,
+        size: None,
+        width: ITermDimension::Cells(2),
+        height: ITermDimension::Automatic,
+        preserve_aspect_ratio: true,
+        inline: true,
+        data,
+    };
+    term.print(format!(
+        "{}",
+        OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(file)))
+    ));
+    assert_image_cells(&term, &["##  ", "    ", "    "]);
+    term.assert_cursor_pos(0, 1, None);
+}
+
+#[test]
+fn test_iterm_image_wider_than_terminal() {
+    let mut term = TestTerm::new(3, 4, 0);
+    // Asking for 100 cells is clamped to the 4 cell width of the
+    // terminal, and the automatic height is scaled to match, making
+    // the image 32x32 pixels, or 4x2 cells.
+    let mut data = vec![];
+    image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 4))
+        .write_to(&mut data, image::ImageOutputFormat::PNG)
+        .unwrap();
+    let file = ITermFileData {
+        name: None,
+        size: None,
+        width: ITermDimension::Cells(100),
+        height: ITermDimension::Automatic,
+        preserve_aspect_ratio: true,
+        inline: true,
+        data,
+    };
+    term.print(format!(
+        "{}",
+        OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(file)))
+    ));
+    assert_image_cells(&term, &["####", "####", "    "]);
+    term.assert_cursor_pos(0, 2, None);
+}
+
+#[test]
+fn test_sixel_image() {
+    let mut term = TestTerm::new(3, 4, 0);
+    // Nine pixels wide and six pixels tall; this needs two cells
+    term.print("\x1bPq#1;2;100;0;0#1!9~\x1b\\");
+    assert_image_cells(&term, &["##  ", "    ", "    "]);
+    term.assert_cursor_pos(0, 1, None);
+}
+
+#[test]
+fn test_bracketed_paste() {
+    let mut term = TestTerm::new(3, 4, 0);
+    let mut output = vec![];
+    term.send_paste("a\nb", &mut output).unwrap();
+    assert_eq!(output, b"a\nb");
+
+    term.set_mode("?2004", true);
+    assert!(term.bracketed_paste_enabled());
+    let mut output = vec![];
+    term.send_paste("a\nb", &mut output).unwrap();
+    assert_eq!(output, b"\x1b[200~a\nb\x1b[201~");
+
+    term.set_mode("?2004", false);
+    assert!(!term.bracketed_paste_enabled());
+}
+
+#[test]
+fn test_focus_tracking() {
+    let mut term = TestTerm::new(3, 4, 0);
+    let mut output = vec![];
+    term.focus_changed(false, &mut output).unwrap();
+    assert!(output.is_empty());
+
+    term.set_mode("?1004", true);
+    term.focus_changed(false, &mut output).unwrap();
+    term.focus_changed(true, &mut output).unwrap();
+    assert_eq!(output, b"\x1b[O\x1b[I");
+
+    term.set_mode("?1004", false);
+    let mut output = vec![];
+    term.focus_changed(true, &mut output).unwrap();
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_synchronized_output() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b[?2026hab");
+    assert!(term.is_synchronized_update_active());
+    assert_visible_contents(&term, &["    ", "    "]);
+    term.print("cd\x1b[?2026l");
+    assert!(!term.is_synchronized_update_active());
+    assert_visible_contents(&term, &["abcd", "    "]);
+
+    // The BSU/ESU device control strings have the same effect
+    term.print("\r\n\x1bP=1s\x1b\\ef");
+    assert!(term.is_synchronized_update_active());
+    assert_visible_contents(&term, &["abcd", "    "]);
+    term.print("\x1bP=2s\x1b\\");
+    assert!(!term.is_synchronized_update_active());
+    assert_visible_contents(&term, &["abcd", "ef  "]);
+}
+
+#[test]
+fn test_mode_query() {
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("\x1b[?2004$p\x1b[?1000$p\x1b[?1049$p");
+    assert_eq!(
+        term.take_output(),
+        "\x1b[?2004;2$y\x1b[?1000;2$y\x1b[?1049;2$y"
+    );
+
+    term.set_mode("?2004", true);
+    term.set_mode("?1000", true);
+    term.set_mode("?1049", true);
+    term.print("\x1b[?2004$p\x1b[?1000$p\x1b[?1049$p\x1b[?25$p");
+    assert_eq!(
+        term.take_output(),
+        "\x1b[?2004;1$y\x1b[?1000;1$y\x1b[?1049;1$y\x1b[?25;1$y"
+    );
+
+    // Modes that are not recognized and modes that can't be changed
+    term.print("\x1b[?4242$p\x1b[?1001$p");
+    assert_eq!(term.take_output(), "\x1b[?4242;0$y\x1b[?1001;4$y");
+
+    // ANSI modes
+    term.print("\x1b[4h\x1b[4$p\x1b[20$p");
+    assert_eq!(term.take_output(), "\x1b[4;1$y\x1b[20;0$y");
+
+    // Synchronized output is answered even while an update is deferred
+    term.print("\x1b[?2026$p\x1b[?2026h\x1b[?2026$p");
+    assert_eq!(term.take_output(), "\x1b[?2026;2$y\x1b[?2026;1$y");
+    term.print("\x1b[?2026l");
+}
+
+#[test]
+fn test_xtgettcap() {
+    let mut term = TestTerm::new(2, 4, 0);
+    // TN;colors;bce;smcup;nonsense
+    term.print("\x1bP+q544E;636F6C6F7273;626365;736D637570;6E6F6E73656E7365\x1b\\");
+    assert_eq!(
+        term.take_output(),
+        "\x1bP1+r544E=77657A7465726D\x1b\\\
+         \x1bP1+r636F6C6F7273=323536\x1b\\\
+         \x1bP1+r626365\x1b\\\
+         \x1bP1+r736D637570=1B5B3F3130343968\x1b\\\
+         \x1bP0+r6E6F6E73656E7365\x1b\\"
+    );
+
+    // The keys described by the terminfo entry are the ones that are sent
+    for (key, cap) in &[
+        (KeyCode::Function(1), "kf1"),
+        (KeyCode::Function(5), "kf5"),
+        (KeyCode::Function(12), "kf12"),
+        (KeyCode::Delete, "kdch1"),
+        (KeyCode::PageUp, "kpp"),
+        (KeyCode::Backspace, "kbs"),
+    ] {
+        let mut output = vec![];
+        term.key_down(*key, KeyModifiers::NONE, &mut output)
+            .unwrap();
+        match crate::terminfo::lookup(cap) {
+            Some(crate::terminfo::Capability::Str(s)) => {
+                assert_eq!(std::str::from_utf8(&output).unwrap(), s, "{}", cap)
+            }
+            _ => panic!("{} is missing", cap),
+        }
+    }
+}
+
+#[test]
+fn test_alt_screen() {
+    let mut term = TestTerm::new(3, 4, 10);
+    term.print("\x1b[1mab");
+
+    term.set_mode("?1049", true);
+    term.assert_viewport_contents(&["    ", "    ", "    "]);
+    term.assert_cursor_pos(0, 0, None);
+
+    // Output on the alternate screen doesn't reach the scrollback,
+    // and its scroll region doesn't apply to the primary screen
+    term.set_scroll_region(0, 1);
+    term.print("\x1b[0mxy\r\n1\r\n2\r\n3");
+    term.assert_viewport_contents(&["2   ", "3   ", "    "]);
+
+    term.set_mode("?1049", false);
+    assert_all_contents(&term, &["ab  ", "    ", "    "]);
+    term.assert_cursor_pos(2, 0, None);
+
+    // The pen was saved along with the cursor position
+    term.print("c");
+    assert_eq!(
+        term.screen().visible_lines()[0].cells()[2]
+            .attrs()
+            .intensity(),
+        termwiz::cell::Intensity::Bold
+    );
+
+    term.print("\r\n\r\n\r\n");
+    assert_all_contents(&term, &["abc ", "    ", "    ", "    "]);
+}
+
+#[test]
+fn test_device_control_handler() {
+    struct Recorder(std::rc::Rc<RefCell<Vec<DeviceControlMode>>>);
+    impl DeviceControlHandler for Recorder {
+        fn handle_device_control(&mut self, control: DeviceControlMode) {
+            self.0.borrow_mut().push(control);
+        }
+    }
+
+    let mut term = TestTerm::new(3, 4, 0);
+    let received = std::rc::Rc::new(RefCell::new(vec![]));
+    term.set_device_control_handler(Box::new(Recorder(std::rc::Rc::clone(&received))));
+
+    term.print("\x1bP1000pa\n\x1b\\b");
+    assert_eq!(
+        *received.borrow(),
+        vec![
+            DeviceControlMode::Enter {
+                params: vec![1000],
+                intermediates: vec![],
+                ignored_extra_intermediates: false,
+                byte: b'p',
+            },
+            DeviceControlMode::Data(b'a'),
+            DeviceControlMode::Data(b'\n'),
+            DeviceControlMode::Exit,
+        ]
+    );
+    term.assert_viewport_contents(&["b   ", "    ", "    "]);
+}
@@ -0,0 +1,122 @@
+use super::*;
+use pretty_assertions::assert_eq;
+
+/// Test that clicks are reported using the SGR encoding
+#[test]
+fn sgr_mouse_report() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.print("hello world");
+    term.set_mode("?1000", true);
+    term.set_mode("?1006", true);
+
+    term.click_n(1, 2, MouseButton::Left, 1);
+    assert_eq!(term.take_output(), "\x1b[<0;2;3M\x1b[<0;2;3m");
+
+    term.click_n_with_mods(1, 2, MouseButton::Right, 1, KeyModifiers::CTRL);
+    assert_eq!(term.take_output(), "\x1b[<18;2;3M\x1b[<18;2;3m");
+
+    term.mouse(MouseEvent {
+        kind: MouseEventKind::Press,
+        x: 0,
+        y: 0,
+        button: MouseButton::WheelUp(1),
+        modifiers: KeyModifiers::default(),
+        action: None,
+    })
+    .unwrap();
+    assert_eq!(term.take_output(), "\x1b[<64;1;1M");
+}
+
+/// Test that clicks are reported using the legacy encoding
+/// when SGR mouse mode is not enabled
+#[test]
+fn legacy_mouse_report() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.set_mode("?1000", true);
+
+    term.click_n(1, 2, MouseButton::Left, 1);
+    assert_eq!(term.take_output(), "\x1b[M \"#\x1b[M#\"#");
+}
+
+/// Test that X10 mode only reports button presses, and
+/// does so without the modifier bits
+#[test]
+fn x10_mouse_report() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.set_mode("?9", true);
+
+    term.click_n_with_mods(1, 2, MouseButton::Right, 1, KeyModifiers::CTRL);
+    assert_eq!(term.take_output(), "\x1b[M\"\"#");
+
+    // Enabling normal tracking reports releases and modifiers
+    term.set_mode("?1000", true);
+    term.click_n_with_mods(1, 2, MouseButton::Right, 1, KeyModifiers::CTRL);
+    assert_eq!(term.take_output(), "\x1b[M2\"#\x1b[M3\"#");
+}
+
+/// Test that clicks are reported using the urxvt encoding
+#[test]
+fn urxvt_mouse_report() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.set_mode("?1000", true);
+    term.set_mode("?1015", true);
+
+    term.click_n(1, 2, MouseButton::Left, 1);
+    assert_eq!(term.take_output(), "\x1b[32;2;3M\x1b[35;2;3M");
+
+    // SGR takes precedence
+    term.set_mode("?1006", true);
+    term.click_n(1, 2, MouseButton::Left, 1);
+    assert_eq!(term.take_output(), "\x1b[<0;2;3M\x1b[<0;2;3m");
+}
+
+/// Test that the UTF-8 encoding can represent large coordinates
+#[test]
+fn utf8_mouse_report() {
+    let mut term = TestTerm::new(3, 300, 0);
+    term.set_mode("?1000", true);
+    term.set_mode("?1005", true);
+
+    term.click_n(250, 2, MouseButton::Left, 1);
+    assert_eq!(term.take_output(), "\x1b[M \u{11b}#\x1b[M#\u{11b}#");
+}
+
+/// Test that motion is only reported in the appropriate modes
+#[test]
+fn mouse_motion_report() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.set_mode("?1000", true);
+    term.set_mode("?1006", true);
+
+    let motion = MouseEvent {
+        kind: MouseEventKind::Move,
+        x: 3,
+        y: 1,
+        button: MouseButton::None,
+        modifiers: KeyModifiers::default(),
+        action: None,
+    };
+
+    term.mouse(motion).unwrap();
+    assert_eq!(term.take_output(), "");
+
+    term.set_mode("?1003", true);
+    term.mouse(motion).unwrap();
+    assert_eq!(term.take_output(), "\x1b[<35;4;2M");
+}
+
+/// Test that holding shift bypasses mouse reporting and
+/// allows making a selection
+#[test]
+fn bypass_mouse_report() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.print("hello world");
+    term.set_mode("?1000", true);
+    term.set_mode("?1006", true);
+
+    term.drag_select_with_mods(0, 0, 4, 0, KeyModifiers::SHIFT);
+    // Only the right click that drag_select_with_mods uses to
+    // break the click streak is reported
+    assert_eq!(term.take_output(), "\x1b[<2;1;1M\x1b[<2;1;1m");
+    assert_eq!(term.get_clipboard().unwrap(), "hello");
+}
@@ -53,6 +53,17 @@ fn double_click_selection() {
     assert_eq!(term.get_clipboard().unwrap(), "hello");
 }
 
+/// Test that double clicking selects words made of double-width
+/// characters, including when clicking on their right hand half
+#[test]
+fn double_click_wide_selection() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("ls \u{65e5}\u{672c} x");
+
+    term.click_n(6, 0, MouseButton::Left, 2);
+    assert_eq!(term.get_clipboard().unwrap(), "\u{65e5}\u{672c}");
+}
+
 /// Test triple click to select a line
 #[test]
 fn triple_click_selection() {
@@ -61,7 +72,40 @@ fn triple_click_selection() {
     assert_visible_contents(&term, &["hello worl", "d         ", "          "]);
     term.click_n(1, 0, MouseButton::Left, 3);
 
-    assert_eq!(term.get_clipboard().unwrap(), "hello worl");
+    assert_eq!(term.get_clipboard().unwrap(), "hello world");
+}
+
+/// Test triple click on the wrapped portion of a line selects
+/// the whole logical line
+#[test]
+fn triple_click_wrapped_selection() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("hello world\r\nfoo");
+    assert_visible_contents(&term, &["hello worl", "d         ", "foo       "]);
+    term.click_n(0, 1, MouseButton::Left, 3);
+
+    assert_eq!(term.get_clipboard().unwrap(), "hello world");
+}
+
+/// Test quadruple click to select a paragraph
+#[test]
+fn quadruple_click_selection() {
+    let mut term = TestTerm::new(6, 10, 0);
+    term.print("one\r\nhello world\r\nthree\r\n\r\nfive");
+    assert_visible_contents(
+        &term,
+        &[
+            "one       ",
+            "hello worl",
+            "d         ",
+            "three     ",
+            "          ",
+            "five      ",
+        ],
+    );
+    term.click_n(1, 2, MouseButton::Left, 4);
+
+    assert_eq!(term.get_clipboard().unwrap(), "one\nhello world\nthree");
 }
 
 /// Test double click on wrapped line selects across the line boundary
@@ -92,9 +136,255 @@ fn selection_in_scrollback() {
     // Clear the click streak
     term.click_n(0, 1, MouseButton::Right, 1);
 
+    // The rows are all part of one wrapped logical line
+    term.click_n(0, 1, MouseButton::Left, 3);
+    assert_eq!(term.get_clipboard().unwrap(), "1\n2\n3\n4");
+
+    term.drag_select(0, 0, 0, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "2\n3");
+}
+
+/// Make sure that triple click selects the logical line under the
+/// mouse when the viewport is scrolled back
+#[test]
+fn triple_click_in_scrollback() {
+    let mut term = TestTerm::new(2, 2, 4);
+    term.print("1\r\n2\r\n3\r\n4");
+    assert_all_contents(&term, &["1 ", "2 ", "3 ", "4 "]);
+
+    term.scroll_viewport(-1);
+    term.assert_viewport_contents(&["2 ", "3 "]);
+
     term.click_n(0, 1, MouseButton::Left, 3);
     assert_eq!(term.get_clipboard().unwrap(), "3");
+}
+
+/// Test that alt+drag makes a rectangular selection
+#[test]
+fn block_selection() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.print("one   1 foo\r\ntwo   2 bar\r\nthree 3 baz");
+    assert_visible_contents(&term, &["one   1 foo ", "two   2 bar ", "three 3 baz "]);
+
+    term.drag_select_with_mods(6, 0, 8, 2, KeyModifiers::ALT);
+    assert_eq!(term.get_clipboard().unwrap(), "1 f\n2 b\n3 b");
+
+    // Dragging from the bottom right to the top left selects the same block
+    term.drag_select_with_mods(8, 2, 6, 0, KeyModifiers::ALT);
+    assert_eq!(term.get_clipboard().unwrap(), "1 f\n2 b\n3 b");
+}
+
+/// Test that the word boundary characters are configurable
+#[test]
+fn double_click_word_boundary() {
+    let mut term = TestTerm::new(1, 30, 0);
+    term.print("ls /usr/local/bin");
+    term.click_n(8, 0, MouseButton::Left, 2);
+    assert_eq!(term.get_clipboard().unwrap(), "/usr/local/bin");
+
+    let mut term = TestTerm::with_word_boundary(1, 30, " /");
+    term.print("ls /usr/local/bin");
+    term.click_n(8, 0, MouseButton::Left, 2);
+    assert_eq!(term.get_clipboard().unwrap(), "local");
+}
+
+/// Test that alt + triple click selects the semantic zone
+/// marked up by OSC 133 sequences
+#[test]
+fn semantic_zone_selection() {
+    let mut term = TestTerm::new(4, 10, 0);
+    term.print("\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n");
+    term.print("\x1b]133;C\x07a.txt\r\nb.txt\r\n");
+    term.print("\x1b]133;D;0\x07\x1b]133;A\x07$ ");
+    assert_visible_contents(
+        &term,
+        &["$ ls      ", "a.txt     ", "b.txt     ", "$         "],
+    );
+
+    term.click_n_with_mods(2, 0, MouseButton::Left, 3, KeyModifiers::ALT);
+    assert_eq!(term.get_clipboard().unwrap(), "ls");
 
+    // Clear the click streak
+    term.click_n(0, 1, MouseButton::Right, 1);
+
+    term.click_n_with_mods(3, 2, MouseButton::Left, 3, KeyModifiers::ALT);
+    assert_eq!(term.get_clipboard().unwrap(), "a.txt\nb.txt");
+}
+
+/// Test that the actions resolved from the mouse bindings are
+/// used in place of the built-in click handling
+#[test]
+fn mouse_action_selection() {
+    let mut term = TestTerm::new(3, 12, 0);
+    term.print("one   1 foo\r\ntwo   2 bar\r\nthree 3 baz");
+
+    // A single click that selects the word under the mouse
+    term.mouse_action(
+        MouseEventKind::Press,
+        9,
+        0,
+        MouseAction::SelectText(MouseSelectionMode::Word),
+    );
+    term.mouse_action(
+        MouseEventKind::Release,
+        9,
+        0,
+        MouseAction::CompleteSelection,
+    );
+    assert_eq!(term.get_clipboard().unwrap(), "foo");
+
+    // Select a block, then extend it as if by shift-clicking
+    term.mouse_action(
+        MouseEventKind::Press,
+        6,
+        0,
+        MouseAction::SelectText(MouseSelectionMode::Block),
+    );
+    term.mouse_action(MouseEventKind::Move, 8, 1, MouseAction::ExtendSelection);
+    term.mouse_action(
+        MouseEventKind::Release,
+        8,
+        1,
+        MouseAction::CompleteSelection,
+    );
+    assert_eq!(term.get_clipboard().unwrap(), "1 f\n2 b");
+
+    term.mouse_action(MouseEventKind::Press, 8, 2, MouseAction::ExtendSelection);
+    term.mouse_action(
+        MouseEventKind::Release,
+        8,
+        2,
+        MouseAction::CompleteSelection,
+    );
+    assert_eq!(term.get_clipboard().unwrap(), "1 f\n2 b\n3 b");
+
+    // Nop suppresses the built-in selection entirely
+    term.mouse_action(MouseEventKind::Press, 0, 2, MouseAction::Nop);
+    term.mouse_action(MouseEventKind::Move, 4, 2, MouseAction::Nop);
+    term.mouse_action(MouseEventKind::Release, 4, 2, MouseAction::Nop);
+    assert_eq!(term.get_clipboard().unwrap(), "1 f\n2 b\n3 b");
+}
+
+/// Test that the selection follows the text as it scrolls into
+/// the scrollback, and is trimmed as the scrollback is discarded
+#[test]
+fn selection_follows_scroll() {
+    let mut term = TestTerm::new(3, 5, 1);
+    term.print("1\r\n2\r\n3");
     term.drag_select(0, 0, 0, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "1\n2");
+
+    // "1" moves into the scrollback
+    term.print("\r\n4");
+    assert_all_contents(&term, &["1    ", "2    ", "3    ", "4    "]);
+    assert_eq!(term.get_selection_text(), "1\n2");
+
+    // "1" is discarded from the scrollback
+    term.print("\r\n5");
+    assert_all_contents(&term, &["2    ", "3    ", "4    ", "5    "]);
+    assert_eq!(term.get_selection_text(), "2");
+
+    // and now "2" is discarded too
+    term.print("\r\n6");
+    assert_eq!(term.get_selection_text(), "");
+    assert!(term.selection_range().is_none());
+}
+
+/// Test that the selection follows the text when the screen is resized
+#[test]
+fn selection_follows_resize() {
+    let mut term = TestTerm::new(3, 5, 5);
+    term.print("1\r\n2\r\n3");
+    term.drag_select(0, 1, 0, 2);
     assert_eq!(term.get_clipboard().unwrap(), "2\n3");
+
+    term.resize(2, 5, 10, 32);
+    term.assert_viewport_contents(&["2    ", "3    "]);
+    assert_eq!(term.get_selection_text(), "2\n3");
+}
+
+/// Test that the selection is only copied to the clipboard when it
+/// is made if copy_on_select is enabled
+#[test]
+fn copy_on_select() {
+    let mut term = TestTerm::with_config(
+        2,
+        5,
+        TestTermConfig {
+            copy_on_select: false,
+            ..Default::default()
+        },
+    );
+    term.print("hello\r\nworld");
+    term.drag_select(0, 0, 4, 0);
+    assert_eq!(term.get_clipboard(), None);
+    assert_eq!(term.get_selection_text(), "hello");
+
+    // Clicking to clear the selection leaves the clipboard alone too
+    term.clip.set_contents(Some("copied".to_string())).unwrap();
+    term.click_n(1, 1, MouseButton::Left, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "copied");
+
+    let mut term = TestTerm::new(2, 5, 0);
+    term.print("hello\r\nworld");
+    term.drag_select(0, 0, 4, 0);
+    assert_eq!(term.get_clipboard().unwrap(), "hello");
+}
+
+/// Test that trailing whitespace is only removed from the rows of the
+/// selection if selection_trim_trailing_whitespace is enabled
+#[test]
+fn selection_trim_trailing_whitespace() {
+    let mut term = TestTerm::new(2, 5, 0);
+    term.print("hi\r\nyo");
+    term.drag_select(0, 0, 4, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "hi\nyo");
+
+    let mut term = TestTerm::with_config(
+        2,
+        5,
+        TestTermConfig {
+            trim_trailing_whitespace: false,
+            ..Default::default()
+        },
+    );
+    term.print("hi\r\nyo");
+    term.drag_select(0, 0, 4, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "hi   \nyo   ");
+}
+
+/// Test that a wrapped line is only copied as a single line if
+/// selection_join_wrapped_lines is enabled
+#[test]
+fn selection_join_wrapped_lines() {
+    let mut term = TestTerm::new(2, 5, 0);
+    term.print("helloworld");
+    assert_visible_contents(&term, &["hello", "world"]);
+    term.drag_select(0, 0, 4, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "helloworld");
+
+    let mut term = TestTerm::with_config(
+        2,
+        5,
+        TestTermConfig {
+            join_wrapped_lines: false,
+            ..Default::default()
+        },
+    );
+    term.print("helloworld");
+    term.drag_select(0, 0, 4, 1);
+    assert_eq!(term.get_clipboard().unwrap(), "hello\nworld");
+}
+
+/// Test that the selection follows the text when it is rewrapped
+#[test]
+fn selection_follows_rewrap() {
+    let mut term = TestTerm::new(3, 10, 10);
+    term.print("hello world\r\nfoo");
+    term.drag_select(0, 2, 2, 2);
+    assert_eq!(term.get_clipboard().unwrap(), "foo");
+
+    term.resize(3, 5, 5 * 8, 3 * 16);
+    term.assert_viewport_contents(&[" worl", "d    ", "foo  "]);
+    assert_eq!(term.get_selection_text(), "foo");
 }
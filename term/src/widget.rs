@@ -0,0 +1,227 @@
+//! A terminal that can be embedded in another application.
+//! `TerminalWidget` couples a pty, the program running in it and the
+//! terminal model, so that an application only needs to forward input
+//! to it and draw its lines.  It also implements the termwiz `Widget`
+//! trait, so that it can be placed in a termwiz widget layout.
+use crate::{KeyCode, KeyModifiers, MouseEvent, Terminal, TerminalConfiguration, TerminalHost};
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize, PtySystemSelection};
+use std::io::Read;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use termwiz::hyperlink::Hyperlink;
+use termwiz::input::InputEvent;
+use termwiz::surface::{Change, Position};
+use termwiz::widgets::{CursorShapeAndPosition, RenderArgs, UpdateArgs, Widget, WidgetEvent};
+
+/// Routes the responses of the terminal to the pty
+struct Host<'a> {
+    writer: &'a mut dyn std::io::Write,
+}
+
+impl<'a> TerminalHost for Host<'a> {
+    fn writer(&mut self) -> &mut dyn std::io::Write {
+        self.writer
+    }
+
+    fn set_title(&mut self, _title: &str) {}
+
+    fn click_link(&mut self, _link: &Arc<Hyperlink>) {}
+}
+
+pub struct TerminalWidget {
+    terminal: Terminal,
+    pty: Box<dyn MasterPty>,
+    process: Box<dyn Child>,
+    /// The output of the pty, read by a background thread
+    output: Receiver<Vec<u8>>,
+    /// Set when the pty has been closed
+    eof: bool,
+}
+
+impl TerminalWidget {
+    /// Spawns `cmd` in a new pty of the given size.
+    /// `notify` is called from a background thread whenever the program
+    /// produces output, and when the pty is closed; the embedding
+    /// application should then arrange to call `poll` from its own
+    /// thread, and redraw the terminal if that returns true.
+    pub fn spawn<F: Fn() + Send + 'static>(
+        cmd: CommandBuilder,
+        size: PtySize,
+        config: Arc<dyn TerminalConfiguration>,
+        notify: F,
+    ) -> anyhow::Result<Self> {
+        let pair = PtySystemSelection::default().get()?.openpty(size)?;
+        let process = pair.slave.spawn_command(cmd)?;
+        let mut reader = pair.master.try_clone_reader()?;
+
+        let (tx, output) = channel();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 32 * 1024];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(size) => {
+                        if tx.send(buf[..size].to_vec()).is_err() {
+                            return;
+                        }
+                        notify();
+                    }
+                }
+            }
+            drop(tx);
+            notify();
+        });
+
+        let terminal = Terminal::new(
+            size.rows as usize,
+            size.cols as usize,
+            size.pixel_width as usize,
+            size.pixel_height as usize,
+            config,
+        );
+
+        Ok(Self {
+            terminal,
+            pty: pair.master,
+            process,
+            output,
+            eof: false,
+        })
+    }
+
+    /// Applies the output that the program has produced so far to the
+    /// terminal model, without blocking.
+    /// Returns true if there was any output.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.output.try_recv() {
+                Ok(data) => {
+                    let mut host = Host {
+                        writer: &mut self.pty,
+                    };
+                    self.terminal.advance_bytes(data, &mut host);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.eof = true;
+                    break;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Returns the terminal model, whose lines and cursor position
+    /// are used to draw the terminal
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+
+    /// Returns true once the program has exited and all of its output
+    /// has been applied by `poll`
+    pub fn is_dead(&mut self) -> bool {
+        match self.process.try_wait() {
+            Ok(None) => self.eof,
+            _ => true,
+        }
+    }
+
+    /// Sends a key press to the program, encoded according to the
+    /// current keyboard modes of the terminal
+    pub fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        self.terminal.key_down(key, mods, &mut self.pty)
+    }
+
+    /// Sends a mouse event to the program, if it has enabled mouse
+    /// reporting, and otherwise uses it to select text
+    pub fn mouse_event(&mut self, event: MouseEvent) -> anyhow::Result<()> {
+        let mut host = Host {
+            writer: &mut self.pty,
+        };
+        self.terminal.mouse_event(event, &mut host)
+    }
+
+    /// Sends text to the program as a paste, which is bracketed if
+    /// the program has asked for that
+    pub fn send_paste(&mut self, text: &str) -> anyhow::Result<()> {
+        self.terminal.send_paste(text, &mut self.pty)
+    }
+
+    /// Tells the program whether the terminal has the input focus,
+    /// if it has asked to be told
+    pub fn focus_changed(&mut self, focused: bool) -> anyhow::Result<()> {
+        self.terminal.focus_changed(focused, &mut self.pty)
+    }
+
+    /// Resizes the terminal model and the pty; the program is notified
+    /// of the change by the pty
+    pub fn resize(&mut self, size: PtySize) -> anyhow::Result<()> {
+        self.terminal.resize(
+            size.rows as usize,
+            size.cols as usize,
+            size.pixel_width as usize,
+            size.pixel_height as usize,
+        );
+        self.pty.resize(size)
+    }
+}
+
+impl Widget for TerminalWidget {
+    fn render(&mut self, args: &mut RenderArgs) {
+        self.poll();
+
+        // The layout decides how big the terminal is
+        let (width, height) = args.surface.dimensions();
+        let (rows, cols) = {
+            let screen = self.terminal.screen();
+            (screen.physical_rows, screen.physical_cols)
+        };
+        if width != cols || height != rows {
+            let size = PtySize {
+                rows: height as u16,
+                cols: width as u16,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            if let Err(err) = self.resize(size) {
+                log::error!("failed to resize pty: {}", err);
+            }
+        }
+
+        args.surface
+            .add_change(Change::ClearScreen(Default::default()));
+        let rows = self.terminal.get_viewport_stable_rows();
+        for (y, (_, line, _)) in self.terminal.get_stable_lines(rows).iter().enumerate() {
+            args.surface.add_change(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(y),
+            });
+            args.surface.add_changes(line.changes(&Default::default()));
+        }
+
+        let cursor = self.terminal.cursor_pos();
+        *args.cursor = CursorShapeAndPosition {
+            shape: cursor.shape,
+            coords: (cursor.x, cursor.y.max(0) as usize).into(),
+            ..Default::default()
+        };
+    }
+
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        match event {
+            WidgetEvent::Input(InputEvent::Key(event)) => {
+                self.key_down(event.key, event.modifiers).is_ok()
+            }
+            WidgetEvent::Input(InputEvent::Paste(text)) => self.send_paste(text).is_ok(),
+            WidgetEvent::Input(InputEvent::FocusGained) => self.focus_changed(true).is_ok(),
+            WidgetEvent::Input(InputEvent::FocusLost) => self.focus_changed(false).is_ok(),
+            _ => false,
+        }
+    }
+}
@@ -54,12 +54,19 @@
 //! implements some heuristics (a fancy word for guessing) to compute
 //! the terminal capabilities, but also offers a `ProbeHintsBuilder`
 //! that can be used by the embedding application to override those choices.
+//!
+//! The heuristics can be refined by actively querying the terminal;
+//! see the `probe` module and `Terminal::probe_capabilities`.
 use anyhow::Error;
 use derive_builder::*;
 use semver::Version;
 use std::env::var;
 use terminfo::{self, capability as cap};
 
+pub mod probe;
+
+use self::probe::ProbeResults;
+
 /// Use the `ProbeHintsBuilder` to configure an instance of
 /// the `ProbeHints` struct.  `ProbeHints` are passed to the `Capabilities`
 /// constructor to influence the effective set of terminal capabilities.
@@ -108,6 +115,9 @@ pub struct ProbeHints {
 
     /// Whether mouse support is present and should be used
     mouse_reporting: Option<bool>,
+
+    /// Whether the synchronized output mode is supported
+    synchronized_output: Option<bool>,
 }
 
 impl ProbeHintsBuilder {
@@ -156,6 +166,7 @@ pub struct Capabilities {
     terminfo_db: Option<terminfo::Database>,
     bracketed_paste: bool,
     mouse_reporting: bool,
+    synchronized_output: bool,
 }
 
 impl Capabilities {
@@ -261,6 +272,9 @@ impl Capabilities {
         let bracketed_paste = hints.bracketed_paste.unwrap_or(true);
         let mouse_reporting = hints.mouse_reporting.unwrap_or(true);
 
+        // There is no way to know this without probing the terminal
+        let synchronized_output = hints.synchronized_output.unwrap_or(false);
+
         Ok(Self {
             color_level,
             sixel,
@@ -270,9 +284,27 @@ impl Capabilities {
             terminfo_db: hints.terminfo_db,
             bracketed_paste,
             mouse_reporting,
+            synchronized_output,
         })
     }
 
+    /// Refine the capabilities using the responses to an active probe
+    /// of the terminal.  Capabilities that the terminal was found to
+    /// support are enabled; the others are left as they were, because
+    /// a terminal not responding to a query doesn't mean that it lacks
+    /// the capability.
+    pub fn apply_probe_results(&mut self, results: &ProbeResults) {
+        if results.true_color == Some(true) {
+            self.color_level = ColorLevel::TrueColor;
+        }
+        if results.sixel() == Some(true) {
+            self.sixel = true;
+        }
+        if let Some(synchronized_output) = results.synchronized_output {
+            self.synchronized_output = synchronized_output;
+        }
+    }
+
     /// Indicates how many colors are supported
     pub fn color_level(&self) -> ColorLevel {
         self.color_level
@@ -315,6 +347,12 @@ impl Capabilities {
     pub fn mouse_reporting(&self) -> bool {
         self.mouse_reporting
     }
+
+    /// Whether the terminal supports synchronized output, which
+    /// allows an update to be presented all at once, avoiding tearing
+    pub fn synchronized_output(&self) -> bool {
+        self.synchronized_output
+    }
 }
 
 #[cfg(test)]
@@ -484,4 +522,21 @@ mod test {
         .unwrap();
         assert_eq!(caps.iterm2_image(), true);
     }
+
+    #[test]
+    fn probe_results() {
+        let mut caps =
+            Capabilities::new_with_hints(ProbeHintsBuilder::default().build().unwrap()).unwrap();
+        assert_eq!(caps.synchronized_output(), false);
+
+        caps.apply_probe_results(&ProbeResults {
+            primary_device_attributes: Some(vec![62, 4]),
+            true_color: Some(true),
+            synchronized_output: Some(true),
+            ..Default::default()
+        });
+        assert_eq!(caps.color_level(), ColorLevel::TrueColor);
+        assert_eq!(caps.sixel(), true);
+        assert_eq!(caps.synchronized_output(), true);
+    }
 }
@@ -0,0 +1,293 @@
+//! Actively probing the terminal for its capabilities.
+//!
+//! Rather than relying solely upon the environment and the terminfo
+//! database, we can ask the terminal to describe itself by sending
+//! a series of queries and examining the responses.  Not every terminal
+//! responds to every query, so the primary device attributes query,
+//! which is answered by practically all terminals, is sent last;
+//! its response marks the end of the responses to the other queries.
+use crate::escape::csi::{Device, DeviceAttribute, DeviceAttributes};
+use crate::escape::esc::{Esc, EscCode};
+use crate::escape::parser::Parser;
+use crate::escape::{Action, DeviceControlMode, CSI};
+use num::ToPrimitive;
+
+/// The number of the DEC private mode that controls synchronized output
+const SYNCHRONIZED_OUTPUT_MODE: i64 = 2026;
+
+/// The sixel graphics feature code reported in the primary device
+/// attributes
+const SIXEL_ATTRIBUTE: i64 = 4;
+
+/// Holds the information gathered from the terminal's responses to
+/// the probe queries.  Fields are `None` if the terminal didn't
+/// respond to the corresponding query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeResults {
+    /// The parameters of the primary device attributes (DA1) response
+    pub primary_device_attributes: Option<Vec<i64>>,
+    /// The parameters of the secondary device attributes (DA2) response
+    pub secondary_device_attributes: Option<Vec<i64>>,
+    /// The name and version of the terminal, as reported by XTVERSION
+    pub version: Option<String>,
+    /// Whether the terminal retained a 24-bit color, as reported by
+    /// a DECRQSS query of the graphic rendition
+    pub true_color: Option<bool>,
+    /// Whether the terminal recognizes the synchronized output mode
+    pub synchronized_output: Option<bool>,
+}
+
+impl ProbeResults {
+    /// Whether the primary device attributes indicate sixel support
+    pub fn sixel(&self) -> Option<bool> {
+        self.primary_device_attributes
+            .as_ref()
+            .map(|attrs| attrs.contains(&SIXEL_ATTRIBUTE))
+    }
+}
+
+/// Returns the sequence of queries that probe the capabilities of
+/// the terminal.  The graphic rendition is reset by the queries.
+pub fn probe_queries() -> String {
+    format!(
+        concat!(
+            // XTVERSION
+            "\x1b[>0q",
+            // Select a 24-bit background color and ask the terminal
+            // to report the graphic rendition via DECRQSS, then reset it
+            "\x1b[48:2::1:2:3m\x1bP$qm\x1b\\\x1b[0m",
+            // DECRQM for synchronized output
+            "\x1b[?{}$p",
+            // DA2, then DA1 last
+            "\x1b[>c\x1b[c",
+        ),
+        SYNCHRONIZED_OUTPUT_MODE
+    )
+}
+
+/// Recognizes the responses to the queries produced by `probe_queries`
+/// in the input received from the terminal.
+pub struct ProbeParser {
+    parser: Parser,
+    results: ProbeResults,
+    /// The device control string that is being accumulated:
+    /// its intermediates, final byte and data
+    dcs: Option<(Vec<u8>, u8, Vec<u8>)>,
+    complete: bool,
+}
+
+impl Default for ProbeParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProbeParser {
+    pub fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+            results: ProbeResults::default(),
+            dcs: None,
+            complete: false,
+        }
+    }
+
+    /// Returns true once the response to the final query has
+    /// been received
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn results(&self) -> &ProbeResults {
+        &self.results
+    }
+
+    pub fn into_results(self) -> ProbeResults {
+        self.results
+    }
+
+    /// Process `bytes` received from the terminal.  Input that is not
+    /// part of a response, such as keys pressed while the probe is in
+    /// progress, is re-encoded and returned so that it can be passed
+    /// on to the `InputParser`.
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut actions = vec![];
+        self.parser.parse(bytes, |action| actions.push(action));
+
+        let mut unconsumed = String::new();
+        for action in actions {
+            if !self.process_action(&action) {
+                if let Action::DeviceControl(_) = action {
+                    // Device control strings can't be re-encoded
+                    continue;
+                }
+                unconsumed.push_str(&action.to_string());
+            }
+        }
+        unconsumed.into_bytes()
+    }
+
+    /// Returns true if the action was part of a response
+    fn process_action(&mut self, action: &Action) -> bool {
+        match action {
+            Action::CSI(CSI::Device(device)) => match &**device {
+                Device::DeviceAttributes(attrs) => {
+                    self.results.primary_device_attributes = Some(attribute_params(attrs));
+                    self.complete = true;
+                    true
+                }
+                _ => false,
+            },
+            Action::CSI(CSI::Unspecified(csi)) => {
+                let params = csi.params();
+                match (csi.intermediates(), csi.control()) {
+                    (b"?", 'c') => {
+                        self.results.primary_device_attributes = Some(params.to_vec());
+                        self.complete = true;
+                    }
+                    (b">", 'c') => {
+                        self.results.secondary_device_attributes = Some(params.to_vec());
+                    }
+                    (b"?$", 'y') if params.first() == Some(&SYNCHRONIZED_OUTPUT_MODE) => {
+                        // 0 means unrecognized and 4 permanently reset
+                        let status = params.get(1).cloned().unwrap_or(0);
+                        self.results.synchronized_output = Some(status > 0 && status < 4);
+                    }
+                    _ => return false,
+                }
+                true
+            }
+            Action::DeviceControl(mode) => {
+                match &**mode {
+                    DeviceControlMode::Enter {
+                        intermediates,
+                        byte,
+                        ..
+                    } => {
+                        self.dcs = Some((intermediates.clone(), *byte, vec![]));
+                    }
+                    DeviceControlMode::Data(b) => {
+                        if let Some((_, _, data)) = self.dcs.as_mut() {
+                            data.push(*b);
+                        }
+                    }
+                    DeviceControlMode::Exit => {
+                        if let Some((intermediates, byte, data)) = self.dcs.take() {
+                            self.process_dcs(&intermediates, byte, &data);
+                        }
+                    }
+                }
+                true
+            }
+            // The string terminator that follows the device control
+            // string responses
+            Action::Esc(Esc::Code(EscCode::StringTerminator)) => true,
+            _ => false,
+        }
+    }
+
+    fn process_dcs(&mut self, intermediates: &[u8], byte: u8, data: &[u8]) {
+        let data = String::from_utf8_lossy(data);
+        match (intermediates, byte) {
+            (b">", b'|') => {
+                self.results.version = Some(data.to_string());
+            }
+            (b"$", b'r') => {
+                // The response is `DCS 1 $ r <sgr> m ST` if the request
+                // was valid.  The parser treats the 1 as a parameter.
+                if data.ends_with('m') {
+                    let params: Vec<&str> = data.trim_end_matches('m').split(';').collect();
+                    self.results.true_color = Some(params.iter().any(|p| {
+                        let sub: Vec<&str> = p.split(':').collect();
+                        sub.first() == Some(&"48")
+                            && sub.get(1) == Some(&"2")
+                            && sub.ends_with(&["1", "2", "3"])
+                    }));
+                } else {
+                    self.results.true_color = Some(false);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Extracts the numeric parameters from a parsed device attributes response
+fn attribute_params(attrs: &DeviceAttributes) -> Vec<i64> {
+    let (class, flags) = match attrs {
+        DeviceAttributes::Vt100WithAdvancedVideoOption => return vec![1, 2],
+        DeviceAttributes::Vt101WithNoOptions => return vec![1, 0],
+        DeviceAttributes::Vt102 => return vec![6],
+        DeviceAttributes::Vt220(flags) => (62, flags),
+        DeviceAttributes::Vt320(flags) => (63, flags),
+        DeviceAttributes::Vt420(flags) => (64, flags),
+    };
+    let mut params = vec![class];
+    for attr in &flags.attributes {
+        params.push(match attr {
+            DeviceAttribute::Code(code) => code.to_i64().unwrap_or(0),
+            DeviceAttribute::Unspecified(n) => i64::from(*n),
+        });
+    }
+    params
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        let mut parser = ProbeParser::new();
+        // Split the responses at an arbitrary point to verify that
+        // they can span reads
+        let response = concat!(
+            "\x1bP>|WezTerm 20200620\x1b\\",
+            "\x1bP1$r0;48:2::1:2:3m\x1b\\",
+            "x",
+            "\x1b[?2026;2$y",
+            "\x1b[>1;10;0c",
+            "\x1b[?62;4;22c"
+        );
+        let (first, second) = response.as_bytes().split_at(20);
+        assert!(parser.parse(first).is_empty());
+        assert!(!parser.is_complete());
+        assert_eq!(parser.parse(second), b"x".to_vec());
+        assert!(parser.is_complete());
+
+        let results = parser.into_results();
+        assert_eq!(
+            results,
+            ProbeResults {
+                primary_device_attributes: Some(vec![62, 4, 22]),
+                secondary_device_attributes: Some(vec![1, 10, 0]),
+                version: Some("WezTerm 20200620".to_string()),
+                true_color: Some(true),
+                synchronized_output: Some(true),
+            }
+        );
+        assert_eq!(results.sixel(), Some(true));
+    }
+
+    #[test]
+    fn minimal_terminal() {
+        // A terminal that ignores everything but DA1
+        let mut parser = ProbeParser::new();
+        assert!(parser.parse(b"\x1b[?1;2c").is_empty());
+        assert!(parser.is_complete());
+        let results = parser.into_results();
+        assert_eq!(results.primary_device_attributes, Some(vec![1, 2]));
+        assert_eq!(results.sixel(), Some(false));
+        assert_eq!(results.true_color, None);
+        assert_eq!(results.synchronized_output, None);
+    }
+
+    #[test]
+    fn rejected_sgr_query() {
+        let mut parser = ProbeParser::new();
+        parser.parse(b"\x1bP0$r\x1b\\\x1b[?2026;0$y\x1b[?62c");
+        let results = parser.results();
+        assert_eq!(results.true_color, Some(false));
+        assert_eq!(results.synchronized_output, Some(false));
+    }
+}
@@ -4,15 +4,19 @@ pub use crate::escape::osc::Hyperlink;
 use crate::image::ImageCell;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_derive::*;
-use smallvec::SmallVec;
 use std;
 use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use unicode_width::UnicodeWidthStr;
 
 /// Holds the attributes for a cell.
 /// Most style attributes are stored internally as part of a bitfield
 /// to reduce per-cell overhead.
+/// The less commonly used attributes, such as hyperlinks, images and
+/// the underline color, are stored separately in a boxed overflow
+/// that is only allocated when one of them is set, and are accessed
+/// via methods rather than fields.
 /// The setter methods return a mutable self reference so that they can
 /// be chained together.
 #[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -22,10 +26,30 @@ pub struct CellAttributes {
     pub foreground: ColorAttribute,
     /// The background color
     pub background: ColorAttribute,
+    #[serde(default)]
+    fat: Option<Box<FatAttributes>>,
+}
+
+/// The attributes that are not set on most cells.
+/// If all of these have their default values then the overflow
+/// is released, so that comparing `CellAttributes` remains cheap.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct FatAttributes {
     /// The hyperlink content, if any
-    pub hyperlink: Option<Arc<Hyperlink>>,
+    hyperlink: Option<Arc<Hyperlink>>,
     /// The image data, if any
-    pub image: Option<Box<ImageCell>>,
+    image: Option<Box<ImageCell>>,
+    /// The color of the underline; `ColorAttribute::Default`
+    /// means that the foreground color is used
+    underline_color: ColorAttribute,
+}
+
+impl FatAttributes {
+    fn is_empty(&self) -> bool {
+        self.hyperlink.is_none()
+            && self.image.is_none()
+            && self.underline_color == ColorAttribute::Default
+    }
 }
 
 /// Define getter and setter for the attributes bitfield.
@@ -68,7 +92,7 @@ macro_rules! bitfield {
     ($getter:ident, $setter:ident, $enum:ident, $bitmask:expr, $bitshift:expr) => {
         #[inline]
         pub fn $getter(&self) -> $enum {
-            unsafe { mem::transmute(((self.attributes >> $bitshift) & $bitmask) as u16)}
+            unsafe { mem::transmute(((self.attributes >> $bitshift) & $bitmask) as u16) }
         }
 
         #[inline]
@@ -110,6 +134,12 @@ pub enum Underline {
     Single = 1,
     /// The cell is underlined with two lines
     Double = 2,
+    /// The cell is underlined with a wavy line
+    Curly = 3,
+    /// The cell is underlined with a dotted line
+    Dotted = 4,
+    /// The cell is underlined with a dashed line
+    Dashed = 5,
 }
 
 impl Default for Underline {
@@ -145,15 +175,37 @@ impl Into<bool> for Blink {
     }
 }
 
+/// Classifies the content of a cell according to the semantic
+/// prompt markers (OSC 133) emitted by a suitably configured shell.
+/// This allows the terminal to distinguish the prompt and the
+/// command line from the output of a command.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[repr(u16)]
+pub enum SemanticType {
+    /// Output from a command, or text that was not marked up
+    Output = 0,
+    /// Input that was typed at the command line
+    Input = 1,
+    /// The prompt text
+    Prompt = 2,
+}
+
+impl Default for SemanticType {
+    fn default() -> Self {
+        Self::Output
+    }
+}
+
 impl CellAttributes {
     bitfield!(intensity, set_intensity, Intensity, 0b11, 0);
-    bitfield!(underline, set_underline, Underline, 0b11, 2);
     bitfield!(blink, set_blink, Blink, 0b11, 4);
     bitfield!(italic, set_italic, 6);
     bitfield!(reverse, set_reverse, 7);
     bitfield!(strikethrough, set_strikethrough, 8);
     bitfield!(invisible, set_invisible, 9);
     bitfield!(wrapped, set_wrapped, 10);
+    bitfield!(semantic_type, set_semantic_type, SemanticType, 0b11, 11);
+    bitfield!(underline, set_underline, Underline, 0b111, 13);
 
     /// Returns true if the attribute bits in both objects are equal.
     /// This can be used to cheaply test whether the styles of the two
@@ -173,55 +225,198 @@ impl CellAttributes {
         self
     }
 
+    /// Set the color of the underline for the cell
+    pub fn set_underline_color<C: Into<ColorAttribute>>(&mut self, color: C) -> &mut Self {
+        let color = color.into();
+        if color != ColorAttribute::Default || self.fat.is_some() {
+            self.fat_mut().underline_color = color;
+            self.release_fat_if_empty();
+        }
+        self
+    }
+
+    /// Returns the color of the underline; `ColorAttribute::Default`
+    /// means that the foreground color is used
+    pub fn underline_color(&self) -> ColorAttribute {
+        self.fat
+            .as_ref()
+            .map(|fat| fat.underline_color)
+            .unwrap_or(ColorAttribute::Default)
+    }
+
     pub fn set_hyperlink(&mut self, link: Option<Arc<Hyperlink>>) -> &mut Self {
-        self.hyperlink = link;
+        if link.is_some() || self.fat.is_some() {
+            self.fat_mut().hyperlink = link;
+            self.release_fat_if_empty();
+        }
         self
     }
 
+    /// Returns the hyperlink content, if any
+    pub fn hyperlink(&self) -> Option<&Arc<Hyperlink>> {
+        self.fat.as_ref().and_then(|fat| fat.hyperlink.as_ref())
+    }
+
     pub fn set_image(&mut self, image: Option<Box<ImageCell>>) -> &mut Self {
-        self.image = image;
+        if image.is_some() || self.fat.is_some() {
+            self.fat_mut().image = image;
+            self.release_fat_if_empty();
+        }
         self
     }
 
+    /// Returns the image data, if any
+    pub fn image(&self) -> Option<&ImageCell> {
+        self.fat.as_ref().and_then(|fat| fat.image.as_deref())
+    }
+
+    fn fat_mut(&mut self) -> &mut FatAttributes {
+        self.fat
+            .get_or_insert_with(|| Box::new(FatAttributes::default()))
+    }
+
+    fn release_fat_if_empty(&mut self) {
+        if self.fat.as_ref().map(|fat| fat.is_empty()).unwrap_or(false) {
+            self.fat = None;
+        }
+    }
+
     /// Clone the attributes, but exclude fancy extras such
     /// as hyperlinks or future sprite things
     pub fn clone_sgr_only(&self) -> Self {
-        Self {
+        let mut attrs = Self {
             attributes: self.attributes,
             foreground: self.foreground,
             background: self.background,
-            hyperlink: None,
-            image: None,
+            fat: None,
+        };
+        attrs.set_underline_color(self.underline_color());
+        attrs
+    }
+}
+
+/// Holds the text of a cell.  Most cells hold a single character,
+/// so strings that are short enough are stored inline in the space
+/// that would otherwise hold a pointer, and only longer grapheme
+/// clusters require a separate heap allocation.
+///
+/// The first byte in memory is a tag: if its low bit is set, the
+/// remaining bits hold the length of the inline string, whose bytes
+/// follow the tag.  Otherwise the value is a pointer, stored in little
+/// endian byte order, to the boxed string.  The pointee is at least
+/// 2-byte aligned, so the low bit of a pointer is always clear.
+struct TeenyString(usize);
+
+impl TeenyString {
+    /// The number of bytes that can be stored inline
+    const MAX_INLINE: usize = mem::size_of::<usize>() - 1;
+
+    fn from_str(s: &str) -> Self {
+        let len = s.len();
+        if len <= Self::MAX_INLINE {
+            let mut bytes = [0u8; mem::size_of::<usize>()];
+            bytes[0] = ((len as u8) << 1) | 1;
+            bytes[1..=len].copy_from_slice(s.as_bytes());
+            Self(usize::from_ne_bytes(bytes))
+        } else {
+            let boxed: Box<Box<str>> = Box::new(s.into());
+            Self((Box::into_raw(boxed) as usize).to_le())
+        }
+    }
+
+    fn from_char(c: char) -> Self {
+        let mut buf = [0u8; 4];
+        Self::from_str(c.encode_utf8(&mut buf))
+    }
+
+    fn tag(&self) -> u8 {
+        self.0.to_ne_bytes()[0]
+    }
+
+    fn is_inline(&self) -> bool {
+        self.tag() & 1 == 1
+    }
+
+    fn heap_ptr(&self) -> *mut Box<str> {
+        usize::from_le(self.0) as *mut Box<str>
+    }
+
+    fn as_str(&self) -> &str {
+        if self.is_inline() {
+            let len = (self.tag() >> 1) as usize;
+            // unsafety: this is safe because the bytes following the
+            // tag are the utf8 that was copied in by from_str
+            unsafe {
+                let ptr = (&self.0 as *const usize as *const u8).add(1);
+                std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len))
+            }
+        } else {
+            // unsafety: this is safe because the pointer was produced
+            // by Box::into_raw in from_str and is only freed by drop
+            unsafe { &*self.heap_ptr() }
+        }
+    }
+}
+
+impl Drop for TeenyString {
+    fn drop(&mut self) {
+        if !self.is_inline() {
+            // unsafety: this is safe because the pointer was produced
+            // by Box::into_raw in from_str
+            unsafe {
+                drop(Box::from_raw(self.heap_ptr()));
+            }
         }
     }
 }
 
-fn deserialize_smallvec<'de, D>(deserializer: D) -> Result<SmallVec<[u8; 4]>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let text = String::deserialize(deserializer)?;
-    Ok(SmallVec::from_slice(text.as_bytes()))
+impl Clone for TeenyString {
+    fn clone(&self) -> Self {
+        if self.is_inline() {
+            Self(self.0)
+        } else {
+            Self::from_str(self.as_str())
+        }
+    }
 }
 
-fn serialize_smallvec<S>(value: &SmallVec<[u8; 4]>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    // unsafety: this is safe because the Cell constructor guarantees
-    // that the storage is valid utf8
-    let s = unsafe { std::str::from_utf8_unchecked(value) };
-    s.serialize(serializer)
+impl PartialEq for TeenyString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for TeenyString {}
+
+impl std::fmt::Debug for TeenyString {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.as_str().fmt(fmt)
+    }
+}
+
+impl Serialize for TeenyString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_str().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TeenyString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        Ok(Self::from_str(&text))
+    }
 }
 
 /// Models the contents of a cell on the terminal display
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Cell {
-    #[serde(
-        deserialize_with = "deserialize_smallvec",
-        serialize_with = "serialize_smallvec"
-    )]
-    text: SmallVec<[u8; 4]>,
+    text: TeenyString,
     attrs: CellAttributes,
 }
 
@@ -235,24 +430,11 @@ impl Cell {
     /// De-fang the input character such that it has no special meaning
     /// to a terminal.  All control and movement characters are rewritten
     /// as a space.
-    fn nerf_control_char(text: &mut SmallVec<[u8; 4]>) {
-        if text.is_empty() {
-            text.push(b' ');
-            return;
-        }
-
-        if text.as_slice() == [b'\r', b'\n'] {
-            text.remove(1);
-            text[0] = b' ';
-            return;
-        }
-
-        if text.len() != 1 {
-            return;
-        }
-
-        if text[0] < 0x20 || text[0] == 0x7f {
-            text[0] = b' ';
+    fn nerf_control_char(text: &str) -> &str {
+        match text.as_bytes() {
+            [] | [b'\r', b'\n'] => " ",
+            [c] if *c < 0x20 || *c == 0x7f => " ",
+            _ => text,
         }
     }
 
@@ -260,16 +442,14 @@ impl Cell {
     /// specified cell attributes.
     /// All control and movement characters are rewritten as a space.
     pub fn new(text: char, attrs: CellAttributes) -> Self {
-        let len = text.len_utf8();
-        let mut storage = SmallVec::with_capacity(len);
-        unsafe {
-            storage.set_len(len);
-        }
-        text.encode_utf8(&mut storage);
-        Self::nerf_control_char(&mut storage);
+        let text = if text < ' ' || text == '\x7f' {
+            ' '
+        } else {
+            text
+        };
 
         Self {
-            text: storage,
+            text: TeenyString::from_char(text),
             attrs,
         }
     }
@@ -282,20 +462,15 @@ impl Cell {
     /// be passed but it should not be used to hold strings other than
     /// graphemes.
     pub fn new_grapheme(text: &str, attrs: CellAttributes) -> Self {
-        let mut storage = SmallVec::from_slice(text.as_bytes());
-        Self::nerf_control_char(&mut storage);
-
         Self {
-            text: storage,
+            text: TeenyString::from_str(Self::nerf_control_char(text)),
             attrs,
         }
     }
 
     /// Returns the textual content of the cell
     pub fn str(&self) -> &str {
-        // unsafety: this is safe because the constructor guarantees
-        // that the storage is valid utf8
-        unsafe { std::str::from_utf8_unchecked(&self.text) }
+        self.text.as_str()
     }
 
     /// Returns the number of cells visually occupied by this grapheme
@@ -320,9 +495,24 @@ pub fn unicode_column_width(s: &str) -> usize {
     s.graphemes(true).map(grapheme_column_width).sum()
 }
 
+static AMBIGUOUS_IS_WIDE: AtomicBool = AtomicBool::new(false);
+
+/// Controls whether characters whose East Asian Width property is
+/// Ambiguous, such as some box drawing characters and greek letters,
+/// occupy two cells rather than one, as is conventional in CJK locales.
+/// This affects all subsequent width calculations in the process, and
+/// should match the setting of the programs running in the terminal.
+pub fn set_east_asian_ambiguous_is_wide(wide: bool) {
+    AMBIGUOUS_IS_WIDE.store(wide, Ordering::Relaxed);
+}
+
 /// Returns the number of cells visually occupied by a grapheme.
 /// The input string must be a single grapheme.
 pub fn grapheme_column_width(s: &str) -> usize {
+    grapheme_width(s, AMBIGUOUS_IS_WIDE.load(Ordering::Relaxed))
+}
+
+fn grapheme_width(s: &str, ambiguous_is_wide: bool) -> usize {
     // Due to this issue:
     // https://github.com/unicode-rs/unicode-width/issues/4
     // we cannot simply use the unicode-width crate to compute
@@ -334,8 +524,20 @@ pub fn grapheme_column_width(s: &str) -> usize {
             // treat modifier sequences as double wide
             return 2;
         }
+        if c == '\u{fe0f}' {
+            // VARIATION SELECTOR-16 requests emoji presentation,
+            // which is double wide
+            return 2;
+        }
     }
-    UnicodeWidthStr::width(s)
+    // A grapheme occupies at most two cells; without this, the
+    // parts of a ZWJ sequence would each contribute their width
+    let width = if ambiguous_is_wide {
+        UnicodeWidthStr::width_cjk(s)
+    } else {
+        UnicodeWidthStr::width(s)
+    };
+    width.min(2)
 }
 
 /// Models a change in the attributes of a cell in a stream of changes.
@@ -372,6 +574,52 @@ mod test {
         }
     }
 
+    #[test]
+    fn teeny_string() {
+        let short = TeenyString::from_str("a");
+        assert!(short.is_inline());
+        assert_eq!(short.as_str(), "a");
+        assert_eq!(short.clone(), short);
+
+        let family = "\u{1F469}\u{200D}\u{1F467}";
+        let long = TeenyString::from_str(family);
+        assert!(!long.is_inline());
+        assert_eq!(long.as_str(), family);
+        let copy = long.clone();
+        drop(long);
+        assert_eq!(copy.as_str(), family);
+
+        assert_eq!(TeenyString::from_char('\u{4e2d}').as_str(), "\u{4e2d}");
+        assert_ne!(short, copy);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn cell_size() {
+        assert_eq!(mem::size_of::<Cell>(), 32);
+        assert_eq!(mem::size_of::<CellAttributes>(), 24);
+    }
+
+    #[test]
+    fn fat_attributes() {
+        let link = Arc::new(Hyperlink::new("http://example.com"));
+        let mut attrs = CellAttributes::default();
+        attrs.set_hyperlink(Some(Arc::clone(&link)));
+        attrs.set_underline_color(ColorAttribute::PaletteIndex(1));
+        assert_eq!(attrs.hyperlink(), Some(&link));
+        assert_eq!(attrs.underline_color(), ColorAttribute::PaletteIndex(1));
+
+        let sgr = attrs.clone_sgr_only();
+        assert_eq!(sgr.hyperlink(), None);
+        assert_eq!(sgr.underline_color(), ColorAttribute::PaletteIndex(1));
+
+        // Clearing the extras releases the overflow, so that the
+        // attributes compare equal to ones that never had them
+        attrs.set_hyperlink(None);
+        attrs.set_underline_color(ColorAttribute::Default);
+        assert_eq!(attrs, CellAttributes::default());
+    }
+
     #[test]
     fn test_width() {
         let foot = "\u{1f9b6}";
@@ -404,5 +652,34 @@ mod test {
             "width of {} should be 2",
             women_holding_hands_dark_skin_tone_medium_light_skin_tone
         );
+
+        let heart = "\u{2764}";
+        assert_eq!(unicode_column_width(heart), 1, "{} should be 1", heart);
+        let heart_emoji = "\u{2764}\u{fe0f}";
+        assert_eq!(
+            unicode_column_width(heart_emoji),
+            2,
+            "{} should be 2",
+            heart_emoji
+        );
+
+        let rainbow_flag = "\u{1f3f3}\u{fe0f}\u{200d}\u{1f308}";
+        assert_eq!(
+            grapheme_column_width(rainbow_flag),
+            2,
+            "{} should be 2",
+            rainbow_flag
+        );
+    }
+
+    #[test]
+    fn ambiguous_width() {
+        for s in &["\u{2500}", "\u{3b1}", "\u{2026}"] {
+            assert_eq!(grapheme_width(s, false), 1, "{} should be 1", s);
+            assert_eq!(grapheme_width(s, true), 2, "{} should be 2", s);
+        }
+        // Characters that aren't ambiguous are unaffected
+        assert_eq!(grapheme_width("a", true), 1);
+        assert_eq!(grapheme_width("\u{4e2d}", false), 2);
     }
 }
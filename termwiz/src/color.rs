@@ -104,10 +104,19 @@ impl RgbColor {
         format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
     }
 
+    /// Returns a string of the form `rgb:RRRR/GGGG/BBBB`, which is
+    /// the form in which xterm reports colors
+    pub fn to_x11_16bit_rgb_string(self) -> String {
+        format!(
+            "rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}",
+            self.red, self.red, self.green, self.green, self.blue, self.blue
+        )
+    }
+
     /// Construct a color from a string of the form `#RRGGBB` where
     /// R, G and B are all hex digits.
     pub fn from_rgb_str(s: &str) -> Option<RgbColor> {
-        if s.as_bytes()[0] == b'#' && s.len() == 7 {
+        if s.starts_with('#') && s.len() == 7 {
             let mut chars = s.chars().skip(1);
 
             macro_rules! digit {
@@ -129,14 +138,47 @@ impl RgbColor {
         }
     }
 
+    /// Construct a color from an X11 color specification of the form
+    /// `rgb:R/G/B`, where each of R, G and B is made of 1 to 4 hex
+    /// digits.  This is the form used by the xterm color escape sequences.
+    pub fn from_x11_rgb_str(s: &str) -> Option<RgbColor> {
+        if !s.starts_with("rgb:") {
+            return None;
+        }
+        let mut components = s[4..].split('/');
+
+        macro_rules! component {
+            () => {{
+                let digits = components.next()?;
+                if digits.is_empty() || digits.len() > 4 {
+                    return None;
+                }
+                let value = u16::from_str_radix(digits, 16).ok()?;
+                // Scale the value from its number of digits to 8 bits
+                let max = (1u32 << (4 * digits.len())) - 1;
+                ((value as u32 * 0xff + max / 2) / max) as u8
+            }};
+        }
+
+        let color = Self::new(component!(), component!(), component!());
+        if components.next().is_some() {
+            None
+        } else {
+            Some(color)
+        }
+    }
+
     /// Construct a color from an SVG/CSS3 color name.
     /// or from a string of the form `#RRGGBB` where
-    /// R, G and B are all hex digits.
+    /// R, G and B are all hex digits, or from an X11 `rgb:R/G/B`
+    /// specification.
     /// Returns None if the supplied name is not recognized.
     /// The list of names can be found here:
     /// <https://ogeon.github.io/docs/palette/master/palette/named/index.html>
     pub fn from_named_or_rgb_string(s: &str) -> Option<Self> {
-        RgbColor::from_rgb_str(&s).or_else(|| RgbColor::from_named(&s))
+        RgbColor::from_rgb_str(&s)
+            .or_else(|| RgbColor::from_x11_rgb_str(&s))
+            .or_else(|| RgbColor::from_named(&s))
     }
 }
 
@@ -251,6 +293,31 @@ mod tests {
         assert_eq!(dark_green.blue, 0);
     }
 
+    #[test]
+    fn x11_rgb() {
+        let orange = RgbColor::new(0xff, 0x80, 0x00);
+        assert_eq!(orange.to_x11_16bit_rgb_string(), "rgb:ffff/8080/0000");
+        assert_eq!(
+            RgbColor::from_x11_rgb_str("rgb:ffff/8080/0000"),
+            Some(orange)
+        );
+        assert_eq!(RgbColor::from_x11_rgb_str("rgb:ff/80/00"), Some(orange));
+        assert_eq!(
+            RgbColor::from_x11_rgb_str("rgb:f/8/0"),
+            Some(RgbColor::new(0xff, 0x88, 0))
+        );
+        assert_eq!(RgbColor::from_x11_rgb_str("rgb:fff/800/000"), Some(orange));
+        assert_eq!(RgbColor::from_x11_rgb_str("rgb:ff/80"), None);
+        assert_eq!(RgbColor::from_x11_rgb_str("rgb:ff/80/00/00"), None);
+        assert_eq!(RgbColor::from_x11_rgb_str("rgb:ff/80/0g"), None);
+        assert_eq!(RgbColor::from_x11_rgb_str("rgb:fffff/0/0"), None);
+        assert_eq!(
+            RgbColor::from_named_or_rgb_string("rgb:00/80/ff"),
+            Some(RgbColor::new(0, 0x80, 0xff))
+        );
+        assert_eq!(RgbColor::from_named_or_rgb_string(""), None);
+    }
+
     #[test]
     fn roundtrip_rgbcolor() {
         let data = varbincode::serialize(&RgbColor::from_named("DarkGreen").unwrap()).unwrap();
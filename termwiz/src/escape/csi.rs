@@ -44,6 +44,20 @@ pub struct Unspecified {
     control: char,
 }
 
+impl Unspecified {
+    pub fn params(&self) -> &[i64] {
+        &self.params
+    }
+
+    pub fn intermediates(&self) -> &[u8] {
+        &self.intermediates
+    }
+
+    pub fn control(&self) -> char {
+        self.control
+    }
+}
+
 impl Display for Unspecified {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         for (idx, p) in self.params.iter().enumerate() {
@@ -413,6 +427,12 @@ pub enum Mode {
     RestoreDecPrivateMode(DecPrivateMode),
     SetMode(TerminalMode),
     ResetMode(TerminalMode),
+    /// DECRQM - ask the terminal to report whether a DEC private
+    /// mode is set, using `CSI ? Ps ; Pm $ y`
+    QueryDecPrivateMode(DecPrivateMode),
+    /// DECRQM - ask the terminal to report whether an ANSI mode
+    /// is set, using `CSI Ps ; Pm $ y`
+    QueryMode(TerminalMode),
 }
 
 impl Display for Mode {
@@ -432,7 +452,7 @@ impl Display for Mode {
                     TerminalMode::Code(mode) => mode.to_u16().ok_or_else(|| FmtError)?,
                     TerminalMode::Unspecified(mode) => *mode,
                 };
-                write!(f, "?{}{}", value, $flag)
+                write!(f, "{}{}", value, $flag)
             }};
         }
         match self {
@@ -442,6 +462,8 @@ impl Display for Mode {
             Mode::RestoreDecPrivateMode(mode) => emit!("r", mode),
             Mode::SetMode(mode) => emit_mode!("h", mode),
             Mode::ResetMode(mode) => emit_mode!("l", mode),
+            Mode::QueryDecPrivateMode(mode) => emit!("$p", mode),
+            Mode::QueryMode(mode) => emit_mode!("$p", mode),
         }
     }
 }
@@ -455,8 +477,15 @@ pub enum DecPrivateMode {
 #[derive(Debug, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 pub enum DecPrivateModeCode {
     ApplicationCursorKeys = 1,
+    /// DECOM - when set, cursor addressing is relative to the
+    /// scroll region and the left/right margins, and the cursor
+    /// cannot be moved outside of them
+    OriginMode = 6,
     StartBlinkingCursor = 12,
     ShowCursor = 25,
+    /// Enable X10 compatible mouse reporting; only button presses
+    /// are reported, without modifiers
+    X10Mouse = 9,
     /// Enable mouse button press/release reporting
     MouseTracking = 1000,
     /// Warning: this requires a cooperative and timely response from
@@ -466,13 +495,34 @@ pub enum DecPrivateModeCode {
     ButtonEventMouse = 1002,
     /// Enable mouse motion, button press/release and drag reporting
     AnyEventMouse = 1003,
+    /// Report focus changes to the application as `CSI I` and `CSI O`
+    FocusTracking = 1004,
+    /// Encode the coordinates in mouse reports as UTF-8 characters,
+    /// which extends the range of the legacy encoding to 2015.
+    /// Does not enable mouse reporting itself.
+    Utf8Mouse = 1005,
     /// Use extended coordinate system in mouse reporting.  Does not
     /// enable mouse reporting itself, it just controls how reports
     /// will be encoded.
     SGRMouse = 1006,
+    /// Use the urxvt extended coordinate system in mouse reporting,
+    /// which encodes the values as decimal numbers.  Does not enable
+    /// mouse reporting itself.
+    UrxvtMouse = 1015,
     ClearAndEnableAlternateScreen = 1049,
     EnableAlternateScreen = 47,
+    /// Like `EnableAlternateScreen`, but the alternate screen is
+    /// cleared when switching back to the primary screen
+    OptEnableAlternateScreen = 1047,
+    /// Save the cursor as in DECSC when set, and restore it as in
+    /// DECRC when reset
+    SaveCursor = 1048,
+    /// DECLRMM - enables the use of `SetLeftAndRightMargins`
+    LeftAndRightMarginMode = 69,
     BracketedPaste = 2004,
+    /// Defer presenting updates to the screen until the mode is reset,
+    /// so that the application can present a complete frame at once
+    SynchronizedOutput = 2026,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -618,6 +668,13 @@ pub enum Cursor {
         bottom: OneBased,
     },
 
+    /// DECSLRM - Set left and right margins.
+    /// Only effective when DECLRMM is enabled
+    SetLeftAndRightMargins {
+        left: OneBased,
+        right: OneBased,
+    },
+
     CursorStyle(CursorStyle),
 }
 
@@ -808,6 +865,13 @@ impl Display for Cursor {
                     write!(f, "{};{}r", top, bottom)?;
                 }
             }
+            Cursor::SetLeftAndRightMargins { left, right } => {
+                if right.as_one_based() == u32::max_value() {
+                    write!(f, "{}s", left)?;
+                } else {
+                    write!(f, "{};{}s", left, right)?;
+                }
+            }
             Cursor::RequestActivePositionReport => write!(f, "6n")?,
             Cursor::SaveCursor => write!(f, "s")?,
             Cursor::RestoreCursor => write!(f, "u")?,
@@ -973,6 +1037,9 @@ pub enum Sgr {
     Font(Font),
     Foreground(ColorSpec),
     Background(ColorSpec),
+    /// Set the color of the underline; `ColorSpec::Default`
+    /// means that the foreground color is used
+    UnderlineColor(ColorSpec),
 }
 
 impl Display for Sgr {
@@ -1003,6 +1070,9 @@ impl Display for Sgr {
             Sgr::Underline(Underline::Single) => code!(UnderlineOn),
             Sgr::Underline(Underline::Double) => code!(UnderlineDouble),
             Sgr::Underline(Underline::None) => code!(UnderlineOff),
+            Sgr::Underline(Underline::Curly) => write!(f, "{}:3m", SgrCode::UnderlineOn as i64)?,
+            Sgr::Underline(Underline::Dotted) => write!(f, "{}:4m", SgrCode::UnderlineOn as i64)?,
+            Sgr::Underline(Underline::Dashed) => write!(f, "{}:5m", SgrCode::UnderlineOn as i64)?,
             Sgr::Blink(Blink::Slow) => code!(BlinkOn),
             Sgr::Blink(Blink::Rapid) => code!(RapidBlinkOn),
             Sgr::Blink(Blink::None) => code!(BlinkOff),
@@ -1089,6 +1159,18 @@ impl Display for Sgr {
                 c.green,
                 c.blue
             )?,
+            Sgr::UnderlineColor(ColorSpec::Default) => code!(ResetUnderlineColor),
+            Sgr::UnderlineColor(ColorSpec::PaletteIndex(idx)) => {
+                write!(f, "{}:5:{}m", SgrCode::UnderlineColor as i64, idx)?
+            }
+            Sgr::UnderlineColor(ColorSpec::TrueColor(c)) => write!(
+                f,
+                "{}:2::{}:{}:{}m",
+                SgrCode::UnderlineColor as i64,
+                c.red,
+                c.green,
+                c.blue
+            )?,
         }
         Ok(())
     }
@@ -1140,6 +1222,95 @@ impl CSI {
             params: Some(params),
         }
     }
+
+    /// Parse a CSI sequence in which some of the parameters have
+    /// colon separated sub-parameters, as produced by
+    /// `VTActor::csi_dispatch_with_subparams`.
+    /// Each element of `params` holds a parameter followed by its
+    /// sub-parameters.
+    /// Only SGR defines a meaning for sub-parameters; runs of parameters
+    /// without sub-parameters are parsed in the same way as `CSI::parse`,
+    /// and anything that isn't understood is returned wrapped in a
+    /// `CSI::Unspecified` container.
+    pub fn parse_with_subparams(
+        params: &[&[i64]],
+        intermediates: &[u8],
+        ignored_extra_intermediates: bool,
+        control: char,
+    ) -> impl Iterator<Item = CSI> {
+        let unspecified = |params: Vec<i64>| {
+            CSI::Unspecified(Box::new(Unspecified {
+                params,
+                intermediates: intermediates.to_vec(),
+                ignored_extra_intermediates,
+                control,
+            }))
+        };
+
+        let mut result = vec![];
+        if control != 'm' || !intermediates.is_empty() {
+            result.push(unspecified(params.concat()));
+            return result.into_iter();
+        }
+
+        let mut run = vec![];
+        for group in params {
+            if group.len() == 1 {
+                run.push(group[0]);
+                continue;
+            }
+            if !run.is_empty() {
+                result.extend(CSI::parse(
+                    &run,
+                    intermediates,
+                    ignored_extra_intermediates,
+                    control,
+                ));
+                run.clear();
+            }
+            match sgr_with_subparams(group) {
+                Ok(sgr) => result.push(CSI::Sgr(sgr)),
+                Err(()) => result.push(unspecified(group.to_vec())),
+            }
+        }
+        if !run.is_empty() {
+            result.extend(CSI::parse(
+                &run,
+                intermediates,
+                ignored_extra_intermediates,
+                control,
+            ));
+        }
+
+        result.into_iter()
+    }
+}
+
+/// Parse an SGR parameter together with its colon separated sub-parameters
+fn sgr_with_subparams(group: &[i64]) -> Result<Sgr, ()> {
+    let color = || match group {
+        [_, 2, red, green, blue] | [_, 2, _, red, green, blue] => {
+            Ok(RgbColor::new(to_u8(*red)?, to_u8(*green)?, to_u8(*blue)?).into())
+        }
+        [_, 5, idx] => Ok(ColorSpec::PaletteIndex(to_u8(*idx)?)),
+        _ => Err(()),
+    };
+
+    match num::FromPrimitive::from_i64(group[0]) {
+        Some(SgrCode::UnderlineOn) => match group {
+            [_, 0] => Ok(Sgr::Underline(Underline::None)),
+            [_, 1] => Ok(Sgr::Underline(Underline::Single)),
+            [_, 2] => Ok(Sgr::Underline(Underline::Double)),
+            [_, 3] => Ok(Sgr::Underline(Underline::Curly)),
+            [_, 4] => Ok(Sgr::Underline(Underline::Dotted)),
+            [_, 5] => Ok(Sgr::Underline(Underline::Dashed)),
+            _ => Err(()),
+        },
+        Some(SgrCode::ForegroundColor) => color().map(Sgr::Foreground),
+        Some(SgrCode::BackgroundColor) => color().map(Sgr::Background),
+        Some(SgrCode::UnderlineColor) => color().map(Sgr::UnderlineColor),
+        _ => Err(()),
+    }
 }
 
 /// A little helper to convert i64 -> u8 if safe
@@ -1243,7 +1414,7 @@ impl<'a> CSIParser<'a> {
             ('n', &[]) => self.dsr(params),
             ('q', &[b' ']) => self.cursor_style(params),
             ('r', &[]) => self.decstbm(params),
-            ('s', &[]) => noparams!(Cursor, SaveCursor, params),
+            ('s', &[]) => self.decslrm(params),
             ('t', &[]) => self.window(params).map(CSI::Window),
             ('u', &[]) => noparams!(Cursor, RestoreCursor, params),
             ('y', &[b'*']) => {
@@ -1280,6 +1451,12 @@ impl<'a> CSIParser<'a> {
             ('s', &[b'?']) => self
                 .dec(params)
                 .map(|mode| CSI::Mode(Mode::SaveDecPrivateMode(mode))),
+            ('p', &[b'?', b'$']) => self
+                .dec(params)
+                .map(|mode| CSI::Mode(Mode::QueryDecPrivateMode(mode))),
+            ('p', &[b'$']) => self
+                .terminal_mode(params)
+                .map(|mode| CSI::Mode(Mode::QueryMode(mode))),
 
             ('m', &[b'<']) | ('M', &[b'<']) => self.mouse_sgr1006(params).map(CSI::Mouse),
 
@@ -1352,8 +1529,38 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    /// Parses DECSLRM.  Without parameters this sequence is
+    /// indistinguishable from SCP (save cursor position), so we
+    /// report that and leave it to the terminal to decide which
+    /// of the two is meant, based on the DECLRMM mode.
+    fn decslrm(&mut self, params: &'a [i64]) -> Result<CSI, ()> {
+        if params.is_empty() {
+            Ok(CSI::Cursor(Cursor::SaveCursor))
+        } else if params.len() == 1 {
+            Ok(self.advance_by(
+                1,
+                params,
+                CSI::Cursor(Cursor::SetLeftAndRightMargins {
+                    left: OneBased::from_esc_param(params[0])?,
+                    right: OneBased::new(u32::max_value()),
+                }),
+            ))
+        } else if params.len() == 2 {
+            Ok(self.advance_by(
+                2,
+                params,
+                CSI::Cursor(Cursor::SetLeftAndRightMargins {
+                    left: OneBased::from_esc_param(params[0])?,
+                    right: OneBased::from_esc_param(params[1])?,
+                }),
+            ))
+        } else {
+            Err(())
+        }
+    }
+
     fn req_primary_device_attributes(&mut self, params: &'a [i64]) -> Result<Device, ()> {
-        if params == [] {
+        if params.is_empty() {
             Ok(Device::RequestPrimaryDeviceAttributes)
         } else if params == [0] {
             Ok(self.advance_by(1, params, Device::RequestPrimaryDeviceAttributes))
@@ -1363,7 +1570,7 @@ impl<'a> CSIParser<'a> {
     }
 
     fn req_secondary_device_attributes(&mut self, params: &'a [i64]) -> Result<Device, ()> {
-        if params == [] {
+        if params.is_empty() {
             Ok(Device::RequestSecondaryDeviceAttributes)
         } else if params == [0] {
             Ok(self.advance_by(1, params, Device::RequestSecondaryDeviceAttributes))
@@ -1651,6 +1858,11 @@ impl<'a> CSIParser<'a> {
                         one!(Sgr::Background(AnsiColor::White.into()))
                     }
 
+                    SgrCode::UnderlineColor => {
+                        self.parse_sgr_color(params).map(Sgr::UnderlineColor)
+                    }
+                    SgrCode::ResetUnderlineColor => one!(Sgr::UnderlineColor(ColorSpec::Default)),
+
                     SgrCode::InverseOn => one!(Sgr::Inverse(true)),
                     SgrCode::InverseOff => one!(Sgr::Inverse(false)),
                     SgrCode::InvisibleOn => one!(Sgr::Invisible(true)),
@@ -1747,6 +1959,9 @@ pub enum SgrCode {
     /// a sequence describing a true color rgb value
     ForegroundColor = 38,
     BackgroundColor = 48,
+    /// Takes the same parameters as ForegroundColor
+    UnderlineColor = 58,
+    ResetUnderlineColor = 59,
 }
 
 impl<'a> Iterator for CSIParser<'a> {
@@ -1882,6 +2097,65 @@ mod test {
         );
     }
 
+    fn parse_sub(params: &[&[i64]], expected: &str) -> Vec<CSI> {
+        let res = CSI::parse_with_subparams(params, &[], false, 'm').collect();
+        assert_eq!(encode(&res), expected);
+        res
+    }
+
+    #[test]
+    fn underline_styles() {
+        assert_eq!(
+            parse_sub(&[&[4, 3]], "\x1b[4:3m"),
+            vec![CSI::Sgr(Sgr::Underline(Underline::Curly))]
+        );
+        assert_eq!(
+            parse_sub(&[&[1], &[4, 5], &[3]], "\x1b[1m\x1b[4:5m\x1b[3m"),
+            vec![
+                CSI::Sgr(Sgr::Intensity(Intensity::Bold)),
+                CSI::Sgr(Sgr::Underline(Underline::Dashed)),
+                CSI::Sgr(Sgr::Italic(true)),
+            ]
+        );
+        assert_eq!(
+            parse_sub(&[&[4, 0]], "\x1b[24m"),
+            vec![CSI::Sgr(Sgr::Underline(Underline::None))]
+        );
+        assert_eq!(
+            parse_sub(&[&[4, 9]], "\x1b[4;9m"),
+            vec![CSI::Unspecified(Box::new(Unspecified {
+                params: [4, 9].to_vec(),
+                intermediates: vec![],
+                ignored_extra_intermediates: false,
+                control: 'm',
+            }))]
+        );
+    }
+
+    #[test]
+    fn underline_color() {
+        assert_eq!(
+            parse('m', &[58, 5, 196], "\x1b[58:5:196m"),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::PaletteIndex(196)))]
+        );
+        assert_eq!(
+            parse('m', &[59], "\x1b[59m"),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::Default))]
+        );
+        assert_eq!(
+            parse_sub(&[&[58, 2, 0, 255, 0, 128]], "\x1b[58:2::255:0:128m"),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::TrueColor(
+                RgbColor::new(255, 0, 128)
+            )))]
+        );
+        assert_eq!(
+            parse_sub(&[&[38, 2, 10, 20, 30]], "\x1b[38;2;10;20;30m"),
+            vec![CSI::Sgr(Sgr::Foreground(ColorSpec::TrueColor(
+                RgbColor::new(10, 20, 30)
+            )))]
+        );
+    }
+
     #[test]
     fn edit() {
         assert_eq!(
@@ -1925,6 +2199,41 @@ mod test {
         );
     }
 
+    #[test]
+    fn margins() {
+        assert_eq!(
+            parse('r', &[2, 10], "\x1b[2;10r"),
+            vec![CSI::Cursor(Cursor::SetTopAndBottomMargins {
+                top: OneBased::new(2),
+                bottom: OneBased::new(10),
+            })]
+        );
+        assert_eq!(
+            parse('s', &[], "\x1b[s"),
+            vec![CSI::Cursor(Cursor::SaveCursor)]
+        );
+        assert_eq!(
+            parse('s', &[3, 20], "\x1b[3;20s"),
+            vec![CSI::Cursor(Cursor::SetLeftAndRightMargins {
+                left: OneBased::new(3),
+                right: OneBased::new(20),
+            })]
+        );
+        assert_eq!(
+            parse('s', &[3], "\x1b[3s"),
+            vec![CSI::Cursor(Cursor::SetLeftAndRightMargins {
+                left: OneBased::new(3),
+                right: OneBased::new(u32::max_value()),
+            })]
+        );
+        assert_eq!(
+            parse_int('h', &[69], b'?', "\x1b[?69h"),
+            vec![CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::LeftAndRightMarginMode,
+            )))]
+        );
+    }
+
     #[test]
     fn decset() {
         assert_eq!(
@@ -1965,6 +2274,34 @@ mod test {
         );
     }
 
+    #[test]
+    fn decrqm() {
+        assert_eq!(
+            CSI::parse(&[2004], b"?$", false, 'p').collect::<Vec<_>>(),
+            vec![CSI::Mode(Mode::QueryDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::BracketedPaste,
+            )))]
+        );
+        assert_eq!(
+            encode(&vec![CSI::Mode(Mode::QueryDecPrivateMode(
+                DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste),
+            ))]),
+            "\x1b[?2004$p"
+        );
+        assert_eq!(
+            parse_int('p', &[4], b'$', "\x1b[4$p"),
+            vec![CSI::Mode(Mode::QueryMode(TerminalMode::Code(
+                TerminalModeCode::Insert,
+            )))]
+        );
+        assert_eq!(
+            parse('h', &[4], "\x1b[4h"),
+            vec![CSI::Mode(Mode::SetMode(TerminalMode::Code(
+                TerminalModeCode::Insert,
+            )))]
+        );
+    }
+
     #[test]
     fn mouse() {
         assert_eq!(
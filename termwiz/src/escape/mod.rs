@@ -12,11 +12,13 @@ pub mod csi;
 pub mod esc;
 pub mod osc;
 pub mod parser;
+pub mod sixel;
 
 pub use self::csi::CSI;
 pub use self::esc::Esc;
 pub use self::esc::EscCode;
 pub use self::osc::OperatingSystemCommand;
+pub use self::sixel::Sixel;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Action {
@@ -32,6 +34,8 @@ pub enum Action {
     OperatingSystemCommand(Box<OperatingSystemCommand>),
     CSI(CSI),
     Esc(Esc),
+    /// A complete sixel graphics image
+    Sixel(Box<Sixel>),
 }
 
 /// Encode self as an escape sequence.  The escape sequence may potentially
@@ -45,6 +49,7 @@ impl Display for Action {
             Action::OperatingSystemCommand(osc) => osc.fmt(f),
             Action::CSI(csi) => csi.fmt(f),
             Action::Esc(esc) => esc.fmt(f),
+            Action::Sixel(sixel) => sixel.fmt(f),
         }
     }
 }
@@ -63,6 +68,8 @@ pub enum DeviceControlMode {
         /// if true, more than two intermediates arrived and the
         /// remaining data was ignored
         ignored_extra_intermediates: bool,
+        /// The final character that selected the device mode
+        byte: u8,
     },
     /// Exit the current device control mode
     Exit,
@@ -20,7 +20,7 @@ impl Display for ColorOrQuery {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         match self {
             ColorOrQuery::Query => write!(f, "?"),
-            ColorOrQuery::Color(c) => write!(f, "{}", c.to_rgb_string()),
+            ColorOrQuery::Color(c) => write!(f, "{}", c.to_x11_16bit_rgb_string()),
         }
     }
 }
@@ -35,9 +35,16 @@ pub enum OperatingSystemCommand {
     QuerySelection(Selection),
     SetSelection(Selection, String),
     SystemNotification(String),
+    /// The `file://` url of the current working directory
+    SetCurrentWorkingDirectory(String),
     ITermProprietary(ITermProprietary),
+    FinalTermSemanticPrompt(FinalTermSemanticPrompt),
     ChangeColorNumber(Vec<ChangeColorPair>),
     ChangeDynamicColors(DynamicColorNumber, Vec<ColorOrQuery>),
+    /// Reset the listed palette entries to their default colors;
+    /// all of them if the list is empty
+    ResetColors(Vec<u8>),
+    ResetDynamicColor(DynamicColorNumber),
 
     Unspecified(Vec<Vec<u8>>),
 }
@@ -212,6 +219,23 @@ impl OperatingSystemCommand {
         ))
     }
 
+    fn parse_reset_colors(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        let mut colors = vec![];
+        for index in osc.iter().skip(1) {
+            // `OSC 104 ;` is the same as `OSC 104`
+            if !index.is_empty() {
+                colors.push(str::from_utf8(index)?.parse()?);
+            }
+        }
+        Ok(OperatingSystemCommand::ResetColors(colors))
+    }
+
+    fn parse_reset_dynamic_color_number(idx: u8) -> anyhow::Result<Self> {
+        let which_color: DynamicColorNumber = num::FromPrimitive::from_u8(idx)
+            .ok_or_else(|| anyhow!("osc code is not a valid DynamicColorNumber!?"))?;
+        Ok(OperatingSystemCommand::ResetDynamicColor(which_color))
+    }
+
     fn internal_parse(osc: &[&[u8]]) -> anyhow::Result<Self> {
         ensure!(!osc.is_empty(), "no params");
         let p1str = String::from_utf8_lossy(osc[0]);
@@ -238,9 +262,12 @@ impl OperatingSystemCommand {
             SetHyperlink => Ok(OperatingSystemCommand::SetHyperlink(Hyperlink::parse(osc)?)),
             ManipulateSelectionData => Self::parse_selection(osc),
             SystemNotification => single_string!(SystemNotification),
+            SetCurrentWorkingDirectory => single_string!(SetCurrentWorkingDirectory),
             ITermProprietary => {
                 self::ITermProprietary::parse(osc).map(OperatingSystemCommand::ITermProprietary)
             }
+            FinalTermSemanticPrompt => self::FinalTermSemanticPrompt::parse(osc)
+                .map(OperatingSystemCommand::FinalTermSemanticPrompt),
             ChangeColorNumber => Self::parse_change_color_number(osc),
 
             SetTextForegroundColor
@@ -256,6 +283,21 @@ impl OperatingSystemCommand {
                 Self::parse_change_dynamic_color_number(osc_code as u8, osc)
             }
 
+            ResetColors => Self::parse_reset_colors(osc),
+            ResetTextForegroundColor
+            | ResetTextBackgroundColor
+            | ResetTextCursorColor
+            | ResetMouseForegroundColor
+            | ResetMouseBackgroundColor
+            | ResetTektronixForegroundColor
+            | ResetTektronixBackgroundColor
+            | ResetHighlightBackgroundColor
+            | ResetTektronixCursorColor
+            | ResetHighlightForegroundColor => {
+                ensure!(osc.len() == 1, "wrong param count");
+                Self::parse_reset_dynamic_color_number(osc_code as u8 - 100)
+            }
+
             _ => bail!("not impl"),
         }
     }
@@ -289,6 +331,19 @@ pub enum OperatingSystemCommandCode {
     SetFont = 50,
     EmacsShell = 51,
     ManipulateSelectionData = 52,
+    ResetColors = 104,
+    ResetTextForegroundColor = 110,
+    ResetTextBackgroundColor = 111,
+    ResetTextCursorColor = 112,
+    ResetMouseForegroundColor = 113,
+    ResetMouseBackgroundColor = 114,
+    ResetTektronixForegroundColor = 115,
+    ResetTektronixBackgroundColor = 116,
+    ResetHighlightBackgroundColor = 117,
+    ResetTektronixCursorColor = 118,
+    ResetHighlightForegroundColor = 119,
+    /// See https://gitlab.freedesktop.org/Per_Bothner/specifications/blob/master/proposals/semantic-prompts.md
+    FinalTermSemanticPrompt = 133,
     RxvtProprietary = 777,
     ITermProprietary = 1337,
 }
@@ -322,11 +377,13 @@ impl Display for OperatingSystemCommand {
             QuerySelection(s) => write!(f, "52;{};?", s)?,
             SetSelection(s, val) => write!(f, "52;{};{}", s, base64::encode(val))?,
             SystemNotification(s) => write!(f, "9;{}", s)?,
+            SetCurrentWorkingDirectory(url) => single_string!(SetCurrentWorkingDirectory, url),
             ITermProprietary(i) => i.fmt(f)?,
+            FinalTermSemanticPrompt(i) => i.fmt(f)?,
             ChangeColorNumber(specs) => {
-                write!(f, "4;")?;
+                write!(f, "4")?;
                 for pair in specs {
-                    write!(f, "{};{}", pair.palette_index, pair.color)?
+                    write!(f, ";{};{}", pair.palette_index, pair.color)?
                 }
             }
             ChangeDynamicColors(first_color, colors) => {
@@ -335,12 +392,73 @@ impl Display for OperatingSystemCommand {
                     write!(f, ";{}", color)?
                 }
             }
+            ResetColors(colors) => {
+                write!(f, "104")?;
+                for color in colors {
+                    write!(f, ";{}", color)?
+                }
+            }
+            ResetDynamicColor(color) => write!(f, "{}", *color as u8 + 100)?,
         };
         write!(f, "\x07")?;
         Ok(())
     }
 }
 
+/// The semantic prompt markers originally defined by FinalTerm.
+/// A shell emits these around its prompt and the command line
+/// so that the terminal can tell which text is the prompt, which
+/// is the input and which is the output of a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinalTermSemanticPrompt {
+    /// `OSC 133 ; A`: the start of the prompt
+    PromptStart,
+    /// `OSC 133 ; B`: the end of the prompt and the start of
+    /// the command line input
+    CommandStart,
+    /// `OSC 133 ; C`: the command has been entered and its
+    /// output follows
+    CommandExecuted,
+    /// `OSC 133 ; D [; status]`: the command has finished,
+    /// optionally with its exit status
+    CommandFinished { status: Option<i32> },
+}
+
+impl FinalTermSemanticPrompt {
+    fn parse(osc: &[&[u8]]) -> anyhow::Result<Self> {
+        ensure!(osc.len() >= 2, "wrong param count");
+        match (osc[1], osc.len()) {
+            (b"A", 2) => Ok(FinalTermSemanticPrompt::PromptStart),
+            (b"B", 2) => Ok(FinalTermSemanticPrompt::CommandStart),
+            (b"C", 2) => Ok(FinalTermSemanticPrompt::CommandExecuted),
+            (b"D", 2) => Ok(FinalTermSemanticPrompt::CommandFinished { status: None }),
+            (b"D", 3) => {
+                let status: i32 = str::from_utf8(osc[2])?.parse()?;
+                Ok(FinalTermSemanticPrompt::CommandFinished {
+                    status: Some(status),
+                })
+            }
+            _ => bail!("unhandled OSC 133: {:?}", osc),
+        }
+    }
+}
+
+impl Display for FinalTermSemanticPrompt {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "133;")?;
+        match self {
+            FinalTermSemanticPrompt::PromptStart => write!(f, "A")?,
+            FinalTermSemanticPrompt::CommandStart => write!(f, "B")?,
+            FinalTermSemanticPrompt::CommandExecuted => write!(f, "C")?,
+            FinalTermSemanticPrompt::CommandFinished { status: None } => write!(f, "D")?,
+            FinalTermSemanticPrompt::CommandFinished {
+                status: Some(status),
+            } => write!(f, "D;{}", status)?,
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ITermProprietary {
     /// The "Set Mark" command allows you to record a location and then jump back to it later
@@ -580,7 +698,7 @@ impl ITermDimension {
     pub fn to_pixels(&self, cell_size: usize, num_cells: usize) -> Option<usize> {
         match self {
             ITermDimension::Automatic => None,
-            ITermDimension::Cells(n) => Some((*n).max(0) as usize * cell_size),
+            ITermDimension::Cells(n) => Some(((*n).max(0) as usize).saturating_mul(cell_size)),
             ITermDimension::Pixels(n) => Some((*n).max(0) as usize),
             ITermDimension::Percent(n) => Some(
                 (((*n).max(0).min(100) as f32 / 100.0) * num_cells as f32 * cell_size as f32)
@@ -747,6 +865,17 @@ mod test {
         result
     }
 
+    #[test]
+    fn cwd() {
+        assert_eq!(
+            parse(
+                &["7", "file://localhost/home/wez"],
+                "\x1b]7;file://localhost/home/wez\x07"
+            ),
+            OperatingSystemCommand::SetCurrentWorkingDirectory("file://localhost/home/wez".into())
+        );
+    }
+
     #[test]
     fn title() {
         assert_eq!(
@@ -802,6 +931,98 @@ mod test {
         );
     }
 
+    #[test]
+    fn change_colors() {
+        assert_eq!(
+            parse(
+                &["4", "1", "#ff8000", "2", "?"],
+                "\x1b]4;1;rgb:ffff/8080/0000;2;?\x07"
+            ),
+            OperatingSystemCommand::ChangeColorNumber(vec![
+                ChangeColorPair {
+                    palette_index: 1,
+                    color: ColorOrQuery::Color(RgbColor::new(0xff, 0x80, 0)),
+                },
+                ChangeColorPair {
+                    palette_index: 2,
+                    color: ColorOrQuery::Query,
+                },
+            ])
+        );
+        assert_eq!(
+            parse(&["11", "?"], "\x1b]11;?\x07"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextBackgroundColor,
+                vec![ColorOrQuery::Query]
+            )
+        );
+        assert_eq!(
+            parse(&["10", "rgb:00/80/ff"], "\x1b]10;rgb:0000/8080/ffff\x07"),
+            OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextForegroundColor,
+                vec![ColorOrQuery::Color(RgbColor::new(0, 0x80, 0xff))]
+            )
+        );
+    }
+
+    #[test]
+    fn reset_colors() {
+        assert_eq!(
+            parse(&["104"], "\x1b]104\x07"),
+            OperatingSystemCommand::ResetColors(vec![])
+        );
+        assert_eq!(
+            parse(&["104", ""], "\x1b]104\x07"),
+            OperatingSystemCommand::ResetColors(vec![])
+        );
+        assert_eq!(
+            parse(&["104", "1", "15"], "\x1b]104;1;15\x07"),
+            OperatingSystemCommand::ResetColors(vec![1, 15])
+        );
+        assert_eq!(
+            parse(&["110"], "\x1b]110\x07"),
+            OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextForegroundColor)
+        );
+        assert_eq!(
+            parse(&["111"], "\x1b]111\x07"),
+            OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextBackgroundColor)
+        );
+        assert_eq!(
+            parse(&["112"], "\x1b]112\x07"),
+            OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextCursorColor)
+        );
+    }
+
+    #[test]
+    fn semantic_prompt() {
+        assert_eq!(
+            parse(&["133", "A"], "\x1b]133;A\x07"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::PromptStart)
+        );
+        assert_eq!(
+            parse(&["133", "B"], "\x1b]133;B\x07"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(FinalTermSemanticPrompt::CommandStart)
+        );
+        assert_eq!(
+            parse(&["133", "C"], "\x1b]133;C\x07"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandExecuted
+            )
+        );
+        assert_eq!(
+            parse(&["133", "D"], "\x1b]133;D\x07"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandFinished { status: None }
+            )
+        );
+        assert_eq!(
+            parse(&["133", "D", "1"], "\x1b]133;D;1\x07"),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandFinished { status: Some(1) }
+            )
+        );
+    }
+
     #[test]
     fn iterm() {
         assert_eq!(
@@ -1,4 +1,5 @@
-use crate::escape::{Action, DeviceControlMode, Esc, OperatingSystemCommand, CSI};
+use crate::escape::sixel::MAX_DATA_BYTES;
+use crate::escape::{Action, DeviceControlMode, Esc, OperatingSystemCommand, Sixel, CSI};
 use log::error;
 use num;
 use std::cell::RefCell;
@@ -12,6 +13,17 @@ use vtparse::{VTActor, VTParser};
 /// decoded actions.
 pub struct Parser {
     state_machine: VTParser,
+    sixel: Option<SixelBuilder>,
+}
+
+/// Accumulates the data for a sixel image while the DCS
+/// sequence that carries it is being parsed
+struct SixelBuilder {
+    params: Vec<i64>,
+    data: Vec<u8>,
+    /// Set if the data exceeded MAX_DATA_BYTES, in which case the
+    /// image is discarded
+    overflowed: bool,
 }
 
 impl Default for Parser {
@@ -24,12 +36,14 @@ impl Parser {
     pub fn new() -> Self {
         Self {
             state_machine: VTParser::new(),
+            sixel: None,
         }
     }
 
     pub fn parse<F: FnMut(Action)>(&mut self, bytes: &[u8], mut callback: F) {
         let mut perform = Performer {
             callback: &mut callback,
+            sixel: &mut self.sixel,
         };
         self.state_machine.parse(bytes, &mut perform);
     }
@@ -54,6 +68,7 @@ impl Parser {
                     }
                     *first.borrow_mut() = Some(action);
                 },
+                sixel: &mut self.sixel,
             };
             for (idx, b) in bytes.iter().enumerate() {
                 self.state_machine.parse_byte(*b, &mut perform);
@@ -88,6 +103,7 @@ impl Parser {
                 *b,
                 &mut Performer {
                     callback: &mut |action| actions.push(action),
+                    sixel: &mut self.sixel,
                 },
             );
             if !actions.is_empty() {
@@ -102,6 +118,7 @@ impl Parser {
 
 struct Performer<'a, F: FnMut(Action) + 'a> {
     callback: &'a mut F,
+    sixel: &'a mut Option<SixelBuilder>,
 }
 
 impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
@@ -121,21 +138,55 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         params: &[i64],
         intermediates: &[u8],
         ignored_extra_intermediates: bool,
+        byte: u8,
     ) {
+        if byte == b'q' && intermediates.is_empty() && !ignored_extra_intermediates {
+            // Sixel graphics; buffer up the data until the sequence
+            // is terminated and then emit the image as a single action
+            self.sixel.replace(SixelBuilder {
+                params: params.to_vec(),
+                data: vec![],
+                overflowed: false,
+            });
+            return;
+        }
         (self.callback)(Action::DeviceControl(Box::new(DeviceControlMode::Enter {
             params: params.to_vec(),
             intermediates: intermediates.to_vec(),
             ignored_extra_intermediates,
+            byte,
         })));
     }
 
     fn dcs_put(&mut self, data: u8) {
+        if let Some(sixel) = self.sixel.as_mut() {
+            if sixel.data.len() < MAX_DATA_BYTES {
+                sixel.data.push(data);
+            } else {
+                sixel.overflowed = true;
+            }
+            return;
+        }
         (self.callback)(Action::DeviceControl(Box::new(DeviceControlMode::Data(
             data,
         ))));
     }
 
     fn dcs_unhook(&mut self) {
+        if let Some(sixel) = self.sixel.take() {
+            if sixel.overflowed {
+                error!(
+                    "sixel image data exceeded {} bytes and was discarded",
+                    MAX_DATA_BYTES
+                );
+                return;
+            }
+            (self.callback)(Action::Sixel(Box::new(Sixel::parse(
+                &sixel.params,
+                &sixel.data,
+            ))));
+            return;
+        }
         (self.callback)(Action::DeviceControl(Box::new(DeviceControlMode::Exit)));
     }
 
@@ -161,6 +212,23 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         }
     }
 
+    fn csi_dispatch_with_subparams(
+        &mut self,
+        params: &[&[i64]],
+        intermediates: &[u8],
+        ignored_extra_intermediates: bool,
+        control: u8,
+    ) {
+        for action in CSI::parse_with_subparams(
+            params,
+            intermediates,
+            ignored_extra_intermediates,
+            control as char,
+        ) {
+            (self.callback)(Action::CSI(action));
+        }
+    }
+
     fn esc_dispatch(
         &mut self,
         _params: &[i64],
@@ -185,7 +253,7 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::cell::Intensity;
+    use crate::cell::{Intensity, Underline};
     use crate::escape::csi::Sgr;
     use crate::escape::EscCode;
     use std::io::Write;
@@ -245,6 +313,22 @@ mod test {
         assert_eq!(encode(&actions), "\x1b[1m\x1b[3mb");
     }
 
+    #[test]
+    fn curly_underline() {
+        let mut p = Parser::new();
+        let actions = p.parse_as_vec(b"\x1b[1;4:3mb");
+        assert_eq!(
+            vec![
+                Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
+                Action::CSI(CSI::Sgr(Sgr::Underline(Underline::Curly))),
+                Action::Print('b'),
+            ],
+            actions
+        );
+
+        assert_eq!(encode(&actions), "\x1b[1m\x1b[4:3mb");
+    }
+
     #[test]
     fn basic_osc() {
         let mut p = Parser::new();
@@ -301,4 +385,49 @@ mod test {
         );
         assert_eq!(encode(&actions), "\x1b%H");
     }
+
+    #[test]
+    fn sixel() {
+        let mut p = Parser::new();
+        let input = "\x1bP0;1q\"1;1;2;6#1;2;100;0;0#1~~\x1b\\";
+        let actions = p.parse_as_vec(input.as_bytes());
+        assert_eq!(
+            vec![
+                Action::Sixel(Box::new(Sixel::parse(
+                    &[0, 1],
+                    b"\"1;1;2;6#1;2;100;0;0#1~~"
+                ))),
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+            ],
+            actions
+        );
+        assert_eq!(encode(&actions[0..1].to_vec()), input);
+
+        // An image with an unreasonable amount of data is discarded
+        // rather than buffered without limit
+        let mut input = b"\x1bPq".to_vec();
+        input.resize(input.len() + MAX_DATA_BYTES + 1, b'~');
+        input.extend_from_slice(b"\x1b\\");
+        let actions = p.parse_as_vec(&input);
+        assert_eq!(
+            vec![Action::Esc(Esc::Code(EscCode::StringTerminator))],
+            actions
+        );
+
+        let actions = p.parse_as_vec(b"\x1bP1$rx\x1b\\");
+        assert_eq!(
+            vec![
+                Action::DeviceControl(Box::new(DeviceControlMode::Enter {
+                    params: vec![1],
+                    intermediates: vec![b'$'],
+                    ignored_extra_intermediates: false,
+                    byte: b'r',
+                })),
+                Action::DeviceControl(Box::new(DeviceControlMode::Data(b'x'))),
+                Action::DeviceControl(Box::new(DeviceControlMode::Exit)),
+                Action::Esc(Esc::Code(EscCode::StringTerminator)),
+            ],
+            actions
+        );
+    }
 }
@@ -0,0 +1,473 @@
+//! Sixel graphics.
+//! A sixel image is transmitted as a device control string of the form
+//! `DCS P1 ; P2 ; P3 q <data> ST`.  The data is a sequence of columns of
+//! six vertical pixels ("sixels") interspersed with commands that define
+//! and select colors, repeat columns and move between bands of sixels.
+//! The VT330/VT340 programmer reference manual describes the format:
+//! <https://vt100.net/docs/vt3xx-gp/chapter14.html>
+use crate::color::RgbColor;
+use image::{Rgba, RgbaImage};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::iter::Peekable;
+
+/// The largest number of pixels that we're prepared to decode
+/// from a single image.
+const MAX_PIXELS: u64 = 4096 * 4096;
+
+/// The largest amount of data that we're prepared to buffer for a
+/// single image while its device control string is being parsed.
+/// An image that fits within MAX_PIXELS needs far less than this.
+pub(crate) const MAX_DATA_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sixel {
+    /// Specifies the numerator for the pixel aspect ratio
+    pub pan: i64,
+    /// Specifies the denominator for the pixel aspect ratio
+    pub pad: i64,
+    /// How wide the image is, in pixels, as declared by the
+    /// raster attributes
+    pub pixel_width: Option<u32>,
+    /// How tall the image is, in pixels, as declared by the
+    /// raster attributes
+    pub pixel_height: Option<u32>,
+    /// When true, pixels that are not drawn by the data remain
+    /// transparent.  Otherwise they are filled with color 0.
+    pub background_is_transparent: bool,
+    /// The horizontal spacing between pixels, in decipoints.
+    /// This is not used by modern devices.
+    pub horizontal_grid_size: Option<i64>,
+    /// The sixel data
+    pub data: Vec<SixelData>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SixelData {
+    /// A column of six pixels, where the least significant bit is
+    /// the topmost pixel.  Set bits are drawn using the current color.
+    Data(u8),
+    /// The column of six pixels is repeated `repeat_count` times
+    Repeat { repeat_count: u32, data: u8 },
+    /// Define color register `color_number` using RGB values
+    DefineColorMapRGB { color_number: u16, rgb: RgbColor },
+    /// Define color register `color_number` using the HLS color
+    /// space.  The hue angle uses the DEC convention in which
+    /// blue is at 0 degrees, red at 120 and green at 240.
+    DefineColorMapHSL {
+        color_number: u16,
+        hue_angle: u16,
+        lightness: u8,
+        saturation: u8,
+    },
+    /// Select the color register used to draw subsequent data
+    SelectColorMapEntry(u16),
+    /// Move back to the left edge of the current band of sixels
+    CarriageReturn,
+    /// Move to the left edge of the next band of sixels
+    NewLine,
+}
+
+impl Sixel {
+    /// Parse the parameters and data of a sixel device control string
+    pub fn parse(params: &[i64], data: &[u8]) -> Self {
+        // The default aspect ratio depends on P1
+        let pan = match params.first().cloned().unwrap_or(0) {
+            2 => 5,
+            3 | 4 => 3,
+            7..=9 => 1,
+            _ => 2,
+        };
+        let background_is_transparent = params.get(1).cloned() == Some(1);
+        let horizontal_grid_size = params.get(2).cloned();
+
+        let mut sixel = Self {
+            pan,
+            pad: 1,
+            pixel_width: None,
+            pixel_height: None,
+            background_is_transparent,
+            horizontal_grid_size,
+            data: vec![],
+        };
+
+        let mut iter = data.iter().cloned().peekable();
+
+        while let Some(b) = iter.next() {
+            match b {
+                b'"' => {
+                    let raster = numbers(&mut iter);
+                    if let (Some(&pan), Some(&pad)) = (raster.first(), raster.get(1)) {
+                        if pan > 0 && pad > 0 {
+                            sixel.pan = pan;
+                            sixel.pad = pad;
+                        }
+                    }
+                    sixel.pixel_width = raster.get(2).map(|&w| w.max(0) as u32);
+                    sixel.pixel_height = raster.get(3).map(|&h| h.max(0) as u32);
+                }
+                b'!' => {
+                    let repeat_count = numbers(&mut iter).first().cloned().unwrap_or(1);
+                    if let Some(d) = iter.next() {
+                        if (0x3f..=0x7e).contains(&d) {
+                            sixel.data.push(SixelData::Repeat {
+                                repeat_count: repeat_count.max(1) as u32,
+                                data: d - 0x3f,
+                            });
+                        }
+                    }
+                }
+                b'#' => {
+                    let color = numbers(&mut iter);
+                    let color_number = color.first().cloned().unwrap_or(0) as u16;
+                    match color.as_slice() {
+                        [_] => sixel
+                            .data
+                            .push(SixelData::SelectColorMapEntry(color_number)),
+                        [_, 1, hue, lightness, saturation] => {
+                            sixel.data.push(SixelData::DefineColorMapHSL {
+                                color_number,
+                                hue_angle: (*hue).max(0).min(360) as u16,
+                                lightness: (*lightness).max(0).min(100) as u8,
+                                saturation: (*saturation).max(0).min(100) as u8,
+                            })
+                        }
+                        [_, 2, red, green, blue] => sixel.data.push(SixelData::DefineColorMapRGB {
+                            color_number,
+                            rgb: RgbColor::new(
+                                percent_to_u8(*red),
+                                percent_to_u8(*green),
+                                percent_to_u8(*blue),
+                            ),
+                        }),
+                        _ => {}
+                    }
+                }
+                b'$' => sixel.data.push(SixelData::CarriageReturn),
+                b'-' => sixel.data.push(SixelData::NewLine),
+                0x3f..=0x7e => sixel.data.push(SixelData::Data(b - 0x3f)),
+                // Anything else, such as whitespace, is ignored
+                _ => {}
+            }
+        }
+
+        sixel
+    }
+
+    /// Returns the width and height, in pixels, of the area
+    /// that is covered by the image
+    pub fn dimensions(&self) -> (u32, u32) {
+        let mut max_x = 0u32;
+        let mut max_band = None;
+        let mut x = 0u32;
+        let mut band = 0u32;
+
+        for d in &self.data {
+            match d {
+                SixelData::Data(_) => {
+                    x = x.saturating_add(1);
+                    max_x = max_x.max(x);
+                    max_band = Some(band);
+                }
+                SixelData::Repeat { repeat_count, .. } => {
+                    x = x.saturating_add(*repeat_count);
+                    max_x = max_x.max(x);
+                    max_band = Some(band);
+                }
+                SixelData::CarriageReturn => x = 0,
+                SixelData::NewLine => {
+                    x = 0;
+                    band = band.saturating_add(1);
+                }
+                _ => {}
+            }
+        }
+
+        let height = max_band.map(|b| b.saturating_add(1).saturating_mul(6));
+
+        (
+            max_x.max(self.pixel_width.unwrap_or(0)),
+            height.unwrap_or(0).max(self.pixel_height.unwrap_or(0)),
+        )
+    }
+
+    /// Render the image into an RGBA bitmap.
+    /// Pixels are treated as being square; the aspect ratio is
+    /// not applied.
+    /// Returns None if the image is empty or unreasonably large.
+    pub fn decode(&self) -> Option<RgbaImage> {
+        let (width, height) = self.dimensions();
+        if width == 0 || height == 0 || u64::from(width) * u64::from(height) > MAX_PIXELS {
+            return None;
+        }
+
+        let mut registers: Vec<RgbColor> = DEFAULT_COLORS
+            .iter()
+            .map(|&(red, green, blue)| {
+                RgbColor::new(
+                    percent_to_u8(red),
+                    percent_to_u8(green),
+                    percent_to_u8(blue),
+                )
+            })
+            .collect();
+        let background = if self.background_is_transparent {
+            Rgba([0, 0, 0, 0])
+        } else {
+            let c = registers[0];
+            Rgba([c.red, c.green, c.blue, 0xff])
+        };
+        let mut image = RgbaImage::from_pixel(width, height, background);
+
+        let mut color = registers[0];
+        let mut x = 0u32;
+        let mut y = 0u32;
+
+        let draw = |image: &mut RgbaImage, x: u32, y: u32, data: u8, color: RgbColor| {
+            for bit in 0..6 {
+                if data & (1 << bit) != 0 && x < width && y + bit < height {
+                    image.put_pixel(x, y + bit, Rgba([color.red, color.green, color.blue, 0xff]));
+                }
+            }
+        };
+
+        for d in &self.data {
+            match *d {
+                SixelData::Data(data) => {
+                    draw(&mut image, x, y, data, color);
+                    x = x.saturating_add(1);
+                }
+                SixelData::Repeat { repeat_count, data } => {
+                    for i in 0..repeat_count.min(width.saturating_sub(x)) {
+                        draw(&mut image, x + i, y, data, color);
+                    }
+                    x = x.saturating_add(repeat_count);
+                }
+                SixelData::DefineColorMapRGB { color_number, rgb } => {
+                    set_register(&mut registers, color_number, rgb);
+                    color = rgb;
+                }
+                SixelData::DefineColorMapHSL {
+                    color_number,
+                    hue_angle,
+                    lightness,
+                    saturation,
+                } => {
+                    let rgb = hls_to_rgb(hue_angle, lightness, saturation);
+                    set_register(&mut registers, color_number, rgb);
+                    color = rgb;
+                }
+                SixelData::SelectColorMapEntry(color_number) => {
+                    color = registers
+                        .get(color_number as usize)
+                        .cloned()
+                        .unwrap_or_else(|| RgbColor::new(0, 0, 0));
+                }
+                SixelData::CarriageReturn => x = 0,
+                SixelData::NewLine => {
+                    x = 0;
+                    y = y.saturating_add(6);
+                }
+            }
+        }
+
+        Some(image)
+    }
+}
+
+/// The default color registers of the VT340, expressed as RGB percentages
+const DEFAULT_COLORS: [(i64, i64, i64); 16] = [
+    (0, 0, 0),
+    (20, 20, 80),
+    (80, 13, 13),
+    (20, 80, 20),
+    (80, 20, 80),
+    (20, 80, 80),
+    (80, 80, 20),
+    (53, 53, 53),
+    (26, 26, 26),
+    (33, 33, 60),
+    (60, 26, 26),
+    (33, 60, 33),
+    (60, 33, 60),
+    (33, 60, 60),
+    (60, 60, 33),
+    (80, 80, 80),
+];
+
+/// Consumes a sequence of `;` separated decimal numbers.
+/// Omitted numbers are returned as 0.
+fn numbers<I: Iterator<Item = u8>>(iter: &mut Peekable<I>) -> Vec<i64> {
+    let mut result = vec![];
+    let mut current: Option<i64> = None;
+    loop {
+        match iter.peek() {
+            Some(&b) if b.is_ascii_digit() => {
+                let digit = i64::from(b - b'0');
+                current = Some(
+                    current
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit),
+                );
+            }
+            Some(b';') => {
+                result.push(current.take().unwrap_or(0));
+            }
+            _ => break,
+        }
+        iter.next();
+    }
+    if let Some(value) = current {
+        result.push(value);
+    }
+    result
+}
+
+fn set_register(registers: &mut Vec<RgbColor>, color_number: u16, rgb: RgbColor) {
+    let idx = color_number as usize;
+    if idx >= registers.len() {
+        registers.resize(idx + 1, RgbColor::new(0, 0, 0));
+    }
+    registers[idx] = rgb;
+}
+
+fn percent_to_u8(value: i64) -> u8 {
+    ((value.max(0).min(100) * 255 + 50) / 100) as u8
+}
+
+fn u8_to_percent(value: u8) -> u16 {
+    (u16::from(value) * 100 + 127) / 255
+}
+
+/// Convert a color from the DEC HLS color space, where blue is at
+/// 0 degrees, to RGB
+fn hls_to_rgb(hue_angle: u16, lightness: u8, saturation: u8) -> RgbColor {
+    let hue = f64::from((hue_angle + 240) % 360);
+    let lightness = f64::from(lightness) / 100.;
+    let saturation = f64::from(saturation) / 100.;
+
+    let chroma = (1. - (2. * lightness - 1.).abs()) * saturation;
+    let h = hue / 60.;
+    let x = chroma * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.),
+        1 => (x, chroma, 0.),
+        2 => (0., chroma, x),
+        3 => (0., x, chroma),
+        4 => (x, 0., chroma),
+        _ => (chroma, 0., x),
+    };
+    let m = lightness - chroma / 2.;
+    let to_u8 = |v: f64| ((v + m) * 255.).round().max(0.).min(255.) as u8;
+    RgbColor::new(to_u8(r), to_u8(g), to_u8(b))
+}
+
+impl Display for SixelData {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            SixelData::Data(d) => write!(f, "{}", (d + 0x3f) as char),
+            SixelData::Repeat { repeat_count, data } => {
+                write!(f, "!{}{}", repeat_count, (data + 0x3f) as char)
+            }
+            SixelData::DefineColorMapRGB { color_number, rgb } => write!(
+                f,
+                "#{};2;{};{};{}",
+                color_number,
+                u8_to_percent(rgb.red),
+                u8_to_percent(rgb.green),
+                u8_to_percent(rgb.blue)
+            ),
+            SixelData::DefineColorMapHSL {
+                color_number,
+                hue_angle,
+                lightness,
+                saturation,
+            } => write!(
+                f,
+                "#{};1;{};{};{}",
+                color_number, hue_angle, lightness, saturation
+            ),
+            SixelData::SelectColorMapEntry(n) => write!(f, "#{}", n),
+            SixelData::CarriageReturn => write!(f, "$"),
+            SixelData::NewLine => write!(f, "-"),
+        }
+    }
+}
+
+impl Display for Sixel {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(
+            f,
+            "\x1bP0;{}",
+            if self.background_is_transparent { 1 } else { 0 }
+        )?;
+        if let Some(grid) = self.horizontal_grid_size {
+            write!(f, ";{}", grid)?;
+        }
+        write!(f, "q\"{};{}", self.pan, self.pad)?;
+        if let (Some(w), Some(h)) = (self.pixel_width, self.pixel_height) {
+            write!(f, ";{};{}", w, h)?;
+        }
+        for d in &self.data {
+            d.fmt(f)?;
+        }
+        write!(f, "\x1b\\")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let sixel = Sixel::parse(&[0, 1], b"\"1;1;3;6#1;2;100;0;0#1~!2@$-#0N");
+        assert_eq!(
+            sixel,
+            Sixel {
+                pan: 1,
+                pad: 1,
+                pixel_width: Some(3),
+                pixel_height: Some(6),
+                background_is_transparent: true,
+                horizontal_grid_size: None,
+                data: vec![
+                    SixelData::DefineColorMapRGB {
+                        color_number: 1,
+                        rgb: RgbColor::new(255, 0, 0),
+                    },
+                    SixelData::SelectColorMapEntry(1),
+                    SixelData::Data(0x3f),
+                    SixelData::Repeat {
+                        repeat_count: 2,
+                        data: 1,
+                    },
+                    SixelData::CarriageReturn,
+                    SixelData::NewLine,
+                    SixelData::SelectColorMapEntry(0),
+                    SixelData::Data(0xf),
+                ],
+            }
+        );
+        assert_eq!(sixel.dimensions(), (3, 12));
+        assert_eq!(
+            sixel.to_string(),
+            "\x1bP0;1q\"1;1;3;6#1;2;100;0;0#1~!2@$-#0N\x1b\\"
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let sixel = Sixel::parse(&[0, 1], b"#1;2;100;0;0#1~!2@");
+        let image = sixel.decode().unwrap();
+        assert_eq!(image.dimensions(), (3, 6));
+        let red = Rgba([0xff, 0, 0, 0xff]);
+        let clear = Rgba([0, 0, 0, 0]);
+        assert_eq!(*image.get_pixel(0, 5), red);
+        assert_eq!(*image.get_pixel(1, 0), red);
+        assert_eq!(*image.get_pixel(2, 0), red);
+        assert_eq!(*image.get_pixel(2, 1), clear);
+
+        assert_eq!(hls_to_rgb(120, 50, 100), RgbColor::new(255, 0, 0));
+    }
+}
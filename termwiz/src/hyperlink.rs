@@ -39,6 +39,16 @@ impl Hyperlink {
         }
     }
 
+    /// Returns true if `self` and `other` are part of the same link.
+    /// A link is normally a run of cells produced by a single escape
+    /// sequence, but links that specify an `id` parameter are also
+    /// the same as other links that have the same `id` and uri, so that
+    /// eg: a link that an application has wrapped across multiple lines
+    /// is treated as a single unit.
+    pub fn is_same_link(&self, other: &Hyperlink) -> bool {
+        std::ptr::eq(self, other) || (self.params.contains_key("id") && self == other)
+    }
+
     #[inline]
     pub fn is_implicit(&self) -> bool {
         self.implicit
@@ -264,4 +274,19 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn same_link() {
+        let a = Hyperlink::new("http://example.com");
+        let b = Hyperlink::new("http://example.com");
+        assert!(a.is_same_link(&a));
+        // Without an id, separately emitted links are distinct
+        assert!(!a.is_same_link(&b));
+
+        let a = Hyperlink::new_with_id("http://example.com", "1");
+        let b = Hyperlink::new_with_id("http://example.com", "1");
+        let c = Hyperlink::new_with_id("http://example.com", "2");
+        assert!(a.is_same_link(&b));
+        assert!(!a.is_same_link(&c));
+    }
 }
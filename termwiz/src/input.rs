@@ -11,8 +11,8 @@ use std;
 
 #[cfg(windows)]
 use winapi::um::wincon::{
-    INPUT_RECORD, KEY_EVENT, KEY_EVENT_RECORD, MOUSE_EVENT, MOUSE_EVENT_RECORD,
-    WINDOW_BUFFER_SIZE_EVENT, WINDOW_BUFFER_SIZE_RECORD,
+    FOCUS_EVENT, FOCUS_EVENT_RECORD, INPUT_RECORD, KEY_EVENT, KEY_EVENT_RECORD, MOUSE_EVENT,
+    MOUSE_EVENT_RECORD, WINDOW_BUFFER_SIZE_EVENT, WINDOW_BUFFER_SIZE_RECORD,
 };
 
 bitflags! {
@@ -23,8 +23,24 @@ bitflags! {
         const ALT = 1<<2;
         const CTRL = 1<<3;
         const SUPER = 1<<4;
+        const HYPER = 1<<5;
+        const META = 1<<6;
     }
 }
+bitflags! {
+    /// The progressive enhancements of the kitty keyboard protocol;
+    /// see <https://sw.kovidgoyal.net/kitty/keyboard-protocol/>
+    #[derive(Default)]
+    pub struct KittyKeyboardFlags: u8 {
+        const NONE = 0;
+        const DISAMBIGUATE_ESCAPE_CODES = 1;
+        const REPORT_EVENT_TYPES = 2;
+        const REPORT_ALTERNATE_KEYS = 4;
+        const REPORT_ALL_KEYS_AS_ESCAPE_CODES = 8;
+        const REPORT_ASSOCIATED_TEXT = 16;
+    }
+}
+
 bitflags! {
     #[derive(Default, Serialize, Deserialize)]
     pub struct MouseButtons: u8 {
@@ -49,9 +65,18 @@ pub enum InputEvent {
         cols: usize,
         rows: usize,
     },
+    /// A key was released.  This is only reported by terminals
+    /// that have been asked to use the kitty keyboard protocol
+    /// with `KittyKeyboardFlags::REPORT_EVENT_TYPES`.
+    KeyRelease(KeyEvent),
     /// For terminals that support Bracketed Paste mode,
     /// pastes are collected and reported as this variant.
     Paste(String),
+    /// The terminal window gained the input focus.  This is only
+    /// reported when focus reporting is enabled.
+    FocusGained,
+    /// The terminal window lost the input focus
+    FocusLost,
     /// The program has woken the input thread.
     Wake,
 }
@@ -171,6 +196,28 @@ pub enum KeyCode {
 }
 
 impl KeyCode {
+    /// Returns true if this is the code of a modifier key, such as
+    /// `Shift` or `LeftControl`, rather than a key that is typed
+    pub fn is_modifier(self) -> bool {
+        match self {
+            KeyCode::Hyper
+            | KeyCode::Super
+            | KeyCode::Meta
+            | KeyCode::Shift
+            | KeyCode::LeftShift
+            | KeyCode::RightShift
+            | KeyCode::Control
+            | KeyCode::LeftControl
+            | KeyCode::RightControl
+            | KeyCode::Alt
+            | KeyCode::LeftAlt
+            | KeyCode::RightAlt
+            | KeyCode::LeftWindows
+            | KeyCode::RightWindows => true,
+            _ => false,
+        }
+    }
+
     /// if SHIFT is held and we have KeyCode::Char('c') we want to normalize
     /// that keycode to KeyCode::Char('C'); that is what this function does.
     /// In theory we should give the same treatment to keys like `[` -> `{`
@@ -189,6 +236,253 @@ impl KeyCode {
     }
 }
 
+/// Selects how the terminal encodes key presses that have no
+/// unambiguous representation in the legacy encoding, such as
+/// `CTRL-I` vs. `Tab`.  The `InputParser` understands all of these
+/// encodings; use `Terminal::set_keyboard_encoding` to request one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardEncoding {
+    /// The traditional encoding
+    Legacy,
+    /// xterm's `modifyOtherKeys` at level 2, which reports modified
+    /// keys as `CSI 27 ; modifiers ; code ~`
+    ModifyOtherKeys,
+    /// The kitty keyboard protocol with the specified enhancements,
+    /// which reports keys as `CSI code ; modifiers u`
+    Kitty(KittyKeyboardFlags),
+}
+
+impl Default for KeyboardEncoding {
+    fn default() -> Self {
+        KeyboardEncoding::Legacy
+    }
+}
+
+impl KeyboardEncoding {
+    /// Returns the escape sequence that asks the terminal to start
+    /// using this encoding
+    pub fn enable_sequence(self) -> String {
+        match self {
+            KeyboardEncoding::Legacy => String::new(),
+            KeyboardEncoding::ModifyOtherKeys => "\x1b[>4;2m".to_string(),
+            // Push the flags onto the terminal's stack, so that
+            // disable_sequence can restore the prior state
+            KeyboardEncoding::Kitty(flags) => format!("\x1b[>{}u", flags.bits()),
+        }
+    }
+
+    /// Returns the escape sequence that asks the terminal to stop
+    /// using this encoding and revert to its prior state
+    pub fn disable_sequence(self) -> String {
+        match self {
+            KeyboardEncoding::Legacy => String::new(),
+            KeyboardEncoding::ModifyOtherKeys => "\x1b[>4;0m".to_string(),
+            KeyboardEncoding::Kitty(_) => "\x1b[<u".to_string(),
+        }
+    }
+}
+
+/// Decodes the modifier parameter used by both the kitty keyboard
+/// protocol and xterm; it is one plus a bitmask of the modifiers.
+/// The caps lock and num lock bits are not represented in `Modifiers`.
+fn decode_key_modifiers(value: u32) -> Modifiers {
+    let bits = value.saturating_sub(1);
+    let mut modifiers = Modifiers::NONE;
+    for (bit, modifier) in &[
+        (1, Modifiers::SHIFT),
+        (2, Modifiers::ALT),
+        (4, Modifiers::CTRL),
+        (8, Modifiers::SUPER),
+        (16, Modifiers::HYPER),
+        (32, Modifiers::META),
+    ] {
+        if bits & bit != 0 {
+            modifiers |= *modifier;
+        }
+    }
+    modifiers
+}
+
+/// Maps a key code from a `CSI u` or modifyOtherKeys sequence to
+/// a `KeyCode`.  Besides unicode codepoints, kitty uses the private
+/// use area for keys that have no character representation.
+fn decode_key_code(code: u32) -> Option<KeyCode> {
+    let key = match code {
+        8 | 127 => KeyCode::Backspace,
+        9 => KeyCode::Tab,
+        13 => KeyCode::Enter,
+        27 => KeyCode::Escape,
+        57358 => KeyCode::CapsLock,
+        57359 => KeyCode::ScrollLock,
+        57360 => KeyCode::NumLock,
+        57361 => KeyCode::PrintScreen,
+        57362 => KeyCode::Pause,
+        57363 => KeyCode::Menu,
+        57376..=57398 => KeyCode::Function((code - 57376 + 13) as u8),
+        57399..=57408 => match code - 57399 {
+            0 => KeyCode::Numpad0,
+            1 => KeyCode::Numpad1,
+            2 => KeyCode::Numpad2,
+            3 => KeyCode::Numpad3,
+            4 => KeyCode::Numpad4,
+            5 => KeyCode::Numpad5,
+            6 => KeyCode::Numpad6,
+            7 => KeyCode::Numpad7,
+            8 => KeyCode::Numpad8,
+            _ => KeyCode::Numpad9,
+        },
+        57409 => KeyCode::Decimal,
+        57410 => KeyCode::Divide,
+        57411 => KeyCode::Multiply,
+        57412 => KeyCode::Subtract,
+        57413 => KeyCode::Add,
+        57414 => KeyCode::Enter,
+        57415 => KeyCode::Char('='),
+        57416 => KeyCode::Separator,
+        57417 => KeyCode::LeftArrow,
+        57418 => KeyCode::RightArrow,
+        57419 => KeyCode::UpArrow,
+        57420 => KeyCode::DownArrow,
+        57421 => KeyCode::PageUp,
+        57422 => KeyCode::PageDown,
+        57423 => KeyCode::Home,
+        57424 => KeyCode::End,
+        57425 => KeyCode::Insert,
+        57426 => KeyCode::Delete,
+        57430 => KeyCode::MediaPlayPause,
+        57432 => KeyCode::MediaStop,
+        57435 => KeyCode::MediaNextTrack,
+        57436 => KeyCode::MediaPrevTrack,
+        57438 => KeyCode::VolumeDown,
+        57439 => KeyCode::VolumeUp,
+        57440 => KeyCode::VolumeMute,
+        57441 => KeyCode::LeftShift,
+        57442 => KeyCode::LeftControl,
+        57443 => KeyCode::LeftAlt,
+        57444 => KeyCode::LeftWindows,
+        57445 | 57451 => KeyCode::Hyper,
+        57446 | 57452 => KeyCode::Meta,
+        57447 => KeyCode::RightShift,
+        57448 => KeyCode::RightControl,
+        57449 => KeyCode::RightAlt,
+        57450 => KeyCode::RightWindows,
+        _ => KeyCode::Char(std::char::from_u32(code)?),
+    };
+    Some(key)
+}
+
+/// Maps the number in a `CSI number ~` sequence to a `KeyCode`
+fn decode_tilde_key(number: u32) -> Option<KeyCode> {
+    Some(match number {
+        2 => KeyCode::Insert,
+        3 => KeyCode::Delete,
+        5 => KeyCode::PageUp,
+        6 => KeyCode::PageDown,
+        7 => KeyCode::Home,
+        8 => KeyCode::End,
+        11..=15 => KeyCode::Function((number - 10) as u8),
+        17..=21 => KeyCode::Function((number - 11) as u8),
+        23 | 24 => KeyCode::Function((number - 12) as u8),
+        _ => return None,
+    })
+}
+
+/// Attempts to decode a key reported using the kitty keyboard protocol
+/// or modifyOtherKeys from the start of `bytes`.  Sequences that use
+/// the legacy encoding yield `Found::None` so that they are handled
+/// by the key map instead.
+fn decode_csi_key(bytes: &[u8]) -> Found<InputEvent> {
+    if !bytes.starts_with(b"\x1b[") {
+        return if b"\x1b[".starts_with(bytes) {
+            Found::NeedData
+        } else {
+            Found::None
+        };
+    }
+
+    let params_len = match bytes[2..]
+        .iter()
+        .position(|&b| !(b.is_ascii_digit() || b == b';' || b == b':'))
+    {
+        Some(len) => len,
+        None => return Found::NeedData,
+    };
+    let params = &bytes[2..2 + params_len];
+    let final_byte = bytes[2 + params_len];
+    let len = 3 + params_len;
+
+    // Each parameter is made up of colon separated sub-parameters,
+    // any of which may be empty
+    let params: Vec<Vec<Option<u32>>> = params
+        .split(|&b| b == b';')
+        .map(|param| {
+            param
+                .split(|&b| b == b':')
+                .map(|sub| std::str::from_utf8(sub).ok()?.parse().ok())
+                .collect()
+        })
+        .collect();
+    let param = |idx: usize, sub: usize| -> Option<u32> {
+        params.get(idx).and_then(|p| p.get(sub)).and_then(|v| *v)
+    };
+
+    let modifiers = decode_key_modifiers(param(1, 0).unwrap_or(1));
+    let event_type = param(1, 1).unwrap_or(1);
+
+    let key = match final_byte {
+        b'u' => {
+            // Prefer the shifted key, if it was reported
+            match param(0, 1)
+                .or_else(|| param(0, 0))
+                .and_then(decode_key_code)
+            {
+                Some(key) => key,
+                None => return Found::None,
+            }
+        }
+        b'~' if param(0, 0) == Some(27) && params.len() == 3 => {
+            // modifyOtherKeys
+            match param(2, 0).and_then(decode_key_code) {
+                Some(key) => key,
+                None => return Found::None,
+            }
+        }
+        // The legacy encoding of these keys is handled by the key map;
+        // we only need to deal with the additional information that the
+        // kitty protocol adds, which is the event type and the modifiers
+        // that are not part of the legacy encoding.
+        _ if params.get(1).map(Vec::len).unwrap_or(0) < 2
+            && (modifiers - (Modifiers::SHIFT | Modifiers::ALT | Modifiers::CTRL)).is_empty() =>
+        {
+            return Found::None;
+        }
+        b'~' => match param(0, 0).and_then(decode_tilde_key) {
+            Some(key) => key,
+            None => return Found::None,
+        },
+        b'A' => KeyCode::UpArrow,
+        b'B' => KeyCode::DownArrow,
+        b'C' => KeyCode::RightArrow,
+        b'D' => KeyCode::LeftArrow,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P' => KeyCode::Function(1),
+        b'Q' => KeyCode::Function(2),
+        b'S' => KeyCode::Function(4),
+        _ => return Found::None,
+    };
+
+    let event = KeyEvent { key, modifiers };
+    Found::Exact(
+        len,
+        if event_type == 3 {
+            InputEvent::KeyRelease(event)
+        } else {
+            InputEvent::Key(event)
+        },
+    )
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum InputState {
     Normal,
@@ -416,6 +710,18 @@ mod windows {
             });
         }
 
+        fn decode_focus_record<F: FnMut(InputEvent)>(
+            &self,
+            event: &FOCUS_EVENT_RECORD,
+            callback: &mut F,
+        ) {
+            callback(if event.bSetFocus != 0 {
+                InputEvent::FocusGained
+            } else {
+                InputEvent::FocusLost
+            });
+        }
+
         pub fn decode_input_records<F: FnMut(InputEvent)>(
             &self,
             records: &[INPUT_RECORD],
@@ -433,6 +739,9 @@ mod windows {
                         unsafe { record.Event.WindowBufferSizeEvent() },
                         callback,
                     ),
+                    FOCUS_EVENT => {
+                        self.decode_focus_record(unsafe { record.Event.FocusEvent() }, callback)
+                    }
                     _ => {}
                 }
             }
@@ -662,6 +971,10 @@ impl InputParser {
             }),
         );
 
+        // Focus reporting
+        map.insert(b"\x1b[I", InputEvent::FocusGained);
+        map.insert(b"\x1b[O", InputEvent::FocusLost);
+
         map.insert(
             b"\x1b[200~",
             InputEvent::Key(KeyEvent {
@@ -752,6 +1065,7 @@ impl InputParser {
             (InputState::EscapeMaybeAlt, event) => {
                 // The prior ESC was not part of an ALT sequence, so emit
                 // both it and the current event
+                self.state = InputState::Normal;
                 callback(InputEvent::Key(KeyEvent {
                     key: KeyCode::Escape,
                     modifiers: Modifiers::NONE,
@@ -770,11 +1084,17 @@ impl InputParser {
                     if let Some(idx) = self.buf.find_subsequence(offset, end_paste) {
                         let pasted =
                             String::from_utf8_lossy(&self.buf.as_slice()[0..idx]).to_string();
-                        self.buf.advance(pasted.len() + end_paste.len());
+                        // Advance by the length of the raw bytes rather than
+                        // the decoded text, as they differ if the paste
+                        // contained invalid UTF-8
+                        self.buf.advance(idx + end_paste.len());
                         callback(InputEvent::Paste(pasted));
                         self.state = InputState::Normal;
                     } else {
-                        self.state = InputState::Pasting(self.buf.len() - end_paste.len());
+                        // The end marker may be split across reads, so
+                        // resume the search far enough back to find it
+                        self.state =
+                            InputState::Pasting(self.buf.len().saturating_sub(end_paste.len() - 1));
                         return;
                     }
                 }
@@ -807,6 +1127,16 @@ impl InputParser {
                             }
                             continue;
                         }
+
+                        match (decode_csi_key(self.buf.as_slice()), maybe_more) {
+                            (Found::Exact(len, event), _) => {
+                                self.buf.advance(len);
+                                callback(event);
+                                continue;
+                            }
+                            (Found::NeedData, true) => return,
+                            _ => {}
+                        }
                     }
 
                     match (self.key_map.lookup(self.buf.as_slice()), maybe_more) {
@@ -1032,4 +1362,118 @@ mod test {
             inputs
         );
     }
+
+    fn key(key: KeyCode, modifiers: Modifiers) -> InputEvent {
+        InputEvent::Key(KeyEvent { key, modifiers })
+    }
+
+    #[test]
+    fn kitty_keys() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            vec![
+                key(KeyCode::Char('a'), Modifiers::NONE),
+                key(KeyCode::Char('i'), Modifiers::CTRL),
+                key(KeyCode::Tab, Modifiers::NONE),
+                key(KeyCode::Char('A'), Modifiers::SHIFT),
+                key(KeyCode::Escape, Modifiers::SUPER | Modifiers::HYPER),
+                key(KeyCode::LeftControl, Modifiers::CTRL),
+            ],
+            p.parse_as_vec(b"\x1b[97u\x1b[105;5u\x1b[9u\x1b[97:65;2u\x1b[27;25u\x1b[57442;5u")
+        );
+    }
+
+    #[test]
+    fn kitty_event_types() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            vec![
+                key(KeyCode::Char('a'), Modifiers::NONE),
+                key(KeyCode::Char('a'), Modifiers::NONE),
+                InputEvent::KeyRelease(KeyEvent {
+                    key: KeyCode::Char('a'),
+                    modifiers: Modifiers::ALT,
+                }),
+                InputEvent::KeyRelease(KeyEvent {
+                    key: KeyCode::UpArrow,
+                    modifiers: Modifiers::NONE,
+                }),
+                key(KeyCode::Function(5), Modifiers::SUPER),
+                // The legacy encoding is still handled by the key map
+                key(KeyCode::UpArrow, Modifiers::CTRL),
+            ],
+            p.parse_as_vec(b"\x1b[97;1:1u\x1b[97;1:2u\x1b[97;3:3u\x1b[1;1:3A\x1b[15;9~\x1b[1;5A")
+        );
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            vec![
+                key(KeyCode::Enter, Modifiers::SHIFT),
+                key(KeyCode::Char('1'), Modifiers::CTRL | Modifiers::ALT),
+            ],
+            p.parse_as_vec(b"\x1b[27;2;13~\x1b[27;7;49~")
+        );
+    }
+
+    #[test]
+    fn kitty_partial() {
+        let mut p = InputParser::new();
+        let mut inputs = Vec::new();
+        p.parse(b"\x1b[105;", |evt| inputs.push(evt), true);
+        assert!(inputs.is_empty());
+        p.parse(b"5:3u", |evt| inputs.push(evt), true);
+        assert_eq!(
+            vec![InputEvent::KeyRelease(KeyEvent {
+                key: KeyCode::Char('i'),
+                modifiers: Modifiers::CTRL,
+            })],
+            inputs
+        );
+    }
+
+    #[test]
+    fn focus() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            vec![
+                InputEvent::FocusLost,
+                InputEvent::FocusGained,
+                key(KeyCode::Char('O'), Modifiers::ALT),
+            ],
+            p.parse_as_vec(b"\x1b[O\x1b[I\x1bO")
+        );
+    }
+
+    #[test]
+    fn paste_across_reads() {
+        let mut p = InputParser::new();
+        let mut inputs = Vec::new();
+        // Deliver the paste, including the markers, one byte at a time
+        for b in b"\x1b[200~hello\xe2\x9c\x93\x1b[201~a" {
+            p.parse(&[*b], |evt| inputs.push(evt), true);
+        }
+        assert_eq!(
+            vec![
+                InputEvent::Paste("hello\u{2713}".to_string()),
+                key(KeyCode::Char('a'), Modifiers::NONE),
+            ],
+            inputs
+        );
+    }
+
+    #[test]
+    fn paste_after_escape() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            vec![
+                key(KeyCode::Escape, Modifiers::NONE),
+                InputEvent::Paste("\u{fffd}!".to_string()),
+                key(KeyCode::Char('a'), Modifiers::NONE),
+            ],
+            p.parse_as_vec(b"\x1b\x1b[200~\xff!\x1b[201~a")
+        );
+    }
 }
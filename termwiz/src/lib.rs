@@ -47,7 +47,9 @@ pub mod istty;
 pub mod keymap;
 pub mod lineedit;
 mod readbuf;
+pub mod recording;
 pub mod render;
 pub mod surface;
 pub mod terminal;
+pub mod tmux_cc;
 pub mod widgets;
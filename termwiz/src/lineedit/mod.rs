@@ -190,241 +190,10 @@ impl<T: Terminal> LineEditor<T> {
         res
     }
 
-    fn resolve_action(&self, event: &InputEvent) -> Option<Action> {
-        match event {
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('C'),
-                modifiers: Modifiers::CTRL,
-            }) => Some(Action::Cancel),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Tab,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::Complete),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('D'),
-                modifiers: Modifiers::CTRL,
-            }) => Some(Action::EndOfFile),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('J'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('M'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::Enter,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::AcceptLine),
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('H'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::Backspace,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::Kill(Movement::BackwardChar(1))),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('P'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::UpArrow,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::HistoryPrevious),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('N'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::DownArrow,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::HistoryNext),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('B'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::LeftArrow,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::Move(Movement::BackwardChar(1))),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('W'),
-                modifiers: Modifiers::CTRL,
-            }) => Some(Action::Kill(Movement::BackwardWord(1))),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('b'),
-                modifiers: Modifiers::ALT,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::LeftArrow,
-                modifiers: Modifiers::ALT,
-            }) => Some(Action::Move(Movement::BackwardWord(1))),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('f'),
-                modifiers: Modifiers::ALT,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::RightArrow,
-                modifiers: Modifiers::ALT,
-            }) => Some(Action::Move(Movement::ForwardWord(1))),
-
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('A'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::Home,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::Move(Movement::StartOfLine)),
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('E'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::End,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::Move(Movement::EndOfLine)),
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('F'),
-                modifiers: Modifiers::CTRL,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::RightArrow,
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::Move(Movement::ForwardChar(1))),
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char(c),
-                modifiers: Modifiers::SHIFT,
-            })
-            | InputEvent::Key(KeyEvent {
-                key: KeyCode::Char(c),
-                modifiers: Modifiers::NONE,
-            }) => Some(Action::InsertChar(1, *c)),
-            InputEvent::Paste(text) => Some(Action::InsertText(1, text.clone())),
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('L'),
-                modifiers: Modifiers::CTRL,
-            }) => Some(Action::Repaint),
-            InputEvent::Key(KeyEvent {
-                key: KeyCode::Char('K'),
-                modifiers: Modifiers::CTRL,
-            }) => Some(Action::Kill(Movement::EndOfLine)),
-            _ => None,
-        }
-    }
-
-    /// Compute the cursor position after applying movement
-    fn eval_movement(&self, movement: Movement) -> usize {
-        match movement {
-            Movement::BackwardChar(rep) => {
-                let mut position = self.cursor;
-                for _ in 0..rep {
-                    let mut cursor = GraphemeCursor::new(position, self.line.len(), false);
-                    if let Ok(Some(pos)) = cursor.prev_boundary(&self.line, 0) {
-                        position = pos;
-                    } else {
-                        break;
-                    }
-                }
-                position
-            }
-            Movement::BackwardWord(rep) => {
-                let char_indices: Vec<(usize, char)> = self.line.char_indices().collect();
-                if char_indices.is_empty() {
-                    return self.cursor;
-                }
-                let mut char_position = char_indices
-                    .iter()
-                    .position(|(idx, _)| *idx == self.cursor)
-                    .unwrap_or(char_indices.len() - 1);
-
-                for _ in 0..rep {
-                    if char_position == 0 {
-                        break;
-                    }
-
-                    let mut found = None;
-                    for prev in (0..char_position - 1).rev() {
-                        if char_indices[prev].1.is_whitespace() {
-                            found = Some(prev + 1);
-                            break;
-                        }
-                    }
-
-                    char_position = found.unwrap_or(0);
-                }
-                char_indices[char_position].0
-            }
-            Movement::ForwardWord(rep) => {
-                let char_indices: Vec<(usize, char)> = self.line.char_indices().collect();
-                if char_indices.is_empty() {
-                    return self.cursor;
-                }
-                let mut char_position = char_indices
-                    .iter()
-                    .position(|(idx, _)| *idx == self.cursor)
-                    .unwrap_or_else(|| char_indices.len());
-
-                for _ in 0..rep {
-                    // Skip any non-whitespace characters
-                    while char_position < char_indices.len()
-                        && !char_indices[char_position].1.is_whitespace()
-                    {
-                        char_position += 1;
-                    }
-
-                    // Skip any whitespace characters
-                    while char_position < char_indices.len()
-                        && char_indices[char_position].1.is_whitespace()
-                    {
-                        char_position += 1;
-                    }
-
-                    // We are now on the start of the next word
-                }
-                char_indices
-                    .get(char_position)
-                    .map(|(i, _)| *i)
-                    .unwrap_or_else(|| self.line.len())
-            }
-            Movement::ForwardChar(rep) => {
-                let mut position = self.cursor;
-                for _ in 0..rep {
-                    let mut cursor = GraphemeCursor::new(position, self.line.len(), false);
-                    if let Ok(Some(pos)) = cursor.next_boundary(&self.line, 0) {
-                        position = pos;
-                    } else {
-                        break;
-                    }
-                }
-                position
-            }
-            Movement::StartOfLine => 0,
-            Movement::EndOfLine => {
-                let mut cursor = GraphemeCursor::new(self.line.len() - 1, self.line.len(), false);
-                if let Ok(Some(pos)) = cursor.next_boundary(&self.line, 0) {
-                    pos
-                } else {
-                    self.cursor
-                }
-            }
-        }
-    }
-
     fn kill_text(&mut self, movement: Movement) {
         self.clear_completion();
 
-        let new_cursor = self.eval_movement(movement);
+        let new_cursor = eval_movement(&self.line, self.cursor, movement);
 
         let (lower, upper) = if new_cursor < self.cursor {
             (new_cursor, self.cursor)
@@ -453,7 +222,7 @@ impl<T: Terminal> LineEditor<T> {
 
         self.render(host)?;
         while let Some(event) = self.terminal.poll_input(None)? {
-            match self.resolve_action(&event) {
+            match resolve_action(&event) {
                 Some(Action::Cancel) => return Ok(None),
                 Some(Action::AcceptLine) => break,
                 Some(Action::EndOfFile) => {
@@ -466,7 +235,7 @@ impl<T: Terminal> LineEditor<T> {
                 Some(Action::Kill(movement)) => self.kill_text(movement),
                 Some(Action::Move(movement)) => {
                     self.clear_completion();
-                    self.cursor = self.eval_movement(movement);
+                    self.cursor = eval_movement(&self.line, self.cursor, movement);
                 }
                 Some(Action::InsertChar(rep, c)) => {
                     self.clear_completion();
@@ -557,6 +326,232 @@ impl<T: Terminal> LineEditor<T> {
     }
 }
 
+/// Map an input event to the default line editing action for it
+pub(crate) fn resolve_action(event: &InputEvent) -> Option<Action> {
+    match event {
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('C'),
+            modifiers: Modifiers::CTRL,
+        }) => Some(Action::Cancel),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Tab,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Complete),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('D'),
+            modifiers: Modifiers::CTRL,
+        }) => Some(Action::EndOfFile),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('J'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('M'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::Enter,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::AcceptLine),
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('H'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::Backspace,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Kill(Movement::BackwardChar(1))),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('P'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::UpArrow,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::HistoryPrevious),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('N'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::DownArrow,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::HistoryNext),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('B'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::LeftArrow,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Move(Movement::BackwardChar(1))),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('W'),
+            modifiers: Modifiers::CTRL,
+        }) => Some(Action::Kill(Movement::BackwardWord(1))),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('b'),
+            modifiers: Modifiers::ALT,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::LeftArrow,
+            modifiers: Modifiers::ALT,
+        }) => Some(Action::Move(Movement::BackwardWord(1))),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('f'),
+            modifiers: Modifiers::ALT,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::RightArrow,
+            modifiers: Modifiers::ALT,
+        }) => Some(Action::Move(Movement::ForwardWord(1))),
+
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('A'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::Home,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Move(Movement::StartOfLine)),
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('E'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::End,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Move(Movement::EndOfLine)),
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('F'),
+            modifiers: Modifiers::CTRL,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::RightArrow,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Move(Movement::ForwardChar(1))),
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char(c),
+            modifiers: Modifiers::SHIFT,
+        })
+        | InputEvent::Key(KeyEvent {
+            key: KeyCode::Char(c),
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::InsertChar(1, *c)),
+        InputEvent::Paste(text) => Some(Action::InsertText(1, text.clone())),
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('L'),
+            modifiers: Modifiers::CTRL,
+        }) => Some(Action::Repaint),
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Char('K'),
+            modifiers: Modifiers::CTRL,
+        }) => Some(Action::Kill(Movement::EndOfLine)),
+        _ => None,
+    }
+}
+
+/// Compute the cursor position after applying movement to the
+/// insertion point `cursor`, which is a byte index into `line`
+pub(crate) fn eval_movement(line: &str, cursor: usize, movement: Movement) -> usize {
+    match movement {
+        Movement::BackwardChar(rep) => {
+            let mut position = cursor;
+            for _ in 0..rep {
+                let mut graphemes = GraphemeCursor::new(position, line.len(), false);
+                if let Ok(Some(pos)) = graphemes.prev_boundary(line, 0) {
+                    position = pos;
+                } else {
+                    break;
+                }
+            }
+            position
+        }
+        Movement::BackwardWord(rep) => {
+            let char_indices: Vec<(usize, char)> = line.char_indices().collect();
+            if char_indices.is_empty() {
+                return cursor;
+            }
+            let mut char_position = char_indices
+                .iter()
+                .position(|(idx, _)| *idx == cursor)
+                .unwrap_or(char_indices.len() - 1);
+
+            for _ in 0..rep {
+                if char_position == 0 {
+                    break;
+                }
+
+                let mut found = None;
+                for prev in (0..char_position - 1).rev() {
+                    if char_indices[prev].1.is_whitespace() {
+                        found = Some(prev + 1);
+                        break;
+                    }
+                }
+
+                char_position = found.unwrap_or(0);
+            }
+            char_indices[char_position].0
+        }
+        Movement::ForwardWord(rep) => {
+            let char_indices: Vec<(usize, char)> = line.char_indices().collect();
+            if char_indices.is_empty() {
+                return cursor;
+            }
+            let mut char_position = char_indices
+                .iter()
+                .position(|(idx, _)| *idx == cursor)
+                .unwrap_or_else(|| char_indices.len());
+
+            for _ in 0..rep {
+                // Skip any non-whitespace characters
+                while char_position < char_indices.len()
+                    && !char_indices[char_position].1.is_whitespace()
+                {
+                    char_position += 1;
+                }
+
+                // Skip any whitespace characters
+                while char_position < char_indices.len()
+                    && char_indices[char_position].1.is_whitespace()
+                {
+                    char_position += 1;
+                }
+
+                // We are now on the start of the next word
+            }
+            char_indices
+                .get(char_position)
+                .map(|(i, _)| *i)
+                .unwrap_or_else(|| line.len())
+        }
+        Movement::ForwardChar(rep) => {
+            let mut position = cursor;
+            for _ in 0..rep {
+                let mut graphemes = GraphemeCursor::new(position, line.len(), false);
+                if let Ok(Some(pos)) = graphemes.next_boundary(line, 0) {
+                    position = pos;
+                } else {
+                    break;
+                }
+            }
+            position
+        }
+        Movement::StartOfLine => 0,
+        Movement::EndOfLine => line.len(),
+    }
+}
+
 /// Create a `Terminal` with the recommended settings, and use that
 /// to create a `LineEditor` instance.
 pub fn line_editor() -> anyhow::Result<LineEditor<impl Terminal>> {
@@ -0,0 +1,641 @@
+//! Recording and replaying terminal sessions in the asciicast v2
+//! format used by asciinema.
+//! A recording consists of a header line holding a JSON object that
+//! describes the terminal, followed by one line per event, each of
+//! which is a JSON array holding the time of the event in seconds,
+//! its type and its data.
+//! See <https://github.com/asciinema/asciinema/blob/develop/doc/asciicast-v2.md>
+//! for more details.
+use crate::cell::CellAttributes;
+use crate::escape::csi::{Cursor, Edit, EraseInDisplay, EraseInLine, Sgr};
+use crate::escape::esc::{Esc, EscCode};
+use crate::escape::parser::Parser;
+use crate::escape::{Action, ControlCode, CSI};
+use crate::surface::{Change, Position, Surface};
+use anyhow::{anyhow, bail, Context};
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::*;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The version of the asciicast format that is produced and understood
+pub const VERSION: u32 = 2;
+
+/// Describes the recorded terminal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: usize,
+    pub height: usize,
+    /// The time at which the recording started, in seconds
+    /// since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Environment variables, such as `SHELL` and `TERM`, that
+    /// describe the recorded session
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Header {
+    /// Creates a header for a recording of a terminal with the
+    /// specified dimensions that starts now
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            version: VERSION,
+            width,
+            height,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+            title: None,
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+/// The type of an event in a recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventType {
+    /// Data written to the terminal by the program
+    Output,
+    /// Data sent to the program from the keyboard
+    Input,
+    /// An event type that isn't understood by this module,
+    /// such as those introduced by later revisions of the format
+    Other(String),
+}
+
+impl EventType {
+    fn code(&self) -> &str {
+        match self {
+            EventType::Output => "o",
+            EventType::Input => "i",
+            EventType::Other(code) => code,
+        }
+    }
+
+    fn from_code(code: String) -> Self {
+        match code.as_str() {
+            "o" => EventType::Output,
+            "i" => EventType::Input,
+            _ => EventType::Other(code),
+        }
+    }
+}
+
+/// An event in a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The time of the event in seconds since the start of the recording
+    pub time: f64,
+    pub event_type: EventType,
+    pub data: String,
+}
+
+impl Serialize for Event {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        (self.time, self.event_type.code(), &self.data).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (time, code, data) = <(f64, String, String)>::deserialize(deserializer)?;
+        Ok(Self {
+            time,
+            event_type: EventType::from_code(code),
+            data,
+        })
+    }
+}
+
+/// Decodes the utf8 in `partial` followed by `data`, returning the
+/// decoded text and leaving any incomplete sequence at the end in
+/// `partial` so that it can be completed by the next call.
+/// Invalid sequences are replaced by U+FFFD.
+fn take_utf8(partial: &mut Vec<u8>, data: &[u8]) -> String {
+    partial.extend_from_slice(data);
+    let mut text = String::new();
+    let mut pos = 0;
+    while pos < partial.len() {
+        match std::str::from_utf8(&partial[pos..]) {
+            Ok(s) => {
+                text.push_str(s);
+                pos = partial.len();
+            }
+            Err(err) => {
+                let valid = err.valid_up_to();
+                text.push_str(
+                    std::str::from_utf8(&partial[pos..pos + valid]).expect("valid_up_to"),
+                );
+                pos += valid;
+                match err.error_len() {
+                    Some(len) => {
+                        text.push('\u{fffd}');
+                        pos += len;
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+    partial.drain(..pos);
+    text
+}
+
+/// Writes a recording, timestamping the events relative to the
+/// time at which the recorder was created
+pub struct Recorder<W: Write> {
+    out: W,
+    start: Instant,
+    partial_output: Vec<u8>,
+    partial_input: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Writes the header to `out` and returns a recorder that
+    /// appends events to it
+    pub fn new(mut out: W, header: &Header) -> anyhow::Result<Self> {
+        serde_json::to_writer(&mut out, header)?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            start: Instant::now(),
+            partial_output: vec![],
+            partial_input: vec![],
+        })
+    }
+
+    fn elapsed(&self) -> f64 {
+        let elapsed = self.start.elapsed();
+        elapsed.as_secs() as f64 + f64::from(elapsed.subsec_micros()) / 1_000_000.0
+    }
+
+    /// Records output produced by the program.  `data` need not end
+    /// on a utf8 character boundary.
+    pub fn output(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let data = take_utf8(&mut self.partial_output, data);
+        let time = self.elapsed();
+        self.write_event(&Event {
+            time,
+            event_type: EventType::Output,
+            data,
+        })
+    }
+
+    /// Records input sent to the program.  `data` need not end
+    /// on a utf8 character boundary.
+    pub fn input(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let data = take_utf8(&mut self.partial_input, data);
+        let time = self.elapsed();
+        self.write_event(&Event {
+            time,
+            event_type: EventType::Input,
+            data,
+        })
+    }
+
+    /// Appends an event to the recording.  Events with no data are
+    /// skipped.
+    pub fn write_event(&mut self, event: &Event) -> anyhow::Result<()> {
+        if event.data.is_empty() {
+            return Ok(());
+        }
+        serde_json::to_writer(&mut self.out, event)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// A recording that has been read back
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    /// Parses a recording.  Blank lines are ignored.
+    pub fn parse<R: BufRead>(reader: R) -> anyhow::Result<Self> {
+        let mut lines = reader.lines().enumerate();
+        let header: Header = match lines.next() {
+            Some((_, line)) => serde_json::from_str(&line?).context("parsing header")?,
+            None => bail!("recording is empty"),
+        };
+        if header.version != VERSION {
+            bail!(
+                "asciicast version {} is not supported; expected version {}",
+                header.version,
+                VERSION
+            );
+        }
+
+        let mut events = vec![];
+        for (idx, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: Event = serde_json::from_str(&line)
+                .map_err(|err| anyhow!("parsing event on line {}: {}", idx + 1, err))?;
+            events.push(event);
+        }
+
+        Ok(Self { header, events })
+    }
+}
+
+/// Replays the output events of a recording into a `Surface`.
+/// The escape sequences in the output are interpreted sufficiently
+/// well to review typical shell sessions and simple full screen
+/// programs: printing, cursor movement, erasing, inserting and
+/// deleting lines and graphic rendition.  Other sequences are ignored.
+pub struct Player {
+    recording: Recording,
+    next_event: usize,
+    parser: Parser,
+    surface: Surface,
+    pen: CellAttributes,
+    saved_cursor: (usize, usize),
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        let surface = Surface::new(recording.header.width, recording.header.height);
+        Self {
+            recording,
+            next_event: 0,
+            parser: Parser::new(),
+            surface,
+            pen: CellAttributes::default(),
+            saved_cursor: (0, 0),
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.recording.header
+    }
+
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    /// Returns the time of the next output event, or `None` if
+    /// the end of the recording has been reached
+    pub fn next_event_time(&self) -> Option<f64> {
+        self.recording.events[self.next_event..]
+            .iter()
+            .find(|event| event.event_type == EventType::Output)
+            .map(|event| event.time)
+    }
+
+    /// Applies the output events up to and including `time` to the
+    /// surface.  Returns true if any events were applied.
+    pub fn advance_to(&mut self, time: f64) -> bool {
+        let mut applied = false;
+        while let Some(event) = self.recording.events.get(self.next_event) {
+            if event.time > time {
+                break;
+            }
+            self.next_event += 1;
+            if event.event_type == EventType::Output {
+                let data = event.data.clone();
+                self.apply_output(&data);
+                applied = true;
+            }
+        }
+        applied
+    }
+
+    /// Returns true once all of the events have been applied
+    pub fn is_finished(&self) -> bool {
+        self.next_event >= self.recording.events.len()
+    }
+
+    /// Interprets `data` as output written to the terminal
+    pub fn apply_output(&mut self, data: &str) {
+        let mut actions = vec![];
+        self.parser
+            .parse(data.as_bytes(), |action| actions.push(action));
+        for action in actions {
+            self.apply_action(action);
+        }
+    }
+
+    fn move_cursor(&mut self, x: Position, y: Position) {
+        self.surface.add_change(Change::CursorPosition { x, y });
+    }
+
+    fn clear_to_end_of_line(&mut self) {
+        self.surface
+            .add_change(Change::ClearToEndOfLine(self.pen.background));
+        // Clearing resets the attributes of the surface
+        self.surface
+            .add_change(Change::AllAttributes(self.pen.clone()));
+    }
+
+    fn apply_action(&mut self, action: Action) {
+        let (x, y) = self.surface.cursor_position();
+        let (width, height) = self.surface.dimensions();
+        match action {
+            Action::Print(c) => {
+                let mut buf = [0u8; 4];
+                self.surface.add_change(&*c.encode_utf8(&mut buf));
+            }
+            Action::Control(code) => match code {
+                ControlCode::LineFeed | ControlCode::VerticalTab | ControlCode::FormFeed => {
+                    self.surface.add_change("\n");
+                }
+                ControlCode::CarriageReturn => {
+                    self.surface.add_change("\r");
+                }
+                ControlCode::Backspace => {
+                    self.move_cursor(Position::Relative(-1), Position::NoChange);
+                }
+                ControlCode::HorizontalTab => {
+                    let x = ((x / 8 + 1) * 8).min(width.saturating_sub(1));
+                    self.move_cursor(Position::Absolute(x), Position::NoChange);
+                }
+                _ => {}
+            },
+            Action::CSI(CSI::Sgr(sgr)) => {
+                self.apply_sgr(sgr);
+                self.surface
+                    .add_change(Change::AllAttributes(self.pen.clone()));
+            }
+            Action::CSI(CSI::Cursor(cursor)) => self.apply_cursor(cursor),
+            Action::CSI(CSI::Edit(edit)) => match edit {
+                Edit::EraseInLine(EraseInLine::EraseToEndOfLine) => self.clear_to_end_of_line(),
+                Edit::EraseInLine(EraseInLine::EraseLine) => {
+                    self.move_cursor(Position::Absolute(0), Position::NoChange);
+                    self.clear_to_end_of_line();
+                    self.move_cursor(Position::Absolute(x), Position::NoChange);
+                }
+                Edit::EraseInLine(EraseInLine::EraseToStartOfLine) => {
+                    self.move_cursor(Position::Absolute(0), Position::NoChange);
+                    self.surface.add_change(" ".repeat(x + 1));
+                    self.move_cursor(Position::Absolute(x), Position::NoChange);
+                }
+                Edit::EraseInDisplay(EraseInDisplay::EraseToEndOfDisplay) => {
+                    self.surface
+                        .add_change(Change::ClearToEndOfScreen(self.pen.background));
+                    self.surface
+                        .add_change(Change::AllAttributes(self.pen.clone()));
+                }
+                Edit::EraseInDisplay(EraseInDisplay::EraseDisplay) => {
+                    self.surface
+                        .add_change(Change::ClearScreen(self.pen.background));
+                    self.surface
+                        .add_change(Change::AllAttributes(self.pen.clone()));
+                    self.move_cursor(Position::Absolute(x), Position::Absolute(y));
+                }
+                Edit::InsertLine(n) => {
+                    self.surface.add_change(Change::ScrollRegionDown {
+                        first_row: y,
+                        region_size: height - y,
+                        scroll_count: n as usize,
+                    });
+                    self.move_cursor(Position::Absolute(x), Position::Absolute(y));
+                }
+                Edit::DeleteLine(n) => {
+                    self.surface.add_change(Change::ScrollRegionUp {
+                        first_row: y,
+                        region_size: height - y,
+                        scroll_count: n as usize,
+                    });
+                    self.move_cursor(Position::Absolute(x), Position::Absolute(y));
+                }
+                _ => {}
+            },
+            Action::Esc(Esc::Code(code)) => match code {
+                EscCode::DecSaveCursorPosition => self.saved_cursor = (x, y),
+                EscCode::DecRestoreCursorPosition => {
+                    let (x, y) = self.saved_cursor;
+                    self.move_cursor(Position::Absolute(x), Position::Absolute(y));
+                }
+                EscCode::FullReset => {
+                    self.pen = CellAttributes::default();
+                    self.saved_cursor = (0, 0);
+                    self.surface
+                        .add_change(Change::ClearScreen(Default::default()));
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn apply_cursor(&mut self, cursor: Cursor) {
+        let (x, y) = self.surface.cursor_position();
+        match cursor {
+            Cursor::Position { line, col } => self.move_cursor(
+                Position::Absolute(col.as_zero_based() as usize),
+                Position::Absolute(line.as_zero_based() as usize),
+            ),
+            Cursor::CharacterAbsolute(col) | Cursor::CharacterPositionAbsolute(col) => self
+                .move_cursor(
+                    Position::Absolute(col.as_zero_based() as usize),
+                    Position::NoChange,
+                ),
+            Cursor::LinePositionAbsolute(line) => self.move_cursor(
+                Position::NoChange,
+                Position::Absolute((line as usize).saturating_sub(1)),
+            ),
+            Cursor::Up(n) | Cursor::LinePositionBackward(n) => {
+                self.move_cursor(Position::NoChange, Position::Relative(-(n as isize)))
+            }
+            Cursor::Down(n) | Cursor::LinePositionForward(n) => {
+                self.move_cursor(Position::NoChange, Position::Relative(n as isize))
+            }
+            Cursor::Left(n) | Cursor::CharacterPositionBackward(n) => {
+                self.move_cursor(Position::Relative(-(n as isize)), Position::NoChange)
+            }
+            Cursor::Right(n) | Cursor::CharacterPositionForward(n) => {
+                self.move_cursor(Position::Relative(n as isize), Position::NoChange)
+            }
+            Cursor::NextLine(n) => {
+                self.move_cursor(Position::Absolute(0), Position::Relative(n as isize))
+            }
+            Cursor::PrecedingLine(n) => {
+                self.move_cursor(Position::Absolute(0), Position::Relative(-(n as isize)))
+            }
+            Cursor::SaveCursor => self.saved_cursor = (x, y),
+            Cursor::RestoreCursor => {
+                let (x, y) = self.saved_cursor;
+                self.move_cursor(Position::Absolute(x), Position::Absolute(y));
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_sgr(&mut self, sgr: Sgr) {
+        match sgr {
+            Sgr::Reset => self.pen = CellAttributes::default(),
+            Sgr::Intensity(intensity) => {
+                self.pen.set_intensity(intensity);
+            }
+            Sgr::Underline(underline) => {
+                self.pen.set_underline(underline);
+            }
+            Sgr::Blink(blink) => {
+                self.pen.set_blink(blink);
+            }
+            Sgr::Italic(italic) => {
+                self.pen.set_italic(italic);
+            }
+            Sgr::Inverse(inverse) => {
+                self.pen.set_reverse(inverse);
+            }
+            Sgr::Invisible(invisible) => {
+                self.pen.set_invisible(invisible);
+            }
+            Sgr::StrikeThrough(strike) => {
+                self.pen.set_strikethrough(strike);
+            }
+            Sgr::Foreground(color) => {
+                self.pen.set_foreground(color);
+            }
+            Sgr::Background(color) => {
+                self.pen.set_background(color);
+            }
+            Sgr::UnderlineColor(color) => {
+                self.pen.set_underline_color(color);
+            }
+            Sgr::Font(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cell::Intensity;
+    use crate::color::{AnsiColor, ColorAttribute};
+
+    #[test]
+    fn utf8_across_chunks() {
+        let mut partial = vec![];
+        let heart = "\u{2764}".as_bytes();
+        assert_eq!(take_utf8(&mut partial, &heart[..1]), "");
+        assert_eq!(take_utf8(&mut partial, &heart[1..]), "\u{2764}");
+        assert!(partial.is_empty());
+        assert_eq!(take_utf8(&mut partial, b"a\xffb"), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut header = Header::new(10, 3);
+        header.timestamp = Some(1_500_000_000);
+        header.env.insert("TERM".to_string(), "xterm".to_string());
+
+        let mut recorder = Recorder::new(vec![], &header).unwrap();
+        recorder.output(b"hello\r\n\"quoted\"").unwrap();
+        recorder.input(b"q").unwrap();
+        recorder.output(b"").unwrap();
+        let data = recorder.into_inner();
+
+        let text = String::from_utf8(data.clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            r#"{"version":2,"width":10,"height":3,"timestamp":1500000000,"env":{"TERM":"xterm"}}"#
+        );
+
+        let recording = Recording::parse(data.as_slice()).unwrap();
+        assert_eq!(recording.header, header);
+        assert_eq!(recording.events.len(), 2);
+        assert_eq!(recording.events[0].event_type, EventType::Output);
+        assert_eq!(recording.events[0].data, "hello\r\n\"quoted\"");
+        assert_eq!(recording.events[1].event_type, EventType::Input);
+    }
+
+    #[test]
+    fn parse_asciinema() {
+        let cast = concat!(
+            "{\"version\": 2, \"width\": 6, \"height\": 2, \"title\": \"demo\"}\n",
+            "[0.5, \"o\", \"one\\r\\n\"]\n",
+            "\n",
+            "[1.0, \"m\", \"\"]\n",
+            "[1.5, \"o\", \"\\u001b[1;31mtwo\\u001b[0m\"]\n",
+        );
+        let recording = Recording::parse(cast.as_bytes()).unwrap();
+        assert_eq!(recording.header.title.as_ref().unwrap(), "demo");
+        assert_eq!(
+            recording.events[1].event_type,
+            EventType::Other("m".to_string())
+        );
+
+        let mut player = Player::new(recording);
+        assert_eq!(player.next_event_time(), Some(0.5));
+        assert!(!player.advance_to(0.25));
+        assert!(player.advance_to(1.0));
+        assert_eq!(
+            player.surface().screen_chars_to_string(),
+            "one   \n      \n"
+        );
+        assert_eq!(player.next_event_time(), Some(1.5));
+
+        assert!(player.advance_to(2.0));
+        assert!(player.is_finished());
+        assert_eq!(player.next_event_time(), None);
+        assert_eq!(
+            player.surface().screen_chars_to_string(),
+            "one   \ntwo   \n"
+        );
+
+        let lines = player.surface().screen_lines();
+        let attrs = lines[1].cells()[0].attrs();
+        assert_eq!(attrs.intensity(), Intensity::Bold);
+        assert_eq!(
+            attrs.foreground,
+            ColorAttribute::PaletteIndex(AnsiColor::Maroon as u8)
+        );
+    }
+
+    #[test]
+    fn cursor_movement_and_erase() {
+        let recording = Recording {
+            header: Header::new(5, 3),
+            events: vec![],
+        };
+        let mut player = Player::new(recording);
+        player.apply_output("abcde\r\nfghij\r\nklmno");
+        player.apply_output("\x1b[2;3H\x1b[K\x1b[1;2H\x1b[1K\x1b[3;1H\x1b[M");
+        assert_eq!(
+            player.surface().screen_chars_to_string(),
+            "  cde\nfg   \n     \n"
+        );
+
+        player.apply_output("\x1b7\x1b[H\x1b[2J\x1b8X\tY");
+        assert_eq!(
+            player.surface().screen_chars_to_string(),
+            "     \n     \nX   Y\n"
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let err = Recording::parse("{\"version\": 1, \"width\": 1, \"height\": 1}".as_bytes())
+            .unwrap_err();
+        assert!(err.to_string().contains("version 1"));
+    }
+}
@@ -1,4 +1,10 @@
-#[cfg(unix)]
-pub mod terminfo;
 #[cfg(windows)]
 pub mod windows;
+
+pub mod terminfo;
+
+/// Represents a terminal device that a renderer writes its output to
+pub trait RenderTty: std::io::Write {
+    /// Returns the (cols, rows) of the terminal
+    fn get_size_in_cells(&mut self) -> anyhow::Result<(usize, usize)>;
+}
@@ -1,13 +1,17 @@
 //! Rendering of Changes using terminfo
 use crate::caps::{Capabilities, ColorLevel};
-use crate::cell::{AttributeChange, Blink, CellAttributes, Intensity, Underline};
+use crate::cell::{
+    unicode_column_width, AttributeChange, Blink, CellAttributes, Intensity, Underline,
+};
 use crate::color::{ColorAttribute, ColorSpec};
-use crate::escape::csi::{Cursor, Edit, EraseInDisplay, EraseInLine, Sgr, CSI};
+use crate::escape::csi::{
+    Cursor, DecPrivateMode, DecPrivateModeCode, Edit, EraseInDisplay, EraseInLine, Mode, Sgr, CSI,
+};
 use crate::escape::osc::{ITermDimension, ITermFileData, ITermProprietary, OperatingSystemCommand};
 use crate::escape::OneBased;
 use crate::image::TextureCoordinate;
+use crate::render::RenderTty;
 use crate::surface::{Change, CursorShape, Position};
-use crate::terminal::unix::UnixTty;
 use log::error;
 use std::io::{Read, Write};
 use terminfo::{capability as cap, Capability as TermInfoCapability};
@@ -16,16 +20,96 @@ pub struct TerminfoRenderer {
     caps: Capabilities,
     current_attr: CellAttributes,
     pending_attr: Option<CellAttributes>,
-    /* TODO: we should record cursor position, shape and color here
+    /// The (x, y) position of the cursor, if we know it.  This allows
+    /// skipping redundant moves and picking the cheapest sequence
+    /// for the moves that remain.
+    cursor: Option<(usize, usize)>,
+    /// The (cols, rows) of the terminal as of the start of the
+    /// current render
+    screen_size: Option<(usize, usize)>,
+    /* TODO: we should record cursor shape and color here
      * so that we can optimize updating them on screen. */
 }
 
+/// Returns true if the two sets of attributes appear the same on
+/// screen.  Unlike `CellAttributes::attribute_bits_equal`, this ignores
+/// bits such as `wrapped` that don't affect the rendition of the cell.
+fn same_rendition(a: &CellAttributes, b: &CellAttributes) -> bool {
+    a.intensity() == b.intensity()
+        && a.underline() == b.underline()
+        && a.blink() == b.blink()
+        && a.italic() == b.italic()
+        && a.reverse() == b.reverse()
+        && a.strikethrough() == b.strikethrough()
+        && a.invisible() == b.invisible()
+}
+
+/// Returns true if `new` can be reached from `current` purely by
+/// turning on attributes, so that we don't need to emit a reset
+/// (which also resets the colors) and then build the attributes
+/// up again from scratch.
+fn only_adds_attributes(current: &CellAttributes, new: &CellAttributes) -> bool {
+    (current.intensity() == Intensity::Normal || current.intensity() == new.intensity())
+        && (current.underline() == Underline::None || current.underline() == new.underline())
+        && (current.blink() == Blink::None || current.blink() == new.blink())
+        && (!current.italic() || new.italic())
+        && (!current.reverse() || new.reverse())
+        && (!current.strikethrough() || new.strikethrough())
+        && (!current.invisible() || new.invisible())
+}
+
+/// Resolves a `Position` to an absolute coordinate given the current
+/// coordinate and the size of that dimension, if they are known.
+/// The result is clamped to the screen, as the terminal would do.
+fn resolve_position(
+    position: &Position,
+    current: Option<usize>,
+    limit: Option<usize>,
+) -> Option<usize> {
+    let value = match position {
+        Position::NoChange => current?,
+        Position::Relative(n) => (current? as isize + n).max(0) as usize,
+        Position::Absolute(n) => *n,
+        Position::EndRelative(n) => limit?.saturating_sub(1 + n),
+    };
+    Some(match limit {
+        Some(limit) if limit > 0 => value.min(limit - 1),
+        _ => value,
+    })
+}
+
+/// Accumulates the output of a render so that it is written to the
+/// terminal in a single batch
+struct BatchedOutput<'a, W: RenderTty> {
+    out: &'a mut W,
+    buf: Vec<u8>,
+}
+
+impl<'a, W: RenderTty> Write for BatchedOutput<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, W: RenderTty> RenderTty for BatchedOutput<'a, W> {
+    fn get_size_in_cells(&mut self) -> anyhow::Result<(usize, usize)> {
+        self.out.get_size_in_cells()
+    }
+}
+
 impl TerminfoRenderer {
     pub fn new(caps: Capabilities) -> Self {
         Self {
             caps,
             current_attr: CellAttributes::default(),
             pending_attr: None,
+            cursor: None,
+            screen_size: None,
         }
     }
 
@@ -48,7 +132,7 @@ impl TerminfoRenderer {
     }
 
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::cognitive_complexity))]
-    fn flush_pending_attr<W: UnixTty + Write>(&mut self, out: &mut W) -> anyhow::Result<()> {
+    fn flush_pending_attr<W: RenderTty>(&mut self, out: &mut W) -> anyhow::Result<()> {
         macro_rules! attr_on {
             ($cap:ident, $sgr:expr) => {
                 if let Some(attr) = self.get_capability::<cap::$cap>() {
@@ -66,7 +150,54 @@ impl TerminfoRenderer {
             let mut current_foreground = self.current_attr.foreground;
             let mut current_background = self.current_attr.background;
 
-            if !attr.attribute_bits_equal(&self.current_attr) {
+            if same_rendition(&attr, &self.current_attr) {
+                // Nothing to do for the attributes
+            } else if only_adds_attributes(&self.current_attr, &attr) {
+                let current = &self.current_attr;
+
+                if attr.intensity() != current.intensity() {
+                    match attr.intensity() {
+                        Intensity::Bold => attr_on!(EnterBoldMode, Sgr::Intensity(Intensity::Bold)),
+                        Intensity::Half => attr_on!(EnterDimMode, Sgr::Intensity(Intensity::Half)),
+                        Intensity::Normal => {}
+                    }
+                }
+
+                if attr.underline() != current.underline() {
+                    match attr.underline() {
+                        Underline::None => {}
+                        Underline::Single => {
+                            attr_on!(EnterUnderlineMode, Sgr::Underline(Underline::Single))
+                        }
+                        // These styles have no terminfo capability
+                        underline => attr_on!(Sgr::Underline(underline)),
+                    }
+                }
+
+                if attr.blink() != current.blink() {
+                    match attr.blink() {
+                        Blink::None => {}
+                        Blink::Slow => attr_on!(EnterBlinkMode, Sgr::Blink(Blink::Slow)),
+                        Blink::Rapid => attr_on!(Sgr::Blink(Blink::Rapid)),
+                    }
+                }
+
+                if attr.reverse() && !current.reverse() {
+                    attr_on!(EnterReverseMode, Sgr::Inverse(true));
+                }
+
+                if attr.invisible() && !current.invisible() {
+                    attr_on!(Sgr::Invisible(true));
+                }
+
+                if attr.italic() && !current.italic() {
+                    attr_on!(EnterItalicsMode, Sgr::Italic(true));
+                }
+
+                if attr.strikethrough() && !current.strikethrough() {
+                    attr_on!(Sgr::StrikeThrough(true));
+                }
+            } else {
                 // Updating the attribute bits also resets the colors.
                 current_foreground = ColorAttribute::Default;
                 current_background = ColorAttribute::Default;
@@ -107,8 +238,10 @@ impl TerminfoRenderer {
                     }
                 }
 
-                if attr.underline() == Underline::Double {
-                    attr_on!(Sgr::Underline(Underline::Double));
+                match attr.underline() {
+                    Underline::None | Underline::Single => {}
+                    // These styles have no terminfo capability
+                    underline => attr_on!(Sgr::Underline(underline)),
                 }
 
                 if attr.blink() == Blink::Rapid {
@@ -188,11 +321,11 @@ impl TerminfoRenderer {
                 }
             }
 
-            if self.caps.hyperlinks() {
-                if let Some(link) = attr.hyperlink.as_ref() {
+            if self.caps.hyperlinks() && attr.hyperlink() != self.current_attr.hyperlink() {
+                if let Some(link) = attr.hyperlink() {
                     let osc = OperatingSystemCommand::SetHyperlink(Some((**link).clone()));
                     write!(out, "{}", osc)?;
-                } else if self.current_attr.hyperlink.is_some() {
+                } else if self.current_attr.hyperlink().is_some() {
                     // Close out the old hyperlink
                     let osc = OperatingSystemCommand::SetHyperlink(None);
                     write!(out, "{}", osc)?;
@@ -205,7 +338,7 @@ impl TerminfoRenderer {
         Ok(())
     }
 
-    fn cursor_up<W: UnixTty + Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
+    fn cursor_up<W: Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
         if let Some(attr) = self.get_capability::<cap::ParmUpCursor>() {
             attr.expand().count(n).to(out.by_ref())?;
         } else {
@@ -213,7 +346,7 @@ impl TerminfoRenderer {
         }
         Ok(())
     }
-    fn cursor_down<W: UnixTty + Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
+    fn cursor_down<W: Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
         if let Some(attr) = self.get_capability::<cap::ParmDownCursor>() {
             attr.expand().count(n).to(out.by_ref())?;
         } else {
@@ -222,7 +355,7 @@ impl TerminfoRenderer {
         Ok(())
     }
 
-    fn cursor_left<W: UnixTty + Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
+    fn cursor_left<W: Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
         if let Some(attr) = self.get_capability::<cap::ParmLeftCursor>() {
             attr.expand().count(n).to(out.by_ref())?;
         } else {
@@ -230,7 +363,7 @@ impl TerminfoRenderer {
         }
         Ok(())
     }
-    fn cursor_right<W: UnixTty + Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
+    fn cursor_right<W: Write>(&mut self, n: u32, out: &mut W) -> anyhow::Result<()> {
         if let Some(attr) = self.get_capability::<cap::ParmRightCursor>() {
             attr.expand().count(n).to(out.by_ref())?;
         } else {
@@ -238,17 +371,200 @@ impl TerminfoRenderer {
         }
         Ok(())
     }
+
+    fn cursor_address<W: Write>(&mut self, x: usize, y: usize, out: &mut W) -> anyhow::Result<()> {
+        if x == 0 && y == 0 {
+            if let Some(attr) = self.get_capability::<cap::CursorHome>() {
+                attr.expand().to(out.by_ref())?;
+                return Ok(());
+            }
+        }
+
+        let x = x as u32;
+        let y = y as u32;
+        if let Some(attr) = self.get_capability::<cap::CursorAddress>() {
+            // terminfo expansion automatically converts coordinates to 1-based,
+            // so we can pass in the 0-based coordinates as-is
+            attr.expand().x(x).y(y).to(out.by_ref())?;
+        } else {
+            // We need to manually convert to 1-based as the CSI representation
+            // requires it and there's no automatic conversion.
+            write!(
+                out,
+                "{}",
+                CSI::Cursor(Cursor::Position {
+                    line: OneBased::from_zero_based(y),
+                    col: OneBased::from_zero_based(x),
+                })
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Resolves a cursor motion to an absolute position, if it can
+    /// be determined from what we know about the cursor and screen
+    fn target_position(&self, x: &Position, y: &Position) -> Option<(usize, usize)> {
+        let (cols, rows) = match self.screen_size {
+            Some((cols, rows)) => (Some(cols), Some(rows)),
+            None => (None, None),
+        };
+        let x = resolve_position(x, self.cursor.map(|(x, _)| x), cols)?;
+        let y = resolve_position(y, self.cursor.map(|(_, y)| y), rows)?;
+        Some((x, y))
+    }
+
+    /// Moves the cursor to the absolute position (x, y).  When the current
+    /// position is known, this emits whichever of the absolute or relative
+    /// motion sequences is shortest, and nothing at all if the cursor is
+    /// already in the right place.
+    fn move_cursor<W: Write>(&mut self, x: usize, y: usize, out: &mut W) -> anyhow::Result<()> {
+        let mut best = vec![];
+        self.cursor_address(x, y, &mut best)?;
+
+        if let Some((cur_x, cur_y)) = self.cursor {
+            if (cur_x, cur_y) == (x, y) {
+                return Ok(());
+            }
+
+            let mut relative = vec![];
+            if y < cur_y {
+                self.cursor_up((cur_y - y) as u32, &mut relative)?;
+            } else if y > cur_y {
+                self.cursor_down((y - cur_y) as u32, &mut relative)?;
+            }
+
+            if x == 0 && cur_x != 0 {
+                relative.push(b'\r');
+            } else if x > cur_x {
+                self.cursor_right((x - cur_x) as u32, &mut relative)?;
+            } else if x < cur_x {
+                let mut left = vec![];
+                self.cursor_left((cur_x - x) as u32, &mut left)?;
+
+                let backspaces = vec![b'\x08'; cur_x - x];
+                if backspaces.len() < left.len() {
+                    left = backspaces;
+                }
+
+                let mut from_start = vec![b'\r'];
+                self.cursor_right(x as u32, &mut from_start)?;
+                if from_start.len() < left.len() {
+                    left = from_start;
+                }
+
+                relative.extend_from_slice(&left);
+            }
+
+            if relative.len() < best.len() {
+                best = relative;
+            }
+        }
+
+        out.write_all(&best)?;
+        self.cursor = Some((x, y));
+        Ok(())
+    }
+
+    /// Handles cursor motion when we don't know where the cursor is
+    /// and so can only move it relative to its current position
+    fn move_cursor_relative<W: Write>(
+        &mut self,
+        x: &Position,
+        y: &Position,
+        out: &mut W,
+    ) -> anyhow::Result<()> {
+        match (x, y) {
+            (Position::Absolute(0), Position::NoChange)
+            | (Position::Absolute(0), Position::Relative(0)) => {
+                out.by_ref().write_all(b"\r")?;
+            }
+            (Position::NoChange, Position::Relative(n)) if *n > 0 => {
+                self.cursor_down(*n as u32, out)?;
+            }
+            (Position::NoChange, Position::Relative(n)) if *n < 0 => {
+                self.cursor_up((-*n) as u32, out)?;
+            }
+            (Position::Relative(n), Position::NoChange) if *n < 0 => {
+                self.cursor_left((-*n) as u32, out)?;
+            }
+            (Position::Relative(n), Position::NoChange) if *n > 0 => {
+                self.cursor_right(*n as u32, out)?;
+            }
+            (Position::Absolute(n), Position::NoChange) => {
+                out.by_ref().write_all(b"\r")?;
+                if *n > 0 {
+                    self.cursor_right(*n as u32, out)?;
+                }
+            }
+            _ => {
+                error!(
+                    "unhandled CursorPosition in TerminfoRenderer::render_to: {:?} {:?}",
+                    x, y
+                );
+            }
+        }
+        self.cursor = None;
+        Ok(())
+    }
+
+    /// Updates the tracked cursor position after printing `text`
+    fn advance_cursor(&mut self, text: &str) {
+        self.cursor = match (self.cursor, self.screen_size) {
+            (Some((x, y)), Some((cols, _))) if !text.chars().any(char::is_control) => {
+                let x = x + unicode_column_width(text);
+                // Where the cursor ends up once it reaches the right margin
+                // depends on the autowrap behavior of the terminal
+                if x < cols {
+                    Some((x, y))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+    }
 }
 
 impl TerminfoRenderer {
+    pub fn render_to<R: Read, W: RenderTty>(
+        &mut self,
+        changes: &[Change],
+        _read: &mut R,
+        out: &mut W,
+    ) -> anyhow::Result<()> {
+        // Something other than this renderer may have moved the cursor
+        // since the last render, so don't trust what we knew about it.
+        self.cursor = None;
+        self.screen_size = out.get_size_in_cells().ok();
+
+        let mut batch = BatchedOutput {
+            out,
+            buf: Vec::with_capacity(4096),
+        };
+        self.render_changes(changes, &mut batch)?;
+
+        let BatchedOutput { out, buf } = batch;
+        if self.caps.synchronized_output() && !buf.is_empty() {
+            // Ask the terminal to present the whole batch at once,
+            // rather than potentially showing a partial update
+            let mode = DecPrivateMode::Code(DecPrivateModeCode::SynchronizedOutput);
+            write!(out, "{}", CSI::Mode(Mode::SetDecPrivateMode(mode.clone())))?;
+            out.write_all(&buf)?;
+            write!(out, "{}", CSI::Mode(Mode::ResetDecPrivateMode(mode)))?;
+        } else {
+            out.write_all(&buf)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
     #[cfg_attr(
         feature = "cargo-clippy",
         allow(clippy::cyclomatic_complexity, clippy::cognitive_complexity)
     )]
-    pub fn render_to<R: Read, W: UnixTty + Write>(
+    fn render_changes<W: RenderTty>(
         &mut self,
         changes: &[Change],
-        _read: &mut R,
         out: &mut W,
     ) -> anyhow::Result<()> {
         macro_rules! record {
@@ -298,6 +614,8 @@ impl TerminfoRenderer {
                                 CSI::Edit(Edit::EraseInDisplay(EraseInDisplay::EraseDisplay))
                             )?;
                         }
+                        // Clearing the screen also homes the cursor
+                        self.cursor = Some((0, 0));
                     } else {
                         // We're setting the background to a specific color, so we get to
                         // paint the whole thing.
@@ -315,11 +633,12 @@ impl TerminfoRenderer {
                             )?;
                         }
 
-                        let size = out.get_size()?;
-                        let num_spaces = size.ws_col as usize * size.ws_row as usize;
+                        let (cols, rows) = out.get_size_in_cells()?;
+                        let num_spaces = cols * rows;
                         let mut buf = Vec::with_capacity(num_spaces);
                         buf.resize(num_spaces, b' ');
                         out.write_all(buf.as_slice())?;
+                        self.cursor = None;
                     }
                 }
                 Change::ClearToEndOfLine(color) => {
@@ -398,7 +717,9 @@ impl TerminfoRenderer {
                     self.attr_apply(|attr| attr.background = *col);
                 }
                 Change::Attribute(AttributeChange::Hyperlink(link)) => {
-                    self.attr_apply(|attr| attr.hyperlink = link.clone());
+                    self.attr_apply(|attr| {
+                        attr.set_hyperlink(link.clone());
+                    });
                 }
                 Change::AllAttributes(all) => {
                     self.pending_attr = Some(all.clone());
@@ -406,102 +727,26 @@ impl TerminfoRenderer {
                 Change::Text(text) => {
                     self.flush_pending_attr(out)?;
                     out.by_ref().write_all(text.as_bytes())?;
+                    self.advance_cursor(text);
                 }
                 Change::CursorPosition {
                     x: Position::Absolute(0),
                     y: Position::Relative(1),
                 } => {
+                    // Unlike moving the cursor down, a newline scrolls the
+                    // screen when the cursor is on the bottom row
                     out.by_ref().write_all(b"\r\n")?;
+                    self.cursor = match (self.cursor, self.screen_size) {
+                        (Some((_, y)), Some((_, rows))) => {
+                            Some((0, (y + 1).min(rows.saturating_sub(1))))
+                        }
+                        _ => None,
+                    };
                 }
-                Change::CursorPosition {
-                    x: Position::Absolute(0),
-                    y: Position::NoChange,
-                }
-                | Change::CursorPosition {
-                    x: Position::Absolute(0),
-                    y: Position::Relative(0),
-                } => {
-                    out.by_ref().write_all(b"\r")?;
-                }
-                Change::CursorPosition {
-                    x: Position::Absolute(0),
-                    y: Position::Absolute(0),
-                } => {
-                    if let Some(attr) = self.get_capability::<cap::CursorHome>() {
-                        attr.expand().to(out.by_ref())?;
-                    } else {
-                        write!(
-                            out,
-                            "{}",
-                            CSI::Cursor(Cursor::Position {
-                                line: OneBased::new(1),
-                                col: OneBased::new(1)
-                            })
-                        )?;
-                    }
-                }
-                Change::CursorPosition {
-                    x: Position::NoChange,
-                    y: Position::Relative(n),
-                } if *n > 0 => {
-                    self.cursor_down(*n as u32, out)?;
-                }
-                Change::CursorPosition {
-                    x: Position::NoChange,
-                    y: Position::Relative(n),
-                } if *n < 0 => {
-                    self.cursor_up(*n as u32, out)?;
-                }
-                Change::CursorPosition {
-                    x: Position::Relative(n),
-                    y: Position::NoChange,
-                } if *n < 0 => {
-                    self.cursor_left(*n as u32, out)?;
-                }
-                Change::CursorPosition {
-                    x: Position::Relative(n),
-                    y: Position::NoChange,
-                } if *n > 0 => {
-                    self.cursor_right(*n as u32, out)?;
-                }
-                Change::CursorPosition {
-                    x: Position::Absolute(n),
-                    y: Position::NoChange,
-                } => {
-                    out.by_ref().write_all(b"\r")?;
-                    if *n > 0 {
-                        self.cursor_right(*n as u32, out)?;
-                    }
-                }
-                Change::CursorPosition {
-                    x: Position::Absolute(x),
-                    y: Position::Absolute(y),
-                } => {
-                    let x = *x as u32;
-                    let y = *y as u32;
-                    if let Some(attr) = self.get_capability::<cap::CursorAddress>() {
-                        // terminfo expansion automatically converts coordinates to 1-based,
-                        // so we can pass in the 0-based coordinates as-is
-                        attr.expand().x(x).y(y).to(out.by_ref())?;
-                    } else {
-                        // We need to manually convert to 1-based as the CSI representation
-                        // requires it and there's no automatic conversion.
-                        write!(
-                            out,
-                            "{}",
-                            CSI::Cursor(Cursor::Position {
-                                line: OneBased::from_zero_based(x),
-                                col: OneBased::from_zero_based(y),
-                            })
-                        )?;
-                    }
-                }
-                Change::CursorPosition { .. } => {
-                    error!(
-                        "unhandled CursorPosition in TerminfoRenderer::render_to: {:?}",
-                        change
-                    );
-                }
+                Change::CursorPosition { x, y } => match self.target_position(x, y) {
+                    Some((x, y)) => self.move_cursor(x, y, out)?,
+                    None => self.move_cursor_relative(x, y, out)?,
+                },
                 Change::CursorColor(_color) => {
                     // TODO: this isn't spec'd by terminfo, but some terminals
                     // support it.  Add this to capabilities?
@@ -587,33 +832,44 @@ impl TerminfoRenderer {
                         }
                         self.cursor_up(image.height as u32, out)?;
                     }
+                    self.cursor = None;
                 }
                 Change::ScrollRegionUp {
                     first_row,
                     region_size,
                     scroll_count,
                 } => {
+                    // Setting the scroll region moves the cursor
+                    self.cursor = None;
                     if *region_size > 0 {
+                        let top = *first_row as u32;
+                        let bottom = (*first_row + *region_size - 1) as u32;
+                        let scroll_count = *scroll_count as u32;
                         if let Some(csr) = self.get_capability::<cap::ChangeScrollRegion>() {
-                            let top = *first_row as u32;
-                            let bottom = (*first_row + *region_size - 1) as u32;
-                            let scroll_count = *scroll_count as u32;
                             csr.expand().top(top).bottom(bottom).to(out.by_ref())?;
-                            if scroll_count > 0 {
-                                if let Some(scroll) = self.get_capability::<cap::ParmIndex>() {
-                                    scroll.expand().count(scroll_count).to(out.by_ref())?
-                                } else {
-                                    let scroll = self.get_capability::<cap::ScrollForward>();
-                                    let set_position = self.get_capability::<cap::CursorAddress>();
-                                    if let (Some(scroll), Some(set_position)) =
-                                        (scroll, set_position)
-                                    {
-                                        set_position.expand().x(0).y(bottom).to(out.by_ref())?;
-                                        for _ in 0..scroll_count {
-                                            scroll.expand().to(out.by_ref())?
-                                        }
-                                    }
+                        } else {
+                            write!(
+                                out,
+                                "{}",
+                                CSI::Cursor(Cursor::SetTopAndBottomMargins {
+                                    top: OneBased::from_zero_based(top),
+                                    bottom: OneBased::from_zero_based(bottom),
+                                })
+                            )?;
+                        }
+                        if scroll_count > 0 {
+                            if let Some(scroll) = self.get_capability::<cap::ParmIndex>() {
+                                scroll.expand().count(scroll_count).to(out.by_ref())?
+                            } else if let (Some(scroll), Some(set_position)) = (
+                                self.get_capability::<cap::ScrollForward>(),
+                                self.get_capability::<cap::CursorAddress>(),
+                            ) {
+                                set_position.expand().x(0).y(bottom).to(out.by_ref())?;
+                                for _ in 0..scroll_count {
+                                    scroll.expand().to(out.by_ref())?
                                 }
+                            } else {
+                                write!(out, "{}", CSI::Edit(Edit::ScrollUp(scroll_count)))?;
                             }
                         }
                     }
@@ -623,27 +879,36 @@ impl TerminfoRenderer {
                     region_size,
                     scroll_count,
                 } => {
+                    self.cursor = None;
                     if *region_size > 0 {
+                        let top = *first_row as u32;
+                        let bottom = (*first_row + *region_size - 1) as u32;
+                        let scroll_count = *scroll_count as u32;
                         if let Some(csr) = self.get_capability::<cap::ChangeScrollRegion>() {
-                            let top = *first_row as u32;
-                            let bottom = (*first_row + *region_size - 1) as u32;
-                            let scroll_count = *scroll_count as u32;
                             csr.expand().top(top).bottom(bottom).to(out.by_ref())?;
-                            if scroll_count > 0 {
-                                if let Some(scroll) = self.get_capability::<cap::ParmRindex>() {
-                                    scroll.expand().count(scroll_count).to(out.by_ref())?
-                                } else {
-                                    let scroll = self.get_capability::<cap::ScrollReverse>();
-                                    let set_position = self.get_capability::<cap::CursorAddress>();
-                                    if let (Some(scroll), Some(set_position)) =
-                                        (scroll, set_position)
-                                    {
-                                        set_position.expand().x(0).y(top).to(out.by_ref())?;
-                                        for _ in 0..scroll_count {
-                                            scroll.expand().to(out.by_ref())?
-                                        }
-                                    }
+                        } else {
+                            write!(
+                                out,
+                                "{}",
+                                CSI::Cursor(Cursor::SetTopAndBottomMargins {
+                                    top: OneBased::from_zero_based(top),
+                                    bottom: OneBased::from_zero_based(bottom),
+                                })
+                            )?;
+                        }
+                        if scroll_count > 0 {
+                            if let Some(scroll) = self.get_capability::<cap::ParmRindex>() {
+                                scroll.expand().count(scroll_count).to(out.by_ref())?
+                            } else if let (Some(scroll), Some(set_position)) = (
+                                self.get_capability::<cap::ScrollReverse>(),
+                                self.get_capability::<cap::CursorAddress>(),
+                            ) {
+                                set_position.expand().x(0).y(top).to(out.by_ref())?;
+                                for _ in 0..scroll_count {
+                                    scroll.expand().to(out.by_ref())?
                                 }
+                            } else {
+                                write!(out, "{}", CSI::Edit(Edit::ScrollDown(scroll_count)))?;
                             }
                         }
                     }
@@ -663,7 +928,6 @@ impl TerminfoRenderer {
         }
 
         self.flush_pending_attr(out)?;
-        out.flush()?;
         Ok(())
     }
 }
@@ -674,7 +938,7 @@ mod test {
     use crate::caps::ProbeHintsBuilder;
     use crate::color::{AnsiColor, ColorAttribute, RgbColor};
     use crate::escape::parser::Parser;
-    use crate::escape::{Action, Esc, EscCode};
+    use crate::escape::{Action, ControlCode, Esc, EscCode};
     use crate::input::InputEvent;
     use crate::terminal::unix::{Purge, SetAttributeWhen, UnixTty};
     use crate::terminal::ScreenSize;
@@ -764,6 +1028,12 @@ mod test {
         }
     }
 
+    impl RenderTty for FakeTty {
+        fn get_size_in_cells(&mut self) -> anyhow::Result<(usize, usize)> {
+            Ok((self.size.ws_col as usize, self.size.ws_row as usize))
+        }
+    }
+
     impl Read for FakeTty {
         fn read(&mut self, _buf: &mut [u8]) -> Result<usize, IoError> {
             Err(IoError::new(ErrorKind::Other, "not implemented"))
@@ -897,8 +1167,7 @@ mod test {
                 Action::Print('o'),
                 Action::Print('t'),
                 Action::Print(' '),
-                Action::Esc(Esc::Code(EscCode::AsciiCharacterSet)),
-                Action::CSI(CSI::Sgr(Sgr::Reset)),
+                // Turning on bold doesn't require a reset
                 Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
                 Action::Print('f'),
                 Action::Print('o'),
@@ -989,6 +1258,41 @@ mod test {
         assert_eq!(out.renderer.current_attr, CellAttributes::default());
     }
 
+    #[test]
+    fn scroll_region_no_terminfo() {
+        let mut out = FakeTerm::new_with_size(no_terminfo_all_enabled(), 4, 3);
+        out.render(&[
+            Change::ScrollRegionUp {
+                first_row: 0,
+                region_size: 3,
+                scroll_count: 1,
+            },
+            Change::ScrollRegionDown {
+                first_row: 1,
+                region_size: 2,
+                scroll_count: 2,
+            },
+        ])
+        .unwrap();
+
+        let result = out.parse();
+        assert_eq!(
+            result,
+            vec![
+                Action::CSI(CSI::Cursor(Cursor::SetTopAndBottomMargins {
+                    top: OneBased::new(1),
+                    bottom: OneBased::new(3),
+                })),
+                Action::CSI(CSI::Edit(Edit::ScrollUp(1))),
+                Action::CSI(CSI::Cursor(Cursor::SetTopAndBottomMargins {
+                    top: OneBased::new(2),
+                    bottom: OneBased::new(3),
+                })),
+                Action::CSI(CSI::Edit(Edit::ScrollDown(2))),
+            ]
+        );
+    }
+
     #[test]
     fn clear_screen_bce_no_terminfo() {
         let mut out = FakeTerm::new_with_size(no_terminfo_all_enabled(), 4, 3);
@@ -1047,7 +1351,7 @@ mod test {
                 Action::Print('o'),
                 Action::Print('t'),
                 Action::Print(' '),
-                Action::CSI(CSI::Sgr(Sgr::Reset)),
+                // Turning on bold doesn't require a reset
                 Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
                 Action::Print('f'),
                 Action::Print('o'),
@@ -1078,8 +1382,6 @@ mod test {
         assert_eq!(
             result,
             vec![
-                Action::Esc(Esc::Code(EscCode::AsciiCharacterSet)),
-                Action::CSI(CSI::Sgr(Sgr::Reset)),
                 // Note that the render code rearranges (red,bold) to (bold,red)
                 Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
                 Action::CSI(CSI::Sgr(Sgr::Foreground(AnsiColor::Maroon.into()))),
@@ -1114,7 +1416,6 @@ mod test {
         assert_eq!(
             result,
             vec![
-                Action::CSI(CSI::Sgr(Sgr::Reset)),
                 // Note that the render code rearranges (red,bold) to (bold,red)
                 Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
                 Action::CSI(CSI::Sgr(Sgr::Foreground(AnsiColor::Maroon.into()))),
@@ -1150,8 +1451,6 @@ mod test {
         assert_eq!(
             result,
             vec![
-                Action::Esc(Esc::Code(EscCode::AsciiCharacterSet)),
-                Action::CSI(CSI::Sgr(Sgr::Reset)),
                 // Note that the render code rearranges (red,bold) to (bold,red)
                 Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
                 Action::CSI(CSI::Sgr(Sgr::Foreground(AnsiColor::Maroon.into()))),
@@ -1219,4 +1518,154 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn cursor_motion() {
+        let mut out = FakeTerm::new(xterm_terminfo());
+        out.render(&[
+            Change::CursorPosition {
+                x: Position::Absolute(10),
+                y: Position::Absolute(5),
+            },
+            Change::Text("hello".into()),
+            // The cursor is already here, so nothing is emitted
+            Change::CursorPosition {
+                x: Position::Absolute(15),
+                y: Position::Absolute(5),
+            },
+            // Backspaces are shorter than any escape sequence
+            Change::CursorPosition {
+                x: Position::Absolute(12),
+                y: Position::NoChange,
+            },
+            Change::CursorPosition {
+                x: Position::NoChange,
+                y: Position::Relative(1),
+            },
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(7),
+            },
+        ])
+        .unwrap();
+
+        let result = out.parse();
+        assert_eq!(
+            result,
+            vec![
+                Action::CSI(CSI::Cursor(Cursor::Position {
+                    line: OneBased::new(6),
+                    col: OneBased::new(11)
+                })),
+                Action::Print('h'),
+                Action::Print('e'),
+                Action::Print('l'),
+                Action::Print('l'),
+                Action::Print('o'),
+                Action::Control(ControlCode::Backspace),
+                Action::Control(ControlCode::Backspace),
+                Action::Control(ControlCode::Backspace),
+                Action::CSI(CSI::Cursor(Cursor::Down(1))),
+                Action::CSI(CSI::Cursor(Cursor::Down(1))),
+                Action::Control(ControlCode::CarriageReturn),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_motion_no_terminfo() {
+        let mut out = FakeTerm::new(no_terminfo_all_enabled());
+        out.render(&[
+            Change::CursorPosition {
+                x: Position::Absolute(3),
+                y: Position::Absolute(2),
+            },
+            Change::Text("a".into()),
+            Change::CursorPosition {
+                x: Position::Absolute(20),
+                y: Position::Absolute(1),
+            },
+        ])
+        .unwrap();
+
+        let result = out.parse();
+        assert_eq!(
+            result,
+            vec![
+                Action::CSI(CSI::Cursor(Cursor::Position {
+                    line: OneBased::new(3),
+                    col: OneBased::new(4)
+                })),
+                Action::Print('a'),
+                Action::CSI(CSI::Cursor(Cursor::Position {
+                    line: OneBased::new(2),
+                    col: OneBased::new(21)
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn redundant_attributes() {
+        let mut out = FakeTerm::new(no_terminfo_all_enabled());
+        out.render(&[
+            Change::Attribute(AttributeChange::Intensity(Intensity::Bold)),
+            Change::Text("a".into()),
+            // Changing a non-visual attribute bit doesn't emit anything
+            Change::AllAttributes(
+                CellAttributes::default()
+                    .set_intensity(Intensity::Bold)
+                    .set_wrapped(true)
+                    .clone(),
+            ),
+            Change::Text("b".into()),
+            // Adding an attribute preserves the others without a reset
+            Change::Attribute(AttributeChange::Italic(true)),
+            Change::Text("c".into()),
+        ])
+        .unwrap();
+
+        let result = out.parse();
+        assert_eq!(
+            result,
+            vec![
+                Action::CSI(CSI::Sgr(Sgr::Intensity(Intensity::Bold))),
+                Action::Print('a'),
+                Action::Print('b'),
+                Action::CSI(CSI::Sgr(Sgr::Italic(true))),
+                Action::Print('c'),
+            ]
+        );
+    }
+
+    #[test]
+    fn synchronized_output() {
+        let data = include_bytes!("../../data/xterm-256color");
+        let caps = Capabilities::new_with_hints(
+            ProbeHintsBuilder::default()
+                .terminfo_db(Some(
+                    terminfo::Database::from_buffer(data.as_ref()).unwrap(),
+                ))
+                .synchronized_output(Some(true))
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        let mut out = FakeTerm::new(caps);
+        out.render(&[Change::Text("hi".into())]).unwrap();
+
+        // Nothing is emitted when there is nothing to render
+        out.render(&[]).unwrap();
+
+        let mode = DecPrivateMode::Code(DecPrivateModeCode::SynchronizedOutput);
+        assert_eq!(
+            out.parse(),
+            vec![
+                Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(mode.clone()))),
+                Action::Print('h'),
+                Action::Print('i'),
+                Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(mode))),
+            ]
+        );
+    }
 }
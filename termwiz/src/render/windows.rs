@@ -3,7 +3,7 @@
 use crate::caps::Capabilities;
 use crate::cell::{AttributeChange, CellAttributes, Underline};
 use crate::color::{AnsiColor, ColorAttribute};
-use crate::surface::{Change, Position};
+use crate::surface::{Change, CursorShape, Position};
 use crate::terminal::windows::{ConsoleInputHandle, ConsoleOutputHandle};
 use num;
 use std::io::{Read, Write};
@@ -254,13 +254,22 @@ impl WindowsConsoleRenderer {
                     self.current_attr.set_background(*col);
                 }
                 Change::Attribute(AttributeChange::Hyperlink(link)) => {
-                    self.current_attr.hyperlink = link.clone();
+                    self.current_attr.set_hyperlink(link.clone());
                 }
                 Change::AllAttributes(all) => {
                     self.current_attr = all.clone();
                 }
                 Change::CursorColor(_color) => {}
-                Change::CursorShape(_shape) => {}
+                Change::CursorShape(shape) => {
+                    out.flush()?;
+                    match shape {
+                        CursorShape::Hidden => out.set_cursor_info(false, 25)?,
+                        CursorShape::BlinkingBlock | CursorShape::SteadyBlock => {
+                            out.set_cursor_info(true, 100)?
+                        }
+                        _ => out.set_cursor_info(true, 25)?,
+                    }
+                }
                 Change::Image(image) => {
                     // Images are not supported, so just blank out the cells and
                     // move the cursor to the right spot
@@ -1,9 +1,10 @@
 use crate::cell::{Cell, CellAttributes};
 use crate::cellcluster::CellCluster;
 use crate::hyperlink::Rule;
-use crate::surface::Change;
+use crate::surface::{Change, SequenceNo};
 use bitflags::bitflags;
 use serde_derive::*;
+use std::borrow::Cow;
 use std::ops::Range;
 use std::sync::Arc;
 use unicode_segmentation::UnicodeSegmentation;
@@ -28,8 +29,95 @@ bitflags! {
 pub struct Line {
     bits: LineBits,
     cells: Vec<Cell>,
+    /// The sequence number at which the line was last seen to change.
+    /// This is only meaningful to whoever assigns the numbers, so it
+    /// isn't serialized.
+    #[serde(skip)]
+    seqno: SequenceNo,
+    /// When the line has been compressed, holds the compact form
+    /// of the cells and `cells` is empty.
+    compressed: Option<Box<CompressedCells>>,
 }
 
+/// A compact representation of the cells in a line, used to reduce
+/// the memory consumed by a large scrollback.  Rather than holding
+/// a `Cell` for each column, the text of the cells is concatenated
+/// together and the attributes are held as runs of cells that share
+/// the same attributes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CompressedCells {
+    /// The text of each of the cells, concatenated together
+    text: String,
+    /// The length in bytes of the text of each cell
+    text_lens: Vec<u8>,
+    /// Each entry holds the number of consecutive cells that
+    /// share the attributes
+    attrs: Vec<(usize, CellAttributes)>,
+}
+
+impl CompressedCells {
+    /// Returns None if the cells cannot be represented; this is the
+    /// case for a cell whose grapheme is unreasonably long.
+    fn new(cells: &[Cell]) -> Option<Self> {
+        let mut text = String::new();
+        let mut text_lens = Vec::with_capacity(cells.len());
+        let mut attrs: Vec<(usize, CellAttributes)> = vec![];
+
+        for cell in cells {
+            let s = cell.str();
+            if s.len() > u8::max_value() as usize {
+                return None;
+            }
+            text.push_str(s);
+            text_lens.push(s.len() as u8);
+            match attrs.last_mut() {
+                Some((count, last)) if *last == *cell.attrs() => *count += 1,
+                _ => attrs.push((1, cell.attrs().clone())),
+            }
+        }
+
+        text.shrink_to_fit();
+        attrs.shrink_to_fit();
+        Some(Self {
+            text,
+            text_lens,
+            attrs,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.text_lens.len()
+    }
+
+    fn attrs_at(&self, idx: usize) -> Option<&CellAttributes> {
+        let mut end = 0;
+        for (count, attrs) in &self.attrs {
+            end += count;
+            if idx < end {
+                return Some(attrs);
+            }
+        }
+        None
+    }
+
+    fn to_cells(&self) -> Vec<Cell> {
+        let mut cells = Vec::with_capacity(self.len());
+        let mut pos = 0;
+        let attrs = self
+            .attrs
+            .iter()
+            .flat_map(|(count, attrs)| std::iter::repeat(attrs).take(*count));
+        for (len, attrs) in self.text_lens.iter().zip(attrs) {
+            let end = pos + *len as usize;
+            cells.push(Cell::new_grapheme(&self.text[pos..end], attrs.clone()));
+            pos = end;
+        }
+        cells
+    }
+}
+
+/// The range of columns returned by `Line::cluster_range_for`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DoubleClickRange {
     Range(Range<usize>),
     RangeWithWrap(Range<usize>),
@@ -40,7 +128,12 @@ impl Line {
         let mut cells = Vec::with_capacity(width);
         cells.resize(width, Cell::default());
         let bits = LineBits::DIRTY;
-        Self { bits, cells }
+        Self {
+            bits,
+            cells,
+            seqno: 0,
+            compressed: None,
+        }
     }
 
     pub fn from_text(s: &str, attrs: &CellAttributes) -> Line {
@@ -58,9 +151,34 @@ impl Line {
         Line {
             cells,
             bits: LineBits::DIRTY,
+            seqno: 0,
+            compressed: None,
         }
     }
 
+    /// Create a line from the provided cells
+    pub fn from_cells(cells: Vec<Cell>) -> Line {
+        let mut bits = LineBits::DIRTY;
+        if cells.iter().any(|cell| cell.attrs().hyperlink().is_some()) {
+            bits |= LineBits::HAS_HYPERLINK;
+        }
+        Line {
+            cells,
+            bits,
+            seqno: 0,
+            compressed: None,
+        }
+    }
+
+    /// Consumes the line and returns its cells.  Implicit hyperlinks
+    /// are removed, as they may not be valid for the cells in a
+    /// different context.
+    pub fn into_cells(mut self) -> Vec<Cell> {
+        self.invalidate_implicit_hyperlinks();
+        self.decompress();
+        self.cells
+    }
+
     pub fn from_text_with_wrapped_last_col(s: &str, attrs: &CellAttributes) -> Line {
         let mut line = Self::from_text(s, attrs);
         line.cells
@@ -71,16 +189,82 @@ impl Line {
 
     pub fn resize_and_clear(&mut self, width: usize) {
         let blank = Cell::default();
+        self.compressed = None;
         self.cells.clear();
         self.cells.resize(width, blank);
         self.bits = LineBits::DIRTY;
     }
 
     pub fn resize(&mut self, width: usize) {
+        self.decompress();
         self.cells.resize(width, Cell::default());
         self.bits |= LineBits::DIRTY;
     }
 
+    /// Replaces the cells of the line with a compact representation
+    /// that uses significantly less memory.  This is intended for lines
+    /// that are unlikely to change, such as those in the scrollback.
+    /// The line is transparently decompressed if it is subsequently
+    /// modified.
+    pub fn compress(&mut self) {
+        if self.compressed.is_some() || self.cells.is_empty() {
+            return;
+        }
+        if let Some(compressed) = CompressedCells::new(&self.cells) {
+            self.compressed = Some(Box::new(compressed));
+            self.cells = vec![];
+        }
+    }
+
+    /// Returns true if the line has been compressed by `compress`
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.compressed.is_some()
+    }
+
+    /// Restores the cells of a line that was compressed by `compress`,
+    /// so that they can be accessed via `cells`
+    pub fn decompress(&mut self) {
+        if let Some(compressed) = self.compressed.take() {
+            self.cells = compressed.to_cells();
+        }
+    }
+
+    /// Returns a version of the line that is not compressed, so that
+    /// its cells can be accessed via `cells` and `visible_cells`.
+    /// This is cheap if the line is not compressed.
+    pub fn uncompressed(&self) -> Cow<Line> {
+        match self.compressed.as_ref() {
+            Some(compressed) => Cow::Owned(Line {
+                bits: self.bits,
+                cells: compressed.to_cells(),
+                seqno: self.seqno,
+                compressed: None,
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Returns the number of cells in the line
+    pub fn len(&self) -> usize {
+        match self.compressed.as_ref() {
+            Some(compressed) => compressed.len(),
+            None => self.cells.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the attributes of the cell at the specified index
+    pub fn cell_attrs(&self, idx: usize) -> Option<&CellAttributes> {
+        match self.compressed.as_ref() {
+            Some(compressed) => compressed.attrs_at(idx),
+            None => self.cells.get(idx).map(Cell::attrs),
+        }
+    }
+
     /// Check whether the dirty bit is set.
     /// If it is set, then something about the line has changed since
     /// the dirty bit was last cleared.
@@ -102,6 +286,27 @@ impl Line {
         self.bits &= !LineBits::DIRTY;
     }
 
+    /// If the dirty bit is set, record `seqno` as the point at which
+    /// the line last changed and clear the dirty bit.
+    /// Returns true if the line was dirty.
+    pub fn update_last_change_seqno(&mut self, seqno: SequenceNo) -> bool {
+        if self.is_dirty() {
+            self.seqno = seqno;
+            self.clear_dirty();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if the line has changed after the point described
+    /// by `seqno`; either it is dirty, or it was recorded as changing
+    /// at a later sequence number by `update_last_change_seqno`.
+    #[inline]
+    pub fn changed_since(&self, seqno: SequenceNo) -> bool {
+        self.is_dirty() || self.seqno > seqno
+    }
+
     /// If we have any cells with an implicit hyperlink, remove the hyperlink
     /// from the cell attributes but leave the remainder of the attributes alone.
     pub fn invalidate_implicit_hyperlinks(&mut self) {
@@ -116,9 +321,10 @@ impl Line {
             return;
         }
 
+        self.decompress();
         for cell in &mut self.cells {
-            let replace = match cell.attrs().hyperlink {
-                Some(ref link) if link.is_implicit() => Some(Cell::new_grapheme(
+            let replace = match cell.attrs().hyperlink() {
+                Some(link) if link.is_implicit() => Some(Cell::new_grapheme(
                     cell.str(),
                     cell.attrs().clone().set_hyperlink(None).clone(),
                 )),
@@ -148,6 +354,7 @@ impl Line {
             // Has not changed since last time we scanned
             return;
         }
+        self.decompress();
 
         // FIXME: let's build a string and a byte-to-cell map here, and
         // use this as an opportunity to rebuild HAS_HYPERLINK, skip matching
@@ -161,7 +368,7 @@ impl Line {
             // The capture range is measured in bytes but we need to translate
             // that to the char index of the column.
             for (cell_idx, (byte_idx, _char)) in line.char_indices().enumerate() {
-                if self.cells[cell_idx].attrs().hyperlink.is_some() {
+                if self.cells[cell_idx].attrs().hyperlink().is_some() {
                     // Don't replace existing links
                     continue;
                 }
@@ -186,8 +393,21 @@ impl Line {
             != LineBits::NONE
     }
 
+    /// Returns true if the last cell of the line has the wrapped
+    /// attribute, meaning that the line continues on the next row
+    pub fn last_cell_was_wrapped(&self) -> bool {
+        self.len()
+            .checked_sub(1)
+            .and_then(|idx| self.cell_attrs(idx))
+            .map(|attrs| attrs.wrapped())
+            .unwrap_or(false)
+    }
+
     /// Recompose line into the corresponding utf8 string.
     pub fn as_str(&self) -> String {
+        if self.is_compressed() {
+            return self.uncompressed().as_str();
+        }
         let mut s = String::new();
         for (_, cell) in self.visible_cells() {
             s.push_str(cell.str());
@@ -195,41 +415,77 @@ impl Line {
         s
     }
 
+    /// Computes the range of a word for a double click selection;
+    /// `is_word` is passed the text of each cell and returns true
+    /// if it is part of a word.  This is `cluster_range_for` by
+    /// another name.
     pub fn compute_double_click_range<F: Fn(&str) -> bool>(
         &self,
         click_col: usize,
         is_word: F,
     ) -> DoubleClickRange {
-        let mut lower = click_col;
-        let mut upper = click_col;
+        self.cluster_range_for(click_col, is_word)
+    }
 
-        // TODO: look back and look ahead for cells that are hidden by
-        // a preceding multi-wide cell
-        for (idx, cell) in self.cells.iter().enumerate().skip(click_col) {
-            if !is_word(cell.str()) {
-                break;
-            }
-            upper = idx + 1;
+    /// Returns the ranges of columns spanned by each maximal run of
+    /// cells whose text satisfies `predicate`.
+    /// A double-width cell is classified as a whole, and its range
+    /// includes the column that it hides.
+    pub fn cluster_boundaries<F: Fn(&str) -> bool>(&self, predicate: F) -> Vec<Range<usize>> {
+        if self.is_compressed() {
+            return self.uncompressed().cluster_boundaries(predicate);
         }
-        for (idx, cell) in self.cells.iter().enumerate().rev() {
-            if idx > click_col {
-                continue;
-            }
-            if !is_word(cell.str()) {
-                break;
+        let mut ranges: Vec<Range<usize>> = vec![];
+        let mut current: Option<Range<usize>> = None;
+        for (idx, cell) in self.visible_cells() {
+            let end = (idx + cell.width().max(1)).min(self.cells.len());
+            if predicate(cell.str()) {
+                match current.as_mut() {
+                    Some(range) => range.end = end,
+                    None => current = Some(idx..end),
+                }
+            } else if let Some(range) = current.take() {
+                ranges.push(range);
             }
-            lower = idx;
         }
+        if let Some(range) = current.take() {
+            ranges.push(range);
+        }
+        ranges
+    }
 
-        if upper > lower && self.cells[upper - 1].attrs().wrapped() {
-            DoubleClickRange::RangeWithWrap(lower..upper)
+    /// Returns the range of columns around `col` whose cells satisfy
+    /// `predicate`, as computed by `cluster_boundaries`.  If the cell
+    /// at `col` doesn't satisfy it, the range is empty and starts at
+    /// `col`.  The range is returned as `DoubleClickRange::RangeWithWrap`
+    /// if it ends in a cell that wraps onto the next line, in which
+    /// case the caller may wish to continue the range on that line.
+    pub fn cluster_range_for<F: Fn(&str) -> bool>(
+        &self,
+        col: usize,
+        predicate: F,
+    ) -> DoubleClickRange {
+        if self.is_compressed() {
+            return self.uncompressed().cluster_range_for(col, predicate);
+        }
+        let range = self
+            .cluster_boundaries(predicate)
+            .into_iter()
+            .find(|range| range.contains(&col))
+            .unwrap_or(col..col);
+
+        if range.end > range.start && self.cells[range.end - 1].attrs().wrapped() {
+            DoubleClickRange::RangeWithWrap(range)
         } else {
-            DoubleClickRange::Range(lower..upper)
+            DoubleClickRange::Range(range)
         }
     }
 
     /// Returns a substring from the line.
     pub fn columns_as_str(&self, range: Range<usize>) -> String {
+        if self.is_compressed() {
+            return self.uncompressed().columns_as_str(range);
+        }
         let mut s = String::new();
         for (n, c) in self.visible_cells() {
             if n < range.start {
@@ -249,6 +505,7 @@ impl Line {
     /// Similarly, when we assign a cell, we need to blank out those
     /// occluded successor cells.
     pub fn set_cell(&mut self, idx: usize, cell: Cell) -> &Cell {
+        self.decompress();
         let width = cell.width();
 
         // if the line isn't wide enough, pad it out with the default attributes
@@ -258,7 +515,7 @@ impl Line {
 
         self.invalidate_implicit_hyperlinks();
         self.bits |= LineBits::DIRTY;
-        if cell.attrs().hyperlink.is_some() {
+        if cell.attrs().hyperlink().is_some() {
             self.bits |= LineBits::HAS_HYPERLINK;
         }
         self.invalidate_grapheme_at_or_before(idx);
@@ -289,6 +546,7 @@ impl Line {
     }
 
     pub fn insert_cell(&mut self, x: usize, cell: Cell) {
+        self.decompress();
         self.invalidate_implicit_hyperlinks();
 
         // If we're inserting a wide cell, we should also insert the overlapped cells.
@@ -302,6 +560,7 @@ impl Line {
     }
 
     pub fn erase_cell(&mut self, x: usize) {
+        self.decompress();
         self.invalidate_implicit_hyperlinks();
         self.invalidate_grapheme_at_or_before(x);
         self.cells.remove(x);
@@ -309,6 +568,7 @@ impl Line {
     }
 
     pub fn fill_range(&mut self, cols: impl Iterator<Item = usize>, cell: &Cell) {
+        self.decompress();
         let max_col = self.cells.len();
         for x in cols {
             if x >= max_col {
@@ -327,7 +587,9 @@ impl Line {
     /// the characters that follow wide characters, the column index may
     /// skip some positions.  It is returned as a convenience to the consumer
     /// as using .enumerate() on this iterator wouldn't be as useful.
+    /// The line must not be compressed; see `uncompressed`.
     pub fn visible_cells(&self) -> impl Iterator<Item = (usize, &Cell)> {
+        debug_assert!(!self.is_compressed());
         let mut skip_width = 0;
         self.cells.iter().enumerate().filter(move |(_idx, cell)| {
             if skip_width > 0 {
@@ -341,16 +603,25 @@ impl Line {
     }
 
     pub fn cluster(&self) -> Vec<CellCluster> {
+        if self.is_compressed() {
+            return self.uncompressed().cluster();
+        }
         CellCluster::make_cluster(self.visible_cells())
     }
 
+    /// Returns the cells of the line.
+    /// The line must not be compressed; see `uncompressed`.
     pub fn cells(&self) -> &[Cell] {
+        debug_assert!(!self.is_compressed());
         &self.cells
     }
 
     /// Given a starting attribute value, produce a series of Change
     /// entries to recreate the current line
     pub fn changes(&self, start_attr: &CellAttributes) -> Vec<Change> {
+        if self.is_compressed() {
+            return self.uncompressed().changes(start_attr);
+        }
         let mut result = Vec::new();
         let mut attr = start_attr.clone();
         let mut text_run = String::new();
@@ -421,3 +692,61 @@ impl<'a> From<&'a str> for Line {
         Line::from_text(s, &CellAttributes::default())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compress_round_trip() {
+        let mut attrs = CellAttributes::default();
+        attrs.set_wrapped(true);
+
+        let mut line = Line::from_text("hello 日本 wo\u{301}rld", &CellAttributes::default());
+        line.set_cell(3, Cell::new('L', attrs.clone()));
+        let last = line.len() - 1;
+        line.set_cell(last, Cell::new('d', attrs.clone()));
+        let expect = line.clone();
+
+        line.compress();
+        assert!(line.is_compressed());
+        assert_eq!(line.len(), expect.len());
+        assert_eq!(line.as_str(), expect.as_str());
+        assert_eq!(line.cell_attrs(3), Some(&attrs));
+        assert_eq!(line.cell_attrs(4), Some(&CellAttributes::default()));
+        assert_eq!(line.cell_attrs(line.len()), None);
+        assert!(line.last_cell_was_wrapped());
+        assert_eq!(line.uncompressed().cells(), expect.cells());
+
+        line.set_cell(0, Cell::new('J', CellAttributes::default()));
+        assert!(!line.is_compressed());
+        assert_eq!(line.as_str(), "JelLo 日本 wo\u{301}rld");
+    }
+
+    #[test]
+    fn cluster_ranges() {
+        let is_word = |s: &str| s != " ";
+        let mut line = Line::from_text("ab 日本 c", &CellAttributes::default());
+        assert_eq!(line.cluster_boundaries(is_word), vec![0..2, 3..7, 8..9]);
+
+        // Clicking on the column hidden by a double-width cell selects
+        // the whole word
+        assert_eq!(
+            line.cluster_range_for(6, is_word),
+            DoubleClickRange::Range(3..7)
+        );
+        assert_eq!(
+            line.cluster_range_for(2, is_word),
+            DoubleClickRange::Range(2..2)
+        );
+
+        let mut attrs = CellAttributes::default();
+        attrs.set_wrapped(true);
+        line.set_cell(8, Cell::new('c', attrs));
+        line.compress();
+        assert_eq!(
+            line.cluster_range_for(8, is_word),
+            DoubleClickRange::RangeWithWrap(8..9)
+        );
+    }
+}
@@ -462,7 +462,9 @@ impl Surface {
             }
             Foreground(value) => self.attributes.foreground = *value,
             Background(value) => self.attributes.background = *value,
-            Hyperlink(value) => self.attributes.hyperlink = value.clone(),
+            Hyperlink(value) => {
+                self.attributes.set_hyperlink(value.clone());
+            }
         }
     }
 
@@ -783,10 +785,124 @@ impl Surface {
 
     /// Computes the change stream required to make `self` have the same
     /// screen contents as `other`.
+    /// If the contents of `other` are those of `self` shifted up or down,
+    /// as is the case when a full screen application scrolls its display,
+    /// the change stream scrolls the screen and then paints only the
+    /// lines that were exposed or otherwise changed.
     pub fn diff_screens(&self, other: &Surface) -> Vec<Change> {
+        if self.width == other.width && self.height == other.height {
+            let blank = Line::with_width(self.width);
+            if let Some(count) = self.detect_scroll(other, &blank) {
+                return self.diff_scrolled_screens(other, &blank, count);
+            }
+        }
         self.diff_region(0, 0, self.width, self.height, other, 0, 0)
     }
 
+    /// Returns the line that would be displayed at `row` after scrolling
+    /// the whole screen by `count` lines; positive values scroll up and
+    /// negative values scroll down.  Rows exposed by the scroll are blank.
+    fn scrolled_line<'a>(&'a self, blank: &'a Line, count: isize, row: usize) -> &'a Line {
+        let src = row as isize + count;
+        if src < 0 || src >= self.height as isize {
+            blank
+        } else {
+            &self.lines[src as usize]
+        }
+    }
+
+    /// Counts the lines that differ between `other` and `self` scrolled
+    /// by `count` lines, giving up once `limit` lines have been counted.
+    fn count_mismatched_lines(
+        &self,
+        other: &Surface,
+        blank: &Line,
+        count: isize,
+        limit: usize,
+    ) -> usize {
+        let mut mismatched = 0;
+        for (row, other_line) in other.lines.iter().enumerate() {
+            if self.scrolled_line(blank, count, row).cells() != other_line.cells() {
+                mismatched += 1;
+                if mismatched >= limit {
+                    break;
+                }
+            }
+        }
+        mismatched
+    }
+
+    /// Looks for a whole-screen scroll that leaves fewer lines to be
+    /// repainted than diffing the screens in place.  Returns the number
+    /// of lines to scroll, using the same sign convention as `scrolled_line`.
+    fn detect_scroll(&self, other: &Surface, blank: &Line) -> Option<isize> {
+        let mut best_cost = self.count_mismatched_lines(other, blank, 0, self.height);
+        let mut best = None;
+        if best_cost <= 1 {
+            return None;
+        }
+
+        for distance in 1..self.height as isize {
+            for &count in &[distance, -distance] {
+                // Scrolling and then restoring the scroll region costs
+                // about as much as painting a line, so account for that
+                // to avoid scrolling for no real gain.
+                let cost = self.count_mismatched_lines(other, blank, count, best_cost) + 1;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best = Some(count);
+                }
+            }
+        }
+
+        best
+    }
+
+    fn diff_scrolled_screens(&self, other: &Surface, blank: &Line, count: isize) -> Vec<Change> {
+        let mut diff_state = DiffState::default();
+
+        // The lines exposed by the scroll are filled with the current
+        // background color, so make sure that is the default.
+        diff_state
+            .changes
+            .push(Change::AllAttributes(CellAttributes::default()));
+        diff_state.attr = Some(CellAttributes::default());
+
+        let scroll_count = count.abs() as usize;
+        diff_state.changes.push(if count > 0 {
+            Change::ScrollRegionUp {
+                first_row: 0,
+                region_size: self.height,
+                scroll_count,
+            }
+        } else {
+            Change::ScrollRegionDown {
+                first_row: 0,
+                region_size: self.height,
+                scroll_count,
+            }
+        });
+        // Restore the scroll region to the full screen
+        diff_state.changes.push(Change::ScrollRegionUp {
+            first_row: 0,
+            region_size: self.height,
+            scroll_count: 0,
+        });
+
+        // The cursor position is undefined after scrolling, which
+        // is the default state of `diff_state.cursor`.
+        for (row_num, other_line) in other.lines.iter().enumerate() {
+            let line = self.scrolled_line(blank, count, row_num);
+            for ((col_num, cell), (_, other_cell)) in
+                line.visible_cells().zip(other_line.visible_cells())
+            {
+                diff_state.diff_cells(col_num, row_num, cell, other_cell);
+            }
+        }
+
+        diff_state.changes
+    }
+
     /// Draw the contents of `other` into self at the specified coordinates.
     /// The required updates are recorded as Change entries as well as stored
     /// in the screen line/cell data.
@@ -796,7 +912,11 @@ impl Surface {
     pub fn draw_from_screen(&mut self, other: &Surface, x: usize, y: usize) -> SequenceNo {
         let attrs = self.attributes.clone();
         let cursor = (self.xpos, self.ypos);
-        let changes = self.diff_region(x, y, other.width, other.height, other, 0, 0);
+        let changes = if x == 0 && y == 0 && self.dimensions() == other.dimensions() {
+            self.diff_screens(other)
+        } else {
+            self.diff_region(x, y, other.width, other.height, other, 0, 0)
+        };
         let seq = self.add_changes(changes);
         self.xpos = cursor.0;
         self.ypos = cursor.1;
@@ -1399,6 +1519,65 @@ mod test {
         }
     }
 
+    #[test]
+    fn diff_scrolled_screens() {
+        let mut s = Surface::new(3, 4);
+        s.add_change("aaa\r\nbbb\r\nccc\r\nddd");
+
+        let mut up = Surface::new(3, 4);
+        up.add_change("bbb\r\nccc\r\nddd\r\neee");
+
+        let changes = s.diff_screens(&up);
+        assert_eq!(
+            vec![
+                Change::AllAttributes(CellAttributes::default()),
+                Change::ScrollRegionUp {
+                    first_row: 0,
+                    region_size: 4,
+                    scroll_count: 1,
+                },
+                Change::ScrollRegionUp {
+                    first_row: 0,
+                    region_size: 4,
+                    scroll_count: 0,
+                },
+                Change::CursorPosition {
+                    x: Position::Absolute(0),
+                    y: Position::Absolute(3),
+                },
+                Change::Text("eee".into()),
+            ],
+            changes
+        );
+
+        let mut down = Surface::new(3, 4);
+        down.add_change("xxx\r\naaa\r\nbbb\r\nccc");
+
+        let mut scrolled = Surface::new(3, 4);
+        scrolled.add_change("aaa\r\nbbb\r\nccc\r\nddd");
+        let seq = scrolled.current_seqno();
+        scrolled.draw_from_screen(&down, 0, 0);
+        assert_eq!(
+            scrolled.screen_chars_to_string(),
+            down.screen_chars_to_string()
+        );
+        let (_seq, changes) = scrolled.get_changes(seq);
+        assert!(changes.contains(&Change::ScrollRegionDown {
+            first_row: 0,
+            region_size: 4,
+            scroll_count: 1,
+        }));
+
+        // Unrelated content is painted in place
+        let mut other = Surface::new(3, 4);
+        other.add_change("123\r\n456\r\n789\r\n0ab");
+        let changes = s.diff_screens(&other);
+        assert!(!changes.iter().any(|c| match c {
+            Change::ScrollRegionUp { .. } | Change::ScrollRegionDown { .. } => true,
+            _ => false,
+        }));
+    }
+
     #[test]
     fn draw_screens() {
         let mut s = Surface::new(4, 4);
@@ -1,9 +1,10 @@
 //! An abstraction over a terminal device
 
+use crate::caps::probe::ProbeResults;
 use crate::caps::Capabilities;
-use crate::input::InputEvent;
+use crate::input::{InputEvent, KeyboardEncoding};
 use crate::surface::Change;
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
 use num::{self, NumCast};
 use std::fmt::Display;
 use std::time::Duration;
@@ -91,6 +92,48 @@ pub trait Terminal {
     /// the terminal is set to raw mode.
     fn poll_input(&mut self, wait: Option<Duration>) -> Result<Option<InputEvent>, Error>;
 
+    /// Enable or disable reporting of changes in the input focus
+    /// as `InputEvent::FocusGained` and `InputEvent::FocusLost`.
+    /// Focus reporting is disabled when the `Terminal` is dropped.
+    fn set_focus_reporting(&mut self, enable: bool) -> Result<(), Error> {
+        if enable {
+            bail!("focus reporting is not supported by this terminal");
+        }
+        Ok(())
+    }
+
+    /// Enable or disable bracketed paste mode, in which pasted text
+    /// is reported as a single `InputEvent::Paste` rather than as a
+    /// series of key presses.  Raw mode enables bracketed paste if the
+    /// capabilities indicate that the terminal supports it.
+    /// Bracketed paste is disabled when the `Terminal` is dropped.
+    fn set_bracketed_paste(&mut self, enable: bool) -> Result<(), Error> {
+        if enable {
+            bail!("bracketed paste is not supported by this terminal");
+        }
+        Ok(())
+    }
+
+    /// Actively query the terminal for its capabilities, waiting up to
+    /// `timeout` for the responses, and refine the capabilities used for
+    /// rendering accordingly.  The terminal must be in raw mode.
+    /// Input received during the probe that isn't part of a response
+    /// is retained and returned by subsequent calls to `poll_input`.
+    fn probe_capabilities(&mut self, _timeout: Duration) -> Result<ProbeResults, Error> {
+        bail!("probing capabilities is not supported by this terminal");
+    }
+
+    /// Ask the terminal to report keys using the specified encoding.
+    /// The terminal silently ignores encodings that it doesn't support,
+    /// so the input may continue to use the legacy encoding.
+    /// The legacy encoding is restored when the `Terminal` is dropped.
+    fn set_keyboard_encoding(&mut self, encoding: KeyboardEncoding) -> Result<(), Error> {
+        if encoding != KeyboardEncoding::Legacy {
+            bail!("{:?} is not supported by this terminal", encoding);
+        }
+        Ok(())
+    }
+
     fn waker(&self) -> TerminalWaker;
 }
 
@@ -9,16 +9,18 @@ use std::mem;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use termios::{
     cfmakeraw, tcdrain, tcflush, tcsetattr, Termios, TCIFLUSH, TCIOFLUSH, TCOFLUSH, TCSADRAIN,
     TCSAFLUSH, TCSANOW,
 };
 
+use crate::caps::probe::{probe_queries, ProbeParser, ProbeResults};
 use crate::caps::Capabilities;
 use crate::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, CSI};
-use crate::input::{InputEvent, InputParser};
+use crate::input::{InputEvent, InputParser, KeyboardEncoding};
 use crate::render::terminfo::TerminfoRenderer;
+use crate::render::RenderTty;
 use crate::surface::Change;
 use crate::terminal::{cast, Blocking, ScreenSize, Terminal};
 
@@ -125,6 +127,13 @@ impl Write for TtyWriteHandle {
     }
 }
 
+impl RenderTty for TtyWriteHandle {
+    fn get_size_in_cells(&mut self) -> Result<(usize, usize), Error> {
+        let size = self.get_size()?;
+        Ok((size.ws_col as usize, size.ws_row as usize))
+    }
+}
+
 impl UnixTty for TtyWriteHandle {
     fn get_size(&mut self) -> Result<winsize, Error> {
         let mut size: winsize = unsafe { mem::zeroed() };
@@ -186,6 +195,9 @@ pub struct UnixTerminal {
     wake_pipe_write: Arc<Mutex<UnixStream>>,
     caps: Capabilities,
     in_alternate_screen: bool,
+    keyboard_encoding: KeyboardEncoding,
+    bracketed_paste: bool,
+    focus_reporting: bool,
 }
 
 impl UnixTerminal {
@@ -231,6 +243,9 @@ impl UnixTerminal {
             wake_pipe,
             wake_pipe_write: Arc::new(Mutex::new(wake_pipe_write)),
             in_alternate_screen: false,
+            keyboard_encoding: KeyboardEncoding::Legacy,
+            bracketed_paste: false,
+            focus_reporting: false,
         })
     }
 
@@ -243,6 +258,22 @@ impl UnixTerminal {
         Self::new_with(caps, &file, &file)
     }
 
+    fn set_dec_private_mode(
+        &mut self,
+        code: DecPrivateModeCode,
+        enable: bool,
+    ) -> Result<(), Error> {
+        let mode = DecPrivateMode::Code(code);
+        let mode = if enable {
+            Mode::SetDecPrivateMode(mode)
+        } else {
+            Mode::ResetDecPrivateMode(mode)
+        };
+        write!(self.write, "{}", CSI::Mode(mode))?;
+        self.write.flush()?;
+        Ok(())
+    }
+
     /// Test whether we caught delivery of SIGWINCH.
     /// If so, yield an `InputEvent` with the current size of the tty.
     fn caught_sigwinch(&mut self) -> Result<Option<InputEvent>, Error> {
@@ -302,6 +333,7 @@ impl Terminal for UnixTerminal {
 
         if self.caps.bracketed_paste() {
             decset!(BracketedPaste);
+            self.bracketed_paste = true;
         }
         if self.caps.mouse_reporting() {
             decset!(AnyEventMouse);
@@ -345,6 +377,85 @@ impl Terminal for UnixTerminal {
         Ok(())
     }
 
+    fn set_focus_reporting(&mut self, enable: bool) -> Result<(), Error> {
+        self.set_dec_private_mode(DecPrivateModeCode::FocusTracking, enable)?;
+        self.focus_reporting = enable;
+        Ok(())
+    }
+
+    fn set_bracketed_paste(&mut self, enable: bool) -> Result<(), Error> {
+        self.set_dec_private_mode(DecPrivateModeCode::BracketedPaste, enable)?;
+        self.bracketed_paste = enable;
+        Ok(())
+    }
+
+    fn probe_capabilities(&mut self, timeout: Duration) -> Result<ProbeResults, Error> {
+        write!(self.write, "{}", probe_queries())?;
+        self.write.flush()?;
+
+        let mut parser = ProbeParser::new();
+        let deadline = Instant::now() + timeout;
+        while !parser.is_complete() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            let mut pfd = [pollfd {
+                fd: self.read.fd.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            }];
+            if let Err(err) = poll(&mut pfd, Some(deadline - now)) {
+                let interrupted = err
+                    .downcast_ref::<IoError>()
+                    .map(|e| e.kind() == ErrorKind::Interrupted)
+                    .unwrap_or(false);
+                if interrupted {
+                    continue;
+                }
+                return Err(anyhow!("poll(2) error: {}", err));
+            }
+            if pfd[0].revents == 0 {
+                continue;
+            }
+
+            let mut buf = [0u8; 256];
+            match self.read.read(&mut buf) {
+                Ok(n) => {
+                    // Anything that isn't a response is regular input
+                    let unconsumed = parser.parse(&buf[0..n]);
+                    let input_queue = &mut self.input_queue;
+                    self.input_parser
+                        .parse(&unconsumed, |evt| input_queue.push_back(evt), false);
+                }
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(anyhow!("failed to read input {}", e)),
+            }
+        }
+
+        let results = parser.into_results();
+        self.caps.apply_probe_results(&results);
+        // The probe reset the graphic rendition, so a fresh renderer
+        // is in sync with the terminal
+        self.renderer = TerminfoRenderer::new(self.caps.clone());
+        Ok(results)
+    }
+
+    fn set_keyboard_encoding(&mut self, encoding: KeyboardEncoding) -> Result<(), Error> {
+        if encoding != self.keyboard_encoding {
+            write!(
+                self.write,
+                "{}{}",
+                self.keyboard_encoding.disable_sequence(),
+                encoding.enable_sequence()
+            )?;
+            self.keyboard_encoding = encoding;
+        }
+        Ok(())
+    }
+
     fn get_screen_size(&mut self) -> Result<ScreenSize, Error> {
         let size = self.write.get_size()?;
         Ok(ScreenSize {
@@ -485,13 +596,18 @@ impl Drop for UnixTerminal {
                 .unwrap();
             };
         }
-        if self.caps.bracketed_paste() {
+        if self.bracketed_paste {
             decreset!(BracketedPaste);
         }
+        if self.focus_reporting {
+            decreset!(FocusTracking);
+        }
         if self.caps.mouse_reporting() {
             decreset!(SGRMouse);
             decreset!(AnyEventMouse);
         }
+        self.set_keyboard_encoding(KeyboardEncoding::Legacy)
+            .unwrap();
         self.exit_alternate_screen().unwrap();
         self.write.flush().unwrap();
 
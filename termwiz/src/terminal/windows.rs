@@ -11,20 +11,27 @@ use std::time::Duration;
 use std::{mem, ptr};
 use winapi::shared::winerror::WAIT_TIMEOUT;
 use winapi::um::consoleapi;
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
 use winapi::um::synchapi::{CreateEventW, SetEvent, WaitForMultipleObjects};
 use winapi::um::winbase::{INFINITE, WAIT_FAILED, WAIT_OBJECT_0};
 use winapi::um::wincon::{
-    FillConsoleOutputAttribute, FillConsoleOutputCharacterW, GetConsoleScreenBufferInfo,
-    ScrollConsoleScreenBufferW, SetConsoleCursorPosition, SetConsoleScreenBufferSize,
-    SetConsoleTextAttribute, SetConsoleWindowInfo, CHAR_INFO, CONSOLE_SCREEN_BUFFER_INFO, COORD,
-    DISABLE_NEWLINE_AUTO_RETURN, ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT,
-    ENABLE_PROCESSED_INPUT, ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
-    ENABLE_WINDOW_INPUT, INPUT_RECORD, SMALL_RECT,
+    CreateConsoleScreenBuffer, FillConsoleOutputAttribute, FillConsoleOutputCharacterW,
+    GetConsoleScreenBufferInfo, ScrollConsoleScreenBufferW, SetConsoleActiveScreenBuffer,
+    SetConsoleCursorInfo, SetConsoleCursorPosition, SetConsoleScreenBufferSize,
+    SetConsoleTextAttribute, SetConsoleWindowInfo, CHAR_INFO, CONSOLE_CURSOR_INFO,
+    CONSOLE_SCREEN_BUFFER_INFO, CONSOLE_TEXTMODE_BUFFER, COORD, DISABLE_NEWLINE_AUTO_RETURN,
+    ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT,
+    ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT,
+    INPUT_RECORD, SMALL_RECT,
 };
+use winapi::um::winnt::{FILE_SHARE_READ, FILE_SHARE_WRITE, GENERIC_READ, GENERIC_WRITE};
 
 use crate::caps::Capabilities;
-use crate::input::{InputEvent, InputParser};
+use crate::escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, CSI};
+use crate::input::{InputEvent, InputParser, KeyboardEncoding};
+use crate::render::terminfo::TerminfoRenderer;
 use crate::render::windows::WindowsConsoleRenderer;
+use crate::render::RenderTty;
 use crate::surface::Change;
 use crate::terminal::{cast, ScreenSize, Terminal};
 
@@ -44,6 +51,9 @@ pub trait ConsoleOutputHandle {
     fn fill_attr(&mut self, attr: u16, x: i16, y: i16, len: u32) -> Result<u32, Error>;
     fn set_attr(&mut self, attr: u16) -> Result<(), Error>;
     fn set_cursor_position(&mut self, x: i16, y: i16) -> Result<(), Error>;
+    /// Sets the visibility of the cursor and the percentage of the
+    /// cell that it fills, which must be in the range 1-100.
+    fn set_cursor_info(&mut self, visible: bool, size: u32) -> Result<(), Error>;
     fn get_buffer_info(&mut self) -> Result<CONSOLE_SCREEN_BUFFER_INFO, Error>;
     fn set_viewport(&mut self, left: i16, top: i16, right: i16, bottom: i16) -> Result<(), Error>;
     fn scroll_region(
@@ -139,6 +149,54 @@ impl OutputHandle {
             write_buffer: Vec::with_capacity(BUF_SIZE),
         }
     }
+
+    /// Creates a new console screen buffer with the same dimensions as
+    /// the visible window of this one.  This is used to implement the
+    /// alternate screen for consoles that don't support VT sequences.
+    fn new_screen_buffer(&mut self) -> Result<Self, Error> {
+        let handle = unsafe {
+            CreateConsoleScreenBuffer(
+                GENERIC_READ | GENERIC_WRITE,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ptr::null(),
+                CONSOLE_TEXTMODE_BUFFER,
+                ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            bail!(
+                "CreateConsoleScreenBuffer failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        let buffer = Self::new(unsafe { FileDescriptor::from_raw_handle(handle as *mut _) });
+
+        // Size the buffer to match the window so that it has no scrollback
+        let info = self.get_buffer_info()?;
+        let size = COORD {
+            X: 1 + info.srWindow.Right - info.srWindow.Left,
+            Y: 1 + info.srWindow.Bottom - info.srWindow.Top,
+        };
+        let handle = buffer.handle.as_raw_handle();
+        if unsafe { SetConsoleScreenBufferSize(handle as *mut _, size) } == 0 {
+            bail!(
+                "SetConsoleScreenBufferSize failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        Ok(buffer)
+    }
+
+    /// Makes this the screen buffer that is displayed by the console
+    fn set_active(&mut self) -> Result<(), Error> {
+        if unsafe { SetConsoleActiveScreenBuffer(self.handle.as_raw_handle() as *mut _) } == 0 {
+            bail!(
+                "SetConsoleActiveScreenBuffer failed: {}",
+                IoError::last_os_error()
+            );
+        }
+        Ok(())
+    }
 }
 
 struct EventHandle {
@@ -269,6 +327,17 @@ impl ConsoleOutputHandle for OutputHandle {
         Ok(())
     }
 
+    fn set_cursor_info(&mut self, visible: bool, size: u32) -> Result<(), Error> {
+        let info = CONSOLE_CURSOR_INFO {
+            dwSize: size.max(1).min(100),
+            bVisible: if visible { 1 } else { 0 },
+        };
+        if unsafe { SetConsoleCursorInfo(self.handle.as_raw_handle() as *mut _, &info) } == 0 {
+            bail!("SetConsoleCursorInfo failed: {}", IoError::last_os_error());
+        }
+        Ok(())
+    }
+
     fn get_buffer_info(&mut self) -> Result<CONSOLE_SCREEN_BUFFER_INFO, Error> {
         let mut info: CONSOLE_SCREEN_BUFFER_INFO = unsafe { mem::zeroed() };
         let ok = unsafe {
@@ -333,7 +402,7 @@ impl ConsoleOutputHandle for OutputHandle {
                 &clip_rect,
                 COORD {
                     X: max(left, left + dx),
-                    Y: max(left, top + dy),
+                    Y: max(top, top + dy),
                 },
                 &fill,
             )
@@ -348,19 +417,54 @@ impl ConsoleOutputHandle for OutputHandle {
     }
 }
 
+impl RenderTty for OutputHandle {
+    fn get_size_in_cells(&mut self) -> Result<(usize, usize), Error> {
+        let info = self.get_buffer_info()?;
+        let cols = 1 + (info.srWindow.Right - info.srWindow.Left);
+        let rows = 1 + (info.srWindow.Bottom - info.srWindow.Top);
+        Ok((cast(cols)?, cast(rows)?))
+    }
+}
+
+/// Consoles that support virtual terminal processing are driven by
+/// emitting escape sequences, just as we do for unix terminals.
+/// Older consoles are driven by making Console API calls.
+enum Renderer {
+    Terminfo(TerminfoRenderer),
+    Windows(WindowsConsoleRenderer),
+}
+
 pub struct WindowsTerminal {
     input_handle: InputHandle,
     output_handle: OutputHandle,
+    /// When the alternate screen is active on a console that doesn't
+    /// support VT sequences, holds the screen buffer that we created
+    /// for it
+    alt_screen_buffer: Option<OutputHandle>,
+    in_alternate_screen: bool,
+    keyboard_encoding: KeyboardEncoding,
+    bracketed_paste: bool,
+    focus_reporting: bool,
     waker_handle: Arc<EventHandle>,
     saved_input_mode: u32,
     saved_output_mode: u32,
-    renderer: WindowsConsoleRenderer,
+    caps: Capabilities,
+    renderer: Renderer,
     input_parser: InputParser,
     input_queue: VecDeque<InputEvent>,
 }
 
 impl Drop for WindowsTerminal {
     fn drop(&mut self) {
+        self.set_keyboard_encoding(KeyboardEncoding::Legacy)
+            .expect("failed to restore keyboard encoding");
+        self.set_bracketed_paste(false)
+            .expect("failed to disable bracketed paste");
+        self.set_focus_reporting(false)
+            .expect("failed to disable focus reporting");
+        self.exit_alternate_screen()
+            .expect("failed to exit alternate screen");
+        self.flush().expect("failed to flush console output");
         self.input_handle
             .set_input_mode(self.saved_input_mode)
             .expect("failed to restore console input mode");
@@ -400,19 +504,36 @@ impl WindowsTerminal {
 
         let saved_input_mode = input_handle.get_input_mode()?;
         let saved_output_mode = output_handle.get_output_mode()?;
-        let renderer = WindowsConsoleRenderer::new(caps);
+        let renderer = Renderer::Windows(WindowsConsoleRenderer::new(caps.clone()));
         let input_parser = InputParser::new();
 
-        Ok(Self {
+        let mut terminal = Self {
             input_handle,
             output_handle,
+            alt_screen_buffer: None,
+            in_alternate_screen: false,
+            keyboard_encoding: KeyboardEncoding::Legacy,
+            bracketed_paste: false,
+            focus_reporting: false,
             waker_handle,
             saved_input_mode,
             saved_output_mode,
+            caps,
             renderer,
             input_parser,
             input_queue: VecDeque::new(),
-        })
+        };
+
+        // Prefer to render using escape sequences, which is faster and
+        // more capable than the Console API, but fall back to the
+        // Console API on versions of Windows that don't support them.
+        if terminal.enable_virtual_terminal_output().is_err() {
+            terminal
+                .output_handle
+                .set_output_mode(terminal.saved_output_mode)?;
+        }
+
+        Ok(terminal)
     }
 
     /// Attempt to explicitly open handles to a console device (CONIN$,
@@ -424,15 +545,62 @@ impl WindowsTerminal {
         Self::new_with(caps, read, write)
     }
 
+    /// Enables VT sequence processing for both the output and input
+    /// of the console.  Note that this causes input to be delivered as
+    /// escape sequences rather than as input records.
     pub fn enable_virtual_terminal_processing(&mut self) -> Result<(), Error> {
+        self.enable_virtual_terminal_output()?;
+
+        let mode = self.input_handle.get_input_mode()?;
+        self.input_handle
+            .set_input_mode(mode | ENABLE_VIRTUAL_TERMINAL_INPUT)?;
+        Ok(())
+    }
+
+    /// Enables VT sequence processing for the output of the console and
+    /// switches to rendering using escape sequences.  This fails if the
+    /// console doesn't support VT sequences.
+    fn enable_virtual_terminal_output(&mut self) -> Result<(), Error> {
         let mode = self.output_handle.get_output_mode()?;
         self.output_handle.set_output_mode(
             mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING | DISABLE_NEWLINE_AUTO_RETURN,
         )?;
+        if let Renderer::Windows(_) = self.renderer {
+            self.flush()?;
+            self.renderer = Renderer::Terminfo(TerminfoRenderer::new(self.caps.clone()));
+        }
+        Ok(())
+    }
 
-        let mode = self.input_handle.get_input_mode()?;
-        self.input_handle
-            .set_input_mode(mode | ENABLE_VIRTUAL_TERMINAL_INPUT)?;
+    /// Returns true if output is rendered using VT sequences
+    pub fn is_virtual_terminal_output(&self) -> bool {
+        match self.renderer {
+            Renderer::Terminfo(_) => true,
+            Renderer::Windows(_) => false,
+        }
+    }
+
+    /// Returns the screen buffer that is currently displayed
+    fn active_output(&mut self) -> &mut OutputHandle {
+        match self.alt_screen_buffer.as_mut() {
+            Some(buffer) => buffer,
+            None => &mut self.output_handle,
+        }
+    }
+
+    fn set_dec_private_mode(
+        &mut self,
+        code: DecPrivateModeCode,
+        enable: bool,
+    ) -> Result<(), Error> {
+        let mode = DecPrivateMode::Code(code);
+        let mode = if enable {
+            Mode::SetDecPrivateMode(mode)
+        } else {
+            Mode::ResetDecPrivateMode(mode)
+        };
+        write!(self.output_handle, "{}", CSI::Mode(mode))?;
+        self.output_handle.flush()?;
         Ok(())
     }
 }
@@ -464,7 +632,7 @@ impl Terminal for WindowsTerminal {
         let mode = self.input_handle.get_input_mode()?;
 
         self.input_handle.set_input_mode(
-            (mode & !(ENABLE_WINDOW_INPUT | ENABLE_WINDOW_INPUT))
+            (mode & !(ENABLE_MOUSE_INPUT | ENABLE_WINDOW_INPUT))
                 | ENABLE_ECHO_INPUT
                 | ENABLE_LINE_INPUT
                 | ENABLE_PROCESSED_INPUT,
@@ -472,25 +640,104 @@ impl Terminal for WindowsTerminal {
     }
 
     fn enter_alternate_screen(&mut self) -> Result<(), Error> {
-        // TODO: Implement using CreateConsoleScreenBuffer and
-        // SetConsoleActiveScreenBuffer.
+        if self.in_alternate_screen {
+            return Ok(());
+        }
+        if self.is_virtual_terminal_output() {
+            write!(
+                self.output_handle,
+                "{}",
+                CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::ClearAndEnableAlternateScreen
+                )))
+            )?;
+        } else {
+            self.output_handle.flush()?;
+            let mut buffer = self.output_handle.new_screen_buffer()?;
+            buffer.set_active()?;
+            self.alt_screen_buffer = Some(buffer);
+        }
+        self.in_alternate_screen = true;
         Ok(())
     }
 
     fn exit_alternate_screen(&mut self) -> Result<(), Error> {
-        // TODO: Implement using SetConsoleActiveScreenBuffer.
+        if !self.in_alternate_screen {
+            return Ok(());
+        }
+        if self.is_virtual_terminal_output() {
+            write!(
+                self.output_handle,
+                "{}",
+                CSI::Mode(Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                    DecPrivateModeCode::ClearAndEnableAlternateScreen
+                )))
+            )?;
+        } else if let Some(mut buffer) = self.alt_screen_buffer.take() {
+            buffer.flush()?;
+            self.output_handle.set_active()?;
+        }
+        self.in_alternate_screen = false;
+        Ok(())
+    }
+
+    fn set_focus_reporting(&mut self, enable: bool) -> Result<(), Error> {
+        if enable == self.focus_reporting {
+            return Ok(());
+        }
+        // The console reports focus changes as input records regardless,
+        // so we only need to ask when using VT sequences
+        if self.is_virtual_terminal_output() {
+            self.set_dec_private_mode(DecPrivateModeCode::FocusTracking, enable)?;
+        }
+        self.focus_reporting = enable;
+        Ok(())
+    }
+
+    fn set_bracketed_paste(&mut self, enable: bool) -> Result<(), Error> {
+        if enable == self.bracketed_paste {
+            return Ok(());
+        }
+        if !self.is_virtual_terminal_output() {
+            bail!("bracketed paste requires a console that supports VT sequences");
+        }
+        self.set_dec_private_mode(DecPrivateModeCode::BracketedPaste, enable)?;
+        self.bracketed_paste = enable;
+        Ok(())
+    }
+
+    fn set_keyboard_encoding(&mut self, encoding: KeyboardEncoding) -> Result<(), Error> {
+        if encoding == self.keyboard_encoding {
+            return Ok(());
+        }
+        if !self.is_virtual_terminal_output() {
+            bail!(
+                "{:?} requires a console that supports VT sequences",
+                encoding
+            );
+        }
+        write!(
+            self.output_handle,
+            "{}{}",
+            self.keyboard_encoding.disable_sequence(),
+            encoding.enable_sequence()
+        )?;
+        self.keyboard_encoding = encoding;
         Ok(())
     }
 
     fn get_screen_size(&mut self) -> Result<ScreenSize, Error> {
-        let info = self.output_handle.get_buffer_info()?;
+        let vt = self.is_virtual_terminal_output();
+        let info = self.active_output().get_buffer_info()?;
 
         // NOTE: the default console behavior is different from unix style
         // terminals wrt. handling printing in the last column position.
         // We under report the width by one to make it easier to have similar
-        // semantics to unix style terminals.
+        // semantics to unix style terminals.  That isn't necessary when
+        // the console is processing VT sequences.
+        let adjust = if vt { 1 } else { 0 };
 
-        let visible_width = 0 + (info.srWindow.Right - info.srWindow.Left);
+        let visible_width = adjust + (info.srWindow.Right - info.srWindow.Left);
         let visible_height = 1 + (info.srWindow.Bottom - info.srWindow.Top);
 
         Ok(ScreenSize {
@@ -504,12 +751,17 @@ impl Terminal for WindowsTerminal {
     fn set_screen_size(&mut self, size: ScreenSize) -> Result<(), Error> {
         // FIXME: take into account the visible window size here;
         // this probably changes the size of everything including scrollback
+        let adjust = if self.is_virtual_terminal_output() {
+            0
+        } else {
+            1
+        };
         let size = COORD {
             // See the note in get_screen_size() for info on the +1.
-            X: cast(size.cols + 1)?,
+            X: cast(size.cols + adjust)?,
             Y: cast(size.rows)?,
         };
-        let handle = self.output_handle.handle.as_raw_handle();
+        let handle = self.active_output().handle.as_raw_handle();
         if unsafe { SetConsoleScreenBufferSize(handle as *mut _, size) } != 1 {
             bail!(
                 "failed to SetConsoleScreenBufferSize: {}",
@@ -520,11 +772,21 @@ impl Terminal for WindowsTerminal {
     }
 
     fn render(&mut self, changes: &[Change]) -> Result<(), Error> {
-        self.renderer
-            .render_to(changes, &mut self.input_handle, &mut self.output_handle)
+        let output = match self.alt_screen_buffer.as_mut() {
+            Some(buffer) => buffer,
+            None => &mut self.output_handle,
+        };
+        match &mut self.renderer {
+            Renderer::Terminfo(renderer) => {
+                renderer.render_to(changes, &mut self.input_handle, output)
+            }
+            Renderer::Windows(renderer) => {
+                renderer.render_to(changes, &mut self.input_handle, output)
+            }
+        }
     }
     fn flush(&mut self) -> Result<(), Error> {
-        self.output_handle
+        self.active_output()
             .flush()
             .map_err(|e| anyhow!("flush failed: {}", e))
     }
@@ -568,9 +830,36 @@ impl Terminal for WindowsTerminal {
 
             let records = self.input_handle.read_console_input(pending)?;
 
-            let input_queue = &mut self.input_queue;
+            let mut events = vec![];
             self.input_parser
-                .decode_input_records(&records, &mut |evt| input_queue.push_back(evt));
+                .decode_input_records(&records, &mut |evt| events.push(evt));
+
+            // Input records report positions and sizes in terms of the
+            // screen buffer, which may be larger than the visible window
+            // when there is scrollback, so translate them to be relative
+            // to the window.
+            let window = self.active_output().get_buffer_info()?.srWindow;
+            for event in events {
+                let event = match event {
+                    InputEvent::Resized { .. } => {
+                        let size = self.get_screen_size()?;
+                        InputEvent::Resized {
+                            cols: size.cols,
+                            rows: size.rows,
+                        }
+                    }
+                    InputEvent::Mouse(mut mouse) => {
+                        mouse.x = mouse.x.saturating_sub(window.Left as u16);
+                        mouse.y = mouse.y.saturating_sub(window.Top as u16);
+                        InputEvent::Mouse(mouse)
+                    }
+                    InputEvent::FocusGained | InputEvent::FocusLost if !self.focus_reporting => {
+                        continue;
+                    }
+                    event => event,
+                };
+                self.input_queue.push_back(event);
+            }
         }
     }
 
@@ -0,0 +1,568 @@
+//! Parses the output of tmux when it is running in control mode,
+//! which is enabled by starting tmux with `-CC`.
+//! In control mode tmux reports changes to its state, along with the
+//! output of its panes, as notifications that are sent one per line.
+//! The output of the commands that are sent to tmux is bracketed
+//! by `%begin` and `%end` (or `%error`) lines.
+//! See <https://github.com/tmux/tmux/wiki/Control-Mode> for more details.
+use anyhow::{anyhow, bail};
+
+pub type TmuxWindowId = u64;
+pub type TmuxPaneId = u64;
+pub type TmuxSessionId = u64;
+
+/// A notification received from tmux
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The output of a command.  The commands are numbered by
+    /// tmux in the order in which it receives them.
+    /// If `error` is true then the command failed and `output`
+    /// holds the error message.
+    CommandResponse {
+        number: u64,
+        output: String,
+        error: bool,
+    },
+    /// Output produced by the program running in a pane
+    Output {
+        pane: TmuxPaneId,
+        text: Vec<u8>,
+    },
+    WindowAdd {
+        window: TmuxWindowId,
+    },
+    WindowClose {
+        window: TmuxWindowId,
+    },
+    WindowRenamed {
+        window: TmuxWindowId,
+        name: String,
+    },
+    /// The arrangement of the panes in a window has changed.
+    /// `layout` can be parsed with `WindowLayout::parse`.
+    LayoutChange {
+        window: TmuxWindowId,
+        layout: String,
+    },
+    WindowPaneChanged {
+        window: TmuxWindowId,
+        pane: TmuxPaneId,
+    },
+    /// The client is now attached to a different session
+    SessionChanged {
+        session: TmuxSessionId,
+        name: String,
+    },
+    SessionRenamed {
+        name: String,
+    },
+    SessionWindowChanged {
+        session: TmuxSessionId,
+        window: TmuxWindowId,
+    },
+    /// A session was created or destroyed
+    SessionsChanged,
+    /// The client has detached from tmux, or tmux has exited
+    Exit {
+        reason: Option<String>,
+    },
+}
+
+/// Accumulates the output from tmux and parses it into events
+#[derive(Debug, Default)]
+pub struct Parser {
+    line: Vec<u8>,
+    /// The number and output lines of the command response
+    /// that is being accumulated
+    response: Option<(u64, Vec<String>)>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a single byte to the parser, returning an event
+    /// if the byte completes a notification
+    pub fn advance_byte(&mut self, byte: u8) -> Option<Event> {
+        if byte != b'\n' {
+            self.line.push(byte);
+            return None;
+        }
+        let mut line = std::mem::take(&mut self.line);
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        self.parse_line(&line)
+    }
+
+    /// Feeds a sequence of bytes to the parser and returns the
+    /// events for the notifications that were completed
+    pub fn advance_bytes(&mut self, bytes: &[u8]) -> Vec<Event> {
+        bytes
+            .iter()
+            .filter_map(|&byte| self.advance_byte(byte))
+            .collect()
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> Option<Event> {
+        if let Some((number, mut output)) = self.response.take() {
+            // The response is terminated only by an %end or %error
+            // that carries the same command number as the %begin;
+            // anything else, even if it starts with `%`, is output
+            let text = String::from_utf8_lossy(line);
+            let mut words = text.split(' ');
+            let guard = words.next();
+            if (guard == Some("%end") || guard == Some("%error"))
+                && words.nth(1).and_then(|n| n.parse().ok()) == Some(number)
+            {
+                return Some(Event::CommandResponse {
+                    number,
+                    output: output.join("\n"),
+                    error: guard == Some("%error"),
+                });
+            }
+            output.push(text.into_owned());
+            self.response = Some((number, output));
+            return None;
+        }
+
+        if line.starts_with(b"%output ") {
+            return parse_output(&line[8..]);
+        }
+
+        let text = String::from_utf8_lossy(line);
+        let mut words = text.splitn(2, ' ');
+        let command = words.next().unwrap_or("");
+        let args = words.next().unwrap_or("");
+        match parse_notification(command, args) {
+            Ok(Some(Notification::Begin(number))) => {
+                self.response = Some((number, vec![]));
+                None
+            }
+            Ok(Some(Notification::Event(event))) => Some(event),
+            Ok(None) => None,
+            Err(_) => None,
+        }
+    }
+}
+
+enum Notification {
+    Begin(u64),
+    Event(Event),
+}
+
+/// Returns the text following the first word of `args`
+fn after_first_word(args: &str) -> &str {
+    match args.find(' ') {
+        Some(idx) => &args[idx + 1..],
+        None => "",
+    }
+}
+
+/// Parses an id such as `@1` (window), `%1` (pane) or `$1` (session)
+fn parse_id(word: Option<&str>, sigil: char) -> anyhow::Result<u64> {
+    let word = word.ok_or_else(|| anyhow!("missing {} id", sigil))?;
+    if !word.starts_with(sigil) {
+        bail!("expected {} id but got {}", sigil, word);
+    }
+    Ok(word[1..].parse()?)
+}
+
+fn parse_notification(command: &str, args: &str) -> anyhow::Result<Option<Notification>> {
+    let mut words = args.split(' ');
+    let event = match command {
+        "%begin" => {
+            let number = words
+                .nth(1)
+                .ok_or_else(|| anyhow!("missing command number"))?
+                .parse()?;
+            return Ok(Some(Notification::Begin(number)));
+        }
+        "%window-add" => Event::WindowAdd {
+            window: parse_id(words.next(), '@')?,
+        },
+        "%window-close" => Event::WindowClose {
+            window: parse_id(words.next(), '@')?,
+        },
+        "%window-renamed" => {
+            let window = parse_id(words.next(), '@')?;
+            let name = after_first_word(args).to_string();
+            Event::WindowRenamed { window, name }
+        }
+        "%layout-change" => {
+            let window = parse_id(words.next(), '@')?;
+            let layout = words
+                .next()
+                .ok_or_else(|| anyhow!("missing layout"))?
+                .to_string();
+            Event::LayoutChange { window, layout }
+        }
+        "%window-pane-changed" => Event::WindowPaneChanged {
+            window: parse_id(words.next(), '@')?,
+            pane: parse_id(words.next(), '%')?,
+        },
+        "%session-changed" => {
+            let session = parse_id(words.next(), '$')?;
+            let name = after_first_word(args).to_string();
+            Event::SessionChanged { session, name }
+        }
+        "%session-renamed" => {
+            // Newer versions of tmux include the session id
+            let name = if args.starts_with('$') {
+                after_first_word(args)
+            } else {
+                args
+            };
+            Event::SessionRenamed {
+                name: name.to_string(),
+            }
+        }
+        "%session-window-changed" => Event::SessionWindowChanged {
+            session: parse_id(words.next(), '$')?,
+            window: parse_id(words.next(), '@')?,
+        },
+        "%sessions-changed" => Event::SessionsChanged,
+        "%exit" => Event::Exit {
+            reason: if args.is_empty() {
+                None
+            } else {
+                Some(args.to_string())
+            },
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(Notification::Event(event)))
+}
+
+/// Parses the arguments of an `%output` notification.
+/// tmux escapes the bytes that are less than space, along with
+/// the backslash itself, as a backslash followed by three octal digits.
+fn parse_output(args: &[u8]) -> Option<Event> {
+    let space = args.iter().position(|&b| b == b' ').unwrap_or(args.len());
+    let pane = parse_id(std::str::from_utf8(&args[..space]).ok(), '%').ok()?;
+    let value = args.get(space + 1..).unwrap_or(&[]);
+
+    let mut text = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        if value[i] == b'\\' && i + 3 < value.len() {
+            let digits = &value[i + 1..i + 4];
+            if digits.iter().all(|b| (b'0'..=b'7').contains(b)) {
+                text.push(
+                    digits
+                        .iter()
+                        .fold(0u8, |acc, b| acc.wrapping_mul(8) + (b - b'0')),
+                );
+                i += 4;
+                continue;
+            }
+        }
+        text.push(value[i]);
+        i += 1;
+    }
+
+    Some(Event::Output { pane, text })
+}
+
+/// The size and position of a node in a `WindowLayout`,
+/// measured in cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutGeometry {
+    pub cols: usize,
+    pub rows: usize,
+    pub left: usize,
+    pub top: usize,
+}
+
+/// Describes the arrangement of the panes in a tmux window, as
+/// reported by `%layout-change` and the `#{window_layout}` format.
+/// A layout looks like `b25d,160x48,0,0{80x48,0,0,1,79x48,81,0,2}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowLayout {
+    Pane {
+        pane: TmuxPaneId,
+        geometry: LayoutGeometry,
+    },
+    /// Children arranged side by side
+    LeftRight {
+        geometry: LayoutGeometry,
+        children: Vec<WindowLayout>,
+    },
+    /// Children arranged one above the other
+    TopBottom {
+        geometry: LayoutGeometry,
+        children: Vec<WindowLayout>,
+    },
+}
+
+impl WindowLayout {
+    pub fn parse(layout: &str) -> anyhow::Result<Self> {
+        // Skip the checksum
+        let layout = match layout.find(',') {
+            Some(4) => &layout[5..],
+            _ => bail!("invalid layout {}", layout),
+        };
+        let mut parser = LayoutParser {
+            text: layout.as_bytes(),
+            pos: 0,
+        };
+        let result = parser.parse_node()?;
+        if parser.pos != parser.text.len() {
+            bail!("trailing data in layout {}", layout);
+        }
+        Ok(result)
+    }
+
+    pub fn geometry(&self) -> &LayoutGeometry {
+        match self {
+            WindowLayout::Pane { geometry, .. }
+            | WindowLayout::LeftRight { geometry, .. }
+            | WindowLayout::TopBottom { geometry, .. } => geometry,
+        }
+    }
+
+    /// Returns the ids of the panes in the layout, ordered
+    /// left to right and top to bottom
+    pub fn pane_ids(&self) -> Vec<TmuxPaneId> {
+        let mut ids = vec![];
+        self.collect_pane_ids(&mut ids);
+        ids
+    }
+
+    fn collect_pane_ids(&self, ids: &mut Vec<TmuxPaneId>) {
+        match self {
+            WindowLayout::Pane { pane, .. } => ids.push(*pane),
+            WindowLayout::LeftRight { children, .. } | WindowLayout::TopBottom { children, .. } => {
+                for child in children {
+                    child.collect_pane_ids(ids);
+                }
+            }
+        }
+    }
+}
+
+struct LayoutParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> LayoutParser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, byte: u8) -> anyhow::Result<()> {
+        match self.peek() {
+            Some(b) if b == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => bail!(
+                "expected '{}' at offset {} in layout",
+                byte as char,
+                self.pos
+            ),
+        }
+    }
+
+    fn number(&mut self) -> anyhow::Result<usize> {
+        let start = self.pos;
+        while self.peek().map(|b| b.is_ascii_digit()).unwrap_or(false) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            bail!("expected a number at offset {} in layout", start);
+        }
+        Ok(std::str::from_utf8(&self.text[start..self.pos])?.parse()?)
+    }
+
+    fn parse_node(&mut self) -> anyhow::Result<WindowLayout> {
+        let cols = self.number()?;
+        self.expect(b'x')?;
+        let rows = self.number()?;
+        self.expect(b',')?;
+        let left = self.number()?;
+        self.expect(b',')?;
+        let top = self.number()?;
+        let geometry = LayoutGeometry {
+            cols,
+            rows,
+            left,
+            top,
+        };
+
+        match self.peek() {
+            Some(b'{') => Ok(WindowLayout::LeftRight {
+                geometry,
+                children: self.parse_children(b'}')?,
+            }),
+            Some(b'[') => Ok(WindowLayout::TopBottom {
+                geometry,
+                children: self.parse_children(b']')?,
+            }),
+            _ => {
+                self.expect(b',')?;
+                let pane = self.number()? as TmuxPaneId;
+                Ok(WindowLayout::Pane { pane, geometry })
+            }
+        }
+    }
+
+    fn parse_children(&mut self, close: u8) -> anyhow::Result<Vec<WindowLayout>> {
+        // Skip the opening bracket
+        self.pos += 1;
+        let mut children = vec![self.parse_node()?];
+        while self.peek() == Some(b',') {
+            self.pos += 1;
+            children.push(self.parse_node()?);
+        }
+        self.expect(close)?;
+        Ok(children)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn notifications() {
+        let mut parser = Parser::new();
+        let events = parser.advance_bytes(
+            b"%window-add @1\n\
+              %window-renamed @1 my window\r\n\
+              %layout-change @1 b25d,80x24,0,0,3 b25d,80x24,0,0,3 *\n\
+              %session-changed $2 main\n\
+              %session-renamed $2 other\n\
+              %window-pane-changed @1 %3\n\
+              %unlinked-window-add @7\n\
+              %exit\n",
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event::WindowAdd { window: 1 },
+                Event::WindowRenamed {
+                    window: 1,
+                    name: "my window".to_string()
+                },
+                Event::LayoutChange {
+                    window: 1,
+                    layout: "b25d,80x24,0,0,3".to_string()
+                },
+                Event::SessionChanged {
+                    session: 2,
+                    name: "main".to_string()
+                },
+                Event::SessionRenamed {
+                    name: "other".to_string()
+                },
+                Event::WindowPaneChanged { window: 1, pane: 3 },
+                Event::Exit { reason: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn output() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.advance_bytes(b"%output %1 hello\\015\\012\\134x\\01\n"),
+            vec![Event::Output {
+                pane: 1,
+                text: b"hello\r\n\\x\\01".to_vec()
+            }]
+        );
+
+        // Split across calls
+        assert_eq!(parser.advance_bytes(b"%output %12 \xe2\x94"), vec![]);
+        assert_eq!(
+            parser.advance_bytes(b"\x80\n"),
+            vec![Event::Output {
+                pane: 12,
+                text: "\u{2500}".as_bytes().to_vec()
+            }]
+        );
+    }
+
+    #[test]
+    fn command_response() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.advance_bytes(
+                b"%begin 1578920019 258 1\n\
+                  @1 b25d,80x24,0,0,3 zsh\n\
+                  %end 1578920019 257 1\n\
+                  %end 1578920019 258 1\n\
+                  %begin 1578920019 259 1\n\
+                  unknown command: foo\n\
+                  %error 1578920019 259 1\n"
+            ),
+            vec![
+                Event::CommandResponse {
+                    number: 258,
+                    output: "@1 b25d,80x24,0,0,3 zsh\n%end 1578920019 257 1".to_string(),
+                    error: false,
+                },
+                Event::CommandResponse {
+                    number: 259,
+                    output: "unknown command: foo".to_string(),
+                    error: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn layout() {
+        let geometry = |cols, rows, left, top| LayoutGeometry {
+            cols,
+            rows,
+            left,
+            top,
+        };
+
+        assert_eq!(
+            WindowLayout::parse("b25d,80x24,0,0,3").unwrap(),
+            WindowLayout::Pane {
+                pane: 3,
+                geometry: geometry(80, 24, 0, 0)
+            }
+        );
+
+        let layout = WindowLayout::parse(
+            "e5b3,160x48,0,0{80x48,0,0,1,79x48,81,0[79x24,81,0,2,79x23,81,25,4]}",
+        )
+        .unwrap();
+        assert_eq!(
+            layout,
+            WindowLayout::LeftRight {
+                geometry: geometry(160, 48, 0, 0),
+                children: vec![
+                    WindowLayout::Pane {
+                        pane: 1,
+                        geometry: geometry(80, 48, 0, 0)
+                    },
+                    WindowLayout::TopBottom {
+                        geometry: geometry(79, 48, 81, 0),
+                        children: vec![
+                            WindowLayout::Pane {
+                                pane: 2,
+                                geometry: geometry(79, 24, 81, 0)
+                            },
+                            WindowLayout::Pane {
+                                pane: 4,
+                                geometry: geometry(79, 23, 81, 25)
+                            },
+                        ]
+                    },
+                ]
+            }
+        );
+        assert_eq!(layout.pane_ids(), vec![1, 2, 4]);
+
+        assert!(WindowLayout::parse("80x24,0,0,3").is_err());
+        assert!(WindowLayout::parse("b25d,80x24,0,0{80x24,0,0,1").is_err());
+    }
+}
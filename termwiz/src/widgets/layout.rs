@@ -2,21 +2,18 @@
 //! The parameters are similar to those that you may have encountered
 //! in HTML, but do not fully recreate the layout model.
 use anyhow::{anyhow, Error};
-use cassowary::strength::{REQUIRED, STRONG, WEAK};
+use cassowary::strength::{MEDIUM, REQUIRED, STRONG, WEAK};
 use cassowary::WeightedRelation::*;
 use cassowary::{AddConstraintError, Expression, Solver, SuggestValueError, Variable};
 use std::collections::HashMap;
 
 use crate::widgets::{Rect, WidgetId};
 
-/// Expands to an Expression holding the value of the variable,
-/// or if there is no variable, a constant with the specified
-/// value.
+/// Expands to the expression, or if there is no expression,
+/// a constant with the specified value.
 /// Equivalent to Option::unwrap_or().
-fn unwrap_variable_or(var: Option<Variable>, value: f64) -> Expression {
-    // The `v + 0.0` portion "converts" the variable to an Expression.
-    var.map(|v| v + 0.0)
-        .unwrap_or_else(|| Expression::from_constant(value))
+fn unwrap_expression_or(expr: Option<Expression>, value: f64) -> Expression {
+    expr.unwrap_or_else(|| Expression::from_constant(value))
 }
 
 /// Specify whether a width or a height has a preferred fixed size
@@ -28,6 +25,12 @@ pub enum DimensionSpec {
     Fixed(u16),
     /// Occupy a percentage of the space in the parent container
     Percentage(u8),
+    /// Share the space left over by the other children of the parent
+    /// container with its other flexible children, in proportion to
+    /// the weight.  This only applies along the direction in which
+    /// the children of the parent are laid out; in the other direction
+    /// the widget fills the parent container.
+    Flex(u16),
 }
 
 impl Default for DimensionSpec {
@@ -93,6 +96,28 @@ impl Default for HorizontalAlignment {
     }
 }
 
+/// Specifies the number of cells between the edges of a widget
+/// and its children
+#[derive(Clone, Default, Copy, Debug, PartialEq, Eq)]
+pub struct Padding {
+    pub left: u16,
+    pub top: u16,
+    pub right: u16,
+    pub bottom: u16,
+}
+
+impl Padding {
+    /// Padding of the same size on every edge
+    pub fn uniform(size: u16) -> Self {
+        Self {
+            left: size,
+            top: size,
+            right: size,
+            bottom: size,
+        }
+    }
+}
+
 /// Specifies the size constraints for a widget
 #[derive(Clone, Default, Copy, Debug, PartialEq, Eq)]
 pub struct Constraints {
@@ -101,6 +126,7 @@ pub struct Constraints {
     pub valign: VerticalAlignment,
     pub halign: HorizontalAlignment,
     pub child_orientation: ChildOrientation,
+    pub padding: Padding,
 }
 
 impl Constraints {
@@ -144,6 +170,52 @@ impl Constraints {
         self
     }
 
+    pub fn set_flex_width(&mut self, weight: u16) -> &mut Self {
+        self.width = Dimension {
+            spec: DimensionSpec::Flex(weight),
+            ..Default::default()
+        };
+        self
+    }
+
+    pub fn set_flex_height(&mut self, weight: u16) -> &mut Self {
+        self.height = Dimension {
+            spec: DimensionSpec::Flex(weight),
+            ..Default::default()
+        };
+        self
+    }
+
+    pub fn set_min_width(&mut self, width: u16) -> &mut Self {
+        self.width.minimum = Some(width);
+        self
+    }
+
+    pub fn set_max_width(&mut self, width: u16) -> &mut Self {
+        self.width.maximum = Some(width);
+        self
+    }
+
+    pub fn set_min_height(&mut self, height: u16) -> &mut Self {
+        self.height.minimum = Some(height);
+        self
+    }
+
+    pub fn set_max_height(&mut self, height: u16) -> &mut Self {
+        self.height.maximum = Some(height);
+        self
+    }
+
+    pub fn set_child_orientation(&mut self, orientation: ChildOrientation) -> &mut Self {
+        self.child_orientation = orientation;
+        self
+    }
+
+    pub fn set_padding(&mut self, padding: Padding) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
     pub fn set_valign(&mut self, valign: VerticalAlignment) -> &mut Self {
         self.valign = valign;
         self
@@ -155,12 +227,20 @@ impl Constraints {
     }
 }
 
-/// Holds state used to compute the layout of a tree of widgets
+/// Holds state used to compute the layout of a tree of widgets.
+/// The state can be retained and used to compute the layout again,
+/// for example when the screen is resized.  The solver is only
+/// rebuilt when the widgets or their constraints have changed;
+/// otherwise only the screen dimensions are updated, which allows
+/// the solver to incrementally adjust the existing solution.
 pub struct LayoutState {
     solver: Solver,
     screen_width: Variable,
     screen_height: Variable,
     widget_states: HashMap<WidgetId, WidgetState>,
+    /// The root of the widget tree that the constraints in the solver
+    /// were built for, or None if they need to be rebuilt
+    solved_root: Option<WidgetId>,
 }
 
 /// Each `WidgetId` has a `WidgetState` associated with it.
@@ -203,6 +283,17 @@ impl Default for LayoutState {
 impl LayoutState {
     /// Create a new `LayoutState`
     pub fn new() -> Self {
+        let (solver, screen_width, screen_height) = Self::new_solver();
+        Self {
+            solver,
+            screen_width,
+            screen_height,
+            widget_states: HashMap::new(),
+            solved_root: None,
+        }
+    }
+
+    fn new_solver() -> (Solver, Variable, Variable) {
         let mut solver = Solver::new();
         let screen_width = Variable::new();
         let screen_height = Variable::new();
@@ -212,21 +303,23 @@ impl LayoutState {
         solver
             .add_edit_variable(screen_height, STRONG)
             .expect("failed to add screen_height to solver");
-        Self {
-            solver,
-            screen_width,
-            screen_height,
-            widget_states: HashMap::new(),
-        }
+        (solver, screen_width, screen_height)
     }
 
-    /// Creates a WidgetState entry for a widget.
+    /// Creates a WidgetState entry for a widget, or updates the
+    /// existing entry if the constraints or children have changed.
     pub fn add_widget(
         &mut self,
         widget: WidgetId,
         constraints: &Constraints,
         children: &[WidgetId],
     ) {
+        if let Some(state) = self.widget_states.get(&widget) {
+            if state.constraints == *constraints && state.children == children {
+                return;
+            }
+        }
+
         let state = WidgetState {
             left: Variable::new(),
             top: Variable::new(),
@@ -236,6 +329,7 @@ impl LayoutState {
             children: children.to_vec(),
         };
         self.widget_states.insert(widget, state);
+        self.solved_root = None;
     }
 
     /// Assign the screen dimensions, compute constraints, solve
@@ -247,6 +341,14 @@ impl LayoutState {
         screen_height: usize,
         root_widget: WidgetId,
     ) -> Result<Vec<LaidOutWidget>, Error> {
+        let rebuild = self.solved_root != Some(root_widget);
+        if rebuild {
+            let (solver, width, height) = Self::new_solver();
+            self.solver = solver;
+            self.screen_width = width;
+            self.screen_height = height;
+        }
+
         self.solver
             .suggest_value(self.screen_width, screen_width as f64)
             .map_err(suggesterr)?;
@@ -254,9 +356,12 @@ impl LayoutState {
             .suggest_value(self.screen_height, screen_height as f64)
             .map_err(suggesterr)?;
 
-        let width = self.screen_width;
-        let height = self.screen_height;
-        self.update_widget_constraint(root_widget, width, height, None, None)?;
+        if rebuild {
+            let width = self.screen_width + 0.0;
+            let height = self.screen_height + 0.0;
+            self.update_widget_constraint(root_widget, width, height, None, None, None)?;
+            self.solved_root = Some(root_widget);
+        }
 
         // The updates are in an unspecified order, and the coordinates are in
         // the screen absolute coordinate space, rather than the parent-relative
@@ -306,13 +411,18 @@ impl LayoutState {
         Ok(())
     }
 
+    /// Adds the constraints for a widget and its children to the solver.
+    /// The parent_* parameters describe the area within the parent
+    /// that the widget is laid out in, and `parent_orientation` the
+    /// direction in which the parent lays out its children.
     fn update_widget_constraint(
         &mut self,
         widget: WidgetId,
-        parent_width: Variable,
-        parent_height: Variable,
-        parent_left: Option<Variable>,
-        parent_top: Option<Variable>,
+        parent_width: Expression,
+        parent_height: Expression,
+        parent_left: Option<Expression>,
+        parent_top: Option<Expression>,
+        parent_orientation: Option<ChildOrientation>,
     ) -> Result<WidgetState, Error> {
         let state = self
             .widget_states
@@ -322,13 +432,15 @@ impl LayoutState {
 
         let is_root_widget = parent_left.is_none();
 
-        let parent_left = unwrap_variable_or(parent_left, 0.0);
-        let parent_top = unwrap_variable_or(parent_top, 0.0);
+        let parent_left = unwrap_expression_or(parent_left, 0.0);
+        let parent_top = unwrap_expression_or(parent_top, 0.0);
 
         // First, we should fit inside the parent container
         self.solver
             .add_constraint(
-                (state.left + state.width) | LE(REQUIRED) | (parent_left.clone() + parent_width),
+                (state.left + state.width)
+                    | LE(REQUIRED)
+                    | (parent_left.clone() + parent_width.clone()),
             )
             .map_err(adderr)?;
         self.solver
@@ -337,7 +449,9 @@ impl LayoutState {
 
         self.solver
             .add_constraint(
-                (state.top + state.height) | LE(REQUIRED) | (parent_top.clone() + parent_height),
+                (state.top + state.height)
+                    | LE(REQUIRED)
+                    | (parent_top.clone() + parent_height.clone()),
             )
             .map_err(adderr)?;
         self.solver
@@ -354,11 +468,13 @@ impl LayoutState {
                     .map_err(adderr)?,
                 HorizontalAlignment::Right => self
                     .solver
-                    .add_constraint(state.left | EQ(STRONG) | (parent_width - state.width))
+                    .add_constraint(state.left | EQ(STRONG) | (parent_width.clone() - state.width))
                     .map_err(adderr)?,
                 HorizontalAlignment::Center => self
                     .solver
-                    .add_constraint(state.left | EQ(STRONG) | ((parent_width - state.width) / 2.0))
+                    .add_constraint(
+                        state.left | EQ(STRONG) | ((parent_width.clone() - state.width) / 2.0),
+                    )
                     .map_err(adderr)?,
             }
 
@@ -369,11 +485,13 @@ impl LayoutState {
                     .map_err(adderr)?,
                 VerticalAlignment::Bottom => self
                     .solver
-                    .add_constraint(state.top | EQ(STRONG) | (parent_height - state.height))
+                    .add_constraint(state.top | EQ(STRONG) | (parent_height.clone() - state.height))
                     .map_err(adderr)?,
                 VerticalAlignment::Middle => self
                     .solver
-                    .add_constraint(state.top | EQ(STRONG) | ((parent_height - state.height) / 2.0))
+                    .add_constraint(
+                        state.top | EQ(STRONG) | ((parent_height.clone() - state.height) / 2.0),
+                    )
                     .map_err(adderr)?,
             }
         }
@@ -391,6 +509,15 @@ impl LayoutState {
                     )
                     .map_err(adderr)?;
             }
+            DimensionSpec::Flex(_) => {
+                // Along the direction of the parent's layout, the size is
+                // determined by the parent in proportion to the weights
+                if parent_orientation != Some(ChildOrientation::Horizontal) {
+                    self.solver
+                        .add_constraint(state.width | EQ(STRONG) | parent_width)
+                        .map_err(adderr)?;
+                }
+            }
         }
         self.solver
             .add_constraint(
@@ -418,6 +545,13 @@ impl LayoutState {
                     )
                     .map_err(adderr)?;
             }
+            DimensionSpec::Flex(_) => {
+                if parent_orientation != Some(ChildOrientation::Vertical) {
+                    self.solver
+                        .add_constraint(state.height | EQ(STRONG) | parent_height)
+                        .map_err(adderr)?;
+                }
+            }
         }
         self.solver
             .add_constraint(
@@ -434,18 +568,34 @@ impl LayoutState {
 
         let has_children = !state.children.is_empty();
         if has_children {
-            let mut left_edge: Expression = state.left + 0.0;
-            let mut top_edge: Expression = state.top + 0.0;
-            let mut width_constraint = Expression::from_constant(0.0);
-            let mut height_constraint = Expression::from_constant(0.0);
+            // The children are laid out within the area left after
+            // removing the padding from the edges of this widget
+            let padding = state.constraints.padding;
+            let content_left = state.left + f64::from(padding.left);
+            let content_top = state.top + f64::from(padding.top);
+            let content_width = state.width - (f64::from(padding.left) + f64::from(padding.right));
+            let content_height =
+                state.height - (f64::from(padding.top) + f64::from(padding.bottom));
+
+            let mut left_edge = content_left.clone();
+            let mut top_edge = content_top.clone();
+            let mut width_constraint =
+                Expression::from_constant(f64::from(padding.left) + f64::from(padding.right));
+            let mut height_constraint =
+                Expression::from_constant(f64::from(padding.top) + f64::from(padding.bottom));
+            // The first flexible child along the direction of the layout,
+            // and its weight; the sizes of the other flexible children are
+            // expressed in proportion to it
+            let mut first_flex: Option<(Variable, u16)> = None;
 
             for child in &state.children {
                 let child_state = self.update_widget_constraint(
                     *child,
-                    state.width,
-                    state.height,
-                    Some(state.left),
-                    Some(state.top),
+                    content_width.clone(),
+                    content_height.clone(),
+                    Some(content_left.clone()),
+                    Some(content_top.clone()),
+                    Some(state.constraints.child_orientation),
                 )?;
 
                 match child_state.constraints.halign {
@@ -458,7 +608,7 @@ impl LayoutState {
                         .add_constraint(
                             (child_state.left + child_state.width)
                                 | EQ(STRONG)
-                                | (state.left + state.width),
+                                | (content_left.clone() + content_width.clone()),
                         )
                         .map_err(adderr)?,
                     HorizontalAlignment::Center => self
@@ -466,7 +616,8 @@ impl LayoutState {
                         .add_constraint(
                             child_state.left
                                 | EQ(STRONG)
-                                | (state.left + (state.width - child_state.width) / 2.0),
+                                | (content_left.clone()
+                                    + (content_width.clone() - child_state.width) / 2.0),
                         )
                         .map_err(adderr)?,
                 }
@@ -481,7 +632,7 @@ impl LayoutState {
                         .add_constraint(
                             (child_state.top + child_state.height)
                                 | EQ(STRONG)
-                                | (state.top + state.height),
+                                | (content_top.clone() + content_height.clone()),
                         )
                         .map_err(adderr)?,
                     VerticalAlignment::Middle => self
@@ -489,19 +640,39 @@ impl LayoutState {
                         .add_constraint(
                             child_state.top
                                 | EQ(STRONG)
-                                | (state.top + (state.height - child_state.height) / 2.0),
+                                | (content_top.clone()
+                                    + (content_height.clone() - child_state.height) / 2.0),
                         )
                         .map_err(adderr)?,
                 }
 
-                match state.constraints.child_orientation {
+                let (size, spec) = match state.constraints.child_orientation {
                     ChildOrientation::Horizontal => {
                         left_edge = child_state.left + child_state.width;
                         width_constraint = width_constraint + child_state.width;
+                        (child_state.width, child_state.constraints.width.spec)
                     }
                     ChildOrientation::Vertical => {
                         top_edge = child_state.top + child_state.height;
                         height_constraint = height_constraint + child_state.height;
+                        (child_state.height, child_state.constraints.height.spec)
+                    }
+                };
+
+                if let DimensionSpec::Flex(weight) = spec {
+                    match first_flex {
+                        None => first_flex = Some((size, weight)),
+                        Some((first_size, first_weight)) => {
+                            // This is weaker than the minimum and maximum
+                            // sizes so that those take precedence
+                            self.solver
+                                .add_constraint(
+                                    (size * f64::from(first_weight))
+                                        | EQ(MEDIUM)
+                                        | (first_size * f64::from(weight)),
+                                )
+                                .map_err(adderr)?;
+                        }
                     }
                 }
             }
@@ -509,7 +680,7 @@ impl LayoutState {
             // This constraint encourages the contents to fill out to the width
             // of the container, rather than clumping left
             self.solver
-                .add_constraint(left_edge | EQ(STRONG) | (state.left + state.width))
+                .add_constraint(left_edge | EQ(STRONG) | (content_left + content_width))
                 .map_err(adderr)?;
 
             self.solver
@@ -519,7 +690,7 @@ impl LayoutState {
             // This constraint encourages the contents to fill out to the height
             // of the container, rather than clumping top
             self.solver
-                .add_constraint(top_edge | EQ(STRONG) | (state.top + state.height))
+                .add_constraint(top_edge | EQ(STRONG) | (content_top + content_height))
                 .map_err(adderr)?;
 
             self.solver
@@ -881,4 +1052,128 @@ mod test {
         45,
         98
     );
+
+    fn rects(results: &[LaidOutWidget]) -> Vec<(usize, usize, usize, usize)> {
+        results
+            .iter()
+            .map(|r| (r.rect.x, r.rect.y, r.rect.width, r.rect.height))
+            .collect()
+    }
+
+    #[test]
+    fn flex_children_share_by_weight() {
+        let mut layout = LayoutState::new();
+        let root = WidgetId::new();
+        let a = WidgetId::new();
+        let b = WidgetId::new();
+        let c = WidgetId::new();
+        layout.add_widget(
+            root,
+            Constraints::default().set_child_orientation(ChildOrientation::Horizontal),
+            &[a, b, c],
+        );
+        layout.add_widget(a, Constraints::default().set_fixed_width(10), &[]);
+        layout.add_widget(b, Constraints::default().set_flex_width(1), &[]);
+        layout.add_widget(c, Constraints::default().set_flex_width(2), &[]);
+
+        let results = layout.compute_constraints(100, 10, root).unwrap();
+        assert_eq!(
+            rects(&results),
+            vec![
+                (0, 0, 100, 10),
+                (0, 0, 10, 10),
+                (10, 0, 30, 10),
+                (40, 0, 60, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn flex_respects_min_and_max() {
+        let mut layout = LayoutState::new();
+        let root = WidgetId::new();
+        let a = WidgetId::new();
+        let b = WidgetId::new();
+        layout.add_widget(
+            root,
+            Constraints::default().set_child_orientation(ChildOrientation::Vertical),
+            &[a, b],
+        );
+        layout.add_widget(
+            a,
+            Constraints::default().set_flex_height(1).set_max_height(2),
+            &[],
+        );
+        layout.add_widget(b, Constraints::default().set_flex_height(1), &[]);
+
+        let results = layout.compute_constraints(20, 10, root).unwrap();
+        assert_eq!(
+            rects(&results),
+            vec![(0, 0, 20, 10), (0, 0, 20, 2), (0, 2, 20, 8)]
+        );
+    }
+
+    #[test]
+    fn padding() {
+        let mut layout = LayoutState::new();
+        let root = WidgetId::new();
+        let a = WidgetId::new();
+        layout.add_widget(
+            root,
+            Constraints::default().set_padding(Padding {
+                left: 2,
+                top: 1,
+                right: 3,
+                bottom: 1,
+            }),
+            &[a],
+        );
+        layout.add_widget(a, Constraints::default().set_flex_width(1), &[]);
+
+        let results = layout.compute_constraints(20, 10, root).unwrap();
+        assert_eq!(rects(&results), vec![(0, 0, 20, 10), (2, 1, 15, 8)]);
+    }
+
+    #[test]
+    fn relayout_on_resize() {
+        let mut layout = LayoutState::new();
+        let root = WidgetId::new();
+        let a = WidgetId::new();
+        let b = WidgetId::new();
+        let root_constraints = *Constraints::default()
+            .set_child_orientation(ChildOrientation::Horizontal)
+            .set_padding(Padding::uniform(1));
+        let a_constraints = *Constraints::default().set_flex_width(1);
+        let b_constraints = *Constraints::default().set_flex_width(1);
+
+        layout.add_widget(root, &root_constraints, &[a, b]);
+        layout.add_widget(a, &a_constraints, &[]);
+        layout.add_widget(b, &b_constraints, &[]);
+        let results = layout.compute_constraints(12, 10, root).unwrap();
+        assert_eq!(
+            rects(&results),
+            vec![(0, 0, 12, 10), (1, 1, 5, 8), (6, 1, 5, 8)]
+        );
+
+        // Re-adding the same widgets keeps the solver, so only the
+        // screen size is updated
+        layout.add_widget(root, &root_constraints, &[a, b]);
+        layout.add_widget(a, &a_constraints, &[]);
+        layout.add_widget(b, &b_constraints, &[]);
+        assert_eq!(layout.solved_root, Some(root));
+        let results = layout.compute_constraints(22, 6, root).unwrap();
+        assert_eq!(
+            rects(&results),
+            vec![(0, 0, 22, 6), (1, 1, 10, 4), (11, 1, 10, 4)]
+        );
+
+        // Changing the constraints causes the solver to be rebuilt
+        layout.add_widget(b, Constraints::default().set_flex_width(3), &[]);
+        assert_eq!(layout.solved_root, None);
+        let results = layout.compute_constraints(22, 6, root).unwrap();
+        assert_eq!(
+            rects(&results),
+            vec![(0, 0, 22, 6), (1, 1, 5, 4), (6, 1, 15, 4)]
+        );
+    }
 }
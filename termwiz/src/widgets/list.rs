@@ -0,0 +1,341 @@
+//! A scrollable list of items with a selection that can be moved
+//! using the keyboard or the mouse.
+use crate::cell::{AttributeChange, CellAttributes};
+use crate::input::{InputEvent, KeyCode, KeyEvent, MouseButtons, MouseEvent};
+use crate::surface::{Change, CursorShape, Position};
+use crate::widgets::layout::Constraints;
+use crate::widgets::{fit_to_width, CursorShapeAndPosition, RenderArgs, UpdateArgs};
+use crate::widgets::{Widget, WidgetEvent};
+
+/// The number of rows that a single step of the mouse wheel scrolls
+const WHEEL_SCROLL_ROWS: usize = 3;
+
+/// Holds the items displayed by a `ListView`, along with the selected
+/// item and the scroll position.  The application retains ownership
+/// of the state so that it can inspect the selection after the `Ui`
+/// has processed input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListState {
+    items: Vec<String>,
+    selected: Option<usize>,
+    /// The index of the item shown on the first row of the view
+    top: usize,
+}
+
+impl ListState {
+    /// Create a new list state.  The first item, if any, is selected.
+    pub fn new(items: Vec<String>) -> Self {
+        let selected = if items.is_empty() { None } else { Some(0) };
+        Self {
+            items,
+            selected,
+            top: 0,
+        }
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Replace the items in the list.  The selection is preserved
+    /// if it is still in range, otherwise the last item is selected.
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.items = items;
+        let selected = self.selected.unwrap_or(0);
+        self.select(Some(selected));
+    }
+
+    /// Returns the index of the selected item
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Returns the text of the selected item
+    pub fn selected_item(&self) -> Option<&str> {
+        self.selected
+            .and_then(|idx| self.items.get(idx))
+            .map(String::as_str)
+    }
+
+    /// Select the item at `idx`, clamping it to the range of the items
+    pub fn select(&mut self, idx: Option<usize>) {
+        self.selected = match idx {
+            _ if self.items.is_empty() => None,
+            Some(idx) => Some(idx.min(self.items.len() - 1)),
+            None => None,
+        };
+    }
+
+    /// Move the selection down by `n` items
+    pub fn select_next(&mut self, n: usize) {
+        let idx = self.selected.map(|idx| idx.saturating_add(n)).unwrap_or(0);
+        self.select(Some(idx));
+    }
+
+    /// Move the selection up by `n` items
+    pub fn select_prev(&mut self, n: usize) {
+        let idx = self.selected.map(|idx| idx.saturating_sub(n)).unwrap_or(0);
+        self.select(Some(idx));
+    }
+
+    pub fn select_first(&mut self) {
+        self.select(Some(0));
+    }
+
+    pub fn select_last(&mut self) {
+        self.select(Some(self.items.len().saturating_sub(1)));
+    }
+
+    /// Returns the index of the item shown on the first row of the view
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    /// Scroll the view by `delta` rows without changing the selection
+    pub fn scroll_by(&mut self, delta: isize, height: usize) {
+        let top = if delta < 0 {
+            self.top.saturating_sub(delta.abs() as usize)
+        } else {
+            self.top.saturating_add(delta as usize)
+        };
+        self.top = top.min(self.max_top(height));
+    }
+
+    /// Adjust the scroll position so that the selected item is
+    /// visible in a view that is `height` rows tall
+    pub fn scroll_to_selection(&mut self, height: usize) {
+        if let Some(selected) = self.selected {
+            if selected < self.top {
+                self.top = selected;
+            } else if height > 0 && selected >= self.top + height {
+                self.top = selected + 1 - height;
+            }
+        }
+        self.top = self.top.min(self.max_top(height));
+    }
+
+    fn max_top(&self, height: usize) -> usize {
+        self.items.len().saturating_sub(height)
+    }
+}
+
+/// A widget that displays the items of a `ListState`, one per row,
+/// with the selected item shown in reverse video.
+///
+/// The selection is moved with the Up and Down arrows, PageUp and
+/// PageDown move by a screenful and Home and End jump to the first
+/// and last items.  Clicking on an item selects it and the mouse wheel
+/// scrolls the view.
+pub struct ListView<'a> {
+    state: &'a mut ListState,
+    constraints: Constraints,
+    /// The height of the view as of the most recent render; this is
+    /// the distance that PageUp and PageDown move the selection
+    height: usize,
+}
+
+impl<'a> ListView<'a> {
+    pub fn new(state: &'a mut ListState) -> Self {
+        Self {
+            state,
+            constraints: Constraints::default(),
+            height: 1,
+        }
+    }
+
+    pub fn with_constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    fn process_key(&mut self, key: &KeyEvent) -> bool {
+        let page = self.height.max(1);
+        match key.key {
+            KeyCode::UpArrow => self.state.select_prev(1),
+            KeyCode::DownArrow => self.state.select_next(1),
+            KeyCode::PageUp => self.state.select_prev(page),
+            KeyCode::PageDown => self.state.select_next(page),
+            KeyCode::Home => self.state.select_first(),
+            KeyCode::End => self.state.select_last(),
+            _ => return false,
+        }
+        self.state.scroll_to_selection(page);
+        true
+    }
+
+    fn process_mouse(&mut self, mouse: &MouseEvent) -> bool {
+        if mouse.mouse_buttons.contains(MouseButtons::VERT_WHEEL) {
+            let rows = WHEEL_SCROLL_ROWS as isize;
+            let delta = if mouse.mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                -rows
+            } else {
+                rows
+            };
+            self.state.scroll_by(delta, self.height);
+            true
+        } else if mouse.mouse_buttons.contains(MouseButtons::LEFT) {
+            let idx = self.state.top + mouse.y as usize;
+            if idx < self.state.items.len() {
+                self.state.select(Some(idx));
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<'a> Widget for ListView<'a> {
+    fn render(&mut self, args: &mut RenderArgs) {
+        let (width, height) = args.surface.dimensions();
+        self.height = height;
+        self.state.top = self.state.top.min(self.state.max_top(height));
+
+        args.surface
+            .add_change(Change::ClearScreen(Default::default()));
+
+        for (row, idx) in (self.state.top..self.state.items.len())
+            .take(height)
+            .enumerate()
+        {
+            let selected = self.state.selected == Some(idx);
+            args.surface.add_changes(vec![
+                Change::CursorPosition {
+                    x: Position::Absolute(0),
+                    y: Position::Absolute(row),
+                },
+                Change::Attribute(AttributeChange::Reverse(selected)),
+                Change::Text(fit_to_width(&self.state.items[idx], width)),
+            ]);
+        }
+        args.surface
+            .add_change(Change::AllAttributes(CellAttributes::default()));
+
+        // The selection is indicated by reverse video, so there is no
+        // need to show the cursor
+        *args.cursor = CursorShapeAndPosition {
+            shape: CursorShape::Hidden,
+            coords: (
+                0,
+                self.state
+                    .selected
+                    .map(|idx| idx.saturating_sub(self.state.top))
+                    .unwrap_or(0),
+            )
+                .into(),
+            ..Default::default()
+        };
+    }
+
+    fn get_size_constraints(&self) -> Constraints {
+        self.constraints
+    }
+
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        match event {
+            WidgetEvent::Input(InputEvent::Key(key)) => self.process_key(key),
+            WidgetEvent::Input(InputEvent::Mouse(mouse)) => self.process_mouse(mouse),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Modifiers;
+    use crate::surface::Surface;
+    use crate::widgets::WidgetId;
+
+    fn items(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("item {}", i)).collect()
+    }
+
+    fn render(view: &mut ListView, surface: &mut Surface) {
+        let mut cursor = CursorShapeAndPosition::default();
+        view.render(&mut RenderArgs {
+            id: WidgetId::new(),
+            is_focused: true,
+            cursor: &mut cursor,
+            surface,
+        });
+    }
+
+    fn key(view: &mut ListView, key: KeyCode) -> bool {
+        let mut cursor = CursorShapeAndPosition::default();
+        view.process_event(
+            &WidgetEvent::Input(InputEvent::Key(KeyEvent {
+                key,
+                modifiers: Modifiers::NONE,
+            })),
+            &mut UpdateArgs {
+                id: WidgetId::new(),
+                cursor: &mut cursor,
+            },
+        )
+    }
+
+    #[test]
+    fn selection() {
+        let mut state = ListState::new(items(3));
+        assert_eq!(state.selected_item(), Some("item 0"));
+        state.select_next(5);
+        assert_eq!(state.selected(), Some(2));
+        state.select_prev(1);
+        assert_eq!(state.selected(), Some(1));
+
+        state.set_items(items(1));
+        assert_eq!(state.selected(), Some(0));
+        state.set_items(vec![]);
+        assert_eq!(state.selected(), None);
+        assert_eq!(state.selected_item(), None);
+    }
+
+    #[test]
+    fn navigation_scrolls() {
+        let mut state = ListState::new(items(10));
+        let mut surface = Surface::new(10, 3);
+        {
+            let mut view = ListView::new(&mut state);
+            render(&mut view, &mut surface);
+            assert_eq!(
+                surface.screen_chars_to_string(),
+                "item 0    \nitem 1    \nitem 2    \n"
+            );
+
+            assert!(key(&mut view, KeyCode::PageDown));
+            assert!(key(&mut view, KeyCode::DownArrow));
+            assert!(!key(&mut view, KeyCode::Char('x')));
+            render(&mut view, &mut surface);
+            assert_eq!(
+                surface.screen_chars_to_string(),
+                "item 2    \nitem 3    \nitem 4    \n"
+            );
+
+            assert!(key(&mut view, KeyCode::End));
+            render(&mut view, &mut surface);
+            assert_eq!(
+                surface.screen_chars_to_string(),
+                "item 7    \nitem 8    \nitem 9    \n"
+            );
+
+            assert!(key(&mut view, KeyCode::Home));
+        }
+        assert_eq!(state.selected(), Some(0));
+        assert_eq!(state.top(), 0);
+    }
+
+    #[test]
+    fn selected_row_is_reversed() {
+        let mut state = ListState::new(items(2));
+        state.select(Some(1));
+        let mut surface = Surface::new(4, 2);
+        render(&mut ListView::new(&mut state), &mut surface);
+
+        let cells = surface.screen_cells();
+        assert!(!cells[0][0].attrs().reverse());
+        assert!(cells[1][0].attrs().reverse());
+        // Items are truncated to fit
+        assert_eq!(surface.screen_chars_to_string(), "ite…\nite…\n");
+    }
+}
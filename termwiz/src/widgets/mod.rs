@@ -1,18 +1,23 @@
 // Ideally this would be scoped to WidgetId, but I can't seem to find the
 // right place for it to take effect
 #![allow(clippy::new_without_default)]
+use crate::cell::{grapheme_column_width, unicode_column_width};
 use crate::color::ColorAttribute;
-use crate::input::InputEvent;
+use crate::input::{InputEvent, MouseButtons};
 use crate::surface::{Change, CursorShape, Position, SequenceNo, Surface};
 use anyhow::Error;
 use fnv::FnvHasher;
 use std::collections::{HashMap, VecDeque};
 use std::hash::BuildHasherDefault;
+use unicode_segmentation::UnicodeSegmentation;
 
 /// fnv is a more appropriate hasher for the WidgetIds we use in this module.
 type FnvHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FnvHasher>>;
 
 pub mod layout;
+pub mod list;
+pub mod table;
+pub mod text;
 
 /// Describes an event that may need to be processed by the widget
 pub enum WidgetEvent {
@@ -113,6 +118,34 @@ impl ScreenRelativeCoords {
     }
 }
 
+/// Truncates or pads `text` so that it occupies exactly `width` cells.
+/// Text that is too wide has its tail replaced by an ellipsis.
+fn fit_to_width(text: &str, width: usize) -> String {
+    let text_width = unicode_column_width(text);
+    if text_width <= width {
+        return format!("{}{}", text, " ".repeat(width - text_width));
+    }
+
+    let ellipsis = "\u{2026}";
+    let ellipsis_width = unicode_column_width(ellipsis);
+    let mut result = String::new();
+    let mut used = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = grapheme_column_width(grapheme);
+        if used + grapheme_width + ellipsis_width > width {
+            break;
+        }
+        result.push_str(grapheme);
+        used += grapheme_width;
+    }
+    if used + ellipsis_width <= width {
+        result.push_str(ellipsis);
+        used += ellipsis_width;
+    }
+    result.push_str(&" ".repeat(width - used));
+    result
+}
+
 static WIDGET_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 
 /// The `WidgetId` uniquely describes an instance of a widget.
@@ -183,6 +216,9 @@ pub struct Ui<'widget> {
     render: FnvHashMap<WidgetId, RenderData<'widget>>,
     input_queue: VecDeque<WidgetEvent>,
     focused: Option<WidgetId>,
+    /// Retained between calls to compute_layout so that the layout
+    /// can be incrementally adjusted when the screen is resized
+    layout: layout::LayoutState,
 }
 
 impl<'widget> Ui<'widget> {
@@ -245,6 +281,9 @@ impl<'widget> Ui<'widget> {
                 }
                 WidgetEvent::Input(InputEvent::Paste(_))
                 | WidgetEvent::Input(InputEvent::Key(_))
+                | WidgetEvent::Input(InputEvent::KeyRelease(_))
+                | WidgetEvent::Input(InputEvent::FocusGained)
+                | WidgetEvent::Input(InputEvent::FocusLost)
                 | WidgetEvent::Input(InputEvent::Wake) => self.do_deliver(id, event),
             };
 
@@ -319,10 +358,17 @@ impl<'widget> Ui<'widget> {
                     if let Some(hover) =
                         self.hovered_widget(&ScreenRelativeCoords::new(m.x as usize, m.y as usize))
                     {
+                        // Clicking on a widget gives it the keyboard focus
+                        if m.mouse_buttons.contains(MouseButtons::LEFT) {
+                            self.focused = Some(hover);
+                        }
                         self.deliver_event(hover, &event);
                     }
                 }
                 WidgetEvent::Input(InputEvent::Key(_))
+                | WidgetEvent::Input(InputEvent::KeyRelease(_))
+                | WidgetEvent::Input(InputEvent::FocusGained)
+                | WidgetEvent::Input(InputEvent::FocusLost)
                 | WidgetEvent::Input(InputEvent::Paste(_))
                 | WidgetEvent::Input(InputEvent::Wake) => {
                     if let Some(focus) = self.focused {
@@ -348,15 +394,20 @@ impl<'widget> Ui<'widget> {
     }
 
     /// Helper for applying the surfaces from the widgets to the target
-    /// screen in the correct order (from the root to the leaves)
+    /// screen in the correct order (from the root to the leaves).
+    /// `parent_coords` is the screen position of the parent widget.
     fn render_recursive(
         &mut self,
         id: WidgetId,
         screen: &mut Surface,
-        abs_coords: &ScreenRelativeCoords,
+        parent_coords: &ScreenRelativeCoords,
     ) -> Result<(), Error> {
-        let (x, y) = {
+        let abs_coords = {
             let render_data = self.render.get_mut(&id).unwrap();
+            let abs_coords = ScreenRelativeCoords::new(
+                parent_coords.x + render_data.coordinates.x,
+                parent_coords.y + render_data.coordinates.y,
+            );
             let surface = &mut render_data.surface;
             {
                 let mut args = RenderArgs {
@@ -369,15 +420,11 @@ impl<'widget> Ui<'widget> {
             }
             screen.draw_from_screen(surface, abs_coords.x, abs_coords.y);
             surface.flush_changes_older_than(SequenceNo::max_value());
-            (render_data.coordinates.x, render_data.coordinates.y)
+            abs_coords
         };
 
         for child in self.graph.children(id).to_vec() {
-            self.render_recursive(
-                child,
-                screen,
-                &ScreenRelativeCoords::new(x + abs_coords.x, y + abs_coords.y),
-            )?;
+            self.render_recursive(child, screen, &abs_coords)?;
         }
 
         Ok(())
@@ -386,15 +433,17 @@ impl<'widget> Ui<'widget> {
     /// Reconsider the layout constraints and apply them.
     /// Returns true if the layout was changed, false if no changes were made.
     fn compute_layout(&mut self, width: usize, height: usize) -> Result<bool, Error> {
-        let mut layout = layout::LayoutState::new();
+        let mut layout = std::mem::replace(&mut self.layout, layout::LayoutState::new());
 
         let root = self.graph.root.unwrap();
         self.add_widget_to_layout(&mut layout, root)?;
+        let results = layout.compute_constraints(width, height, root);
+        self.layout = layout;
         let mut changed = false;
 
         // Clippy is dead wrong about this iterator being an identity_conversion
         #[cfg_attr(feature = "cargo-clippy", allow(clippy::identity_conversion))]
-        for result in layout.compute_constraints(width, height, root)? {
+        for result in results? {
             let render_data = self.render.get_mut(&result.widget).unwrap();
             let coords = ParentRelativeCoords::new(result.rect.x, result.rect.y);
             if coords != render_data.coordinates {
@@ -500,3 +549,39 @@ impl<'widget> Ui<'widget> {
         ParentRelativeCoords { x, y }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::widgets::layout::{ChildOrientation, Constraints};
+
+    struct Label(&'static str, Constraints);
+
+    impl Widget for Label {
+        fn render(&mut self, args: &mut RenderArgs) {
+            args.surface
+                .add_changes(vec![Change::ClearScreen(Default::default()), self.0.into()]);
+        }
+
+        fn get_size_constraints(&self) -> Constraints {
+            self.1
+        }
+    }
+
+    #[test]
+    fn children_are_drawn_where_they_are_laid_out() {
+        let mut column = Constraints::default();
+        column.set_child_orientation(ChildOrientation::Vertical);
+        let mut row = Constraints::default();
+        row.set_fixed_height(1);
+
+        let mut ui = Ui::new();
+        let root = ui.set_root(Label("", column));
+        ui.add_child(root, Label("one", row));
+        ui.add_child(root, Label("two", row));
+
+        let mut screen = Surface::new(4, 3);
+        while ui.render_to_screen(&mut screen).unwrap() {}
+        assert_eq!(screen.screen_chars_to_string(), "one \ntwo \n    \n");
+    }
+}
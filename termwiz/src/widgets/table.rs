@@ -0,0 +1,303 @@
+//! A table widget that displays rows of text aligned into columns
+//! beneath a header row.
+use crate::cell::{unicode_column_width, AttributeChange, CellAttributes, Intensity, Underline};
+use crate::input::{InputEvent, KeyCode, MouseButtons};
+use crate::surface::{Change, Position};
+use crate::widgets::layout::{Constraints, HorizontalAlignment};
+use crate::widgets::{fit_to_width, RenderArgs, UpdateArgs, Widget, WidgetEvent};
+
+/// The number of cells between adjacent columns
+const COLUMN_GAP: usize = 1;
+
+/// Describes a column in a `Table`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    /// The text displayed in the header row
+    pub title: String,
+    /// The width of the column in cells.  If None, the column is
+    /// sized to fit its widest cell, including the title.
+    pub width: Option<usize>,
+    /// How the text is aligned within the column
+    pub halign: HorizontalAlignment,
+}
+
+impl Column {
+    pub fn new<S: Into<String>>(title: S) -> Self {
+        Self {
+            title: title.into(),
+            width: None,
+            halign: HorizontalAlignment::Left,
+        }
+    }
+
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn with_alignment(mut self, halign: HorizontalAlignment) -> Self {
+        self.halign = halign;
+        self
+    }
+}
+
+/// Pads or truncates `text` to occupy exactly `width` cells,
+/// positioned according to `halign`
+fn align_to_width(text: &str, width: usize, halign: HorizontalAlignment) -> String {
+    let text = fit_to_width(text, width);
+    let text = text.trim_end();
+    let padding = width.saturating_sub(unicode_column_width(text));
+    let left = match halign {
+        HorizontalAlignment::Left => 0,
+        HorizontalAlignment::Center => padding / 2,
+        HorizontalAlignment::Right => padding,
+    };
+    format!("{}{}{}", " ".repeat(left), text, " ".repeat(padding - left))
+}
+
+/// A widget that displays rows of text in columns, with a header row
+/// that remains in place as the rows are scrolled.  Text that doesn't
+/// fit in its column is truncated.
+///
+/// The rows are scrolled with the Up and Down arrows, PageUp and
+/// PageDown, Home and End, and the mouse wheel.
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+    constraints: Constraints,
+    /// The index of the row shown immediately beneath the header
+    top: usize,
+    /// The number of rows visible beneath the header as of the
+    /// most recent render
+    height: usize,
+}
+
+impl Table {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self {
+            columns,
+            rows: vec![],
+            constraints: Constraints::default(),
+            top: 0,
+            height: 1,
+        }
+    }
+
+    pub fn with_constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn with_rows(mut self, rows: Vec<Vec<String>>) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    /// Append a row.  Cells beyond the number of columns are ignored
+    /// and missing cells are shown as blank.
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn rows(&self) -> &[Vec<String>] {
+        &self.rows
+    }
+
+    /// Compute the widths of the columns when the table is `width`
+    /// cells wide.  Columns are given their preferred widths from left
+    /// to right, and the columns that don't fit are narrowed.
+    pub fn column_widths(&self, width: usize) -> Vec<usize> {
+        let mut remaining = width;
+        let mut widths = vec![];
+        for (idx, column) in self.columns.iter().enumerate() {
+            let preferred = match column.width {
+                Some(width) => width,
+                None => self
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(idx))
+                    .map(|cell| unicode_column_width(cell))
+                    .max()
+                    .unwrap_or(0)
+                    .max(unicode_column_width(&column.title)),
+            };
+            let width = preferred.min(remaining);
+            remaining = remaining.saturating_sub(width + COLUMN_GAP);
+            widths.push(width);
+        }
+        widths
+    }
+
+    fn format_row(&self, cells: &[String], widths: &[usize]) -> String {
+        let gap = " ".repeat(COLUMN_GAP);
+        let mut line = String::new();
+        for (idx, (column, width)) in self.columns.iter().zip(widths).enumerate() {
+            if idx > 0 {
+                line.push_str(&gap);
+            }
+            let cell = cells.get(idx).map(String::as_str).unwrap_or("");
+            line.push_str(&align_to_width(cell, *width, column.halign));
+        }
+        line
+    }
+
+    fn max_top(&self) -> usize {
+        self.rows.len().saturating_sub(self.height)
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        let top = if delta < 0 {
+            self.top.saturating_sub(delta.abs() as usize)
+        } else {
+            self.top.saturating_add(delta as usize)
+        };
+        self.top = top.min(self.max_top());
+    }
+}
+
+impl Widget for Table {
+    fn render(&mut self, args: &mut RenderArgs) {
+        let (width, height) = args.surface.dimensions();
+        self.height = height.saturating_sub(1);
+        self.top = self.top.min(self.max_top());
+
+        let widths = self.column_widths(width);
+        let titles: Vec<String> = self.columns.iter().map(|c| c.title.clone()).collect();
+
+        args.surface
+            .add_change(Change::ClearScreen(Default::default()));
+        args.surface.add_changes(vec![
+            Change::Attribute(AttributeChange::Intensity(Intensity::Bold)),
+            Change::Attribute(AttributeChange::Underline(Underline::Single)),
+            Change::Text(fit_to_width(&self.format_row(&titles, &widths), width)),
+            Change::AllAttributes(CellAttributes::default()),
+        ]);
+
+        for (row, cells) in self
+            .rows
+            .iter()
+            .skip(self.top)
+            .take(self.height)
+            .enumerate()
+        {
+            args.surface.add_changes(vec![
+                Change::CursorPosition {
+                    x: Position::Absolute(0),
+                    y: Position::Absolute(row + 1),
+                },
+                Change::Text(fit_to_width(&self.format_row(cells, &widths), width)),
+            ]);
+        }
+    }
+
+    fn get_size_constraints(&self) -> Constraints {
+        self.constraints
+    }
+
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        let page = self.height.max(1) as isize;
+        match event {
+            WidgetEvent::Input(InputEvent::Key(key)) => match key.key {
+                KeyCode::UpArrow => self.scroll_by(-1),
+                KeyCode::DownArrow => self.scroll_by(1),
+                KeyCode::PageUp => self.scroll_by(-page),
+                KeyCode::PageDown => self.scroll_by(page),
+                KeyCode::Home => self.top = 0,
+                KeyCode::End => self.top = self.max_top(),
+                _ => return false,
+            },
+            WidgetEvent::Input(InputEvent::Mouse(mouse))
+                if mouse.mouse_buttons.contains(MouseButtons::VERT_WHEEL) =>
+            {
+                if mouse.mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                    self.scroll_by(-1);
+                } else {
+                    self.scroll_by(1);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::surface::Surface;
+    use crate::widgets::{CursorShapeAndPosition, WidgetId};
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn table() -> Table {
+        Table::new(vec![
+            Column::new("Name"),
+            Column::new("Size").with_alignment(HorizontalAlignment::Right),
+            Column::new("Kind").with_width(3),
+        ])
+        .with_rows(vec![
+            row(&["a", "1", "file"]),
+            row(&["bbbbbb", "200", "dir"]),
+            row(&["c"]),
+        ])
+    }
+
+    fn render(table: &mut Table, surface: &mut Surface) {
+        let mut cursor = CursorShapeAndPosition::default();
+        table.render(&mut RenderArgs {
+            id: WidgetId::new(),
+            is_focused: true,
+            cursor: &mut cursor,
+            surface,
+        });
+    }
+
+    #[test]
+    fn column_widths() {
+        let table = table();
+        assert_eq!(table.column_widths(80), vec![6, 4, 3]);
+        // The columns that don't fit are narrowed
+        assert_eq!(table.column_widths(10), vec![6, 3, 0]);
+    }
+
+    #[test]
+    fn render_aligned() {
+        let mut table = table();
+        let mut surface = Surface::new(16, 4);
+        render(&mut table, &mut surface);
+        assert_eq!(
+            surface.screen_chars_to_string(),
+            "Name   Size Ki… \n\
+             a         1 fi… \n\
+             bbbbbb  200 dir \n\
+             c               \n"
+        );
+        assert_eq!(
+            surface.screen_cells()[0][0].attrs().intensity(),
+            Intensity::Bold
+        );
+    }
+
+    #[test]
+    fn scrolling_keeps_header() {
+        let mut table = table();
+        let mut surface = Surface::new(16, 2);
+        render(&mut table, &mut surface);
+        table.scroll_by(1);
+        render(&mut table, &mut surface);
+        assert_eq!(
+            surface.screen_chars_to_string(),
+            "Name   Size Ki… \nbbbbbb  200 dir \n"
+        );
+
+        // Can't scroll past the last row
+        table.scroll_by(10);
+        render(&mut table, &mut surface);
+        assert_eq!(
+            surface.screen_chars_to_string(),
+            "Name   Size Ki… \nc               \n"
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Widgets for editing text: a single line `TextInput` and a multi-line
+//! `TextArea`.  Both use the same key bindings and grapheme and word
+//! motion logic as the `LineEditor`.
+use crate::cell::{grapheme_column_width, unicode_column_width};
+use crate::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use crate::lineedit::{eval_movement, resolve_action, Action, Movement};
+use crate::surface::{Change, CursorShape, Position};
+use crate::widgets::layout::Constraints;
+use crate::widgets::{CursorShapeAndPosition, RenderArgs, UpdateArgs, Widget, WidgetEvent};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Returns the byte range of the line in `text` that contains `cursor`,
+/// excluding the trailing newline
+fn line_bounds(text: &str, cursor: usize) -> (usize, usize) {
+    let start = text[..cursor].rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+    let end = text[cursor..]
+        .find('\n')
+        .map(|idx| idx + cursor)
+        .unwrap_or_else(|| text.len());
+    (start, end)
+}
+
+/// Applies a line editing action to `text` and the insertion point
+/// `cursor`.  When `multiline` is true, the start and end of line
+/// movements are relative to the line containing the cursor rather
+/// than to the whole of the text.
+/// Returns false if the action isn't an editing action.
+fn apply_action(text: &mut String, cursor: &mut usize, action: Action, multiline: bool) -> bool {
+    let eval = |text: &str, cursor: usize, movement: Movement| match movement {
+        Movement::StartOfLine if multiline => line_bounds(text, cursor).0,
+        Movement::EndOfLine if multiline => line_bounds(text, cursor).1,
+        movement => eval_movement(text, cursor, movement),
+    };
+
+    match action {
+        Action::Move(movement) => {
+            *cursor = eval(text, *cursor, movement);
+        }
+        Action::Kill(movement) => {
+            let new_cursor = eval(text, *cursor, movement);
+            let (lower, upper) = if new_cursor < *cursor {
+                (new_cursor, *cursor)
+            } else {
+                (*cursor, new_cursor)
+            };
+            text.replace_range(lower..upper, "");
+            *cursor = lower;
+        }
+        Action::InsertChar(rep, c) => {
+            for _ in 0..rep {
+                text.insert(*cursor, c);
+                *cursor += c.len_utf8();
+            }
+        }
+        Action::InsertText(rep, insert) => {
+            let insert = if multiline {
+                insert.replace("\r\n", "\n").replace('\r', "\n")
+            } else {
+                insert.replace(&['\r', '\n'][..], "")
+            };
+            for _ in 0..rep {
+                text.insert_str(*cursor, &insert);
+                *cursor += insert.len();
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Maps an input event to an editing action, adding the bindings that
+/// are useful in a widget but that the `LineEditor` doesn't have
+fn action_for_event(event: &InputEvent) -> Option<Action> {
+    match event {
+        InputEvent::Key(KeyEvent {
+            key: KeyCode::Delete,
+            modifiers: Modifiers::NONE,
+        }) => Some(Action::Kill(Movement::ForwardChar(1))),
+        event => resolve_action(event),
+    }
+}
+
+fn cursor_shape(is_focused: bool) -> CursorShape {
+    if is_focused {
+        CursorShape::SteadyBar
+    } else {
+        CursorShape::Hidden
+    }
+}
+
+/// A single line text entry field.  The text scrolls horizontally to
+/// keep the cursor in view when it is wider than the widget.
+///
+/// Enter and the Up and Down arrows are not handled, which allows the
+/// parent widget to respond to them.
+pub struct TextInput<'a> {
+    text: &'a mut String,
+    /// byte index into the UTF-8 string data of the insertion point
+    cursor: usize,
+    /// The column of the text shown in the leftmost cell
+    scroll: usize,
+    constraints: Constraints,
+}
+
+impl<'a> TextInput<'a> {
+    /// Create a text input that edits `text`, with the cursor
+    /// positioned at the end of the text
+    pub fn new(text: &'a mut String) -> Self {
+        let cursor = text.len();
+        Self {
+            text,
+            cursor,
+            scroll: 0,
+            constraints: *Constraints::default().set_fixed_height(1),
+        }
+    }
+
+    pub fn with_constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+}
+
+impl<'a> Widget for TextInput<'a> {
+    fn render(&mut self, args: &mut RenderArgs) {
+        let (width, _height) = args.surface.dimensions();
+        let cursor_col = unicode_column_width(&self.text[..self.cursor]);
+        if cursor_col < self.scroll {
+            self.scroll = cursor_col;
+        } else if width > 0 && cursor_col >= self.scroll + width {
+            self.scroll = cursor_col + 1 - width;
+        }
+
+        let mut visible = String::new();
+        let mut col = 0;
+        for grapheme in self.text.graphemes(true) {
+            let grapheme_width = grapheme_column_width(grapheme);
+            if col >= self.scroll {
+                if col + grapheme_width > self.scroll + width {
+                    break;
+                }
+                visible.push_str(grapheme);
+            }
+            col += grapheme_width;
+        }
+
+        args.surface
+            .add_change(Change::ClearScreen(Default::default()));
+        args.surface.add_change(visible);
+
+        *args.cursor = CursorShapeAndPosition {
+            shape: cursor_shape(args.is_focused),
+            coords: (cursor_col - self.scroll, 0).into(),
+            ..Default::default()
+        };
+    }
+
+    fn get_size_constraints(&self) -> Constraints {
+        self.constraints
+    }
+
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        match event {
+            WidgetEvent::Input(event) => match action_for_event(event) {
+                Some(action) => apply_action(self.text, &mut self.cursor, action, false),
+                None => false,
+            },
+        }
+    }
+}
+
+/// A row of the text area after wrapping; the byte range of the text
+/// that it displays
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct VisualRow {
+    start: usize,
+    end: usize,
+    /// true if this row is the last of its line, which means that
+    /// a cursor positioned at `end` belongs to this row
+    ends_line: bool,
+}
+
+/// Splits `text` into the rows that it occupies when wrapped to `width`
+fn wrap_rows(text: &str, width: usize) -> Vec<VisualRow> {
+    let width = width.max(1);
+    let mut rows = vec![];
+    let mut start = 0;
+    for line in text.split('\n') {
+        let mut row_start = start;
+        let mut col = 0;
+        for (idx, grapheme) in line.grapheme_indices(true) {
+            let grapheme_width = grapheme_column_width(grapheme);
+            if col + grapheme_width > width && col > 0 {
+                rows.push(VisualRow {
+                    start: row_start,
+                    end: start + idx,
+                    ends_line: false,
+                });
+                row_start = start + idx;
+                col = 0;
+            }
+            col += grapheme_width;
+        }
+        if col >= width {
+            // The line exactly fills the row, so a cursor at the end
+            // of the line is displayed at the start of the next row
+            rows.push(VisualRow {
+                start: row_start,
+                end: start + line.len(),
+                ends_line: false,
+            });
+            row_start = start + line.len();
+        }
+        rows.push(VisualRow {
+            start: row_start,
+            end: start + line.len(),
+            ends_line: true,
+        });
+        start += line.len() + 1;
+    }
+    rows
+}
+
+/// A multi-line text editor.  Long lines are wrapped to the width of
+/// the widget and the text scrolls vertically to keep the cursor in view.
+///
+/// In addition to the `LineEditor` key bindings, Enter inserts a newline,
+/// the Up and Down arrows move between rows and PageUp and PageDown move
+/// by a screenful.
+pub struct TextArea<'a> {
+    text: &'a mut String,
+    /// byte index into the UTF-8 string data of the insertion point
+    cursor: usize,
+    /// The index of the row shown at the top of the widget
+    top: usize,
+    /// The dimensions of the widget as of the most recent render
+    width: usize,
+    height: usize,
+    constraints: Constraints,
+}
+
+impl<'a> TextArea<'a> {
+    /// Create a text area that edits `text`, with the cursor
+    /// positioned at the end of the text
+    pub fn new(text: &'a mut String) -> Self {
+        let cursor = text.len();
+        Self {
+            text,
+            cursor,
+            top: 0,
+            width: 80,
+            height: 1,
+            constraints: Constraints::default(),
+        }
+    }
+
+    pub fn with_constraints(mut self, constraints: Constraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    /// Returns the (row, column) of the cursor in the wrapped text
+    fn cursor_row_col(&self, rows: &[VisualRow]) -> (usize, usize) {
+        for (idx, row) in rows.iter().enumerate() {
+            if self.cursor >= row.start
+                && (self.cursor < row.end || (self.cursor == row.end && row.ends_line))
+            {
+                let col = unicode_column_width(&self.text[row.start..self.cursor]);
+                return (idx, col);
+            }
+        }
+        (rows.len().saturating_sub(1), 0)
+    }
+
+    /// Move the cursor up (negative) or down (positive) by `delta` rows,
+    /// keeping it as close as possible to its current column
+    fn move_rows(&mut self, delta: isize) {
+        let rows = wrap_rows(self.text, self.width);
+        let (row, col) = self.cursor_row_col(&rows);
+        let target = if delta < 0 {
+            row.saturating_sub(delta.abs() as usize)
+        } else {
+            (row + delta as usize).min(rows.len() - 1)
+        };
+        if target == row {
+            return;
+        }
+
+        let target = rows[target];
+        let mut cursor = target.start;
+        let mut cursor_col = 0;
+        for (idx, grapheme) in self.text[target.start..target.end].grapheme_indices(true) {
+            let grapheme_width = grapheme_column_width(grapheme);
+            if cursor_col + grapheme_width > col {
+                break;
+            }
+            cursor_col += grapheme_width;
+            cursor = target.start + idx + grapheme.len();
+        }
+        // Don't leave the cursor at the end of a row that continues
+        // on the next row, as it would be displayed on the next row
+        if cursor == target.end && !target.ends_line {
+            cursor = eval_movement(self.text, cursor, Movement::BackwardChar(1));
+        }
+        self.cursor = cursor;
+    }
+}
+
+impl<'a> Widget for TextArea<'a> {
+    fn render(&mut self, args: &mut RenderArgs) {
+        let (width, height) = args.surface.dimensions();
+        self.width = width;
+        self.height = height;
+
+        let rows = wrap_rows(self.text, width);
+        let (cursor_row, cursor_col) = self.cursor_row_col(&rows);
+        if cursor_row < self.top {
+            self.top = cursor_row;
+        } else if height > 0 && cursor_row >= self.top + height {
+            self.top = cursor_row + 1 - height;
+        }
+
+        args.surface
+            .add_change(Change::ClearScreen(Default::default()));
+        for (y, row) in rows.iter().skip(self.top).take(height).enumerate() {
+            args.surface.add_changes(vec![
+                Change::CursorPosition {
+                    x: Position::Absolute(0),
+                    y: Position::Absolute(y),
+                },
+                Change::Text(self.text[row.start..row.end].to_string()),
+            ]);
+        }
+
+        *args.cursor = CursorShapeAndPosition {
+            shape: cursor_shape(args.is_focused),
+            coords: (cursor_col, cursor_row - self.top).into(),
+            ..Default::default()
+        };
+    }
+
+    fn get_size_constraints(&self) -> Constraints {
+        self.constraints
+    }
+
+    fn process_event(&mut self, event: &WidgetEvent, _args: &mut UpdateArgs) -> bool {
+        if let WidgetEvent::Input(InputEvent::Key(KeyEvent {
+            key,
+            modifiers: Modifiers::NONE,
+        })) = event
+        {
+            let page = self.height.max(1) as isize;
+            match key {
+                KeyCode::Enter => {
+                    let newline = Action::InsertChar(1, '\n');
+                    return apply_action(self.text, &mut self.cursor, newline, true);
+                }
+                KeyCode::UpArrow => self.move_rows(-1),
+                KeyCode::DownArrow => self.move_rows(1),
+                KeyCode::PageUp => self.move_rows(-page),
+                KeyCode::PageDown => self.move_rows(page),
+                _ => return self.process_action(event),
+            }
+            return true;
+        }
+        self.process_action(event)
+    }
+}
+
+impl<'a> TextArea<'a> {
+    fn process_action(&mut self, event: &WidgetEvent) -> bool {
+        match event {
+            WidgetEvent::Input(event) => match action_for_event(event) {
+                Some(action) => apply_action(self.text, &mut self.cursor, action, true),
+                None => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::surface::Surface;
+    use crate::widgets::WidgetId;
+
+    fn render<W: Widget>(widget: &mut W, surface: &mut Surface) -> CursorShapeAndPosition {
+        let mut cursor = CursorShapeAndPosition::default();
+        widget.render(&mut RenderArgs {
+            id: WidgetId::new(),
+            is_focused: true,
+            cursor: &mut cursor,
+            surface,
+        });
+        cursor
+    }
+
+    fn key<W: Widget>(widget: &mut W, key: KeyCode, modifiers: Modifiers) -> bool {
+        let mut cursor = CursorShapeAndPosition::default();
+        widget.process_event(
+            &WidgetEvent::Input(InputEvent::Key(KeyEvent { key, modifiers })),
+            &mut UpdateArgs {
+                id: WidgetId::new(),
+                cursor: &mut cursor,
+            },
+        )
+    }
+
+    fn type_text<W: Widget>(widget: &mut W, text: &str) {
+        for c in text.chars() {
+            assert!(key(widget, KeyCode::Char(c), Modifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn text_input_editing() {
+        let mut text = "hello".to_string();
+        {
+            let mut input = TextInput::new(&mut text);
+            type_text(&mut input, " world");
+            assert!(key(&mut input, KeyCode::LeftArrow, Modifiers::ALT));
+            assert!(key(&mut input, KeyCode::Backspace, Modifiers::NONE));
+            assert!(key(&mut input, KeyCode::Home, Modifiers::NONE));
+            assert!(key(&mut input, KeyCode::Delete, Modifiers::NONE));
+            type_text(&mut input, "J");
+            // Enter is left for the parent widget to handle
+            assert!(!key(&mut input, KeyCode::Enter, Modifiers::NONE));
+        }
+        assert_eq!(text, "Jelloworld");
+    }
+
+    #[test]
+    fn text_input_scrolls() {
+        let mut text = "abcdefgh".to_string();
+        let mut input = TextInput::new(&mut text);
+        let mut surface = Surface::new(4, 1);
+
+        let cursor = render(&mut input, &mut surface);
+        assert_eq!(surface.screen_chars_to_string(), "fgh \n");
+        assert_eq!(cursor.coords, (3, 0).into());
+        assert_eq!(cursor.shape, CursorShape::SteadyBar);
+
+        assert!(key(&mut input, KeyCode::Home, Modifiers::NONE));
+        let cursor = render(&mut input, &mut surface);
+        assert_eq!(surface.screen_chars_to_string(), "abcd\n");
+        assert_eq!(cursor.coords, (0, 0).into());
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(
+            wrap_rows("abcde\nf", 3),
+            vec![
+                VisualRow {
+                    start: 0,
+                    end: 3,
+                    ends_line: false
+                },
+                VisualRow {
+                    start: 3,
+                    end: 5,
+                    ends_line: true
+                },
+                VisualRow {
+                    start: 6,
+                    end: 7,
+                    ends_line: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn text_area_editing() {
+        let mut text = String::new();
+        let mut surface = Surface::new(4, 2);
+        {
+            let mut area = TextArea::new(&mut text);
+            type_text(&mut area, "abcdef");
+            assert!(key(&mut area, KeyCode::Enter, Modifiers::NONE));
+            type_text(&mut area, "gh");
+
+            let cursor = render(&mut area, &mut surface);
+            // The first line wraps, and the view scrolls to keep the
+            // cursor visible
+            assert_eq!(surface.screen_chars_to_string(), "ef  \ngh  \n");
+            assert_eq!(cursor.coords, (2, 1).into());
+
+            assert!(key(&mut area, KeyCode::UpArrow, Modifiers::NONE));
+            assert!(key(&mut area, KeyCode::Home, Modifiers::NONE));
+            type_text(&mut area, "X");
+            assert!(key(&mut area, KeyCode::UpArrow, Modifiers::NONE));
+            let cursor = render(&mut area, &mut surface);
+            assert_eq!(surface.screen_chars_to_string(), "Xabc\ndef \n");
+            assert_eq!(cursor.coords, (1, 0).into());
+        }
+        assert_eq!(text, "Xabcdef\ngh");
+    }
+}
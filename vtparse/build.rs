@@ -142,9 +142,8 @@ fn build_tables() -> Tables {
                 0x1c..=0x1f => (Execute, CsiEntry),
                 r(0x7f)     => (Ignore, CsiEntry),
                 0x20..=0x2f => (Collect, CsiIntermediate),
-                r(0x3a)     => (None, CsiIgnore),
                 0x30..=0x39 => (Param, CsiParam),
-                r(0x3b)     => (Param, CsiParam),
+                0x3a..=0x3b => (Param, CsiParam),
                 0x3c..=0x3f => (Collect, CsiParam),
                 0x40..=0x7e => (CsiDispatch, Ground),
             },
@@ -175,9 +174,8 @@ fn build_tables() -> Tables {
                 r(0x19)     => (Execute, CsiParam),
                 0x1c..=0x1f => (Execute, CsiParam),
                 0x30..=0x39 => (Param, CsiParam),
-                r(0x3b)     => (Param, CsiParam),
+                0x3a..=0x3b => (Param, CsiParam),
                 r(0x7f)     => (Ignore, CsiParam),
-                r(0x3a)     => (None, CsiIgnore),
                 0x3c..=0x3f => (None, CsiIgnore),
                 0x20..=0x2f => (Collect, CsiIntermediate),
                 0x40..=0x7e => (CsiDispatch, Ground),
@@ -281,6 +279,13 @@ fn build_tables() -> Tables {
                 0x1c..=0x1f => (Put, DcsPassthrough),
                 0x20..=0x7e => (Put, DcsPassthrough),
                 r(0x7f)     => (Ignore, DcsPassthrough),
+                // This extended range allows for UTF-8 characters
+                // to be embedded in the data string, such as in the
+                // output of tmux control mode.  It is not part of the
+                // base state machine.
+                0xc2..=0xdf => (Utf8, Utf8Sequence),
+                0xe0..=0xef => (Utf8, Utf8Sequence),
+                0xf0..=0xf4 => (Utf8, Utf8Sequence),
             },
         ),
     );
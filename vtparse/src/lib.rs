@@ -64,6 +64,8 @@ fn lookup_exit(state: State) -> Action {
 /// `params` in most of the functions of this trait are decimal integer parameters in escape
 /// sequences.  They are separated by semicolon characters.  An omitted parameter is returned in
 /// this interface as a zero, which represents the default value for that parameter.
+/// Parameters may also be followed by colon separated sub-parameters; sequences
+/// that use them are passed to `csi_dispatch_with_subparams` rather than `csi_dispatch`.
 ///
 /// Other jargon used here is defined in
 /// [ECMA-48](http://www.ecma-international.org/publications/files/ECMA-ST/ECMA-48,%202nd%20Edition,%20August%201979.pdf).
@@ -103,6 +105,9 @@ pub trait VTActor {
     /// final character, and executes it, passing in the parameter list. It also selects a handler
     /// function for the rest of the characters in the control string.
     ///
+    /// `byte` is the final character, which identifies the control string;
+    /// for example, `q` for sixel graphics.
+    ///
     /// See [ECMA-48](http://www.ecma-international.org/publications/files/ECMA-ST/ECMA-48,%202nd%20Edition,%20August%201979.pdf)
     /// for more information on device control strings.
     fn dcs_hook(
@@ -110,6 +115,7 @@ pub trait VTActor {
         params: &[i64],
         intermediates: &[u8],
         ignored_excess_intermediates: bool,
+        byte: u8,
     );
 
     /// This action passes characters from the data string part of a device control string to a
@@ -155,6 +161,21 @@ pub trait VTActor {
         byte: u8,
     );
 
+    /// Like `csi_dispatch`, but called in its place when one or more of the
+    /// parameters has colon separated sub-parameters, as used by the ISO-8613-6
+    /// forms of SGR such as `CSI 4:3 m` and `CSI 58:2::255:0:0 m`.
+    ///
+    /// Each element of `params` holds a parameter followed by its sub-parameters.
+    /// The default implementation ignores the sequence.
+    fn csi_dispatch_with_subparams(
+        &mut self,
+        _params: &[&[i64]],
+        _intermediates: &[u8],
+        _ignored_excess_intermediates: bool,
+        _byte: u8,
+    ) {
+    }
+
     /// Called when an OSC string is terminated by ST, CAN, SUB or ESC.
     ///
     /// `params` is an array of byte strings (which may also be valid utf-8)
@@ -174,6 +195,7 @@ pub enum VTAction {
         params: Vec<i64>,
         intermediates: Vec<u8>,
         ignored_excess_intermediates: bool,
+        byte: u8,
     },
     DcsPut(u8),
     DcsUnhook,
@@ -189,6 +211,12 @@ pub enum VTAction {
         ignored_excess_intermediates: bool,
         byte: u8,
     },
+    CsiDispatchWithSubParams {
+        params: Vec<Vec<i64>>,
+        intermediates: Vec<u8>,
+        ignored_excess_intermediates: bool,
+        byte: u8,
+    },
     OscDispatch(Vec<Vec<u8>>),
 }
 
@@ -230,11 +258,13 @@ impl VTActor for CollectingVTActor {
         params: &[i64],
         intermediates: &[u8],
         ignored_excess_intermediates: bool,
+        byte: u8,
     ) {
         self.actions.push(VTAction::DcsHook {
             params: params.to_vec(),
             intermediates: intermediates.to_vec(),
             ignored_excess_intermediates,
+            byte,
         });
     }
 
@@ -276,6 +306,21 @@ impl VTActor for CollectingVTActor {
         });
     }
 
+    fn csi_dispatch_with_subparams(
+        &mut self,
+        params: &[&[i64]],
+        intermediates: &[u8],
+        ignored_excess_intermediates: bool,
+        byte: u8,
+    ) {
+        self.actions.push(VTAction::CsiDispatchWithSubParams {
+            params: params.iter().map(|p| p.to_vec()).collect(),
+            intermediates: intermediates.to_vec(),
+            ignored_excess_intermediates,
+            byte,
+        });
+    }
+
     fn osc_dispatch(&mut self, params: &[&[u8]]) {
         self.actions.push(VTAction::OscDispatch(
             params.iter().map(|i| i.to_vec()).collect(),
@@ -329,9 +374,13 @@ pub struct VTParser {
     osc: OscState,
 
     params: [i64; MAX_PARAMS],
+    /// true for the entries in `params` that are colon separated
+    /// sub-parameters of the parameter that precedes them
+    is_subparam: [bool; MAX_PARAMS],
     num_params: usize,
     current_param: Option<i64>,
     params_full: bool,
+    next_is_subparam: bool,
 
     utf8_parser: Utf8Parser,
     utf8_return_state: State,
@@ -359,23 +408,34 @@ impl VTParser {
             },
 
             params,
+            is_subparam: [false; MAX_PARAMS],
             num_params: 0,
             params_full: false,
             current_param: None,
+            next_is_subparam: false,
 
             utf8_parser: Utf8Parser::new(),
         }
     }
 
+    fn push_param(&mut self, val: i64) {
+        self.params[self.num_params] = val;
+        self.is_subparam[self.num_params] = self.next_is_subparam;
+        self.num_params += 1;
+    }
+
     fn finish_param(&mut self) {
         if let Some(val) = self.current_param.take() {
             if self.num_params < MAX_PARAMS {
-                self.params[self.num_params] = val;
-                self.num_params += 1;
+                self.push_param(val);
             }
         }
     }
 
+    fn has_subparams(&self) -> bool {
+        self.is_subparam[0..self.num_params].iter().any(|&sub| sub)
+    }
+
     fn action(&mut self, action: Action, param: u8, actor: &mut dyn VTActor) {
         match action {
             Action::None | Action::Ignore => {}
@@ -388,6 +448,7 @@ impl VTParser {
                 self.osc.full = false;
                 self.num_params = 0;
                 self.params_full = false;
+                self.next_is_subparam = false;
                 self.current_param.take();
             }
             Action::Collect => {
@@ -402,12 +463,13 @@ impl VTParser {
                 if self.params_full {
                     return;
                 }
-                if param == b';' {
+                if param == b';' || param == b':' {
                     if self.num_params + 1 > MAX_OSC {
                         self.params_full = true;
                     } else {
-                        self.params[self.num_params] = self.current_param.take().unwrap_or(0);
-                        self.num_params += 1;
+                        let val = self.current_param.take().unwrap_or(0);
+                        self.push_param(val);
+                        self.next_is_subparam = param == b':';
                     }
                 } else {
                     let current = self.current_param.take().unwrap_or(0);
@@ -425,6 +487,7 @@ impl VTParser {
                     &self.params[0..self.num_params],
                     &self.intermediates[0..self.num_intermediates],
                     self.ignored_excess_intermediates,
+                    param,
                 );
             }
             Action::Put => actor.dcs_put(param),
@@ -439,12 +502,31 @@ impl VTParser {
             }
             Action::CsiDispatch => {
                 self.finish_param();
-                actor.csi_dispatch(
-                    &self.params[0..self.num_params],
-                    &self.intermediates[0..self.num_intermediates],
-                    self.ignored_excess_intermediates,
-                    param,
-                );
+                if self.has_subparams() {
+                    let mut groups: [&[i64]; MAX_PARAMS] = [&[]; MAX_PARAMS];
+                    let mut num_groups = 0;
+                    let mut start = 0;
+                    for end in 1..=self.num_params {
+                        if end == self.num_params || !self.is_subparam[end] {
+                            groups[num_groups] = &self.params[start..end];
+                            num_groups += 1;
+                            start = end;
+                        }
+                    }
+                    actor.csi_dispatch_with_subparams(
+                        &groups[0..num_groups],
+                        &self.intermediates[0..self.num_intermediates],
+                        self.ignored_excess_intermediates,
+                        param,
+                    );
+                } else {
+                    actor.csi_dispatch(
+                        &self.params[0..self.num_params],
+                        &self.intermediates[0..self.num_intermediates],
+                        self.ignored_excess_intermediates,
+                        param,
+                    );
+                }
             }
             Action::Unhook => actor.dcs_unhook(),
             Action::OscStart => {
@@ -528,6 +610,12 @@ impl VTParser {
             match self.utf8_return_state {
                 State::Ground => actor.print(c),
                 State::OscString => self.osc.put(c),
+                State::DcsPassthrough => {
+                    let mut buf = [0u8; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        actor.dcs_put(b);
+                    }
+                }
                 state => panic!("unreachable state {:?}", state),
             };
             self.state = self.utf8_return_state;
@@ -554,7 +642,13 @@ impl VTParser {
                 self.action(lookup_exit(self.state), 0, actor);
             }
             self.action(action, byte, actor);
-            self.action(lookup_entry(state), 0, actor);
+            // The byte is passed to the entry action so that the
+            // DCS hook can see the final character of the sequence.
+            // The utf-8 state is not part of the tables and has no
+            // entry action.
+            if state != State::Utf8Sequence {
+                self.action(lookup_entry(state), byte, actor);
+            }
             self.utf8_return_state = self.state;
             self.state = state;
         } else {
@@ -760,6 +854,56 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_dcs() {
+        assert_eq!(
+            parse_as_vec(b"\x1bP0;1q#0!3~\x1b\\"),
+            vec![
+                VTAction::DcsHook {
+                    params: vec![0, 1],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'q',
+                },
+                VTAction::DcsPut(b'#'),
+                VTAction::DcsPut(b'0'),
+                VTAction::DcsPut(b'!'),
+                VTAction::DcsPut(b'3'),
+                VTAction::DcsPut(b'~'),
+                VTAction::DcsUnhook,
+                VTAction::EscDispatch {
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'\\',
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csi_subparams() {
+        assert_eq!(
+            parse_as_vec(b"\x1b[1;4:3;58:2::255:0:128m"),
+            vec![VTAction::CsiDispatchWithSubParams {
+                params: vec![vec![1], vec![4, 3], vec![58, 2, 0, 255, 0, 128]],
+                intermediates: b"".to_vec(),
+                ignored_excess_intermediates: false,
+                byte: b'm'
+            }]
+        );
+        // Without any colons the regular dispatch is used
+        assert_eq!(
+            parse_as_vec(b"\x1b[4;3m"),
+            vec![VTAction::CsiDispatch {
+                params: vec![4, 3],
+                intermediates: b"".to_vec(),
+                ignored_excess_intermediates: false,
+                byte: b'm'
+            }]
+        );
+    }
+
     #[test]
     fn osc_utf8() {
         assert_eq!(
@@ -768,6 +912,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn dcs_utf8() {
+        assert_eq!(
+            parse_as_vec("\x1bP1000p\u{af}\x1b\\".as_bytes()),
+            vec![
+                VTAction::DcsHook {
+                    params: vec![1000],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'p',
+                },
+                VTAction::DcsPut(0xc2),
+                VTAction::DcsPut(0xaf),
+                VTAction::DcsUnhook,
+                VTAction::EscDispatch {
+                    params: vec![],
+                    intermediates: vec![],
+                    ignored_excess_intermediates: false,
+                    byte: b'\\',
+                },
+            ]
+        );
+    }
+
     #[test]
     fn osc_fedora_vte() {
         assert_eq!(
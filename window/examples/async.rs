@@ -64,6 +64,7 @@ async fn spawn_window() -> Result<(), Box<dyn std::error::Error>> {
         "the title",
         800,
         600,
+        WindowTheme::System,
         Box::new(MyWindow {
             allow_close: false,
             cursor_pos: Point::new(100, 200),
@@ -88,6 +88,7 @@ fn spawn_window() -> anyhow::Result<()> {
         "the title",
         800,
         600,
+        WindowTheme::System,
         Box::new(MyWindow {
             allow_close: false,
             cursor_pos: Point::new(100, 200),
@@ -34,6 +34,7 @@ fn spawn_window() -> anyhow::Result<()> {
         "the title",
         800,
         600,
+        WindowTheme::System,
         Box::new(MyWindow {
             allow_close: false,
             cursor_pos: (100, 200),
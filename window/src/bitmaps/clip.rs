@@ -0,0 +1,166 @@
+//! Clipping for the software rendered `PaintContext` implementations.
+//! Each of them keeps a `ClipStack` and routes its drawing through the
+//! functions in this module, which restrict it to the current clip.
+use crate::bitmaps::BitmapImage;
+use crate::color::Color;
+use crate::{Operator, Point, Rect, Size};
+
+/// The stack of clipping rectangles maintained by `push_clip` and
+/// `pop_clip`.  Each pushed rectangle is intersected with the one
+/// below it, so the current clip never grows.
+#[derive(Debug, Default)]
+pub struct ClipStack {
+    stack: Vec<Rect>,
+}
+
+impl ClipStack {
+    pub fn push(&mut self, rect: Rect) {
+        let rect = match self.current() {
+            Some(current) => current
+                .intersection(&rect)
+                .unwrap_or_else(|| Rect::new(rect.origin, Size::zero())),
+            None => rect,
+        };
+        self.stack.push(rect);
+    }
+
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Returns the current clip, or None if drawing is unrestricted
+    pub fn current(&self) -> Option<Rect> {
+        self.stack.last().copied()
+    }
+}
+
+pub fn clear(image: &mut dyn BitmapImage, clip: Option<Rect>, color: Color) {
+    match clip {
+        Some(clip) => image.clear_rect(clip, color),
+        None => image.clear(color),
+    }
+}
+
+pub fn clear_rect(image: &mut dyn BitmapImage, clip: Option<Rect>, rect: Rect, color: Color) {
+    let rect = match clip {
+        Some(clip) => match clip.intersection(&rect) {
+            Some(rect) => rect,
+            None => return,
+        },
+        None => rect,
+    };
+    image.clear_rect(rect, color);
+}
+
+pub fn draw_image(
+    image: &mut dyn BitmapImage,
+    clip: Option<Rect>,
+    dest_top_left: Point,
+    src_rect: Option<Rect>,
+    im: &dyn BitmapImage,
+    operator: Operator,
+) {
+    let clip = match clip {
+        Some(clip) => clip,
+        None => return image.draw_image(dest_top_left, src_rect, im, operator),
+    };
+    let (im_width, im_height) = im.image_dimensions();
+    let src_rect = src_rect
+        .unwrap_or_else(|| Rect::from_size(Size::new(im_width as isize, im_height as isize)));
+    let dest_rect = Rect::new(dest_top_left, src_rect.size);
+    if let Some(visible) = dest_rect.intersection(&clip) {
+        let offset = visible.origin - dest_rect.origin;
+        let src_rect = Rect::new(src_rect.origin + offset, visible.size);
+        image.draw_image(visible.origin, Some(src_rect), im, operator);
+    }
+}
+
+pub fn draw_line(
+    image: &mut dyn BitmapImage,
+    clip: Option<Rect>,
+    start: Point,
+    end: Point,
+    color: Color,
+    operator: Operator,
+) {
+    match clip {
+        Some(clip) => image.draw_line_within(start, end, color, operator, clip),
+        None => image.draw_line(start, end, color, operator),
+    }
+}
+
+/// Scrolls the part of `rect` that is inside the clip
+pub fn scroll_region(
+    image: &mut dyn BitmapImage,
+    clip: Option<Rect>,
+    rect: Rect,
+    dx: isize,
+    dy: isize,
+) {
+    let rect = match clip {
+        Some(clip) => match clip.intersection(&rect) {
+            Some(rect) => rect,
+            None => return,
+        },
+        None => rect,
+    };
+    image.scroll_rect(rect, dx, dy);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bitmaps::Image;
+
+    #[test]
+    fn stack() {
+        let mut stack = ClipStack::default();
+        assert_eq!(stack.current(), None);
+
+        stack.push(Rect::new(Point::new(0, 0), Size::new(10, 10)));
+        stack.push(Rect::new(Point::new(5, 5), Size::new(10, 10)));
+        assert_eq!(
+            stack.current(),
+            Some(Rect::new(Point::new(5, 5), Size::new(5, 5)))
+        );
+
+        // A clip outside of the current one leaves nothing to draw on
+        stack.push(Rect::new(Point::new(20, 20), Size::new(1, 1)));
+        assert!(stack.current().unwrap().is_empty());
+
+        stack.pop();
+        stack.pop();
+        assert_eq!(
+            stack.current(),
+            Some(Rect::new(Point::new(0, 0), Size::new(10, 10)))
+        );
+        stack.pop();
+        assert_eq!(stack.current(), None);
+    }
+
+    fn numbered(width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+        for (idx, pixel) in image.pixels_mut().iter_mut().enumerate() {
+            *pixel = idx as u32;
+        }
+        image
+    }
+
+    #[test]
+    fn scroll() {
+        let mut image = numbered(3, 3);
+        let all = Rect::from_size(Size::new(3, 3));
+        scroll_region(&mut image, None, all, 0, -1);
+        assert_eq!(image.pixels(), &[3, 4, 5, 6, 7, 8, 6, 7, 8]);
+
+        let mut image = numbered(3, 3);
+        scroll_region(&mut image, None, all, 0, 1);
+        assert_eq!(image.pixels(), &[0, 1, 2, 0, 1, 2, 3, 4, 5]);
+
+        // Only the clipped part of the region moves
+        let mut image = numbered(3, 3);
+        let clip = Rect::new(Point::new(1, 0), Size::new(2, 3));
+        scroll_region(&mut image, Some(clip), all, -1, 0);
+        assert_eq!(image.pixels(), &[0, 2, 2, 3, 5, 5, 6, 8, 8]);
+    }
+}
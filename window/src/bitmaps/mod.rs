@@ -6,6 +6,7 @@ use palette::LinSrgba;
 use std::cell::RefCell;
 
 pub mod atlas;
+pub mod clip;
 
 pub struct TextureUnit;
 pub type TextureCoord = euclid::Point2D<f32, TextureUnit>;
@@ -303,11 +304,46 @@ pub trait BitmapImage {
         }
     }
 
+    /// Multiplies the color channels of every pixel by its alpha,
+    /// so that the image can be scaled or composited with
+    /// `Operator::OverPremultiplied` without fringes around the
+    /// transparent areas
+    fn premultiply_alpha(&mut self) {
+        for pix in self.pixels_mut() {
+            *pix = Color(*pix).premultiply().0;
+        }
+    }
+
+    /// Reverses `premultiply_alpha`
+    fn demultiply_alpha(&mut self) {
+        for pix in self.pixels_mut() {
+            *pix = Color(*pix).demultiply().0;
+        }
+    }
+
     /// Draw a line starting at `start` and ending at `end`.
     /// The line will be anti-aliased and applied to the surface using the
     /// specified Operator.
     fn draw_line(&mut self, start: Point, end: Point, color: Color, operator: Operator) {
         let (dim_width, dim_height) = self.image_dimensions();
+        let bounds = Rect::from_size(Size::new(dim_width as isize, dim_height as isize));
+        self.draw_line_within(start, end, color, operator, bounds);
+    }
+
+    /// Like `draw_line`, but only the pixels inside `bounds` are drawn
+    fn draw_line_within(
+        &mut self,
+        start: Point,
+        end: Point,
+        color: Color,
+        operator: Operator,
+        bounds: Rect,
+    ) {
+        let (dim_width, dim_height) = self.image_dimensions();
+        let min_x = bounds.min_x().max(0);
+        let min_y = bounds.min_y().max(0);
+        let max_x = bounds.max_x().min(dim_width as isize);
+        let max_y = bounds.max_y().min(dim_height as isize);
         let linear: LinSrgba = color.into();
         let (red, green, blue, alpha) = linear.into_components();
 
@@ -315,10 +351,7 @@ pub trait BitmapImage {
             (start.x as f32, start.y as f32),
             (end.x as f32, end.y as f32),
         ) {
-            if y < 0 || x < 0 {
-                continue;
-            }
-            if y >= dim_height as isize || x >= dim_width as isize {
+            if y < min_y || x < min_x || y >= max_y || x >= max_x {
                 continue;
             }
             let pix = self.pixel_mut(x as usize, y as usize);
@@ -328,6 +361,47 @@ pub trait BitmapImage {
         }
     }
 
+    /// Moves the pixels inside `rect` by `dx` columns and `dy` rows.
+    /// Pixels that would move outside of `rect` are discarded, and
+    /// the area that they leave behind keeps its old content, so it
+    /// needs to be repainted.
+    fn scroll_rect(&mut self, rect: Rect, dx: isize, dy: isize) {
+        let (width, height) = self.image_dimensions();
+        let bounds = Rect::from_size(Size::new(width as isize, height as isize));
+        let rect = match rect.intersection(&bounds) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let copy_width = rect.size.width - dx.abs();
+        let copy_height = rect.size.height - dy.abs();
+        if copy_width <= 0 || copy_height <= 0 {
+            return;
+        }
+
+        let src_x = (rect.min_x() + (-dx).max(0)) as usize;
+        let dest_x = (rect.min_x() + dx.max(0)) as usize;
+        let src_y = rect.min_y() + (-dy).max(0);
+        let dest_y = rect.min_y() + dy.max(0);
+        let copy_width = copy_width as usize;
+
+        let pixels = self.pixels_mut();
+        let mut copy_row = |row: isize| {
+            let src = (src_y + row) as usize * width + src_x;
+            let dest = (dest_y + row) as usize * width + dest_x;
+            pixels.copy_within(src..src + copy_width, dest);
+        };
+        // Copy in the order that reads each row before it is overwritten
+        if dy > 0 {
+            for row in (0..copy_height).rev() {
+                copy_row(row);
+            }
+        } else {
+            for row in 0..copy_height {
+                copy_row(row);
+            }
+        }
+    }
+
     /// Draw a 1-pixel wide rectangle
     fn draw_rect(&mut self, rect: Rect, color: Color, operator: Operator) {
         let bottom_right = rect.origin.add_size(&rect.size);
@@ -401,6 +475,39 @@ pub trait BitmapImage {
     }
 }
 
+/// Converts pixel data between rgba32 and bgra32 in place; the same
+/// swap of the red and blue bytes works in either direction
+pub fn swap_red_and_blue(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+/// How the pixels of a scaled image are computed from those of
+/// the original
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingFilter {
+    /// Use the nearest pixel; fast, and keeps hard edges hard
+    Nearest,
+    /// Interpolate linearly between the neighboring pixels
+    Bilinear,
+    /// A cubic filter that works well for enlarging
+    Mitchell,
+    /// A sharper filter that works well for shrinking
+    Lanczos3,
+}
+
+impl ScalingFilter {
+    fn resize_type(self) -> resize::Type {
+        match self {
+            Self::Nearest => resize::Type::Point,
+            Self::Bilinear => resize::Type::Triangle,
+            Self::Mitchell => resize::Type::Mitchell,
+            Self::Lanczos3 => resize::Type::Lanczos3,
+        }
+    }
+}
+
 /// A bitmap in big endian bgra32 color format, with storage
 /// in a Vec<u8>.
 pub struct Image {
@@ -520,24 +627,46 @@ impl Image {
         image
     }
 
+    /// Returns the pixel data in rgba32 format, with no padding
+    /// between the rows
+    pub fn to_rgba32(&self) -> Vec<u8> {
+        let mut data = self.data.clone();
+        swap_red_and_blue(&mut data);
+        data
+    }
+
     /// Creates a new image with the contents of the current image, but
-    /// resized to the specified dimensions.
+    /// resized to the specified dimensions.  Lanczos3 is used when
+    /// shrinking the image, and Mitchell when enlarging it.
     pub fn resize(&self, width: usize, height: usize) -> Image {
-        let mut dest = Image::new(width, height);
-        let algo = if (width * height) < (self.width * self.height) {
-            resize::Type::Lanczos3
+        let filter = if (width * height) < (self.width * self.height) {
+            ScalingFilter::Lanczos3
         } else {
-            resize::Type::Mitchell
+            ScalingFilter::Mitchell
         };
+        self.resize_with(width, height, filter)
+    }
+
+    /// Creates a new image with the contents of the current image,
+    /// resized to the specified dimensions using `filter`.
+    /// The pixels are premultiplied while they are filtered, so that
+    /// the color of transparent pixels doesn't bleed into their
+    /// neighbors.
+    pub fn resize_with(&self, width: usize, height: usize, filter: ScalingFilter) -> Image {
+        let mut src = Image::from_raw(self.width, self.height, self.data.clone());
+        src.premultiply_alpha();
+
+        let mut dest = Image::new(width, height);
         resize::new(
             self.width,
             self.height,
             width,
             height,
             resize::Pixel::RGBA,
-            algo,
+            filter.resize_type(),
         )
-        .resize(&self.data, &mut dest.data);
+        .resize(&src.data, &mut dest.data);
+        dest.demultiply_alpha();
         dest
     }
 
@@ -187,7 +187,7 @@ fn srgb8_to_linear_f32(val: u8) -> f32 {
 }
 
 /// A color stored as big endian bgra32
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Color(pub u32);
 
 impl From<LinSrgba> for Color {
@@ -267,6 +267,13 @@ impl From<Color> for Srgba {
     }
 }
 
+/// Returns `a * b / 255`, rounded to the nearest integer
+#[inline]
+fn mul_u8(a: u8, b: u8) -> u8 {
+    let t = u32::from(a) * u32::from(b) + 128;
+    ((t + (t >> 8)) >> 8) as u8
+}
+
 impl Color {
     #[inline]
     pub fn rgb(red: u8, green: u8, blue: u8) -> Color {
@@ -297,6 +304,35 @@ impl Color {
         c.into_format().into_components()
     }
 
+    /// Returns the color with its red, green and blue channels
+    /// multiplied by its alpha
+    #[inline]
+    pub fn premultiply(self) -> Color {
+        let (red, green, blue, alpha) = self.as_rgba();
+        Color::rgba(
+            mul_u8(red, alpha),
+            mul_u8(green, alpha),
+            mul_u8(blue, alpha),
+            alpha,
+        )
+    }
+
+    /// Reverses `premultiply`.  Some precision is lost for colors
+    /// that are nearly transparent, and the color of a fully
+    /// transparent pixel can't be recovered at all.
+    #[inline]
+    pub fn demultiply(self) -> Color {
+        let (red, green, blue, alpha) = self.as_rgba();
+        if alpha == 0 {
+            return Color::rgba(0, 0, 0, 0);
+        }
+        let unmul = |c: u8| {
+            let a = u32::from(alpha);
+            ((u32::from(c) * 255 + a / 2) / a).min(255) as u8
+        };
+        Color::rgba(unmul(red), unmul(green), unmul(blue), alpha)
+    }
+
     /// Compute the composite of two colors according to the supplied operator.
     /// self is the src operand, dest is the dest operand.
     #[inline]
@@ -327,6 +363,75 @@ impl Color {
                 let dest: LinSrgba = dest.into();
                 tinted.over(dest).into()
             }
+            Operator::OverPremultiplied => {
+                let (sr, sg, sb, sa) = self.as_rgba();
+                let (dr, dg, db, da) = dest.as_rgba();
+                let over = |s: u8, d: u8| s.saturating_add(mul_u8(d, 255 - sa));
+                Color::rgba(over(sr, dr), over(sg, dg), over(sb, db), over(sa, da))
+            }
+            Operator::Add => {
+                let (sr, sg, sb, sa) = self.as_rgba();
+                let (dr, dg, db, da) = dest.as_rgba();
+                Color::rgba(
+                    sr.saturating_add(dr),
+                    sg.saturating_add(dg),
+                    sb.saturating_add(db),
+                    sa.saturating_add(da),
+                )
+            }
+            Operator::DestOut => {
+                let (_, _, _, sa) = self.as_rgba();
+                let (dr, dg, db, da) = dest.as_rgba();
+                Color::rgba(dr, dg, db, mul_u8(da, 255 - sa))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn premultiply() {
+        let color = Color::rgba(0xff, 0x80, 0x00, 0x80);
+        assert_eq!(color.premultiply().as_rgba(), (0x80, 0x40, 0x00, 0x80));
+        assert_eq!(
+            color.premultiply().demultiply().as_rgba(),
+            (0xff, 0x80, 0, 0x80)
+        );
+
+        let opaque = Color::rgb(0x12, 0x34, 0x56);
+        assert_eq!(opaque.premultiply(), opaque);
+        assert_eq!(opaque.demultiply(), opaque);
+
+        assert_eq!(
+            Color::rgba(0xff, 0xff, 0xff, 0).premultiply().as_rgba(),
+            (0, 0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn operators() {
+        let dest = Color::rgb(0, 0, 0xff);
+        let half_red = Color::rgba(0xff, 0, 0, 0x80).premultiply();
+        assert_eq!(
+            half_red
+                .composite(dest, Operator::OverPremultiplied)
+                .as_rgba(),
+            (0x80, 0, 0x7f, 0xff)
+        );
+
+        assert_eq!(
+            Color::rgba(0x80, 0x80, 0x80, 0x80)
+                .composite(Color::rgba(0xc0, 0x10, 0, 0x80), Operator::Add)
+                .as_rgba(),
+            (0xff, 0x90, 0x80, 0xff)
+        );
+
+        assert_eq!(
+            half_red.composite(dest, Operator::DestOut).as_rgba(),
+            (0, 0, 0xff, 0x7f)
+        );
+    }
+}
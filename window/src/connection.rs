@@ -5,6 +5,44 @@ use std::rc::Rc;
 
 thread_local! {
     static CONN: RefCell<Option<Rc<Connection>>> = RefCell::new(None);
+    static LAST_WINDOW_CLOSED: RefCell<Option<Box<dyn FnMut() -> LastWindowClosed>>> =
+        RefCell::new(None);
+}
+
+/// What the connection does once its last window has been destroyed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LastWindowClosed {
+    /// Keep running the message loop; the application decides
+    /// when to terminate it
+    KeepRunning,
+    /// Terminate the message loop
+    TerminateMessageLoop,
+}
+
+/// Called by the platform connections once the last of their windows
+/// has been destroyed
+pub(crate) fn last_window_closed() {
+    // The callback is taken out while it runs so that it can replace
+    // itself without tripping over the borrow
+    let callback = LAST_WINDOW_CLOSED.with(|cb| cb.borrow_mut().take());
+    let action = match callback {
+        Some(mut callback) => {
+            let action = callback();
+            LAST_WINDOW_CLOSED.with(|cb| {
+                let mut cb = cb.borrow_mut();
+                if cb.is_none() {
+                    cb.replace(callback);
+                }
+            });
+            action
+        }
+        None => LastWindowClosed::KeepRunning,
+    };
+    if action == LastWindowClosed::TerminateMessageLoop {
+        if let Some(conn) = Connection::get() {
+            conn.terminate_message_loop();
+        }
+    }
 }
 
 pub trait ConnectionOps {
@@ -31,4 +69,18 @@ pub trait ConnectionOps {
 
     // TODO: return a handle that can be used to cancel the timer
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F);
+
+    /// Sounds the system bell
+    fn beep(&self);
+
+    /// Sets the function that is called once the last window has been
+    /// destroyed, whether it was closed by the user or by the
+    /// application.  Its return value decides whether the message loop
+    /// keeps running; without a callback it always does.
+    fn set_last_window_closed_callback<F: FnMut() -> LastWindowClosed + 'static>(
+        &self,
+        callback: F,
+    ) {
+        LAST_WINDOW_CLOSED.with(|cb| *cb.borrow_mut() = Some(Box::new(callback)));
+    }
 }
@@ -149,9 +149,19 @@ pub struct KeyEvent {
     /// Which modifiers are down
     pub modifiers: Modifiers,
 
-    /// How many times this key repeats
+    /// How many key presses this event represents.
+    /// This is always at least 1.  Some systems (eg: Windows)
+    /// coalesce multiple auto-repeated presses into a single
+    /// event when the application falls behind, in which case
+    /// this value will be greater than 1.
     pub repeat_count: u16,
 
+    /// If true, this key down event was generated by the system
+    /// auto-repeat mechanism while the key was held down, rather
+    /// than by the initial physical press of the key.
+    /// This is always false for key up events.
+    pub is_repeat: bool,
+
     /// If true, this is a key down rather than a key up event
     pub key_is_down: bool,
 }
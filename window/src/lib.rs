@@ -5,6 +5,7 @@ pub mod color;
 pub mod connection;
 pub mod input;
 pub mod os;
+pub mod panicpolicy;
 mod spawn;
 mod tasks;
 mod timerlist;
@@ -24,6 +25,7 @@ pub use color::Color;
 pub use connection::*;
 pub use input::*;
 pub use os::*;
+pub use panicpolicy::{set_panic_hook, set_panic_policy, PanicPolicy, PanicReport};
 
 /// Compositing operator.
 /// We implement a small subset of possible compositing operators.
@@ -46,6 +48,15 @@ pub enum Operator {
     /// This is used to colorize the src and then blend the
     /// result into the destination.
     MultiplyThenOver(Color),
+    /// Like Over, but for a src whose color channels have been
+    /// premultiplied by its alpha, as `Color::premultiply` does.
+    /// The result is premultiplied too.
+    OverPremultiplied,
+    /// Add src to dest, clamping each channel at its maximum
+    Add,
+    /// Ignore the color of src and scale the alpha of dest by the
+    /// transparency of src, cutting the shape of src out of dest
+    DestOut,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -90,6 +101,21 @@ pub trait PaintContext {
     );
 
     fn draw_line(&mut self, start: Point, end: Point, color: Color, operator: Operator);
+
+    /// Restrict subsequent drawing to `rect`, intersected with the
+    /// current clip, until the matching `pop_clip`
+    fn push_clip(&mut self, rect: Rect);
+
+    /// Restore the clip that was in effect before the most recent
+    /// `push_clip`
+    fn pop_clip(&mut self);
+
+    /// Move the content of `rect` by `dx` pixels horizontally and
+    /// `dy` pixels vertically, discarding whatever moves outside of
+    /// `rect`.  The area that is uncovered by the move is left as it
+    /// was, and should be repainted by the caller.  This is much
+    /// cheaper than repainting the whole of `rect` when scrolling.
+    fn scroll_region(&mut self, rect: Rect, dx: isize, dy: isize);
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -99,9 +125,82 @@ pub enum MouseCursor {
     Text,
 }
 
+/// Selects which of the system clipboards to operate upon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clipboard {
+    /// The clipboard that is manipulated by explicit copy and
+    /// paste operations
+    Clipboard,
+    /// The X11/Wayland primary selection, which holds the most
+    /// recently selected text and is traditionally pasted with
+    /// the middle mouse button.  Systems that have no primary
+    /// selection use the clipboard in its place.
+    PrimarySelection,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Clipboard::Clipboard
+    }
+}
+
+/// Constrains the interactive resizing of a window so that the
+/// size of its client area is the base size plus a whole number
+/// of increments, such as the size of a terminal cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeIncrements {
+    pub base_width: usize,
+    pub base_height: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+/// Controls the stacking order of a window relative to other
+/// windows on the desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowLevel {
+    /// The normal stacking order
+    Normal,
+    /// Keep the window above other normal windows
+    AlwaysOnTop,
+    /// Keep the window below other normal windows
+    AlwaysBelow,
+}
+
+impl Default for WindowLevel {
+    fn default() -> Self {
+        WindowLevel::Normal
+    }
+}
+
+/// Selects the light or dark variant of the window decorations
+/// (titlebar, frame and so on) provided by the windowing system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowTheme {
+    /// Follow the system-wide preference
+    System,
+    Light,
+    Dark,
+}
+
+impl Default for WindowTheme {
+    fn default() -> Self {
+        WindowTheme::System
+    }
+}
+
+/// A standard command from the Edit menu of the application's
+/// menu bar, which is directed at the key window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuCommand {
+    Copy,
+    Paste,
+}
+
 #[allow(unused_variables)]
 pub trait WindowCallbacks: Any {
-    /// Called when the window close button is clicked.
+    /// Called when the window close button is clicked, or when
+    /// `WindowOps::request_close` is used.
     /// Return true to allow the close to continue, false to
     /// prevent it from closing.
     fn can_close(&mut self) -> bool {
@@ -117,6 +216,26 @@ pub trait WindowCallbacks: Any {
     /// Called when window gains/loses focus
     fn focus_change(&mut self, focused: bool) {}
 
+    /// Called when the user starts to interactively resize or move
+    /// the window.  Until `live_resize_ended` is called, painting may
+    /// be throttled, and `resize` is called repeatedly as the frame
+    /// is dragged, so this is a good time to switch to a cheaper way
+    /// of painting.  Only Windows reports this.
+    fn live_resize_started(&mut self) {}
+
+    /// Called when the interactive resize or move that was reported
+    /// by `live_resize_started` has finished.  A final `resize` (if
+    /// the size is snapped to the resize increments) and a full
+    /// repaint follow.
+    fn live_resize_ended(&mut self) {}
+
+    /// Called when the system-wide light/dark appearance preference
+    /// changes.  `theme` is either `WindowTheme::Light` or
+    /// `WindowTheme::Dark`.  Windows that were created with (or set to)
+    /// `WindowTheme::System` will have already had their decorations
+    /// updated to match by the time this is called.
+    fn system_theme_changed(&mut self, theme: WindowTheme) {}
+
     /// Called when the window contents need painting.
     /// This is used only when the software renderer is enabled (which
     /// is the default).  When the window is set to opengl mode, the
@@ -149,6 +268,15 @@ pub trait WindowCallbacks: Any {
     /// app to reference the window and operate upon it.
     fn created(&mut self, window: &Window) {}
 
+    /// Called when an entry that was added to the window menu by
+    /// `add_system_menu_item` is chosen.  `id` is the identifier
+    /// that the entry was added with.
+    fn system_menu_item_selected(&mut self, id: u16, context: &dyn WindowOps) {}
+
+    /// Called when a command is chosen from the application's menu
+    /// bar while this is the key window.  Only macOS has such a menu.
+    fn menu_command(&mut self, command: MenuCommand, context: &dyn WindowOps) {}
+
     /// An unfortunate bit of boilerplate; you need to provie an impl
     /// of this method that returns `self` in order for the downcast_ref
     /// method of the Any trait to be usable on WindowCallbacks.
@@ -164,9 +292,15 @@ pub trait WindowOps {
     /// Hide a visible window
     fn hide(&self) -> Future<()>;
 
-    /// Schedule the window to be closed
+    /// Schedule the window to be closed.  The window is destroyed
+    /// without consulting `WindowCallbacks::can_close`.
     fn close(&self) -> Future<()>;
 
+    /// Ask the window to close, as though its close button had been
+    /// clicked: it is only closed if `WindowCallbacks::can_close`
+    /// allows it
+    fn request_close(&self) -> Future<()>;
+
     /// Change the cursor
     fn set_cursor(&self, cursor: Option<MouseCursor>) -> Future<()>;
 
@@ -174,6 +308,14 @@ pub trait WindowOps {
     /// be repainted shortly
     fn invalidate(&self) -> Future<()>;
 
+    /// Invalidate the part of the window's client area described by
+    /// `rect`.  Where the system supports it, only that part is
+    /// presented when the window is next painted, which is cheaper
+    /// than invalidating the whole window when only a little of it
+    /// has changed.  The `paint` callback must still draw everything
+    /// that changed, as it isn't told which part was invalidated.
+    fn invalidate_rect(&self, rect: Rect) -> Future<()>;
+
     /// Change the titlebar text for the window
     fn set_title(&self, title: &str) -> Future<()>;
 
@@ -187,6 +329,57 @@ pub trait WindowOps {
         Future::ok(())
     }
 
+    /// Change the stacking level of the window.
+    /// Not all windowing systems support this; those that
+    /// don't will silently ignore the request.
+    fn set_window_level(&self, _level: WindowLevel) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Hint to the windowing system that the window should (or
+    /// should not) be shown in the taskbar, pager or Dock.
+    /// This is useful for drop-down style terminal windows.
+    fn set_skip_taskbar(&self, _skip: bool) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Change the light/dark theme used for the window decorations
+    fn set_window_theme(&self, _theme: WindowTheme) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Constrain interactive resizing of the window to the specified
+    /// increments, or allow any size if `increments` is None.
+    /// Not all windowing systems support this; those that
+    /// don't will silently ignore the request.
+    fn set_resize_increments(&self, _increments: Option<ResizeIncrements>) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Control whether pressing Alt+Space opens the window menu.
+    /// When disabled, the key press is only passed to `key_event`,
+    /// so that it can be bound to something else.
+    /// Only Windows has such a menu; other systems ignore this.
+    fn set_alt_space_opens_system_menu(&self, _enable: bool) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Add an entry with the text `label` to the window menu (the
+    /// menu under the icon in the titlebar); choosing it calls
+    /// `WindowCallbacks::system_menu_item_selected` with `id`.
+    /// Adding an entry with an `id` that is already present replaces
+    /// that entry.  Only Windows has such a menu; other systems
+    /// ignore this.
+    fn add_system_menu_item(&self, _id: u16, _label: &str) -> Future<()> {
+        Future::ok(())
+    }
+
+    /// Show or hide a check mark beside the window menu entry that
+    /// was added with `id`, such as for an "Always on top" toggle
+    fn set_system_menu_item_checked(&self, _id: u16, _checked: bool) -> Future<()> {
+        Future::ok(())
+    }
+
     /// inform the windowing system of the current textual
     /// cursor input location.  This is used primarily for
     /// the platform specific input method editor
@@ -225,10 +418,10 @@ pub trait WindowOps {
         R: Send + 'static;
 
     /// Initiate textual transfer from the clipboard
-    fn get_clipboard(&self) -> Future<String>;
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String>;
 
     /// Set some text in the clipboard
-    fn set_clipboard(&self, text: String) -> Future<()>;
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()>;
 }
 
 pub trait WindowOpsMut {
@@ -238,9 +431,15 @@ pub trait WindowOpsMut {
     /// Hide a visible window
     fn hide(&mut self);
 
-    /// Schedule the window to be closed
+    /// Schedule the window to be closed.  The window is destroyed
+    /// without consulting `WindowCallbacks::can_close`.
     fn close(&mut self);
 
+    /// Ask the window to close, as though its close button had been
+    /// clicked: it is only closed if `WindowCallbacks::can_close`
+    /// allows it
+    fn request_close(&mut self);
+
     /// Change the cursor
     fn set_cursor(&mut self, cursor: Option<MouseCursor>);
 
@@ -248,6 +447,10 @@ pub trait WindowOpsMut {
     /// be repainted shortly
     fn invalidate(&mut self);
 
+    /// Invalidate the part of the window's client area described by
+    /// `rect`; see `WindowOps::invalidate_rect`
+    fn invalidate_rect(&mut self, rect: Rect);
+
     /// Change the titlebar text for the window
     fn set_title(&mut self, title: &str);
 
@@ -263,4 +466,22 @@ pub trait WindowOpsMut {
     /// The coordinates are of the top left pixel of the
     /// client area.
     fn set_window_position(&self, _coords: ScreenPoint) {}
+
+    /// Change the stacking level of the window
+    fn set_window_level(&mut self, _level: WindowLevel) {}
+
+    /// Hint whether the window should be shown in the taskbar
+    fn set_skip_taskbar(&mut self, _skip: bool) {}
+
+    /// Change the light/dark theme used for the window decorations
+    fn set_window_theme(&mut self, _theme: WindowTheme) {}
+
+    /// Constrain interactive resizing to the specified increments
+    fn set_resize_increments(&mut self, _increments: Option<ResizeIncrements>) {}
+
+    fn set_alt_space_opens_system_menu(&mut self, _enable: bool) {}
+
+    fn add_system_menu_item(&mut self, _id: u16, _label: &str) {}
+
+    fn set_system_menu_item_checked(&mut self, _id: u16, _checked: bool) {}
 }
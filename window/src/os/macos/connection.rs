@@ -1,26 +1,45 @@
 // let () = msg_send! is a common pattern for objc
 #![allow(clippy::let_unit_value)]
 
+use super::menu::{create_app_delegate, install_menu_bar, ApplicationCommand};
 use super::window::WindowInner;
 use crate::connection::ConnectionOps;
 use crate::spawn::*;
 use crate::tasks::{Task, Tasks};
 use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyRegular};
-use cocoa::base::{id, nil};
+use cocoa::base::{id, nil, BOOL, YES};
 use core_foundation::date::CFAbsoluteTimeGetCurrent;
 use core_foundation::runloop::*;
+use objc::rc::StrongPtr;
 use objc::*;
 use promise::BasicExecutor;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::atomic::AtomicUsize;
 
+extern "C" {
+    fn NSBeep();
+}
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn EnableSecureEventInput() -> i32;
+    fn DisableSecureEventInput() -> i32;
+}
+
 pub struct Connection {
     ns_app: id,
     pub(crate) windows: RefCell<HashMap<usize, Rc<RefCell<WindowInner>>>>,
     pub(crate) next_window_id: AtomicUsize,
     tasks: Tasks,
+    app_delegate: StrongPtr,
+    application_command_handler: RefCell<Option<Box<dyn FnMut(ApplicationCommand)>>>,
+    /// Whether secure keyboard entry was asked for
+    secure_keyboard_entry: Cell<bool>,
+    /// Whether we currently hold secure event input; it is only held
+    /// while the application is active
+    secure_event_input: Cell<bool>,
 }
 
 impl Connection {
@@ -37,11 +56,81 @@ impl Connection {
                 windows: RefCell::new(HashMap::new()),
                 tasks: Default::default(),
                 next_window_id: AtomicUsize::new(1),
+                app_delegate: create_app_delegate(),
+                application_command_handler: RefCell::new(None),
+                secure_keyboard_entry: Cell::new(false),
+                secure_event_input: Cell::new(false),
             };
             Ok(conn)
         }
     }
 
+    /// Installs the menu bar, with the application menu titled with
+    /// `app_name`, an Edit menu whose commands are passed to the key
+    /// window via `WindowCallbacks::menu_command`, and a Window menu
+    /// that lists the open windows.  Quit, both from the menu and from
+    /// the Dock, and New Window from the Dock menu are passed to
+    /// `handler`.
+    pub fn set_application_menu<F: FnMut(ApplicationCommand) + 'static>(
+        &self,
+        app_name: &str,
+        handler: F,
+    ) {
+        install_menu_bar(app_name, *self.app_delegate);
+        self.application_command_handler
+            .borrow_mut()
+            .replace(Box::new(handler));
+    }
+
+    /// Turns secure keyboard entry on or off.  While it is on and the
+    /// application is active, other processes are unable to observe
+    /// the keys that are typed, which protects passwords typed into
+    /// the terminal.  It can also be toggled from the application menu.
+    pub fn set_secure_keyboard_entry(&self, enabled: bool) {
+        self.secure_keyboard_entry.set(enabled);
+        self.update_secure_event_input();
+    }
+
+    pub fn secure_keyboard_entry(&self) -> bool {
+        self.secure_keyboard_entry.get()
+    }
+
+    pub(crate) fn update_secure_event_input(&self) {
+        let active: BOOL = unsafe { msg_send![self.ns_app, isActive] };
+        let wanted = self.secure_keyboard_entry.get() && active == YES;
+        if wanted != self.secure_event_input.get() {
+            // The calls are reference counted, so they must be paired
+            unsafe {
+                if wanted {
+                    EnableSecureEventInput();
+                } else {
+                    DisableSecureEventInput();
+                }
+            }
+            self.secure_event_input.set(wanted);
+        }
+    }
+
+    pub(crate) fn dispatch_application_command(&self, command: ApplicationCommand) {
+        // Take the handler while it runs, so that it may use
+        // the connection without tripping over the borrow
+        let handler = self.application_command_handler.borrow_mut().take();
+        match handler {
+            Some(mut handler) => {
+                handler(command);
+                let mut slot = self.application_command_handler.borrow_mut();
+                if slot.is_none() {
+                    slot.replace(handler);
+                }
+            }
+            None => {
+                if command == ApplicationCommand::Quit {
+                    self.terminate_message_loop();
+                }
+            }
+        }
+    }
+
     pub(crate) fn next_window_id(&self) -> usize {
         self.next_window_id
             .fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
@@ -109,6 +198,12 @@ impl ConnectionOps for Connection {
         }));
     }
 
+    fn beep(&self) {
+        unsafe {
+            NSBeep();
+        }
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         let secs_f64 =
             (interval.as_secs() as f64) + (f64::from(interval.subsec_nanos()) / 1_000_000_000_f64);
@@ -0,0 +1,263 @@
+//! The application menu bar and the Dock menu.
+//! The standard items use the usual AppKit actions and are sent up the
+//! responder chain, so AppKit handles hiding, minimizing and closing
+//! windows, and the Edit items reach the view of the key window.
+// let () = msg_send! is a common pattern for objc
+#![allow(clippy::let_unit_value)]
+
+use super::nsstring;
+use crate::connection::ConnectionOps;
+use crate::Connection;
+use cocoa::appkit::{NSApp, NSEventModifierFlags};
+use cocoa::base::{id, BOOL, YES};
+use cocoa::foundation::NSUInteger;
+use objc::declare::ClassDecl;
+use objc::rc::StrongPtr;
+use objc::runtime::{Class, Object, Sel};
+use objc::*;
+
+/// A command from the application menu or the Dock menu that is not
+/// directed at a particular window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplicationCommand {
+    /// Quit was chosen from the application menu, with Cmd-Q or from
+    /// the Dock
+    Quit,
+    /// New Window was chosen from the Dock menu
+    NewWindow,
+}
+
+const CLS_NAME: &str = "WezTermAppDelegate";
+
+/// The reply to `applicationShouldTerminate:` that stops AppKit from
+/// terminating the process; the handler decides what happens instead
+const NS_TERMINATE_CANCEL: NSUInteger = 0;
+
+fn dispatch(command: ApplicationCommand) {
+    if let Some(conn) = Connection::get() {
+        conn.dispatch_application_command(command);
+    }
+}
+
+extern "C" fn application_should_terminate(_this: &mut Object, _sel: Sel, _app: id) -> NSUInteger {
+    dispatch(ApplicationCommand::Quit);
+    NS_TERMINATE_CANCEL
+}
+
+extern "C" fn application_dock_menu(this: &mut Object, _sel: Sel, _app: id) -> id {
+    unsafe {
+        let menu = new_menu("");
+        let item = add_item(*menu, "New Window", sel!(newWindow:), "", None);
+        let () = msg_send![item, setTarget: this as *mut Object];
+        // The caller doesn't take ownership of the returned menu
+        let menu: id = msg_send![*menu, retain];
+        msg_send![menu, autorelease]
+    }
+}
+
+extern "C" fn new_window(_this: &mut Object, _sel: Sel, _sender: id) {
+    dispatch(ApplicationCommand::NewWindow);
+}
+
+extern "C" fn toggle_secure_keyboard_entry(_this: &mut Object, _sel: Sel, _sender: id) {
+    if let Some(conn) = Connection::get() {
+        conn.set_secure_keyboard_entry(!conn.secure_keyboard_entry());
+    }
+}
+
+/// Secure keyboard entry affects every application, so it is only
+/// really turned on while this one is active
+extern "C" fn application_active_changed(_this: &mut Object, _sel: Sel, _notification: id) {
+    if let Some(conn) = Connection::get() {
+        conn.update_secure_event_input();
+    }
+}
+
+/// Called by AppKit before showing a menu item that targets the
+/// delegate; keeps the check mark of Secure Keyboard Entry up to date
+extern "C" fn validate_menu_item(_this: &mut Object, _sel: Sel, item: id) -> BOOL {
+    unsafe {
+        let action: Sel = msg_send![item, action];
+        if action == sel!(toggleSecureKeyboardEntry:) {
+            let enabled = Connection::get()
+                .map(|conn| conn.secure_keyboard_entry())
+                .unwrap_or(false);
+            let state: isize = if enabled { 1 } else { 0 };
+            let () = msg_send![item, setState: state];
+        }
+    }
+    YES
+}
+
+fn get_class() -> &'static Class {
+    Class::get(CLS_NAME).unwrap_or_else(define_class)
+}
+
+fn define_class() -> &'static Class {
+    let mut cls =
+        ClassDecl::new(CLS_NAME, class!(NSObject)).expect("Unable to register AppDelegate class");
+
+    unsafe {
+        cls.add_method(
+            sel!(applicationShouldTerminate:),
+            application_should_terminate as extern "C" fn(&mut Object, Sel, id) -> NSUInteger,
+        );
+        cls.add_method(
+            sel!(applicationDockMenu:),
+            application_dock_menu as extern "C" fn(&mut Object, Sel, id) -> id,
+        );
+        cls.add_method(
+            sel!(newWindow:),
+            new_window as extern "C" fn(&mut Object, Sel, id),
+        );
+        cls.add_method(
+            sel!(toggleSecureKeyboardEntry:),
+            toggle_secure_keyboard_entry as extern "C" fn(&mut Object, Sel, id),
+        );
+        cls.add_method(
+            sel!(applicationDidBecomeActive:),
+            application_active_changed as extern "C" fn(&mut Object, Sel, id),
+        );
+        cls.add_method(
+            sel!(applicationDidResignActive:),
+            application_active_changed as extern "C" fn(&mut Object, Sel, id),
+        );
+        cls.add_method(
+            sel!(validateMenuItem:),
+            validate_menu_item as extern "C" fn(&mut Object, Sel, id) -> BOOL,
+        );
+    }
+
+    cls.register()
+}
+
+/// Creates the application delegate, which answers the Quit command,
+/// provides the Dock menu and looks after secure keyboard entry.  NSApp doesn't retain its delegate, so
+/// the caller must keep it alive.
+pub(crate) fn create_app_delegate() -> StrongPtr {
+    unsafe {
+        let delegate = StrongPtr::new(msg_send![get_class(), new]);
+        let () = msg_send![NSApp(), setDelegate: *delegate];
+        delegate
+    }
+}
+
+unsafe fn new_menu(title: &str) -> StrongPtr {
+    let menu: id = msg_send![class!(NSMenu), alloc];
+    StrongPtr::new(msg_send![menu, initWithTitle: *nsstring(title)])
+}
+
+/// Adds an item to `menu` and returns it; the menu holds the only
+/// reference to it.  Items without a target are sent to the first
+/// responder that implements `action`.
+unsafe fn add_item(
+    menu: id,
+    title: &str,
+    action: Sel,
+    key: &str,
+    modifiers: Option<NSEventModifierFlags>,
+) -> id {
+    let item: id = msg_send![class!(NSMenuItem), alloc];
+    let item = StrongPtr::new(msg_send![item,
+        initWithTitle: *nsstring(title)
+        action: action
+        keyEquivalent: *nsstring(key)]);
+    if let Some(modifiers) = modifiers {
+        let () = msg_send![*item, setKeyEquivalentModifierMask: modifiers.bits()];
+    }
+    let () = msg_send![menu, addItem: *item];
+    *item
+}
+
+unsafe fn add_separator(menu: id) {
+    let separator: id = msg_send![class!(NSMenuItem), separatorItem];
+    let () = msg_send![menu, addItem: separator];
+}
+
+/// Adds a top level menu called `title` to `menu_bar` and returns it
+unsafe fn add_submenu(menu_bar: id, title: &str) -> StrongPtr {
+    let submenu = new_menu(title);
+    let item = add_item(menu_bar, title, sel!(submenuAction:), "", None);
+    let () = msg_send![item, setSubmenu: *submenu];
+    submenu
+}
+
+/// Builds the menu bar and makes it the main menu of the application.
+/// Only Quit, Hide, Hide Others and Close Window have key equivalents;
+/// the Edit commands are left to the key assignments of the window so
+/// that they can be rebound.
+pub(crate) fn install_menu_bar(app_name: &str, delegate: id) {
+    let command = NSEventModifierFlags::NSCommandKeyMask;
+    let command_option = command | NSEventModifierFlags::NSAlternateKeyMask;
+    unsafe {
+        let menu_bar = new_menu("");
+
+        // AppKit always titles the first menu with the name of the app
+        let app_menu = add_submenu(*menu_bar, app_name);
+        let item = add_item(
+            *app_menu,
+            "Secure Keyboard Entry",
+            sel!(toggleSecureKeyboardEntry:),
+            "",
+            None,
+        );
+        let () = msg_send![item, setTarget: delegate];
+        add_separator(*app_menu);
+        add_item(
+            *app_menu,
+            &format!("Hide {}", app_name),
+            sel!(hide:),
+            "h",
+            Some(command),
+        );
+        add_item(
+            *app_menu,
+            "Hide Others",
+            sel!(hideOtherApplications:),
+            "h",
+            Some(command_option),
+        );
+        add_item(
+            *app_menu,
+            "Show All",
+            sel!(unhideAllApplications:),
+            "",
+            None,
+        );
+        add_separator(*app_menu);
+        add_item(
+            *app_menu,
+            &format!("Quit {}", app_name),
+            sel!(terminate:),
+            "q",
+            Some(command),
+        );
+
+        let edit_menu = add_submenu(*menu_bar, "Edit");
+        add_item(*edit_menu, "Copy", sel!(copy:), "", None);
+        add_item(*edit_menu, "Paste", sel!(paste:), "", None);
+
+        let window_menu = add_submenu(*menu_bar, "Window");
+        add_item(
+            *window_menu,
+            "Minimize",
+            sel!(performMiniaturize:),
+            "",
+            None,
+        );
+        add_item(*window_menu, "Zoom", sel!(performZoom:), "", None);
+        add_separator(*window_menu);
+        add_item(
+            *window_menu,
+            "Close Window",
+            sel!(performClose:),
+            "w",
+            Some(command),
+        );
+
+        let app = NSApp();
+        let () = msg_send![app, setMainMenu: *menu_bar];
+        // AppKit appends the list of open windows to this menu
+        let () = msg_send![app, setWindowsMenu: *window_menu];
+    }
+}
@@ -5,6 +5,7 @@ use objc::runtime::Object;
 
 pub mod bitmap;
 pub mod connection;
+pub mod menu;
 pub mod window;
 
 mod keycodes;
@@ -12,6 +13,7 @@ mod keycodes;
 pub use self::window::*;
 pub use bitmap::*;
 pub use connection::*;
+pub use menu::ApplicationCommand;
 
 /// Convert a rust string to a cocoa string
 fn nsstring(s: &str) -> StrongPtr {
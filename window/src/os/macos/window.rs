@@ -2,19 +2,22 @@
 #![allow(clippy::let_unit_value)]
 
 use super::{nsstring, nsstring_to_str};
+use crate::bitmaps::clip::{self, ClipStack};
 use crate::bitmaps::Image;
 use crate::connection::ConnectionOps;
 use crate::os::macos::bitmap::BitmapRef;
 use crate::{
-    BitmapImage, Color, Connection, Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons,
-    MouseCursor, MouseEvent, MouseEventKind, MousePress, Operator, PaintContext, Point, Rect,
-    ScreenPoint, Size, WindowCallbacks, WindowOps, WindowOpsMut,
+    BitmapImage, Clipboard, Color, Connection, Dimensions, KeyCode, KeyEvent, MenuCommand,
+    Modifiers, MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress, Operator,
+    PaintContext, Point, Rect, ResizeIncrements, ScreenPoint, Size, WindowCallbacks, WindowLevel,
+    WindowOps, WindowOpsMut, WindowTheme,
 };
 use anyhow::{anyhow, bail, ensure};
 use cocoa::appkit::{
-    NSApplicationActivateIgnoringOtherApps, NSBackingStoreBuffered, NSEvent, NSEventModifierFlags,
-    NSRunningApplication, NSScreen, NSView, NSViewHeightSizable, NSViewWidthSizable, NSWindow,
-    NSWindowStyleMask,
+    NSApp, NSApplication, NSApplicationActivateIgnoringOtherApps,
+    NSApplicationActivationPolicyAccessory, NSApplicationActivationPolicyRegular,
+    NSBackingStoreBuffered, NSEvent, NSEventModifierFlags, NSRunningApplication, NSScreen, NSView,
+    NSViewHeightSizable, NSViewWidthSizable, NSWindow, NSWindowStyleMask,
 };
 use cocoa::base::*;
 use cocoa::foundation::{NSArray, NSNotFound, NSPoint, NSRect, NSSize, NSUInteger};
@@ -263,6 +266,7 @@ impl Window {
         name: &str,
         width: usize,
         height: usize,
+        theme: WindowTheme,
         callbacks: Box<dyn WindowCallbacks>,
     ) -> anyhow::Result<Window> {
         unsafe {
@@ -286,6 +290,7 @@ impl Window {
                 #[cfg(feature = "opengl")]
                 gl_context_pair: None,
                 text_cursor_position: Rect::new(Point::new(0, 0), Size::new(0, 0)),
+                key_is_repeat: false,
             }));
 
             let window = StrongPtr::new(
@@ -311,6 +316,14 @@ impl Window {
             window.setContentView_(*view);
             window.setDelegate_(*view);
 
+            // Ask to be told when the system appearance changes
+            let center: id = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+            let () = msg_send![center,
+                addObserver: *view
+                selector: sel!(systemThemeChanged:)
+                name: *nsstring("AppleInterfaceThemeChangedNotification")
+                object: nil];
+
             let frame = NSView::frame(*view);
             let backing_frame = NSView::convertRectToBacking(*view, frame);
             let width = backing_frame.size.width;
@@ -321,6 +334,7 @@ impl Window {
                 window,
                 view,
             }));
+            window_inner.borrow_mut().set_window_theme(theme);
             conn.windows
                 .borrow_mut()
                 .insert(window_id, Rc::clone(&window_inner));
@@ -350,6 +364,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn request_close(&self) -> Future<()> {
+        Connection::with_window_inner(self.0, |inner| {
+            inner.request_close();
+            Ok(())
+        })
+    }
+
     fn hide(&self) -> Future<()> {
         Connection::with_window_inner(self.0, |inner| {
             inner.hide();
@@ -378,6 +399,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn invalidate_rect(&self, rect: Rect) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.invalidate_rect(rect);
+            Ok(())
+        })
+    }
+
     fn set_title(&self, title: &str) -> Future<()> {
         let title = title.to_owned();
         Connection::with_window_inner(self.0, move |inner| {
@@ -407,6 +435,34 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        })
+    }
+
+    fn set_skip_taskbar(&self, skip: bool) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_skip_taskbar(skip);
+            Ok(())
+        })
+    }
+
+    fn set_window_theme(&self, theme: WindowTheme) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_theme(theme);
+            Ok(())
+        })
+    }
+
+    fn set_resize_increments(&self, increments: Option<ResizeIncrements>) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(increments);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         func: F,
@@ -464,7 +520,7 @@ impl WindowOps for Window {
         })
     }
 
-    fn get_clipboard(&self) -> Future<String> {
+    fn get_clipboard(&self, _clipboard: Clipboard) -> Future<String> {
         use clipboard::ClipboardProvider;
         Future::result(
             clipboard::ClipboardContext::new()
@@ -473,7 +529,7 @@ impl WindowOps for Window {
         )
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, _clipboard: Clipboard, text: String) -> Future<()> {
         use clipboard::ClipboardProvider;
         Future::result(
             clipboard::ClipboardContext::new()
@@ -530,6 +586,14 @@ impl WindowOpsMut for WindowInner {
         }
     }
 
+    fn request_close(&mut self) {
+        // performClose: goes through windowShouldClose: just as
+        // clicking the close button does
+        unsafe {
+            let () = msg_send![*self.window, performClose: nil];
+        }
+    }
+
     fn hide(&mut self) {}
 
     fn set_cursor(&mut self, cursor: Option<MouseCursor>) {
@@ -551,6 +615,18 @@ impl WindowOpsMut for WindowInner {
             let () = msg_send![*self.view, setNeedsDisplay: YES];
         }
     }
+
+    fn invalidate_rect(&mut self, rect: Rect) {
+        let backing_rect = NSRect::new(
+            NSPoint::new(rect.origin.x as f64, rect.origin.y as f64),
+            NSSize::new(rect.size.width as f64, rect.size.height as f64),
+        );
+        unsafe {
+            // The view is flipped, so its origin is also the top left
+            let rect: NSRect = msg_send![*self.view, convertRectFromBacking: backing_rect];
+            let () = msg_send![*self.view, setNeedsDisplayInRect: rect];
+        }
+    }
     fn set_title(&mut self, title: &str) {
         let title = nsstring(title);
         unsafe {
@@ -595,6 +671,75 @@ impl WindowOpsMut for WindowInner {
             let () = msg_send![input_context, invalidateCharacterCoordinates];
         }
     }
+
+    fn set_window_level(&mut self, level: WindowLevel) {
+        // These correspond to kCGNormalWindowLevel, kCGFloatingWindowLevel
+        // and one step below kCGNormalWindowLevel respectively
+        let level = match level {
+            WindowLevel::Normal => 0,
+            WindowLevel::AlwaysOnTop => 3,
+            WindowLevel::AlwaysBelow => -1,
+        };
+        unsafe {
+            NSWindow::setLevel_(*self.window, level);
+        }
+    }
+
+    fn set_window_theme(&mut self, theme: WindowTheme) {
+        unsafe {
+            let appearance: id = match theme {
+                WindowTheme::System => nil,
+                WindowTheme::Light => {
+                    msg_send![class!(NSAppearance), appearanceNamed: *nsstring("NSAppearanceNameAqua")]
+                }
+                WindowTheme::Dark => {
+                    msg_send![class!(NSAppearance), appearanceNamed: *nsstring("NSAppearanceNameDarkAqua")]
+                }
+            };
+            let () = msg_send![*self.window, setAppearance: appearance];
+        }
+    }
+
+    fn set_skip_taskbar(&mut self, skip: bool) {
+        // The Dock icon belongs to the application rather than to
+        // an individual window, so this affects the whole app.
+        unsafe {
+            NSApp().setActivationPolicy_(if skip {
+                NSApplicationActivationPolicyAccessory
+            } else {
+                NSApplicationActivationPolicyRegular
+            });
+        }
+    }
+
+    /// Cocoa has no notion of a base size, so the increments apply
+    /// to the whole content area
+    fn set_resize_increments(&mut self, increments: Option<ResizeIncrements>) {
+        unsafe {
+            let frame = NSView::frame(*self.view as *mut _);
+            let backing_frame = NSView::convertRectToBacking(*self.view as *mut _, frame);
+            let scale = backing_frame.size.width / frame.size.width;
+
+            let size = match increments {
+                Some(incr) => NSSize::new(incr.x as f64 / scale, incr.y as f64 / scale),
+                None => NSSize::new(1., 1.),
+            };
+            let () = msg_send![*self.window, setContentResizeIncrements: size];
+        }
+    }
+}
+
+/// Returns the current system-wide appearance preference
+fn system_theme() -> WindowTheme {
+    unsafe {
+        let defaults: id = msg_send![class!(NSUserDefaults), standardUserDefaults];
+        let style: id = msg_send![defaults, stringForKey: *nsstring("AppleInterfaceStyle")];
+        if style != nil && nsstring_to_str(style) == "Dark" {
+            WindowTheme::Dark
+        } else {
+            WindowTheme::Light
+        }
+    }
 }
 
 struct Inner {
@@ -604,6 +749,10 @@ struct Inner {
     #[cfg(feature = "opengl")]
     gl_context_pair: Option<opengl::GlContextPair>,
     text_cursor_position: Rect,
+    /// The `isARepeat` state of the key event that is currently
+    /// being processed by the input context.  Used to populate
+    /// events synthesized by `doCommandBySelector`.
+    key_is_repeat: bool,
 }
 
 const CLS_NAME: &str = "WezTermWindowView";
@@ -623,15 +772,16 @@ pub fn superclass(this: &Object) -> &'static Class {
 struct MacGraphicsContext<'a> {
     buffer: &'a mut dyn BitmapImage,
     dpi: usize,
+    clip: ClipStack,
 }
 
 impl<'a> PaintContext for MacGraphicsContext<'a> {
     fn clear_rect(&mut self, rect: Rect, color: Color) {
-        self.buffer.clear_rect(rect, color)
+        clip::clear_rect(self.buffer, self.clip.current(), rect, color)
     }
 
     fn clear(&mut self, color: Color) {
-        self.buffer.clear(color);
+        clip::clear(self.buffer, self.clip.current(), color);
     }
 
     fn get_dimensions(&self) -> Dimensions {
@@ -650,12 +800,37 @@ impl<'a> PaintContext for MacGraphicsContext<'a> {
         im: &dyn BitmapImage,
         operator: Operator,
     ) {
-        self.buffer
-            .draw_image(dest_top_left, src_rect, im, operator)
+        clip::draw_image(
+            self.buffer,
+            self.clip.current(),
+            dest_top_left,
+            src_rect,
+            im,
+            operator,
+        )
     }
 
     fn draw_line(&mut self, start: Point, end: Point, color: Color, operator: Operator) {
-        self.buffer.draw_line(start, end, color, operator);
+        clip::draw_line(
+            self.buffer,
+            self.clip.current(),
+            start,
+            end,
+            color,
+            operator,
+        );
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        self.clip.push(rect);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clip.pop();
+    }
+
+    fn scroll_region(&mut self, rect: Rect, dx: isize, dy: isize) {
+        clip::scroll_region(self.buffer, self.clip.current(), rect, dx, dy);
     }
 }
 
@@ -720,6 +895,9 @@ impl WindowView {
     /// `dealloc` and `windowWillClose` to `drop_inner`.
     fn drop_inner(this: &mut Object) {
         unsafe {
+            let center: id = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+            let () = msg_send![center, removeObserver: this as *mut Object];
+
             let myself: *mut c_void = *this.get_ivar(CLS_NAME);
             this.set_ivar(CLS_NAME, std::ptr::null_mut() as *mut c_void);
 
@@ -762,16 +940,16 @@ impl WindowView {
             }
         };
 
-        let event = KeyEvent {
-            key,
-            raw_key: None,
-            modifiers,
-            repeat_count: 1,
-            key_is_down: true,
-        };
-
         if let Some(myself) = Self::get_this(this) {
             let mut inner = myself.inner.borrow_mut();
+            let event = KeyEvent {
+                key,
+                raw_key: None,
+                modifiers,
+                repeat_count: 1,
+                is_repeat: inner.key_is_repeat,
+                key_is_down: true,
+            };
             let window = Window(inner.window_id);
             inner.callbacks.key_event(&event, &window);
         }
@@ -803,6 +981,7 @@ impl WindowView {
             raw_key: None,
             modifiers: Modifiers::default(),
             repeat_count: 1,
+            is_repeat: false,
             key_is_down: true,
         };
 
@@ -902,6 +1081,25 @@ impl WindowView {
         YES
     }
 
+    fn menu_command(this: &mut Object, command: MenuCommand) {
+        if let Some(myself) = Self::get_this(this) {
+            let mut inner = myself.inner.borrow_mut();
+            let window = Window(inner.window_id);
+            inner.callbacks.menu_command(command, &window);
+        }
+    }
+
+    // The Edit menu items are sent up the responder chain, which
+    // delivers them here when this is the key window
+
+    extern "C" fn copy(this: &mut Object, _sel: Sel, _sender: id) {
+        Self::menu_command(this, MenuCommand::Copy);
+    }
+
+    extern "C" fn paste(this: &mut Object, _sel: Sel, _sender: id) {
+        Self::menu_command(this, MenuCommand::Paste);
+    }
+
     extern "C" fn window_should_close(this: &mut Object, _sel: Sel, _id: id) -> BOOL {
         unsafe {
             let () = msg_send![this, setNeedsDisplay: YES];
@@ -930,6 +1128,20 @@ impl WindowView {
         }
     }
 
+    /// Called via NSDistributedNotificationCenter when the user
+    /// toggles between light and dark mode in System Preferences.
+    /// Windows with no explicit appearance follow the system
+    /// automatically, so all we need to do is tell the app.
+    extern "C" fn system_theme_changed(this: &mut Object, _sel: Sel, _notification: id) {
+        if let Some(this) = Self::get_this(this) {
+            let theme = system_theme();
+            this.inner
+                .borrow_mut()
+                .callbacks
+                .system_theme_changed(theme);
+        }
+    }
+
     // Switch the coordinate system to have 0,0 in the top left
     extern "C" fn is_flipped(_this: &Object, _sel: Sel) -> BOOL {
         YES
@@ -938,7 +1150,23 @@ impl WindowView {
     extern "C" fn window_will_close(this: &mut Object, _sel: Sel, _id: id) {
         if let Some(this) = Self::get_this(this) {
             // Advise the window of its impending death
-            this.inner.borrow_mut().callbacks.destroy();
+            let mut inner = this.inner.borrow_mut();
+            inner.callbacks.destroy();
+
+            // Forgetting the window releases the NSWindow, which must
+            // not happen while it is still in the middle of closing
+            let window_id = inner.window_id;
+            Future::with_executor(Connection::executor(), move || {
+                let conn = Connection::get().unwrap();
+                let last_window = {
+                    let mut windows = conn.windows.borrow_mut();
+                    windows.remove(&window_id).is_some() && windows.is_empty()
+                };
+                if last_window {
+                    crate::connection::last_window_closed();
+                }
+                Ok(())
+            });
         }
 
         // Release and zero out the inner member
@@ -1006,7 +1234,7 @@ impl WindowView {
     }
 
     fn key_common(this: &mut Object, nsevent: id, key_is_down: bool) {
-        // let is_a_repeat = unsafe { nsevent.isARepeat() == YES };
+        let is_a_repeat = key_is_down && unsafe { nsevent.isARepeat() == YES };
         let chars = unsafe { nsstring_to_str(nsevent.characters()) };
         let unmod = unsafe { nsstring_to_str(nsevent.charactersIgnoringModifiers()) };
         let modifiers = unsafe { key_modifiers(nsevent.modifierFlags()) };
@@ -1025,6 +1253,10 @@ impl WindowView {
                 unmod
             };
 
+        if let Some(myself) = Self::get_this(this) {
+            myself.inner.borrow_mut().key_is_repeat = is_a_repeat;
+        }
+
         if modifiers.is_empty() {
             unsafe {
                 let input_context: id = msg_send![this, inputContext];
@@ -1125,6 +1357,7 @@ impl WindowView {
                 raw_key,
                 modifiers,
                 repeat_count: 1,
+                is_repeat: is_a_repeat,
                 key_is_down,
             };
 
@@ -1213,6 +1446,7 @@ impl WindowView {
             let mut ctx = MacGraphicsContext {
                 buffer: &mut *buffer,
                 dpi: (96.0 * backing_frame.size.width / frame.size.width) as usize,
+                clip: ClipStack::default(),
             };
 
             inner.callbacks.paint(&mut ctx);
@@ -1316,6 +1550,10 @@ impl WindowView {
                 sel!(windowDidResignKey:),
                 Self::did_resign_key as extern "C" fn(&mut Object, Sel, id),
             );
+            cls.add_method(
+                sel!(systemThemeChanged:),
+                Self::system_theme_changed as extern "C" fn(&mut Object, Sel, id),
+            );
 
             cls.add_method(
                 sel!(mouseMoved:),
@@ -1369,6 +1607,15 @@ impl WindowView {
                 Self::accepts_first_responder as extern "C" fn(&mut Object, Sel) -> BOOL,
             );
 
+            cls.add_method(
+                sel!(copy:),
+                Self::copy as extern "C" fn(&mut Object, Sel, id),
+            );
+            cls.add_method(
+                sel!(paste:),
+                Self::paste as extern "C" fn(&mut Object, Sel, id),
+            );
+
             // NSTextInputClient
 
             cls.add_method(
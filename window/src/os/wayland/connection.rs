@@ -97,6 +97,12 @@ impl WaylandConnection {
             &environment.data_device_manager,
         )?;
 
+        // The toolkit has no support for completing a startup sequence
+        // on wayland, but the tokens mustn't be inherited by the
+        // programs that we spawn either
+        std::env::remove_var("DESKTOP_STARTUP_ID");
+        std::env::remove_var("XDG_ACTIVATION_TOKEN");
+
         Ok(Self {
             display: RefCell::new(display),
             event_q: RefCell::new(event_q),
@@ -269,6 +275,10 @@ impl ConnectionOps for WaylandConnection {
         Ok(())
     }
 
+    fn beep(&self) {
+        // Wayland has no protocol for sounding the bell
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         self.timers.borrow_mut().insert(TimerEntry {
             callback: Box::new(callback),
@@ -10,6 +10,9 @@ use toolkit::reexports::client::protocol::wl_data_source::WlDataSource;
 pub struct CopyAndPaste {
     data_offer: Option<WlDataOffer>,
     last_serial: u32,
+    /// We don't implement the primary selection protocol, so the
+    /// primary selection is only shared between our own windows
+    pub primary: Option<String>,
 }
 
 impl std::fmt::Debug for CopyAndPaste {
@@ -17,6 +20,7 @@ impl std::fmt::Debug for CopyAndPaste {
         fmt.debug_struct("CopyAndPaste")
             .field("last_serial", &self.last_serial)
             .field("data_offer", &self.data_offer.is_some())
+            .field("primary", &self.primary.is_some())
             .finish()
     }
 }
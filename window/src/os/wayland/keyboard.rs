@@ -43,6 +43,7 @@ impl Inner {
             rawkey,
             keysym,
             is_down: true,
+            is_repeat: true,
             utf8,
         });
     }
@@ -111,6 +112,7 @@ pub enum KeyboardEvent {
         rawkey: u32,
         keysym: u32,
         is_down: bool,
+        is_repeat: bool,
         serial: u32,
         utf8: Option<String>,
     },
@@ -135,6 +137,7 @@ impl KeyboardEvent {
                 rawkey,
                 keysym,
                 is_down: state == KeyState::Pressed,
+                is_repeat: false,
                 serial,
                 utf8,
             },
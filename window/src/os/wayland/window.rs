@@ -1,6 +1,7 @@
 use super::copy_and_paste::*;
 use super::keyboard::KeyboardEvent;
 use super::pointer::*;
+use crate::bitmaps::clip::{self, ClipStack};
 use crate::bitmaps::BitmapImage;
 use crate::color::Color;
 use crate::connection::ConnectionOps;
@@ -8,8 +9,8 @@ use crate::input::*;
 use crate::os::wayland::connection::WaylandConnection;
 use crate::os::xkeysyms::keysym_to_keycode;
 use crate::{
-    Connection, Dimensions, MouseCursor, Operator, PaintContext, Point, Rect, ScreenPoint, Window,
-    WindowCallbacks, WindowOps, WindowOpsMut,
+    Clipboard, Connection, Dimensions, MouseCursor, Operator, PaintContext, Point, Rect,
+    ScreenPoint, Size, Window, WindowCallbacks, WindowOps, WindowOpsMut, WindowTheme,
 };
 use anyhow::{anyhow, bail, Context};
 use filedescriptor::FileDescriptor;
@@ -89,6 +90,9 @@ pub struct WaylandWindowInner {
     pool: MemPool,
     dimensions: Dimensions,
     need_paint: bool,
+    /// The part of the window that needs to be repainted, when
+    /// that isn't the whole of it
+    damaged: Option<Rect>,
     last_mouse_coords: Point,
     mouse_buttons: MouseButtons,
     modifiers: Modifiers,
@@ -154,6 +158,9 @@ impl WaylandWindow {
         name: &str,
         width: usize,
         height: usize,
+        // Our client side decorations are rendered by MyTheme,
+        // which doesn't have a light/dark variant, so we ignore this
+        _theme: WindowTheme,
         callbacks: Box<dyn WindowCallbacks>,
     ) -> anyhow::Result<Window> {
         let conn = WaylandConnection::get()
@@ -235,6 +242,7 @@ impl WaylandWindow {
             pool,
             dimensions,
             need_paint: true,
+            damaged: None,
             last_mouse_coords: Point::new(0, 0),
             mouse_buttons: MouseButtons::NONE,
             modifiers: Modifiers::NONE,
@@ -263,6 +271,7 @@ impl WaylandWindowInner {
                 rawkey,
                 keysym,
                 is_down,
+                is_repeat,
                 utf8,
                 serial,
             } => {
@@ -297,6 +306,7 @@ impl WaylandWindowInner {
                     raw_key,
                     modifiers: self.modifiers,
                     repeat_count: 1,
+                    is_repeat,
                 };
                 self.callbacks
                     .key_event(&key_event, &Window::Wayland(WaylandWindow(self.window_id)));
@@ -424,9 +434,8 @@ impl WaylandWindowInner {
             pending = pending_events.clone();
             *pending_events = PendingEvent::default();
         }
-        if pending.close && self.callbacks.can_close() {
-            self.callbacks.destroy();
-            self.window.take();
+        if pending.close {
+            self.request_close();
         }
 
         if pending.configure.is_none() && pending.dpi.is_some() {
@@ -475,6 +484,7 @@ impl WaylandWindowInner {
                 }
 
                 self.refresh_frame();
+                self.damaged = None;
                 self.do_paint().unwrap();
             }
         }
@@ -529,6 +539,7 @@ impl WaylandWindowInner {
             mmap: self.pool.mmap(),
             dimensions: (self.dimensions.pixel_width, self.dimensions.pixel_height),
             dpi,
+            clip: ClipStack::default(),
         };
         self.callbacks.paint(&mut context);
         context.mmap.flush()?;
@@ -542,7 +553,8 @@ impl WaylandWindowInner {
         );
 
         self.surface.attach(Some(&buffer), 0, 0);
-        self.damage();
+        let damaged = self.damaged.take();
+        self.damage(damaged);
 
         self.surface.commit();
         self.refresh_frame();
@@ -551,23 +563,31 @@ impl WaylandWindowInner {
         Ok(())
     }
 
-    fn damage(&mut self) {
+    /// Tells the compositor which part of the buffer changed; all of
+    /// it if `rect` is None
+    fn damage(&mut self, rect: Option<Rect>) {
+        let rect = rect.unwrap_or_else(|| {
+            Rect::from_size(Size::new(
+                self.dimensions.pixel_width as isize,
+                self.dimensions.pixel_height as isize,
+            ))
+        });
         if self.surface.as_ref().version() >= 4 {
             self.surface.damage_buffer(
-                0,
-                0,
-                self.dimensions.pixel_width as i32,
-                self.dimensions.pixel_height as i32,
+                rect.origin.x as i32,
+                rect.origin.y as i32,
+                rect.size.width as i32,
+                rect.size.height as i32,
             );
         } else {
             // Older versions use the surface size which is the pre-scaled
             // dimensions.  Since we store the scaled dimensions, we need
             // to compensate here.
             self.surface.damage(
-                0,
-                0,
-                self.pixels_to_surface(self.dimensions.pixel_width as i32),
-                self.pixels_to_surface(self.dimensions.pixel_height as i32),
+                self.pixels_to_surface(rect.origin.x as i32),
+                self.pixels_to_surface(rect.origin.y as i32),
+                self.pixels_to_surface(rect.size.width as i32),
+                self.pixels_to_surface(rect.size.height as i32),
             );
         }
     }
@@ -577,6 +597,7 @@ struct MmapImage<'a> {
     mmap: &'a mut memmap::MmapMut,
     dimensions: (usize, usize),
     dpi: usize,
+    clip: ClipStack,
 }
 
 impl<'a> BitmapImage for MmapImage<'a> {
@@ -595,11 +616,13 @@ impl<'a> BitmapImage for MmapImage<'a> {
 
 impl<'a> PaintContext for MmapImage<'a> {
     fn clear_rect(&mut self, rect: Rect, color: Color) {
-        BitmapImage::clear_rect(self, rect, color)
+        let current = self.clip.current();
+        clip::clear_rect(self, current, rect, color)
     }
 
     fn clear(&mut self, color: Color) {
-        BitmapImage::clear(self, color);
+        let current = self.clip.current();
+        clip::clear(self, current, color);
     }
 
     fn get_dimensions(&self) -> Dimensions {
@@ -618,11 +641,26 @@ impl<'a> PaintContext for MmapImage<'a> {
         im: &dyn BitmapImage,
         operator: Operator,
     ) {
-        BitmapImage::draw_image(self, dest_top_left, src_rect, im, operator)
+        let current = self.clip.current();
+        clip::draw_image(self, current, dest_top_left, src_rect, im, operator)
     }
 
     fn draw_line(&mut self, start: Point, end: Point, color: Color, operator: Operator) {
-        BitmapImage::draw_line(self, start, end, color, operator);
+        let current = self.clip.current();
+        clip::draw_line(self, current, start, end, color, operator);
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        self.clip.push(rect);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clip.pop();
+    }
+
+    fn scroll_region(&mut self, rect: Rect, dx: isize, dy: isize) {
+        let current = self.clip.current();
+        clip::scroll_region(self, current, rect, dx, dy);
     }
 }
 
@@ -634,6 +672,13 @@ impl WindowOps for WaylandWindow {
         })
     }
 
+    fn request_close(&self) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.request_close();
+            Ok(())
+        })
+    }
+
     fn hide(&self) -> Future<()> {
         WaylandConnection::with_window_inner(self.0, |inner| {
             inner.hide();
@@ -662,6 +707,13 @@ impl WindowOps for WaylandWindow {
         })
     }
 
+    fn invalidate_rect(&self, rect: Rect) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.invalidate_rect(rect);
+            Ok(())
+        })
+    }
+
     fn set_title(&self, title: &str) -> Future<()> {
         let title = title.to_owned();
         WaylandConnection::with_window_inner(self.0, move |inner| {
@@ -755,11 +807,16 @@ impl WindowOps for WaylandWindow {
         })
     }
 
-    fn get_clipboard(&self) -> Future<String> {
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         let mut promise = Promise::new();
         let future = promise.get_future().unwrap();
         let promise = Arc::new(Mutex::new(promise));
         WaylandConnection::with_window_inner(self.0, move |inner| {
+            if clipboard == Clipboard::PrimarySelection {
+                let text = inner.copy_and_paste.lock().unwrap().primary.clone();
+                promise.lock().unwrap().ok(text.unwrap_or_else(String::new));
+                return Ok(());
+            }
             let read = inner.copy_and_paste.lock().unwrap().get_clipboard_data()?;
             let promise = Arc::clone(&promise);
             std::thread::spawn(move || {
@@ -782,9 +839,13 @@ impl WindowOps for WaylandWindow {
         future
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         WaylandConnection::with_window_inner(self.0, move |inner| {
             let text = text.clone();
+            if clipboard == Clipboard::PrimarySelection {
+                inner.copy_and_paste.lock().unwrap().primary.replace(text);
+                return Ok(());
+            }
             let conn = Connection::get().unwrap().wayland();
             let source = conn
                 .environment
@@ -882,6 +943,21 @@ impl WindowOpsMut for WaylandWindowInner {
     fn close(&mut self) {
         self.callbacks.destroy();
         self.window.take();
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let last_window = {
+            let mut windows = conn.windows.borrow_mut();
+            windows.remove(&self.window_id).is_some() && windows.is_empty()
+        };
+        if last_window {
+            crate::connection::last_window_closed();
+        }
+    }
+
+    fn request_close(&mut self) {
+        if self.callbacks.can_close() {
+            self.close();
+        }
     }
 
     fn hide(&mut self) {
@@ -897,6 +973,7 @@ impl WindowOpsMut for WaylandWindowInner {
         let conn = Connection::get().unwrap().wayland();
 
         if !conn.environment.borrow().shell.needs_configure() {
+            self.damaged = None;
             self.do_paint().unwrap();
         } else {
             self.refresh_frame();
@@ -915,6 +992,18 @@ impl WindowOpsMut for WaylandWindowInner {
     }
 
     fn invalidate(&mut self) {
+        self.need_paint = true;
+        self.damaged = None;
+        self.do_paint().unwrap();
+    }
+
+    fn invalidate_rect(&mut self, rect: Rect) {
+        self.damaged = match (self.need_paint, self.damaged) {
+            // The whole window is already due to be repainted
+            (true, None) => None,
+            (_, Some(prior)) => Some(prior.union(&rect)),
+            (false, None) => Some(rect),
+        };
         self.need_paint = true;
         self.do_paint().unwrap();
     }
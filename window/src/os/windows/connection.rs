@@ -42,6 +42,7 @@ impl ConnectionOps for Connection {
                     // trigger `drop` handlers during shutdown and that
                     // can have bad interactions
                     self.windows.borrow_mut().clear();
+                    crate::panicpolicy::resume_pending_panic();
                     return Ok(());
                 }
 
@@ -69,6 +70,12 @@ impl ConnectionOps for Connection {
         }));
     }
 
+    fn beep(&self) {
+        unsafe {
+            MessageBeep(MB_OK);
+        }
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         let millis = interval
             .as_millis()
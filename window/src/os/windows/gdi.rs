@@ -1,8 +1,10 @@
 use crate::bitmaps::BitmapImage;
+use crate::Rect;
 use anyhow::bail;
 use std::io::Error as IoError;
 use winapi::shared::windef::*;
 use winapi::um::wingdi::*;
+use winapi::um::winuser::ScrollDC;
 
 pub struct GdiBitmap {
     hdc: HDC,
@@ -24,6 +26,30 @@ impl BitmapImage for GdiBitmap {
     fn image_dimensions(&self) -> (usize, usize) {
         (self.width, self.height)
     }
+
+    /// Lets GDI move the pixels, rather than copying them row by row
+    fn scroll_rect(&mut self, rect: Rect, dx: isize, dy: isize) {
+        let rect = RECT {
+            left: rect.min_x() as i32,
+            top: rect.min_y() as i32,
+            right: rect.max_x() as i32,
+            bottom: rect.max_y() as i32,
+        };
+        unsafe {
+            ScrollDC(
+                self.hdc,
+                dx as i32,
+                dy as i32,
+                &rect,
+                &rect,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            // Make sure that GDI is done before the pixels are
+            // accessed directly again
+            GdiFlush();
+        }
+    }
 }
 
 impl Drop for GdiBitmap {
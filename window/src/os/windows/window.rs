@@ -1,22 +1,24 @@
 use super::gdi::*;
 use super::*;
+use crate::bitmaps::clip::{self, ClipStack};
 use crate::bitmaps::*;
 use crate::color::Color;
 use crate::connection::ConnectionOps;
 use crate::{
-    Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
-    MouseEventKind, MousePress, Operator, PaintContext, Point, Rect, ScreenPoint, WindowCallbacks,
-    WindowOps, WindowOpsMut,
+    Clipboard, Dimensions, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
+    MouseEventKind, MousePress, Operator, PaintContext, PanicPolicy, Point, Rect, ResizeIncrements,
+    ScreenPoint, WindowCallbacks, WindowLevel, WindowOps, WindowOpsMut, WindowTheme,
 };
 use anyhow::{bail, Context};
 use promise::Future;
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert::TryInto;
 use std::io::Error as IoError;
 use std::os::windows::ffi::OsStringExt;
 use std::ptr::{null, null_mut};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 use winapi::shared::minwindef::*;
 use winapi::shared::ntdef::*;
 use winapi::shared::windef::*;
@@ -30,6 +32,11 @@ extern "system" {
     pub fn ImmGetCompositionStringW(himc: HIMC, index: DWORD, buf: LPVOID, buflen: DWORD) -> LONG;
 }
 
+#[link(name = "shell32")]
+extern "system" {
+    fn SetCurrentProcessExplicitAppUserModelID(app_id: LPCWSTR) -> HRESULT;
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct HWindow(HWND);
 unsafe impl Send for HWindow {}
@@ -42,8 +49,26 @@ pub(crate) struct WindowInner {
     bitmap: RefCell<GdiBitmap>,
     #[cfg(feature = "opengl")]
     gl_state: Option<Rc<glium::backend::Context>>,
+    theme: WindowTheme,
+    resize_increments: Option<ResizeIncrements>,
+    alt_space_opens_system_menu: bool,
+    /// Whether the separator between the standard window menu
+    /// entries and those added by the application is present
+    has_system_menu_separator: bool,
+    /// True between WM_ENTERSIZEMOVE and WM_EXITSIZEMOVE
+    in_size_move: bool,
+    /// When the window was last painted during an interactive resize
+    last_live_resize_paint: Cell<Option<Instant>>,
 }
 
+/// The minimum time between paints while the window is being
+/// interactively resized; a paint that is requested sooner than
+/// this is deferred using a timer
+const LIVE_RESIZE_PAINT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// The id of the timer that performs a deferred paint
+const LIVE_RESIZE_PAINT_TIMER: UINT_PTR = 1;
+
 #[derive(Debug, Clone)]
 pub struct Window(HWindow);
 
@@ -116,6 +141,12 @@ impl Window {
         }
 
         let class_name = wide_string(class_name);
+
+        // The taskbar groups windows and pins them by this id, which has
+        // to be set before the first window is shown
+        unsafe {
+            SetCurrentProcessExplicitAppUserModelID(class_name.as_ptr());
+        }
         let h_inst = unsafe { GetModuleHandleW(null()) };
         let class = WNDCLASSW {
             style: CS_HREDRAW | CS_VREDRAW | CS_OWNDC | CS_DBLCLKS,
@@ -175,6 +206,7 @@ impl Window {
         name: &str,
         width: usize,
         height: usize,
+        theme: WindowTheme,
         callbacks: Box<dyn WindowCallbacks>,
     ) -> anyhow::Result<Window> {
         let inner = Rc::new(RefCell::new(WindowInner {
@@ -183,6 +215,12 @@ impl Window {
             bitmap: RefCell::new(GdiBitmap::new_empty()),
             #[cfg(feature = "opengl")]
             gl_state: None,
+            theme,
+            resize_increments: None,
+            alt_space_opens_system_menu: true,
+            has_system_menu_separator: false,
+            in_size_move: false,
+            last_live_resize_paint: Cell::new(None),
         }));
 
         // Careful: `raw` owns a ref to inner, but there is no Drop impl
@@ -197,7 +235,7 @@ impl Window {
             }
         };
 
-        enable_dark_mode(hwnd.0);
+        apply_theme(hwnd.0, theme);
 
         Connection::get()
             .expect("Connection::init was not called")
@@ -234,6 +272,14 @@ impl WindowOpsMut for WindowInner {
         });
     }
 
+    fn request_close(&mut self) {
+        // WM_CLOSE is what the close button sends; its handler
+        // consults can_close before the window is destroyed
+        unsafe {
+            PostMessageW(self.hwnd.0, WM_CLOSE, 0, 0);
+        }
+    }
+
     fn show(&mut self) {
         schedule_show_window(self.hwnd, true);
     }
@@ -252,6 +298,18 @@ impl WindowOpsMut for WindowInner {
         }
     }
 
+    fn invalidate_rect(&mut self, rect: Rect) {
+        let rect = RECT {
+            left: rect.min_x() as i32,
+            top: rect.min_y() as i32,
+            right: rect.max_x() as i32,
+            bottom: rect.max_y() as i32,
+        };
+        unsafe {
+            InvalidateRect(self.hwnd.0, &rect, 0);
+        }
+    }
+
     fn set_inner_size(&mut self, width: usize, height: usize) {
         let (width, height) = adjust_client_to_window_dimensions(width, height);
         let hwnd = self.hwnd;
@@ -307,6 +365,114 @@ impl WindowOpsMut for WindowInner {
         let imc = ImmContext::get(self.hwnd.0);
         imc.set_position(cursor.origin.x.max(0) as i32, cursor.origin.y.max(0) as i32);
     }
+
+    fn set_window_level(&mut self, level: WindowLevel) {
+        let insert_after = match level {
+            WindowLevel::Normal => HWND_NOTOPMOST,
+            WindowLevel::AlwaysOnTop => HWND_TOPMOST,
+            WindowLevel::AlwaysBelow => HWND_BOTTOM,
+        };
+        unsafe {
+            if level == WindowLevel::AlwaysBelow {
+                // HWND_BOTTOM doesn't clear the topmost state, so
+                // explicitly drop it first
+                SetWindowPos(
+                    self.hwnd.0,
+                    HWND_NOTOPMOST,
+                    0,
+                    0,
+                    0,
+                    0,
+                    SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOSIZE,
+                );
+            }
+            SetWindowPos(
+                self.hwnd.0,
+                insert_after,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOSIZE,
+            );
+        }
+    }
+
+    fn set_window_theme(&mut self, theme: WindowTheme) {
+        self.theme = theme;
+        apply_theme(self.hwnd.0, theme);
+    }
+
+    /// Windows has no built-in support for this; the increments
+    /// are applied when handling WM_SIZING
+    fn set_resize_increments(&mut self, increments: Option<ResizeIncrements>) {
+        self.resize_increments = increments;
+    }
+
+    /// This is applied when handling WM_SYSCOMMAND
+    fn set_alt_space_opens_system_menu(&mut self, enable: bool) {
+        self.alt_space_opens_system_menu = enable;
+    }
+
+    fn add_system_menu_item(&mut self, id: u16, label: &str) {
+        let command = match system_menu_command(id) {
+            Some(command) => command,
+            None => {
+                log::error!("window menu item id {} is out of range", id);
+                return;
+            }
+        };
+        let label = wide_string(label);
+        unsafe {
+            let menu = GetSystemMenu(self.hwnd.0, FALSE);
+            if menu.is_null() {
+                return;
+            }
+            if !self.has_system_menu_separator {
+                AppendMenuW(menu, MF_SEPARATOR, 0, null());
+                self.has_system_menu_separator = true;
+            }
+            RemoveMenu(menu, command as UINT, MF_BYCOMMAND);
+            AppendMenuW(menu, MF_STRING, command, label.as_ptr());
+        }
+    }
+
+    fn set_system_menu_item_checked(&mut self, id: u16, checked: bool) {
+        if let Some(command) = system_menu_command(id) {
+            unsafe {
+                let menu = GetSystemMenu(self.hwnd.0, FALSE);
+                if !menu.is_null() {
+                    let check = if checked { MF_CHECKED } else { MF_UNCHECKED };
+                    CheckMenuItem(menu, command as UINT, MF_BYCOMMAND | check);
+                }
+            }
+        }
+    }
+
+    fn set_skip_taskbar(&mut self, skip: bool) {
+        // A tool window doesn't get a taskbar button.  The shell
+        // only re-evaluates this when the window is shown, so
+        // if the window is currently visible we hide and re-show it.
+        unsafe {
+            let style = GetWindowLongPtrW(self.hwnd.0, GWL_EXSTYLE) as DWORD;
+            let new_style = if skip {
+                style | WS_EX_TOOLWINDOW
+            } else {
+                style & !WS_EX_TOOLWINDOW
+            };
+            if new_style == style {
+                return;
+            }
+            let visible = IsWindowVisible(self.hwnd.0) != 0;
+            if visible {
+                ShowWindow(self.hwnd.0, SW_HIDE);
+            }
+            SetWindowLongPtrW(self.hwnd.0, GWL_EXSTYLE, new_style as _);
+            if visible {
+                ShowWindow(self.hwnd.0, SW_SHOWNA);
+            }
+        }
+    }
 }
 
 impl WindowOps for Window {
@@ -317,6 +483,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn request_close(&self) -> Future<()> {
+        Connection::with_window_inner(self.0, |inner| {
+            inner.request_close();
+            Ok(())
+        })
+    }
+
     fn show(&self) -> Future<()> {
         schedule_show_window(self.0, true)
     }
@@ -339,6 +512,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn invalidate_rect(&self, rect: Rect) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.invalidate_rect(rect);
+            Ok(())
+        })
+    }
+
     fn set_title(&self, title: &str) -> Future<()> {
         let title = title.to_owned();
         Connection::with_window_inner(self.0, move |inner| {
@@ -368,6 +548,56 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        })
+    }
+
+    fn set_skip_taskbar(&self, skip: bool) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_skip_taskbar(skip);
+            Ok(())
+        })
+    }
+
+    fn set_window_theme(&self, theme: WindowTheme) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_window_theme(theme);
+            Ok(())
+        })
+    }
+
+    fn set_resize_increments(&self, increments: Option<ResizeIncrements>) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(increments);
+            Ok(())
+        })
+    }
+
+    fn set_alt_space_opens_system_menu(&self, enable: bool) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_alt_space_opens_system_menu(enable);
+            Ok(())
+        })
+    }
+
+    fn add_system_menu_item(&self, id: u16, label: &str) -> Future<()> {
+        let label = label.to_owned();
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.add_system_menu_item(id, &label);
+            Ok(())
+        })
+    }
+
+    fn set_system_menu_item_checked(&self, id: u16, checked: bool) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_system_menu_item_checked(id, checked);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         func: F,
@@ -423,11 +653,11 @@ impl WindowOps for Window {
         })
     }
 
-    fn get_clipboard(&self) -> Future<String> {
+    fn get_clipboard(&self, _clipboard: Clipboard) -> Future<String> {
         Future::result(clipboard_win::get_clipboard_string().context("Error getting clipboard"))
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, _clipboard: Clipboard, text: String) -> Future<()> {
         Future::result(
             clipboard_win::set_clipboard_string(&text).context("Error setting clipboard"),
         )
@@ -464,28 +694,76 @@ unsafe fn wm_ncdestroy(
         SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
     }
 
+    if let Some(conn) = Connection::get() {
+        let last_window = {
+            let mut windows = conn.windows.borrow_mut();
+            windows.remove(&HWindow(hwnd)).is_some() && windows.is_empty()
+        };
+        if last_window {
+            crate::connection::last_window_closed();
+        }
+    }
+
     None
 }
 
-fn enable_dark_mode(hwnd: HWND) {
-    // Prefer to run in dark mode. This could be made configurable without
-    // a huge amount of effort, but I think it's fine to just be always
-    // dark mode by default :-p
+/// Returns the current system-wide app theme preference, as set
+/// in the Colors section of the Personalization settings.
+fn system_theme() -> WindowTheme {
+    use winapi::um::winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+    let subkey = wide_string("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize");
+    let value = wide_string("AppsUseLightTheme");
+    let mut data: DWORD = 1;
+    let mut size = std::mem::size_of_val(&data) as DWORD;
+    let res = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            subkey.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_DWORD,
+            null_mut(),
+            &mut data as *mut _ as *mut _,
+            &mut size,
+        )
+    };
+    // If the value is missing then we're on a version of Windows
+    // that predates dark mode, which means that we're light.
+    if res == 0 && data == 0 {
+        WindowTheme::Dark
+    } else {
+        WindowTheme::Light
+    }
+}
+
+fn apply_theme(hwnd: HWND, theme: WindowTheme) {
     // Note that the MS terminal app uses the logic found here for this
     // stuff:
     // https://github.com/microsoft/terminal/blob/9b92986b49bed8cc41fde4d6ef080921c41e6d9e/src/interactivity/win32/windowtheme.cpp#L62
     use winapi::um::dwmapi::DwmSetWindowAttribute;
     use winapi::um::uxtheme::SetWindowTheme;
 
+    let dark = match theme {
+        WindowTheme::System => system_theme() == WindowTheme::Dark,
+        WindowTheme::Light => false,
+        WindowTheme::Dark => true,
+    };
+
     const DWMWA_USE_IMMERSIVE_DARK_MODE: DWORD = 19;
     unsafe {
         SetWindowTheme(
             hwnd as _,
-            wide_string("DarkMode_Explorer").as_slice().as_ptr(),
+            wide_string(if dark {
+                "DarkMode_Explorer"
+            } else {
+                "Explorer"
+            })
+            .as_slice()
+            .as_ptr(),
             std::ptr::null_mut(),
         );
 
-        let enabled: BOOL = 1;
+        let enabled: BOOL = if dark { 1 } else { 0 };
         DwmSetWindowAttribute(
             hwnd as _,
             DWMWA_USE_IMMERSIVE_DARK_MODE,
@@ -498,15 +776,16 @@ fn enable_dark_mode(hwnd: HWND) {
 struct GdiGraphicsContext<'a> {
     bitmap: &'a mut GdiBitmap,
     dpi: u32,
+    clip: ClipStack,
 }
 
 impl<'a> PaintContext for GdiGraphicsContext<'a> {
     fn clear_rect(&mut self, rect: Rect, color: Color) {
-        self.bitmap.clear_rect(rect, color)
+        clip::clear_rect(self.bitmap, self.clip.current(), rect, color)
     }
 
     fn clear(&mut self, color: Color) {
-        self.bitmap.clear(color);
+        clip::clear(self.bitmap, self.clip.current(), color);
     }
 
     fn get_dimensions(&self) -> Dimensions {
@@ -525,12 +804,37 @@ impl<'a> PaintContext for GdiGraphicsContext<'a> {
         im: &dyn BitmapImage,
         operator: Operator,
     ) {
-        self.bitmap
-            .draw_image(dest_top_left, src_rect, im, operator)
+        clip::draw_image(
+            self.bitmap,
+            self.clip.current(),
+            dest_top_left,
+            src_rect,
+            im,
+            operator,
+        )
     }
 
     fn draw_line(&mut self, start: Point, end: Point, color: Color, operator: Operator) {
-        self.bitmap.draw_line(start, end, color, operator);
+        clip::draw_line(
+            self.bitmap,
+            self.clip.current(),
+            start,
+            end,
+            color,
+            operator,
+        );
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        self.clip.push(rect);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clip.pop();
+    }
+
+    fn scroll_region(&mut self, rect: Rect, dx: isize, dy: isize) {
+        clip::scroll_region(self.bitmap, self.clip.current(), rect, dx, dy);
     }
 }
 
@@ -552,6 +856,112 @@ unsafe fn wm_size(hwnd: HWND, _msg: UINT, _wparam: WPARAM, lparam: LPARAM) -> Op
     None
 }
 
+/// Returns the size of the window rectangle `rect`, reduced so that
+/// its client area is a whole number of resize increments
+fn snap_to_increments(rect: &RECT, incr: &ResizeIncrements) -> (i32, i32) {
+    // The frame adds a fixed amount to the size of the client area
+    let (frame_width, frame_height) = adjust_client_to_window_dimensions(0, 0);
+    let snap = |size: i32, frame: i32, base: usize, step: usize| -> i32 {
+        let base = frame + base as i32;
+        let step = step.max(1) as i32;
+        base + (size - base).max(0) / step * step
+    };
+    (
+        snap(rect_width(rect), frame_width, incr.base_width, incr.x),
+        snap(rect_height(rect), frame_height, incr.base_height, incr.y),
+    )
+}
+
+/// Snaps the window rectangle that is being interactively resized
+/// to the resize increments, keeping the edge opposite to the one
+/// being dragged in place
+unsafe fn wm_sizing(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
+    let inner = rc_from_hwnd(hwnd)?;
+    let incr = inner.borrow().resize_increments?;
+    let rect = &mut *(lparam as *mut RECT);
+    let (width, height) = snap_to_increments(rect, &incr);
+
+    match wparam as UINT {
+        WMSZ_LEFT | WMSZ_TOPLEFT | WMSZ_BOTTOMLEFT => rect.left = rect.right - width,
+        _ => rect.right = rect.left + width,
+    }
+    match wparam as UINT {
+        WMSZ_TOP | WMSZ_TOPLEFT | WMSZ_TOPRIGHT => rect.top = rect.bottom - height,
+        _ => rect.bottom = rect.top + height,
+    }
+    Some(1)
+}
+
+unsafe fn wm_enter_size_move(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    _lparam: LPARAM,
+) -> Option<LRESULT> {
+    let inner = rc_from_hwnd(hwnd)?;
+    inner.borrow_mut().in_size_move = true;
+    let inner = inner.borrow();
+    inner.last_live_resize_paint.set(None);
+    inner.callbacks.borrow_mut().live_resize_started();
+    None
+}
+
+unsafe fn wm_exit_size_move(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    _lparam: LPARAM,
+) -> Option<LRESULT> {
+    let inner = rc_from_hwnd(hwnd)?;
+    inner.borrow_mut().in_size_move = false;
+    KillTimer(hwnd, LIVE_RESIZE_PAINT_TIMER);
+    let incr = {
+        let inner = inner.borrow();
+        inner.callbacks.borrow_mut().live_resize_ended();
+        inner.resize_increments
+    };
+
+    // WM_SIZING doesn't see every way in which the size can change,
+    // such as snapping the window to the edge of the screen, so make
+    // sure that the final size is a whole number of increments.
+    // A maximized window has to fill the screen, so it is left alone.
+    if let Some(incr) = incr {
+        if IsZoomed(hwnd) == 0 {
+            let mut rect = RECT {
+                left: 0,
+                bottom: 0,
+                right: 0,
+                top: 0,
+            };
+            GetWindowRect(hwnd, &mut rect);
+            let (width, height) = snap_to_increments(&rect, &incr);
+            if width != rect_width(&rect) || height != rect_height(&rect) {
+                SetWindowPos(
+                    hwnd,
+                    null_mut(),
+                    0,
+                    0,
+                    width,
+                    height,
+                    SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOZORDER,
+                );
+            }
+        }
+    }
+
+    InvalidateRect(hwnd, null(), 1);
+    None
+}
+
+unsafe fn wm_timer(hwnd: HWND, _msg: UINT, wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
+    if wparam != LIVE_RESIZE_PAINT_TIMER {
+        return None;
+    }
+    KillTimer(hwnd, LIVE_RESIZE_PAINT_TIMER);
+    InvalidateRect(hwnd, null(), 1);
+    Some(0)
+}
+
 unsafe fn wm_set_focus(
     hwnd: HWND,
     _msg: UINT,
@@ -582,6 +992,28 @@ unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) ->
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let inner = inner.borrow();
 
+        if inner.in_size_move {
+            // Dragging the frame produces a flood of paint requests;
+            // paint at most once per interval, and arrange for the
+            // most recent request to be painted once it has elapsed
+            let now = Instant::now();
+            if let Some(last) = inner.last_live_resize_paint.get() {
+                let elapsed = now.duration_since(last);
+                if elapsed < LIVE_RESIZE_PAINT_INTERVAL {
+                    let remaining = LIVE_RESIZE_PAINT_INTERVAL - elapsed;
+                    ValidateRect(hwnd, null());
+                    SetTimer(
+                        hwnd,
+                        LIVE_RESIZE_PAINT_TIMER,
+                        remaining.as_millis().max(1) as UINT,
+                        None,
+                    );
+                    return Some(0);
+                }
+            }
+            inner.last_live_resize_paint.set(Some(now));
+        }
+
         let mut ps = PAINTSTRUCT {
             fErase: 0,
             fIncUpdate: 0,
@@ -630,18 +1062,21 @@ unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) ->
             let mut context = GdiGraphicsContext {
                 dpi,
                 bitmap: &mut bitmap,
+                clip: ClipStack::default(),
             };
 
             inner.callbacks.borrow_mut().paint(&mut context);
+            // Only the invalidated part of the window needs to be copied
+            let update = &ps.rcPaint;
             BitBlt(
                 dc,
-                0,
-                0,
-                width as i32,
-                height as i32,
+                update.left,
+                update.top,
+                rect_width(update),
+                rect_height(update),
                 context.bitmap.hdc(),
-                0,
-                0,
+                update.left,
+                update.top,
                 SRCCOPY,
             );
         }
@@ -881,6 +1316,7 @@ unsafe fn ime_composition(
                         raw_key: None,
                         modifiers: Modifiers::default(),
                         repeat_count: 1,
+                        is_repeat: false,
                         key_is_down: true,
                     };
                     inner
@@ -900,14 +1336,16 @@ unsafe fn ime_composition(
 unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let inner = inner.borrow();
-        let repeat = (lparam & 0xffff) as u16;
+        // The hardware repeat count can be 0 in some synthesized
+        // messages; normalize to at least one press.
+        let repeat = ((lparam & 0xffff) as u16).max(1);
         let scan_code = ((lparam >> 16) & 0xff) as u8;
         let releasing = (lparam & (1 << 31)) != 0;
+        let was_down = (lparam & (1 << 30)) != 0;
         let ime_active = wparam == VK_PROCESSKEY as _;
 
         /*
         let alt_pressed = (lparam & (1 << 29)) != 0;
-        let was_down = (lparam & (1 << 30)) != 0;
         let label = match msg {
             WM_CHAR => "WM_CHAR",
             WM_IME_CHAR => "WM_IME_CHAR",
@@ -1069,6 +1507,9 @@ unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<L
                 raw_key: None,
                 modifiers,
                 repeat_count: repeat,
+                // The previous key state bit is always set for key
+                // up events, so only consider it for presses
+                is_repeat: !releasing && was_down,
                 key_is_down: !releasing,
             };
             let handled = inner
@@ -1084,14 +1525,88 @@ unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<L
     None
 }
 
+unsafe fn setting_change(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    lparam: LPARAM,
+) -> Option<LRESULT> {
+    // The light/dark preference is signalled by a change to the
+    // "ImmersiveColorSet" setting
+    if lparam == 0 {
+        return None;
+    }
+    let name = lparam as *const u16;
+    let len = (0..).take_while(|&i| *name.offset(i) != 0).count();
+    let name = std::ffi::OsString::from_wide(std::slice::from_raw_parts(name, len));
+    if name != "ImmersiveColorSet" {
+        return None;
+    }
+
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let inner = inner.borrow();
+        if inner.theme == WindowTheme::System {
+            apply_theme(hwnd, WindowTheme::System);
+        }
+        inner
+            .callbacks
+            .borrow_mut()
+            .system_theme_changed(system_theme());
+    }
+    None
+}
+
+/// The command of the first window menu entry added by the application.
+/// The system uses the low four bits of the command passed with
+/// WM_SYSCOMMAND, so the commands are spaced 16 apart, and they must
+/// be below the 0xF000 used by the standard entries.
+const FIRST_SYSTEM_MENU_COMMAND: usize = 0x1000;
+
+/// Returns the WM_SYSCOMMAND command for the entry with `id`, or
+/// None if it is out of range
+fn system_menu_command(id: u16) -> Option<usize> {
+    let command = FIRST_SYSTEM_MENU_COMMAND + ((id as usize) << 4);
+    if command < SC_SIZE {
+        Some(command)
+    } else {
+        None
+    }
+}
+
+unsafe fn sys_command(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
+    let inner = rc_from_hwnd(hwnd)?;
+    let inner = inner.borrow();
+    let command = wparam & 0xfff0;
+    if command == SC_KEYMENU && lparam == b' ' as LPARAM && !inner.alt_space_opens_system_menu {
+        // Alt+Space was passed to key_event as usual; don't
+        // also open the menu
+        return Some(0);
+    }
+    if command >= FIRST_SYSTEM_MENU_COMMAND && command < SC_SIZE {
+        let id = ((command - FIRST_SYSTEM_MENU_COMMAND) >> 4) as u16;
+        inner
+            .callbacks
+            .borrow_mut()
+            .system_menu_item_selected(id, &Window::from_hwnd(hwnd));
+        return Some(0);
+    }
+    None
+}
+
 unsafe fn do_wnd_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
     match msg {
         WM_NCCREATE => wm_nccreate(hwnd, msg, wparam, lparam),
         WM_NCDESTROY => wm_ncdestroy(hwnd, msg, wparam, lparam),
         WM_PAINT => wm_paint(hwnd, msg, wparam, lparam),
         WM_SIZE => wm_size(hwnd, msg, wparam, lparam),
+        WM_SIZING => wm_sizing(hwnd, msg, wparam, lparam),
+        WM_ENTERSIZEMOVE => wm_enter_size_move(hwnd, msg, wparam, lparam),
+        WM_EXITSIZEMOVE => wm_exit_size_move(hwnd, msg, wparam, lparam),
+        WM_TIMER => wm_timer(hwnd, msg, wparam, lparam),
         WM_SETFOCUS => wm_set_focus(hwnd, msg, wparam, lparam),
         WM_KILLFOCUS => wm_kill_focus(hwnd, msg, wparam, lparam),
+        WM_SETTINGCHANGE => setting_change(hwnd, msg, wparam, lparam),
+        WM_SYSCOMMAND => sys_command(hwnd, msg, wparam, lparam),
         WM_KEYDOWN | WM_CHAR | WM_IME_CHAR | WM_KEYUP | WM_SYSKEYUP | WM_SYSKEYDOWN => {
             key(hwnd, msg, wparam, lparam)
         }
@@ -1122,11 +1637,22 @@ unsafe extern "system" fn wnd_proc(
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
-    match std::panic::catch_unwind(|| {
+    // Unwinding across this FFI boundary is undefined behavior, so
+    // we catch any panic here and apply the configured panic policy.
+    match crate::panicpolicy::catch_callback_panic(|| {
         do_wnd_proc(hwnd, msg, wparam, lparam)
             .unwrap_or_else(|| DefWindowProcW(hwnd, msg, wparam, lparam))
     }) {
         Ok(result) => result,
-        Err(_) => std::process::exit(1),
+        Err(PanicPolicy::CloseWindow) => {
+            DestroyWindow(hwnd);
+            0
+        }
+        Err(_) => {
+            // The panic will be resumed by run_message_loop
+            // once the loop has stopped
+            PostQuitMessage(0);
+            0
+        }
     }
 }
@@ -5,6 +5,7 @@ use crate::os::Connection;
 use crate::spawn::*;
 use crate::tasks::{Task, Tasks};
 use crate::timerlist::{TimerEntry, TimerList};
+use crate::PanicPolicy;
 use anyhow::{anyhow, bail};
 use mio::unix::EventedFd;
 use mio::{Evented, Events, Poll, PollOpt, Ready, Token};
@@ -29,6 +30,12 @@ pub struct XConnection {
     pub atom_xsel_data: xcb::Atom,
     pub atom_targets: xcb::Atom,
     pub atom_clipboard: xcb::Atom,
+    pub atom_gtk_theme_variant: xcb::Atom,
+    pub atom_net_startup_id: xcb::Atom,
+    pub atom_net_startup_info_begin: xcb::Atom,
+    pub atom_net_startup_info: xcb::Atom,
+    /// The startup notification id that the first window completes
+    pub(crate) startup_id: RefCell<Option<String>>,
     keysyms: *mut xcb_key_symbols_t,
     pub(crate) windows: RefCell<HashMap<xcb::xproto::Window, Arc<Mutex<XWindowInner>>>>,
     should_terminate: RefCell<bool>,
@@ -251,9 +258,16 @@ impl ConnectionOps for XConnection {
             }
         }
 
+        crate::panicpolicy::resume_pending_panic();
+
         Ok(())
     }
 
+    fn beep(&self) {
+        xcb::bell(&self.conn, 0);
+        self.conn.flush();
+    }
+
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F) {
         self.timers.borrow_mut().insert(TimerEntry {
             callback: Box::new(callback),
@@ -315,8 +329,23 @@ impl XConnection {
         event: &xcb::GenericEvent,
     ) -> anyhow::Result<()> {
         if let Some(window) = self.window_by_id(window_id) {
-            let mut inner = window.lock().unwrap();
-            inner.dispatch_event(event)?;
+            match crate::panicpolicy::catch_callback_panic(|| {
+                window.lock().unwrap().dispatch_event(event)
+            }) {
+                Ok(result) => result?,
+                Err(PanicPolicy::CloseWindow) => {
+                    // The panic poisoned the lock, but the callbacks
+                    // still need to learn that the window is going away.
+                    // It is forgotten here, so the DestroyNotify that
+                    // follows won't be dispatched to it.
+                    let mut inner = window
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    xcb::destroy_window(self.conn(), window_id);
+                    inner.destroyed();
+                }
+                Err(_) => self.terminate_message_loop(),
+            }
         }
         Ok(())
     }
@@ -350,6 +379,18 @@ impl XConnection {
         let atom_clipboard = xcb::intern_atom(&conn, false, "CLIPBOARD")
             .get_reply()?
             .atom();
+        let atom_gtk_theme_variant = xcb::intern_atom(&conn, false, "_GTK_THEME_VARIANT")
+            .get_reply()?
+            .atom();
+        let atom_net_startup_id = xcb::intern_atom(&conn, false, "_NET_STARTUP_ID")
+            .get_reply()?
+            .atom();
+        let atom_net_startup_info_begin = xcb::intern_atom(&conn, false, "_NET_STARTUP_INFO_BEGIN")
+            .get_reply()?
+            .atom();
+        let atom_net_startup_info = xcb::intern_atom(&conn, false, "_NET_STARTUP_INFO")
+            .get_reply()?
+            .atom();
 
         let keysyms = unsafe { xcb_key_symbols_alloc((*conn).get_raw_conn()) };
 
@@ -388,6 +429,11 @@ impl XConnection {
             screen_num,
             atom_protocols,
             atom_clipboard,
+            atom_gtk_theme_variant,
+            atom_net_startup_id,
+            atom_net_startup_info_begin,
+            atom_net_startup_info,
+            startup_id: RefCell::new(super::startup::take_startup_id()),
             atom_delete,
             keysyms,
             keyboard,
@@ -3,6 +3,7 @@ use crate::{KeyCode, Modifiers};
 use anyhow::{anyhow, ensure};
 use libc;
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::ffi::CStr;
 use xkb::compose::Status as ComposeStatus;
 use xkbcommon::xkb;
@@ -14,6 +15,10 @@ pub struct Keyboard {
 
     state: RefCell<xkb::State>,
     compose_state: RefCell<xkb::compose::State>,
+
+    /// The set of keycodes that are currently held down; used
+    /// to identify auto-repeated key presses
+    pressed_keys: RefCell<HashSet<xcb::Keycode>>,
 }
 
 impl Keyboard {
@@ -91,17 +96,57 @@ impl Keyboard {
             cookie.request_check()?;
         }
 
+        {
+            // Ask the server not to synthesize release events for
+            // auto-repeated keys; we'll see a sequence of presses
+            // followed by a single release, which allows us to
+            // distinguish the initial press from the repeats.
+            let flag = xcb::xkb::PER_CLIENT_FLAG_DETECTABLE_AUTO_REPEAT;
+            let cookie =
+                xcb::xkb::per_client_flags(&connection, device_id as u16, flag, flag, 0, 0, 0);
+            match cookie.get_reply() {
+                Ok(r) if r.value() & flag == 0 => {
+                    log::warn!("X server doesn't support detectable auto repeat");
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    log::warn!("failed to enable detectable auto repeat: {:?}", err);
+                }
+            }
+        }
+
         let kbd = Keyboard {
             context,
             device_id,
             keymap: RefCell::new(keymap),
             state: RefCell::new(state),
             compose_state: RefCell::new(compose_state),
+            pressed_keys: RefCell::new(HashSet::new()),
         };
 
         Ok((kbd, first_ev))
     }
 
+    /// Track the up/down state of the key from the event.
+    /// Returns true if the event is an auto-repeat of a key that
+    /// is already held down.
+    pub fn update_key_state(&self, xcb_ev: &xcb::KeyPressEvent) -> bool {
+        let pressed = (xcb_ev.response_type() & !0x80) == xcb::KEY_PRESS;
+        let mut keys = self.pressed_keys.borrow_mut();
+        if pressed {
+            !keys.insert(xcb_ev.detail())
+        } else {
+            keys.remove(&xcb_ev.detail());
+            false
+        }
+    }
+
+    /// Forget any held keys; used when focus is lost and we
+    /// won't receive the corresponding release events.
+    pub fn clear_key_state(&self) {
+        self.pressed_keys.borrow_mut().clear();
+    }
+
     pub fn process_key_event(&self, xcb_ev: &xcb::KeyPressEvent) -> Option<(KeyCode, Modifiers)> {
         let pressed = (xcb_ev.response_type() & !0x80) == xcb::KEY_PRESS;
 
@@ -2,6 +2,7 @@
 pub mod bitmap;
 pub mod connection;
 pub mod keyboard;
+mod startup;
 pub mod window;
 
 pub use self::window::*;
@@ -0,0 +1,89 @@
+//! Startup notification, which lets the launcher that started us
+//! show feedback until our first window appears, and the window
+//! manager place that window where the launch was requested.
+//! <https://specifications.freedesktop.org/startup-notification-spec/>
+use super::XConnection;
+use anyhow::anyhow;
+
+/// The launcher passes the startup id in the environment.  It is
+/// removed so that the programs that we spawn don't also claim it.
+pub(crate) fn take_startup_id() -> Option<String> {
+    let id = std::env::var("DESKTOP_STARTUP_ID").ok();
+    std::env::remove_var("DESKTOP_STARTUP_ID");
+    id.filter(|id| !id.is_empty())
+}
+
+/// Quotes a value in a startup notification message, if needed
+fn quote(value: &str) -> String {
+    if !value.contains(|c: char| c == ' ' || c == '"' || c == '\\') {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Sets the startup id on `window`, which should be done before it is
+/// mapped, so that the window manager can associate the two
+pub(crate) fn set_startup_id(conn: &XConnection, window: xcb::xproto::Window, id: &str) {
+    xcb::change_property(
+        conn.conn(),
+        xcb::PROP_MODE_REPLACE as u8,
+        window,
+        conn.atom_net_startup_id,
+        conn.atom_utf8_string,
+        8,
+        id.as_bytes(),
+    );
+}
+
+/// Tells the launcher that the startup sequence `id` is complete.
+/// The message is broadcast to the root window in pieces of 20 bytes;
+/// the first uses the `_NET_STARTUP_INFO_BEGIN` type and the rest use
+/// `_NET_STARTUP_INFO`.
+pub(crate) fn send_startup_complete(
+    conn: &XConnection,
+    window: xcb::xproto::Window,
+    id: &str,
+) -> anyhow::Result<()> {
+    let root = conn
+        .conn()
+        .get_setup()
+        .roots()
+        .nth(conn.screen_num as usize)
+        .ok_or_else(|| anyhow!("no screen?"))?
+        .root();
+
+    let mut message = format!("remove: ID={}", quote(id)).into_bytes();
+    message.push(0);
+
+    for (idx, chunk) in message.chunks(20).enumerate() {
+        let mut data = [0u8; 20];
+        data[..chunk.len()].copy_from_slice(chunk);
+        let message_type = if idx == 0 {
+            conn.atom_net_startup_info_begin
+        } else {
+            conn.atom_net_startup_info
+        };
+        xcb::xproto::send_event(
+            conn.conn(),
+            false,
+            root,
+            xcb::EVENT_MASK_PROPERTY_CHANGE,
+            &xcb::ClientMessageEvent::new(
+                8,
+                window,
+                message_type,
+                xcb::ClientMessageData::from_data8(data),
+            ),
+        );
+    }
+    conn.conn().flush();
+    Ok(())
+}
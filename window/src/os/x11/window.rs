@@ -1,12 +1,13 @@
 use super::*;
+use crate::bitmaps::clip::{self, ClipStack};
 use crate::bitmaps::*;
 use crate::connection::ConnectionOps;
 use crate::os::xkeysyms;
 use crate::os::{Connection, Window};
 use crate::{
-    Color, Dimensions, KeyEvent, MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress,
-    Operator, PaintContext, Point, Rect, ScreenPoint, Size, WindowCallbacks, WindowOps,
-    WindowOpsMut,
+    Clipboard, Color, Dimensions, KeyEvent, MouseButtons, MouseCursor, MouseEvent, MouseEventKind,
+    MousePress, Operator, PaintContext, Point, Rect, ResizeIncrements, ScreenPoint, Size,
+    WindowCallbacks, WindowLevel, WindowOps, WindowOpsMut, WindowTheme,
 };
 use anyhow::anyhow;
 use promise::{Future, Promise};
@@ -18,11 +19,28 @@ use std::sync::{Arc, Mutex};
 
 #[derive(Default)]
 struct CopyAndPaste {
-    owned: Option<String>,
+    clipboard: Option<String>,
+    primary: Option<String>,
     request: Option<Promise<String>>,
     time: u32,
 }
 
+impl CopyAndPaste {
+    fn owned(&self, clipboard: Clipboard) -> Option<&String> {
+        match clipboard {
+            Clipboard::Clipboard => self.clipboard.as_ref(),
+            Clipboard::PrimarySelection => self.primary.as_ref(),
+        }
+    }
+
+    fn owned_mut(&mut self, clipboard: Clipboard) -> &mut Option<String> {
+        match clipboard {
+            Clipboard::Clipboard => &mut self.clipboard,
+            Clipboard::PrimarySelection => &mut self.primary,
+        }
+    }
+}
+
 pub(crate) struct XWindowInner {
     window_id: xcb::xproto::Window,
     conn: Rc<XConnection>,
@@ -57,15 +75,16 @@ impl Drop for XWindowInner {
 
 struct X11GraphicsContext<'a> {
     buffer: &'a mut dyn BitmapImage,
+    clip: ClipStack,
 }
 
 impl<'a> PaintContext for X11GraphicsContext<'a> {
     fn clear_rect(&mut self, rect: Rect, color: Color) {
-        self.buffer.clear_rect(rect, color)
+        clip::clear_rect(self.buffer, self.clip.current(), rect, color)
     }
 
     fn clear(&mut self, color: Color) {
-        self.buffer.clear(color);
+        clip::clear(self.buffer, self.clip.current(), color);
     }
 
     fn get_dimensions(&self) -> Dimensions {
@@ -84,12 +103,37 @@ impl<'a> PaintContext for X11GraphicsContext<'a> {
         im: &dyn BitmapImage,
         operator: Operator,
     ) {
-        self.buffer
-            .draw_image(dest_top_left, src_rect, im, operator)
+        clip::draw_image(
+            self.buffer,
+            self.clip.current(),
+            dest_top_left,
+            src_rect,
+            im,
+            operator,
+        )
     }
 
     fn draw_line(&mut self, start: Point, end: Point, color: Color, operator: Operator) {
-        self.buffer.draw_line(start, end, color, operator);
+        clip::draw_line(
+            self.buffer,
+            self.clip.current(),
+            start,
+            end,
+            color,
+            operator,
+        );
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        self.clip.push(rect);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clip.pop();
+    }
+
+    fn scroll_region(&mut self, rect: Rect, dx: isize, dy: isize) {
+        clip::scroll_region(self.buffer, self.clip.current(), rect, dx, dy);
     }
 }
 
@@ -148,6 +192,7 @@ impl XWindowInner {
 
             let mut context = X11GraphicsContext {
                 buffer: &mut self.buffer_image,
+                clip: ClipStack::default(),
             };
 
             self.callbacks.paint(&mut context);
@@ -251,6 +296,17 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Lets the callbacks know that the window has gone and forgets it
+    pub(crate) fn destroyed(&mut self) {
+        self.callbacks.destroy();
+        let mut windows = self.conn.windows.borrow_mut();
+        windows.remove(&self.window_id);
+        if windows.is_empty() {
+            drop(windows);
+            crate::connection::last_window_closed();
+        }
+    }
+
     pub fn dispatch_event(&mut self, event: &xcb::GenericEvent) -> anyhow::Result<()> {
         let r = event.response_type() & 0x7f;
         match r {
@@ -271,12 +327,14 @@ impl XWindowInner {
             xcb::KEY_PRESS | xcb::KEY_RELEASE => {
                 let key_press: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
                 self.copy_and_paste.time = key_press.time();
+                let is_repeat = self.conn.keyboard.update_key_state(key_press);
                 if let Some((code, mods)) = self.conn.keyboard.process_key_event(key_press) {
                     let key = KeyEvent {
                         key: code,
                         raw_key: None,
                         modifiers: mods,
                         repeat_count: 1,
+                        is_repeat,
                         key_is_down: r == xcb::KEY_PRESS,
                     };
                     self.callbacks
@@ -363,12 +421,9 @@ impl XWindowInner {
                     xcb::destroy_window(self.conn.conn(), self.window_id);
                 }
             }
-            xcb::DESTROY_NOTIFY => {
-                self.callbacks.destroy();
-                self.conn.windows.borrow_mut().remove(&self.window_id);
-            }
+            xcb::DESTROY_NOTIFY => self.destroyed(),
             xcb::SELECTION_CLEAR => {
-                self.selection_clear()?;
+                self.selection_clear(unsafe { xcb::cast_event(event) })?;
             }
             xcb::SELECTION_REQUEST => {
                 self.selection_request(unsafe { xcb::cast_event(event) })?;
@@ -389,6 +444,9 @@ impl XWindowInner {
                 self.callbacks.focus_change(true);
             }
             xcb::FOCUS_OUT => {
+                // We won't see the release events for any keys that
+                // are still held down, so forget about them
+                self.conn.keyboard.clear_key_state();
                 log::trace!("Calling focus_change(false)");
                 self.callbacks.focus_change(false);
             }
@@ -400,39 +458,56 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Returns the selection atom that corresponds to `clipboard`
+    fn selection_atom(&self, clipboard: Clipboard) -> xcb::Atom {
+        match clipboard {
+            Clipboard::Clipboard => self.conn.atom_clipboard,
+            Clipboard::PrimarySelection => xcb::ATOM_PRIMARY,
+        }
+    }
+
+    /// Returns the clipboard that corresponds to the selection atom,
+    /// if it is one that we manage
+    fn clipboard_for_selection(&self, selection: xcb::Atom) -> Option<Clipboard> {
+        if selection == self.conn.atom_clipboard {
+            Some(Clipboard::Clipboard)
+        } else if selection == xcb::ATOM_PRIMARY {
+            Some(Clipboard::PrimarySelection)
+        } else {
+            None
+        }
+    }
+
     /// If we own the selection, make sure that the X server reflects
     /// that and vice versa.
-    fn update_selection_owner(&mut self) {
-        for &selection in &[xcb::ATOM_PRIMARY, self.conn.atom_clipboard] {
-            let current_owner = xcb::get_selection_owner(&self.conn, selection)
-                .get_reply()
-                .unwrap()
-                .owner();
-            if self.copy_and_paste.owned.is_none() && current_owner == self.window_id {
-                // We don't have a selection but X thinks we do; disown it!
-                xcb::set_selection_owner(
-                    &self.conn,
-                    xcb::NONE,
-                    selection,
-                    self.copy_and_paste.time,
-                );
-            } else if self.copy_and_paste.owned.is_some() && current_owner != self.window_id {
-                // We have the selection but X doesn't think we do; assert it!
-                xcb::set_selection_owner(
-                    &self.conn,
-                    self.window_id,
-                    selection,
-                    self.copy_and_paste.time,
-                );
-            }
+    fn update_selection_owner(&mut self, clipboard: Clipboard) {
+        let selection = self.selection_atom(clipboard);
+        let owned = self.copy_and_paste.owned(clipboard).is_some();
+        let current_owner = xcb::get_selection_owner(&self.conn, selection)
+            .get_reply()
+            .unwrap()
+            .owner();
+        if !owned && current_owner == self.window_id {
+            // We don't have a selection but X thinks we do; disown it!
+            xcb::set_selection_owner(&self.conn, xcb::NONE, selection, self.copy_and_paste.time);
+        } else if owned && current_owner != self.window_id {
+            // We have the selection but X doesn't think we do; assert it!
+            xcb::set_selection_owner(
+                &self.conn,
+                self.window_id,
+                selection,
+                self.copy_and_paste.time,
+            );
         }
         self.conn.flush();
     }
 
-    fn selection_clear(&mut self) -> anyhow::Result<()> {
-        self.copy_and_paste.owned.take();
-        self.copy_and_paste.request.take();
-        self.update_selection_owner();
+    fn selection_clear(&mut self, clear: &xcb::SelectionClearEvent) -> anyhow::Result<()> {
+        if let Some(clipboard) = self.clipboard_for_selection(clear.selection()) {
+            self.copy_and_paste.owned_mut(clipboard).take();
+            self.copy_and_paste.request.take();
+            self.update_selection_owner(clipboard);
+        }
         Ok(())
     }
 
@@ -478,7 +553,10 @@ impl XWindowInner {
             // We don't and won't do any conversion from UTF-8 to
             // whatever STRING represents; let's just assume that
             // the other end is going to handle it correctly.
-            if let Some(text) = self.copy_and_paste.owned.as_ref() {
+            let owned = self
+                .clipboard_for_selection(request.selection())
+                .and_then(|clipboard| self.copy_and_paste.owned(clipboard));
+            if let Some(text) = owned {
                 xcb::xproto::change_property(
                     &self.conn,
                     xcb::xproto::PROP_MODE_REPLACE as u8,
@@ -614,12 +692,13 @@ impl XWindow {
     }
 
     /// Create a new window on the specified screen with the specified
-    /// dimensions
+    /// dimensions.  `class_name` is used for both parts of its WM_CLASS.
     pub fn new_window(
-        _class_name: &str,
+        class_name: &str,
         name: &str,
         width: usize,
         height: usize,
+        theme: WindowTheme,
         callbacks: Box<dyn WindowCallbacks>,
     ) -> anyhow::Result<Window> {
         let conn = Connection::get()
@@ -702,8 +781,27 @@ impl XWindow {
             &[conn.atom_delete],
         );
 
+        // WM_CLASS holds the instance name followed by the class name
+        let wm_class = format!("{}\0{}\0", class_name, class_name);
+        xcb::change_property(
+            &*conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window_id,
+            xcb::ATOM_WM_CLASS,
+            xcb::ATOM_STRING,
+            8,
+            wm_class.as_bytes(),
+        );
+
+        let startup_id = conn.startup_id.borrow_mut().take();
+        if let Some(id) = startup_id.as_ref() {
+            super::startup::set_startup_id(&conn, window_id, id);
+        }
+
         // window.lock().unwrap().disable_decorations()?;
 
+        window.lock().unwrap().set_window_theme(theme);
+
         let window_handle = Window::X11(XWindow::from_id(window_id));
 
         window.lock().unwrap().callbacks.created(&window_handle);
@@ -713,10 +811,43 @@ impl XWindow {
         window_handle.set_title(name);
         window_handle.show();
 
+        if let Some(id) = startup_id {
+            // show() is deferred, so complete the startup sequence
+            // once the window has been mapped
+            Future::with_executor(Connection::executor(), move || {
+                let conn = Connection::get().unwrap().x11();
+                if let Err(err) = super::startup::send_startup_complete(&conn, window_id, &id) {
+                    log::error!("failed to complete startup notification: {}", err);
+                }
+                Ok(())
+            });
+        }
+
         Ok(window_handle)
     }
 }
 
+/// Action values for the _NET_WM_STATE client message, as
+/// defined by the EWMH spec.
+const WM_STATE_REMOVE: u32 = 0;
+const WM_STATE_ADD: u32 = 1;
+
+impl XWindowInner {
+    /// Ask the window manager to add or remove up to two
+    /// _NET_WM_STATE atoms.  Pass 0 for an unused property.
+    fn change_wm_state(&self, action: u32, first: xcb::Atom, second: xcb::Atom) {
+        xcb_util::ewmh::request_change_wm_state(
+            self.conn.ewmh_conn(),
+            self.conn.screen_num,
+            self.window_id,
+            action,
+            first,
+            second,
+            1, // normal program
+        );
+    }
+}
+
 impl Drawable for XWindow {
     fn as_drawable(&self) -> xcb::xproto::Drawable {
         self.0
@@ -727,6 +858,11 @@ impl WindowOpsMut for XWindowInner {
     fn close(&mut self) {
         xcb::destroy_window(self.conn.conn(), self.window_id);
     }
+    fn request_close(&mut self) {
+        if self.callbacks.can_close() {
+            self.close();
+        }
+    }
     fn hide(&mut self) {}
     fn show(&mut self) {
         xcb::map_window(self.conn.conn(), self.window_id);
@@ -738,6 +874,19 @@ impl WindowOpsMut for XWindowInner {
         self.paint_all = true;
     }
 
+    fn invalidate_rect(&mut self, rect: Rect) {
+        let window_dimensions =
+            Rect::from_size(Size::new(self.width as isize, self.height as isize));
+        if let Some(rect) = rect.intersection(&window_dimensions) {
+            self.expose(
+                rect.origin.x as u16,
+                rect.origin.y as u16,
+                rect.size.width as u16,
+                rect.size.height as u16,
+            );
+        }
+    }
+
     fn set_inner_size(&mut self, width: usize, height: usize) {
         xcb::configure_window(
             self.conn.conn(),
@@ -790,6 +939,66 @@ impl WindowOpsMut for XWindowInner {
     fn set_title(&mut self, title: &str) {
         xcb_util::icccm::set_wm_name(self.conn.conn(), self.window_id, title);
     }
+
+    fn set_window_level(&mut self, level: WindowLevel) {
+        let ewmh = self.conn.ewmh_conn();
+        let (add, remove) = match level {
+            WindowLevel::Normal => (None, [ewmh.WM_STATE_ABOVE(), ewmh.WM_STATE_BELOW()]),
+            WindowLevel::AlwaysOnTop => (Some(ewmh.WM_STATE_ABOVE()), [ewmh.WM_STATE_BELOW(), 0]),
+            WindowLevel::AlwaysBelow => (Some(ewmh.WM_STATE_BELOW()), [ewmh.WM_STATE_ABOVE(), 0]),
+        };
+        self.change_wm_state(WM_STATE_REMOVE, remove[0], remove[1]);
+        if let Some(add) = add {
+            self.change_wm_state(WM_STATE_ADD, add, 0);
+        }
+    }
+
+    /// There is no standard way to select the theme for the window
+    /// decorations under X11, but GTK based window managers such as
+    /// mutter will honor the _GTK_THEME_VARIANT property.
+    fn set_window_theme(&mut self, theme: WindowTheme) {
+        let variant = match theme {
+            WindowTheme::System => {
+                xcb::delete_property(
+                    self.conn.conn(),
+                    self.window_id,
+                    self.conn.atom_gtk_theme_variant,
+                );
+                return;
+            }
+            WindowTheme::Light => "light",
+            WindowTheme::Dark => "dark",
+        };
+        xcb::change_property(
+            self.conn.conn(),
+            xcb::PROP_MODE_REPLACE as u8,
+            self.window_id,
+            self.conn.atom_gtk_theme_variant,
+            self.conn.atom_utf8_string,
+            8,
+            variant.as_bytes(),
+        );
+    }
+
+    fn set_skip_taskbar(&mut self, skip: bool) {
+        let ewmh = self.conn.ewmh_conn();
+        self.change_wm_state(
+            if skip { WM_STATE_ADD } else { WM_STATE_REMOVE },
+            ewmh.WM_STATE_SKIP_TASKBAR(),
+            ewmh.WM_STATE_SKIP_PAGER(),
+        );
+    }
+
+    fn set_resize_increments(&mut self, increments: Option<ResizeIncrements>) {
+        let hints = match increments {
+            Some(incr) => xcb_util::icccm::SizeHints::empty()
+                .base(incr.base_width as i32, incr.base_height as i32)
+                .resize(incr.x as i32, incr.y as i32)
+                .build(),
+            None => xcb_util::icccm::SizeHints::empty().build(),
+        };
+        xcb_util::icccm::set_wm_normal_hints(self.conn.conn(), self.window_id, &hints);
+    }
 }
 
 impl WindowOps for XWindow {
@@ -800,6 +1009,13 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn request_close(&self) -> Future<()> {
+        XConnection::with_window_inner(self.0, |inner| {
+            inner.request_close();
+            Ok(())
+        })
+    }
+
     fn hide(&self) -> Future<()> {
         XConnection::with_window_inner(self.0, |inner| {
             inner.hide();
@@ -828,6 +1044,13 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn invalidate_rect(&self, rect: Rect) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.invalidate_rect(rect);
+            Ok(())
+        })
+    }
+
     fn set_title(&self, title: &str) -> Future<()> {
         let title = title.to_owned();
         XConnection::with_window_inner(self.0, move |inner| {
@@ -850,6 +1073,34 @@ impl WindowOps for XWindow {
         })
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_window_level(level);
+            Ok(())
+        })
+    }
+
+    fn set_skip_taskbar(&self, skip: bool) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_skip_taskbar(skip);
+            Ok(())
+        })
+    }
+
+    fn set_window_theme(&self, theme: WindowTheme) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_window_theme(theme);
+            Ok(())
+        })
+    }
+
+    fn set_resize_increments(&self, increments: Option<ResizeIncrements>) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_resize_increments(increments);
+            Ok(())
+        })
+    }
+
     fn apply<R, F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         func: F,
@@ -909,13 +1160,13 @@ impl WindowOps for XWindow {
     }
 
     /// Initiate textual transfer from the clipboard
-    fn get_clipboard(&self) -> Future<String> {
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         let mut promise = Promise::new();
         let future = promise.get_future().unwrap();
         let mut promise = Some(promise);
         XConnection::with_window_inner(self.0, move |inner| {
             let mut promise = promise.take().unwrap();
-            if let Some(text) = inner.copy_and_paste.owned.as_ref() {
+            if let Some(text) = inner.copy_and_paste.owned(clipboard) {
                 promise.ok(text.to_owned());
 
                 // Cancel any outstanding promise from the other branch
@@ -928,11 +1179,7 @@ impl WindowOps for XWindow {
                 xcb::convert_selection(
                     &inner.conn,
                     inner.window_id,
-                    // Important: we request the clipboard rather than the
-                    // primary selection because, under wayland, access to the
-                    // primary selection is forbidden by default citing a security
-                    // concern.
-                    inner.conn.atom_clipboard,
+                    inner.selection_atom(clipboard),
                     inner.conn.atom_utf8_string,
                     inner.conn.atom_xsel_data,
                     inner.copy_and_paste.time,
@@ -945,10 +1192,13 @@ impl WindowOps for XWindow {
     }
 
     /// Set some text in the clipboard
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         XConnection::with_window_inner(self.0, move |inner| {
-            inner.copy_and_paste.owned.replace(text.clone());
-            inner.update_selection_owner();
+            inner
+                .copy_and_paste
+                .owned_mut(clipboard)
+                .replace(text.clone());
+            inner.update_selection_owner(clipboard);
             Ok(())
         })
     }
@@ -8,7 +8,10 @@ use crate::os::wayland::window::WaylandWindow;
 use crate::os::x11::connection::XConnection;
 use crate::os::x11::window::XWindow;
 use crate::spawn::*;
-use crate::{MouseCursor, ScreenPoint, WindowCallbacks, WindowOps};
+use crate::{
+    Clipboard, MouseCursor, Rect, ResizeIncrements, ScreenPoint, WindowCallbacks, WindowLevel,
+    WindowOps, WindowTheme,
+};
 use promise::*;
 use std::any::Any;
 use std::rc::Rc;
@@ -64,13 +67,14 @@ impl Connection {
         name: &str,
         width: usize,
         height: usize,
+        theme: WindowTheme,
         callbacks: Box<dyn WindowCallbacks>,
     ) -> anyhow::Result<Window> {
         match self {
-            Self::X11(_) => XWindow::new_window(class_name, name, width, height, callbacks),
+            Self::X11(_) => XWindow::new_window(class_name, name, width, height, theme, callbacks),
             #[cfg(feature = "wayland")]
             Self::Wayland(_) => {
-                WaylandWindow::new_window(class_name, name, width, height, callbacks)
+                WaylandWindow::new_window(class_name, name, width, height, theme, callbacks)
             }
         }
     }
@@ -145,6 +149,14 @@ impl ConnectionOps for Connection {
             Self::Wayland(w) => w.schedule_timer(interval, callback),
         }
     }
+
+    fn beep(&self) {
+        match self {
+            Self::X11(x) => x.beep(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.beep(),
+        }
+    }
 }
 
 impl Window {
@@ -153,11 +165,12 @@ impl Window {
         name: &str,
         width: usize,
         height: usize,
+        theme: WindowTheme,
         callbacks: Box<dyn WindowCallbacks>,
     ) -> anyhow::Result<Window> {
         Connection::get()
             .unwrap()
-            .new_window(class_name, name, width, height, callbacks)
+            .new_window(class_name, name, width, height, theme, callbacks)
     }
 }
 
@@ -170,6 +183,14 @@ impl WindowOps for Window {
         }
     }
 
+    fn request_close(&self) -> Future<()> {
+        match self {
+            Self::X11(x) => x.request_close(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.request_close(),
+        }
+    }
+
     fn hide(&self) -> Future<()> {
         match self {
             Self::X11(x) => x.hide(),
@@ -202,6 +223,14 @@ impl WindowOps for Window {
         }
     }
 
+    fn invalidate_rect(&self, rect: Rect) -> Future<()> {
+        match self {
+            Self::X11(x) => x.invalidate_rect(rect),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.invalidate_rect(rect),
+        }
+    }
+
     fn set_title(&self, title: &str) -> Future<()> {
         match self {
             Self::X11(x) => x.set_title(title),
@@ -226,6 +255,38 @@ impl WindowOps for Window {
         }
     }
 
+    fn set_window_level(&self, level: WindowLevel) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_window_level(level),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_window_level(level),
+        }
+    }
+
+    fn set_skip_taskbar(&self, skip: bool) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_skip_taskbar(skip),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_skip_taskbar(skip),
+        }
+    }
+
+    fn set_window_theme(&self, theme: WindowTheme) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_window_theme(theme),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_window_theme(theme),
+        }
+    }
+
+    fn set_resize_increments(&self, increments: Option<ResizeIncrements>) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_resize_increments(increments),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_resize_increments(increments),
+        }
+    }
+
     fn apply<R, F: Send + 'static + Fn(&mut dyn Any, &dyn WindowOps) -> anyhow::Result<R>>(
         &self,
         func: F,
@@ -266,18 +327,18 @@ impl WindowOps for Window {
         }
     }
 
-    fn get_clipboard(&self) -> Future<String> {
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         match self {
-            Self::X11(x) => x.get_clipboard(),
+            Self::X11(x) => x.get_clipboard(clipboard),
             #[cfg(feature = "wayland")]
-            Self::Wayland(w) => w.get_clipboard(),
+            Self::Wayland(w) => w.get_clipboard(clipboard),
         }
     }
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         match self {
-            Self::X11(x) => x.set_clipboard(text),
+            Self::X11(x) => x.set_clipboard(clipboard, text),
             #[cfg(feature = "wayland")]
-            Self::Wayland(w) => w.set_clipboard(text),
+            Self::Wayland(w) => w.set_clipboard(clipboard, text),
         }
     }
 }
@@ -0,0 +1,197 @@
+//! Controls what happens when one of the `WindowCallbacks` methods
+//! panics while being called from the event loop.
+//!
+//! Some of the native windowing systems call back into our code via
+//! an FFI boundary (eg: the window procedure on Windows) where it is
+//! not safe to allow a panic to unwind.  Rather than abruptly terminating
+//! the process, we catch the panic at that boundary and then apply
+//! the policy configured here.
+//! The policy is currently applied to window events on Windows and X11;
+//! on other systems a panic in a callback propagates out of the event
+//! loop as it would with `PanicPolicy::Propagate`.
+use lazy_static::lazy_static;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex, Once};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Stop the event loop and resume the panic from
+    /// `ConnectionOps::run_message_loop` once it has unwound.
+    /// This is the default.
+    Propagate,
+    /// Close the window whose callback panicked, but allow
+    /// the event loop and any other windows to keep running.
+    CloseWindow,
+    /// Terminate the process immediately
+    Exit,
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        PanicPolicy::Propagate
+    }
+}
+
+/// Describes a panic that was caught in a window callback
+#[derive(Debug, Clone)]
+pub struct PanicReport {
+    /// The panic message
+    pub message: String,
+    /// The source location of the panic, if known
+    pub location: Option<String>,
+    /// A formatted backtrace captured at the point of the panic
+    pub backtrace: String,
+}
+
+impl std::fmt::Display for PanicReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(fmt, "panic at {}: {}", location, self.message),
+            None => write!(fmt, "panic: {}", self.message),
+        }
+    }
+}
+
+type PanicHook = Arc<dyn Fn(&PanicReport) + Send + Sync>;
+
+struct State {
+    policy: PanicPolicy,
+    hook: Option<PanicHook>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State {
+        policy: PanicPolicy::default(),
+        hook: None,
+    });
+}
+
+thread_local! {
+    /// Set while a window callback is running under `catch_callback_panic`
+    static IN_CALLBACK: Cell<bool> = Cell::new(false);
+    /// Populated by our panic hook so that we can pick up the
+    /// location and backtrace after catch_unwind returns
+    static LAST_PANIC: RefCell<Option<PanicReport>> = RefCell::new(None);
+    /// Holds the payload of a panic that is waiting to be resumed
+    /// once the event loop has terminated
+    static PENDING: RefCell<Option<Box<dyn Any + Send + 'static>>> = RefCell::new(None);
+}
+
+/// Set the policy to apply when a window callback panics
+pub fn set_panic_policy(policy: PanicPolicy) {
+    STATE.lock().unwrap().policy = policy;
+}
+
+/// Register a function that will be called with the details of
+/// any panic caught in a window callback, before the panic policy
+/// is applied.  This is intended to allow the embedding application
+/// to log or display the problem to the user.
+pub fn set_panic_hook<F: Fn(&PanicReport) + Send + Sync + 'static>(hook: F) {
+    STATE.lock().unwrap().hook.replace(Arc::new(hook));
+}
+
+fn install_capture_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let prior = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Panics elsewhere are none of our business
+            if !IN_CALLBACK.with(Cell::get) {
+                prior(info);
+                return;
+            }
+            let message = payload_to_string(info.payload());
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+            let backtrace = format!("{:?}", backtrace::Backtrace::new());
+            LAST_PANIC.with(|p| {
+                p.borrow_mut().replace(PanicReport {
+                    message,
+                    location,
+                    backtrace,
+                })
+            });
+            // A panic that the policy recovers from is reported via
+            // our own hook rather than as though it were fatal
+            if STATE.lock().unwrap().policy != PanicPolicy::CloseWindow {
+                prior(info);
+            }
+        }));
+    });
+}
+
+fn payload_to_string(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Box<Any>".to_string()
+    }
+}
+
+/// Run `func`, catching any panic.
+/// On panic, the report is logged and passed to the hook, and
+/// the policy that the caller needs to apply is returned.
+/// The `Exit` policy is applied here and never returns.
+/// The `Propagate` policy stashes the panic payload so that
+/// `resume_pending_panic` can re-raise it later; the caller is
+/// responsible for terminating its event loop.
+pub(crate) fn catch_callback_panic<R, F: FnOnce() -> R>(func: F) -> Result<R, PanicPolicy> {
+    install_capture_hook();
+    LAST_PANIC.with(|p| p.borrow_mut().take());
+
+    let was_in_callback = IN_CALLBACK.with(|flag| flag.replace(true));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(func));
+    IN_CALLBACK.with(|flag| flag.set(was_in_callback));
+    let payload = match result {
+        Ok(result) => return Ok(result),
+        Err(payload) => payload,
+    };
+
+    let report = LAST_PANIC
+        .with(|p| p.borrow_mut().take())
+        .unwrap_or_else(|| PanicReport {
+            message: payload_to_string(&*payload),
+            location: None,
+            backtrace: String::new(),
+        });
+
+    log::error!("{} in window callback\n{}", report, report.backtrace);
+
+    let (policy, hook) = {
+        let state = STATE.lock().unwrap();
+        (state.policy, state.hook.clone())
+    };
+    if let Some(hook) = hook {
+        hook(&report);
+    }
+
+    match policy {
+        PanicPolicy::Exit => std::process::exit(1),
+        PanicPolicy::Propagate => {
+            PENDING.with(|p| {
+                // If multiple callbacks panic before the loop
+                // terminates, we report the first one
+                let mut pending = p.borrow_mut();
+                if pending.is_none() {
+                    pending.replace(payload);
+                }
+            });
+        }
+        PanicPolicy::CloseWindow => {}
+    }
+
+    Err(policy)
+}
+
+/// If a panic was deferred by the `Propagate` policy, resume
+/// unwinding it now.  Called by the event loop implementations
+/// after they have terminated.
+pub(crate) fn resume_pending_panic() {
+    if let Some(payload) = PENDING.with(|p| p.borrow_mut().take()) {
+        std::panic::resume_unwind(payload);
+    }
+}